@@ -0,0 +1,216 @@
+//! Blacklist / whitelist filtering of mesh nodes
+//!
+//! In blacklist mode (the default) every node is allowed except the listed
+//! ones; in whitelist mode only listed nodes are. The list comes from
+//! `mesh.access.mode` and `mesh.access.list` and, once changed at runtime,
+//! is stored in the `mesh_access_control` tree and restored on restart.
+
+use crate::error::MeshError;
+use crate::routing::NodeId;
+use crate::storage_schema::{open_versioned_tree, TreeSchema, SCHEMA_VERSION_KEY};
+use bllvm_node::module::ipc::protocol::StorageOperation;
+use bllvm_node::module::traits::NodeAPI;
+use dashmap::DashSet;
+use tracing::warn;
+
+/// Storage tree for the access list
+const ACCESS_CONTROL_TREE: &str = "mesh_access_control";
+
+/// Key of the access mode; every other key is a listed node ID
+const MODE_KEY: &[u8] = b"mode";
+
+/// Schema of the access control tree (v1: mode under `mode`, one empty value per listed node ID)
+pub const ACCESS_CONTROL_SCHEMA: TreeSchema = TreeSchema {
+    tree: ACCESS_CONTROL_TREE,
+    version: 1,
+    migrations: &[],
+};
+
+/// How the access list is applied
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessMode {
+    /// Listed nodes are refused
+    Blacklist,
+    /// Only listed nodes are allowed
+    Whitelist,
+}
+
+impl AccessMode {
+    /// Config name of the mode (`mesh.access.mode`)
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AccessMode::Blacklist => "blacklist",
+            AccessMode::Whitelist => "whitelist",
+        }
+    }
+
+    /// Parse a config name
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "blacklist" => Some(AccessMode::Blacklist),
+            "whitelist" => Some(AccessMode::Whitelist),
+            _ => None,
+        }
+    }
+}
+
+/// Node access list
+#[derive(Debug, Clone)]
+pub struct AccessControl {
+    pub mode: AccessMode,
+    pub list: DashSet<NodeId>,
+}
+
+impl Default for AccessControl {
+    /// An empty blacklist: every node is allowed
+    fn default() -> Self {
+        Self::new(AccessMode::Blacklist)
+    }
+}
+
+impl AccessControl {
+    /// Create an empty access list
+    pub fn new(mode: AccessMode) -> Self {
+        Self {
+            mode,
+            list: DashSet::new(),
+        }
+    }
+
+    /// Load from module context config keys (`mesh.access.mode`, `mesh.access.list`)
+    pub fn from_context(ctx: &bllvm_node::module::traits::ModuleContext) -> Result<Self, MeshError> {
+        let mode_name = ctx.get_config_or("mesh.access.mode", "blacklist");
+        let mode = AccessMode::from_name(&mode_name)
+            .ok_or_else(|| MeshError::ConfigError(format!("Unknown mesh.access.mode: {}", mode_name)))?;
+        let access = Self::new(mode);
+        for entry in ctx.get_config_or("mesh.access.list", "").split(',') {
+            let entry = entry.trim();
+            if entry.is_empty() {
+                continue;
+            }
+            let node_id = parse_node_id(entry)
+                .ok_or_else(|| MeshError::ConfigError(format!("Invalid node ID in mesh.access.list: {}", entry)))?;
+            access.list.insert(node_id);
+        }
+        Ok(access)
+    }
+
+    /// Whether `node_id` may send or receive through this node
+    pub fn allows(&self, node_id: &NodeId) -> bool {
+        let listed = self.list.contains(node_id);
+        match self.mode {
+            AccessMode::Blacklist => !listed,
+            AccessMode::Whitelist => listed,
+        }
+    }
+
+    /// Fail with `AccessDenied` unless `node_id` is allowed
+    pub fn check(&self, node_id: &NodeId) -> Result<(), MeshError> {
+        if self.allows(node_id) {
+            Ok(())
+        } else {
+            Err(MeshError::AccessDenied(*node_id))
+        }
+    }
+
+    /// Replace the stored access list with this one
+    pub async fn save(&self, node_api: &dyn NodeAPI) -> Result<(), MeshError> {
+        let tree_id = open_versioned_tree(node_api, &ACCESS_CONTROL_SCHEMA).await?;
+        let stored = node_api
+            .storage_iter(tree_id.clone())
+            .await
+            .map_err(|e| MeshError::ModuleError(format!("Failed to read access control tree: {}", e)))?;
+
+        let mut operations = vec![StorageOperation::Insert {
+            key: MODE_KEY.to_vec(),
+            value: self.mode.as_str().as_bytes().to_vec(),
+        }];
+        for (key, _) in stored {
+            let listed = <NodeId>::try_from(key.as_slice()).map_or(false, |node_id| self.list.contains(&node_id));
+            if key != SCHEMA_VERSION_KEY && key != MODE_KEY && !listed {
+                operations.push(StorageOperation::Remove { key });
+            }
+        }
+        for node_id in self.list.iter() {
+            operations.push(StorageOperation::Insert {
+                key: node_id.to_vec(),
+                value: Vec::new(),
+            });
+        }
+
+        node_api
+            .storage_transaction(tree_id, operations)
+            .await
+            .map_err(|e| MeshError::ModuleError(format!("Failed to save access control list: {}", e)))
+    }
+
+    /// Access list stored by `save`, if any
+    ///
+    /// Fails only if the stored schema is newer than this build.
+    pub async fn load(node_api: &dyn NodeAPI) -> Result<Option<Self>, MeshError> {
+        let tree_id = match open_versioned_tree(node_api, &ACCESS_CONTROL_SCHEMA).await {
+            Ok(tree_id) => tree_id,
+            Err(e @ MeshError::UnsupportedVersion(_)) => return Err(e),
+            Err(e) => {
+                warn!("Access control storage unavailable: {}", e);
+                return Ok(None);
+            }
+        };
+        let stored = match node_api.storage_iter(tree_id).await {
+            Ok(stored) => stored,
+            Err(e) => {
+                warn!("Failed to restore access control list: {}", e);
+                return Ok(None);
+            }
+        };
+
+        let Some((_, mode)) = stored.iter().find(|(key, _)| key == MODE_KEY) else {
+            return Ok(None);
+        };
+        let Some(mode) = std::str::from_utf8(mode).ok().and_then(AccessMode::from_name) else {
+            warn!("Ignoring malformed stored access control mode");
+            return Ok(None);
+        };
+        let access = Self::new(mode);
+        for (key, _) in &stored {
+            if let Ok(node_id) = <NodeId>::try_from(key.as_slice()) {
+                access.list.insert(node_id);
+            }
+        }
+        Ok(Some(access))
+    }
+}
+
+/// Parse a 64-character hex node ID
+fn parse_node_id(hex_id: &str) -> Option<NodeId> {
+    hex::decode(hex_id).ok()?.try_into().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blacklist_and_whitelist() {
+        let banned = [1u8; 32];
+        let other = [2u8; 32];
+
+        let blacklist = AccessControl::default();
+        blacklist.list.insert(banned);
+        assert!(!blacklist.allows(&banned));
+        assert!(blacklist.allows(&other));
+        assert!(matches!(blacklist.check(&banned), Err(MeshError::AccessDenied(id)) if id == banned));
+
+        let whitelist = AccessControl::new(AccessMode::Whitelist);
+        whitelist.list.insert(other);
+        assert!(!whitelist.allows(&banned));
+        assert!(whitelist.allows(&other));
+    }
+
+    #[test]
+    fn test_parse_node_id() {
+        assert_eq!(parse_node_id(&hex::encode([7u8; 32])), Some([7u8; 32]));
+        assert_eq!(parse_node_id("abcd"), None);
+        assert_eq!(parse_node_id("zz"), None);
+    }
+}
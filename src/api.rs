@@ -0,0 +1,256 @@
+//! Cross-module mesh transport API
+//!
+//! Other bllvm modules use the mesh as a transport through
+//! `NodeAPI::call_module`. Requests and responses are bincode-encoded structs
+//! defined here so other crates can depend on them directly.
+
+use crate::error::MeshError;
+use crate::manager::MeshManager;
+use crate::packet::{MeshPacket, MeshPacketBuilder, PacketType};
+use crate::payment_proof::PaymentProof;
+use crate::routing::NodeId;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+/// Current module API version
+pub const MESH_API_VERSION: u32 = 1;
+
+/// Send a packet through the mesh
+pub const METHOD_SEND: &str = "mesh.v1.send";
+/// Poll locally delivered packets
+pub const METHOD_RECEIVE_POLL: &str = "mesh.v1.receive_poll";
+/// Quote the price of sending a payload
+pub const METHOD_QUOTE: &str = "mesh.v1.quote";
+/// Resolve a name to a node ID
+pub const METHOD_RESOLVE_NAME: &str = "mesh.v1.resolve_name";
+
+/// All methods registered via `register_module_api`
+pub const MESH_API_METHODS: [&str; 4] = [
+    METHOD_SEND,
+    METHOD_RECEIVE_POLL,
+    METHOD_QUOTE,
+    METHOD_RESOLVE_NAME,
+];
+
+/// `mesh.v1.send` request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SendRequest {
+    /// Destination node ID
+    pub destination: NodeId,
+    /// Packet type
+    pub packet_type: PacketType,
+    /// Payload bytes
+    pub payload: Vec<u8>,
+    /// Payment proof (required for paid packets)
+    pub payment_proof: Option<PaymentProof>,
+}
+
+/// `mesh.v1.send` response
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SendResponse {
+    /// Sequence number assigned to the packet
+    pub sequence: u64,
+}
+
+/// `mesh.v1.receive_poll` request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReceivePollRequest {
+    /// Maximum number of packets to return
+    pub max_packets: u32,
+}
+
+/// `mesh.v1.receive_poll` response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReceivePollResponse {
+    /// Delivered packets, oldest first
+    pub packets: Vec<ReceivedPacket>,
+}
+
+/// A packet delivered to this node
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReceivedPacket {
+    /// Source node ID
+    pub source: NodeId,
+    /// Sequence number
+    pub sequence: u64,
+    /// Packet timestamp
+    pub timestamp: u64,
+    /// Payload bytes
+    pub payload: Vec<u8>,
+    /// Set when the packet is a mesh RPC call; answer with `MeshManager::send_reply`
+    #[serde(default)]
+    pub correlation_id: Option<u64>,
+}
+
+impl From<&MeshPacket> for ReceivedPacket {
+    fn from(packet: &MeshPacket) -> Self {
+        Self {
+            source: packet.source,
+            sequence: packet.sequence,
+            timestamp: packet.timestamp,
+            payload: packet.payload.clone(),
+            correlation_id: packet.correlation_id().filter(|_| packet.is_rpc_request()),
+        }
+    }
+}
+
+/// `mesh.v1.quote` request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuoteRequest {
+    /// Destination node ID
+    pub destination: NodeId,
+    /// Payload size in bytes
+    pub payload_len: u64,
+}
+
+/// `mesh.v1.quote` response
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuoteResponse {
+    /// Whether a route to the destination is currently known
+    pub routable: bool,
+    /// Number of hops on the known route
+    pub hop_count: u32,
+    /// Required payment in satoshis
+    pub amount_sats: u64,
+}
+
+/// `mesh.v1.resolve_name` request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResolveNameRequest {
+    /// Name to resolve (currently a hex-encoded node ID)
+    pub name: String,
+}
+
+/// `mesh.v1.resolve_name` response
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResolveNameResponse {
+    /// Resolved node ID (if known)
+    pub node_id: Option<NodeId>,
+}
+
+/// Encode an API struct
+pub fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>, MeshError> {
+    bincode::serialize(value)
+        .map_err(|e| MeshError::InvalidRequest(format!("Failed to encode API message: {}", e)))
+}
+
+/// Decode an API struct
+pub fn decode<T: DeserializeOwned>(data: &[u8]) -> Result<T, MeshError> {
+    bincode::deserialize(data)
+        .map_err(|e| MeshError::InvalidRequest(format!("Malformed API request: {}", e)))
+}
+
+/// Whether a method name is in the versioned module API namespace
+///
+/// `mesh.v<N>.<method>` names (any version) belong to this API; operator RPC
+/// endpoints (`crate::rpc`) use other names.
+pub fn is_api_method(method: &str) -> bool {
+    let mut parts = method.splitn(3, '.');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some("mesh"), Some(version), Some(_)) => version
+            .strip_prefix('v')
+            .is_some_and(|number| !number.is_empty() && number.bytes().all(|b| b.is_ascii_digit())),
+        _ => false,
+    }
+}
+
+/// Split a `mesh.<version>.<method>` name, rejecting unknown versions
+fn parse_method(method: &str) -> Result<&str, MeshError> {
+    let mut parts = method.splitn(3, '.');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some("mesh"), Some("v1"), Some(name)) => Ok(name),
+        (Some("mesh"), Some(version), Some(_)) => Err(MeshError::UnsupportedVersion(format!(
+            "Mesh API version {} is not supported (expected v{})",
+            version, MESH_API_VERSION
+        ))),
+        _ => Err(MeshError::InvalidRequest(format!(
+            "Unknown mesh API method: {}",
+            method
+        ))),
+    }
+}
+
+/// Dispatch a module API call to the mesh manager
+///
+/// Returns the bincode-encoded response for the method.
+pub async fn dispatch(
+    manager: &MeshManager,
+    method: &str,
+    params: &[u8],
+) -> Result<Vec<u8>, MeshError> {
+    debug!("Dispatching mesh API call: method={}", method);
+
+    match parse_method(method)? {
+        "send" => {
+            let request: SendRequest = decode(params)?;
+            // Control, ack, discovery and fragment packets are built by the mesh itself
+            if matches!(
+                request.packet_type,
+                PacketType::Control | PacketType::Ack | PacketType::Discovery | PacketType::Fragment
+            ) {
+                return Err(MeshError::InvalidRequest(format!(
+                    "Packet type {:?} cannot be sent through the API",
+                    request.packet_type
+                )));
+            }
+            let mut builder = MeshPacketBuilder::new(manager.node_id(), request.destination)
+                .packet_type(request.packet_type)
+                .payload(request.payload);
+            if let Some(payment_proof) = request.payment_proof {
+                builder = builder.payment_proof(payment_proof);
+            }
+            let mut packet = builder.build()?;
+            let sequence = manager.originate(&mut packet).await?;
+            encode(&SendResponse { sequence })
+        }
+        "receive_poll" => {
+            let request: ReceivePollRequest = decode(params)?;
+            encode(&ReceivePollResponse {
+                packets: manager.poll_delivered(request.max_packets as usize),
+            })
+        }
+        "quote" => {
+            let request: QuoteRequest = decode(params)?;
+            encode(&manager.quote(&request.destination, request.payload_len as usize))
+        }
+        "resolve_name" => {
+            let request: ResolveNameRequest = decode(params)?;
+            let node_id = hex::decode(request.name.trim())
+                .ok()
+                .and_then(|bytes| <NodeId>::try_from(bytes.as_slice()).ok());
+            encode(&ResolveNameResponse { node_id })
+        }
+        other => Err(MeshError::InvalidRequest(format!(
+            "Unknown mesh API method: {}",
+            other
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_method() {
+        assert_eq!(parse_method("mesh.v1.send").unwrap(), "send");
+        assert!(matches!(
+            parse_method("mesh.v2.send"),
+            Err(MeshError::UnsupportedVersion(_))
+        ));
+        assert!(matches!(
+            parse_method("other.v1.send"),
+            Err(MeshError::InvalidRequest(_))
+        ));
+    }
+
+    #[test]
+    fn test_is_api_method() {
+        assert!(is_api_method("mesh.v1.send"));
+        assert!(is_api_method("mesh.v2.send"));
+        assert!(!is_api_method("mesh.capture_dump"));
+        assert!(!is_api_method("mesh.vx.send"));
+        assert!(!is_api_method("mesh_getstats"));
+    }
+}
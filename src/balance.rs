@@ -0,0 +1,244 @@
+//! Prepaid session balances
+//!
+//! A paid packet whose proof pays more than the packet's fee opens a balance
+//! with the rest, keyed by the proof hash and the packet's source. Later
+//! packets from that source name the proof hash in their
+//! `PAYMENT_SESSION_FIELD` metadata field instead of carrying a proof, and
+//! each is debited its fee until the balance runs out or the proof expires.
+//!
+//! The proof is verified (and marked used by replay prevention) once, when
+//! the balance opens. Packets drawing on it skip the proof replay check; the
+//! source's sequence window still applies, and the packet signature covers
+//! the metadata, so relays cannot move a packet onto another balance.
+
+use crate::error::MeshError;
+use crate::routing::NodeId;
+use crate::storage_schema::{open_versioned_tree, TreeSchema};
+use bllvm_node::module::traits::NodeAPI;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::{debug, warn};
+
+/// Metadata field naming the balance a packet is paid from (hex proof hash)
+pub const PAYMENT_SESSION_FIELD: &str = "payment_session";
+
+/// How long a balance lasts when its proof carries no expiry
+pub const DEFAULT_SESSION_MAX_AGE_SECONDS: u64 = 24 * 60 * 60; // 24 hours
+
+/// Storage tree for session balances
+const BALANCES_TREE: &str = "mesh_balances";
+
+/// Schema of the session balance tree
+pub const BALANCES_SCHEMA: TreeSchema = TreeSchema {
+    tree: BALANCES_TREE,
+    version: 1,
+    migrations: &[],
+};
+
+/// Balance key: proof hash (32) + source (32)
+type BalanceKey = [u8; 64];
+
+fn balance_key(proof_hash: &[u8; 32], source: &NodeId) -> BalanceKey {
+    let mut key = [0u8; 64];
+    key[..32].copy_from_slice(proof_hash);
+    key[32..].copy_from_slice(source);
+    key
+}
+
+/// Credit left on one prepaid proof
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionBalance {
+    /// Sats still available
+    pub remaining_sats: u64,
+    /// When the proof, and with it the balance, expires (Unix seconds)
+    pub expires_at: u64,
+}
+
+/// Open balance as reported by `mesh_getbalances`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionBalanceInfo {
+    /// Hash of the proof that opened the balance (hex)
+    pub proof_hash: String,
+    /// Node the balance belongs to (hex)
+    pub source: String,
+    /// Sats still available
+    pub remaining_sats: u64,
+    /// When the balance expires (Unix seconds)
+    pub expires_at: u64,
+}
+
+/// Session balance summary
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BalanceStats {
+    /// Open balances
+    pub sessions: usize,
+    /// Sats left across all open balances
+    pub remaining_sats: u64,
+}
+
+/// Persistent prepaid balances, by proof hash and source
+pub struct SessionBalances {
+    node_api: Arc<dyn NodeAPI>,
+    /// Storage tree ID (None if storage is unavailable)
+    tree_id: Option<String>,
+    balances: DashMap<BalanceKey, SessionBalance>,
+}
+
+impl SessionBalances {
+    /// Open the balances, restoring unexpired ones from storage
+    ///
+    /// Fails only if the stored schema is newer than this build.
+    pub async fn load(node_api: Arc<dyn NodeAPI>, now: u64) -> Result<Self, MeshError> {
+        let tree_id = match open_versioned_tree(node_api.as_ref(), &BALANCES_SCHEMA).await {
+            Ok(tree_id) => Some(tree_id),
+            Err(e @ MeshError::UnsupportedVersion(_)) => return Err(e),
+            Err(e) => {
+                warn!("Session balance storage unavailable, keeping balances in memory only: {}", e);
+                None
+            }
+        };
+
+        let balances = Self {
+            node_api,
+            tree_id,
+            balances: DashMap::new(),
+        };
+
+        if let Some(ref tree_id) = balances.tree_id {
+            match balances.node_api.storage_iter(tree_id.clone()).await {
+                Ok(stored) => {
+                    for (key, value) in stored {
+                        let (Ok(key), Ok(balance)) = (
+                            BalanceKey::try_from(key.as_slice()),
+                            bincode::deserialize::<SessionBalance>(&value),
+                        ) else {
+                            continue;
+                        };
+                        if balance.expires_at > now {
+                            balances.balances.insert(key, balance);
+                        }
+                    }
+                    debug!("Restored {} session balances", balances.balances.len());
+                }
+                Err(e) => warn!("Failed to restore session balances: {}", e),
+            }
+        }
+
+        Ok(balances)
+    }
+
+    /// Open a balance of `credit_sats` on a verified proof
+    pub async fn open(&self, proof_hash: &[u8; 32], source: &NodeId, credit_sats: u64, expires_at: u64) {
+        let key = balance_key(proof_hash, source);
+        let balance = SessionBalance {
+            remaining_sats: credit_sats,
+            expires_at,
+        };
+        self.balances.insert(key, balance);
+        debug!(
+            "Session balance opened: source={:x?}, proof={:x?}, credit={} sats",
+            &source[..8],
+            &proof_hash[..8],
+            credit_sats
+        );
+        self.persist(&key, Some(&balance)).await;
+    }
+
+    /// Take `fee_sats` from a source's balance
+    ///
+    /// Returns the sats left. Fails with `PaymentVerification` if the
+    /// balance is unknown, expired or too small; a failed debit takes
+    /// nothing.
+    pub async fn debit(&self, proof_hash: &[u8; 32], source: &NodeId, fee_sats: u64, now: u64) -> Result<u64, MeshError> {
+        let key = balance_key(proof_hash, source);
+        let result = match self.balances.get_mut(&key) {
+            None => Err(MeshError::PaymentVerification("unknown payment session".to_string())),
+            Some(balance) if balance.expires_at <= now => {
+                Err(MeshError::PaymentVerification("payment session expired".to_string()))
+            }
+            Some(balance) if balance.remaining_sats < fee_sats => Err(MeshError::PaymentVerification(format!(
+                "payment session exhausted: need {}, have {}",
+                fee_sats, balance.remaining_sats
+            ))),
+            Some(mut balance) => {
+                balance.remaining_sats -= fee_sats;
+                Ok(*balance)
+            }
+        };
+
+        match result {
+            Ok(balance) => {
+                self.persist(&key, Some(&balance)).await;
+                Ok(balance.remaining_sats)
+            }
+            Err(e) => {
+                if self.balances.remove_if(&key, |_, balance| balance.expires_at <= now).is_some() {
+                    self.persist(&key, None).await;
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// A source's balance on a proof
+    pub fn get(&self, proof_hash: &[u8; 32], source: &NodeId) -> Option<SessionBalance> {
+        self.balances.get(&balance_key(proof_hash, source)).map(|balance| *balance)
+    }
+
+    /// Unexpired balances
+    pub fn list(&self, now: u64) -> Vec<SessionBalanceInfo> {
+        self.balances
+            .iter()
+            .filter(|entry| entry.value().expires_at > now)
+            .map(|entry| SessionBalanceInfo {
+                proof_hash: hex::encode(&entry.key()[..32]),
+                source: hex::encode(&entry.key()[32..]),
+                remaining_sats: entry.value().remaining_sats,
+                expires_at: entry.value().expires_at,
+            })
+            .collect()
+    }
+
+    /// Number of balances and the sats left on them
+    pub fn stats(&self) -> BalanceStats {
+        self.balances.iter().fold(BalanceStats::default(), |stats, entry| BalanceStats {
+            sessions: stats.sessions + 1,
+            remaining_sats: stats.remaining_sats.saturating_add(entry.value().remaining_sats),
+        })
+    }
+
+    /// Forget expired balances
+    pub async fn cleanup_expired(&self, now: u64) {
+        let expired: Vec<BalanceKey> = self
+            .balances
+            .iter()
+            .filter(|entry| entry.value().expires_at <= now)
+            .map(|entry| *entry.key())
+            .collect();
+        for key in &expired {
+            self.balances.remove(key);
+            self.persist(key, None).await;
+        }
+        if !expired.is_empty() {
+            debug!("Cleaned up {} expired session balances", expired.len());
+        }
+    }
+
+    /// Write a balance through to storage (None removes it)
+    async fn persist(&self, key: &BalanceKey, balance: Option<&SessionBalance>) {
+        let Some(ref tree_id) = self.tree_id else {
+            return;
+        };
+        let result = match balance {
+            Some(balance) => {
+                let value = bincode::serialize(balance).expect("session balance should be serializable");
+                self.node_api.storage_insert(tree_id.clone(), key.to_vec(), value).await
+            }
+            None => self.node_api.storage_remove(tree_id.clone(), key.to_vec()).await,
+        };
+        if let Err(e) = result {
+            warn!("Failed to persist session balance: {}", e);
+        }
+    }
+}
@@ -0,0 +1,452 @@
+//! Monthly bandwidth cap for relays on metered uplinks
+//!
+//! Tracks month-to-date relayed bytes against `mesh.bandwidth_cap_gb_per_month`.
+//! Past the soft threshold paid traffic gets more expensive, bulk-priority
+//! paid packets are shed and advertised routes cost more; at the cap all paid
+//! traffic is rejected and advertised routes are withdrawn. Free (consensus)
+//! traffic is never throttled.
+
+use crate::error::MeshError;
+use crate::storage_schema::{open_versioned_tree, Migration, TreeSchema};
+use bllvm_node::module::ipc::protocol::StorageOperation;
+use bllvm_node::module::traits::NodeAPI;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use tracing::{debug, info, warn};
+
+/// Storage tree for bandwidth accounting
+const BANDWIDTH_TREE: &str = "mesh_bandwidth";
+
+/// Storage key for the month-to-date counter
+const MONTH_TO_DATE_KEY: &[u8] = b"month_to_date";
+
+/// Schema of the bandwidth tree
+///
+/// v1: the month-to-date record remembers the billing day its period was
+/// computed with.
+pub const BANDWIDTH_SCHEMA: TreeSchema = TreeSchema {
+    tree: BANDWIDTH_TREE,
+    version: 1,
+    migrations: &[Migration {
+        from: 0,
+        description: "record billing day with month-to-date usage",
+        migrate: migrate_month_to_date_v0,
+    }],
+};
+
+/// Bytes per GB
+const BYTES_PER_GB: u64 = 1_000_000_000;
+
+/// Bandwidth cap configuration
+#[derive(Debug, Clone)]
+pub struct BandwidthConfig {
+    /// Monthly cap in bytes (None = unlimited)
+    pub cap_bytes: Option<u64>,
+    /// Soft threshold as a percentage of the cap
+    pub soft_threshold_pct: u8,
+    /// Day of month the counter resets (1-28)
+    pub billing_day: u8,
+    /// Paid-traffic price multiplier past the soft threshold
+    pub soft_price_multiplier: u64,
+}
+
+impl Default for BandwidthConfig {
+    fn default() -> Self {
+        Self {
+            cap_bytes: None,
+            soft_threshold_pct: 90,
+            billing_day: 1,
+            soft_price_multiplier: 2,
+        }
+    }
+}
+
+impl BandwidthConfig {
+    /// Load from module context config keys
+    pub fn from_context(ctx: &bllvm_node::module::traits::ModuleContext) -> Self {
+        let defaults = Self::default();
+        let cap_bytes = ctx
+            .get_config_or("mesh.bandwidth_cap_gb_per_month", "0")
+            .parse::<u64>()
+            .ok()
+            .filter(|gb| *gb > 0)
+            .map(|gb| gb * BYTES_PER_GB);
+        let soft_threshold_pct = ctx
+            .get_config_or("mesh.bandwidth_soft_threshold_pct", "90")
+            .parse::<u8>()
+            .unwrap_or(defaults.soft_threshold_pct)
+            .min(100);
+        let billing_day = ctx
+            .get_config_or("mesh.bandwidth_billing_day", "1")
+            .parse::<u8>()
+            .unwrap_or(defaults.billing_day)
+            .clamp(1, 28);
+        let soft_price_multiplier = ctx
+            .get_config_or("mesh.bandwidth_soft_price_multiplier", "2")
+            .parse::<u64>()
+            .unwrap_or(defaults.soft_price_multiplier)
+            .max(1);
+
+        Self {
+            cap_bytes,
+            soft_threshold_pct,
+            billing_day,
+            soft_price_multiplier,
+        }
+    }
+}
+
+/// Bandwidth usage state relative to the cap
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BandwidthState {
+    /// Below the soft threshold
+    Normal,
+    /// Past the soft threshold (paid pricing raised, bulk shed)
+    Soft,
+    /// Cap reached (paid traffic rejected)
+    Capped,
+}
+
+/// Persisted month-to-date counter
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct MonthToDate {
+    /// Billing period (months since year 0)
+    period: i64,
+    /// Bytes relayed in the period
+    bytes: u64,
+    /// Billing day the period was computed with (None = recorded before v1)
+    billing_day: Option<u8>,
+}
+
+/// Month-to-date counter as written before schema v1
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct MonthToDateV0 {
+    period: i64,
+    bytes: u64,
+}
+
+/// v0 -> v1: add the (unknown) billing day to the month-to-date record
+fn migrate_month_to_date_v0(entries: &[(Vec<u8>, Vec<u8>)]) -> Result<Vec<StorageOperation>, MeshError> {
+    let mut operations = Vec::new();
+    for (key, value) in entries {
+        if key != MONTH_TO_DATE_KEY {
+            continue;
+        }
+        let legacy: MonthToDateV0 = bincode::deserialize(value)
+            .map_err(|e| MeshError::ModuleError(format!("Failed to decode v0 bandwidth usage: {}", e)))?;
+        let migrated = MonthToDate {
+            period: legacy.period,
+            bytes: legacy.bytes,
+            billing_day: None,
+        };
+        operations.push(StorageOperation::Insert {
+            key: key.clone(),
+            value: bincode::serialize(&migrated)
+                .map_err(|e| MeshError::ModuleError(format!("Failed to encode bandwidth usage: {}", e)))?,
+        });
+    }
+    Ok(operations)
+}
+
+/// Month-to-date bandwidth accountant
+pub struct BandwidthAccountant {
+    config: BandwidthConfig,
+    /// Bytes relayed in the current billing period
+    used_bytes: AtomicU64,
+    /// Current billing period (months since year 0)
+    period: Mutex<i64>,
+}
+
+impl BandwidthAccountant {
+    /// Create a new accountant starting at zero usage
+    pub fn new(config: BandwidthConfig, now: u64) -> Self {
+        let period = billing_period(now, config.billing_day);
+        Self {
+            config,
+            used_bytes: AtomicU64::new(0),
+            period: Mutex::new(period),
+        }
+    }
+
+    /// Load month-to-date usage from storage
+    ///
+    /// Usage from an earlier billing period (or computed with a different
+    /// billing day) is discarded. Fails only if the stored schema is newer
+    /// than this build.
+    pub async fn load(node_api: &dyn NodeAPI, config: BandwidthConfig, now: u64) -> Result<Self, MeshError> {
+        let accountant = Self::new(config, now);
+
+        let tree_id = match open_versioned_tree(node_api, &BANDWIDTH_SCHEMA).await {
+            Ok(tree_id) => tree_id,
+            Err(e @ MeshError::UnsupportedVersion(_)) => return Err(e),
+            Err(e) => {
+                warn!("Bandwidth usage storage unavailable: {}", e);
+                return Ok(accountant);
+            }
+        };
+
+        if let Ok(Some(data)) = node_api.storage_get(tree_id, MONTH_TO_DATE_KEY.to_vec()).await {
+            match bincode::deserialize::<MonthToDate>(&data) {
+                Ok(stored)
+                    if stored.period == *accountant.period.lock().unwrap()
+                        && stored.billing_day.unwrap_or(accountant.config.billing_day)
+                            == accountant.config.billing_day =>
+                {
+                    accountant.used_bytes.store(stored.bytes, Ordering::Relaxed);
+                    debug!("Restored month-to-date bandwidth: {} bytes", stored.bytes);
+                }
+                Ok(_) => debug!("Stored bandwidth usage is from a different billing period"),
+                Err(e) => warn!("Failed to decode stored bandwidth usage: {}", e),
+            }
+        }
+
+        Ok(accountant)
+    }
+
+    /// Persist month-to-date usage to storage
+    pub async fn save(&self, node_api: &dyn NodeAPI) -> Result<(), MeshError> {
+        let record = MonthToDate {
+            period: *self.period.lock().unwrap(),
+            bytes: self.used_bytes.load(Ordering::Relaxed),
+            billing_day: Some(self.config.billing_day),
+        };
+        let data = bincode::serialize(&record)
+            .map_err(|e| MeshError::ModuleError(format!("Failed to encode bandwidth usage: {}", e)))?;
+
+        let tree_id = node_api
+            .storage_open_tree(BANDWIDTH_TREE.to_string())
+            .await
+            .map_err(|e| MeshError::ModuleError(format!("Failed to open bandwidth tree: {}", e)))?;
+        node_api
+            .storage_insert(tree_id, MONTH_TO_DATE_KEY.to_vec(), data)
+            .await
+            .map_err(|e| MeshError::ModuleError(format!("Failed to save bandwidth usage: {}", e)))
+    }
+
+    /// Whether a cap is configured
+    pub fn is_capped(&self) -> bool {
+        self.config.cap_bytes.is_some()
+    }
+
+    /// Record relayed bytes
+    pub fn record(&self, bytes: u64, now: u64) {
+        self.roll_period(now);
+        self.used_bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Bytes relayed in the current billing period
+    pub fn used_bytes(&self, now: u64) -> u64 {
+        self.roll_period(now);
+        self.used_bytes.load(Ordering::Relaxed)
+    }
+
+    /// Current usage state
+    pub fn state(&self, now: u64) -> BandwidthState {
+        let Some(cap) = self.config.cap_bytes else {
+            return BandwidthState::Normal;
+        };
+
+        let used = self.used_bytes(now);
+        if used >= cap {
+            BandwidthState::Capped
+        } else if used as u128 * 100 >= cap as u128 * self.config.soft_threshold_pct as u128 {
+            BandwidthState::Soft
+        } else {
+            BandwidthState::Normal
+        }
+    }
+
+    /// Paid-traffic pricing multiplier for the current state
+    pub fn price_multiplier(&self, now: u64) -> u64 {
+        match self.state(now) {
+            BandwidthState::Normal => 1,
+            BandwidthState::Soft | BandwidthState::Capped => self.config.soft_price_multiplier,
+        }
+    }
+
+    /// Multiplier for the route costs this node advertises
+    ///
+    /// Past the soft threshold routes through this node are advertised at
+    /// `soft_price_multiplier` times their cost, steering traffic elsewhere;
+    /// at the cap (`None`) they are withdrawn.
+    pub fn advertised_cost_multiplier(&self, now: u64) -> Option<u64> {
+        match self.state(now) {
+            BandwidthState::Normal => Some(1),
+            BandwidthState::Soft => Some(self.config.soft_price_multiplier),
+            BandwidthState::Capped => None,
+        }
+    }
+
+    /// Decide whether a paid packet may be relayed
+    ///
+    /// Free traffic must not be passed through this check.
+    pub fn admit_paid(&self, bulk: bool, now: u64) -> Result<(), MeshError> {
+        match self.state(now) {
+            BandwidthState::Normal => Ok(()),
+            BandwidthState::Soft if bulk => Err(MeshError::BandwidthCapReached(
+                "Shedding bulk paid traffic near bandwidth cap".to_string(),
+            )),
+            BandwidthState::Soft => Ok(()),
+            BandwidthState::Capped => Err(MeshError::BandwidthCapReached(
+                "Monthly bandwidth cap reached".to_string(),
+            )),
+        }
+    }
+
+    /// Reset the counter when a new billing period starts
+    fn roll_period(&self, now: u64) {
+        let current = billing_period(now, self.config.billing_day);
+        let mut period = self.period.lock().unwrap();
+        if *period != current {
+            info!(
+                "New bandwidth billing period, resetting counter ({} bytes used last period)",
+                self.used_bytes.swap(0, Ordering::Relaxed)
+            );
+            *period = current;
+        }
+    }
+}
+
+/// Billing period containing `now` (months since year 0)
+///
+/// A period starts on `billing_day` and runs until the same day next month.
+fn billing_period(now: u64, billing_day: u8) -> i64 {
+    let (year, month, day) = civil_from_days((now / 86_400) as i64);
+    let months = year * 12 + (month as i64 - 1);
+    if day >= billing_day as u32 {
+        months
+    } else {
+        months - 1
+    }
+}
+
+/// Convert days since the Unix epoch to a (year, month, day) civil date
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = (if z >= 0 { z } else { z - 146_096 }) / 146_097;
+    let doe = (z - era * 146_097) as u64;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+    let year = yoe as i64 + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const JAN_14_2026: u64 = 1_768_348_800;
+    const JAN_15_2026: u64 = 1_768_435_200;
+    const FEB_14_2026: u64 = 1_771_027_200;
+    const FEB_15_2026: u64 = 1_771_113_600;
+
+    fn accountant(cap_bytes: u64, billing_day: u8, now: u64) -> BandwidthAccountant {
+        BandwidthAccountant::new(
+            BandwidthConfig {
+                cap_bytes: Some(cap_bytes),
+                soft_threshold_pct: 90,
+                billing_day,
+                soft_price_multiplier: 3,
+            },
+            now,
+        )
+    }
+
+    #[test]
+    fn test_migrate_month_to_date_v0() {
+        // bincode of the v0 record { period: 24312, bytes: 5000 }
+        let fixture = vec![0xF8, 0x5E, 0, 0, 0, 0, 0, 0, 0x88, 0x13, 0, 0, 0, 0, 0, 0];
+        let operations = migrate_month_to_date_v0(&[(MONTH_TO_DATE_KEY.to_vec(), fixture)]).unwrap();
+
+        let [StorageOperation::Insert { key, value }] = operations.as_slice() else {
+            panic!("expected a single insert, got {:?}", operations);
+        };
+        assert_eq!(key.as_slice(), MONTH_TO_DATE_KEY);
+        assert_eq!(
+            bincode::deserialize::<MonthToDate>(value).unwrap(),
+            MonthToDate {
+                period: 24312,
+                bytes: 5000,
+                billing_day: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_civil_from_days() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days((JAN_15_2026 / 86_400) as i64), (2026, 1, 15));
+        assert_eq!(civil_from_days((FEB_14_2026 / 86_400) as i64), (2026, 2, 14));
+    }
+
+    #[test]
+    fn test_soft_threshold_raises_price_and_sheds_bulk() {
+        let accountant = accountant(1000, 1, JAN_15_2026);
+        assert_eq!(accountant.price_multiplier(JAN_15_2026), 1);
+
+        accountant.record(900, JAN_15_2026);
+        assert_eq!(accountant.state(JAN_15_2026), BandwidthState::Soft);
+        assert_eq!(accountant.price_multiplier(JAN_15_2026), 3);
+        assert!(accountant.admit_paid(false, JAN_15_2026).is_ok());
+        assert!(matches!(
+            accountant.admit_paid(true, JAN_15_2026),
+            Err(MeshError::BandwidthCapReached(_))
+        ));
+    }
+
+    #[test]
+    fn test_hard_cap_rejects_paid_traffic() {
+        let accountant = accountant(1000, 1, JAN_15_2026);
+        accountant.record(1000, JAN_15_2026);
+
+        assert_eq!(accountant.state(JAN_15_2026), BandwidthState::Capped);
+        let result = accountant.admit_paid(false, JAN_15_2026);
+        assert!(matches!(result, Err(MeshError::BandwidthCapReached(_))));
+        assert!(result.unwrap_err().is_retriable());
+    }
+
+    #[test]
+    fn test_advertised_costs_penalized_then_withdrawn() {
+        let accountant = accountant(1000, 1, JAN_15_2026);
+        assert_eq!(accountant.advertised_cost_multiplier(JAN_15_2026), Some(1));
+
+        accountant.record(900, JAN_15_2026);
+        assert_eq!(accountant.advertised_cost_multiplier(JAN_15_2026), Some(3));
+
+        accountant.record(100, JAN_15_2026);
+        assert_eq!(accountant.advertised_cost_multiplier(JAN_15_2026), None);
+    }
+
+    #[test]
+    fn test_resets_on_billing_day() {
+        let accountant = accountant(1000, 15, JAN_15_2026);
+        accountant.record(1000, JAN_15_2026);
+
+        // Still the same period the day before the billing day
+        assert_eq!(accountant.state(FEB_14_2026), BandwidthState::Capped);
+
+        // Counter resets on the billing day
+        assert_eq!(accountant.state(FEB_15_2026), BandwidthState::Normal);
+        assert_eq!(accountant.used_bytes(FEB_15_2026), 0);
+    }
+
+    #[test]
+    fn test_period_before_billing_day_belongs_to_previous_month() {
+        assert_eq!(
+            billing_period(JAN_14_2026, 15),
+            billing_period(JAN_15_2026, 15) - 1
+        );
+    }
+
+    #[test]
+    fn test_uncapped_is_always_normal() {
+        let accountant = BandwidthAccountant::new(BandwidthConfig::default(), JAN_15_2026);
+        accountant.record(u64::MAX / 2, JAN_15_2026);
+        assert_eq!(accountant.state(JAN_15_2026), BandwidthState::Normal);
+        assert!(accountant.admit_paid(true, JAN_15_2026).is_ok());
+    }
+}
@@ -0,0 +1,338 @@
+//! Opt-in packet capture for post-incident debugging
+//!
+//! A bounded ring buffer of sanitized packet headers with their disposition
+//! (forwarded, delivered, dropped and why). Payloads are only kept when
+//! `mesh.capture.include_payload = true`. Capture is off by default, can be
+//! toggled at runtime, and switches itself off after
+//! `mesh.capture.auto_disable_secs` so it is never left running by accident.
+
+use crate::packet::{MeshPacket, PacketType};
+use crate::routing::NodeId;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use tracing::info;
+
+/// Capture configuration
+#[derive(Debug, Clone)]
+pub struct CaptureConfig {
+    /// Start capturing at startup
+    pub enabled: bool,
+    /// Maximum number of captured packets
+    pub max_packets: usize,
+    /// Maximum memory used by captured packets
+    pub max_bytes: usize,
+    /// Keep payload bytes (redacted by default)
+    pub include_payload: bool,
+    /// Capture switches off this long after being enabled (0 = never)
+    pub auto_disable_secs: u64,
+}
+
+impl Default for CaptureConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_packets: 1024,
+            max_bytes: 1024 * 1024,
+            include_payload: false,
+            auto_disable_secs: 15 * 60,
+        }
+    }
+}
+
+impl CaptureConfig {
+    /// Load from module context config keys
+    pub fn from_context(ctx: &bllvm_node::module::traits::ModuleContext) -> Self {
+        let defaults = Self::default();
+        Self {
+            enabled: ctx.get_config_or("mesh.capture.enabled", "false") == "true",
+            max_packets: ctx
+                .get_config_or("mesh.capture.max_packets", &defaults.max_packets.to_string())
+                .parse::<usize>()
+                .unwrap_or(defaults.max_packets),
+            max_bytes: ctx
+                .get_config_or("mesh.capture.max_bytes", &defaults.max_bytes.to_string())
+                .parse::<usize>()
+                .unwrap_or(defaults.max_bytes),
+            include_payload: ctx.get_config_or("mesh.capture.include_payload", "false") == "true",
+            auto_disable_secs: ctx
+                .get_config_or(
+                    "mesh.capture.auto_disable_secs",
+                    &defaults.auto_disable_secs.to_string(),
+                )
+                .parse::<u64>()
+                .unwrap_or(defaults.auto_disable_secs),
+        }
+    }
+}
+
+/// What happened to a captured packet
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "disposition", rename_all = "snake_case")]
+pub enum Disposition {
+    /// Sent on to a next hop
+    Forwarded,
+    /// Handled by this node
+    Delivered,
+    /// Discarded
+    Dropped { reason: String },
+}
+
+/// Sanitized copy of a packet header
+#[derive(Debug, Clone)]
+pub struct CapturedPacket {
+    /// When the packet was captured
+    pub captured_at: u64,
+    /// Packet type
+    pub packet_type: PacketType,
+    /// Source node ID
+    pub source: NodeId,
+    /// Destination node ID
+    pub destination: NodeId,
+    /// Route recorded in the packet
+    pub route: Vec<NodeId>,
+    /// Sequence number
+    pub sequence: u64,
+    /// Packet timestamp
+    pub timestamp: u64,
+    /// Payload length in bytes
+    pub payload_len: usize,
+    /// Payload bytes (only with `include_payload`)
+    pub payload: Option<Vec<u8>>,
+    /// Whether a payment proof was attached (the proof itself is never kept)
+    pub paid: bool,
+    /// Disposition
+    pub disposition: Disposition,
+}
+
+impl CapturedPacket {
+    /// Memory held by this record (inline size plus heap allocations)
+    pub fn memory_bytes(&self) -> usize {
+        let reason = match &self.disposition {
+            Disposition::Dropped { reason } => reason.capacity(),
+            _ => 0,
+        };
+        std::mem::size_of::<Self>()
+            + self.route.capacity() * std::mem::size_of::<NodeId>()
+            + self.payload.as_ref().map(|payload| payload.capacity()).unwrap_or(0)
+            + reason
+    }
+
+    /// JSON representation for dumps
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "captured_at": self.captured_at,
+            "packet_type": format!("{:?}", self.packet_type),
+            "source": hex::encode(self.source),
+            "destination": hex::encode(self.destination),
+            "route": self.route.iter().map(hex::encode).collect::<Vec<_>>(),
+            "sequence": self.sequence,
+            "timestamp": self.timestamp,
+            "payload_len": self.payload_len,
+            "payload": self.payload.as_ref().map(hex::encode),
+            "paid": self.paid,
+            "disposition": self.disposition,
+        })
+    }
+}
+
+/// Ring buffer state
+#[derive(Debug, Default)]
+struct CaptureState {
+    /// Capture is on until this time (None = off, Some(u64::MAX) = no auto-disable)
+    enabled_until: Option<u64>,
+    /// Captured packets, oldest first
+    buffer: VecDeque<CapturedPacket>,
+    /// Memory held by `buffer` entries
+    bytes: usize,
+}
+
+/// Bounded packet capture
+pub struct PacketCapture {
+    config: CaptureConfig,
+    state: Mutex<CaptureState>,
+}
+
+impl PacketCapture {
+    /// Create a capture buffer (enabled if configured)
+    pub fn new(config: CaptureConfig, now: u64) -> Self {
+        let capture = Self {
+            config,
+            state: Mutex::new(CaptureState::default()),
+        };
+        if capture.config.enabled {
+            capture.enable(now, None);
+        }
+        capture
+    }
+
+    /// Capture configuration
+    pub fn config(&self) -> &CaptureConfig {
+        &self.config
+    }
+
+    /// Turn capture on for `duration_secs` (default: `auto_disable_secs`)
+    pub fn enable(&self, now: u64, duration_secs: Option<u64>) {
+        let duration = duration_secs.unwrap_or(self.config.auto_disable_secs);
+        let until = if duration == 0 {
+            u64::MAX
+        } else {
+            now.saturating_add(duration)
+        };
+        self.state.lock().unwrap().enabled_until = Some(until);
+        info!("Packet capture enabled (auto-disable in {}s)", duration);
+    }
+
+    /// Turn capture off (captured packets are kept until cleared)
+    pub fn disable(&self) {
+        self.state.lock().unwrap().enabled_until = None;
+        info!("Packet capture disabled");
+    }
+
+    /// Whether capture is currently on (auto-disabling when expired)
+    pub fn is_enabled(&self, now: u64) -> bool {
+        let mut state = self.state.lock().unwrap();
+        Self::check_expiry(&mut state, now)
+    }
+
+    /// Capture a packet and its disposition (no-op while disabled)
+    pub fn record(&self, packet: &MeshPacket, disposition: Disposition, now: u64) {
+        let mut state = self.state.lock().unwrap();
+        if !Self::check_expiry(&mut state, now) {
+            return;
+        }
+
+        let captured = CapturedPacket {
+            captured_at: now,
+            packet_type: packet.packet_type.clone(),
+            source: packet.source,
+            destination: packet.destination,
+            route: packet.route.clone(),
+            sequence: packet.sequence,
+            timestamp: packet.timestamp,
+            payload_len: packet.payload.len(),
+            payload: self.config.include_payload.then(|| packet.payload.clone()),
+            paid: packet.payment_proof.is_some(),
+            disposition,
+        };
+        let size = captured.memory_bytes();
+        if size > self.config.max_bytes || self.config.max_packets == 0 {
+            return;
+        }
+
+        while state.buffer.len() >= self.config.max_packets
+            || state.bytes + size > self.config.max_bytes
+        {
+            let Some(evicted) = state.buffer.pop_front() else {
+                break;
+            };
+            state.bytes -= evicted.memory_bytes();
+        }
+        state.bytes += size;
+        state.buffer.push_back(captured);
+    }
+
+    /// Snapshot captured packets, oldest first
+    pub fn snapshot(&self) -> Vec<CapturedPacket> {
+        self.state.lock().unwrap().buffer.iter().cloned().collect()
+    }
+
+    /// Dump capture status and packets as JSON
+    pub fn dump(&self, now: u64) -> serde_json::Value {
+        let mut state = self.state.lock().unwrap();
+        let enabled = Self::check_expiry(&mut state, now);
+        serde_json::json!({
+            "enabled": enabled,
+            "enabled_until": state.enabled_until.filter(|until| *until != u64::MAX),
+            "include_payload": self.config.include_payload,
+            "memory_bytes": state.bytes,
+            "packets": state.buffer.iter().map(CapturedPacket::to_json).collect::<Vec<_>>(),
+        })
+    }
+
+    /// Discard all captured packets, returning how many were dropped
+    pub fn clear(&self) -> usize {
+        let mut state = self.state.lock().unwrap();
+        state.bytes = 0;
+        let count = state.buffer.len();
+        state.buffer.clear();
+        count
+    }
+
+    /// Memory held by captured packets
+    pub fn memory_bytes(&self) -> usize {
+        self.state.lock().unwrap().bytes
+    }
+
+    fn check_expiry(state: &mut CaptureState, now: u64) -> bool {
+        match state.enabled_until {
+            Some(until) if now >= until => {
+                state.enabled_until = None;
+                info!("Packet capture auto-disabled");
+                false
+            }
+            Some(_) => true,
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::MeshPacketBuilder;
+
+    fn capture(max_packets: usize, max_bytes: usize) -> PacketCapture {
+        PacketCapture::new(
+            CaptureConfig {
+                enabled: true,
+                max_packets,
+                max_bytes,
+                include_payload: false,
+                auto_disable_secs: 0,
+            },
+            0,
+        )
+    }
+
+    fn packet(sequence: u64) -> MeshPacket {
+        let mut packet = MeshPacketBuilder::new([1u8; 32], [2u8; 32])
+            .packet_type(PacketType::BitcoinP2P)
+            .payload(vec![0; 64])
+            .build()
+            .unwrap();
+        packet.sequence = sequence;
+        packet
+    }
+
+    #[test]
+    fn test_count_bound_evicts_oldest() {
+        let capture = capture(2, usize::MAX);
+        for sequence in 0..3 {
+            capture.record(&packet(sequence), Disposition::Delivered, 0);
+        }
+
+        let sequences: Vec<u64> = capture.snapshot().iter().map(|p| p.sequence).collect();
+        assert_eq!(sequences, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_memory_accounting_matches_buffer() {
+        let one = {
+            let capture = capture(10, usize::MAX);
+            capture.record(&packet(0), Disposition::Forwarded, 0);
+            capture.memory_bytes()
+        };
+
+        let capture = capture(10, one * 2);
+        for sequence in 0..5 {
+            capture.record(&packet(sequence), Disposition::Forwarded, 0);
+        }
+        assert_eq!(capture.snapshot().len(), 2);
+        let held: usize = capture.snapshot().iter().map(CapturedPacket::memory_bytes).sum();
+        assert_eq!(capture.memory_bytes(), held);
+
+        capture.clear();
+        assert_eq!(capture.memory_bytes(), 0);
+    }
+}
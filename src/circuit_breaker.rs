@@ -0,0 +1,252 @@
+//! Per-next-hop circuit breaker for forwarding
+//!
+//! Each next hop starts `Closed`. After `mesh.circuit_breaker.failure_threshold`
+//! consecutive send failures it trips `Open`, and packets avoid it (taking
+//! the next-best route) instead of failing against it one by one. Once
+//! `mesh.circuit_breaker.reset_timeout_secs` have passed the breaker is
+//! `HalfOpen`: one packet may try the peer again, closing the breaker on
+//! success and re-opening it on failure.
+
+use crate::routing::NodeId;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info};
+
+/// Circuit breaker configuration
+#[derive(Debug, Clone)]
+pub struct CircuitBreakerConfig {
+    /// Consecutive failures that open the breaker (0 = breaker disabled)
+    pub failure_threshold: u32,
+    /// How long an open breaker refuses traffic before a trial (seconds)
+    pub reset_timeout_secs: u64,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 3,
+            reset_timeout_secs: 30,
+        }
+    }
+}
+
+impl CircuitBreakerConfig {
+    /// Load from module context config keys
+    pub fn from_context(ctx: &bllvm_node::module::traits::ModuleContext) -> Self {
+        let defaults = Self::default();
+        Self {
+            failure_threshold: ctx
+                .get_config_or(
+                    "mesh.circuit_breaker.failure_threshold",
+                    &defaults.failure_threshold.to_string(),
+                )
+                .parse::<u32>()
+                .unwrap_or(defaults.failure_threshold),
+            reset_timeout_secs: ctx
+                .get_config_or(
+                    "mesh.circuit_breaker.reset_timeout_secs",
+                    &defaults.reset_timeout_secs.to_string(),
+                )
+                .parse::<u64>()
+                .unwrap_or(defaults.reset_timeout_secs),
+        }
+    }
+
+    /// Whether the breaker is enabled
+    pub fn enabled(&self) -> bool {
+        self.failure_threshold > 0
+    }
+}
+
+/// Breaker state of one next hop
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerState {
+    /// Traffic flows normally
+    Closed,
+    /// Too many failures; traffic avoids this hop
+    Open,
+    /// Reset timeout passed; one trial packet is let through
+    HalfOpen,
+}
+
+/// Breaker of one next hop
+#[derive(Debug, Clone)]
+struct Breaker {
+    state: BreakerState,
+    consecutive_failures: u32,
+    /// When the breaker last opened
+    opened_at: u64,
+    /// A HalfOpen trial is in flight
+    trial_in_flight: bool,
+}
+
+impl Breaker {
+    fn closed() -> Self {
+        Self {
+            state: BreakerState::Closed,
+            consecutive_failures: 0,
+            opened_at: 0,
+            trial_in_flight: false,
+        }
+    }
+}
+
+/// Circuit breakers keyed by next-hop node ID
+pub struct CircuitBreaker {
+    config: CircuitBreakerConfig,
+    /// Hops that failed recently (lock-free with DashMap); absent = Closed
+    breakers: DashMap<NodeId, Breaker>,
+}
+
+impl CircuitBreaker {
+    /// Create a new circuit breaker
+    pub fn new(config: CircuitBreakerConfig) -> Self {
+        Self {
+            config,
+            breakers: DashMap::new(),
+        }
+    }
+
+    /// Circuit breaker configuration
+    pub fn config(&self) -> &CircuitBreakerConfig {
+        &self.config
+    }
+
+    /// Current state of a next hop's breaker
+    pub fn state(&self, next_hop: &NodeId, now: u64) -> BreakerState {
+        match self.breakers.get(next_hop) {
+            Some(breaker) if breaker.state == BreakerState::Open && self.reset_elapsed(&breaker, now) => {
+                BreakerState::HalfOpen
+            }
+            Some(breaker) => breaker.state,
+            None => BreakerState::Closed,
+        }
+    }
+
+    /// Whether a packet may be sent to `next_hop` now
+    ///
+    /// An open breaker whose reset timeout has passed turns HalfOpen and
+    /// admits a single trial until its outcome is recorded.
+    pub fn allow(&self, next_hop: &NodeId, now: u64) -> bool {
+        if !self.config.enabled() {
+            return true;
+        }
+        let Some(mut breaker) = self.breakers.get_mut(next_hop) else {
+            return true;
+        };
+        match breaker.state {
+            BreakerState::Closed => true,
+            BreakerState::Open if self.reset_elapsed(&breaker, now) => {
+                breaker.state = BreakerState::HalfOpen;
+                breaker.trial_in_flight = true;
+                debug!("Circuit breaker half-open: next_hop={:x?}", &next_hop[..8]);
+                true
+            }
+            BreakerState::Open => false,
+            BreakerState::HalfOpen if breaker.trial_in_flight => false,
+            BreakerState::HalfOpen => {
+                breaker.trial_in_flight = true;
+                true
+            }
+        }
+    }
+
+    /// Record a successful send to `next_hop`, closing its breaker
+    pub fn record_success(&self, next_hop: &NodeId) {
+        if let Some((_, breaker)) = self.breakers.remove(next_hop) {
+            if breaker.state != BreakerState::Closed {
+                info!("Circuit breaker closed: next_hop={:x?}", &next_hop[..8]);
+            }
+        }
+    }
+
+    /// Record a failed send to `next_hop`
+    ///
+    /// Returns true if this failure tripped the breaker open.
+    pub fn record_failure(&self, next_hop: &NodeId, now: u64) -> bool {
+        if !self.config.enabled() {
+            return false;
+        }
+        let mut breaker = self.breakers.entry(*next_hop).or_insert_with(Breaker::closed);
+        breaker.consecutive_failures = breaker.consecutive_failures.saturating_add(1);
+        let trips = match breaker.state {
+            BreakerState::Closed => breaker.consecutive_failures >= self.config.failure_threshold,
+            BreakerState::HalfOpen => true,
+            BreakerState::Open => false,
+        };
+        if trips {
+            breaker.state = BreakerState::Open;
+            breaker.opened_at = now;
+            breaker.trial_in_flight = false;
+            info!(
+                "Circuit breaker opened after {} failures: next_hop={:x?}",
+                breaker.consecutive_failures,
+                &next_hop[..8]
+            );
+        }
+        trips
+    }
+
+    /// Next hops whose breaker is not closed
+    pub fn open_count(&self) -> usize {
+        self.breakers
+            .iter()
+            .filter(|breaker| breaker.state != BreakerState::Closed)
+            .count()
+    }
+
+    fn reset_elapsed(&self, breaker: &Breaker, now: u64) -> bool {
+        now.saturating_sub(breaker.opened_at) >= self.config.reset_timeout_secs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOP: NodeId = [1u8; 32];
+
+    fn breaker() -> CircuitBreaker {
+        CircuitBreaker::new(CircuitBreakerConfig {
+            failure_threshold: 3,
+            reset_timeout_secs: 30,
+        })
+    }
+
+    #[test]
+    fn test_opens_after_threshold_and_recovers() {
+        let breaker = breaker();
+        assert!(!breaker.record_failure(&HOP, 100));
+        assert!(!breaker.record_failure(&HOP, 100));
+        assert!(breaker.allow(&HOP, 100));
+        assert!(breaker.record_failure(&HOP, 100));
+        assert_eq!(breaker.state(&HOP, 100), BreakerState::Open);
+        assert!(!breaker.allow(&HOP, 129));
+
+        // One trial after the reset timeout
+        assert_eq!(breaker.state(&HOP, 130), BreakerState::HalfOpen);
+        assert!(breaker.allow(&HOP, 130));
+        assert!(!breaker.allow(&HOP, 130));
+        breaker.record_success(&HOP);
+        assert_eq!(breaker.state(&HOP, 130), BreakerState::Closed);
+        assert_eq!(breaker.open_count(), 0);
+    }
+
+    #[test]
+    fn test_failed_trial_reopens() {
+        let breaker = breaker();
+        for _ in 0..3 {
+            breaker.record_failure(&HOP, 100);
+        }
+        assert!(breaker.allow(&HOP, 130));
+        assert!(breaker.record_failure(&HOP, 130));
+        assert!(!breaker.allow(&HOP, 159));
+        assert!(breaker.allow(&HOP, 160));
+
+        // A success resets the failure count
+        breaker.record_success(&HOP);
+        assert!(!breaker.record_failure(&HOP, 161));
+        assert!(breaker.allow(&HOP, 161));
+    }
+}
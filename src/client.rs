@@ -18,6 +18,7 @@ pub struct ModuleClient {
     module_name: String,
     version: String,
     event_receiver: mpsc::Receiver<ModuleMessage>,
+    request_receiver: Option<mpsc::Receiver<RequestMessage>>,
 }
 
 impl ModuleClient {
@@ -66,6 +67,8 @@ impl ModuleClient {
 
         // Create event channel
         let (event_tx, event_rx) = mpsc::channel(1000);
+        // Requests from the node (RPC invocations)
+        let (request_tx, request_rx) = mpsc::channel(100);
 
         // Spawn event receiver task
         let ipc_client_arc = Arc::new(tokio::sync::Mutex::new(ipc_client));
@@ -79,8 +82,13 @@ impl ModuleClient {
                             break; // Receiver dropped
                         }
                     }
+                    Ok(Some(ModuleMessage::Request(request))) => {
+                        if request_tx.try_send(request).is_err() {
+                            warn!("Dropping node request for module {}: queue full", module_id_for_events);
+                        }
+                    }
                     Ok(Some(_)) => {
-                        // Non-event message - ignore
+                        // Other message - ignore
                     }
                     Ok(None) => {
                         // No event available - continue
@@ -99,6 +107,7 @@ impl ModuleClient {
             module_name,
             version,
             event_receiver: event_rx,
+            request_receiver: Some(request_rx),
         })
     }
 
@@ -127,6 +136,11 @@ impl ModuleClient {
         &mut self.event_receiver
     }
 
+    /// Take the receiver of requests sent by the node (once)
+    pub fn take_request_receiver(&mut self) -> Option<mpsc::Receiver<RequestMessage>> {
+        self.request_receiver.take()
+    }
+
     /// Send a log message to the node
     pub async fn log(
         &self,
@@ -0,0 +1,63 @@
+//! Time source for mesh timers
+//!
+//! Production code reads the system clock. Tests and the in-process cluster
+//! harness use a manually advanced clock so time-driven behaviour (billing
+//! periods, timeouts, expiry) is deterministic.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Source of the current Unix time in seconds
+pub trait Clock: Send + Sync {
+    /// Current Unix time in seconds
+    fn now_secs(&self) -> u64;
+}
+
+/// Wall clock
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_secs(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+}
+
+/// Clock that only moves when told to
+#[derive(Debug, Default)]
+pub struct ManualClock {
+    now: AtomicU64,
+}
+
+impl ManualClock {
+    /// Create a clock starting at the given Unix time
+    pub fn new(start_secs: u64) -> Self {
+        Self {
+            now: AtomicU64::new(start_secs),
+        }
+    }
+
+    /// Create a clock starting at the current wall-clock time
+    pub fn starting_now() -> Self {
+        Self::new(SystemClock.now_secs())
+    }
+
+    /// Move the clock forward
+    pub fn advance(&self, secs: u64) {
+        self.now.fetch_add(secs, Ordering::SeqCst);
+    }
+
+    /// Set the clock to an absolute time
+    pub fn set(&self, secs: u64) {
+        self.now.store(secs, Ordering::SeqCst);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now_secs(&self) -> u64 {
+        self.now.load(Ordering::SeqCst)
+    }
+}
@@ -0,0 +1,171 @@
+//! zstd compression of large paid payloads
+//!
+//! Bulk data (IPFS blocks, file transfers) travels as `PacketType::Paid`
+//! packets. When such a payload exceeds `mesh.compression.threshold_bytes`,
+//! the origin marks the packet `compressed` and `serialize_mesh_packet`
+//! compresses the payload at `mesh.compression.level` on the way out.
+//! Receivers decompress while decoding, so everything above the wire
+//! (signatures, delivery, applications) sees the original bytes.
+
+use crate::error::MeshError;
+use crate::packet::{MeshPacket, PacketType, MAX_PACKET_SIZE};
+
+/// Default smallest payload worth compressing (`mesh.compression.threshold_bytes`)
+pub const DEFAULT_COMPRESSION_THRESHOLD: usize = 1024;
+
+/// Default zstd level (`mesh.compression.level`)
+pub const DEFAULT_COMPRESSION_LEVEL: i32 = 3;
+
+/// Payload compression settings (`mesh.compression.*`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompressionConfig {
+    /// Whether originated paid packets are compressed at all
+    pub enabled: bool,
+    /// Payloads up to this many bytes are sent as-is
+    pub threshold_bytes: usize,
+    /// zstd level (1-22; higher is smaller and slower)
+    pub level: i32,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            threshold_bytes: DEFAULT_COMPRESSION_THRESHOLD,
+            level: DEFAULT_COMPRESSION_LEVEL,
+        }
+    }
+}
+
+impl CompressionConfig {
+    /// Load from module context config keys
+    pub fn from_context(ctx: &bllvm_node::module::traits::ModuleContext) -> Self {
+        let defaults = Self::default();
+        Self {
+            enabled: ctx.get_config_or("mesh.compression.enabled", "true") == "true",
+            threshold_bytes: ctx
+                .get_config_or("mesh.compression.threshold_bytes", &defaults.threshold_bytes.to_string())
+                .parse()
+                .unwrap_or(defaults.threshold_bytes),
+            level: ctx
+                .get_config_or("mesh.compression.level", &defaults.level.to_string())
+                .parse::<i32>()
+                .map(|level| level.clamp(1, 22))
+                .unwrap_or(defaults.level),
+        }
+    }
+}
+
+impl MeshPacket {
+    /// Mark the payload for compression on the wire
+    ///
+    /// Only `Paid` packets with a payload over `config.threshold_bytes` are
+    /// compressed, and only when zstd actually shrinks them. Returns the
+    /// number of bytes the payload takes on the wire.
+    pub fn compress(&mut self, config: &CompressionConfig) -> Result<usize, MeshError> {
+        if !config.enabled
+            || self.packet_type != PacketType::Paid
+            || self.payload.len() <= config.threshold_bytes
+        {
+            self.decompress();
+            return Ok(self.payload.len());
+        }
+
+        let compressed = compress_payload(&self.payload, config.level)?;
+        if compressed.len() >= self.payload.len() {
+            self.decompress();
+            return Ok(self.payload.len());
+        }
+        self.compressed = true;
+        self.compression_level = config.level;
+        Ok(compressed.len())
+    }
+
+    /// Send the payload uncompressed
+    pub fn decompress(&mut self) {
+        self.compressed = false;
+        self.compression_level = 0;
+    }
+}
+
+/// Compress a payload at a zstd level (0 selects zstd's default)
+pub fn compress_payload(payload: &[u8], level: i32) -> Result<Vec<u8>, MeshError> {
+    zstd::bulk::compress(payload, level)
+        .map_err(|e| MeshError::InvalidPacket(format!("Failed to compress payload: {}", e)))
+}
+
+/// Decompress a payload, refusing anything that expands past `MAX_PACKET_SIZE`
+pub fn decompress_payload(payload: &[u8]) -> Result<Vec<u8>, MeshError> {
+    zstd::bulk::decompress(payload, MAX_PACKET_SIZE)
+        .map_err(|e| MeshError::InvalidPacket(format!("Failed to decompress payload: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::MeshPacketBuilder;
+    use crate::payment_proof::PaymentProof;
+
+    fn paid_packet(payload: Vec<u8>) -> MeshPacket {
+        let proof = PaymentProof::Keysend {
+            payment_hash: [0u8; 32],
+            preimage: [0u8; 32],
+            amount_msats: 1000,
+            timestamp: 0,
+            custom_tlv: Vec::new(),
+            destination_pubkey: vec![2u8; 33],
+        };
+        MeshPacketBuilder::new([1u8; 32], [2u8; 32])
+            .packet_type(PacketType::Paid)
+            .payload(payload)
+            .payment_proof(proof)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_only_large_paid_payloads_compress() {
+        let config = CompressionConfig::default();
+
+        let mut small = paid_packet(vec![0u8; DEFAULT_COMPRESSION_THRESHOLD]);
+        assert_eq!(small.compress(&config).unwrap(), DEFAULT_COMPRESSION_THRESHOLD);
+        assert!(!small.compressed);
+
+        let mut unpaid = MeshPacketBuilder::new([1u8; 32], [2u8; 32])
+            .packet_type(PacketType::BitcoinP2P)
+            .payload(vec![0u8; 4096])
+            .build()
+            .unwrap();
+        assert_eq!(unpaid.compress(&config).unwrap(), 4096);
+        assert!(!unpaid.compressed);
+
+        let mut large = paid_packet(vec![0u8; 4096]);
+        assert!(large.compress(&config).unwrap() < 4096);
+        assert!(large.compressed);
+        assert_eq!(large.payload, vec![0u8; 4096]);
+    }
+
+    #[test]
+    fn test_incompressible_payload_sent_as_is() {
+        // An xorshift stream does not shrink
+        let mut state = 0x9e37_79b9_7f4a_7c15u64;
+        let payload: Vec<u8> = (0..4096)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect();
+        let mut packet = paid_packet(payload);
+
+        assert_eq!(packet.compress(&CompressionConfig::default()).unwrap(), 4096);
+        assert!(!packet.compressed);
+    }
+
+    #[test]
+    fn test_decompression_is_bounded() {
+        let bomb = compress_payload(&vec![0u8; MAX_PACKET_SIZE + 1], 3).unwrap();
+        assert!(decompress_payload(&bomb).is_err());
+    }
+}
@@ -0,0 +1,72 @@
+//! Runtime changes to the stored mesh configuration
+//!
+//! `MeshManager::set_mode` stores the mode in the `mesh_config` tree, where
+//! it overrides `mesh.mode` on restart. The key can also be written by an
+//! operator tool sharing the node's storage; every
+//! `CONFIG_POLL_INTERVAL_SECONDS` the manager has a `ConfigWatcher` read it
+//! back, switch the routing policy engine to a changed mode and publish
+//! `EventType::ConfigChanged` so other modules can react, without a restart.
+
+use crate::routing_policy::{MeshMode, RoutingPolicyEngine};
+use bllvm_node::module::traits::{EventPayload, EventType, NodeAPI};
+use std::sync::Arc;
+use tracing::{info, warn};
+
+/// Interval between reads of the stored configuration
+pub const CONFIG_POLL_INTERVAL_SECONDS: u64 = 30;
+
+/// Storage tree holding the configuration set at runtime
+pub const CONFIG_TREE: &str = "mesh_config";
+
+/// `mesh_config` key of the mode last set at runtime (overrides `mesh.mode`)
+pub const MODE_STORAGE_KEY: &[u8] = b"mode";
+
+/// Applies mode changes written to the `mesh_config` tree
+pub struct ConfigWatcher {
+    node_api: Arc<dyn NodeAPI>,
+    routing_policy: Arc<RoutingPolicyEngine>,
+}
+
+impl ConfigWatcher {
+    /// Create a watcher switching the mode of a shared routing policy engine
+    pub fn new(node_api: Arc<dyn NodeAPI>, routing_policy: Arc<RoutingPolicyEngine>) -> Self {
+        Self {
+            node_api,
+            routing_policy,
+        }
+    }
+
+    /// Read the stored mode once and apply it if it changed
+    ///
+    /// Returns the new mode, or None if no mode is stored or it matches the
+    /// engine's. A failure to publish the event is logged; the mode is
+    /// switched regardless.
+    pub async fn poll(&self) -> Option<MeshMode> {
+        let mode = load_stored_mode(self.node_api.as_ref()).await?;
+        let previous = self.routing_policy.set_mode(mode);
+        if previous == mode {
+            return None;
+        }
+        info!("Stored mesh mode changed: {:?} → {:?}", previous, mode);
+
+        let payload = EventPayload::ConfigChanged {
+            key: "mesh.mode".to_string(),
+            value: mode.as_str().to_string(),
+        };
+        if let Err(e) = self.node_api.publish_event(EventType::ConfigChanged, payload).await {
+            warn!("Failed to publish mesh mode change: {}", e);
+        }
+        Some(mode)
+    }
+}
+
+/// Mode stored in the `mesh_config` tree, if any
+pub async fn load_stored_mode(node_api: &dyn NodeAPI) -> Option<MeshMode> {
+    let tree_id = node_api.storage_open_tree(CONFIG_TREE.to_string()).await.ok()?;
+    let stored = node_api.storage_get(tree_id, MODE_STORAGE_KEY.to_vec()).await.ok()??;
+    let mode = std::str::from_utf8(&stored).ok().and_then(MeshMode::from_name);
+    if mode.is_none() {
+        warn!("Ignoring malformed stored mesh mode");
+    }
+    mode
+}
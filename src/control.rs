@@ -0,0 +1,82 @@
+//! Mesh control messages
+//!
+//! Carried as the payload of `PacketType::Control` packets. Hellos,
+//! keepalives and latency probes stay between direct peers; delivery acks and
+//! constraint errors travel back to the packet source, HTLC claims from the source to the relays of an escrowed packet,
+//! and subscriptions to the node asked to push data.
+//! Control traffic is never payment-gated.
+
+use crate::error::MeshError;
+use crate::routing::NodeId;
+use crate::signing::SigningPublicKey;
+use crate::subscriptions::MeshTopic;
+use serde::{Deserialize, Serialize};
+
+/// Control message carried in a `PacketType::Control` packet
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ControlMessage {
+    /// Announcement of a newly connected direct peer's identity, in a packet
+    /// signed with `pubkey`
+    ///
+    /// With Noise sessions, `static_key` is the sender's session static key;
+    /// the signature binds it to `pubkey` and so to `node_id`.
+    Hello {
+        node_id: NodeId,
+        pubkey: SigningPublicKey,
+        listen_addrs: Vec<String>,
+        static_key: Option<[u8; 32]>,
+    },
+    /// Liveness probe for an idle link
+    Keepalive {
+        nonce: u64,
+    },
+    /// Response to a keepalive probe
+    KeepaliveAck {
+        nonce: u64,
+    },
+    /// Round-trip latency probe (see `crate::latency`)
+    Probe {
+        nonce: u64,
+        /// Sender's clock when the probe was sent (Unix microseconds)
+        sent_at_us: u64,
+    },
+    /// Answer to a latency probe, echoing its nonce and send time
+    ProbeReply {
+        nonce: u64,
+        sent_at_us: u64,
+        /// Responder's clock when it answered (Unix microseconds)
+        echo_at_us: u64,
+    },
+    /// Source reveals the preimage of an escrow HTLC to a relay once the
+    /// escrowed packet was delivered (see `verifier::HtlcVerifier`)
+    HtlcClaim {
+        payment_hash: [u8; 32],
+        preimage: [u8; 32],
+    },
+    /// A relay tells the source it has no route to `destination` that
+    /// satisfies the packet's route constraints, so the packet was dropped
+    ConstraintUnsatisfiable {
+        sequence: u64,
+        destination: NodeId,
+    },
+    /// Ask the receiver to push data on these topics to the sender,
+    /// replacing any earlier subscription (empty = unsubscribe; see
+    /// `crate::subscriptions`)
+    Subscribe {
+        topics: Vec<MeshTopic>,
+    },
+}
+
+impl ControlMessage {
+    /// Encode as a packet payload
+    pub fn encode(&self) -> Result<Vec<u8>, MeshError> {
+        bincode::serialize(self)
+            .map_err(|e| MeshError::InvalidPacket(format!("Failed to encode control message: {}", e)))
+    }
+
+    /// Decode from a packet payload
+    pub fn decode(data: &[u8]) -> Result<Self, MeshError> {
+        bincode::deserialize(data)
+            .map_err(|e| MeshError::InvalidPacket(format!("Malformed control message: {}", e)))
+    }
+}
@@ -0,0 +1,184 @@
+//! Incoming packet deduplication
+//!
+//! With several paths through the mesh, the same packet (same source and
+//! sequence number) can arrive more than once. `DeduplicationCache`
+//! remembers when each (source, sequence) pair was first handled, for
+//! `mesh.dedup_window_seconds`, so later copies are dropped before they are
+//! verified, paid for, forwarded or delivered again. Entries expire from the
+//! front of an insertion-ordered queue like `ReplayPrevention`'s proof
+//! hashes, with `cleanup_expired` as the periodic backstop.
+//!
+//! Packets without a sequence number (older nodes) are never suppressed.
+//! The cache is in memory only; retransmissions after a restart are left to
+//! the delivery ledger (`crate::delivery_ledger`).
+
+use crate::routing::NodeId;
+use dashmap::DashMap;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use tracing::debug;
+
+/// Default deduplication window (`mesh.dedup_window_seconds`)
+pub const DEFAULT_DEDUP_WINDOW_SECONDS: u64 = 600;
+
+/// (source, sequence) of a packet
+type PacketKey = (NodeId, u64);
+
+/// Recently handled (source, sequence) pairs
+pub struct DeduplicationCache {
+    /// (source, sequence) -> first seen
+    /// Lock-free concurrent access using DashMap
+    seen: DashMap<PacketKey, u64>,
+    /// (first seen, key) in insertion order, oldest first
+    ///
+    /// May hold stale pairs for keys re-inserted later; those are skipped
+    /// when popped.
+    expiry_queue: Mutex<VecDeque<(u64, PacketKey)>>,
+    /// How long a pair suppresses copies (0 = deduplication disabled)
+    window_seconds: u64,
+}
+
+impl DeduplicationCache {
+    /// Create an empty cache
+    pub fn new(window_seconds: u64) -> Self {
+        Self {
+            seen: DashMap::new(),
+            expiry_queue: Mutex::new(VecDeque::new()),
+            window_seconds,
+        }
+    }
+
+    /// Window in seconds
+    pub fn window_seconds(&self) -> u64 {
+        self.window_seconds
+    }
+
+    /// Whether a packet was already handled within the window
+    pub fn is_duplicate(&self, source: &NodeId, sequence: u64, now: u64) -> bool {
+        if sequence == 0 {
+            return false;
+        }
+        self.seen
+            .get(&(*source, sequence))
+            .map(|first_seen| now.saturating_sub(*first_seen) < self.window_seconds)
+            .unwrap_or(false)
+    }
+
+    /// Record a packet as handled; false if it already was within the window
+    ///
+    /// Checks and inserts atomically, so of two copies arriving at the same
+    /// time only one is recorded.
+    pub fn record(&self, source: &NodeId, sequence: u64, now: u64) -> bool {
+        if sequence == 0 || self.window_seconds == 0 {
+            return true;
+        }
+        self.expire_queued(now);
+
+        let key = (*source, sequence);
+        match self.seen.entry(key) {
+            dashmap::mapref::entry::Entry::Occupied(mut entry) => {
+                if now.saturating_sub(*entry.get()) < self.window_seconds {
+                    return false;
+                }
+                entry.insert(now);
+            }
+            dashmap::mapref::entry::Entry::Vacant(entry) => {
+                entry.insert(now);
+            }
+        }
+        self.expiry_queue.lock().unwrap().push_back((now, key));
+        true
+    }
+
+    /// Number of remembered packets
+    pub fn len(&self) -> usize {
+        self.seen.len()
+    }
+
+    /// Whether no packets are remembered
+    pub fn is_empty(&self) -> bool {
+        self.seen.is_empty()
+    }
+
+    /// Forget packets first seen outside the window
+    ///
+    /// Scans the whole table; `record` expires entries incrementally, so
+    /// this is only a periodic backstop.
+    pub fn cleanup_expired(&self, now: u64) {
+        let before = self.seen.len();
+        self.seen
+            .retain(|_, first_seen| now.saturating_sub(*first_seen) < self.window_seconds);
+        self.expiry_queue
+            .lock()
+            .unwrap()
+            .retain(|(first_seen, _)| now.saturating_sub(*first_seen) < self.window_seconds);
+        let removed = before.saturating_sub(self.seen.len());
+        if removed > 0 {
+            debug!("Cleaned up {} expired deduplication entries", removed);
+        }
+    }
+
+    /// Expire entries from the front of the queue (amortized O(1))
+    fn expire_queued(&self, now: u64) {
+        let mut queue = self.expiry_queue.lock().unwrap();
+        while let Some(&(first_seen, key)) = queue.front() {
+            if now.saturating_sub(first_seen) < self.window_seconds {
+                break;
+            }
+            queue.pop_front();
+            // Unless it was removed or re-inserted since
+            self.seen.remove_if(&key, |_, seen_at| *seen_at == first_seen);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: NodeId = [1u8; 32];
+
+    #[test]
+    fn test_copy_within_window_is_duplicate() {
+        let cache = DeduplicationCache::new(60);
+        assert!(!cache.is_duplicate(&SOURCE, 7, 1_000));
+        assert!(cache.record(&SOURCE, 7, 1_000));
+
+        assert!(cache.is_duplicate(&SOURCE, 7, 1_059));
+        assert!(!cache.record(&SOURCE, 7, 1_059));
+        assert!(!cache.is_duplicate(&SOURCE, 8, 1_059));
+        assert!(!cache.is_duplicate(&[2u8; 32], 7, 1_059));
+
+        // Outside the window the pair is new again
+        assert!(!cache.is_duplicate(&SOURCE, 7, 1_060));
+        assert!(cache.record(&SOURCE, 7, 1_060));
+    }
+
+    #[test]
+    fn test_unsequenced_packets_and_zero_window_never_suppressed() {
+        let cache = DeduplicationCache::new(60);
+        assert!(cache.record(&SOURCE, 0, 1_000));
+        assert!(cache.record(&SOURCE, 0, 1_000));
+        assert!(!cache.is_duplicate(&SOURCE, 0, 1_000));
+
+        let disabled = DeduplicationCache::new(0);
+        assert!(disabled.record(&SOURCE, 7, 1_000));
+        assert!(disabled.record(&SOURCE, 7, 1_000));
+        assert!(disabled.is_empty());
+    }
+
+    #[test]
+    fn test_entries_expire() {
+        let cache = DeduplicationCache::new(60);
+        cache.record(&SOURCE, 1, 1_000);
+        cache.record(&SOURCE, 2, 1_030);
+
+        // Recording expires from the front of the queue
+        cache.record(&SOURCE, 3, 1_070);
+        assert_eq!(cache.len(), 2);
+
+        cache.cleanup_expired(1_100);
+        assert_eq!(cache.len(), 1);
+        assert!(cache.is_duplicate(&SOURCE, 3, 1_100));
+    }
+}
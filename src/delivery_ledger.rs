@@ -0,0 +1,184 @@
+//! At-most-once local delivery ledger
+//!
+//! Records `(source, sequence, payload_hash)` of every packet handed to the
+//! local delivery queue in a bounded, persistent set. A retransmission after
+//! a crash (delivered, but the ack never left) is recognized and suppressed
+//! instead of reaching the application twice; the caller still acks it so
+//! the sender stops retrying.
+
+use crate::error::MeshError;
+use crate::packet::MeshPacket;
+use crate::storage_schema::{open_versioned_tree, tag_only, Migration, TreeSchema};
+use bllvm_node::module::traits::NodeAPI;
+use dashmap::DashMap;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use tracing::{debug, warn};
+
+/// Storage tree for the delivery ledger
+const DELIVERY_LEDGER_TREE: &str = "mesh_delivery_ledger";
+
+/// Schema of the delivery ledger tree (v1: first versioned release, format unchanged)
+pub const DELIVERY_LEDGER_SCHEMA: TreeSchema = TreeSchema {
+    tree: DELIVERY_LEDGER_TREE,
+    version: 1,
+    migrations: &[Migration {
+        from: 0,
+        description: "start tracking schema version",
+        migrate: tag_only,
+    }],
+};
+
+/// Default maximum number of remembered deliveries
+pub const DEFAULT_MAX_LEDGER_ENTRIES: usize = 100_000;
+
+/// Ledger key: source (32) + sequence (8, big-endian) + payload hash (32)
+pub type DeliveryKey = [u8; 72];
+
+/// Build the ledger key for a packet
+pub fn delivery_key(packet: &MeshPacket) -> DeliveryKey {
+    let mut key = [0u8; 72];
+    key[..32].copy_from_slice(&packet.source);
+    key[32..40].copy_from_slice(&packet.sequence.to_be_bytes());
+    key[40..].copy_from_slice(&Sha256::digest(&packet.payload));
+    key
+}
+
+/// Bounded persistent set of delivered packets
+pub struct DeliveryLedger {
+    node_api: Arc<dyn NodeAPI>,
+    /// Storage tree ID (None if storage is unavailable)
+    tree_id: Option<String>,
+    /// Delivered packets (key -> delivered at)
+    /// Lock-free concurrent access using DashMap
+    entries: DashMap<DeliveryKey, u64>,
+    /// Entries older than this are forgotten (matches the replay window)
+    ttl_seconds: u64,
+    /// Maximum number of entries kept
+    max_entries: usize,
+}
+
+impl DeliveryLedger {
+    /// Open the ledger, restoring unexpired entries from storage
+    ///
+    /// Fails only if the stored schema is newer than this build.
+    pub async fn load(
+        node_api: Arc<dyn NodeAPI>,
+        ttl_seconds: u64,
+        max_entries: usize,
+        now: u64,
+    ) -> Result<Self, MeshError> {
+        let tree_id = match open_versioned_tree(node_api.as_ref(), &DELIVERY_LEDGER_SCHEMA).await {
+            Ok(tree_id) => Some(tree_id),
+            Err(e @ MeshError::UnsupportedVersion(_)) => return Err(e),
+            Err(e) => {
+                warn!("Delivery ledger storage unavailable, deduplicating in memory only: {}", e);
+                None
+            }
+        };
+
+        let ledger = Self {
+            node_api,
+            tree_id,
+            entries: DashMap::new(),
+            ttl_seconds,
+            max_entries: max_entries.max(1),
+        };
+
+        if let Some(ref tree_id) = ledger.tree_id {
+            match ledger.node_api.storage_iter(tree_id.clone()).await {
+                Ok(stored) => {
+                    for (key, value) in stored {
+                        let (Ok(key), Ok(delivered_at)) = (
+                            DeliveryKey::try_from(key.as_slice()),
+                            <[u8; 8]>::try_from(value.as_slice()).map(u64::from_be_bytes),
+                        ) else {
+                            continue;
+                        };
+                        if now.saturating_sub(delivered_at) < ttl_seconds {
+                            ledger.entries.insert(key, delivered_at);
+                        }
+                    }
+                    debug!("Restored {} delivery ledger entries", ledger.entries.len());
+                }
+                Err(e) => warn!("Failed to restore delivery ledger: {}", e),
+            }
+        }
+
+        Ok(ledger)
+    }
+
+    /// Whether a packet was already delivered within the TTL
+    pub fn contains(&self, key: &DeliveryKey, now: u64) -> bool {
+        self.entries
+            .get(key)
+            .map(|delivered_at| now.saturating_sub(*delivered_at) < self.ttl_seconds)
+            .unwrap_or(false)
+    }
+
+    /// Record a delivery, persisting it before returning
+    ///
+    /// Callers must only ack after this returns, so an ack never precedes
+    /// the record that suppresses the retransmission it prevents.
+    pub async fn record(&self, key: DeliveryKey, now: u64) -> Result<(), MeshError> {
+        if self.entries.len() >= self.max_entries {
+            self.evict_oldest().await;
+        }
+        self.entries.insert(key, now);
+
+        if let Some(ref tree_id) = self.tree_id {
+            self.node_api
+                .storage_insert(tree_id.clone(), key.to_vec(), now.to_be_bytes().to_vec())
+                .await
+                .map_err(|e| MeshError::ModuleError(format!("Failed to persist delivery record: {}", e)))?;
+        }
+        Ok(())
+    }
+
+    /// Forget expired entries
+    pub async fn cleanup_expired(&self, now: u64) {
+        let expired: Vec<DeliveryKey> = self
+            .entries
+            .iter()
+            .filter(|entry| now.saturating_sub(*entry.value()) >= self.ttl_seconds)
+            .map(|entry| *entry.key())
+            .collect();
+        for key in &expired {
+            self.remove(key).await;
+        }
+        if !expired.is_empty() {
+            debug!("Cleaned up {} expired delivery ledger entries", expired.len());
+        }
+    }
+
+    /// Number of remembered deliveries
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether the ledger is empty
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Drop the oldest entry to stay within `max_entries`
+    async fn evict_oldest(&self) {
+        let oldest = self
+            .entries
+            .iter()
+            .min_by_key(|entry| *entry.value())
+            .map(|entry| *entry.key());
+        if let Some(key) = oldest {
+            self.remove(&key).await;
+        }
+    }
+
+    async fn remove(&self, key: &DeliveryKey) {
+        self.entries.remove(key);
+        if let Some(ref tree_id) = self.tree_id {
+            if let Err(e) = self.node_api.storage_remove(tree_id.clone(), key.to_vec()).await {
+                warn!("Failed to remove delivery ledger entry: {}", e);
+            }
+        }
+    }
+}
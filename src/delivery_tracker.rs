@@ -0,0 +1,211 @@
+//! Delivery confirmation for paid and ack-requested packets
+//!
+//! The destination of a paid packet, or of one whose sender set
+//! `ACK_REQUESTED_FIELD`, answers with a `PacketType::Ack` whose payload
+//! names the packet by `(source, sequence)`. The ack travels back along the
+//! reverse of the recorded route and is always routed for free. Other
+//! packets are never acked. The sender tracks each such packet it originates
+//! until the ack arrives or the ack timeout passes.
+
+use crate::error::MeshError;
+use crate::routing::NodeId;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Default time to wait for an ack (seconds)
+pub const DEFAULT_ACK_TIMEOUT_SECS: u64 = 60;
+
+/// How long confirmed and timed-out deliveries stay queryable (seconds)
+pub const RESOLVED_RETENTION_SECS: u64 = 3600;
+
+/// Metadata field asking the destination to ack a free packet ("1")
+pub const ACK_REQUESTED_FIELD: &str = "ack";
+
+/// Payload of a `PacketType::Ack` packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AckPayload {
+    /// Source of the acknowledged packet
+    pub source: NodeId,
+    /// Sequence number of the acknowledged packet
+    pub sequence: u64,
+}
+
+impl AckPayload {
+    /// Encode as a packet payload
+    pub fn encode(&self) -> Result<Vec<u8>, MeshError> {
+        bincode::serialize(self).map_err(|e| MeshError::InvalidPacket(format!("Failed to encode ack: {}", e)))
+    }
+
+    /// Decode from a packet payload
+    pub fn decode(data: &[u8]) -> Result<Self, MeshError> {
+        bincode::deserialize(data).map_err(|e| MeshError::InvalidPacket(format!("Malformed ack: {}", e)))
+    }
+}
+
+/// Delivery state of a paid packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    /// Sent, no ack yet
+    Pending,
+    /// Acked by the destination
+    Confirmed,
+    /// No ack within the timeout
+    TimedOut,
+}
+
+/// Delivery confirmation counters
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeliveryStats {
+    /// Paid packets awaiting an ack
+    pub pending: usize,
+    /// Paid packets acked by their destination
+    pub confirmed: u64,
+    /// Paid packets not acked within the timeout
+    pub timed_out: u64,
+}
+
+/// A tracked paid packet
+#[derive(Debug, Clone, Copy)]
+struct TrackedDelivery {
+    destination: NodeId,
+    sent_at: u64,
+    status: DeliveryStatus,
+    /// When the status left `Pending`
+    resolved_at: u64,
+}
+
+/// Paid packets originated by this node, by sequence number
+pub struct PendingDeliveries {
+    entries: DashMap<u64, TrackedDelivery>,
+    timeout_secs: u64,
+    confirmed: AtomicU64,
+    timed_out: AtomicU64,
+}
+
+impl PendingDeliveries {
+    /// Create a tracker with the given ack timeout
+    pub fn new(timeout_secs: u64) -> Self {
+        Self {
+            entries: DashMap::new(),
+            timeout_secs,
+            confirmed: AtomicU64::new(0),
+            timed_out: AtomicU64::new(0),
+        }
+    }
+
+    /// Start waiting for the ack of a packet sent to `destination`
+    pub fn track(&self, sequence: u64, destination: NodeId, now: u64) {
+        self.entries.insert(
+            sequence,
+            TrackedDelivery {
+                destination,
+                sent_at: now,
+                status: DeliveryStatus::Pending,
+                resolved_at: 0,
+            },
+        );
+    }
+
+    /// Record an ack from `from`; false if nothing was waiting for it
+    ///
+    /// Only the packet's destination can confirm it, and only before the
+    /// timeout.
+    pub fn confirm(&self, sequence: u64, from: &NodeId, now: u64) -> bool {
+        self.expire(now);
+        let Some(mut entry) = self.entries.get_mut(&sequence) else {
+            return false;
+        };
+        if entry.status != DeliveryStatus::Pending || entry.destination != *from {
+            return false;
+        }
+        entry.status = DeliveryStatus::Confirmed;
+        entry.resolved_at = now;
+        self.confirmed.fetch_add(1, Ordering::Relaxed);
+        true
+    }
+
+    /// Status and destination of a tracked packet
+    pub fn status(&self, sequence: u64, now: u64) -> Option<(DeliveryStatus, NodeId)> {
+        self.expire(now);
+        self.entries
+            .get(&sequence)
+            .map(|entry| (entry.status, entry.destination))
+    }
+
+    /// Time out overdue packets and forget long-resolved ones
+    ///
+    /// Returns the number of packets that timed out.
+    pub fn expire(&self, now: u64) -> usize {
+        let mut expired = 0;
+        self.entries.retain(|_, entry| {
+            if entry.status == DeliveryStatus::Pending && now.saturating_sub(entry.sent_at) >= self.timeout_secs {
+                entry.status = DeliveryStatus::TimedOut;
+                entry.resolved_at = now;
+                expired += 1;
+            }
+            entry.status == DeliveryStatus::Pending
+                || now.saturating_sub(entry.resolved_at) < RESOLVED_RETENTION_SECS
+        });
+        self.timed_out.fetch_add(expired as u64, Ordering::Relaxed);
+        expired
+    }
+
+    /// Current counters
+    pub fn stats(&self) -> DeliveryStats {
+        DeliveryStats {
+            pending: self
+                .entries
+                .iter()
+                .filter(|entry| entry.status == DeliveryStatus::Pending)
+                .count(),
+            confirmed: self.confirmed.load(Ordering::Relaxed),
+            timed_out: self.timed_out.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DESTINATION: NodeId = [9u8; 32];
+
+    #[test]
+    fn test_confirm_only_by_destination_before_timeout() {
+        let deliveries = PendingDeliveries::new(60);
+        deliveries.track(1, DESTINATION, 1_000);
+        deliveries.track(2, DESTINATION, 1_000);
+
+        assert!(!deliveries.confirm(1, &[8u8; 32], 1_010));
+        assert!(deliveries.confirm(1, &DESTINATION, 1_010));
+        assert!(!deliveries.confirm(1, &DESTINATION, 1_011));
+        assert_eq!(deliveries.status(1, 1_011), Some((DeliveryStatus::Confirmed, DESTINATION)));
+
+        // Too late
+        assert!(!deliveries.confirm(2, &DESTINATION, 1_060));
+        assert_eq!(deliveries.status(2, 1_060), Some((DeliveryStatus::TimedOut, DESTINATION)));
+        assert_eq!(
+            deliveries.stats(),
+            DeliveryStats {
+                pending: 0,
+                confirmed: 1,
+                timed_out: 1
+            }
+        );
+
+        // Resolved entries are forgotten after the retention period
+        assert_eq!(deliveries.status(1, 1_010 + RESOLVED_RETENTION_SECS), None);
+    }
+
+    #[test]
+    fn test_ack_payload_round_trip() {
+        let ack = AckPayload {
+            source: [1u8; 32],
+            sequence: 7,
+        };
+        assert_eq!(AckPayload::decode(&ack.encode().unwrap()).unwrap(), ack);
+        assert!(AckPayload::decode(&[1, 2]).is_err());
+    }
+}
@@ -1,14 +1,31 @@
 //! Route discovery protocol for mesh networking
 //!
 //! Implements route discovery using distance vector routing (simple, scalable later).
+//!
+//! Alongside it runs a link-state protocol: every node floods a signed list
+//! of its direct links, and each receiver keeps the latest list per origin as
+//! a topology graph. Routes are computed locally with Dijkstra over that
+//! graph, so advertised costs are never taken on trust and cannot form loops.
 
 use crate::error::MeshError;
-use crate::routing::{NodeId, RoutingEntry, RoutingTable};
+use crate::gossip::BloomFilter;
+use crate::rate_limiter::{RateLimitConfig, RateLimiter};
+use crate::routing::{shortest_path, NodeId, RouteGraph, RoutingEntry, RoutingTable};
+use crate::signing::{node_id_from_signing_key, signing_public_key, PeerKeys, SigningPublicKey};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::OsRng;
+use dashmap::DashMap;
+use secp256k1::schnorr::Signature;
+use secp256k1::{Keypair, Message, Secp256k1, XOnlyPublicKey};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use std::time::{SystemTime, UNIX_EPOCH};
-use tokio::sync::RwLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::{oneshot, RwLock};
+use tokio::time::Instant;
 use tracing::{debug, info, warn};
 
 /// Route discovery message types
@@ -19,7 +36,10 @@ pub enum DiscoveryMessage {
         destination: NodeId,
         source: NodeId,
         request_id: u64,
+        /// Hops the request may still be flooded
         max_hops: u8,
+        /// Nodes the request has traversed, starting with `source`
+        path: Vec<NodeId>,
     },
     /// Route response (route found)
     RouteResponse {
@@ -29,14 +49,180 @@ pub enum DiscoveryMessage {
         route: Vec<NodeId>,
         cost: u64,
     },
-    /// Route advertisement (announce routes to neighbors)
+    /// Route advertisement (announce routes to neighbors, gossiped on)
     RouteAdvertisement {
         routes: Vec<RouteAdvertisementEntry>,
         source: NodeId,
+        /// Increases with every advertisement round of `source`
+        sequence: u64,
+        /// Nodes that already have this advertisement (see `crate::gossip`)
+        seen: BloomFilter,
+    },
+    /// Link-state advertisement (origin's direct links, flooded unchanged)
+    LinkStateAdvertisement {
+        origin: NodeId,
+        /// Origin's signing key, for receivers that have not learned it
+        /// (`origin` must be its node ID, see `node_id_from_signing_key`)
+        origin_key: SigningPublicKey,
+        /// Increases with every advertisement from `origin`
+        sequence: u64,
+        links: Vec<LinkStateEntry>,
+        /// Schnorr signature by the origin's signing key
+        #[serde(with = "signature_bytes")]
+        signature: [u8; 64],
+    },
+    /// Route error: `from` can no longer reach these destinations
+    ///
+    /// Sent to the neighbors that learned routes to them through `from`,
+    /// which drop those routes and tell their own such neighbors in turn.
+    RouteError {
+        unreachable: Vec<NodeId>,
+        from: NodeId,
+    },
+    /// Route response or advertisement signed by the node that produced it
+    Signed(SignedDiscovery),
+    /// Route withdrawal: `source` is going offline
+    ///
+    /// Sent (signed) to the direct peers before disconnecting and flooded
+    /// on, so routes through `source` are dropped at once instead of
+    /// lingering until they expire.
+    RouteWithdrawal {
+        /// Destinations reached through `source` (itself included)
+        withdrawn_destinations: Vec<NodeId>,
+        source: NodeId,
+        /// Unix time of the withdrawal
+        timestamp: u64,
     },
 }
 
+impl DiscoveryMessage {
+    /// Encode as a `PacketType::Discovery` payload
+    pub fn encode(&self) -> Result<Vec<u8>, MeshError> {
+        bincode::serialize(self)
+            .map_err(|e| MeshError::InvalidPacket(format!("Failed to encode discovery message: {}", e)))
+    }
+
+    /// Decode from a `PacketType::Discovery` payload
+    pub fn decode(data: &[u8]) -> Result<Self, MeshError> {
+        bincode::deserialize(data)
+            .map_err(|e| MeshError::InvalidPacket(format!("Malformed discovery message: {}", e)))
+    }
+}
+
+/// Discovery message signed by its originator
+///
+/// Route responses are signed by the node that answered the request and
+/// route advertisements by their `source`, so relays cannot poison routes
+/// on the way. The `seen` filter of advertisements is not covered, because
+/// every gossip hop rewrites it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedDiscovery {
+    pub originator: NodeId,
+    /// Unix time the originator signed the message at
+    pub timestamp: u64,
+    pub message: Box<DiscoveryMessage>,
+    /// Schnorr signature by the originator's signing key
+    #[serde(with = "signature_bytes")]
+    pub signature: [u8; 64],
+}
+
+impl SignedDiscovery {
+    /// Sign a route response or advertisement as `originator`
+    pub fn sign(
+        message: DiscoveryMessage,
+        originator: NodeId,
+        timestamp: u64,
+        keypair: &Keypair,
+    ) -> Result<Self, MeshError> {
+        if !vouches_for(&message, &originator) {
+            return Err(MeshError::InvalidRequest(
+                "Only route responses and advertisements of the originator can be signed".to_string(),
+            ));
+        }
+        let digest = signed_discovery_digest(&originator, timestamp, &message)?;
+        let signature = Secp256k1::new().sign_schnorr_no_aux_rand(&digest, keypair);
+        Ok(Self {
+            originator,
+            timestamp,
+            message: Box::new(message),
+            signature: *signature.as_ref(),
+        })
+    }
+
+    /// Check the envelope against the originator's key in `peer_keys`
+    ///
+    /// Messages signed more than `MAX_DISCOVERY_AGE_SECONDS` away from `now`,
+    /// messages the originator could not have produced and bad signatures
+    /// are rejected. An originator whose key is not known yet (no Hello
+    /// received) yields `RouteTrust::Quarantined`.
+    pub fn verify(&self, peer_keys: &PeerKeys, now: u64) -> Result<RouteTrust, MeshError> {
+        if now.abs_diff(self.timestamp) > MAX_DISCOVERY_AGE_SECONDS {
+            return Err(MeshError::InvalidSignature(format!(
+                "Discovery message from {:x?} signed at {} is too old (now {})",
+                &self.originator[..8],
+                self.timestamp,
+                now
+            )));
+        }
+        if !vouches_for(&self.message, &self.originator) {
+            return Err(MeshError::InvalidSignature(format!(
+                "{:x?} cannot sign this discovery message",
+                &self.originator[..8]
+            )));
+        }
+        let Some(public_key) = peer_keys.get(&self.originator) else {
+            return Ok(RouteTrust::Quarantined);
+        };
+        let signature = Signature::from_slice(&self.signature)
+            .map_err(|e| MeshError::InvalidSignature(format!("Malformed signature: {}", e)))?;
+        let digest = signed_discovery_digest(&self.originator, self.timestamp, &self.message)?;
+        Secp256k1::new()
+            .verify_schnorr(&signature, &digest, &public_key)
+            .map_err(|_| {
+                MeshError::InvalidSignature(format!(
+                    "Discovery message does not match originator {:x?}",
+                    &self.originator[..8]
+                ))
+            })?;
+        Ok(RouteTrust::Verified)
+    }
+}
+
+/// How far the originator of a route response or advertisement is trusted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RouteTrust {
+    /// Signed by the originator's known key
+    Verified,
+    /// Unsigned (open mode only) or signed by an originator whose key is not
+    /// known yet; its routes get `QUARANTINED_ROUTE_QUALITY`
+    Quarantined,
+}
+
+impl RouteTrust {
+    /// Quality score for a route learned with this trust
+    fn quality(self, verified_quality: f64) -> f64 {
+        match self {
+            RouteTrust::Verified => verified_quality,
+            RouteTrust::Quarantined => QUARANTINED_ROUTE_QUALITY,
+        }
+    }
+}
+
+/// Outcome of looking up a route before sending
+#[derive(Debug, Clone)]
+pub enum RouteLookup {
+    /// A route is already known
+    Found(Vec<NodeId>),
+    /// No route is known; the caller should broadcast this request to its direct peers
+    Requested(DiscoveryMessage),
+    /// A request for this destination is already in flight
+    Pending,
+}
+
 /// Route advertisement entry
+///
+/// A `cost` of `WITHDRAWN_ROUTE_COST` withdraws a route the advertiser
+/// announced before.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RouteAdvertisementEntry {
     pub destination: NodeId,
@@ -45,29 +231,232 @@ pub struct RouteAdvertisementEntry {
     pub hop_count: u8,
 }
 
+/// Cost of a poisoned route advertisement entry (the route is withdrawn)
+pub const WITHDRAWN_ROUTE_COST: u64 = u64::MAX;
+
+/// Maximum routes in one route advertisement
+pub const MAX_ROUTE_ADVERTISEMENT_ENTRIES: usize = 256;
+
+/// Default interval between route advertisement rounds (`mesh.discovery.advertise_interval_secs`)
+pub const DEFAULT_ADVERTISE_INTERVAL_SECONDS: u64 = 60;
+
+/// Domain separator for signed discovery messages
+const SIGNED_DISCOVERY_CONTEXT: &[u8] = b"bllvm-mesh-discovery-v1";
+
+/// Maximum distance between a signed discovery message's timestamp and now
+pub const MAX_DISCOVERY_AGE_SECONDS: u64 = 5 * 60;
+
+/// Quality of routes learned from unverified originators
+pub const QUARANTINED_ROUTE_QUALITY: f64 = 0.4;
+
+/// Default route requests accepted per second from each neighbor (`mesh.discovery.request_rate`)
+pub const DEFAULT_DISCOVERY_REQUEST_RATE: u64 = 10;
+
+/// Default route request burst per neighbor (`mesh.discovery.request_burst`)
+pub const DEFAULT_DISCOVERY_REQUEST_BURST: u64 = 50;
+
+/// Default route requests this node may have pending (`mesh.discovery.max_pending`)
+pub const DEFAULT_MAX_PENDING_DISCOVERIES: usize = 256;
+
+/// Default route requests pending per destination (`mesh.discovery.max_pending_per_destination`)
+pub const DEFAULT_MAX_PENDING_PER_DESTINATION: usize = 4;
+
+/// Limits on the route discovery work peers and local callers can cause (`mesh.discovery.*`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscoveryLimits {
+    /// Route requests accepted per second from each neighbor
+    pub request_rate: u64,
+    /// Route requests a neighbor may send at once (0 = no rate limit)
+    pub request_burst: u64,
+    /// Route requests of this node pending at once (expired ones count until cleaned up)
+    pub max_pending: usize,
+    /// Route requests of this node pending at once for one destination
+    pub max_pending_per_destination: usize,
+}
+
+impl Default for DiscoveryLimits {
+    fn default() -> Self {
+        Self {
+            request_rate: DEFAULT_DISCOVERY_REQUEST_RATE,
+            request_burst: DEFAULT_DISCOVERY_REQUEST_BURST,
+            max_pending: DEFAULT_MAX_PENDING_DISCOVERIES,
+            max_pending_per_destination: DEFAULT_MAX_PENDING_PER_DESTINATION,
+        }
+    }
+}
+
+impl DiscoveryLimits {
+    /// Load from module context config keys
+    pub fn from_context(ctx: &bllvm_node::module::traits::ModuleContext) -> Self {
+        let defaults = Self::default();
+        Self {
+            request_rate: ctx
+                .get_config_or("mesh.discovery.request_rate", &defaults.request_rate.to_string())
+                .parse()
+                .unwrap_or(defaults.request_rate),
+            request_burst: ctx
+                .get_config_or("mesh.discovery.request_burst", &defaults.request_burst.to_string())
+                .parse()
+                .unwrap_or(defaults.request_burst),
+            max_pending: ctx
+                .get_config_or("mesh.discovery.max_pending", &defaults.max_pending.to_string())
+                .parse()
+                .unwrap_or(defaults.max_pending),
+            max_pending_per_destination: ctx
+                .get_config_or(
+                    "mesh.discovery.max_pending_per_destination",
+                    &defaults.max_pending_per_destination.to_string(),
+                )
+                .parse()
+                .unwrap_or(defaults.max_pending_per_destination),
+        }
+    }
+
+    /// Token bucket settings of the per-neighbor request limiter
+    fn request_rate_limit(&self) -> RateLimitConfig {
+        RateLimitConfig {
+            burst: self.request_burst,
+            refill_per_second: self.request_rate,
+            free_multiplier: 1,
+        }
+    }
+}
+
+/// Route discovery statistics
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiscoveryStats {
+    /// Route requests of this node pending (including expired ones not yet cleaned up)
+    pub pending_requests: usize,
+    /// Discoveries refused because too many requests were pending
+    pub pending_rejected: u64,
+    /// Route requests from neighbors dropped by the rate limit
+    pub requests_rate_limited: u64,
+    /// Neighbors with a request bucket that is not full
+    pub rate_limited_neighbors: usize,
+}
+
+/// Direct link reported in a link-state advertisement
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LinkStateEntry {
+    pub neighbor: NodeId,
+    /// Measured round-trip latency to the neighbor
+    pub latency_ms: u32,
+    /// Payment capacity of the link (0 = unknown)
+    pub capacity_sats: u64,
+}
+
+/// Domain separator for link-state advertisement signatures
+const LINK_STATE_CONTEXT: &[u8] = b"bllvm-mesh-link-state-v1";
+
+/// Maximum links accepted in one advertisement
+pub const MAX_LINK_STATE_ENTRIES: usize = 256;
+
+/// Cost of a direct link this node has not advertised a latency for
+const DEFAULT_LINK_COST: u64 = 1;
+
+/// Hop limits of the expanding-ring search before `max_hops` (`mesh.discovery.ring_hops`)
+pub const DEFAULT_RING_HOPS: [u8; 2] = [2, 4];
+
+/// How long each ring but the last waits for a response (`mesh.discovery.ring_timeout_ms`)
+pub const DEFAULT_RING_TIMEOUT_MS: u64 = 500;
+
+/// Most route request keys remembered for flood suppression
+///
+/// Entries normally expire with the discovery timeout; past this bound the
+/// oldest are dropped, so a flood of unique requests cannot grow the cache
+/// without bound.
+pub const MAX_SEEN_REQUESTS: usize = 4096;
+
+/// Most destinations listed in one route error
+pub const MAX_ROUTE_ERROR_DESTINATIONS: usize = 256;
+
+/// Most destinations listed in one route withdrawal
+pub const MAX_WITHDRAWN_DESTINATIONS: usize = 256;
+
+/// How long a neighbor is remembered as having learned a route through us
+const PRECURSOR_EXPIRY_SECONDS: u64 = 60 * 60;
+
+/// Most rings one discovery goes through (including the final `max_hops` ring)
+pub const MAX_DISCOVERY_RINGS: usize = 8;
+
+/// Request ID bits above which the ring index is stored
+///
+/// Every ring of one discovery shares the lower bits (its family), so a
+/// late response to an earlier ring still matches the pending request.
+const RING_SHIFT: u32 = 56;
+
 /// Route discovery manager
+///
+/// Route requests search in expanding rings: each ring floods with a larger
+/// hop limit (`ring_hops`, ending at `max_hops`) once the previous one went
+/// unanswered for `ring_timeout`, so nearby destinations are found without
+/// flooding the whole mesh.
 pub struct RouteDiscovery {
-    /// Pending route requests (request_id -> RouteRequest)
-    pending_requests: Arc<RwLock<HashMap<u64, PendingRequest>>>,
-    /// Request ID counter
-    request_id_counter: Arc<RwLock<u64>>,
+    /// Pending route requests ((requester, request family) -> RouteRequest)
+    pending_requests: Arc<RwLock<HashMap<(NodeId, u64), PendingRequest>>>,
     /// Routing table reference
     routing_table: Arc<RoutingTable>,
     /// Maximum route discovery hops
     max_hops: u8,
     /// Route discovery timeout (seconds)
     timeout_seconds: u64,
+    /// Hop limit of each ring, the last one being `max_hops`
+    ring_hops: Vec<u8>,
+    /// How long a ring waits for a response before the next one is sent
+    ring_timeout: Duration,
+    /// Route requests already handled ((source, request_id) -> first seen), so floods stop
+    seen_requests: DashMap<(NodeId, u64), u64>,
+    /// Request families already answered ((source, family) -> first answered),
+    /// so wider rings of one discovery are answered once
+    answered_requests: DashMap<(NodeId, u64), u64>,
+    /// Route errors already handled ((from, digest of destinations) -> first seen)
+    seen_route_errors: DashMap<(NodeId, u64), u64>,
+    /// Route withdrawals already handled ((source, digest of withdrawal) -> first seen)
+    seen_withdrawals: DashMap<(NodeId, u64), u64>,
+    /// Neighbors that learned a route through this node (destination -> neighbor -> last learned)
+    precursors: DashMap<NodeId, HashMap<NodeId, u64>>,
+    /// Latest advertised links per origin (lock-free with DashMap)
+    topology_graph: DashMap<NodeId, Vec<LinkStateEntry>>,
+    /// Sequence of the latest accepted advertisement per origin
+    link_state_sequences: DashMap<NodeId, u64>,
+    /// Sequence for this node's next advertisement
+    next_link_state_sequence: AtomicU64,
+    /// Sequence of the latest route advertisement per source
+    route_advertisement_sequences: DashMap<NodeId, u64>,
+    /// Sequence for this node's next route advertisement round
+    next_route_advertisement_sequence: AtomicU64,
+    /// Destinations announced in this node's last route advertisement round,
+    /// withdrawn in the next one if their route is gone
+    advertised_destinations: std::sync::Mutex<HashSet<NodeId>>,
+    /// This node's ID (root of link-state shortest paths)
+    local_node_id: Option<NodeId>,
+    /// Caps on pending requests and neighbor request rates
+    limits: DiscoveryLimits,
+    /// Route requests accepted from each neighbor (by its node ID)
+    request_limiter: RateLimiter,
+    /// Discoveries refused because too many requests were pending
+    pending_rejected: AtomicU64,
 }
 
 /// Pending route request
 struct PendingRequest {
     destination: NodeId,
     source: NodeId,
+    /// Request family (the ring 0 request ID)
     request_id: u64,
     timestamp: u64,
+    /// Index of the ring in flight
+    ring: usize,
+    /// When the ring in flight was sent
+    ring_started: Instant,
     responders: Vec<NodeId>,
+    /// Callers waiting in `discover_route` for the discovered route
+    waiters: Vec<RouteWaiter>,
 }
 
+/// Wakes a `discover_route` caller with the route, or why none will come
+type RouteWaiter = oneshot::Sender<Result<Vec<NodeId>, MeshError>>;
+
 impl RouteDiscovery {
     /// Create a new route discovery manager
     pub fn new(
@@ -77,89 +466,278 @@ impl RouteDiscovery {
     ) -> Self {
         Self {
             pending_requests: Arc::new(RwLock::new(HashMap::new())),
-            request_id_counter: Arc::new(RwLock::new(0)),
             routing_table,
             max_hops,
             timeout_seconds,
+            ring_hops: ring_schedule(&DEFAULT_RING_HOPS, max_hops),
+            ring_timeout: Duration::from_millis(DEFAULT_RING_TIMEOUT_MS),
+            seen_requests: DashMap::new(),
+            answered_requests: DashMap::new(),
+            seen_route_errors: DashMap::new(),
+            seen_withdrawals: DashMap::new(),
+            precursors: DashMap::new(),
+            topology_graph: DashMap::new(),
+            link_state_sequences: DashMap::new(),
+            // Start from the clock so sequences keep increasing across restarts
+            next_link_state_sequence: AtomicU64::new(
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs()
+                    << 20,
+            ),
+            route_advertisement_sequences: DashMap::new(),
+            // Start from the clock so sequences keep increasing across restarts
+            next_route_advertisement_sequence: AtomicU64::new(
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs()
+                    << 20,
+            ),
+            advertised_destinations: std::sync::Mutex::new(HashSet::new()),
+            local_node_id: None,
+            limits: DiscoveryLimits::default(),
+            request_limiter: RateLimiter::new(DiscoveryLimits::default().request_rate_limit()),
+            pending_rejected: AtomicU64::new(0),
         }
     }
 
-    /// Generate a new request ID
-    async fn next_request_id(&self) -> u64 {
-        let mut counter = self.request_id_counter.write().await;
-        *counter += 1;
-        *counter
+    /// Set this node's ID, needed to compute routes from link-state
+    /// advertisements
+    pub fn with_local_node_id(mut self, node_id: NodeId) -> Self {
+        self.local_node_id = Some(node_id);
+        self
+    }
+
+    /// Set the expanding-ring schedule
+    ///
+    /// `ring_hops` are the hop limits tried before `max_hops` (smaller limits
+    /// first; zero and limits not below `max_hops` are ignored), each ring
+    /// waiting `ring_timeout` for a response. An empty schedule floods at
+    /// `max_hops` at once.
+    pub fn with_ring_schedule(mut self, ring_hops: &[u8], ring_timeout: Duration) -> Self {
+        self.ring_hops = ring_schedule(ring_hops, self.max_hops);
+        self.ring_timeout = ring_timeout;
+        self
+    }
+
+    /// Set the pending request caps and per-neighbor request rate limit
+    pub fn with_limits(mut self, limits: DiscoveryLimits) -> Self {
+        self.request_limiter = RateLimiter::new(limits.request_rate_limit());
+        self.limits = limits;
+        self
+    }
+
+    /// Hop limit of each ring, the last one being `max_hops`
+    pub fn ring_hops(&self) -> &[u8] {
+        &self.ring_hops
     }
 
     /// Discover route to destination
     ///
-    /// Returns route if found, None if not found or timeout.
-    pub async fn discover_route(
+    /// Returns a known route at once. Otherwise a route request is started
+    /// (handed to `broadcast` for sending to direct peers) and the call waits
+    /// up to the discovery timeout for the response, returning None on
+    /// timeout. Each unanswered ring is followed by the next, wider one.
+    /// Concurrent callers for the same destination share one request; only
+    /// one of them broadcasts each ring. A new request beyond the
+    /// `DiscoveryLimits` pending caps fails with `CapacityExceeded`.
+    pub async fn discover_route<F, Fut>(
+        &self,
+        destination: NodeId,
+        source: NodeId,
+        broadcast: F,
+    ) -> Result<Option<Vec<NodeId>>, MeshError>
+    where
+        F: Fn(DiscoveryMessage) -> Fut,
+        Fut: Future<Output = Result<usize, MeshError>>,
+    {
+        let deadline = Instant::now() + Duration::from_secs(self.timeout_seconds);
+        let (waiter, mut route) = oneshot::channel();
+        let mut request = match self.lookup(destination, source, Some(waiter)).await? {
+            RouteLookup::Found(route) => return Ok(Some(route)),
+            RouteLookup::Requested(request) => Some(request),
+            RouteLookup::Pending => None,
+        };
+
+        loop {
+            if let Some(request) = request.take() {
+                let sent = broadcast(request).await?;
+                debug!("Route request sent to {} direct peers", sent);
+            }
+
+            let ring_deadline = deadline.min(Instant::now() + self.ring_timeout);
+            match tokio::time::timeout_at(ring_deadline, &mut route).await {
+                Ok(Ok(route)) => return route.map(Some),
+                // The request expired before a response arrived
+                Ok(Err(_)) => break,
+                Err(_) if Instant::now() >= deadline => break,
+                Err(_) => request = self.widen_pending(&destination).await,
+            }
+        }
+
+        debug!("Route discovery timed out: destination={:x?}", &destination[..8]);
+        Ok(None)
+    }
+
+    /// Look up a route, starting a route request if none is known
+    ///
+    /// Never waits: at most one request per destination is in flight at a
+    /// time, and the returned request must be broadcast by the caller using
+    /// the network layer. The route is installed when the response arrives.
+    pub async fn resolve_route(
+        &self,
+        destination: NodeId,
+        source: NodeId,
+    ) -> Result<RouteLookup, MeshError> {
+        self.lookup(destination, source, None).await
+    }
+
+    /// Look up a route, registering `waiter` on the (new or shared) pending
+    /// request if there is none
+    async fn lookup(
         &self,
         destination: NodeId,
         source: NodeId,
-    ) -> Result<Option<Vec<NodeId>>, MeshError> {
+        waiter: Option<RouteWaiter>,
+    ) -> Result<RouteLookup, MeshError> {
         // Check if we already have a route
         if let Some(route) = self.routing_table.find_route(&destination) {
-            return Ok(Some(route));
+            return Ok(RouteLookup::Found(route));
         }
 
         // Check if destination is a direct peer (lock-free with DashMap)
-        if let Some(entry) = self.routing_table.routes.get(&destination) {
+        if let Some(entry) = self.routing_table.get_route(&destination) {
             if entry.direct_address.is_some() {
                 // Direct peer - return direct route
-                return Ok(Some(vec![source, destination]));
+                return Ok(RouteLookup::Found(vec![destination]));
             }
         }
 
-        // Create route request
-        let request_id = self.next_request_id().await;
-        let request = DiscoveryMessage::RouteRequest {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        let mut pending = self.pending_requests.write().await;
+        if let Some(request) = pending.values_mut().find(|request| {
+            request.destination == destination && now <= request.timestamp + self.timeout_seconds
+        }) {
+            request.waiters.extend(waiter);
+            return Ok(match self.widen(request, now) {
+                Some(wider) => RouteLookup::Requested(wider),
+                None => RouteLookup::Pending,
+            });
+        }
+
+        let for_destination = pending
+            .values()
+            .filter(|request| request.destination == destination)
+            .count();
+        if pending.len() >= self.limits.max_pending
+            || for_destination >= self.limits.max_pending_per_destination
+        {
+            self.pending_rejected.fetch_add(1, Ordering::Relaxed);
+            return Err(MeshError::CapacityExceeded(format!(
+                "Too many pending route requests ({} in total, {} for {:x?})",
+                pending.len(),
+                for_destination,
+                &destination[..8]
+            )));
+        }
+
+        // Create route request (the family's first ring)
+        let request_id = loop {
+            let family = random_request_family();
+            if !pending.contains_key(&(source, family)) {
+                break family;
+            }
+        };
+        let request = PendingRequest {
             destination,
             source,
             request_id,
-            max_hops: self.max_hops,
+            timestamp: now,
+            ring: 0,
+            ring_started: Instant::now(),
+            responders: Vec::new(),
+            waiters: waiter.into_iter().collect(),
         };
+        // Our own request must not be handled again when neighbors flood it back
+        self.remember_request(&self.seen_requests, (source, request_id), now);
+        let message = self.ring_request(&request);
+        pending.insert((source, request_id), request);
 
-        // Broadcast route request to neighbors
-        // Note: Actual broadcasting would be done by the caller using the network layer
-        // This method prepares the request, and network integration handles the broadcast
-        // For now, we'll just return None (route discovery not yet implemented)
-        warn!(
-            "Route discovery not yet implemented: destination={:x?}",
-            &destination[..8]
+        debug!(
+            "Starting route discovery: destination={:x?}, request_id={}",
+            &destination[..8],
+            request_id
         );
+        Ok(RouteLookup::Requested(message))
+    }
 
-        // Store pending request
+    /// Widen the pending request for a destination if its ring went unanswered
+    async fn widen_pending(&self, destination: &NodeId) -> Option<DiscoveryMessage> {
         let now = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .unwrap()
             .as_secs();
-
         let mut pending = self.pending_requests.write().await;
-        pending.insert(
-            request_id,
-            PendingRequest {
-                destination,
-                source,
-                request_id,
-                timestamp: now,
-                responders: Vec::new(),
-            },
-        );
+        let request = pending.values_mut().find(|request| {
+            request.destination == *destination && now <= request.timestamp + self.timeout_seconds
+        })?;
+        self.widen(request, now)
+    }
 
-        Ok(None)
+    /// Move a request to its next ring once the ring in flight timed out
+    ///
+    /// Returns the wider request to broadcast, or None while the ring in
+    /// flight may still be answered or after the last ring.
+    fn widen(&self, request: &mut PendingRequest, now: u64) -> Option<DiscoveryMessage> {
+        if request.ring + 1 >= self.ring_hops.len() || request.ring_started.elapsed() < self.ring_timeout {
+            return None;
+        }
+        request.ring += 1;
+        request.ring_started = Instant::now();
+        let message = self.ring_request(request);
+        if let DiscoveryMessage::RouteRequest { request_id, max_hops, .. } = &message {
+            self.remember_request(&self.seen_requests, (request.source, *request_id), now);
+            debug!(
+                "Widening route discovery: destination={:x?}, ring={}, max_hops={}",
+                &request.destination[..8],
+                request.ring + 1,
+                max_hops
+            );
+        }
+        Some(message)
+    }
+
+    /// Route request for the ring a pending request is in
+    fn ring_request(&self, request: &PendingRequest) -> DiscoveryMessage {
+        DiscoveryMessage::RouteRequest {
+            destination: request.destination,
+            source: request.source,
+            request_id: ring_request_id(request.request_id, request.ring),
+            max_hops: self.ring_hops[request.ring],
+            path: vec![request.source],
+        }
     }
 
     /// Discover multiple routes in parallel (batch operation)
     ///
     /// Processes multiple route discoveries concurrently for better performance.
     /// Returns a HashMap of destination -> route (or None if not found).
-    pub async fn discover_routes_batch(
+    pub async fn discover_routes_batch<F, Fut>(
         &self,
         destinations: &[NodeId],
         source: NodeId,
-    ) -> Result<std::collections::HashMap<NodeId, Option<Vec<NodeId>>>, MeshError> {
+        broadcast: F,
+    ) -> Result<std::collections::HashMap<NodeId, Option<Vec<NodeId>>>, MeshError>
+    where
+        F: Fn(DiscoveryMessage) -> Fut,
+        Fut: Future<Output = Result<usize, MeshError>>,
+    {
         if destinations.is_empty() {
             return Ok(std::collections::HashMap::new());
         }
@@ -167,7 +745,7 @@ impl RouteDiscovery {
         // Discover all routes in parallel
         let futures: Vec<_> = destinations
             .iter()
-            .map(|dest| self.discover_route(*dest, source))
+            .map(|dest| self.discover_route(*dest, source, &broadcast))
             .collect();
         
         // Wait for all discoveries to complete
@@ -183,6 +761,18 @@ impl RouteDiscovery {
     }
 
     /// Handle route request from another node
+    ///
+    /// Returns a `RouteResponse` to send back to the request's source when
+    /// this node is the destination or knows a loop-free route to it, or the
+    /// request extended with this node to flood on to the other direct peers.
+    /// Requests already seen (including our own) return `None`, as do wider
+    /// rings of a request this node already answered. The reverse of the
+    /// request path is remembered as a route to the source, so the response
+    /// can travel back. Requests from a neighbor over its rate limit are
+    /// dropped (and counted) before any other work.
+    ///
+    /// A `RouteWithdrawal` drops the routes through its source (see
+    /// `handle_route_withdrawal`) and is returned to flood on if new.
     pub async fn handle_route_request(
         &self,
         request: &DiscoveryMessage,
@@ -194,69 +784,389 @@ impl RouteDiscovery {
                 source,
                 request_id,
                 max_hops,
+                path,
             } => {
-                // Check if we have a route to destination (lock-free with DashMap)
-                if let Some(route) = self.routing_table.find_route(destination) {
-                    // We have a route - send response
-                    let response = DiscoveryMessage::RouteResponse {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs();
+                if self.request_limiter.check(&from_node, false, now).is_err() {
+                    debug!(
+                        "Dropping route request over the rate limit: from={:x?}",
+                        &from_node[..8]
+                    );
+                    return Ok(None);
+                }
+
+                let Some(local) = self.local_node_id else {
+                    warn!("Ignoring route request: local node ID not set");
+                    return Ok(None);
+                };
+                if path.first() != Some(source) {
+                    return Err(MeshError::InvalidRequest(
+                        "Route request path must start with its source".to_string(),
+                    ));
+                }
+                if *source == local || path.contains(&local) {
+                    return Ok(None);
+                }
+
+                if !self.remember_request(&self.seen_requests, (*source, *request_id), now) {
+                    return Ok(None);
+                }
+
+                let mut path = path.clone();
+                path.push(local);
+                self.learn_reverse_route(&path, now);
+
+                // We are the destination, or know how to reach it without revisiting the path
+                let route = if *destination == local {
+                    Some(path.clone())
+                } else {
+                    self.routing_table.find_route(destination).and_then(|known| {
+                        let onward = known.strip_prefix(&[local]).unwrap_or(&known);
+                        if onward.iter().any(|node_id| path.contains(node_id)) {
+                            return None;
+                        }
+                        let mut route = path.clone();
+                        route.extend_from_slice(onward);
+                        Some(route)
+                    })
+                };
+                if let Some(route) = route {
+                    let family = (*source, request_family(*request_id));
+                    if !self.remember_request(&self.answered_requests, family, now) {
+                        return Ok(None);
+                    }
+                    if *destination != local {
+                        self.add_precursor(*destination, from_node, now);
+                    }
+                    debug!(
+                        "Answering route request: destination={:x?}, from={:x?}, route_length={}",
+                        &destination[..8],
+                        &from_node[..8],
+                        route.len()
+                    );
+                    return Ok(Some(DiscoveryMessage::RouteResponse {
                         destination: *destination,
                         source: *source,
                         request_id: *request_id,
-                        route: route.clone(),
                         cost: route.len() as u64 * 100, // Simple cost calculation
-                    };
-                    return Ok(Some(response));
+                        route,
+                    }));
                 }
 
-                // Check if we are the destination
-                // Note: This method should receive the node's actual node_id as a parameter
-                // For now, we check if destination matches any route in our routing table
-                // (which would indicate we know about this node, possibly ourselves)
-                // The caller should pass the actual node_id to properly check if we are the destination
-                // For now, if we have a direct route to the destination, we might be the destination
-                // In production, the caller should pass the actual node_id to compare
-                if let Some(route) = self.routing_table.get_route(destination) {
-                    // We have a route to this destination
-                    // If it's a direct route (only one hop), we might be the destination
-                    // For now, we'll assume if we have a direct route, we can respond
-                    // The caller should verify we are actually the destination
-                    if route.len() == 1 {
-                        // Direct route - might be ourselves
-                        // In production, compare with actual node_id
-                        let response = DiscoveryMessage::RouteResponse {
+                // Forward request if we haven't exceeded max hops
+                match max_hops.checked_sub(1).filter(|remaining| *remaining > 0) {
+                    Some(remaining) => {
+                        debug!(
+                            "Forwarding route request: destination={:x?}, hops_remaining={}",
+                            &destination[..8],
+                            remaining
+                        );
+                        Ok(Some(DiscoveryMessage::RouteRequest {
                             destination: *destination,
                             source: *source,
                             request_id: *request_id,
-                            route: vec![*source, *destination],
-                            cost: 0, // Direct route has no cost
-                        };
-                        return Ok(Some(response));
+                            max_hops: remaining,
+                            path,
+                        }))
                     }
+                    None => Ok(None),
                 }
+            }
+            DiscoveryMessage::RouteWithdrawal { .. } => {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs();
+                Ok(self.handle_route_withdrawal(request, now)?.then(|| request.clone()))
+            }
+            _ => Ok(None),
+        }
+    }
 
-                // Forward request if we haven't exceeded max hops
-                if *max_hops > 0 {
-                    // Forward request to neighbors
-                    // Note: Actual forwarding would be done by the caller using network layer
-                    debug!(
-                        "Forwarding route request: destination={:x?}, hops_remaining={}",
-                        &destination[..8],
-                        max_hops - 1
-                    );
-                }
+    /// Drop the routes a route withdrawal names
+    ///
+    /// Removes the learned routes to the withdrawn destinations that pass
+    /// through the withdrawal's source. Returns whether the withdrawal was
+    /// new; repeats, and withdrawals timestamped more than
+    /// `MAX_DISCOVERY_AGE_SECONDS` away from `now`, change nothing.
+    fn handle_route_withdrawal(&self, withdrawal: &DiscoveryMessage, now: u64) -> Result<bool, MeshError> {
+        let DiscoveryMessage::RouteWithdrawal {
+            withdrawn_destinations,
+            source,
+            timestamp,
+        } = withdrawal
+        else {
+            return Ok(false);
+        };
+        if withdrawn_destinations.len() > MAX_WITHDRAWN_DESTINATIONS {
+            return Err(MeshError::InvalidRequest(format!(
+                "Route withdrawal lists too many destinations: {} > {}",
+                withdrawn_destinations.len(),
+                MAX_WITHDRAWN_DESTINATIONS
+            )));
+        }
+        if now.abs_diff(*timestamp) > MAX_DISCOVERY_AGE_SECONDS || Some(*source) == self.local_node_id {
+            return Ok(false);
+        }
 
-                Ok(None)
+        let mut sorted = withdrawn_destinations.clone();
+        sorted.sort_unstable();
+        let mut hasher = Sha256::new();
+        hasher.update(timestamp.to_be_bytes());
+        hasher.update(sorted.concat());
+        let digest = hasher.finalize();
+        let key = (*source, u64::from_be_bytes(digest[..8].try_into().expect("digest is 32 bytes")));
+        if !self.remember_request(&self.seen_withdrawals, key, now) {
+            return Ok(false);
+        }
+
+        let destinations: Vec<NodeId> = withdrawn_destinations
+            .iter()
+            .filter(|destination| Some(**destination) != self.local_node_id)
+            .copied()
+            .collect();
+        let lost = self.routing_table.remove_routes_through(&destinations, source);
+        debug!(
+            "Route withdrawal from {:x?}: {} destinations listed, {} now unreachable",
+            &source[..8],
+            withdrawn_destinations.len(),
+            lost.len()
+        );
+        Ok(true)
+    }
+
+    /// Route withdrawals announcing that this node goes offline
+    ///
+    /// Lists this node and every destination it has a route to, split into
+    /// messages of at most `MAX_WITHDRAWN_DESTINATIONS`.
+    pub fn originate_route_withdrawals(&self, now: u64) -> Result<Vec<DiscoveryMessage>, MeshError> {
+        let local = self
+            .local_node_id
+            .ok_or_else(|| MeshError::ConfigError("Local node ID not set".to_string()))?;
+        let destinations: Vec<NodeId> = std::iter::once(local)
+            .chain(
+                self.routing_table
+                    .entries()
+                    .into_iter()
+                    .map(|entry| entry.node_id)
+                    .filter(|destination| *destination != local),
+            )
+            .collect();
+        Ok(destinations
+            .chunks(MAX_WITHDRAWN_DESTINATIONS)
+            .map(|chunk| DiscoveryMessage::RouteWithdrawal {
+                withdrawn_destinations: chunk.to_vec(),
+                source: local,
+                timestamp: now,
+            })
+            .collect())
+    }
+
+    /// Learn the onward part of a route response this node relays
+    ///
+    /// Relays on a discovered route need their own route to its destination
+    /// to forward the requester's traffic. Existing routes are kept. The hop
+    /// before this node is remembered as a precursor of the destination.
+    pub fn learn_relayed_route(&self, response: &DiscoveryMessage) {
+        let (Some(local), DiscoveryMessage::RouteResponse { destination, route, .. }) =
+            (self.local_node_id, response)
+        else {
+            return;
+        };
+        let Some(index) = route.iter().position(|node_id| *node_id == local) else {
+            return;
+        };
+        let onward = &route[index..];
+        if onward.len() < 2 || onward.last() != Some(destination) {
+            return;
+        }
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        // The hop before us toward the requester will route through us
+        if let Some(previous) = index.checked_sub(1).map(|previous| route[previous]) {
+            self.add_precursor(*destination, previous, now);
+        }
+        if onward.len() < 3 || self.routing_table.get_route(destination).is_some() {
+            return;
+        }
+        self.routing_table.add_route(RoutingEntry {
+            node_id: *destination,
+            direct_address: None,
+            next_hop: Some(onward[1]),
+            route_path: onward.to_vec(),
+            route_cost: onward.len() as u64 * 100,
+            last_updated: now,
+            quality_score: 0.8, // Same as discovered routes
+        });
+    }
+
+    /// Install (or refresh) the reverse of a request path as a route to its source
+    ///
+    /// The next hop is the neighbor the request came from and the cost grows
+    /// with the hops it has traversed, so the response can be unicast back.
+    /// A source that is a direct peer needs no route.
+    fn learn_reverse_route(&self, path: &[NodeId], now: u64) {
+        let source = path[0];
+        let direct = || {
+            self.routing_table
+                .get_route(&source)
+                .is_some_and(|entry| entry.direct_address.is_some())
+        };
+        if path.len() < 3 || direct() {
+            return;
+        }
+
+        let route_path: Vec<NodeId> = path.iter().rev().copied().collect();
+        self.routing_table.add_route(RoutingEntry {
+            node_id: source,
+            direct_address: None,
+            next_hop: Some(route_path[1]),
+            route_cost: route_path.len() as u64 * 100,
+            route_path,
+            last_updated: now,
+            quality_score: 0.7, // Same as advertised routes
+        });
+    }
+
+    /// Remember a route request key, returning false if it was already known
+    ///
+    /// Past `MAX_SEEN_REQUESTS` keys, expired ones are dropped and then the
+    /// oldest.
+    fn remember_request(&self, keys: &DashMap<(NodeId, u64), u64>, key: (NodeId, u64), now: u64) -> bool {
+        if keys.insert(key, now).is_some() {
+            return false;
+        }
+        if keys.len() > MAX_SEEN_REQUESTS {
+            keys.retain(|_, first_seen| now <= *first_seen + self.timeout_seconds);
+            while keys.len() > MAX_SEEN_REQUESTS {
+                let oldest = keys
+                    .iter()
+                    .filter(|entry| *entry.key() != key)
+                    .min_by_key(|entry| *entry.value())
+                    .map(|entry| *entry.key());
+                let Some(oldest) = oldest else {
+                    break;
+                };
+                keys.remove(&oldest);
             }
-            _ => Ok(None),
         }
+        true
+    }
+
+    /// Remember that `neighbor` learned a route to `destination` through this node
+    fn add_precursor(&self, destination: NodeId, neighbor: NodeId, now: u64) {
+        if Some(neighbor) == self.local_node_id || neighbor == destination {
+            return;
+        }
+        self.precursors.entry(destination).or_default().insert(neighbor, now);
+    }
+
+    /// Neighbors that learned a route to `destination` through this node
+    pub fn precursors(&self, destination: &NodeId) -> Vec<NodeId> {
+        self.precursors
+            .get(destination)
+            .map(|neighbors| neighbors.keys().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// Group destinations by the neighbors to send their route error to
+    ///
+    /// The destinations are forgotten as precursor entries, since the
+    /// neighbors are about to drop their routes to them.
+    pub fn route_error_recipients(&self, unreachable: &[NodeId]) -> HashMap<NodeId, Vec<NodeId>> {
+        let mut recipients: HashMap<NodeId, Vec<NodeId>> = HashMap::new();
+        for destination in unreachable {
+            let Some((_, neighbors)) = self.precursors.remove(destination) else {
+                continue;
+            };
+            for neighbor in neighbors.into_keys() {
+                recipients.entry(neighbor).or_default().push(*destination);
+            }
+        }
+        recipients
+    }
+
+    /// Handle a route error from a neighbor
+    ///
+    /// Drops the routes to the listed destinations that go through
+    /// `from_node` (the sender, which must match the error's `from`).
+    /// Returns the destinations this node can no longer reach at all, for
+    /// `route_error_recipients`; an error already seen returns none, so
+    /// errors cannot circulate.
+    pub fn handle_route_error(&self, error: &DiscoveryMessage, from_node: NodeId) -> Result<Vec<NodeId>, MeshError> {
+        let DiscoveryMessage::RouteError { unreachable, from } = error else {
+            return Ok(Vec::new());
+        };
+        if *from != from_node {
+            return Err(MeshError::InvalidRequest(
+                "Route error must come from the node it names".to_string(),
+            ));
+        }
+        if unreachable.len() > MAX_ROUTE_ERROR_DESTINATIONS {
+            return Err(MeshError::InvalidRequest(format!(
+                "Route error lists too many destinations: {} > {}",
+                unreachable.len(),
+                MAX_ROUTE_ERROR_DESTINATIONS
+            )));
+        }
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let mut sorted = unreachable.clone();
+        sorted.sort_unstable();
+        let digest = Sha256::digest(sorted.concat());
+        let key = (*from, u64::from_be_bytes(digest[..8].try_into().expect("digest is 32 bytes")));
+        if !self.remember_request(&self.seen_route_errors, key, now) {
+            return Ok(Vec::new());
+        }
+
+        let destinations: Vec<NodeId> = unreachable
+            .iter()
+            .filter(|destination| Some(**destination) != self.local_node_id)
+            .copied()
+            .collect();
+        let lost = self.routing_table.remove_routes_to_via(&destinations, from);
+        debug!(
+            "Route error from {:x?}: {} destinations listed, {} now unreachable",
+            &from[..8],
+            unreachable.len(),
+            lost.len()
+        );
+        Ok(lost)
     }
 
     /// Handle route response
+    ///
+    /// A response to any ring of a pending request completes it. Returns
+    /// the number of rings the discovery needed (up to the ring answered),
+    /// or None if the response matched no pending request.
     pub async fn handle_route_response(
         &self,
         response: &DiscoveryMessage,
         from_node: NodeId,
-    ) -> Result<(), MeshError> {
+    ) -> Result<Option<usize>, MeshError> {
+        self.handle_route_response_with_trust(response, from_node, RouteTrust::Verified)
+            .await
+    }
+
+    /// Handle a route response whose originator is trusted as given
+    ///
+    /// Quarantined responses still complete the request, but the route is
+    /// installed with `QUARANTINED_ROUTE_QUALITY`.
+    pub async fn handle_route_response_with_trust(
+        &self,
+        response: &DiscoveryMessage,
+        from_node: NodeId,
+        trust: RouteTrust,
+    ) -> Result<Option<usize>, MeshError> {
         match response {
             DiscoveryMessage::RouteResponse {
                 destination,
@@ -266,8 +1176,20 @@ impl RouteDiscovery {
                 cost,
             } => {
                 // Check if this is a response to a pending request
+                if route.len() < 2 || route.last() != Some(destination) {
+                    return Err(MeshError::InvalidRequest(
+                        "Route response must end with its destination".to_string(),
+                    ));
+                }
+
+                // Keyed by requester too, so another node's request with
+                // the same ID cannot complete ours
+                let key = (*source, request_family(*request_id));
                 let mut pending = self.pending_requests.write().await;
-                if let Some(request) = pending.get_mut(request_id) {
+                if let Some(request) = pending
+                    .get_mut(&key)
+                    .filter(|request| request.destination == *destination)
+                {
                     // Add responder
                     request.responders.push(from_node);
 
@@ -284,7 +1206,7 @@ impl RouteDiscovery {
                         route_path: route.clone(),
                         route_cost: *cost,
                         last_updated: now,
-                        quality_score: 0.8, // Default quality for discovered routes
+                        quality_score: trust.quality(0.8), // Default quality for discovered routes
                     };
 
                     // Add route to routing table (lock-free with DashMap)
@@ -297,29 +1219,173 @@ impl RouteDiscovery {
                         cost
                     );
 
-                    // Remove pending request
-                    pending.remove(request_id);
+                    // Remove pending request and wake its waiters
+                    if let Some(request) = pending.remove(&key) {
+                        for waiter in request.waiters {
+                            let _ = waiter.send(Ok(route.clone()));
+                        }
+                    }
+                    return Ok(Some(request_ring(*request_id) + 1));
                 }
 
-                Ok(())
+                Ok(None)
             }
-            _ => Ok(()),
+            _ => Ok(None),
         }
     }
 
-    /// Handle route advertisement
-    pub async fn handle_route_advertisement(
-        &self,
-        advertisement: &DiscoveryMessage,
-        from_node: NodeId,
+    /// Build this round's route advertisement for each direct peer
+    ///
+    /// Every advertisement carries the best route to each destination this
+    /// node knows (at most `MAX_ROUTE_ADVERTISEMENT_ENTRIES`), except the
+    /// peer itself and routes whose next hop is that peer (split horizon).
+    /// Destinations announced in the previous round that no longer have a
+    /// route are withdrawn with `WITHDRAWN_ROUTE_COST`. All advertisements
+    /// of a round share one sequence number.
+    pub fn originate_route_advertisements(&self) -> Result<Vec<(NodeId, Vec<u8>, DiscoveryMessage)>, MeshError> {
+        self.originate_route_advertisements_with_cost_multiplier(Some(1))
+    }
+
+    /// Build this round's route advertisements with scaled costs
+    ///
+    /// Like `originate_route_advertisements`, with every advertised cost
+    /// multiplied by `cost_multiplier`. `None` announces no routes, which
+    /// withdraws everything announced in the previous round (a relay that
+    /// cannot carry transit traffic).
+    pub fn originate_route_advertisements_with_cost_multiplier(
+        &self,
+        cost_multiplier: Option<u64>,
+    ) -> Result<Vec<(NodeId, Vec<u8>, DiscoveryMessage)>, MeshError> {
+        let local = self.local_node_id.ok_or_else(|| {
+            MeshError::ConfigError("Route advertisement needs the local node ID".to_string())
+        })?;
+
+        let mut routes: Vec<(RouteAdvertisementEntry, NodeId)> = self
+            .routing_table
+            .entries()
+            .into_iter()
+            .filter(|entry| cost_multiplier.is_some() && entry.node_id != local)
+            .map(|entry| {
+                let first_hop = entry
+                    .route_path
+                    .iter()
+                    .find(|node_id| **node_id != local)
+                    .copied()
+                    .unwrap_or(entry.node_id);
+                let is_direct = entry.direct_address.is_some() && entry.next_hop.is_none();
+                let advertised = RouteAdvertisementEntry {
+                    destination: entry.node_id,
+                    // Direct peers are reached through this node itself
+                    next_hop: if is_direct { local } else { first_hop },
+                    cost: entry
+                        .route_cost
+                        .saturating_mul(cost_multiplier.unwrap_or(1))
+                        .min(WITHDRAWN_ROUTE_COST - 1),
+                    hop_count: route_hop_count(&entry.route_path, &local),
+                };
+                (advertised, first_hop)
+            })
+            .collect();
+        routes.sort_by_key(|(entry, _)| (entry.hop_count, entry.cost));
+        routes.truncate(MAX_ROUTE_ADVERTISEMENT_ENTRIES);
+
+        let announced: HashSet<NodeId> = routes.iter().map(|(entry, _)| entry.destination).collect();
+        let withdrawn: Vec<RouteAdvertisementEntry> = {
+            let mut advertised = self.advertised_destinations.lock().unwrap();
+            let withdrawn = advertised
+                .difference(&announced)
+                .map(|destination| RouteAdvertisementEntry {
+                    destination: *destination,
+                    next_hop: local,
+                    cost: WITHDRAWN_ROUTE_COST,
+                    hop_count: 0,
+                })
+                .collect();
+            *advertised = announced;
+            withdrawn
+        };
+
+        let sequence = self.next_route_advertisement_sequence.fetch_add(1, Ordering::SeqCst);
+        Ok(self
+            .routing_table
+            .direct_peer_addresses()
+            .into_iter()
+            .map(|(peer, address)| {
+                let mut entries: Vec<RouteAdvertisementEntry> = routes
+                    .iter()
+                    .filter(|(entry, first_hop)| entry.destination != peer && *first_hop != peer)
+                    .map(|(entry, _)| entry.clone())
+                    .collect();
+                entries.extend(withdrawn.iter().filter(|entry| entry.destination != peer).cloned());
+                entries.truncate(MAX_ROUTE_ADVERTISEMENT_ENTRIES);
+                let message = DiscoveryMessage::RouteAdvertisement {
+                    routes: entries,
+                    source: local,
+                    sequence,
+                    seen: BloomFilter::default(),
+                };
+                (peer, address, message)
+            })
+            .collect())
+    }
+
+    /// Handle route advertisement
+    ///
+    /// Advertisements older than the latest one seen from their source are
+    /// ignored. Withdrawn entries remove the routes learned from the
+    /// source; routes through this node are never learned.
+    pub async fn handle_route_advertisement(
+        &self,
+        advertisement: &DiscoveryMessage,
+        from_node: NodeId,
+    ) -> Result<(), MeshError> {
+        self.handle_route_advertisement_with_trust(advertisement, from_node, RouteTrust::Verified)
+            .await
+    }
+
+    /// Handle a route advertisement whose source is trusted as given
+    ///
+    /// Routes from quarantined advertisements get `QUARANTINED_ROUTE_QUALITY`.
+    pub async fn handle_route_advertisement_with_trust(
+        &self,
+        advertisement: &DiscoveryMessage,
+        from_node: NodeId,
+        trust: RouteTrust,
     ) -> Result<(), MeshError> {
         match advertisement {
-            DiscoveryMessage::RouteAdvertisement { routes, source } => {
+            DiscoveryMessage::RouteAdvertisement { routes, source, sequence, .. } => {
                 debug!(
-                    "Received route advertisement: source={:x?}, routes={}",
+                    "Received route advertisement: source={:x?}, from={:x?}, routes={}",
                     &source[..8],
+                    &from_node[..8],
                     routes.len()
                 );
+                if Some(*source) == self.local_node_id {
+                    return Ok(());
+                }
+                if routes.len() > MAX_ROUTE_ADVERTISEMENT_ENTRIES {
+                    return Err(MeshError::InvalidPacket(format!(
+                        "Too many routes in advertisement: {} (max {})",
+                        routes.len(),
+                        MAX_ROUTE_ADVERTISEMENT_ENTRIES
+                    )));
+                }
+                match self.route_advertisement_sequences.entry(*source) {
+                    dashmap::mapref::entry::Entry::Occupied(mut latest) => {
+                        if *sequence < *latest.get() {
+                            debug!(
+                                "Ignoring stale route advertisement: source={:x?}, sequence={}",
+                                &source[..8],
+                                sequence
+                            );
+                            return Ok(());
+                        }
+                        latest.insert(*sequence);
+                    }
+                    dashmap::mapref::entry::Entry::Vacant(latest) => {
+                        latest.insert(*sequence);
+                    }
+                }
 
                 // Update routing table with advertised routes
                 let now = SystemTime::now()
@@ -328,6 +1394,16 @@ impl RouteDiscovery {
                     .as_secs();
 
                 for route_entry in routes {
+                    if Some(route_entry.destination) == self.local_node_id
+                        || Some(route_entry.next_hop) == self.local_node_id
+                    {
+                        continue;
+                    }
+                    if route_entry.cost == WITHDRAWN_ROUTE_COST {
+                        self.routing_table.withdraw_routes(&route_entry.destination, source);
+                        continue;
+                    }
+
                     // Create route path (source -> next_hop -> destination)
                     let route_path = vec![*source, route_entry.next_hop, route_entry.destination];
 
@@ -338,7 +1414,7 @@ impl RouteDiscovery {
                         route_path,
                         route_cost: route_entry.cost,
                         last_updated: now,
-                        quality_score: 0.7, // Default quality for advertised routes
+                        quality_score: trust.quality(0.7), // Default quality for advertised routes
                     };
 
                     // Add or update route (lock-free with DashMap)
@@ -351,6 +1427,232 @@ impl RouteDiscovery {
         }
     }
 
+    /// Build and sign this node's link-state advertisement
+    ///
+    /// The links are also recorded in the local topology graph, so their
+    /// latencies are used as the cost of this node's own links.
+    pub fn originate_link_state(
+        &self,
+        links: Vec<LinkStateEntry>,
+        keypair: &Keypair,
+    ) -> Result<DiscoveryMessage, MeshError> {
+        let origin = self.local_node_id.ok_or_else(|| {
+            MeshError::ConfigError("Link-state advertisement needs the local node ID".to_string())
+        })?;
+        if links.len() > MAX_LINK_STATE_ENTRIES {
+            return Err(MeshError::InvalidRequest(format!(
+                "Too many links in advertisement: {} (max {})",
+                links.len(),
+                MAX_LINK_STATE_ENTRIES
+            )));
+        }
+
+        let sequence = self.next_link_state_sequence.fetch_add(1, Ordering::SeqCst);
+        let digest = link_state_digest(&origin, sequence, &links);
+        let signature = Secp256k1::new().sign_schnorr_no_aux_rand(&digest, keypair);
+
+        self.link_state_sequences.insert(origin, sequence);
+        self.topology_graph.insert(origin, links.clone());
+        self.recompute_link_state_routes();
+
+        Ok(DiscoveryMessage::LinkStateAdvertisement {
+            origin,
+            origin_key: signing_public_key(keypair),
+            sequence,
+            links,
+            signature: *signature.as_ref(),
+        })
+    }
+
+    /// Handle a link-state advertisement
+    ///
+    /// The signature is checked against the origin's key in `peer_keys`, or
+    /// for origins beyond the direct peers against the carried key, whose
+    /// hash must be the origin's node ID; that key is then registered.
+    /// Advertisements from unverifiable origins, our own echoes and anything
+    /// not newer than the last accepted sequence are dropped. Returns `true`
+    /// when the advertisement was new, in which case the caller should flood
+    /// it unchanged to its other peers.
+    pub async fn handle_link_state_advertisement(
+        &self,
+        advertisement: &DiscoveryMessage,
+        from_node: NodeId,
+        peer_keys: &PeerKeys,
+    ) -> Result<bool, MeshError> {
+        let DiscoveryMessage::LinkStateAdvertisement {
+            origin,
+            origin_key,
+            sequence,
+            links,
+            signature,
+        } = advertisement
+        else {
+            return Ok(false);
+        };
+
+        if Some(*origin) == self.local_node_id {
+            return Ok(false);
+        }
+        if self
+            .link_state_sequences
+            .get(origin)
+            .is_some_and(|last| *sequence <= *last)
+        {
+            debug!(
+                "Ignoring stale link-state advertisement: origin={:x?}, sequence={}",
+                &origin[..8],
+                sequence
+            );
+            return Ok(false);
+        }
+        if links.len() > MAX_LINK_STATE_ENTRIES {
+            return Err(MeshError::InvalidRequest(format!(
+                "Too many links in advertisement: {} (max {})",
+                links.len(),
+                MAX_LINK_STATE_ENTRIES
+            )));
+        }
+
+        let public_key = match peer_keys.get(origin) {
+            Some(known) if known.serialize() != *origin_key => {
+                return Err(MeshError::InvalidSignature(format!(
+                    "Link-state advertisement carries a different key for {:x?}",
+                    &origin[..8]
+                )));
+            }
+            Some(known) => known,
+            None if node_id_from_signing_key(origin_key) == *origin => XOnlyPublicKey::from_slice(origin_key)
+                .map_err(|e| MeshError::InvalidSignature(format!("Invalid link-state origin key: {}", e)))?,
+            None => {
+                return Err(MeshError::InvalidSignature(format!(
+                    "Unknown link-state origin {:x?}",
+                    &origin[..8]
+                )));
+            }
+        };
+        let signature = Signature::from_slice(signature)
+            .map_err(|e| MeshError::InvalidSignature(format!("Malformed signature: {}", e)))?;
+        Secp256k1::new()
+            .verify_schnorr(&signature, &link_state_digest(origin, *sequence, links), &public_key)
+            .map_err(|_| {
+                MeshError::InvalidSignature(format!(
+                    "Link-state advertisement does not match origin {:x?}",
+                    &origin[..8]
+                ))
+            })?;
+
+        debug!(
+            "Accepted link-state advertisement: origin={:x?}, from={:x?}, sequence={}, links={}",
+            &origin[..8],
+            &from_node[..8],
+            sequence,
+            links.len()
+        );
+        if peer_keys.get(origin).is_none() {
+            peer_keys.register(*origin, origin_key)?;
+        }
+        self.link_state_sequences.insert(*origin, *sequence);
+        self.topology_graph.insert(*origin, links.clone());
+        self.recompute_link_state_routes();
+        Ok(true)
+    }
+
+    /// Snapshot of the topology graph (origin -> advertised links)
+    pub fn get_topology_snapshot(&self) -> HashMap<NodeId, Vec<LinkStateEntry>> {
+        self.topology_graph
+            .iter()
+            .map(|entry| (*entry.key(), entry.value().clone()))
+            .collect()
+    }
+
+    /// Install shortest-path routes over the topology graph
+    ///
+    /// Runs `routing::shortest_path` from this node with link latency as
+    /// cost. This node's edges are its direct peers (at their advertised
+    /// latency if any); every other edge comes from its origin's
+    /// advertisement. Direct peers keep their direct routing entries.
+    fn recompute_link_state_routes(&self) {
+        let Some(local) = self.local_node_id else {
+            return;
+        };
+
+        let own_links = self.topology_graph.get(&local).map(|links| links.clone()).unwrap_or_default();
+        let local_edges = self
+            .routing_table
+            .direct_peer_addresses()
+            .into_iter()
+            .map(|(peer, _)| {
+                let cost = own_links
+                    .iter()
+                    .find(|link| link.neighbor == peer)
+                    .map_or(DEFAULT_LINK_COST, |link| u64::from(link.latency_ms).max(1));
+                (Some(peer), cost)
+            })
+            .collect();
+        let mut graph: RouteGraph = HashMap::from([(Some(local), local_edges)]);
+        for entry in self.topology_graph.iter().filter(|entry| *entry.key() != local) {
+            let edges = entry
+                .value()
+                .iter()
+                .map(|link| (Some(link.neighbor), u64::from(link.latency_ms).max(1)))
+                .collect();
+            graph.insert(Some(*entry.key()), edges);
+        }
+
+        let destinations: HashSet<NodeId> = graph
+            .iter()
+            .flat_map(|(vertex, edges)| std::iter::once(*vertex).chain(edges.iter().map(|(to, _)| *to)))
+            .flatten()
+            .collect();
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        for destination in destinations {
+            if destination == local || self.is_direct_peer(&destination) {
+                continue;
+            }
+            let Some((path, cost)) =
+                shortest_path(&graph, &HashSet::new(), &HashSet::new(), Some(local), Some(destination))
+            else {
+                continue;
+            };
+            let route_path: Vec<NodeId> = path.into_iter().flatten().collect();
+
+            self.routing_table.add_route(RoutingEntry {
+                node_id: destination,
+                direct_address: None,
+                next_hop: Some(route_path[1]),
+                route_path,
+                route_cost: cost,
+                last_updated: now,
+                quality_score: 0.8, // Same as discovered routes
+            });
+        }
+    }
+
+    /// Whether a node has a direct routing entry
+    fn is_direct_peer(&self, node_id: &NodeId) -> bool {
+        self.routing_table
+            .get_route(node_id)
+            .is_some_and(|entry| entry.direct_address.is_some() && entry.next_hop.is_none())
+    }
+
+    /// Abandon all pending route requests
+    ///
+    /// Callers waiting in `discover_route` fail with the error made by
+    /// `error`. Returns the number of requests dropped.
+    pub async fn cancel_pending(&self, error: impl Fn() -> MeshError) -> usize {
+        let mut pending = self.pending_requests.write().await;
+        let cancelled = pending.len();
+        for (_, request) in pending.drain() {
+            for waiter in request.waiters {
+                let _ = waiter.send(Err(error()));
+            }
+        }
+        cancelled
+    }
+
     /// Clean up expired pending requests
     pub async fn cleanup_expired(&self) {
         let now = SystemTime::now()
@@ -361,19 +1663,809 @@ impl RouteDiscovery {
         let mut pending = self.pending_requests.write().await;
         let mut expired = Vec::new();
 
-        for (request_id, request) in pending.iter() {
+        for (key, request) in pending.iter() {
             if now > request.timestamp + self.timeout_seconds {
-                expired.push(*request_id);
+                expired.push(*key);
             }
         }
 
-        for request_id in &expired {
-            pending.remove(request_id);
+        for key in &expired {
+            pending.remove(key);
         }
 
         if !expired.is_empty() {
             debug!("Cleaned up {} expired route discovery requests", expired.len());
         }
+
+        self.seen_requests
+            .retain(|_, first_seen| now <= *first_seen + self.timeout_seconds);
+        self.answered_requests
+            .retain(|_, first_answered| now <= *first_answered + self.timeout_seconds);
+        self.seen_route_errors
+            .retain(|_, first_seen| now <= *first_seen + self.timeout_seconds);
+        // Withdrawals stay valid for MAX_DISCOVERY_AGE_SECONDS either side of their timestamp
+        self.seen_withdrawals
+            .retain(|_, first_seen| now <= *first_seen + 2 * MAX_DISCOVERY_AGE_SECONDS);
+        self.precursors.retain(|_, neighbors| {
+            neighbors.retain(|_, learned| now <= *learned + PRECURSOR_EXPIRY_SECONDS);
+            !neighbors.is_empty()
+        });
+        self.request_limiter.cleanup_idle(now);
+    }
+
+    /// Pending request and rate limit statistics
+    pub async fn stats(&self) -> DiscoveryStats {
+        let limiter = self.request_limiter.stats();
+        DiscoveryStats {
+            pending_requests: self.pending_requests.read().await.len(),
+            pending_rejected: self.pending_rejected.load(Ordering::Relaxed),
+            requests_rate_limited: limiter.rate_limited,
+            rate_limited_neighbors: limiter.tracked_sources,
+        }
     }
 }
 
+/// Hops from this node along a stored route path (at least 1)
+fn route_hop_count(route_path: &[NodeId], local: &NodeId) -> u8 {
+    let mut hops: Vec<&NodeId> = route_path.iter().filter(|node_id| *node_id != local).collect();
+    hops.dedup();
+    hops.len().clamp(1, u8::MAX as usize) as u8
+}
+
+/// Ring hop limits: the configured limits below `max_hops` in increasing
+/// order, then `max_hops` (at most `MAX_DISCOVERY_RINGS` rings)
+fn ring_schedule(ring_hops: &[u8], max_hops: u8) -> Vec<u8> {
+    let mut rings: Vec<u8> = ring_hops
+        .iter()
+        .copied()
+        .filter(|hops| *hops > 0 && *hops < max_hops)
+        .collect();
+    rings.sort_unstable();
+    rings.dedup();
+    rings.truncate(MAX_DISCOVERY_RINGS - 1);
+    rings.push(max_hops);
+    rings
+}
+
+/// New random request family, never zero
+///
+/// Drawn from the OS CSPRNG rather than counted, so a restarted node does
+/// not reuse the IDs of its previous run and stale responses to them match
+/// nothing. The ring bits are left clear.
+fn random_request_family() -> u64 {
+    loop {
+        let family = request_family(OsRng.next_u64());
+        if family != 0 {
+            return family;
+        }
+    }
+}
+
+/// Request ID of a ring of a request family
+fn ring_request_id(family: u64, ring: usize) -> u64 {
+    family | (ring as u64) << RING_SHIFT
+}
+
+/// Request family a (ring) request ID belongs to
+fn request_family(request_id: u64) -> u64 {
+    request_id & ((1 << RING_SHIFT) - 1)
+}
+
+/// Ring index of a request ID (0 for the first ring)
+fn request_ring(request_id: u64) -> usize {
+    (request_id >> RING_SHIFT) as usize
+}
+
+
+/// Digest signed by the origin of a link-state advertisement
+fn link_state_digest(origin: &NodeId, sequence: u64, links: &[LinkStateEntry]) -> Message {
+    let mut hasher = Sha256::new();
+    hasher.update(LINK_STATE_CONTEXT);
+    hasher.update(origin);
+    hasher.update(sequence.to_be_bytes());
+    hasher.update(bincode::serialize(links).expect("link-state entries should be serializable"));
+    Message::from_digest(hasher.finalize().into())
+}
+
+/// Digest signed by the originator of a discovery message
+fn signed_discovery_digest(originator: &NodeId, timestamp: u64, message: &DiscoveryMessage) -> Result<Message, MeshError> {
+    let mut message = message.clone();
+    if let DiscoveryMessage::RouteAdvertisement { seen, .. } = &mut message {
+        *seen = BloomFilter::default();
+    }
+    let mut hasher = Sha256::new();
+    hasher.update(SIGNED_DISCOVERY_CONTEXT);
+    hasher.update(originator);
+    hasher.update(timestamp.to_be_bytes());
+    hasher.update(message.encode()?);
+    Ok(Message::from_digest(hasher.finalize().into()))
+}
+
+/// Whether `originator` produced `message`: the source of an advertisement
+/// or withdrawal, or a node on the route of a response (the destination or a
+/// node that answered from its cache)
+fn vouches_for(message: &DiscoveryMessage, originator: &NodeId) -> bool {
+    match message {
+        DiscoveryMessage::RouteAdvertisement { source, .. } => source == originator,
+        DiscoveryMessage::RouteResponse { route, .. } => route.contains(originator),
+        DiscoveryMessage::RouteWithdrawal { source, .. } => source == originator,
+        _ => false,
+    }
+}
+
+/// Serde for 64-byte signatures (serde derives arrays only up to 32)
+mod signature_bytes {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8; 64], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(bytes)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[u8; 64], D::Error> {
+        let bytes = Vec::<u8>::deserialize(deserializer)?;
+        bytes
+            .try_into()
+            .map_err(|_| serde::de::Error::custom("signature must be 64 bytes"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    const LOCAL: NodeId = [1u8; 32];
+    const RELAY: NodeId = [2u8; 32];
+    const DESTINATION: NodeId = [3u8; 32];
+
+    fn discovery(timeout_seconds: u64) -> RouteDiscovery {
+        let table = Arc::new(RoutingTable::new(3600).with_local_node_id(LOCAL));
+        table.add_direct_peer(RELAY, b"10.0.0.2:8334".to_vec());
+        RouteDiscovery::new(table, 10, timeout_seconds).with_local_node_id(LOCAL)
+    }
+
+    #[tokio::test]
+    async fn test_discover_route_times_out() {
+        let discovery = discovery(0);
+        let broadcasts = AtomicUsize::new(0);
+        let broadcast = |_request: DiscoveryMessage| {
+            broadcasts.fetch_add(1, Ordering::SeqCst);
+            async { Ok::<usize, MeshError>(1) }
+        };
+
+        assert_eq!(discovery.discover_route(DESTINATION, LOCAL, broadcast).await.unwrap(), None);
+        assert_eq!(broadcasts.load(Ordering::SeqCst), 1);
+    }
+
+    /// Request ID of a route request
+    fn request_id(request: &DiscoveryMessage) -> u64 {
+        match request {
+            DiscoveryMessage::RouteRequest { request_id, .. } => *request_id,
+            other => panic!("expected a route request, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_callers_share_request() {
+        let discovery = discovery(30);
+        let broadcasts = AtomicUsize::new(0);
+        let sent_id = AtomicU64::new(0);
+        let broadcast = |request: DiscoveryMessage| {
+            sent_id.store(request_id(&request), Ordering::SeqCst);
+            broadcasts.fetch_add(1, Ordering::SeqCst);
+            async { Ok::<usize, MeshError>(1) }
+        };
+        let route = vec![LOCAL, RELAY, DESTINATION];
+
+        let (first, second, ()) = tokio::join!(
+            discovery.discover_route(DESTINATION, LOCAL, &broadcast),
+            discovery.discover_route(DESTINATION, LOCAL, &broadcast),
+            async {
+                // Let both callers register before the response arrives
+                tokio::task::yield_now().await;
+                let response = response(sent_id.load(Ordering::SeqCst));
+                discovery.handle_route_response(&response, RELAY).await.unwrap();
+            }
+        );
+
+        assert_eq!(broadcasts.load(Ordering::SeqCst), 1);
+        assert_eq!(first.unwrap(), Some(route.clone()));
+        assert_eq!(second.unwrap(), Some(route));
+    }
+
+    #[tokio::test]
+    async fn test_cancel_pending_fails_waiters() {
+        let discovery = discovery(30);
+        let broadcast = |_request: DiscoveryMessage| async { Ok::<usize, MeshError>(1) };
+
+        let (result, cancelled) = tokio::join!(discovery.discover_route(DESTINATION, LOCAL, broadcast), async {
+            tokio::task::yield_now().await;
+            discovery
+                .cancel_pending(|| MeshError::MeshDisabled("Mesh is disabled".to_string()))
+                .await
+        });
+
+        assert_eq!(cancelled, 1);
+        assert!(matches!(result, Err(MeshError::MeshDisabled(_))));
+    }
+
+    fn response(request_id: u64) -> DiscoveryMessage {
+        DiscoveryMessage::RouteResponse {
+            destination: DESTINATION,
+            source: LOCAL,
+            request_id,
+            route: vec![LOCAL, RELAY, DESTINATION],
+            cost: 300,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unanswered_ring_widens() {
+        let discovery = discovery(30).with_ring_schedule(&[2], Duration::from_millis(10));
+        assert_eq!(discovery.ring_hops(), &[2, 10]);
+        let sent = std::sync::Mutex::new(Vec::new());
+        let broadcast = |request: DiscoveryMessage| {
+            if let DiscoveryMessage::RouteRequest { request_id, max_hops, .. } = request {
+                sent.lock().unwrap().push((request_id, max_hops));
+            }
+            async { Ok::<usize, MeshError>(1) }
+        };
+
+        let (route, rings) = tokio::join!(discovery.discover_route(DESTINATION, LOCAL, broadcast), async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            let family = sent.lock().unwrap()[0].0;
+            discovery.handle_route_response(&response(ring_request_id(family, 1)), RELAY).await.unwrap()
+        });
+
+        // Ring 1 shares the family of ring 0
+        let family = sent.lock().unwrap()[0].0;
+        assert_eq!(*sent.lock().unwrap(), vec![(family, 2), (ring_request_id(family, 1), 10)]);
+        assert_eq!(route.unwrap(), Some(vec![LOCAL, RELAY, DESTINATION]));
+        assert_eq!(rings, Some(2));
+    }
+
+    #[tokio::test]
+    async fn test_late_response_to_earlier_ring_accepted() {
+        let discovery = discovery(30).with_ring_schedule(&[2], Duration::from_millis(10));
+        let family = AtomicU64::new(0);
+        let broadcast = |request: DiscoveryMessage| {
+            family.fetch_max(request_id(&request), Ordering::SeqCst);
+            async { Ok::<usize, MeshError>(1) }
+        };
+
+        let (route, rings) = tokio::join!(discovery.discover_route(DESTINATION, LOCAL, broadcast), async {
+            // The wider ring is in flight by the time the first ring's answer arrives
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            let family = request_family(family.load(Ordering::SeqCst));
+            discovery.handle_route_response(&response(family), RELAY).await.unwrap()
+        });
+
+        assert_eq!(route.unwrap(), Some(vec![LOCAL, RELAY, DESTINATION]));
+        assert_eq!(rings, Some(1));
+        // The other ring's response finds nothing pending
+        let ring_1 = ring_request_id(request_family(family.load(Ordering::SeqCst)), 1);
+        assert_eq!(discovery.handle_route_response(&response(ring_1), RELAY).await.unwrap(), None);
+    }
+
+    fn request_from(source: NodeId, destination: NodeId, max_hops: u8) -> DiscoveryMessage {
+        DiscoveryMessage::RouteRequest {
+            destination,
+            source,
+            request_id: 1,
+            max_hops,
+            path: vec![source, RELAY],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_destination_answers_with_request_path() {
+        const SOURCE: NodeId = [5u8; 32];
+        let discovery = discovery(30);
+
+        let answer = discovery.handle_route_request(&request_from(SOURCE, LOCAL, 5), RELAY).await.unwrap();
+        let Some(DiscoveryMessage::RouteResponse { destination, route, cost, .. }) = answer else {
+            panic!("expected a route response");
+        };
+        assert_eq!(destination, LOCAL);
+        assert_eq!(route, vec![SOURCE, RELAY, LOCAL]);
+        assert_eq!(cost, 300);
+    }
+
+    #[tokio::test]
+    async fn test_cached_route_answer_includes_full_path() {
+        const SOURCE: NodeId = [5u8; 32];
+        const NEXT: NodeId = [6u8; 32];
+        let discovery = discovery(30);
+        discovery.routing_table.add_direct_peer(NEXT, b"10.0.0.6:8334".to_vec());
+        discovery.routing_table.add_route(RoutingEntry {
+            node_id: DESTINATION,
+            direct_address: None,
+            next_hop: Some(NEXT),
+            route_path: vec![LOCAL, NEXT, DESTINATION],
+            route_cost: 300,
+            last_updated: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(),
+            quality_score: 0.7,
+        });
+
+        let answer = discovery.handle_route_request(&request_from(SOURCE, DESTINATION, 5), RELAY).await.unwrap();
+        let Some(DiscoveryMessage::RouteResponse { route, .. }) = answer else {
+            panic!("expected a route response");
+        };
+        // The requester's path, this node, then the cached onward hops
+        assert_eq!(route, vec![SOURCE, RELAY, LOCAL, NEXT, DESTINATION]);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_destination_forwarded() {
+        const SOURCE: NodeId = [5u8; 32];
+        let discovery = discovery(30);
+
+        // Knowing other direct peers is no claim of reaching the destination
+        let forwarded = discovery.handle_route_request(&request_from(SOURCE, DESTINATION, 5), RELAY).await.unwrap();
+        let Some(DiscoveryMessage::RouteRequest { max_hops, path, .. }) = forwarded else {
+            panic!("expected the request to be flooded on");
+        };
+        assert_eq!(max_hops, 4);
+        assert_eq!(path, vec![SOURCE, RELAY, LOCAL]);
+
+        // Out of hops: dropped
+        let last_hop = DiscoveryMessage::RouteRequest {
+            destination: DESTINATION,
+            source: SOURCE,
+            request_id: 2,
+            max_hops: 1,
+            path: vec![SOURCE, RELAY],
+        };
+        assert!(discovery.handle_route_request(&last_hop, RELAY).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_diamond_flood_handled_once_per_node() {
+        // A - B, A - C, B - D, C - D; the destination is not in the mesh
+        const UNREACHABLE: NodeId = [9u8; 32];
+        let ids: Vec<NodeId> = (1..=4).map(|id| [id; 32]).collect();
+        let links = [(0, 1), (0, 2), (1, 3), (2, 3)];
+        let nodes: Vec<RouteDiscovery> = ids
+            .iter()
+            .map(|id| {
+                let table = Arc::new(RoutingTable::new(3600).with_local_node_id(*id));
+                RouteDiscovery::new(table, 10, 30).with_local_node_id(*id)
+            })
+            .collect();
+        let neighbors = |node: usize| {
+            links.iter().filter_map(move |&(a, b)| {
+                if a == node {
+                    Some(b)
+                } else if b == node {
+                    Some(a)
+                } else {
+                    None
+                }
+            })
+        };
+
+        let RouteLookup::Requested(request) = nodes[0].resolve_route(UNREACHABLE, ids[0]).await.unwrap() else {
+            panic!("expected a new route request");
+        };
+        let mut in_flight: std::collections::VecDeque<(usize, usize, DiscoveryMessage)> =
+            neighbors(0).map(|to| (to, 0, request.clone())).collect();
+        let mut forwarded = [0; 4];
+        let mut received = [0; 4];
+        while let Some((to, from, request)) = in_flight.pop_front() {
+            received[to] += 1;
+            if let Some(onward) = nodes[to].handle_route_request(&request, ids[from]).await.unwrap() {
+                forwarded[to] += 1;
+                // Never back to the node it came from
+                in_flight.extend(neighbors(to).filter(|next| *next != from).map(|next| (next, to, onward.clone())));
+            }
+        }
+
+        assert_eq!(forwarded, [0, 1, 1, 1]);
+        // D hears the request from both sides but floods it on once
+        assert_eq!(received[3], 2);
+    }
+
+    #[tokio::test]
+    async fn test_forwarded_request_installs_reverse_route() {
+        const SOURCE: NodeId = [5u8; 32];
+        const OTHER_RELAY: NodeId = [6u8; 32];
+        let discovery = discovery(30);
+        // A stale route to the source exists already
+        discovery.routing_table.add_route(RoutingEntry {
+            node_id: SOURCE,
+            direct_address: None,
+            next_hop: Some(OTHER_RELAY),
+            route_path: vec![LOCAL, OTHER_RELAY, SOURCE],
+            route_cost: 300,
+            last_updated: 0,
+            quality_score: 0.7,
+        });
+        let request = DiscoveryMessage::RouteRequest {
+            destination: DESTINATION,
+            source: SOURCE,
+            request_id: 1,
+            max_hops: 5,
+            path: vec![SOURCE, [7u8; 32], RELAY],
+        };
+
+        let forwarded = discovery.handle_route_request(&request, RELAY).await.unwrap();
+        assert!(matches!(forwarded, Some(DiscoveryMessage::RouteRequest { max_hops: 4, .. })));
+
+        let reverse = discovery
+            .routing_table
+            .candidates(&SOURCE)
+            .into_iter()
+            .find(|entry| entry.next_hop == Some(RELAY))
+            .unwrap();
+        assert_eq!(reverse.route_path, vec![LOCAL, RELAY, [7u8; 32], SOURCE]);
+        assert_eq!(reverse.route_cost, 400);
+        assert!(reverse.last_updated > 0);
+    }
+
+    #[tokio::test]
+    async fn test_route_error_purges_routes_and_finds_precursors() {
+        const REQUESTER: NodeId = [5u8; 32];
+        const UPSTREAM: NodeId = [6u8; 32];
+        let discovery = discovery(30);
+        // Relaying a response teaches us the route and that UPSTREAM uses it
+        discovery.learn_relayed_route(&DiscoveryMessage::RouteResponse {
+            destination: DESTINATION,
+            source: REQUESTER,
+            request_id: 1,
+            route: vec![REQUESTER, UPSTREAM, LOCAL, RELAY, DESTINATION],
+            cost: 500,
+        });
+        assert_eq!(discovery.routing_table.get_route(&DESTINATION).unwrap().next_hop, Some(RELAY));
+        assert_eq!(discovery.precursors(&DESTINATION), vec![UPSTREAM]);
+
+        let error = DiscoveryMessage::RouteError {
+            unreachable: vec![DESTINATION, LOCAL],
+            from: RELAY,
+        };
+        assert!(discovery.handle_route_error(&error, UPSTREAM).is_err());
+        assert_eq!(discovery.handle_route_error(&error, RELAY).unwrap(), vec![DESTINATION]);
+        assert!(discovery.routing_table.get_route(&DESTINATION).is_none());
+        // Seen before: ignored
+        assert!(discovery.handle_route_error(&error, RELAY).unwrap().is_empty());
+
+        let recipients = discovery.route_error_recipients(&[DESTINATION]);
+        assert_eq!(recipients.get(&UPSTREAM), Some(&vec![DESTINATION]));
+        assert!(discovery.precursors(&DESTINATION).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_route_withdrawal_purges_routes_through_source() {
+        const LEAVING: NodeId = [5u8; 32];
+        const OTHER: NodeId = [6u8; 32];
+        let discovery = discovery(30);
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        for (destination, route_path) in [
+            (DESTINATION, vec![LOCAL, RELAY, LEAVING, DESTINATION]),
+            (OTHER, vec![LOCAL, RELAY, OTHER]),
+        ] {
+            discovery.routing_table.add_route(RoutingEntry {
+                node_id: destination,
+                direct_address: None,
+                next_hop: Some(RELAY),
+                route_path,
+                route_cost: 300,
+                last_updated: now,
+                quality_score: 0.8,
+            });
+        }
+
+        let withdrawal = DiscoveryMessage::RouteWithdrawal {
+            withdrawn_destinations: vec![LEAVING, DESTINATION, OTHER],
+            source: LEAVING,
+            timestamp: now,
+        };
+        // New withdrawals come back to be flooded on, repeats do not
+        let flooded = discovery.handle_route_request(&withdrawal, RELAY).await.unwrap();
+        assert!(matches!(flooded, Some(DiscoveryMessage::RouteWithdrawal { .. })));
+        assert!(discovery.routing_table.get_route(&DESTINATION).is_none());
+        assert!(discovery.routing_table.get_route(&OTHER).is_some(), "not routed through the source");
+        assert!(discovery.handle_route_request(&withdrawal, RELAY).await.unwrap().is_none());
+
+        let stale = DiscoveryMessage::RouteWithdrawal {
+            withdrawn_destinations: vec![OTHER],
+            source: RELAY,
+            timestamp: now - 2 * MAX_DISCOVERY_AGE_SECONDS,
+        };
+        assert!(discovery.handle_route_request(&stale, RELAY).await.unwrap().is_none());
+        assert!(discovery.routing_table.get_route(&OTHER).is_some());
+    }
+
+    #[test]
+    fn test_originated_withdrawals_list_this_node_first() {
+        let discovery = discovery(30);
+        let withdrawals = discovery.originate_route_withdrawals(1_000).unwrap();
+        assert_eq!(withdrawals.len(), 1);
+        let DiscoveryMessage::RouteWithdrawal {
+            withdrawn_destinations,
+            source,
+            timestamp,
+        } = &withdrawals[0]
+        else {
+            panic!("expected a route withdrawal");
+        };
+        assert_eq!((*source, *timestamp), (LOCAL, 1_000));
+        assert_eq!(withdrawn_destinations, &vec![LOCAL, RELAY]);
+    }
+
+    #[tokio::test]
+    async fn test_seen_requests_bounded() {
+        // Nothing expires, so only the bound evicts
+        let discovery = discovery(1 << 32);
+        for request_id in 0..MAX_SEEN_REQUESTS as u64 + 10 {
+            assert!(discovery.remember_request(&discovery.seen_requests, (RELAY, request_id), request_id));
+        }
+
+        assert_eq!(discovery.seen_requests.len(), MAX_SEEN_REQUESTS);
+        // The oldest went first
+        assert!(!discovery.seen_requests.contains_key(&(RELAY, 0)));
+        assert!(discovery.seen_requests.contains_key(&(RELAY, MAX_SEEN_REQUESTS as u64 + 9)));
+    }
+
+    #[tokio::test]
+    async fn test_wider_ring_answered_once() {
+        let table = Arc::new(RoutingTable::new(3600).with_local_node_id(DESTINATION));
+        let responder = RouteDiscovery::new(table, 10, 30).with_local_node_id(DESTINATION);
+        let request = |request_id| DiscoveryMessage::RouteRequest {
+            destination: DESTINATION,
+            source: LOCAL,
+            request_id,
+            max_hops: 2,
+            path: vec![LOCAL, RELAY],
+        };
+
+        let answer = responder.handle_route_request(&request(1), RELAY).await.unwrap();
+        assert!(matches!(answer, Some(DiscoveryMessage::RouteResponse { request_id: 1, .. })));
+        assert!(responder.handle_route_request(&request(RING_1_REQUEST_ID), RELAY).await.unwrap().is_none());
+    }
+
+    fn advertised(message: &DiscoveryMessage) -> (Vec<RouteAdvertisementEntry>, u64) {
+        let DiscoveryMessage::RouteAdvertisement { routes, sequence, .. } = message else {
+            panic!("expected a route advertisement");
+        };
+        (routes.clone(), *sequence)
+    }
+
+    #[test]
+    fn test_route_advertisements_split_horizon_and_withdraw() {
+        const OTHER: NodeId = [4u8; 32];
+        let discovery = discovery(30);
+        discovery.routing_table.add_direct_peer(OTHER, b"10.0.0.4:8334".to_vec());
+        discovery.routing_table.add_route(RoutingEntry {
+            node_id: DESTINATION,
+            direct_address: None,
+            next_hop: Some(RELAY),
+            route_path: vec![LOCAL, RELAY, DESTINATION],
+            route_cost: 200,
+            last_updated: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(),
+            quality_score: 0.8,
+        });
+
+        let round: HashMap<NodeId, DiscoveryMessage> = discovery
+            .originate_route_advertisements()
+            .unwrap()
+            .into_iter()
+            .map(|(peer, _, message)| (peer, message))
+            .collect();
+        // RELAY hears neither about itself nor about the route through it
+        let (to_relay, first_sequence) = advertised(&round[&RELAY]);
+        assert_eq!(to_relay.len(), 1);
+        assert_eq!((to_relay[0].destination, to_relay[0].next_hop), (OTHER, LOCAL));
+        let (to_other, _) = advertised(&round[&OTHER]);
+        let via_relay = to_other.iter().find(|entry| entry.destination == DESTINATION).unwrap();
+        assert_eq!((via_relay.next_hop, via_relay.cost, via_relay.hop_count), (RELAY, 200, 2));
+
+        // A lost route is withdrawn in the next round
+        discovery.routing_table.remove_routes_via(&RELAY);
+        let round = discovery.originate_route_advertisements().unwrap();
+        let (_, _, message) = round.iter().find(|(peer, _, _)| *peer == OTHER).unwrap();
+        let (to_other, sequence) = advertised(message);
+        assert!(sequence > first_sequence);
+        let withdrawn = to_other.iter().find(|entry| entry.destination == DESTINATION).unwrap();
+        assert_eq!(withdrawn.cost, WITHDRAWN_ROUTE_COST);
+    }
+
+    #[tokio::test]
+    async fn test_stale_and_withdrawn_advertisements() {
+        let discovery = discovery(30);
+        let advertisement = |sequence, next_hop, cost| DiscoveryMessage::RouteAdvertisement {
+            routes: vec![RouteAdvertisementEntry {
+                destination: DESTINATION,
+                next_hop,
+                cost,
+                hop_count: 1,
+            }],
+            source: RELAY,
+            sequence,
+            seen: BloomFilter::default(),
+        };
+
+        // Routes back through this node are not learned
+        discovery.handle_route_advertisement(&advertisement(4, LOCAL, 100), RELAY).await.unwrap();
+        assert!(discovery.routing_table.get_route(&DESTINATION).is_none());
+
+        discovery.handle_route_advertisement(&advertisement(5, RELAY, 100), RELAY).await.unwrap();
+        assert!(discovery.routing_table.get_route(&DESTINATION).is_some());
+
+        // An older withdrawal is ignored, a newer one removes the route
+        let withdrawal = |sequence| advertisement(sequence, RELAY, WITHDRAWN_ROUTE_COST);
+        discovery.handle_route_advertisement(&withdrawal(4), RELAY).await.unwrap();
+        assert!(discovery.routing_table.get_route(&DESTINATION).is_some());
+        discovery.handle_route_advertisement(&withdrawal(6), RELAY).await.unwrap();
+        assert!(discovery.routing_table.get_route(&DESTINATION).is_none());
+    }
+
+    fn signed_response(keypair: &Keypair, timestamp: u64, request_id: u64) -> SignedDiscovery {
+        SignedDiscovery::sign(response(request_id), DESTINATION, timestamp, keypair).unwrap()
+    }
+
+    #[test]
+    fn test_tampered_route_vector_rejected() {
+        let keypair = crate::signing::signing_key_from_bytes(&[7u8; 32]).unwrap();
+        let peer_keys = PeerKeys::new();
+        peer_keys
+            .register(DESTINATION, &crate::signing::signing_public_key(&keypair))
+            .unwrap();
+        let now = 1_700_000_000;
+
+        let signed = signed_response(&keypair, now, 1);
+        assert_eq!(signed.verify(&peer_keys, now).unwrap(), RouteTrust::Verified);
+
+        // A relay rewrites the route to pass through a node it controls
+        let mut tampered = signed.clone();
+        if let DiscoveryMessage::RouteResponse { route, .. } = tampered.message.as_mut() {
+            route[1] = [9u8; 32];
+        }
+        assert!(matches!(
+            tampered.verify(&peer_keys, now),
+            Err(MeshError::InvalidSignature(_))
+        ));
+
+        // Too old, or claimed by a node that is not on the route
+        assert!(signed.verify(&peer_keys, now + MAX_DISCOVERY_AGE_SECONDS + 1).is_err());
+        let impostor = SignedDiscovery {
+            originator: [9u8; 32],
+            ..signed.clone()
+        };
+        assert!(impostor.verify(&peer_keys, now).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_unknown_originator_quarantined() {
+        let discovery = discovery(30);
+        let keypair = crate::signing::signing_key_from_bytes(&[7u8; 32]).unwrap();
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let RouteLookup::Requested(request) = discovery.resolve_route(DESTINATION, LOCAL).await.unwrap() else {
+            panic!("expected a route request");
+        };
+        let signed = signed_response(&keypair, now, request_id(&request));
+        let trust = signed.verify(&PeerKeys::new(), now).unwrap();
+        assert_eq!(trust, RouteTrust::Quarantined);
+
+        // Wait on the request already in flight
+        let broadcast = |_request: DiscoveryMessage| async { Ok::<usize, MeshError>(1) };
+        let waiter = discovery.discover_route(DESTINATION, LOCAL, broadcast);
+        let respond = async {
+            tokio::task::yield_now().await;
+            discovery
+                .handle_route_response_with_trust(&signed.message, RELAY, trust)
+                .await
+                .unwrap();
+        };
+        let (route, ()) = tokio::join!(waiter, respond);
+        assert_eq!(route.unwrap(), Some(vec![LOCAL, RELAY, DESTINATION]));
+
+        let entry = discovery.routing_table.get_route(&DESTINATION).unwrap();
+        assert_eq!(entry.quality_score, QUARANTINED_ROUTE_QUALITY);
+    }
+
+    #[tokio::test]
+    async fn test_request_flood_shed_per_neighbor() {
+        let limits = DiscoveryLimits {
+            request_rate: 1,
+            request_burst: 3,
+            ..DiscoveryLimits::default()
+        };
+        let discovery = discovery(30).with_limits(limits);
+        let flooder = [8u8; 32];
+
+        // Requests for an unknown destination are flooded on while within the burst
+        let mut flooded = 0;
+        for request_id in 0..10u64 {
+            let request = DiscoveryMessage::RouteRequest {
+                destination: [9u8; 32],
+                source: flooder,
+                request_id,
+                max_hops: 5,
+                path: vec![flooder],
+            };
+            if discovery.handle_route_request(&request, flooder).await.unwrap().is_some() {
+                flooded += 1;
+            }
+        }
+        assert_eq!(flooded, 3);
+
+        // A well-behaved neighbor is unaffected
+        let request = request_from([6u8; 32], [9u8; 32], 5);
+        assert!(discovery.handle_route_request(&request, RELAY).await.unwrap().is_some());
+
+        let stats = discovery.stats().await;
+        assert_eq!(stats.requests_rate_limited, 7);
+        assert_eq!(stats.rate_limited_neighbors, 2);
+    }
+
+    #[tokio::test]
+    async fn test_pending_requests_capped() {
+        let limits = DiscoveryLimits {
+            max_pending: 2,
+            max_pending_per_destination: 1,
+            ..DiscoveryLimits::default()
+        };
+        let discovery = discovery(30).with_limits(limits);
+
+        assert!(matches!(
+            discovery.resolve_route([7u8; 32], LOCAL).await.unwrap(),
+            RouteLookup::Requested(_)
+        ));
+        // The same destination shares the pending request
+        assert!(matches!(
+            discovery.resolve_route([7u8; 32], LOCAL).await.unwrap(),
+            RouteLookup::Pending
+        ));
+        assert!(matches!(
+            discovery.resolve_route([8u8; 32], LOCAL).await.unwrap(),
+            RouteLookup::Requested(_)
+        ));
+        assert!(matches!(
+            discovery.resolve_route([9u8; 32], LOCAL).await,
+            Err(MeshError::CapacityExceeded(_))
+        ));
+
+        let stats = discovery.stats().await;
+        assert_eq!(stats.pending_requests, 2);
+        assert_eq!(stats.pending_rejected, 1);
+    }
+
+    #[tokio::test]
+    async fn test_response_for_other_requester_ignored() {
+        let discovery = discovery(30);
+        let RouteLookup::Requested(request) = discovery.resolve_route(DESTINATION, LOCAL).await.unwrap() else {
+            panic!("expected a route request");
+        };
+        let request_id = request_id(&request);
+
+        // Another node's request that happens to use the same ID
+        let foreign = DiscoveryMessage::RouteResponse {
+            destination: DESTINATION,
+            source: [9u8; 32],
+            request_id,
+            route: vec![[9u8; 32], RELAY, DESTINATION],
+            cost: 300,
+        };
+        assert_eq!(discovery.handle_route_response(&foreign, RELAY).await.unwrap(), None);
+        assert!(discovery.routing_table.get_route(&DESTINATION).is_none());
+        assert_eq!(discovery.stats().await.pending_requests, 1);
+
+        assert_eq!(discovery.handle_route_response(&response(request_id), RELAY).await.unwrap(), Some(1));
+        assert_eq!(discovery.stats().await.pending_requests, 0);
+    }
+
+    #[tokio::test]
+    async fn test_request_ids_random() {
+        let discovery = discovery(30);
+        let mut families = HashSet::new();
+        for i in 10..20u8 {
+            let RouteLookup::Requested(request) = discovery.resolve_route([i; 32], LOCAL).await.unwrap() else {
+                panic!("expected a route request");
+            };
+            // Random families, not a counter restarting at 1, with the ring bits clear
+            let request_id = request_id(&request);
+            assert_eq!(request_ring(request_id), 0);
+            assert!(families.insert(request_id));
+        }
+    }
+}
@@ -0,0 +1,323 @@
+//! Routing earnings ledger
+//!
+//! Every payment this node accepts is recorded once in `PaymentLedger`,
+//! keyed by its proof hash, with this node's role on the packet's path and
+//! its share of the fee (`MeshManager::calculate_routing_fee`). A proof
+//! verified again (from the verification cache, or on a retry) is not
+//! counted twice.
+//!
+//! The `mesh_earnings` tree holds individual entries under
+//! `'e' + day + proof hash` and running totals per UTC day under
+//! `'d' + day`, written together in one storage transaction. Entries are
+//! kept for `mesh.earnings.retention_days`; daily totals are kept for good,
+//! so totals over any range stay cheap to compute.
+
+use crate::error::MeshError;
+use crate::routing::NodeId;
+use crate::storage_schema::{open_versioned_tree, TreeSchema};
+use bllvm_node::module::ipc::protocol::StorageOperation;
+use bllvm_node::module::traits::NodeAPI;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::{debug, warn};
+
+/// Default days individual entries are kept (`mesh.earnings.retention_days`)
+pub const DEFAULT_EARNINGS_RETENTION_DAYS: u64 = 30;
+
+/// Length of one aggregation period
+pub const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Storage tree for the earnings ledger
+const EARNINGS_TREE: &str = "mesh_earnings";
+
+/// Schema of the earnings tree
+pub const EARNINGS_SCHEMA: TreeSchema = TreeSchema {
+    tree: EARNINGS_TREE,
+    version: 1,
+    migrations: &[],
+};
+
+/// Key prefix of entries: 'e' + day (8, big-endian) + proof hash (32)
+const ENTRY_PREFIX: u8 = b'e';
+
+/// Key prefix of daily totals: 'd' + day (8, big-endian)
+const DAY_PREFIX: u8 = b'd';
+
+fn entry_key(day: u64, proof_hash: &[u8; 32]) -> Vec<u8> {
+    let mut key = Vec::with_capacity(41);
+    key.push(ENTRY_PREFIX);
+    key.extend_from_slice(&day.to_be_bytes());
+    key.extend_from_slice(proof_hash);
+    key
+}
+
+fn day_key(day: u64) -> Vec<u8> {
+    let mut key = Vec::with_capacity(9);
+    key.push(DAY_PREFIX);
+    key.extend_from_slice(&day.to_be_bytes());
+    key
+}
+
+/// This node's position on a paid packet's path
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EarningsRole {
+    /// The packet was sent by this node
+    Source,
+    /// This node relayed the packet
+    Intermediate,
+    /// The packet was addressed to this node
+    Destination,
+}
+
+/// One accepted payment
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EarningsEntry {
+    /// Hash of the payment proof (`PaymentProof::hash`)
+    pub proof_hash: [u8; 32],
+    /// Source of the paid packet
+    pub source: NodeId,
+    /// Destination of the paid packet
+    pub destination: NodeId,
+    /// Amount the payment was verified for in satoshis
+    pub amount_sats: u64,
+    /// This node's role on the packet's path
+    pub role: EarningsRole,
+    /// This node's share of the fee in satoshis
+    pub fee_sats: u64,
+    /// When the payment was accepted (Unix seconds)
+    pub timestamp: u64,
+}
+
+impl EarningsEntry {
+    /// JSON form for RPC responses (node IDs and hash in hex)
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "proof_hash": hex::encode(self.proof_hash),
+            "source": hex::encode(self.source),
+            "destination": hex::encode(self.destination),
+            "amount_sats": self.amount_sats,
+            "role": self.role,
+            "fee_sats": self.fee_sats,
+            "timestamp": self.timestamp,
+        })
+    }
+}
+
+/// Totals over one UTC day
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DailyEarnings {
+    /// Start of the day (Unix seconds)
+    pub day_start: u64,
+    /// Payments accepted
+    pub payments: u64,
+    /// Sats the payments were verified for
+    pub amount_sats: u64,
+    /// This node's fee share in sats
+    pub fee_sats: u64,
+}
+
+/// Earnings over a time range, as reported by `mesh_getearnings`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EarningsReport {
+    /// Totals over the days overlapping the range, oldest first
+    pub days: Vec<DailyEarnings>,
+    /// Retained entries inside the range, newest first
+    pub entries: Vec<EarningsEntry>,
+}
+
+impl EarningsReport {
+    /// Payments, verified sats and fee share summed over `days`
+    pub fn totals(&self) -> (u64, u64, u64) {
+        self.days.iter().fold((0, 0, 0), |(payments, amount, fee), day| {
+            (
+                payments + day.payments,
+                amount.saturating_add(day.amount_sats),
+                fee.saturating_add(day.fee_sats),
+            )
+        })
+    }
+}
+
+/// Persistent ledger of accepted payments
+pub struct PaymentLedger {
+    node_api: Arc<dyn NodeAPI>,
+    /// Storage tree ID (None if storage is unavailable)
+    tree_id: Option<String>,
+    /// Retained entries by proof hash
+    entries: DashMap<[u8; 32], EarningsEntry>,
+    /// Totals by day number (timestamp / `SECONDS_PER_DAY`)
+    days: DashMap<u64, DailyEarnings>,
+    /// Days individual entries are kept
+    retention_days: u64,
+}
+
+impl PaymentLedger {
+    /// Open the ledger, restoring retained entries and daily totals from storage
+    ///
+    /// Fails only if the stored schema is newer than this build.
+    pub async fn load(node_api: Arc<dyn NodeAPI>, retention_days: u64, now: u64) -> Result<Self, MeshError> {
+        let tree_id = match open_versioned_tree(node_api.as_ref(), &EARNINGS_SCHEMA).await {
+            Ok(tree_id) => Some(tree_id),
+            Err(e @ MeshError::UnsupportedVersion(_)) => return Err(e),
+            Err(e) => {
+                warn!("Earnings storage unavailable, keeping earnings in memory only: {}", e);
+                None
+            }
+        };
+
+        let ledger = Self {
+            node_api,
+            tree_id,
+            entries: DashMap::new(),
+            days: DashMap::new(),
+            retention_days: retention_days.max(1),
+        };
+
+        if let Some(ref tree_id) = ledger.tree_id {
+            match ledger.node_api.storage_iter(tree_id.clone()).await {
+                Ok(stored) => {
+                    let oldest_day = ledger.oldest_retained_day(now);
+                    for (key, value) in stored {
+                        match key.first() {
+                            Some(&ENTRY_PREFIX) => {
+                                if let Ok(entry) = bincode::deserialize::<EarningsEntry>(&value) {
+                                    if entry.timestamp / SECONDS_PER_DAY >= oldest_day {
+                                        ledger.entries.insert(entry.proof_hash, entry);
+                                    }
+                                }
+                            }
+                            Some(&DAY_PREFIX) => {
+                                if let Ok(totals) = bincode::deserialize::<DailyEarnings>(&value) {
+                                    ledger.days.insert(totals.day_start / SECONDS_PER_DAY, totals);
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+                    debug!(
+                        "Restored {} earnings entries over {} days",
+                        ledger.entries.len(),
+                        ledger.days.len()
+                    );
+                }
+                Err(e) => warn!("Failed to restore earnings: {}", e),
+            }
+        }
+
+        Ok(ledger)
+    }
+
+    /// Record an accepted payment; false if its proof was already recorded
+    pub async fn record(&self, entry: EarningsEntry) -> bool {
+        match self.entries.entry(entry.proof_hash) {
+            dashmap::mapref::entry::Entry::Occupied(_) => return false,
+            dashmap::mapref::entry::Entry::Vacant(vacant) => {
+                vacant.insert(entry.clone());
+            }
+        }
+
+        let day = entry.timestamp / SECONDS_PER_DAY;
+        let totals = {
+            let mut totals = self.days.entry(day).or_insert(DailyEarnings {
+                day_start: day * SECONDS_PER_DAY,
+                ..DailyEarnings::default()
+            });
+            totals.payments += 1;
+            totals.amount_sats = totals.amount_sats.saturating_add(entry.amount_sats);
+            totals.fee_sats = totals.fee_sats.saturating_add(entry.fee_sats);
+            *totals
+        };
+        debug!(
+            "Earnings recorded: role={:?}, amount={} sats, fee share={} sats, proof={:x?}",
+            entry.role,
+            entry.amount_sats,
+            entry.fee_sats,
+            &entry.proof_hash[..8]
+        );
+
+        if let Some(ref tree_id) = self.tree_id {
+            let operations = vec![
+                StorageOperation::Insert {
+                    key: entry_key(day, &entry.proof_hash),
+                    value: bincode::serialize(&entry).expect("earnings entry should be serializable"),
+                },
+                StorageOperation::Insert {
+                    key: day_key(day),
+                    value: bincode::serialize(&totals).expect("daily earnings should be serializable"),
+                },
+            ];
+            if let Err(e) = self.node_api.storage_transaction(tree_id.clone(), operations).await {
+                warn!("Failed to persist earnings: {}", e);
+            }
+        }
+        true
+    }
+
+    /// Whether a payment with this proof hash was recorded (and is still retained)
+    pub fn contains(&self, proof_hash: &[u8; 32]) -> bool {
+        self.entries.contains_key(proof_hash)
+    }
+
+    /// Daily totals and retained entries between `from` and `to` (Unix seconds, inclusive)
+    ///
+    /// Days are included whole when any part of them is in the range. At
+    /// most `limit` entries are returned, newest first.
+    pub fn report(&self, from: u64, to: u64, limit: usize) -> EarningsReport {
+        let (first_day, last_day) = (from / SECONDS_PER_DAY, to / SECONDS_PER_DAY);
+        let mut days: Vec<DailyEarnings> = self
+            .days
+            .iter()
+            .filter(|totals| (first_day..=last_day).contains(totals.key()))
+            .map(|totals| *totals.value())
+            .collect();
+        days.sort_by_key(|totals| totals.day_start);
+
+        let mut entries: Vec<EarningsEntry> = self
+            .entries
+            .iter()
+            .filter(|entry| (from..=to).contains(&entry.timestamp))
+            .map(|entry| entry.value().clone())
+            .collect();
+        entries.sort_by(|a, b| b.timestamp.cmp(&a.timestamp).then(a.proof_hash.cmp(&b.proof_hash)));
+        entries.truncate(limit);
+
+        EarningsReport { days, entries }
+    }
+
+    /// Forget entries older than the retention period (daily totals stay)
+    pub async fn cleanup_expired(&self, now: u64) {
+        let oldest_day = self.oldest_retained_day(now);
+        let expired: Vec<EarningsEntry> = self
+            .entries
+            .iter()
+            .filter(|entry| entry.timestamp / SECONDS_PER_DAY < oldest_day)
+            .map(|entry| entry.value().clone())
+            .collect();
+        if expired.is_empty() {
+            return;
+        }
+        for entry in &expired {
+            self.entries.remove(&entry.proof_hash);
+        }
+        debug!("Cleaned up {} expired earnings entries", expired.len());
+
+        if let Some(ref tree_id) = self.tree_id {
+            let operations = expired
+                .iter()
+                .map(|entry| StorageOperation::Remove {
+                    key: entry_key(entry.timestamp / SECONDS_PER_DAY, &entry.proof_hash),
+                })
+                .collect();
+            if let Err(e) = self.node_api.storage_transaction(tree_id.clone(), operations).await {
+                warn!("Failed to prune earnings: {}", e);
+            }
+        }
+    }
+
+    /// First day whose entries are retained
+    fn oldest_retained_day(&self, now: u64) -> u64 {
+        (now / SECONDS_PER_DAY).saturating_sub(self.retention_days - 1)
+    }
+}
@@ -33,5 +33,69 @@ pub enum MeshError {
     
     #[error("Mesh disabled: {0}")]
     MeshDisabled(String),
+    
+    #[error("Route constraints unsatisfiable: {0}")]
+    ConstraintUnsatisfiable(String),
+    
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+    
+    #[error("Unsupported version: {0}")]
+    UnsupportedVersion(String),
+    
+    #[error("Bandwidth cap reached: {0}")]
+    BandwidthCapReached(String),
+    
+    #[error("TTL expired: {0}")]
+    TtlExpired(String),
+    
+    #[error("Invalid signature: {0}")]
+    InvalidSignature(String),
+    
+    #[error("Handshake failed: {0}")]
+    HandshakeFailed(String),
+    
+    #[error("Capacity exceeded: {0}")]
+    CapacityExceeded(String),
+    
+    #[error("Rate limited: source {}", hex::encode(&.0[..8]))]
+    RateLimited([u8; 32]),
+    
+    #[error("Access denied: node {}", hex::encode(&.0[..8]))]
+    AccessDenied([u8; 32]),
+    
+    #[error("Network error: {0}")]
+    NetworkError(String),
+    
+    #[error("IPC error: {0}")]
+    IpcError(String),
+    
+    #[error("Timed out: {0}")]
+    Timeout(String),
+}
+
+impl From<bincode::Error> for MeshError {
+    fn from(e: bincode::Error) -> Self {
+        MeshError::IpcError(format!("Serialization failed: {}", e))
+    }
+}
+
+impl From<bllvm_node::module::traits::ModuleError> for MeshError {
+    fn from(e: bllvm_node::module::traits::ModuleError) -> Self {
+        MeshError::ModuleError(e.to_string())
+    }
+}
+
+impl MeshError {
+    /// Whether the operation may succeed if retried later
+    pub fn is_retriable(&self) -> bool {
+        matches!(
+            self,
+            MeshError::BandwidthCapReached(_)
+                | MeshError::CapacityExceeded(_)
+                | MeshError::RateLimited(_)
+                | MeshError::NetworkError(_)
+        )
+    }
 }
 
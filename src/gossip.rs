@@ -0,0 +1,402 @@
+//! Epidemic gossip of route advertisements
+//!
+//! A node that learns a new `DiscoveryMessage::RouteAdvertisement` forwards
+//! it to a random subset of `fanout` direct peers instead of flooding every
+//! link. Each copy carries a seen-set bloom filter of the nodes that already
+//! have it (the relays and the peers they picked), so the next hop skips
+//! those peers. A local bloom filter of advertisement digests makes every
+//! node forward a given advertisement at most once.
+
+use crate::discovery::{DiscoveryMessage, RouteAdvertisementEntry};
+use crate::routing::{NodeId, RoutingTable};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::OsRng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::{Arc, Mutex};
+use tracing::debug;
+
+/// Default number of direct peers an advertisement is forwarded to
+pub const DEFAULT_GOSSIP_FANOUT: usize = 3;
+
+/// Size of the seen-set filter carried by advertisements (bits)
+pub const SEEN_SET_BITS: usize = 256;
+
+/// Hash functions of the seen-set filter
+pub const SEEN_SET_HASHES: u8 = 3;
+
+/// Size of the local filter of forwarded advertisements (bits)
+const SEEN_FILTER_BITS: usize = 64 * 1024;
+
+/// Hash functions of the local filter
+const SEEN_FILTER_HASHES: u8 = 4;
+
+/// Advertisements remembered before the local filter is reset
+///
+/// Keeps the false-positive rate below ~0.3%; after a reset an old
+/// advertisement may be forwarded once more.
+const SEEN_FILTER_CAPACITY: usize = 4096;
+
+/// Fixed-size bloom filter over byte strings
+///
+/// Bit indices are taken from SHA256 of the item, 4 bytes per hash function,
+/// so at most 8 hash functions are used.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BloomFilter {
+    bits: Vec<u8>,
+    hashes: u8,
+    /// Items inserted since creation or the last `clear`
+    #[serde(skip)]
+    inserted: usize,
+}
+
+impl Default for BloomFilter {
+    /// An empty seen-set filter
+    fn default() -> Self {
+        Self::new(SEEN_SET_BITS, SEEN_SET_HASHES)
+    }
+}
+
+impl BloomFilter {
+    /// Create an empty filter of `bits` bits (rounded up to whole bytes)
+    pub fn new(bits: usize, hashes: u8) -> Self {
+        Self {
+            bits: vec![0u8; bits.div_ceil(8).max(1)],
+            hashes: hashes.clamp(1, 8),
+            inserted: 0,
+        }
+    }
+
+    /// Create an empty filter sized to hold `capacity` items at about
+    /// `false_positive_rate`
+    ///
+    /// Uses the optimal number of hash functions up to the 8 available and
+    /// sizes the bit array for that number.
+    pub fn with_false_positive_rate(capacity: usize, false_positive_rate: f64) -> Self {
+        let items = capacity.max(1) as f64;
+        let rate = false_positive_rate.clamp(1e-9, 0.5);
+        let hashes = (-rate.log2()).round().clamp(1.0, 8.0);
+        // p = (1 - e^(-k·n/m))^k solved for m
+        let bits = (-hashes * items / (1.0 - rate.powf(1.0 / hashes)).ln()).ceil();
+        Self::new(bits as usize, hashes as u8)
+    }
+
+    /// Size of the filter in bits
+    pub fn bit_len(&self) -> usize {
+        self.bits.len() * 8
+    }
+
+    /// Number of hash functions
+    pub fn hash_count(&self) -> u8 {
+        self.hashes
+    }
+
+    /// Expected false-positive rate with the items inserted so far
+    pub fn false_positive_rate(&self) -> f64 {
+        false_positive_rate(self.bit_len(), self.hashes, self.inserted)
+    }
+
+    /// Add an item
+    pub fn insert(&mut self, item: &[u8]) {
+        for index in self.indices(item) {
+            self.bits[index / 8] |= 1 << (index % 8);
+        }
+        self.inserted += 1;
+    }
+
+    /// Whether the item may have been inserted (false positives possible)
+    pub fn contains(&self, item: &[u8]) -> bool {
+        let mut indices = self.indices(item).peekable();
+        indices.peek().is_some() && indices.all(|index| self.bits[index / 8] & (1 << (index % 8)) != 0)
+    }
+
+    /// Items inserted since creation or the last `clear`
+    pub fn len(&self) -> usize {
+        self.inserted
+    }
+
+    /// Whether nothing has been inserted
+    pub fn is_empty(&self) -> bool {
+        self.inserted == 0
+    }
+
+    /// Remove all items
+    pub fn clear(&mut self) {
+        self.bits.fill(0);
+        self.inserted = 0;
+    }
+
+    fn indices(&self, item: &[u8]) -> impl Iterator<Item = usize> {
+        let digest = Sha256::digest(item);
+        let bit_count = self.bits.len() * 8;
+        // Filters decoded from the wire may be empty or claim more hashes
+        let hashes = if bit_count == 0 { 0 } else { self.hashes.min(8) as usize };
+        (0..hashes).map(move |i| {
+            let word = u32::from_be_bytes(digest[i * 4..i * 4 + 4].try_into().unwrap());
+            word as usize % bit_count
+        })
+    }
+}
+
+/// Expected false-positive rate of a bloom filter of `bits` bits and
+/// `hashes` hash functions holding `items` items: `(1 - e^(-k·n/m))^k`
+pub fn false_positive_rate(bits: usize, hashes: u8, items: usize) -> f64 {
+    if bits == 0 || items == 0 {
+        return 0.0;
+    }
+    let hashes = hashes as f64;
+    (1.0 - (-hashes * items as f64 / bits as f64).exp()).powf(hashes)
+}
+
+/// An advertisement and the direct peers to forward it to
+#[derive(Debug, Clone)]
+pub struct GossipForward {
+    /// Advertisement with the updated seen-set
+    pub message: DiscoveryMessage,
+    /// Chosen direct peers (node ID, address)
+    pub peers: Vec<(NodeId, Vec<u8>)>,
+}
+
+/// Forwards route advertisements to a random subset of direct peers
+pub struct GossipManager {
+    /// Direct peers an advertisement is forwarded to
+    fanout: usize,
+    /// Digests of advertisements already forwarded
+    seen_filter: Mutex<BloomFilter>,
+    /// Routing table the direct peers are taken from
+    routing_table: Arc<RoutingTable>,
+    /// This node's ID, added to forwarded seen-sets
+    node_id: NodeId,
+}
+
+impl GossipManager {
+    /// Create a new gossip manager
+    pub fn new(fanout: usize, routing_table: Arc<RoutingTable>, node_id: NodeId) -> Self {
+        Self {
+            fanout,
+            seen_filter: Mutex::new(BloomFilter::new(SEEN_FILTER_BITS, SEEN_FILTER_HASHES)),
+            routing_table,
+            node_id,
+        }
+    }
+
+    /// Direct peers an advertisement is forwarded to
+    pub fn fanout(&self) -> usize {
+        self.fanout
+    }
+
+    /// Handle a discovery message received from direct peer `from`
+    ///
+    /// Returns the advertisement to forward and the peers to send it to, or
+    /// None if the message is not a route advertisement, was already
+    /// forwarded, or every peer has seen it.
+    pub fn on_message_received(&self, message: &DiscoveryMessage, from: NodeId) -> Option<GossipForward> {
+        let DiscoveryMessage::RouteAdvertisement {
+            routes,
+            source,
+            sequence,
+            seen,
+        } = message
+        else {
+            return None;
+        };
+        if self.fanout == 0 || !self.mark_seen(&advertisement_digest(routes, source, *sequence)) {
+            return None;
+        }
+
+        let mut seen = if seen.bits.len() * 8 == SEEN_SET_BITS {
+            seen.clone()
+        } else {
+            BloomFilter::default()
+        };
+        seen.insert(&from);
+        seen.insert(&self.node_id);
+
+        let mut candidates: Vec<(NodeId, Vec<u8>)> = self
+            .routing_table
+            .direct_peer_addresses()
+            .into_iter()
+            .filter(|(peer, _)| *peer != from && peer != source && !seen.contains(peer))
+            .collect();
+        let peers = choose_random(&mut candidates, self.fanout);
+        if peers.is_empty() {
+            return None;
+        }
+        for (peer, _) in &peers {
+            seen.insert(peer);
+        }
+
+        debug!(
+            "Gossiping route advertisement: source={:x?}, peers={}",
+            &source[..8],
+            peers.len()
+        );
+        Some(GossipForward {
+            message: DiscoveryMessage::RouteAdvertisement {
+                routes: routes.clone(),
+                source: *source,
+                sequence: *sequence,
+                seen,
+            },
+            peers,
+        })
+    }
+
+    /// Record an advertisement; false if it was already seen
+    fn mark_seen(&self, digest: &[u8; 32]) -> bool {
+        let mut filter = self.seen_filter.lock().unwrap();
+        if filter.contains(digest) {
+            return false;
+        }
+        if filter.len() >= SEEN_FILTER_CAPACITY {
+            filter.clear();
+        }
+        filter.insert(digest);
+        true
+    }
+}
+
+/// Digest identifying an advertisement regardless of its seen-set
+fn advertisement_digest(routes: &[RouteAdvertisementEntry], source: &NodeId, sequence: u64) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(source);
+    hasher.update(sequence.to_be_bytes());
+    for route in routes {
+        hasher.update(route.destination);
+        hasher.update(route.next_hop);
+        hasher.update(route.cost.to_be_bytes());
+        hasher.update(route.hop_count.to_be_bytes());
+    }
+    hasher.finalize().into()
+}
+
+/// Move up to `count` random items out of `items` (partial Fisher-Yates)
+fn choose_random<T>(items: &mut Vec<T>, count: usize) -> Vec<T> {
+    let count = count.min(items.len());
+    for i in 0..count {
+        let j = i + (OsRng.next_u64() % (items.len() - i) as u64) as usize;
+        items.swap(i, j);
+    }
+    items.drain(..count).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ME: NodeId = [1u8; 32];
+    const SOURCE: NodeId = [9u8; 32];
+
+    fn manager(peers: u8, fanout: usize) -> GossipManager {
+        let routing_table = Arc::new(RoutingTable::new(3600));
+        for i in 0..peers {
+            routing_table.add_direct_peer([10 + i; 32], format!("10.0.0.{}:8334", i).into_bytes());
+        }
+        GossipManager::new(fanout, routing_table, ME)
+    }
+
+    fn advertisement(cost: u64) -> DiscoveryMessage {
+        DiscoveryMessage::RouteAdvertisement {
+            routes: vec![RouteAdvertisementEntry {
+                destination: [3u8; 32],
+                next_hop: SOURCE,
+                cost,
+                hop_count: 1,
+            }],
+            source: SOURCE,
+            sequence: 1,
+            seen: BloomFilter::default(),
+        }
+    }
+
+    #[test]
+    fn test_bloom_filter() {
+        let mut filter = BloomFilter::default();
+        assert!(!filter.contains(b"a"));
+        filter.insert(b"a");
+        assert!(filter.contains(b"a"));
+        assert!(!filter.contains(b"b"));
+        assert_eq!(filter.len(), 1);
+        filter.clear();
+        assert!(!filter.contains(b"a"));
+
+        // A filter decoded with no bits contains nothing
+        let empty = BloomFilter {
+            bits: Vec::new(),
+            hashes: 3,
+            inserted: 0,
+        };
+        assert!(!empty.contains(b"a"));
+    }
+
+    #[test]
+    fn test_bloom_filter_sized_for_false_positive_rate() {
+        let mut filter = BloomFilter::with_false_positive_rate(1000, 0.001);
+        assert_eq!(filter.hash_count(), 8);
+        for i in 0u32..1000 {
+            filter.insert(&i.to_be_bytes());
+        }
+        assert!((filter.false_positive_rate() - 0.001).abs() < 0.0001);
+
+        let false_positives = (1000u32..101_000).filter(|i| filter.contains(&i.to_be_bytes())).count();
+        assert!(false_positives < 200, "{} false positives in 100000", false_positives);
+    }
+
+    #[test]
+    fn test_forwards_new_advertisement_to_fanout_peers_once() {
+        let gossip = manager(8, 3);
+        let from = [10u8; 32];
+        let forward = gossip.on_message_received(&advertisement(100), from).unwrap();
+        assert_eq!(forward.peers.len(), 3);
+        assert!(forward.peers.iter().all(|(peer, _)| *peer != from));
+
+        let DiscoveryMessage::RouteAdvertisement { seen, .. } = &forward.message else {
+            panic!("expected a route advertisement");
+        };
+        assert!(seen.contains(&from));
+        assert!(seen.contains(&ME));
+        assert!(forward.peers.iter().all(|(peer, _)| seen.contains(peer)));
+
+        // The same advertisement (with any seen-set) is not forwarded again
+        assert!(gossip.on_message_received(&forward.message, [11u8; 32]).is_none());
+        // A changed advertisement is new
+        assert!(gossip.on_message_received(&advertisement(200), from).is_some());
+    }
+
+    #[test]
+    fn test_skips_peers_in_seen_set() {
+        let gossip = manager(4, 3);
+        let mut seen = BloomFilter::default();
+        seen.insert(&[11u8; 32]);
+        seen.insert(&[12u8; 32]);
+        let DiscoveryMessage::RouteAdvertisement {
+            routes, source, sequence, ..
+        } = advertisement(100)
+        else {
+            unreachable!()
+        };
+        let message = DiscoveryMessage::RouteAdvertisement {
+            routes,
+            source,
+            sequence,
+            seen,
+        };
+
+        let forward = gossip.on_message_received(&message, [10u8; 32]).unwrap();
+        let peers: Vec<NodeId> = forward.peers.iter().map(|(peer, _)| *peer).collect();
+        assert_eq!(peers, vec![[13u8; 32]]);
+    }
+
+    #[test]
+    fn test_non_advertisements_ignored() {
+        let gossip = manager(4, 3);
+        let request = DiscoveryMessage::RouteRequest {
+            destination: [3u8; 32],
+            source: SOURCE,
+            request_id: 1,
+            max_hops: 4,
+            path: vec![SOURCE],
+        };
+        assert!(gossip.on_message_received(&request, [10u8; 32]).is_none());
+    }
+}
@@ -0,0 +1,663 @@
+//! Noise_XX handshake for authenticated sessions between direct peers
+//!
+//! With `mesh.noise_handshake` enabled, a newly connected peer is not trusted
+//! by address. Both sides run a `Noise_XX_25519_ChaChaPoly_SHA256` handshake
+//! over `NoiseFrame`s; each proves possession of its static key. Every frame
+//! after the handshake is encrypted with the session's transport keys, and
+//! each transport nonce is accepted once (see `NONCE_WINDOW_SIZE`).
+//!
+//! Noise needs a Diffie-Hellman static key, so the static key is X25519. It
+//! is not the node's identity: node IDs are derived from signing keys in
+//! every mode (see `signing::node_id_from_signing_key`), and the first frame
+//! over a new session is a Hello, signed with the node's signing key, that
+//! names the session's static key. That signed binding is what ties the
+//! session to a node ID.
+//! Since both sides see PeerConnected, both start as initiator; colliding
+//! handshakes are resolved by the random `tiebreak` in `NoiseFrame::Initiate`
+//! (the higher value stays initiator).
+
+use crate::error::MeshError;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::OsRng;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use snow::{HandshakeState, StatelessTransportState};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tracing::debug;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+/// Noise protocol used for peer sessions
+pub const NOISE_PARAMS: &str = "Noise_XX_25519_ChaChaPoly_SHA256";
+
+/// Magic bytes prefixing every `NoiseFrame` on the wire
+pub const NOISE_FRAME_MAGIC: [u8; 4] = *b"MNSE";
+
+/// Largest Noise message (handshake or transport)
+pub const NOISE_MAX_MESSAGE_LEN: usize = 65535;
+
+/// AEAD tag appended to every encrypted Noise message
+pub const NOISE_TAG_LEN: usize = 16;
+
+/// Largest mesh frame that fits in one transport message
+pub const MAX_SEALED_FRAME_LEN: usize = NOISE_MAX_MESSAGE_LEN - NOISE_TAG_LEN;
+
+/// Default time allowed for a handshake to complete
+pub const DEFAULT_HANDSHAKE_TIMEOUT_SECS: u64 = 30;
+
+/// Transport nonces accepted out of order behind the highest one received
+pub const NONCE_WINDOW_SIZE: u64 = 1024;
+
+/// Static X25519 key a node authenticates its sessions with
+#[derive(Clone)]
+pub struct NoiseKeypair {
+    secret: [u8; 32],
+    public: [u8; 32],
+}
+
+impl NoiseKeypair {
+    /// Restore a static key from its secret bytes
+    pub fn from_secret_bytes(secret: [u8; 32]) -> Self {
+        let public = PublicKey::from(&StaticSecret::from(secret)).to_bytes();
+        Self { secret, public }
+    }
+
+    /// Static public key
+    pub fn public_key(&self) -> [u8; 32] {
+        self.public
+    }
+}
+
+/// Frame exchanged between direct peers when handshakes are enabled
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum NoiseFrame {
+    /// First handshake message (`-> e`)
+    Initiate {
+        /// Random value deciding which side initiates when both do
+        tiebreak: u64,
+        /// Noise message
+        message: Vec<u8>,
+    },
+    /// Later handshake messages (`<- e, ee, s, es` and `-> s, se`)
+    Handshake(Vec<u8>),
+    /// Mesh frame encrypted with the session's transport keys
+    Transport {
+        /// Noise nonce (per direction, carried so frames may be reordered)
+        nonce: u64,
+        /// Encrypted frame
+        ciphertext: Vec<u8>,
+    },
+}
+
+impl NoiseFrame {
+    /// Encode for the wire (magic + bincode)
+    pub fn encode(&self) -> Result<Vec<u8>, MeshError> {
+        let mut data = NOISE_FRAME_MAGIC.to_vec();
+        bincode::serialize_into(&mut data, self)
+            .map_err(|e| MeshError::InvalidPacket(format!("Failed to encode noise frame: {}", e)))?;
+        Ok(data)
+    }
+
+    /// Decode a frame, or None if the data is not a noise frame
+    pub fn extract(data: &[u8]) -> Option<Result<Self, MeshError>> {
+        let body = data.strip_prefix(&NOISE_FRAME_MAGIC[..])?;
+        Some(
+            bincode::deserialize(body)
+                .map_err(|e| MeshError::InvalidPacket(format!("Malformed noise frame: {}", e))),
+        )
+    }
+}
+
+/// In-progress Noise_XX handshake with one peer
+pub struct MeshHandshake {
+    /// This node's static key
+    local_keypair: NoiseKeypair,
+    /// Peer's static key, once received
+    remote_static: Option<[u8; 32]>,
+    state: HandshakeState,
+}
+
+impl MeshHandshake {
+    /// Start a handshake as initiator
+    pub fn initiator(local_keypair: &NoiseKeypair) -> Result<Self, MeshError> {
+        Self::build(local_keypair, true)
+    }
+
+    /// Start a handshake as responder
+    pub fn responder(local_keypair: &NoiseKeypair) -> Result<Self, MeshError> {
+        Self::build(local_keypair, false)
+    }
+
+    fn build(local_keypair: &NoiseKeypair, initiator: bool) -> Result<Self, MeshError> {
+        let params = NOISE_PARAMS.parse().map_err(noise_error)?;
+        let builder = snow::Builder::new(params).local_private_key(&local_keypair.secret);
+        let state = if initiator {
+            builder.build_initiator()
+        } else {
+            builder.build_responder()
+        }
+        .map_err(noise_error)?;
+        Ok(Self {
+            local_keypair: local_keypair.clone(),
+            remote_static: None,
+            state,
+        })
+    }
+
+    /// Write the next handshake message
+    pub fn write_message(&mut self) -> Result<Vec<u8>, MeshError> {
+        let mut message = vec![0u8; NOISE_MAX_MESSAGE_LEN];
+        let len = self.state.write_message(&[], &mut message).map_err(noise_error)?;
+        message.truncate(len);
+        Ok(message)
+    }
+
+    /// Read the peer's next handshake message
+    pub fn read_message(&mut self, message: &[u8]) -> Result<(), MeshError> {
+        let mut payload = vec![0u8; NOISE_MAX_MESSAGE_LEN];
+        self.state.read_message(message, &mut payload).map_err(noise_error)?;
+        if let Some(remote) = self.state.get_remote_static() {
+            let remote: [u8; 32] = remote
+                .try_into()
+                .map_err(|_| MeshError::HandshakeFailed("Invalid remote static key".to_string()))?;
+            self.remote_static = Some(remote);
+        }
+        Ok(())
+    }
+
+    /// Peer's static key, once received
+    pub fn remote_static(&self) -> Option<[u8; 32]> {
+        self.remote_static
+    }
+
+    /// Whether both sides have proven their static keys
+    pub fn is_finished(&self) -> bool {
+        self.state.is_handshake_finished()
+    }
+
+    /// Switch a finished handshake to transport mode
+    pub fn into_session(self) -> Result<NoiseSession, MeshError> {
+        let remote_static = self
+            .remote_static
+            .ok_or_else(|| MeshError::HandshakeFailed("Peer static key not received".to_string()))?;
+        if remote_static == self.local_keypair.public_key() {
+            return Err(MeshError::HandshakeFailed("Peer presented our own static key".to_string()));
+        }
+        let transport = self.state.into_stateless_transport_mode().map_err(noise_error)?;
+        Ok(NoiseSession {
+            remote_static,
+            transport,
+            next_nonce: AtomicU64::new(0),
+            received: Mutex::new(NonceWindow::default()),
+        })
+    }
+}
+
+/// Established session with an authenticated peer
+pub struct NoiseSession {
+    remote_static: [u8; 32],
+    transport: StatelessTransportState,
+    /// Nonce for the next outgoing frame
+    next_nonce: AtomicU64,
+    /// Nonces of frames already received
+    received: Mutex<NonceWindow>,
+}
+
+impl NoiseSession {
+    /// Peer's static key
+    pub fn remote_static(&self) -> [u8; 32] {
+        self.remote_static
+    }
+
+    /// Encrypt a mesh frame for the peer
+    pub fn encrypt(&self, frame: &[u8]) -> Result<NoiseFrame, MeshError> {
+        if frame.len() > MAX_SEALED_FRAME_LEN {
+            return Err(MeshError::InvalidPacket(format!(
+                "Frame of {} bytes exceeds the session limit of {}",
+                frame.len(),
+                MAX_SEALED_FRAME_LEN
+            )));
+        }
+        let nonce = self.next_nonce.fetch_add(1, Ordering::Relaxed);
+        let mut ciphertext = vec![0u8; frame.len() + NOISE_TAG_LEN];
+        let len = self
+            .transport
+            .write_message(nonce, frame, &mut ciphertext)
+            .map_err(noise_error)?;
+        ciphertext.truncate(len);
+        Ok(NoiseFrame::Transport { nonce, ciphertext })
+    }
+
+    /// Decrypt a mesh frame from the peer
+    ///
+    /// The nonce comes from the wire, so each one is accepted once: frames
+    /// may arrive reordered within `NONCE_WINDOW_SIZE`, but a replayed or
+    /// older frame is refused. Only authenticated frames move the window.
+    pub fn decrypt(&self, nonce: u64, ciphertext: &[u8]) -> Result<Vec<u8>, MeshError> {
+        self.received.lock().unwrap().check(nonce)?;
+        let mut frame = vec![0u8; ciphertext.len()];
+        let len = self
+            .transport
+            .read_message(nonce, ciphertext, &mut frame)
+            .map_err(|_| MeshError::HandshakeFailed("Frame failed session authentication".to_string()))?;
+        // Checked again: a concurrent copy may have been accepted meanwhile
+        self.received.lock().unwrap().accept(nonce)?;
+        frame.truncate(len);
+        Ok(frame)
+    }
+}
+
+/// Sliding window of received transport nonces (as in IPsec anti-replay)
+///
+/// Bit `nonce % NONCE_WINDOW_SIZE` marks whether that nonce (within the
+/// window ending below `next`) was already received.
+struct NonceWindow {
+    /// One past the highest nonce received (0 before the first frame)
+    next: u64,
+    seen: [u64; (NONCE_WINDOW_SIZE / 64) as usize],
+}
+
+impl Default for NonceWindow {
+    fn default() -> Self {
+        Self {
+            next: 0,
+            seen: [0; (NONCE_WINDOW_SIZE / 64) as usize],
+        }
+    }
+}
+
+impl NonceWindow {
+    /// Check whether a nonce may be accepted (without recording it)
+    fn check(&self, nonce: u64) -> Result<(), MeshError> {
+        if nonce >= self.next {
+            return Ok(());
+        }
+        if self.next - nonce > NONCE_WINDOW_SIZE {
+            return Err(MeshError::ReplayDetected(format!(
+                "Transport nonce {} is behind the receive window",
+                nonce
+            )));
+        }
+        if self.is_set(nonce) {
+            return Err(MeshError::ReplayDetected(format!("Transport nonce {} already received", nonce)));
+        }
+        Ok(())
+    }
+
+    /// Record a received nonce, sliding the window forward if needed
+    fn accept(&mut self, nonce: u64) -> Result<(), MeshError> {
+        self.check(nonce)?;
+        if nonce >= self.next {
+            if nonce - self.next >= NONCE_WINDOW_SIZE {
+                self.seen = [0; (NONCE_WINDOW_SIZE / 64) as usize];
+            } else {
+                for skipped in self.next..nonce {
+                    self.clear(skipped);
+                }
+            }
+            self.next = nonce.saturating_add(1);
+        }
+        self.set(nonce);
+        Ok(())
+    }
+
+    fn position(nonce: u64) -> (usize, u64) {
+        let bit = nonce % NONCE_WINDOW_SIZE;
+        ((bit / 64) as usize, 1 << (bit % 64))
+    }
+
+    fn is_set(&self, nonce: u64) -> bool {
+        let (word, mask) = Self::position(nonce);
+        self.seen[word] & mask != 0
+    }
+
+    fn set(&mut self, nonce: u64) {
+        let (word, mask) = Self::position(nonce);
+        self.seen[word] |= mask;
+    }
+
+    fn clear(&mut self, nonce: u64) {
+        let (word, mask) = Self::position(nonce);
+        self.seen[word] &= !mask;
+    }
+}
+
+/// Result of handling a noise frame from a peer
+pub enum LinkEvent {
+    /// Handshake progressed: send `reply` if any; `established` carries the
+    /// peer's static key once the session is up
+    Handshake {
+        reply: Option<Vec<u8>>,
+        established: Option<[u8; 32]>,
+    },
+    /// Decrypted mesh frame
+    Frame(Vec<u8>),
+}
+
+/// State of the link to one peer address
+enum PeerLink {
+    /// Handshake running (our tiebreak if we initiated, started at)
+    Handshaking {
+        handshake: MeshHandshake,
+        tiebreak: Option<u64>,
+        started_at: u64,
+    },
+    /// Session established
+    Established(Arc<NoiseSession>),
+}
+
+/// Handshakes and sessions of all direct peers, by peer address
+pub struct PeerSessions {
+    local_keypair: NoiseKeypair,
+    /// Lock-free concurrent access using DashMap
+    links: DashMap<String, PeerLink>,
+    /// Handshakes older than this are abandoned
+    handshake_timeout_secs: u64,
+}
+
+impl PeerSessions {
+    /// Create an empty session table
+    pub fn new(local_keypair: NoiseKeypair, handshake_timeout_secs: u64) -> Self {
+        Self {
+            local_keypair,
+            links: DashMap::new(),
+            handshake_timeout_secs,
+        }
+    }
+
+    /// This node's static key, announced in its Hello
+    pub fn local_static_key(&self) -> [u8; 32] {
+        self.local_keypair.public_key()
+    }
+
+    /// Start a handshake with a newly connected peer
+    ///
+    /// Replaces any earlier handshake or session with the address. Returns
+    /// the encoded frame to send.
+    pub fn initiate(&self, peer_addr: &str, now: u64) -> Result<Vec<u8>, MeshError> {
+        let mut handshake = MeshHandshake::initiator(&self.local_keypair)?;
+        let message = handshake.write_message()?;
+        let tiebreak = OsRng.next_u64();
+        self.links.insert(
+            peer_addr.to_string(),
+            PeerLink::Handshaking {
+                handshake,
+                tiebreak: Some(tiebreak),
+                started_at: now,
+            },
+        );
+        NoiseFrame::Initiate { tiebreak, message }.encode()
+    }
+
+    /// Handle a noise frame received from a peer address
+    pub fn handle_frame(&self, peer_addr: &str, frame: NoiseFrame, now: u64) -> Result<LinkEvent, MeshError> {
+        match frame {
+            NoiseFrame::Initiate { tiebreak, message } => self.handle_initiate(peer_addr, tiebreak, &message, now),
+            NoiseFrame::Handshake(message) => self.handle_handshake(peer_addr, &message),
+            NoiseFrame::Transport { nonce, ciphertext } => {
+                let session = self.session(peer_addr).ok_or_else(|| {
+                    MeshError::HandshakeFailed(format!("No session with {}", peer_addr))
+                })?;
+                session.decrypt(nonce, &ciphertext).map(LinkEvent::Frame)
+            }
+        }
+    }
+
+    fn handle_initiate(
+        &self,
+        peer_addr: &str,
+        tiebreak: u64,
+        message: &[u8],
+        now: u64,
+    ) -> Result<LinkEvent, MeshError> {
+        // Both sides initiated: the higher tiebreak keeps the initiator role
+        if let Some(link) = self.links.get(peer_addr) {
+            if let PeerLink::Handshaking { tiebreak: Some(ours), .. } = link.value() {
+                if *ours > tiebreak {
+                    debug!("Ignoring colliding handshake from {}", peer_addr);
+                    return Ok(LinkEvent::Handshake {
+                        reply: None,
+                        established: None,
+                    });
+                }
+            }
+        }
+
+        // A fresh initiation also replaces an old session (peer restarted)
+        let mut handshake = MeshHandshake::responder(&self.local_keypair)?;
+        handshake.read_message(message)?;
+        let reply = NoiseFrame::Handshake(handshake.write_message()?).encode()?;
+        self.links.insert(
+            peer_addr.to_string(),
+            PeerLink::Handshaking {
+                handshake,
+                tiebreak: None,
+                started_at: now,
+            },
+        );
+        Ok(LinkEvent::Handshake {
+            reply: Some(reply),
+            established: None,
+        })
+    }
+
+    fn handle_handshake(&self, peer_addr: &str, message: &[u8]) -> Result<LinkEvent, MeshError> {
+        let Some((
+            addr,
+            PeerLink::Handshaking {
+                mut handshake,
+                tiebreak,
+                started_at,
+            },
+        )) = self
+            .links
+            .remove_if(peer_addr, |_, link| matches!(link, PeerLink::Handshaking { .. }))
+        else {
+            return Err(MeshError::HandshakeFailed(format!(
+                "Unexpected handshake message from {}",
+                peer_addr
+            )));
+        };
+
+        // A malformed (or injected) message does not abort the handshake:
+        // snow leaves the state as it was, so the genuine message can follow
+        if let Err(e) = handshake.read_message(message) {
+            self.links.entry(addr).or_insert(PeerLink::Handshaking {
+                handshake,
+                tiebreak,
+                started_at,
+            });
+            return Err(e);
+        }
+        // The initiator answers the responder's message with the final one
+        let reply = if handshake.is_finished() {
+            None
+        } else {
+            Some(NoiseFrame::Handshake(handshake.write_message()?).encode()?)
+        };
+        let session = handshake.into_session()?;
+        let remote_static = session.remote_static();
+        self.links.insert(addr, PeerLink::Established(Arc::new(session)));
+        debug!(
+            "Noise session established: peer={}, static_key={:x?}",
+            peer_addr,
+            &remote_static[..8]
+        );
+
+        Ok(LinkEvent::Handshake {
+            reply,
+            established: Some(remote_static),
+        })
+    }
+
+    /// Established session with a peer address
+    pub fn session(&self, peer_addr: &str) -> Option<Arc<NoiseSession>> {
+        match self.links.get(peer_addr)?.value() {
+            PeerLink::Established(session) => Some(Arc::clone(session)),
+            PeerLink::Handshaking { .. } => None,
+        }
+    }
+
+    /// Encrypt a mesh frame for a peer with an established session
+    pub fn seal(&self, peer_addr: &str, frame: &[u8]) -> Result<Vec<u8>, MeshError> {
+        self.session(peer_addr)
+            .ok_or_else(|| MeshError::HandshakeFailed(format!("No session with {}", peer_addr)))?
+            .encrypt(frame)?
+            .encode()
+    }
+
+    /// Forget a peer address and any handshake or session with it
+    pub fn remove(&self, peer_addr: &str) {
+        self.links.remove(peer_addr);
+    }
+
+    /// Abandon handshakes that did not complete in time
+    pub fn expire_handshakes(&self, now: u64) {
+        self.links.retain(|_, link| match link {
+            PeerLink::Handshaking { started_at, .. } => {
+                now.saturating_sub(*started_at) < self.handshake_timeout_secs
+            }
+            PeerLink::Established(_) => true,
+        });
+    }
+}
+
+fn noise_error(e: snow::Error) -> MeshError {
+    MeshError::HandshakeFailed(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keypair(seed: u8) -> NoiseKeypair {
+        NoiseKeypair::from_secret_bytes([seed; 32])
+    }
+
+    /// Deliver a frame and return the reply
+    fn deliver(sessions: &PeerSessions, from: &str, data: &[u8]) -> LinkEvent {
+        let frame = NoiseFrame::extract(data).unwrap().unwrap();
+        sessions.handle_frame(from, frame, 0).unwrap()
+    }
+
+    fn reply(event: LinkEvent) -> (Option<Vec<u8>>, Option<[u8; 32]>) {
+        match event {
+            LinkEvent::Handshake { reply, established } => (reply, established),
+            LinkEvent::Frame(_) => panic!("expected a handshake event"),
+        }
+    }
+
+    #[test]
+    fn test_handshake_authenticates_static_keys() {
+        let (alice, bob) = (PeerSessions::new(keypair(1), 30), PeerSessions::new(keypair(2), 30));
+
+        let initiate = alice.initiate("bob", 0).unwrap();
+        let (msg2, established) = reply(deliver(&bob, "alice", &initiate));
+        assert!(established.is_none());
+        let (msg3, alice_sees) = reply(deliver(&alice, "bob", &msg2.unwrap()));
+        let (none, bob_sees) = reply(deliver(&bob, "alice", &msg3.unwrap()));
+
+        assert!(none.is_none());
+        assert_eq!(alice_sees, Some(keypair(2).public_key()));
+        assert_eq!(bob_sees, Some(keypair(1).public_key()));
+
+        // Transport frames round-trip, tampering is detected
+        let sealed = alice.seal("bob", b"mesh frame").unwrap();
+        match deliver(&bob, "alice", &sealed) {
+            LinkEvent::Frame(frame) => assert_eq!(frame, b"mesh frame"),
+            LinkEvent::Handshake { .. } => panic!("expected a frame"),
+        }
+        let mut tampered = alice.seal("bob", b"mesh frame").unwrap();
+        *tampered.last_mut().unwrap() ^= 1;
+        let frame = NoiseFrame::extract(&tampered).unwrap().unwrap();
+        assert!(matches!(
+            bob.handle_frame("alice", frame, 0),
+            Err(MeshError::HandshakeFailed(_))
+        ));
+    }
+
+    #[test]
+    fn test_replayed_transport_frames_rejected() {
+        let (alice, bob) = (PeerSessions::new(keypair(1), 30), PeerSessions::new(keypair(2), 30));
+        let initiate = alice.initiate("bob", 0).unwrap();
+        let (msg2, _) = reply(deliver(&bob, "alice", &initiate));
+        let (msg3, _) = reply(deliver(&alice, "bob", &msg2.unwrap()));
+        deliver(&bob, "alice", &msg3.unwrap());
+
+        let first = alice.seal("bob", b"first").unwrap();
+        let second = alice.seal("bob", b"second").unwrap();
+        // Reordered frames are accepted once each
+        for sealed in [&second, &first] {
+            assert!(matches!(deliver(&bob, "alice", sealed), LinkEvent::Frame(_)));
+        }
+        for sealed in [&first, &second] {
+            let frame = NoiseFrame::extract(sealed).unwrap().unwrap();
+            assert!(matches!(
+                bob.handle_frame("alice", frame, 0),
+                Err(MeshError::ReplayDetected(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_nonce_window_slides() {
+        let mut window = NonceWindow::default();
+        window.accept(0).unwrap();
+        window.accept(NONCE_WINDOW_SIZE + 5).unwrap();
+        // Now behind the window
+        assert!(window.check(0).is_err());
+        assert!(window.check(4).is_err());
+        // Skipped nonces inside the window are still accepted, once
+        window.accept(NONCE_WINDOW_SIZE).unwrap();
+        assert!(window.accept(NONCE_WINDOW_SIZE).is_err());
+        assert!(window.accept(NONCE_WINDOW_SIZE + 5).is_err());
+    }
+
+    #[test]
+    fn test_malformed_handshake_message_keeps_handshake() {
+        let (alice, bob) = (PeerSessions::new(keypair(1), 30), PeerSessions::new(keypair(2), 30));
+        let initiate = alice.initiate("bob", 0).unwrap();
+        let (msg2, _) = reply(deliver(&bob, "alice", &initiate));
+
+        // An injected frame fails without discarding Alice's handshake
+        let junk = NoiseFrame::Handshake(vec![0u8; 96]);
+        assert!(alice.handle_frame("bob", junk, 0).is_err());
+
+        let (msg3, alice_sees) = reply(deliver(&alice, "bob", &msg2.unwrap()));
+        let (_, bob_sees) = reply(deliver(&bob, "alice", &msg3.unwrap()));
+        assert_eq!(alice_sees, Some(keypair(2).public_key()));
+        assert_eq!(bob_sees, Some(keypair(1).public_key()));
+    }
+
+    #[test]
+    fn test_simultaneous_initiation_resolves() {
+        let (alice, bob) = (PeerSessions::new(keypair(1), 30), PeerSessions::new(keypair(2), 30));
+        let from_alice = alice.initiate("bob", 0).unwrap();
+        let from_bob = bob.initiate("alice", 0).unwrap();
+
+        // Exactly one side yields and answers
+        let (alice_reply, _) = reply(deliver(&alice, "bob", &from_bob));
+        let (bob_reply, _) = reply(deliver(&bob, "alice", &from_alice));
+        assert!(alice_reply.is_some() != bob_reply.is_some());
+
+        let (responder, initiator, msg2) = match (alice_reply, bob_reply) {
+            (Some(msg2), None) => ((&alice, "bob"), (&bob, "alice"), msg2),
+            (None, Some(msg2)) => ((&bob, "alice"), (&alice, "bob"), msg2),
+            _ => unreachable!(),
+        };
+        let (msg3, _) = reply(deliver(initiator.0, initiator.1, &msg2));
+        let (_, established) = reply(deliver(responder.0, responder.1, &msg3.unwrap()));
+        assert!(established.is_some());
+        assert!(alice.session("bob").is_some() && bob.session("alice").is_some());
+    }
+
+    #[test]
+    fn test_stale_handshakes_expire() {
+        let alice = PeerSessions::new(keypair(1), 30);
+        alice.initiate("bob", 100).unwrap();
+        alice.expire_handshakes(129);
+        assert!(alice.links.contains_key("bob"));
+        alice.expire_handshakes(130);
+        assert!(!alice.links.contains_key("bob"));
+    }
+}
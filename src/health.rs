@@ -0,0 +1,144 @@
+//! Module health reported to the node
+//!
+//! Every `HEALTH_REPORT_INTERVAL_SECONDS` the manager measures one IPC round
+//! trip to the node, takes a `HealthSnapshot` of its own state and reports
+//! the `HealthChecker` verdict with `NodeAPI::report_module_health`. The mesh
+//! is `Healthy` while it is enabled, has a direct peer, its replay cache is at
+//! most 90% full and the last IPC round trip took under 100 ms; otherwise it
+//! is `Degraded` with the reasons.
+
+use bllvm_node::module::process::monitor::ModuleHealth;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Interval between health reports to the node
+pub const HEALTH_REPORT_INTERVAL_SECONDS: u64 = 30;
+
+/// Replay cache fill level above which the mesh is degraded
+pub const MAX_REPLAY_UTILIZATION: f64 = 0.9;
+
+/// IPC round-trip latency from which the mesh is degraded
+pub const MAX_IPC_LATENCY: Duration = Duration::from_millis(100);
+
+/// State the health verdict is based on
+#[derive(Debug, Clone, PartialEq)]
+pub struct HealthSnapshot {
+    /// Whether the mesh is enabled
+    pub enabled: bool,
+    /// Direct peers in the routing table
+    pub direct_peers: usize,
+    /// Fill level of the replay cache (0.0 to 1.0)
+    pub replay_utilization: f64,
+}
+
+/// Judges mesh health from a snapshot and the last IPC round trip
+pub struct HealthChecker {
+    max_replay_utilization: f64,
+    max_ipc_latency: Duration,
+    /// Latency of the last measured IPC round trip (None = not measured yet)
+    last_ipc_latency: Mutex<Option<Duration>>,
+}
+
+impl Default for HealthChecker {
+    fn default() -> Self {
+        Self::new(MAX_REPLAY_UTILIZATION, MAX_IPC_LATENCY)
+    }
+}
+
+impl HealthChecker {
+    /// Create a checker with the given thresholds
+    pub fn new(max_replay_utilization: f64, max_ipc_latency: Duration) -> Self {
+        Self {
+            max_replay_utilization,
+            max_ipc_latency,
+            last_ipc_latency: Mutex::new(None),
+        }
+    }
+
+    /// Record the latency of an IPC round trip to the node
+    pub fn record_ipc_latency(&self, latency: Duration) {
+        *self.last_ipc_latency.lock().unwrap() = Some(latency);
+    }
+
+    /// Latency of the last recorded IPC round trip
+    pub fn last_ipc_latency(&self) -> Option<Duration> {
+        *self.last_ipc_latency.lock().unwrap()
+    }
+
+    /// Health verdict for a snapshot
+    ///
+    /// `Degraded` lists every exceeded threshold, separated by "; ". An IPC
+    /// latency that was never measured does not count against the mesh.
+    pub fn check(&self, snapshot: &HealthSnapshot) -> ModuleHealth {
+        let mut problems = Vec::new();
+        if !snapshot.enabled {
+            problems.push("mesh disabled".to_string());
+        }
+        if snapshot.direct_peers == 0 {
+            problems.push("no direct peers".to_string());
+        }
+        if snapshot.replay_utilization > self.max_replay_utilization {
+            problems.push(format!("replay cache {:.0}% full", snapshot.replay_utilization * 100.0));
+        }
+        if let Some(latency) = self.last_ipc_latency().filter(|latency| *latency >= self.max_ipc_latency) {
+            problems.push(format!("IPC round trip took {} ms", latency.as_millis()));
+        }
+
+        if problems.is_empty() {
+            ModuleHealth::Healthy
+        } else {
+            ModuleHealth::Degraded(problems.join("; "))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn healthy() -> HealthSnapshot {
+        HealthSnapshot {
+            enabled: true,
+            direct_peers: 2,
+            replay_utilization: 0.5,
+        }
+    }
+
+    #[test]
+    fn test_healthy_within_thresholds() {
+        let checker = HealthChecker::default();
+        assert!(matches!(checker.check(&healthy()), ModuleHealth::Healthy));
+
+        checker.record_ipc_latency(Duration::from_millis(99));
+        assert!(matches!(checker.check(&healthy()), ModuleHealth::Healthy));
+    }
+
+    #[test]
+    fn test_degraded_lists_every_problem() {
+        let checker = HealthChecker::default();
+        checker.record_ipc_latency(Duration::from_millis(250));
+        let snapshot = HealthSnapshot {
+            enabled: false,
+            direct_peers: 0,
+            replay_utilization: 0.95,
+        };
+
+        let ModuleHealth::Degraded(reason) = checker.check(&snapshot) else {
+            panic!("expected degraded health");
+        };
+        assert_eq!(
+            reason,
+            "mesh disabled; no direct peers; replay cache 95% full; IPC round trip took 250 ms"
+        );
+    }
+
+    #[test]
+    fn test_single_threshold_degrades() {
+        let checker = HealthChecker::default();
+        let snapshot = HealthSnapshot {
+            replay_utilization: 0.91,
+            ..healthy()
+        };
+        assert!(matches!(checker.check(&snapshot), ModuleHealth::Degraded(_)));
+    }
+}
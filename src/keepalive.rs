@@ -0,0 +1,256 @@
+//! Keepalive and dead-peer detection for direct mesh peers
+//!
+//! Direct peers can vanish (NAT timeout, crash) without a PeerDisconnected
+//! event. Links that have been idle in either direction for
+//! `mesh.keepalive_idle_secs` are probed with a `Keepalive` control message;
+//! a probe not answered within `mesh.keepalive_timeout_secs` counts as a
+//! miss, and after `mesh.keepalive_max_misses` consecutive misses the peer is
+//! declared dead and the manager tears it down like a disconnect. Peers
+//! exchanging traffic in both directions are never probed.
+//!
+//! The monitor only keeps liveness state; the manager sends the probes and
+//! answers through its normal send path.
+
+use crate::clock::Clock;
+use crate::control::ControlMessage;
+use crate::routing::{NodeId, RoutingTable};
+use dashmap::DashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tracing::{debug, info};
+
+/// Keepalive configuration
+#[derive(Debug, Clone)]
+pub struct KeepaliveConfig {
+    /// Idle time before a link is probed (0 = keepalives disabled)
+    pub idle_secs: u64,
+    /// Time to wait for a probe response
+    pub timeout_secs: u64,
+    /// Consecutive missed probes before the peer is declared dead
+    pub max_misses: u32,
+}
+
+impl Default for KeepaliveConfig {
+    fn default() -> Self {
+        Self {
+            idle_secs: 60,
+            timeout_secs: 15,
+            max_misses: 3,
+        }
+    }
+}
+
+impl KeepaliveConfig {
+    /// Load from module context config keys
+    pub fn from_context(ctx: &bllvm_node::module::traits::ModuleContext) -> Self {
+        let defaults = Self::default();
+        Self {
+            idle_secs: ctx
+                .get_config_or("mesh.keepalive_idle_secs", &defaults.idle_secs.to_string())
+                .parse::<u64>()
+                .unwrap_or(defaults.idle_secs),
+            timeout_secs: ctx
+                .get_config_or("mesh.keepalive_timeout_secs", &defaults.timeout_secs.to_string())
+                .parse::<u64>()
+                .unwrap_or(defaults.timeout_secs)
+                .max(1),
+            max_misses: ctx
+                .get_config_or("mesh.keepalive_max_misses", &defaults.max_misses.to_string())
+                .parse::<u32>()
+                .unwrap_or(defaults.max_misses)
+                .max(1),
+        }
+    }
+
+    /// Whether keepalives are enabled
+    pub fn enabled(&self) -> bool {
+        self.idle_secs > 0
+    }
+
+    /// How often the monitor should tick
+    pub fn tick_secs(&self) -> u64 {
+        self.timeout_secs.min(self.idle_secs).max(1)
+    }
+}
+
+/// Liveness state of one direct peer
+#[derive(Debug, Clone)]
+struct PeerLiveness {
+    /// Last time we sent anything to the peer
+    last_sent: u64,
+    /// Last time we received anything from the peer
+    last_received: u64,
+    /// Outstanding probe (nonce, sent at)
+    outstanding: Option<(u64, u64)>,
+    /// Consecutive missed probes
+    misses: u32,
+}
+
+impl PeerLiveness {
+    fn new(now: u64) -> Self {
+        Self {
+            last_sent: now,
+            last_received: now,
+            outstanding: None,
+            misses: 0,
+        }
+    }
+}
+
+/// Outcome of one keepalive round
+#[derive(Debug, Default)]
+pub struct KeepaliveRound {
+    /// Probes to send (peer, `Keepalive` message)
+    pub probes: Vec<(NodeId, ControlMessage)>,
+    /// Peers that reached `max_misses` and must be removed
+    pub dead: Vec<NodeId>,
+}
+
+/// Tracks direct peer liveness and decides which peers to probe or drop
+pub struct KeepaliveMonitor {
+    config: KeepaliveConfig,
+    routing_table: Arc<RoutingTable>,
+    clock: Arc<dyn Clock>,
+    /// Per-peer liveness (lock-free with DashMap)
+    peers: DashMap<NodeId, PeerLiveness>,
+    /// Probe nonce counter
+    next_nonce: AtomicU64,
+}
+
+impl KeepaliveMonitor {
+    /// Create a new keepalive monitor
+    pub fn new(config: KeepaliveConfig, routing_table: Arc<RoutingTable>, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            config,
+            routing_table,
+            clock,
+            peers: DashMap::new(),
+            next_nonce: AtomicU64::new(1),
+        }
+    }
+
+    /// Keepalive configuration
+    pub fn config(&self) -> &KeepaliveConfig {
+        &self.config
+    }
+
+    /// Start tracking a newly connected peer (counts as fresh traffic)
+    pub fn track(&self, peer: &NodeId) {
+        self.peers.insert(*peer, PeerLiveness::new(self.clock.now_secs()));
+    }
+
+    /// Record traffic sent to a peer
+    pub fn record_sent(&self, peer: &NodeId) {
+        let now = self.clock.now_secs();
+        self.peers
+            .entry(*peer)
+            .or_insert_with(|| PeerLiveness::new(now))
+            .last_sent = now;
+    }
+
+    /// Record traffic received from a peer
+    ///
+    /// Any traffic proves the peer is alive, so outstanding misses are cleared.
+    pub fn record_received(&self, peer: &NodeId) {
+        let now = self.clock.now_secs();
+        let mut liveness = self
+            .peers
+            .entry(*peer)
+            .or_insert_with(|| PeerLiveness::new(now));
+        liveness.last_received = now;
+        liveness.outstanding = None;
+        liveness.misses = 0;
+    }
+
+    /// Forget a peer (disconnected)
+    pub fn forget(&self, peer: &NodeId) {
+        self.peers.remove(peer);
+    }
+
+    /// Handle a keepalive control message from a direct peer
+    ///
+    /// Returns the reply to send, if any.
+    pub fn handle_control(&self, from: NodeId, message: &ControlMessage) -> Option<ControlMessage> {
+        match message {
+            ControlMessage::Keepalive { nonce } => {
+                debug!("Answering keepalive: peer={:x?}, nonce={}", &from[..8], nonce);
+                Some(ControlMessage::KeepaliveAck { nonce: *nonce })
+            }
+            ControlMessage::KeepaliveAck { nonce } => {
+                debug!("Keepalive answered: peer={:x?}, nonce={}", &from[..8], nonce);
+                // record_received (called for every incoming packet) clears the probe
+                None
+            }
+            ControlMessage::Hello { .. }
+            | ControlMessage::Probe { .. }
+            | ControlMessage::ProbeReply { .. }
+            | ControlMessage::HtlcClaim { .. }
+            | ControlMessage::ConstraintUnsatisfiable { .. }
+            | ControlMessage::Subscribe { .. } => None,
+        }
+    }
+
+    /// Run one keepalive round
+    ///
+    /// Picks idle peers to probe and counts expired probes as misses. Peers
+    /// that reached `max_misses` are returned in `dead` for the caller to
+    /// remove (see `MeshManager::run_keepalive`).
+    pub fn tick(&self) -> KeepaliveRound {
+        let mut round = KeepaliveRound::default();
+        if !self.config.enabled() {
+            return round;
+        }
+
+        let now = self.clock.now_secs();
+        let direct_peers: Vec<NodeId> = self
+            .routing_table
+            .direct_peer_addresses()
+            .into_iter()
+            .map(|(node_id, _)| node_id)
+            .collect();
+
+        // Drop state for peers that are no longer direct
+        self.peers.retain(|peer, _| direct_peers.contains(peer));
+
+        for peer in direct_peers {
+            let mut liveness = self
+                .peers
+                .entry(peer)
+                .or_insert_with(|| PeerLiveness::new(now));
+
+            if let Some((_, sent_at)) = liveness.outstanding {
+                if now.saturating_sub(sent_at) < self.config.timeout_secs {
+                    continue;
+                }
+                liveness.outstanding = None;
+                liveness.misses += 1;
+                debug!(
+                    "Keepalive missed: peer={:x?}, misses={}",
+                    &peer[..8],
+                    liveness.misses
+                );
+                if liveness.misses >= self.config.max_misses {
+                    info!(
+                        "Peer declared dead after {} missed keepalives: node_id={:x?}",
+                        self.config.max_misses,
+                        &peer[..8]
+                    );
+                    round.dead.push(peer);
+                    continue;
+                }
+            }
+
+            // Suppressed while traffic flows both ways; retried at once after a miss
+            let idle = now.saturating_sub(liveness.last_sent) >= self.config.idle_secs
+                || now.saturating_sub(liveness.last_received) >= self.config.idle_secs;
+            if idle || liveness.misses > 0 {
+                let nonce = self.next_nonce.fetch_add(1, Ordering::SeqCst);
+                liveness.outstanding = Some((nonce, now));
+                liveness.last_sent = now;
+                round.probes.push((peer, ControlMessage::Keepalive { nonce }));
+            }
+        }
+
+        round
+    }
+}
@@ -0,0 +1,189 @@
+//! Round-trip latency of direct peers
+//!
+//! Every `LATENCY_PROBE_INTERVAL_SECONDS` the manager sends each direct peer
+//! a `ControlMessage::Probe`, which the peer answers at once with a
+//! `ControlMessage::ProbeReply` echoing the nonce and send time. The round
+//! trip is measured against this node's own record of the probe, so a peer
+//! cannot fake a faster link by editing `sent_at_us`. Samples are smoothed
+//! per peer with an EWMA and fed to `RoutingTable::update_route_latency`.
+
+use crate::control::ControlMessage;
+use crate::routing::NodeId;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::OsRng;
+use dashmap::DashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::debug;
+
+/// Interval between latency probe rounds
+pub const LATENCY_PROBE_INTERVAL_SECONDS: u64 = 60;
+
+/// Weight of a new round-trip sample in the per-peer average
+pub const LATENCY_EWMA_ALPHA: f64 = 0.1;
+
+/// Probes unanswered for this long are forgotten
+const PROBE_EXPIRY_MICROS: u64 = 2 * LATENCY_PROBE_INTERVAL_SECONDS * 1_000_000;
+
+/// Probe sent and not yet answered
+#[derive(Debug, Clone, Copy)]
+struct OutstandingProbe {
+    peer: NodeId,
+    sent_at_us: u64,
+}
+
+/// Measures and smooths round-trip times to direct peers
+pub struct LatencyProbe {
+    alpha: f64,
+    /// Probes awaiting their reply, by nonce
+    outstanding: DashMap<u64, OutstandingProbe>,
+    /// Smoothed round trip per peer in milliseconds
+    rtt_ms: DashMap<NodeId, f64>,
+    /// Random start so nonces are not reused across restarts
+    next_nonce: AtomicU64,
+}
+
+impl Default for LatencyProbe {
+    fn default() -> Self {
+        Self::new(LATENCY_EWMA_ALPHA)
+    }
+}
+
+impl LatencyProbe {
+    /// Create a probe with the given EWMA weight (0.0-1.0) for new samples
+    pub fn new(alpha: f64) -> Self {
+        Self {
+            alpha: alpha.clamp(0.0, 1.0),
+            outstanding: DashMap::new(),
+            rtt_ms: DashMap::new(),
+            next_nonce: AtomicU64::new(OsRng.next_u64()),
+        }
+    }
+
+    /// Build a probe for a peer sent at `now_us`, remembering it until answered
+    pub fn probe(&self, peer: NodeId, now_us: u64) -> ControlMessage {
+        self.outstanding
+            .retain(|_, probe| now_us.saturating_sub(probe.sent_at_us) < PROBE_EXPIRY_MICROS);
+        let nonce = self.next_nonce.fetch_add(1, Ordering::Relaxed);
+        self.outstanding.insert(
+            nonce,
+            OutstandingProbe {
+                peer,
+                sent_at_us: now_us,
+            },
+        );
+        ControlMessage::Probe {
+            nonce,
+            sent_at_us: now_us,
+        }
+    }
+
+    /// Answer a peer's probe, or None if the message is not a probe
+    pub fn reply(message: &ControlMessage, now_us: u64) -> Option<ControlMessage> {
+        match message {
+            ControlMessage::Probe { nonce, sent_at_us } => Some(ControlMessage::ProbeReply {
+                nonce: *nonce,
+                sent_at_us: *sent_at_us,
+                echo_at_us: now_us,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Record the reply to one of our probes received at `now_us`
+    ///
+    /// Returns the peer's smoothed round trip in milliseconds, or None if the
+    /// nonce is unknown or belongs to a probe sent to another peer.
+    pub fn record_reply(&self, from: &NodeId, nonce: u64, now_us: u64) -> Option<f64> {
+        let (_, probe) = self
+            .outstanding
+            .remove_if(&nonce, |_, probe| probe.peer == *from)?;
+        let sample = now_us.saturating_sub(probe.sent_at_us) as f64 / 1000.0;
+        let mut rtt = self.rtt_ms.entry(*from).or_insert(sample);
+        *rtt += self.alpha * (sample - *rtt);
+        debug!(
+            "Probe answered: peer={:x?}, sample_ms={:.1}, rtt_ms={:.1}",
+            &from[..8],
+            sample,
+            *rtt
+        );
+        Some(*rtt)
+    }
+
+    /// Smoothed round trip to a peer in milliseconds
+    pub fn rtt_ms(&self, peer: &NodeId) -> Option<f64> {
+        self.rtt_ms.get(peer).map(|rtt| *rtt)
+    }
+
+    /// Forget a peer (disconnected)
+    pub fn forget(&self, peer: &NodeId) {
+        self.rtt_ms.remove(peer);
+        self.outstanding.retain(|_, probe| probe.peer != *peer);
+    }
+}
+
+/// Current Unix time in microseconds
+pub fn now_micros() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_micros() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PEER: NodeId = [2u8; 32];
+
+    fn nonce(message: &ControlMessage) -> u64 {
+        match message {
+            ControlMessage::Probe { nonce, .. } => *nonce,
+            other => panic!("expected a probe, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_rtt_smoothed_with_ewma() {
+        let latency = LatencyProbe::default();
+
+        // The first sample is taken as is
+        let first = nonce(&latency.probe(PEER, 1_000_000));
+        assert_eq!(latency.record_reply(&PEER, first, 1_050_000), Some(50.0));
+
+        // Later samples move the average by alpha
+        let second = nonce(&latency.probe(PEER, 2_000_000));
+        let rtt = latency.record_reply(&PEER, second, 2_150_000).unwrap();
+        assert!((rtt - 60.0).abs() < 1e-9);
+        assert_eq!(latency.rtt_ms(&PEER), Some(rtt));
+    }
+
+    #[test]
+    fn test_unsolicited_replies_ignored() {
+        let latency = LatencyProbe::default();
+        let nonce = nonce(&latency.probe(PEER, 1_000_000));
+
+        // Another peer cannot answer for PEER, and a nonce counts once
+        assert_eq!(latency.record_reply(&[3u8; 32], nonce, 1_010_000), None);
+        assert!(latency.record_reply(&PEER, nonce, 1_010_000).is_some());
+        assert_eq!(latency.record_reply(&PEER, nonce, 1_020_000), None);
+        assert_eq!(latency.rtt_ms(&[3u8; 32]), None);
+    }
+
+    #[test]
+    fn test_reply_echoes_probe() {
+        let probe = ControlMessage::Probe {
+            nonce: 7,
+            sent_at_us: 100,
+        };
+        assert_eq!(
+            LatencyProbe::reply(&probe, 250),
+            Some(ControlMessage::ProbeReply {
+                nonce: 7,
+                sent_at_us: 100,
+                echo_at_us: 250,
+            })
+        );
+        assert_eq!(LatencyProbe::reply(&ControlMessage::KeepaliveAck { nonce: 7 }, 250), None);
+    }
+}
@@ -1,15 +1,51 @@
 //! Commons Mesh networking module for bllvm-node
 
+pub mod access_control;
+pub mod api;
+pub mod balance;
+pub mod bandwidth;
+pub mod capture;
+pub mod circuit_breaker;
 pub mod client;
+pub mod clock;
+pub mod compression;
+pub mod config;
+pub mod control;
+pub mod dedup;
+pub mod delivery_ledger;
+pub mod delivery_tracker;
 pub mod discovery;
+pub mod earnings;
 pub mod error;
+pub mod gossip;
+pub mod handshake;
+pub mod health;
+pub mod keepalive;
+pub mod latency;
 pub mod manager;
+pub mod metadata_schema;
+pub mod metrics;
+pub mod multipath;
 pub mod network;
+pub mod node_gossip;
+pub mod nodeapi_cached;
 pub mod nodeapi_ipc;
+pub mod onion;
 pub mod packet;
 pub mod payment_proof;
+pub mod priority_queue;
+pub mod proof_backend;
+pub mod rate_limiter;
 pub mod replay;
 pub mod routing;
 pub mod routing_policy;
+pub mod reassembly;
+pub mod rpc;
+pub mod signing;
+pub mod storage_schema;
+pub mod subscriptions;
+pub mod taproot;
+#[cfg(feature = "testkit")]
+pub mod testkit;
 pub mod verifier;
 
@@ -4,24 +4,58 @@
 //! including payment-gated routing, traffic classification, and fee distribution.
 
 use anyhow::Result;
-use bllvm_node::module::ipc::protocol::{EventMessage, EventPayload, EventType, LogLevel, ModuleMessage};
+use bllvm_node::module::ipc::protocol::{EventMessage, EventPayload, EventType, LogLevel};
 use clap::Parser;
 use std::path::PathBuf;
 use std::sync::Arc;
 use tracing::{error, info, warn};
 
+mod access_control;
+mod api;
+mod balance;
+mod bandwidth;
+mod capture;
+mod circuit_breaker;
+mod clock;
+mod compression;
+mod config;
+mod control;
+mod dedup;
+mod delivery_ledger;
+mod delivery_tracker;
+mod handshake;
+mod health;
+mod keepalive;
+mod latency;
 mod manager;
+mod metadata_schema;
+mod metrics;
+mod multipath;
 mod routing_policy;
 mod routing;
+mod rpc;
+mod signing;
+mod storage_schema;
+mod subscriptions;
+mod taproot;
 mod verifier;
 mod payment_proof;
+mod proof_backend;
+mod priority_queue;
 mod replay;
 mod packet;
 mod discovery;
+mod earnings;
 mod network;
+mod gossip;
+mod node_gossip;
 mod error;
 mod client;
+mod nodeapi_cached;
 mod nodeapi_ipc;
+mod onion;
+mod rate_limiter;
+mod reassembly;
 
 use error::MeshError;
 use manager::MeshManager;
@@ -97,6 +131,8 @@ async fn main() -> Result<()> {
         // Mempool events
         EventType::MempoolTransactionAdded,
         EventType::FeeRateChanged,
+        // Runtime config changes (mesh.mode)
+        EventType::ConfigChanged,
         // Add more events as needed for mesh routing
     ];
 
@@ -105,11 +141,12 @@ async fn main() -> Result<()> {
         return Err(anyhow::anyhow!("Subscription failed: {}", e));
     }
 
-    // Create NodeAPI IPC wrapper
-    let node_api = Arc::new(nodeapi_ipc::NodeApiIpc::new(
+    // Create NodeAPI IPC wrapper, caching repeated chain and mempool queries
+    let ipc_api = Arc::new(nodeapi_ipc::NodeApiIpc::new(
         Arc::clone(&client.ipc_client()),
         module_id.clone(),
     ));
+    let node_api = Arc::new(nodeapi_cached::NodeApiIpcCached::new(ipc_api.clone()));
 
     // Create mesh manager
     let ctx = bllvm_node::module::traits::ModuleContext {
@@ -119,9 +156,11 @@ async fn main() -> Result<()> {
         socket_path: socket_path.to_string_lossy().to_string(),
     };
 
-    let manager = MeshManager::new(&ctx, Arc::clone(&node_api))
-        .await
-        .map_err(|e| anyhow::anyhow!("Failed to create mesh manager: {}", e))?;
+    let manager = Arc::new(
+        MeshManager::with_prefix_storage(&ctx, Arc::clone(&node_api), ipc_api)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to create mesh manager: {}", e))?,
+    );
 
     // Start mesh manager
     if let Err(e) = manager.start().await {
@@ -129,71 +168,45 @@ async fn main() -> Result<()> {
         return Err(anyhow::anyhow!("Mesh manager startup failed: {}", e));
     }
 
-    info!("Mesh module initialized and running");
-
-    // Event processing loop with parallel batch processing
-    let mut event_receiver = client.event_receiver();
-    loop {
-        // Collect batch of events (up to 10) for parallel processing
-        let mut event_batch = Vec::with_capacity(10);
-        for _ in 0..10 {
-            match event_receiver.try_recv() {
-                Ok(event) => event_batch.push(event),
-                Err(tokio::sync::mpsc::error::TryRecvError::Empty) => break,
-                Err(tokio::sync::mpsc::error::TryRecvError::Disconnected) => {
-                    warn!("Event channel disconnected");
-                    return Ok(());
+    // Route outgoing packets by priority class
+    let queue_manager = Arc::clone(&manager);
+    tokio::spawn(async move { queue_manager.run_packet_queue().await });
+
+    // Advertise known routes to direct peers
+    let advertise_manager = Arc::clone(&manager);
+    tokio::spawn(async move { advertise_manager.run_route_advertisements().await });
+
+    // Probe idle direct peers and drop dead ones
+    let keepalive_manager = Arc::clone(&manager);
+    tokio::spawn(async move { keepalive_manager.run_keepalives().await });
+
+    // Measure direct peer round trips and fold them into route quality
+    let latency_manager = Arc::clone(&manager);
+    tokio::spawn(async move { latency_manager.run_latency_probes().await });
+
+    // Answer RPC invocations forwarded by the node
+    if let Some(mut requests) = client.take_request_receiver() {
+        let manager = Arc::clone(&manager);
+        let ipc_client = client.ipc_client();
+        tokio::spawn(async move {
+            while let Some(request) = requests.recv().await {
+                let response = rpc::answer_request(&manager, &request).await;
+                if let Err(e) = ipc_client.lock().await.send_response(response).await {
+                    warn!("Failed to send RPC response: {}", e);
                 }
             }
-        }
-        
-        // If no events in batch, wait for next event
-        if event_batch.is_empty() {
-            if let Some(event) = event_receiver.recv().await {
-                event_batch.push(event);
-            } else {
-                break; // Channel closed
-            }
-        }
-        
-        // Process events in parallel
-        let futures: Vec<_> = event_batch
-            .iter()
-            .map(|event| async {
-                match event {
-                    ModuleMessage::Event(event_msg) => {
-                        match event_msg.event_type {
-                            EventType::PeerConnected => {
-                                info!("Peer connected event received");
-                            }
-                            EventType::PeerDisconnected => {
-                                info!("Peer disconnected event received");
-                            }
-                            EventType::MessageReceived => {
-                                info!("Message received event");
-                            }
-                            EventType::PaymentRequestCreated => {
-                                info!("Payment request created event received");
-                            }
-                            EventType::NewBlock => {
-                                info!("New block event received");
-                            }
-                            _ => {
-                                // Ignore other events for now
-                            }
-                        }
-                    }
-                    _ => {
-                        // Not an event message
-                    }
-                }
-            })
-            .collect();
-        
-        // Wait for all events in batch to be processed
-        futures::future::join_all(futures).await;
+        });
     }
 
+    info!("Mesh module initialized and running");
+
+    // Dispatch node events to the manager until the node closes the channel.
+    // Events of one peer are handled in order, different peers in parallel.
+    manager
+        .run_event_loop(client.event_receiver(), node_api.as_ref())
+        .await;
+
     warn!("Event receiver closed, module shutting down");
+    manager.stop().await;
     Ok(())
 }
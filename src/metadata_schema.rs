@@ -0,0 +1,218 @@
+//! Schemas for protocol-specific packet metadata
+//!
+//! `PacketMetadata::fields` is a free-form string map whose meaning depends
+//! on `PacketMetadata::protocol`. A `SchemaRegistry` maps each protocol to a
+//! `MetadataSchema` listing its required and optional fields and per-field
+//! value checks. Metadata of a protocol without a schema (or without a
+//! protocol) is not checked beyond the fields every packet may carry.
+
+use crate::error::MeshError;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// Fields allowed on packets of any protocol (`correlation_id` and `rpc`
+/// tag mesh RPC calls, see `crate::rpc::MeshRpc`; `payment_session` names a
+/// prepaid balance, see `crate::balance`)
+pub const COMMON_FIELDS: [&str; 4] = ["priority", "correlation_id", "rpc", "payment_session"];
+
+/// Checks a field value
+pub type FieldValidator = Box<dyn Fn(&str) -> bool + Send + Sync>;
+
+/// Allowed metadata fields of one protocol
+#[derive(Default)]
+pub struct MetadataSchema {
+    /// Fields every packet of the protocol must carry
+    pub required_fields: Vec<String>,
+    /// Fields a packet may carry
+    pub optional_fields: Vec<String>,
+    /// Value checks by field name
+    pub validators: HashMap<String, FieldValidator>,
+}
+
+impl MetadataSchema {
+    /// Create an empty schema (only common fields allowed)
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a required field
+    pub fn required(mut self, name: &str) -> Self {
+        self.required_fields.push(name.to_string());
+        self
+    }
+
+    /// Add an optional field
+    pub fn optional(mut self, name: &str) -> Self {
+        self.optional_fields.push(name.to_string());
+        self
+    }
+
+    /// Check values of a field with `validator`
+    pub fn validator(mut self, name: &str, validator: impl Fn(&str) -> bool + Send + Sync + 'static) -> Self {
+        self.validators.insert(name.to_string(), Box::new(validator));
+        self
+    }
+
+    /// Check metadata fields against the schema
+    ///
+    /// Fails if a required field is missing, a field is neither listed nor
+    /// common, or a value is rejected by its validator.
+    pub fn validate(&self, protocol: &str, fields: &HashMap<String, String>) -> Result<(), MeshError> {
+        if let Some(missing) = self.required_fields.iter().find(|name| !fields.contains_key(*name)) {
+            return Err(MeshError::InvalidPacket(format!(
+                "Missing {} metadata field: {}",
+                protocol, missing
+            )));
+        }
+        for (name, value) in fields {
+            if !self.allows(name) {
+                return Err(MeshError::InvalidPacket(format!(
+                    "Unknown {} metadata field: {}",
+                    protocol, name
+                )));
+            }
+            if let Some(validator) = self.validators.get(name) {
+                if !validator(value) {
+                    return Err(MeshError::InvalidPacket(format!(
+                        "Invalid {} metadata field {}: {:?}",
+                        protocol, name, value
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn allows(&self, name: &str) -> bool {
+        COMMON_FIELDS.contains(&name)
+            || self.required_fields.iter().any(|field| field == name)
+            || self.optional_fields.iter().any(|field| field == name)
+    }
+}
+
+/// Metadata schemas by protocol identifier
+#[derive(Default)]
+pub struct SchemaRegistry {
+    schemas: HashMap<String, MetadataSchema>,
+}
+
+impl SchemaRegistry {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a registry with the built-in protocol schemas
+    pub fn with_builtin() -> Self {
+        let mut registry = Self::new();
+        registry.register(
+            "bitcoin-p2p",
+            MetadataSchema::new()
+                .optional("command")
+                .validator("command", |value| {
+                    !value.is_empty() && value.len() <= 12 && value.bytes().all(|b| b.is_ascii_graphic())
+                })
+                .optional("network")
+                .validator("network", |value| {
+                    matches!(value, "mainnet" | "testnet" | "signet" | "regtest")
+                }),
+        );
+        registry.register(
+            "stratum-v2",
+            MetadataSchema::new()
+                .required("job_id")
+                .validator("job_id", |value| value.parse::<u32>().is_ok())
+                .optional("channel_id")
+                .validator("channel_id", |value| value.parse::<u32>().is_ok()),
+        );
+        registry.register(
+            "commons-governance",
+            MetadataSchema::new()
+                .required("proposal_id")
+                .validator("proposal_id", is_hex_hash)
+                .optional("vote")
+                .validator("vote", |value| matches!(value, "yes" | "no" | "abstain")),
+        );
+        registry.register(
+            "mesh-packet",
+            MetadataSchema::new()
+                .optional("content_type")
+                .validator("content_type", |value| !value.is_empty() && value.len() <= 64),
+        );
+        registry
+    }
+
+    /// The shared registry of built-in schemas
+    pub fn builtin() -> &'static SchemaRegistry {
+        static BUILTIN: OnceLock<SchemaRegistry> = OnceLock::new();
+        BUILTIN.get_or_init(Self::with_builtin)
+    }
+
+    /// Register (or replace) the schema of a protocol
+    pub fn register(&mut self, protocol: &str, schema: MetadataSchema) {
+        self.schemas.insert(protocol.to_string(), schema);
+    }
+
+    /// Get the schema of a protocol
+    pub fn get(&self, protocol: &str) -> Option<&MetadataSchema> {
+        self.schemas.get(protocol)
+    }
+}
+
+/// 32-byte hash as 64 hex characters
+fn is_hex_hash(value: &str) -> bool {
+    value.len() == 64 && value.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::PacketMetadata;
+
+    fn metadata(protocol: Option<&str>, fields: &[(&str, &str)]) -> PacketMetadata {
+        PacketMetadata {
+            protocol: protocol.map(str::to_string),
+            fields: fields.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_builtin_schemas() {
+        let registry = SchemaRegistry::builtin();
+        let proposal = "ab".repeat(32);
+
+        assert!(metadata(Some("stratum-v2"), &[("job_id", "42")]).validate(registry).is_ok());
+        assert!(metadata(Some("stratum-v2"), &[("channel_id", "1")]).validate(registry).is_err());
+        assert!(metadata(Some("stratum-v2"), &[("job_id", "x")]).validate(registry).is_err());
+
+        assert!(metadata(Some("commons-governance"), &[("proposal_id", &proposal), ("vote", "yes")])
+            .validate(registry)
+            .is_ok());
+        assert!(metadata(Some("commons-governance"), &[("proposal_id", "1")]).validate(registry).is_err());
+
+        assert!(metadata(Some("bitcoin-p2p"), &[("command", "inv"), ("priority", "bulk")])
+            .validate(registry)
+            .is_ok());
+        assert!(metadata(Some("bitcoin-p2p"), &[("job_id", "42")]).validate(registry).is_err());
+        assert!(metadata(Some("mesh-packet"), &[]).validate(registry).is_ok());
+    }
+
+    #[test]
+    fn test_unregistered_protocols_not_checked() {
+        let registry = SchemaRegistry::builtin();
+        assert!(metadata(Some("custom"), &[("anything", "goes")]).validate(registry).is_ok());
+        assert!(metadata(None, &[("anything", "goes")]).validate(registry).is_ok());
+    }
+
+    #[test]
+    fn test_custom_schema() {
+        let mut registry = SchemaRegistry::new();
+        registry.register(
+            "custom",
+            MetadataSchema::new().required("id").validator("id", |value| value.len() == 2),
+        );
+        assert!(metadata(Some("custom"), &[("id", "ab")]).validate(&registry).is_ok());
+        assert!(metadata(Some("custom"), &[("id", "abc")]).validate(&registry).is_err());
+    }
+}
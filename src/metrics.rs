@@ -0,0 +1,806 @@
+//! Prometheus metrics for the mesh
+//!
+//! `MeshMetrics` holds the counters updated on the packet path. The
+//! `MetricsExporter` combines them with `MeshStats` snapshots and serves the
+//! Prometheus text exposition format over a minimal HTTP listener
+//! (`mesh.metrics.*`). The listener serves a bounded number of scrapers at
+//! once and gives each a deadline to send its request, so idle connections
+//! cannot pile up.
+
+use crate::balance::SessionBalances;
+use crate::capture::Disposition;
+use crate::clock::{Clock, SystemClock};
+use crate::delivery_tracker::PendingDeliveries;
+use crate::discovery::{RouteDiscovery, MAX_DISCOVERY_RINGS};
+use crate::manager::MeshStats;
+use crate::rate_limiter::RateLimiter;
+use crate::replay::ReplayPrevention;
+use crate::routing::RoutingTable;
+use crate::routing_policy::{MeshMode, RoutingPolicyEngine};
+use bllvm_node::module::traits::ModuleContext;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Semaphore;
+use tracing::{debug, info, warn};
+
+/// Default metrics port (`mesh.metrics.port`)
+pub const DEFAULT_METRICS_PORT: u16 = 9101;
+
+/// Upper bounds (seconds) of the route discovery latency histogram buckets
+pub const DISCOVERY_LATENCY_BUCKETS: [f64; 9] = [0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 10.0, 30.0];
+
+/// Largest request head read from a scraper
+const MAX_REQUEST_BYTES: usize = 4096;
+
+/// Default time a scraper has to send its request (`mesh.metrics.read_timeout_secs`)
+pub const DEFAULT_METRICS_READ_TIMEOUT_SECS: u64 = 5;
+
+/// Default number of scrapers served at once (`mesh.metrics.max_connections`)
+pub const DEFAULT_METRICS_MAX_CONNECTIONS: usize = 16;
+
+/// Metrics listener configuration (`mesh.metrics.*`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetricsConfig {
+    /// Whether the metrics listener is started
+    pub enabled: bool,
+    /// Address to bind
+    pub bind: String,
+    /// Port to listen on
+    pub port: u16,
+    /// Seconds a scraper has to send its request
+    pub read_timeout_secs: u64,
+    /// Scrapers served at once; further connections wait to be accepted
+    pub max_connections: usize,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind: "127.0.0.1".to_string(),
+            port: DEFAULT_METRICS_PORT,
+            read_timeout_secs: DEFAULT_METRICS_READ_TIMEOUT_SECS,
+            max_connections: DEFAULT_METRICS_MAX_CONNECTIONS,
+        }
+    }
+}
+
+impl MetricsConfig {
+    /// Read from module config
+    pub fn from_context(ctx: &ModuleContext) -> Self {
+        let defaults = Self::default();
+        Self {
+            enabled: ctx.get_config_or("mesh.metrics.enabled", "false") == "true",
+            bind: ctx.get_config_or("mesh.metrics.bind", &defaults.bind),
+            port: ctx
+                .get_config_or("mesh.metrics.port", &defaults.port.to_string())
+                .parse()
+                .unwrap_or(defaults.port),
+            read_timeout_secs: ctx
+                .get_config_or("mesh.metrics.read_timeout_secs", &defaults.read_timeout_secs.to_string())
+                .parse::<u64>()
+                .map(|secs| secs.max(1))
+                .unwrap_or(defaults.read_timeout_secs),
+            max_connections: ctx
+                .get_config_or("mesh.metrics.max_connections", &defaults.max_connections.to_string())
+                .parse::<usize>()
+                .map(|max| max.max(1))
+                .unwrap_or(defaults.max_connections),
+        }
+    }
+
+    /// Socket address to listen on
+    pub fn listen_addr(&self) -> String {
+        format!("{}:{}", self.bind, self.port)
+    }
+}
+
+/// Upper bounds of the payload compression ratio histogram buckets (wire / original)
+pub const COMPRESSION_RATIO_BUCKETS: [f64; 6] = [0.1, 0.25, 0.5, 0.75, 0.9, 1.0];
+
+/// Upper bounds of the node event batch size histogram buckets
+pub const EVENT_BATCH_SIZE_BUCKETS: [f64; 7] = [1.0, 2.0, 4.0, 8.0, 16.0, 32.0, 64.0];
+
+/// Cumulative histogram with fixed buckets
+#[derive(Debug)]
+struct Histogram<const N: usize> {
+    /// Observations at or below each bucket bound (non-cumulative)
+    buckets: [AtomicU64; N],
+    /// Sum of observations in millionths
+    sum_micros: AtomicU64,
+    count: AtomicU64,
+}
+
+impl<const N: usize> Default for Histogram<N> {
+    fn default() -> Self {
+        Self {
+            buckets: std::array::from_fn(|_| AtomicU64::new(0)),
+            sum_micros: AtomicU64::new(0),
+            count: AtomicU64::new(0),
+        }
+    }
+}
+
+impl<const N: usize> Histogram<N> {
+    fn observe(&self, bounds: &[f64; N], value: f64) {
+        if let Some(index) = bounds.iter().position(|bound| value <= *bound) {
+            self.buckets[index].fetch_add(1, Ordering::Relaxed);
+        }
+        self.sum_micros
+            .fetch_add((value * 1_000_000.0).round() as u64, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn render(&self, out: &mut String, name: &str, help: &str, bounds: &[f64; N]) {
+        metric(out, name, "histogram", help);
+        let mut cumulative = 0;
+        for (bound, bucket) in bounds.iter().zip(&self.buckets) {
+            cumulative += bucket.load(Ordering::Relaxed);
+            sample(out, &format!("{}_bucket", name), &format!("le=\"{}\"", bound), cumulative);
+        }
+        let count = self.count.load(Ordering::Relaxed);
+        sample(out, &format!("{}_bucket", name), "le=\"+Inf\"", count);
+        let sum = self.sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0;
+        let _ = writeln!(out, "{}_sum {}", name, sum);
+        sample(out, &format!("{}_count", name), "", count);
+    }
+}
+
+/// Counters updated on the packet path
+/// Packet path counters, as included in `MeshStats`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PacketCounters {
+    /// Packets sent onward (originated or forwarded)
+    pub routed: u64,
+    /// Incoming packets relayed to a next hop
+    pub forwarded: u64,
+    /// Incoming packets handled by this node
+    pub delivered: u64,
+    /// Packets discarded (outgoing or incoming)
+    pub dropped: u64,
+    /// Payment proofs verified
+    pub verifications_ok: u64,
+    /// Payment proofs that failed verification
+    pub verifications_failed: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct MeshMetrics {
+    packets_routed: AtomicU64,
+    packets_forwarded: AtomicU64,
+    packets_delivered: AtomicU64,
+    packets_dropped: AtomicU64,
+    verifications_ok: AtomicU64,
+    verifications_failed: AtomicU64,
+    replay_rejected: AtomicU64,
+    /// Incoming copies of packets already handled
+    duplicates_dropped: AtomicU64,
+    /// Verified payments below the fee for their packet
+    underpaid: AtomicU64,
+    /// Sats paid above the fee, summed over accepted payments
+    overpaid_sats: AtomicU64,
+    ttl_expired: AtomicU64,
+    malformed_packets: AtomicU64,
+    forward_failures: AtomicU64,
+    circuit_open: AtomicU64,
+    circuit_trips: AtomicU64,
+    orphaned_responses: AtomicU64,
+    constraint_rejections: AtomicU64,
+    discovery_latency: Histogram<{ DISCOVERY_LATENCY_BUCKETS.len() }>,
+    /// Wire size over original size of compressed payloads
+    compression_ratio: Histogram<{ COMPRESSION_RATIO_BUCKETS.len() }>,
+    /// Successful discoveries by rings needed (index 0 is one ring)
+    discovery_rings: [AtomicU64; MAX_DISCOVERY_RINGS],
+    /// Node events per batch taken by the event loop
+    event_batch_size: Histogram<{ EVENT_BATCH_SIZE_BUCKETS.len() }>,
+    /// Time from taking the last batch's first event to finishing the batch
+    event_processing_lag_ms: AtomicU64,
+}
+
+impl MeshMetrics {
+    /// Create zeroed metrics
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A packet was sent onward (originated or forwarded)
+    pub fn record_routed(&self) {
+        self.packets_routed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// An outgoing packet was discarded
+    pub fn record_dropped(&self) {
+        self.packets_dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// An incoming packet was handled
+    pub fn record_disposition(&self, disposition: &Disposition) {
+        let counter = match disposition {
+            Disposition::Forwarded => &self.packets_forwarded,
+            Disposition::Delivered => &self.packets_delivered,
+            Disposition::Dropped { .. } => &self.packets_dropped,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// A payment proof was verified
+    pub fn record_verification(&self, verified: bool) {
+        if verified {
+            self.verifications_ok.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.verifications_failed.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// A payment proof was rejected as a replay
+    pub fn record_replay_rejected(&self) {
+        self.replay_rejected.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// An incoming packet was dropped as a copy of one already handled
+    pub fn record_duplicate_dropped(&self) {
+        self.duplicates_dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// A verified payment did not cover its packet's fee
+    pub fn record_underpaid(&self) {
+        self.underpaid.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// A payment was accepted with `sats` more than its packet's fee
+    pub fn record_overpaid(&self, sats: u64) {
+        self.overpaid_sats.fetch_add(sats, Ordering::Relaxed);
+    }
+
+    /// A packet was dropped because its hop budget ran out
+    pub fn record_ttl_expired(&self) {
+        self.ttl_expired.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// A peer sent data with the mesh magic that failed to decode
+    pub fn record_malformed(&self) {
+        self.malformed_packets.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// A packet could not be forwarded after all retries
+    pub fn record_forward_failed(&self) {
+        self.forward_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// A send skipped a next hop whose circuit breaker is open
+    pub fn record_circuit_open(&self) {
+        self.circuit_open.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// A next hop's circuit breaker tripped open
+    pub fn record_circuit_trip(&self) {
+        self.circuit_trips.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// A relay reported that it had no route satisfying this node's constraints
+    pub fn record_constraint_rejection(&self) {
+        self.constraint_rejections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// A route response was dropped for lack of a reverse route to its requester
+    pub fn record_orphaned_response(&self) {
+        self.orphaned_responses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// A route discovery attempt finished
+    pub fn observe_discovery_latency(&self, latency: Duration) {
+        self.discovery_latency.observe(&DISCOVERY_LATENCY_BUCKETS, latency.as_secs_f64());
+    }
+
+    /// A payload was compressed to `ratio` of its original size
+    pub fn observe_compression_ratio(&self, ratio: f64) {
+        self.compression_ratio.observe(&COMPRESSION_RATIO_BUCKETS, ratio);
+    }
+
+    /// A route discovery succeeded after `rings` expanding-ring searches
+    pub fn record_discovery_rings(&self, rings: usize) {
+        let index = rings.clamp(1, MAX_DISCOVERY_RINGS) - 1;
+        self.discovery_rings[index].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// The event loop handled a batch of `size` node events, `lag` after
+    /// taking its first event
+    pub fn record_event_batch(&self, size: usize, lag: Duration) {
+        self.event_batch_size.observe(&EVENT_BATCH_SIZE_BUCKETS, size as f64);
+        self.event_processing_lag_ms
+            .store(lag.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    /// Packets sent onward so far
+    pub fn packets_routed(&self) -> u64 {
+        self.packets_routed.load(Ordering::Relaxed)
+    }
+
+    /// Malformed mesh packets received so far
+    pub fn malformed_packets(&self) -> u64 {
+        self.malformed_packets.load(Ordering::Relaxed)
+    }
+
+    /// Packets given up on after all forwarding retries so far
+    pub fn forward_failures(&self) -> u64 {
+        self.forward_failures.load(Ordering::Relaxed)
+    }
+
+    /// Snapshot of the packet path counters
+    pub fn counters(&self) -> PacketCounters {
+        PacketCounters {
+            routed: self.packets_routed(),
+            forwarded: self.packets_forwarded.load(Ordering::Relaxed),
+            delivered: self.packets_delivered.load(Ordering::Relaxed),
+            dropped: self.packets_dropped.load(Ordering::Relaxed),
+            verifications_ok: self.verifications_ok.load(Ordering::Relaxed),
+            verifications_failed: self.verifications_failed.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Render counters and a stats snapshot as Prometheus text exposition
+    pub fn render(&self, stats: &MeshStats) -> String {
+        let mut out = String::new();
+
+        metric(&mut out, "mesh_enabled", "gauge", "Whether the mesh is enabled");
+        sample(&mut out, "mesh_enabled", "", u64::from(stats.enabled));
+        metric(&mut out, "mesh_mode", "gauge", "Operating mode (1 for the active mode)");
+        for (mode, label) in [
+            (MeshMode::BitcoinOnly, "bitcoin_only"),
+            (MeshMode::PaymentGated, "payment_gated"),
+            (MeshMode::Open, "open"),
+        ] {
+            sample(&mut out, "mesh_mode", &format!("mode=\"{}\"", label), u64::from(stats.mode == mode));
+        }
+
+        metric(&mut out, "mesh_packets_routed_total", "counter", "Packets sent onward (originated or forwarded)");
+        sample(&mut out, "mesh_packets_routed_total", "", self.packets_routed());
+
+        metric(&mut out, "mesh_packets_handled_total", "counter", "Packets by outcome (forwarded and delivered count incoming packets only)");
+        sample(&mut out, "mesh_packets_handled_total", "disposition=\"forwarded\"", self.packets_forwarded.load(Ordering::Relaxed));
+        sample(&mut out, "mesh_packets_handled_total", "disposition=\"delivered\"", self.packets_delivered.load(Ordering::Relaxed));
+        sample(&mut out, "mesh_packets_handled_total", "disposition=\"dropped\"", self.packets_dropped.load(Ordering::Relaxed));
+
+        metric(&mut out, "mesh_payment_verifications_total", "counter", "Payment proof verifications by result");
+        sample(&mut out, "mesh_payment_verifications_total", "result=\"ok\"", self.verifications_ok.load(Ordering::Relaxed));
+        sample(&mut out, "mesh_payment_verifications_total", "result=\"fail\"", self.verifications_failed.load(Ordering::Relaxed));
+
+        metric(&mut out, "mesh_replay_rejected_total", "counter", "Payment proofs rejected as replays");
+        sample(&mut out, "mesh_replay_rejected_total", "", self.replay_rejected.load(Ordering::Relaxed));
+        metric(&mut out, "mesh_duplicates_dropped_total", "counter", "Incoming packets dropped as copies of a packet already handled");
+        sample(&mut out, "mesh_duplicates_dropped_total", "", self.duplicates_dropped.load(Ordering::Relaxed));
+
+        metric(&mut out, "mesh_payments_underpaid_total", "counter", "Verified payments refused for not covering the packet's fee");
+        sample(&mut out, "mesh_payments_underpaid_total", "", self.underpaid.load(Ordering::Relaxed));
+        metric(&mut out, "mesh_payment_overpaid_sats_total", "counter", "Sats paid above the fee by accepted payments");
+        sample(&mut out, "mesh_payment_overpaid_sats_total", "", self.overpaid_sats.load(Ordering::Relaxed));
+
+        metric(&mut out, "mesh_packets_ttl_expired_total", "counter", "Packets dropped because their TTL ran out");
+        sample(&mut out, "mesh_packets_ttl_expired_total", "", self.ttl_expired.load(Ordering::Relaxed));
+
+        metric(&mut out, "mesh_packets_malformed_total", "counter", "Received frames with the mesh magic that failed to decode");
+        sample(&mut out, "mesh_packets_malformed_total", "", self.malformed_packets());
+
+        metric(&mut out, "mesh_forward_failures_total", "counter", "Packets dropped after exhausting forwarding retries");
+        sample(&mut out, "mesh_forward_failures_total", "", self.forward_failures());
+
+        metric(&mut out, "mesh_circuit_breaker_open_total", "counter", "Sends that skipped a next hop with an open circuit breaker");
+        sample(&mut out, "mesh_circuit_breaker_open_total", "", self.circuit_open.load(Ordering::Relaxed));
+        metric(&mut out, "mesh_circuit_breaker_trips_total", "counter", "Times a next hop's circuit breaker opened");
+        sample(&mut out, "mesh_circuit_breaker_trips_total", "", self.circuit_trips.load(Ordering::Relaxed));
+
+        metric(&mut out, "mesh_routes_active", "gauge", "Routes in the routing table");
+        sample(&mut out, "mesh_routes_active", "", stats.routing.total_routes as u64);
+        metric(&mut out, "mesh_direct_peers", "gauge", "Direct peers in the routing table");
+        sample(&mut out, "mesh_direct_peers", "", stats.routing.direct_peers as u64);
+        metric(&mut out, "mesh_replay_active_hashes", "gauge", "Payment proof hashes remembered for replay prevention");
+        sample(&mut out, "mesh_replay_active_hashes", "", stats.replay.active_hashes as u64);
+        metric(&mut out, "mesh_replay_tracked_peers", "gauge", "Peers with a tracked sequence window");
+        sample(&mut out, "mesh_replay_tracked_peers", "", stats.replay.tracked_peers as u64);
+        metric(&mut out, "mesh_replay_evictions_total", "counter", "Replay cache entries evicted to stay under the cap");
+        sample(&mut out, "mesh_replay_evictions_total", "", stats.replay.evictions);
+        metric(&mut out, "mesh_replay_sequence_rejections_total", "counter", "Sequence numbers rejected by the per-peer window");
+        sample(&mut out, "mesh_replay_sequence_rejections_total", "reason=\"duplicate\"", stats.replay.duplicate_sequences);
+        sample(&mut out, "mesh_replay_sequence_rejections_total", "reason=\"stale\"", stats.replay.stale_sequences);
+        metric(&mut out, "mesh_replay_out_of_order_total", "counter", "Sequence numbers accepted out of order within the window");
+        sample(&mut out, "mesh_replay_out_of_order_total", "", stats.replay.out_of_order);
+        metric(&mut out, "mesh_rate_limited_total", "counter", "Packets rejected by per-source rate limiting");
+        sample(&mut out, "mesh_rate_limited_total", "", stats.rate_limit.rate_limited);
+        metric(&mut out, "mesh_paid_deliveries_pending", "gauge", "Paid packets sent and awaiting their delivery ack");
+        sample(&mut out, "mesh_paid_deliveries_pending", "", stats.deliveries.pending as u64);
+        metric(&mut out, "mesh_paid_deliveries_total", "counter", "Paid packets sent by delivery outcome");
+        sample(&mut out, "mesh_paid_deliveries_total", "status=\"confirmed\"", stats.deliveries.confirmed);
+        sample(&mut out, "mesh_paid_deliveries_total", "status=\"timed_out\"", stats.deliveries.timed_out);
+
+        self.discovery_latency.render(
+            &mut out,
+            "mesh_route_discovery_latency_seconds",
+            "Time spent discovering routes",
+            &DISCOVERY_LATENCY_BUCKETS,
+        );
+        self.compression_ratio.render(
+            &mut out,
+            "mesh_compression_ratio",
+            "Compressed over original size of compressed paid payloads",
+            &COMPRESSION_RATIO_BUCKETS,
+        );
+
+        metric(&mut out, "mesh_route_responses_orphaned_total", "counter", "Route responses dropped because the reverse route to the requester expired");
+        sample(&mut out, "mesh_route_responses_orphaned_total", "", self.orphaned_responses.load(Ordering::Relaxed));
+        metric(&mut out, "mesh_route_constraint_rejections_total", "counter", "Packets of this node dropped by a relay with no route satisfying their constraints");
+        sample(&mut out, "mesh_route_constraint_rejections_total", "", self.constraint_rejections.load(Ordering::Relaxed));
+
+        metric(&mut out, "mesh_route_discovery_pending", "gauge", "Route requests of this node awaiting a response");
+        sample(&mut out, "mesh_route_discovery_pending", "", stats.discovery.pending_requests as u64);
+        metric(&mut out, "mesh_route_discovery_pending_rejected_total", "counter", "Route discoveries refused because too many requests were pending");
+        sample(&mut out, "mesh_route_discovery_pending_rejected_total", "", stats.discovery.pending_rejected);
+        metric(&mut out, "mesh_route_requests_rate_limited_total", "counter", "Route requests from neighbors dropped by the per-neighbor rate limit");
+        sample(&mut out, "mesh_route_requests_rate_limited_total", "", stats.discovery.requests_rate_limited);
+
+        metric(&mut out, "mesh_route_discovery_rings_total", "counter", "Successful route discoveries by expanding-ring searches needed");
+        for (index, rings) in self.discovery_rings.iter().enumerate() {
+            sample(&mut out, "mesh_route_discovery_rings_total", &format!("rings=\"{}\"", index + 1), rings.load(Ordering::Relaxed));
+        }
+
+        metric(&mut out, "mesh_session_balances", "gauge", "Open prepaid session balances");
+        sample(&mut out, "mesh_session_balances", "", stats.balances.sessions as u64);
+        metric(&mut out, "mesh_session_balance_sats", "gauge", "Sats left across open prepaid session balances");
+        sample(&mut out, "mesh_session_balance_sats", "", stats.balances.remaining_sats);
+
+        self.event_batch_size.render(
+            &mut out,
+            "mesh_event_batch_size",
+            "Node events handled per event loop batch",
+            &EVENT_BATCH_SIZE_BUCKETS,
+        );
+        metric(&mut out, "mesh_event_processing_lag_ms", "gauge", "Time from taking the last event batch's first event to finishing the batch");
+        sample(&mut out, "mesh_event_processing_lag_ms", "", self.event_processing_lag_ms.load(Ordering::Relaxed));
+
+        out
+    }
+}
+
+fn metric(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn sample(out: &mut String, name: &str, labels: &str, value: u64) {
+    if labels.is_empty() {
+        let _ = writeln!(out, "{} {}", name, value);
+    } else {
+        let _ = writeln!(out, "{}{{{}}} {}", name, labels, value);
+    }
+}
+
+/// Serves `/metrics` for Prometheus scrapers
+#[derive(Clone)]
+pub struct MetricsExporter {
+    metrics: Arc<MeshMetrics>,
+    routing_table: Arc<RoutingTable>,
+    replay_prevention: Arc<ReplayPrevention>,
+    rate_limiter: Arc<RateLimiter>,
+    enabled: Arc<AtomicBool>,
+    routing_policy: Arc<RoutingPolicyEngine>,
+    deliveries: Arc<PendingDeliveries>,
+    route_discovery: Option<Arc<RouteDiscovery>>,
+    balances: Option<Arc<SessionBalances>>,
+    clock: Arc<dyn Clock>,
+    read_timeout: Duration,
+    max_connections: usize,
+}
+
+impl MetricsExporter {
+    /// Create an exporter over the manager's shared state
+    pub fn new(
+        metrics: Arc<MeshMetrics>,
+        routing_table: Arc<RoutingTable>,
+        replay_prevention: Arc<ReplayPrevention>,
+        rate_limiter: Arc<RateLimiter>,
+        enabled: Arc<AtomicBool>,
+        routing_policy: Arc<RoutingPolicyEngine>,
+        deliveries: Arc<PendingDeliveries>,
+    ) -> Self {
+        Self {
+            metrics,
+            routing_table,
+            replay_prevention,
+            rate_limiter,
+            enabled,
+            routing_policy,
+            deliveries,
+            route_discovery: None,
+            balances: None,
+            clock: Arc::new(SystemClock),
+            read_timeout: Duration::from_secs(DEFAULT_METRICS_READ_TIMEOUT_SECS),
+            max_connections: DEFAULT_METRICS_MAX_CONNECTIONS,
+        }
+    }
+
+    /// Give scrapers `read_timeout` to send their request
+    pub fn with_read_timeout(mut self, read_timeout: Duration) -> Self {
+        self.read_timeout = read_timeout;
+        self
+    }
+
+    /// Serve at most `max_connections` scrapers at once
+    pub fn with_max_connections(mut self, max_connections: usize) -> Self {
+        self.max_connections = max_connections.max(1);
+        self
+    }
+
+    /// Include route discovery statistics
+    pub fn with_route_discovery(mut self, route_discovery: Arc<RouteDiscovery>) -> Self {
+        self.route_discovery = Some(route_discovery);
+        self
+    }
+
+    /// Include prepaid session balance statistics
+    pub fn with_session_balances(mut self, balances: Arc<SessionBalances>) -> Self {
+        self.balances = Some(balances);
+        self
+    }
+
+    /// Timestamp snapshots with the given clock
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Current stats snapshot
+    pub async fn stats(&self) -> MeshStats {
+        MeshStats {
+            timestamp: self.clock.now_secs(),
+            enabled: self.enabled.load(Ordering::Relaxed),
+            mode: self.routing_policy.mode(),
+            routing: self.routing_table.stats(),
+            replay: self.replay_prevention.stats(),
+            rate_limit: self.rate_limiter.stats(),
+            packets: self.metrics.counters(),
+            deliveries: self.deliveries.stats(),
+            discovery: match &self.route_discovery {
+                Some(route_discovery) => route_discovery.stats().await,
+                None => Default::default(),
+            },
+            balances: self
+                .balances
+                .as_ref()
+                .map(|balances| balances.stats())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Render the current metrics
+    pub async fn render(&self) -> String {
+        self.metrics.render(&self.stats().await)
+    }
+
+    /// Accept scrapers until the listener fails
+    ///
+    /// A new connection is only accepted while fewer than `max_connections`
+    /// are being served.
+    pub async fn serve(self, listener: TcpListener) {
+        if let Ok(addr) = listener.local_addr() {
+            info!("Serving mesh metrics on http://{}/metrics", addr);
+        }
+        let connections = Arc::new(Semaphore::new(self.max_connections));
+        loop {
+            let Ok(permit) = Arc::clone(&connections).acquire_owned().await else {
+                return;
+            };
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    let exporter = self.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = exporter.handle_connection(stream).await {
+                            debug!("Metrics connection failed: {}", e);
+                        }
+                        drop(permit);
+                    });
+                }
+                Err(e) => {
+                    warn!("Metrics listener failed: {}", e);
+                    return;
+                }
+            }
+        }
+    }
+
+    async fn handle_connection(&self, mut stream: TcpStream) -> std::io::Result<()> {
+        let request = tokio::time::timeout(self.read_timeout, read_request_head(&mut stream))
+            .await
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::TimedOut, "Metrics request timed out"))??;
+
+        let request_line = String::from_utf8_lossy(&request);
+        let mut parts = request_line.split_whitespace();
+        let response = match (parts.next(), parts.next()) {
+            (Some("GET"), Some("/metrics")) | (Some("GET"), Some("/")) => {
+                let body = self.render().await;
+                format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                )
+            }
+            _ => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+        };
+
+        stream.write_all(response.as_bytes()).await?;
+        stream.shutdown().await
+    }
+}
+
+/// Read up to the end of the request head (or `MAX_REQUEST_BYTES`)
+async fn read_request_head(stream: &mut TcpStream) -> std::io::Result<Vec<u8>> {
+    let mut request = Vec::new();
+    let mut buf = [0u8; 512];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+        let read = stream.read(&mut buf).await?;
+        if read == 0 || request.len() + read > MAX_REQUEST_BYTES {
+            break;
+        }
+        request.extend_from_slice(&buf[..read]);
+    }
+    Ok(request)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rate_limiter::RateLimitConfig;
+    use crate::replay::ReplayWindowConfig;
+
+    fn exporter(metrics: Arc<MeshMetrics>) -> MetricsExporter {
+        MetricsExporter::new(
+            metrics,
+            Arc::new(RoutingTable::new(3600)),
+            Arc::new(ReplayPrevention::new(ReplayWindowConfig::default())),
+            Arc::new(RateLimiter::new(RateLimitConfig::default())),
+            Arc::new(AtomicBool::new(true)),
+            Arc::new(RoutingPolicyEngine::new(MeshMode::Open)),
+            Arc::new(PendingDeliveries::new(60)),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_render_counters() {
+        let metrics = Arc::new(MeshMetrics::new());
+        metrics.record_routed();
+        metrics.record_routed();
+        metrics.record_verification(true);
+        metrics.record_verification(false);
+        metrics.record_verification(false);
+        metrics.record_replay_rejected();
+        metrics.record_duplicate_dropped();
+        metrics.record_underpaid();
+        metrics.record_overpaid(5);
+        metrics.record_overpaid(3);
+        metrics.record_ttl_expired();
+        metrics.record_malformed();
+        metrics.record_forward_failed();
+        metrics.record_circuit_trip();
+        metrics.record_orphaned_response();
+        metrics.record_dropped();
+        metrics.record_disposition(&Disposition::Delivered);
+
+        let text = exporter(metrics).render().await;
+        assert!(text.contains("# TYPE mesh_packets_routed_total counter\nmesh_packets_routed_total 2\n"));
+        assert!(text.contains("mesh_payment_verifications_total{result=\"ok\"} 1\n"));
+        assert!(text.contains("mesh_payment_verifications_total{result=\"fail\"} 2\n"));
+        assert!(text.contains("mesh_replay_rejected_total 1\n"));
+        assert!(text.contains("mesh_duplicates_dropped_total 1\n"));
+        assert!(text.contains("mesh_payments_underpaid_total 1\n"));
+        assert!(text.contains("mesh_payment_overpaid_sats_total 8\n"));
+        assert!(text.contains("mesh_packets_ttl_expired_total 1\n"));
+        assert!(text.contains("mesh_packets_malformed_total 1\n"));
+        assert!(text.contains("mesh_forward_failures_total 1\n"));
+        assert!(text.contains("mesh_circuit_breaker_trips_total 1\n"));
+        assert!(text.contains("mesh_circuit_breaker_open_total 0\n"));
+        assert!(text.contains("mesh_route_responses_orphaned_total 1\n"));
+        assert!(text.contains("mesh_packets_handled_total{disposition=\"delivered\"} 1\n"));
+        assert!(text.contains("mesh_packets_handled_total{disposition=\"dropped\"} 1\n"));
+        assert!(text.contains("mesh_packets_handled_total{disposition=\"forwarded\"} 0\n"));
+        assert!(text.contains("mesh_routes_active 0\n"));
+        assert!(text.contains("mesh_mode{mode=\"open\"} 1\n"));
+    }
+
+    #[tokio::test]
+    async fn test_histogram_buckets_are_cumulative() {
+        let metrics = Arc::new(MeshMetrics::new());
+        metrics.observe_discovery_latency(Duration::from_millis(2));
+        metrics.observe_discovery_latency(Duration::from_millis(80));
+        metrics.observe_discovery_latency(Duration::from_secs(60));
+
+        let text = exporter(metrics).render().await;
+        assert!(text.contains("mesh_route_discovery_latency_seconds_bucket{le=\"0.005\"} 1\n"));
+        assert!(text.contains("mesh_route_discovery_latency_seconds_bucket{le=\"0.1\"} 2\n"));
+        assert!(text.contains("mesh_route_discovery_latency_seconds_bucket{le=\"30\"} 2\n"));
+        assert!(text.contains("mesh_route_discovery_latency_seconds_bucket{le=\"+Inf\"} 3\n"));
+        assert!(text.contains("mesh_route_discovery_latency_seconds_count 3\n"));
+        assert!(text.contains("mesh_route_discovery_latency_seconds_sum 60.082\n"));
+    }
+
+    #[tokio::test]
+    async fn test_render_compression_ratio() {
+        let metrics = Arc::new(MeshMetrics::new());
+        metrics.observe_compression_ratio(0.2);
+        metrics.observe_compression_ratio(0.6);
+
+        let text = exporter(metrics).render().await;
+        assert!(text.contains("# TYPE mesh_compression_ratio histogram\n"));
+        assert!(text.contains("mesh_compression_ratio_bucket{le=\"0.1\"} 0\n"));
+        assert!(text.contains("mesh_compression_ratio_bucket{le=\"0.25\"} 1\n"));
+        assert!(text.contains("mesh_compression_ratio_bucket{le=\"0.75\"} 2\n"));
+        assert!(text.contains("mesh_compression_ratio_count 2\n"));
+        assert!(text.contains("mesh_compression_ratio_sum 0.8\n"));
+    }
+
+    #[tokio::test]
+    async fn test_render_discovery_rings() {
+        let metrics = Arc::new(MeshMetrics::new());
+        metrics.record_discovery_rings(1);
+        metrics.record_discovery_rings(3);
+        metrics.record_discovery_rings(3);
+
+        let text = exporter(metrics).render().await;
+        assert!(text.contains("# TYPE mesh_route_discovery_rings_total counter\n"));
+        assert!(text.contains("mesh_route_discovery_rings_total{rings=\"1\"} 1\n"));
+        assert!(text.contains("mesh_route_discovery_rings_total{rings=\"2\"} 0\n"));
+        assert!(text.contains("mesh_route_discovery_rings_total{rings=\"3\"} 2\n"));
+    }
+
+    #[tokio::test]
+    async fn test_serves_metrics_over_http() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let metrics = Arc::new(MeshMetrics::new());
+        metrics.record_routed();
+        tokio::spawn(exporter(metrics).serve(listener));
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("mesh_packets_routed_total 1\n"));
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(b"GET /other HTTP/1.1\r\n\r\n").await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 404"));
+    }
+
+    #[tokio::test]
+    async fn test_idle_scraper_times_out_and_frees_its_slot() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let exporter = exporter(Arc::new(MeshMetrics::new()))
+            .with_read_timeout(Duration::from_millis(100))
+            .with_max_connections(1);
+        tokio::spawn(exporter.serve(listener));
+
+        // Holds the only slot without ever sending a request
+        let mut idle = TcpStream::connect(addr).await.unwrap();
+
+        // Queued behind the idle connection until it times out
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(b"GET /metrics HTTP/1.1\r\n\r\n").await.unwrap();
+        let mut response = String::new();
+        tokio::time::timeout(Duration::from_secs(5), stream.read_to_string(&mut response))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+
+        // The idle connection was closed without a response
+        let mut leftover = Vec::new();
+        idle.read_to_end(&mut leftover).await.unwrap();
+        assert!(leftover.is_empty());
+    }
+}
@@ -0,0 +1,201 @@
+//! Multipath forwarding over equal-cost routes
+//!
+//! When the routing table knows several cheapest routes to a destination
+//! with different first hops, packets are spread across them by flow. A
+//! flow is `(source, destination, sequence / FLOW_SEQUENCES)`: consecutive
+//! packets of a session hash to the same first hop and stay in order, while
+//! different sessions (and later stretches of a long one) land on different
+//! paths. A flow switch can reorder at most the packets in flight around
+//! the boundary, which the receiver's replay window tolerates.
+
+use crate::packet::MeshPacket;
+use crate::routing::NodeId;
+use sha2::{Digest, Sha256};
+
+/// Consecutive sequence numbers that form one flow
+pub const FLOW_SEQUENCES: u64 = 256;
+
+/// Multipath forwarding configuration
+#[derive(Debug, Clone)]
+pub struct MultipathConfig {
+    /// Spread flows across equal-cost routes
+    pub enabled: bool,
+    /// Maximum routes a destination's flows are spread over
+    pub max_paths: usize,
+}
+
+impl Default for MultipathConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_paths: 4,
+        }
+    }
+}
+
+impl MultipathConfig {
+    /// Load from module context config keys
+    pub fn from_context(ctx: &bllvm_node::module::traits::ModuleContext) -> Self {
+        let defaults = Self::default();
+        Self {
+            enabled: ctx.get_config_or("mesh.multipath.enabled", "false") == "true",
+            max_paths: ctx
+                .get_config_or("mesh.multipath.max_paths", &defaults.max_paths.to_string())
+                .parse::<usize>()
+                .unwrap_or(defaults.max_paths)
+                .max(1),
+        }
+    }
+}
+
+/// Selects the route a packet's flow is forwarded on
+pub struct MultipathForwarder {
+    config: MultipathConfig,
+}
+
+impl MultipathForwarder {
+    /// Create a new multipath forwarder
+    pub fn new(config: MultipathConfig) -> Self {
+        Self { config }
+    }
+
+    /// Multipath configuration
+    pub fn config(&self) -> &MultipathConfig {
+        &self.config
+    }
+
+    /// Whether flows are spread across routes
+    pub fn enabled(&self) -> bool {
+        self.config.enabled && self.config.max_paths > 1
+    }
+
+    /// Pick the route (and so the next hop) for a packet's flow
+    ///
+    /// `routes` are `RoutingTable::find_k_routes` results, cheapest first.
+    /// Only routes as cheap as the first one with distinct first hops are
+    /// candidates, up to `max_paths`. Returns `None` if no route has a next
+    /// hop.
+    pub fn select_next_hop<'a>(
+        &self,
+        packet: &MeshPacket,
+        routes: &'a [(Vec<NodeId>, u64)],
+    ) -> Option<&'a Vec<NodeId>> {
+        let candidates = self.equal_cost_routes(routes);
+        if candidates.is_empty() {
+            return None;
+        }
+        let index = if self.enabled() {
+            (flow_hash(packet) % candidates.len() as u64) as usize
+        } else {
+            0
+        };
+        candidates.get(index).copied()
+    }
+
+    /// The cheapest routes with distinct first hops, up to `max_paths`
+    ///
+    /// `routes` are `RoutingTable::find_k_routes` results, cheapest first;
+    /// routes without a next hop are skipped.
+    pub fn equal_cost_routes<'a>(&self, routes: &'a [(Vec<NodeId>, u64)]) -> Vec<&'a Vec<NodeId>> {
+        let mut candidates: Vec<&'a Vec<NodeId>> = Vec::new();
+        let Some(cheapest) = routes.iter().find(|(path, _)| path.len() > 1).map(|(_, cost)| *cost) else {
+            return candidates;
+        };
+        for (path, cost) in routes {
+            if candidates.len() >= self.config.max_paths {
+                break;
+            }
+            if path.len() < 2 || *cost != cheapest || candidates.iter().any(|chosen| chosen[1] == path[1]) {
+                continue;
+            }
+            candidates.push(path);
+        }
+        candidates
+    }
+}
+
+/// Hash of the packet's flow key
+fn flow_hash(packet: &MeshPacket) -> u64 {
+    let mut hasher = Sha256::new();
+    hasher.update(packet.source);
+    hasher.update(packet.destination);
+    hasher.update((packet.sequence / FLOW_SEQUENCES).to_be_bytes());
+    let digest = hasher.finalize();
+    u64::from_be_bytes(digest[..8].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::{MeshPacketBuilder, PacketType};
+
+    const ME: NodeId = [1u8; 32];
+    const DESTINATION: NodeId = [9u8; 32];
+
+    fn forwarder(max_paths: usize) -> MultipathForwarder {
+        MultipathForwarder::new(MultipathConfig {
+            enabled: true,
+            max_paths,
+        })
+    }
+
+    fn routes() -> Vec<(Vec<NodeId>, u64)> {
+        vec![
+            (vec![ME, [2u8; 32], DESTINATION], 200),
+            (vec![ME, [3u8; 32], DESTINATION], 200),
+            (vec![ME, [4u8; 32], DESTINATION], 200),
+            (vec![ME, [5u8; 32], [6u8; 32], DESTINATION], 300),
+        ]
+    }
+
+    fn packet(source: u8, sequence: u64) -> MeshPacket {
+        let mut packet = MeshPacketBuilder::new([source; 32], DESTINATION)
+            .packet_type(PacketType::BitcoinP2P)
+            .payload(vec![1])
+            .build()
+            .unwrap();
+        packet.sequence = sequence;
+        packet
+    }
+
+    #[test]
+    fn test_flow_stays_on_one_path() {
+        let forwarder = forwarder(4);
+        let routes = routes();
+        let first = forwarder.select_next_hop(&packet(7, 0), &routes).unwrap();
+        for sequence in 1..FLOW_SEQUENCES {
+            assert_eq!(forwarder.select_next_hop(&packet(7, sequence), &routes).unwrap(), first);
+        }
+    }
+
+    #[test]
+    fn test_flows_spread_over_equal_cost_paths_only() {
+        let forwarder = forwarder(4);
+        let routes = routes();
+        let mut hops = std::collections::HashSet::new();
+        for source in 0..64u8 {
+            hops.insert(forwarder.select_next_hop(&packet(source, 0), &routes).unwrap()[1]);
+        }
+        // The costlier route via [5; 32] is never used
+        assert_eq!(hops.len(), 3);
+        assert!(!hops.contains(&[5u8; 32]));
+
+        // max_paths limits the spread
+        let narrow = self::forwarder(2);
+        let mut hops = std::collections::HashSet::new();
+        for source in 0..64u8 {
+            hops.insert(narrow.select_next_hop(&packet(source, 0), &routes).unwrap()[1]);
+        }
+        assert_eq!(hops.len(), 2);
+    }
+
+    #[test]
+    fn test_disabled_uses_cheapest_route() {
+        let forwarder = MultipathForwarder::new(MultipathConfig::default());
+        let routes = routes();
+        for source in 0..16u8 {
+            assert_eq!(forwarder.select_next_hop(&packet(source, 0), &routes).unwrap()[1], [2u8; 32]);
+        }
+        assert!(forwarder.select_next_hop(&packet(0, 0), &[]).is_none());
+    }
+}
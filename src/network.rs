@@ -3,11 +3,29 @@
 //! This module provides integration points with the node's network layer
 //! for sending and receiving mesh packets.
 
+use crate::compression::{compress_payload, decompress_payload};
 use crate::error::MeshError;
-use crate::packet::{MeshPacket, MESH_PACKET_MAGIC};
-use bincode;
+use crate::packet::{checksum_body, MeshPacket, CHECKSUM_LEN, MAX_PACKET_SIZE, MESH_PACKET_MAGIC, MESH_PACKET_VERSION};
+use bincode::Options;
 use tracing::{debug, warn};
 
+/// Wire header: magic (4) + version (1) + ttl (1) + body length (4, big-endian)
+pub const MESH_HEADER_LEN: usize = MESH_PACKET_MAGIC.len() + 1 + 1 + 4;
+
+/// Offset of the TTL byte, right after the version
+const TTL_OFFSET: usize = MESH_PACKET_MAGIC.len() + 1;
+
+/// Bincode configuration for packet bodies
+///
+/// Same fixed-width encoding as `bincode::serialize`, but bounded and
+/// strict about trailing bytes so a body decodes in exactly one way.
+fn body_options() -> impl Options {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .with_limit(MAX_PACKET_SIZE as u64)
+        .reject_trailing_bytes()
+}
+
 /// Check if data is a mesh packet
 pub fn is_mesh_packet(data: &[u8]) -> bool {
     // Check for mesh packet magic bytes
@@ -15,6 +33,11 @@ pub fn is_mesh_packet(data: &[u8]) -> bool {
 }
 
 /// Deserialize mesh packet from bytes
+///
+/// Expects `magic | version | ttl | length | body` and rejects any header that
+/// does not describe exactly the bytes that follow it, or a body whose
+/// checksum does not match its contents. A compressed payload is restored,
+/// and the packet's checksum then covers the decompressed fields.
 pub fn deserialize_mesh_packet(data: &[u8]) -> Result<MeshPacket, MeshError> {
     // Check magic bytes first
     if !is_mesh_packet(data) {
@@ -23,30 +46,101 @@ pub fn deserialize_mesh_packet(data: &[u8]) -> Result<MeshPacket, MeshError> {
         ));
     }
     
-    // Deserialize packet (skip magic bytes if they're part of the data)
-    // In production, magic bytes might be stripped by network layer
-    let packet: MeshPacket = bincode::deserialize(data)
+    if data.len() < MESH_HEADER_LEN {
+        return Err(MeshError::InvalidPacket(format!(
+            "Truncated mesh packet header: {} < {} bytes",
+            data.len(),
+            MESH_HEADER_LEN
+        )));
+    }
+    
+    let version = data[MESH_PACKET_MAGIC.len()];
+    if version != MESH_PACKET_VERSION {
+        return Err(MeshError::InvalidPacket(format!(
+            "Unsupported mesh packet version: {}",
+            version
+        )));
+    }
+    
+    let length_offset = TTL_OFFSET + 1;
+    let mut length_bytes = [0u8; 4];
+    length_bytes.copy_from_slice(&data[length_offset..MESH_HEADER_LEN]);
+    let length = u32::from_be_bytes(length_bytes) as usize;
+    if length > MAX_PACKET_SIZE {
+        return Err(MeshError::InvalidPacket(format!(
+            "Mesh packet body too large: {} > {}",
+            length, MAX_PACKET_SIZE
+        )));
+    }
+    
+    let body = &data[MESH_HEADER_LEN..];
+    if body.len() != length {
+        warn!(
+            "Mesh packet length mismatch: header={}, actual={}",
+            length,
+            body.len()
+        );
+        return Err(MeshError::InvalidPacket(format!(
+            "Mesh packet length mismatch: header says {}, got {}",
+            length,
+            body.len()
+        )));
+    }
+    
+    let mut packet: MeshPacket = body_options()
+        .deserialize(body)
         .map_err(|e| MeshError::InvalidPacket(format!("Failed to deserialize packet: {}", e)))?;
+    if packet.checksum != checksum_body(body) {
+        warn!("Dropping corrupt mesh packet: checksum mismatch");
+        return Err(MeshError::InvalidPacket("Packet checksum mismatch".to_string()));
+    }
+    packet.ttl = data[TTL_OFFSET];
+    if packet.compressed {
+        packet.payload = decompress_payload(&packet.payload)?;
+        packet.checksum = packet.compute_checksum();
+    }
     
     Ok(packet)
 }
 
-/// Serialize mesh packet to bytes
+/// Serialize mesh packet to bytes (`magic | version | ttl | length | body`)
+///
+/// The TTL sits in the header so relays can read the hop budget without
+/// decoding the body. The body's checksum is computed here, so packets
+/// changed since they were decoded (relays extending the route) are
+/// sealed again. A `compressed` packet's payload is compressed here, and the
+/// checksum covers the compressed body.
 pub fn serialize_mesh_packet(packet: &MeshPacket) -> Result<Vec<u8>, MeshError> {
-    // Validate packet before serialization
-    packet.validate()
+    // Validate packet before serialization (receivers enforce their own `mesh.max_ttl`)
+    packet.validate_structure(u8::MAX)
         .map_err(|e| MeshError::InvalidPacket(e))?;
     
-    // Serialize packet
-    let mut data = bincode::serialize(packet)
+    // Serialize packet body, then fill in its trailing checksum
+    let encoded = if packet.compressed {
+        let mut wire = packet.clone();
+        wire.payload = compress_payload(&packet.payload, packet.compression_level)?;
+        body_options().serialize(&wire)
+    } else {
+        body_options().serialize(packet)
+    };
+    let mut body = encoded
         .map_err(|e| MeshError::InvalidPacket(format!("Failed to serialize packet: {}", e)))?;
+    let checksum_offset = body.len() - CHECKSUM_LEN;
+    let checksum = checksum_body(&body);
+    body[checksum_offset..].copy_from_slice(&checksum.to_le_bytes());
     
-    // Prepend magic bytes (if not already included)
-    // In production, network layer might handle magic bytes
-    let mut packet_with_magic = MESH_PACKET_MAGIC.to_vec();
-    packet_with_magic.extend_from_slice(&data);
+    let length = u32::try_from(body.len())
+        .map_err(|_| MeshError::InvalidPacket(format!("Packet body too large: {} bytes", body.len())))?;
     
-    Ok(packet_with_magic)
+    let mut data = Vec::with_capacity(MESH_HEADER_LEN + body.len());
+    data.extend_from_slice(&MESH_PACKET_MAGIC);
+    data.push(packet.version);
+    data.push(packet.ttl);
+    data.extend_from_slice(&length.to_be_bytes());
+    data.extend_from_slice(&body);
+    
+    debug!("Serialized mesh packet: {} bytes", data.len());
+    Ok(data)
 }
 
 /// Extract mesh packet from network message
@@ -64,8 +158,9 @@ pub fn extract_mesh_packet(data: &[u8]) -> Option<Result<MeshPacket, MeshError>>
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::packet::PacketType;
-    use crate::routing::NodeId;
+    use crate::compression::CompressionConfig;
+    use crate::packet::{MeshPacketBuilder, PacketType};
+    use crate::payment_proof::PaymentProof;
     
     #[test]
     fn test_is_mesh_packet() {
@@ -77,14 +172,24 @@ mod tests {
         assert!(!is_mesh_packet(&not_mesh));
     }
     
+    fn paid_packet() -> MeshPacket {
+        MeshPacketBuilder::new([1u8; 32], [2u8; 32])
+            .packet_type(PacketType::Paid)
+            .payload(vec![1, 2, 3, 4])
+            .payment_proof(PaymentProof::Lightning {
+                invoice: "lnbc1test".to_string(),
+                preimage: [7u8; 32],
+                amount_msats: 1_000,
+                timestamp: 1_700_000_000,
+                expires_at: 1_700_003_600,
+            })
+            .build()
+            .unwrap()
+    }
+    
     #[test]
     fn test_serialize_deserialize() {
-        let packet = MeshPacket::new(
-            PacketType::Paid,
-            [1u8; 32],
-            [2u8; 32],
-            vec![1, 2, 3, 4],
-        );
+        let packet = paid_packet();
         
         let serialized = serialize_mesh_packet(&packet).unwrap();
         assert!(is_mesh_packet(&serialized));
@@ -92,6 +197,152 @@ mod tests {
         let deserialized = deserialize_mesh_packet(&serialized).unwrap();
         assert_eq!(packet.source, deserialized.source);
         assert_eq!(packet.destination, deserialized.destination);
+        assert_eq!(packet.ttl, deserialized.ttl);
+    }
+    
+    #[test]
+    fn test_header_layout() {
+        let packet = MeshPacketBuilder::new([1u8; 32], [2u8; 32])
+            .packet_type(PacketType::BitcoinP2P)
+            .payload(vec![9; 10])
+            .build()
+            .unwrap();
+        let serialized = serialize_mesh_packet(&packet).unwrap();
+        
+        assert_eq!(serialized[0..4], MESH_PACKET_MAGIC);
+        assert_eq!(serialized[4], MESH_PACKET_VERSION);
+        assert_eq!(serialized[5], packet.ttl);
+        let length = u32::from_be_bytes(serialized[6..10].try_into().unwrap()) as usize;
+        assert_eq!(length, serialized.len() - MESH_HEADER_LEN);
+        let mut sealed = packet.clone();
+        sealed.checksum = packet.compute_checksum();
+        assert_eq!(serialized[MESH_HEADER_LEN..], bincode::serialize(&sealed).unwrap()[..]);
+    }
+    
+    #[test]
+    fn test_corrupt_body_rejected() {
+        let packet = MeshPacketBuilder::new([1u8; 32], [2u8; 32])
+            .packet_type(PacketType::BitcoinP2P)
+            .payload(vec![1, 2, 3])
+            .build()
+            .unwrap();
+        let valid = serialize_mesh_packet(&packet).unwrap();
+        let decoded = deserialize_mesh_packet(&valid).unwrap();
+        assert_eq!(decoded.checksum, packet.compute_checksum());
+        assert!(decoded.validate().is_ok());
+        
+        // A flipped payload byte still decodes cleanly, but not its checksum
+        let mut corrupt = valid.clone();
+        let payload_start = corrupt.windows(3).rposition(|bytes| bytes == [1, 2, 3]).unwrap();
+        corrupt[payload_start + 2] ^= 0x01;
+        assert!(matches!(
+            deserialize_mesh_packet(&corrupt),
+            Err(MeshError::InvalidPacket(reason)) if reason.contains("checksum mismatch")
+        ));
+        
+        // A packet changed after decoding no longer validates until re-serialized
+        let mut tampered = decoded;
+        tampered.sequence += 1;
+        assert!(tampered.validate().is_err());
+        assert!(deserialize_mesh_packet(&serialize_mesh_packet(&tampered).unwrap()).is_ok());
+    }
+    
+    #[test]
+    fn test_round_trip_is_byte_exact() {
+        let mut with_metadata = MeshPacketBuilder::new([3u8; 32], [4u8; 32])
+            .packet_type(PacketType::Control)
+            .payload(vec![0xFF; 300])
+            .build()
+            .unwrap();
+        with_metadata.sequence = u64::MAX;
+        with_metadata.metadata = Some(Default::default());
+        
+        for packet in [paid_packet(), with_metadata] {
+            let serialized = serialize_mesh_packet(&packet).unwrap();
+            let reserialized = serialize_mesh_packet(&deserialize_mesh_packet(&serialized).unwrap()).unwrap();
+            assert_eq!(serialized, reserialized);
+        }
+    }
+    
+    #[test]
+    fn test_compressed_round_trip() {
+        let payload = b"ipfs block ".repeat(400);
+        let mut plain = paid_packet();
+        plain.payload = payload.clone();
+        let mut compressed = plain.clone();
+        assert!(compressed.compress(&CompressionConfig::default()).unwrap() < payload.len());
+        
+        let plain_frame = serialize_mesh_packet(&plain).unwrap();
+        let compressed_frame = serialize_mesh_packet(&compressed).unwrap();
+        assert!(compressed_frame.len() < plain_frame.len() / 4);
+        
+        // Receivers see the original payload either way
+        for frame in [&plain_frame, &compressed_frame] {
+            let decoded = deserialize_mesh_packet(frame).unwrap();
+            assert_eq!(decoded.payload, payload);
+            assert!(decoded.validate().is_ok());
+        }
+        let decoded = deserialize_mesh_packet(&compressed_frame).unwrap();
+        assert!(decoded.compressed);
+        assert_eq!(serialize_mesh_packet(&decoded).unwrap(), compressed_frame);
+        
+        // A payload flagged compressed that does not decompress is rejected
+        let mut forged = plain_frame;
+        let flag = forged.len() - CHECKSUM_LEN - 1;
+        forged[flag] = 1;
+        let checksum = checksum_body(&forged[MESH_HEADER_LEN..]);
+        forged[flag + 1..].copy_from_slice(&checksum.to_le_bytes());
+        assert!(matches!(
+            deserialize_mesh_packet(&forged),
+            Err(MeshError::InvalidPacket(reason)) if reason.contains("decompress")
+        ));
+    }
+    
+    fn assert_invalid(data: &[u8]) {
+        assert!(matches!(
+            deserialize_mesh_packet(data),
+            Err(MeshError::InvalidPacket(_))
+        ));
+    }
+    
+    #[test]
+    fn test_malformed_headers_rejected() {
+        let packet = MeshPacketBuilder::new([1u8; 32], [2u8; 32])
+            .packet_type(PacketType::BitcoinP2P)
+            .payload(vec![1, 2, 3])
+            .build()
+            .unwrap();
+        let valid = serialize_mesh_packet(&packet).unwrap();
+        
+        // Wrong magic
+        let mut wrong_magic = valid.clone();
+        wrong_magic[0] ^= 0xFF;
+        assert_invalid(&wrong_magic);
+        
+        // Unknown version
+        let mut wrong_version = valid.clone();
+        wrong_version[4] = MESH_PACKET_VERSION + 1;
+        assert_invalid(&wrong_version);
+        
+        // Truncated header and truncated length field
+        assert_invalid(&valid[..4]);
+        assert_invalid(&valid[..8]);
+        
+        // Body shorter or longer than the header claims
+        assert_invalid(&valid[..valid.len() - 1]);
+        let mut trailing = valid.clone();
+        trailing.push(0);
+        assert_invalid(&trailing);
+        
+        // Oversized length
+        let mut oversized = valid.clone();
+        oversized[6..10].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert_invalid(&oversized);
+        
+        // Consistent header around a garbage body
+        let mut garbage = valid[..MESH_HEADER_LEN].to_vec();
+        garbage[6..10].copy_from_slice(&3u32.to_be_bytes());
+        garbage.extend_from_slice(&[0xFF; 3]);
+        assert_invalid(&garbage);
     }
 }
-
@@ -0,0 +1,157 @@
+//! Gossip bridge over the node's existing P2P connections
+//!
+//! Some deployments don't run a separate mesh transport. When
+//! `mesh.gossip_via_node = true`, mesh control traffic (route advertisements,
+//! discovery) is wrapped in a small gossip frame and sent to every peer the
+//! node already maintains, instead of only to mesh direct peers.
+
+use crate::error::MeshError;
+use bllvm_node::module::traits::NodeAPI;
+use dashmap::DashMap;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{debug, trace, warn};
+
+/// Gossip frame magic bytes
+pub const NODE_GOSSIP_MAGIC: [u8; 4] = [0x4D, 0x47, 0x53, 0x50]; // "MGSP"
+
+/// Default maximum gossip frame size (16 KB) so consensus bandwidth isn't harmed
+pub const DEFAULT_MAX_GOSSIP_FRAME_BYTES: usize = 16 * 1024;
+
+/// Default time to remember seen frames (10 minutes)
+pub const DEFAULT_GOSSIP_SEEN_TTL_SECONDS: u64 = 10 * 60;
+
+/// Bridges mesh control traffic onto the node's P2P broadcast
+pub struct NodeGossipBridge {
+    /// Node API used to enumerate and reach node peers
+    node_api: Arc<dyn NodeAPI>,
+    /// Maximum wrapped frame size in bytes
+    max_frame_bytes: usize,
+    /// How long seen/sent markers are kept
+    seen_ttl_seconds: u64,
+    /// Frames already seen (frame hash -> first seen timestamp)
+    seen: DashMap<[u8; 32], u64>,
+    /// Frames already sent per peer ((peer address, frame hash) -> sent timestamp)
+    sent_to: DashMap<(String, [u8; 32]), u64>,
+}
+
+impl NodeGossipBridge {
+    /// Create a new gossip bridge
+    pub fn new(node_api: Arc<dyn NodeAPI>, max_frame_bytes: usize, seen_ttl_seconds: u64) -> Self {
+        Self {
+            node_api,
+            max_frame_bytes,
+            seen_ttl_seconds,
+            seen: DashMap::new(),
+            sent_to: DashMap::new(),
+        }
+    }
+
+    /// Check if data is a gossip frame
+    pub fn is_gossip_frame(data: &[u8]) -> bool {
+        data.len() > 4 && data[0..4] == NODE_GOSSIP_MAGIC
+    }
+
+    /// Wrap control bytes in a gossip frame
+    pub fn wrap(control: &[u8]) -> Vec<u8> {
+        let mut frame = NODE_GOSSIP_MAGIC.to_vec();
+        frame.extend_from_slice(control);
+        frame
+    }
+
+    /// Broadcast control bytes to every node peer
+    ///
+    /// Each peer receives a given frame at most once. Returns the number of
+    /// peers the frame was sent to.
+    pub async fn broadcast(&self, control: &[u8]) -> Result<usize, MeshError> {
+        let frame = Self::wrap(control);
+        if frame.len() > self.max_frame_bytes {
+            return Err(MeshError::InvalidPacket(format!(
+                "Gossip frame exceeds maximum: {} > {}",
+                frame.len(),
+                self.max_frame_bytes
+            )));
+        }
+
+        let hash = Self::frame_hash(control);
+        let now = Self::now();
+
+        // Remember our own frame so the echoed copy is dropped
+        self.seen.entry(hash).or_insert(now);
+
+        let peers = self
+            .node_api
+            .get_network_peers()
+            .await
+            .map_err(|e| MeshError::NetworkError(format!("Failed to get network peers: {}", e)))?;
+
+        let mut sent = 0;
+        for peer in peers {
+            let key = (peer.addr.clone(), hash);
+            if self.sent_to.contains_key(&key) {
+                trace!("Gossip frame already sent to peer {}", peer.addr);
+                continue;
+            }
+
+            match self
+                .node_api
+                .send_mesh_packet_to_peer(peer.addr.clone(), frame.clone())
+                .await
+            {
+                Ok(()) => {
+                    self.sent_to.insert(key, now);
+                    sent += 1;
+                }
+                Err(e) => {
+                    warn!("Failed to gossip frame to peer {}: {}", peer.addr, e);
+                }
+            }
+        }
+
+        debug!("Gossiped control frame to {} node peers ({} bytes)", sent, frame.len());
+        Ok(sent)
+    }
+
+    /// Accept a received gossip frame
+    ///
+    /// Returns the unwrapped control bytes the first time a frame is seen, and
+    /// `None` for non-gossip data, oversized frames, and duplicates.
+    pub fn accept(&self, frame: &[u8]) -> Option<Vec<u8>> {
+        if !Self::is_gossip_frame(frame) || frame.len() > self.max_frame_bytes {
+            return None;
+        }
+
+        let control = &frame[4..];
+        let hash = Self::frame_hash(control);
+        if self.seen.contains_key(&hash) {
+            trace!("Dropping duplicate gossip frame: hash={:x?}", &hash[..8]);
+            return None;
+        }
+        self.seen.insert(hash, Self::now());
+
+        Some(control.to_vec())
+    }
+
+    /// Clean up expired seen/sent markers
+    pub fn cleanup_expired(&self) {
+        let now = Self::now();
+        let ttl = self.seen_ttl_seconds;
+        self.seen.retain(|_, seen_at| now <= *seen_at + ttl);
+        self.sent_to.retain(|_, sent_at| now <= *sent_at + ttl);
+    }
+
+    fn frame_hash(control: &[u8]) -> [u8; 32] {
+        let hash = Sha256::digest(control);
+        let mut result = [0u8; 32];
+        result.copy_from_slice(&hash);
+        result
+    }
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+}
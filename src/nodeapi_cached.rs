@@ -0,0 +1,256 @@
+//! Caching NodeAPI wrapper
+//!
+//! `NodeApiIpcCached` sits in front of `NodeApiIpc` (or any other NodeAPI)
+//! and answers repeated chain and mempool queries from an in-memory LRU
+//! cache, so hot paths do not pay an IPC round trip per packet. Each entry
+//! lives for the TTL of its query kind; errors are never cached. All other
+//! methods are passed through unchanged.
+
+use crate::clock::{Clock, SystemClock};
+use async_trait::async_trait;
+use bllvm_node::module::ipc::protocol::{EventPayload, ModuleMessage};
+use bllvm_node::module::traits::{
+    ChainInfo, EventType, LightningInfo, MempoolSize, ModuleError, NetworkStats, NodeAPI,
+    PaymentState, PeerInfo,
+};
+use bllvm_node::{Block, BlockHeader, Hash, OutPoint, Transaction, UTXO};
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+
+/// Default number of cached query results
+pub const DEFAULT_CACHE_CAPACITY: usize = 1024;
+
+/// Chain tip lifetime (seconds)
+pub const CHAIN_TIP_TTL_SECS: u64 = 10;
+
+/// Block height lifetime (seconds)
+pub const BLOCK_HEIGHT_TTL_SECS: u64 = 10;
+
+/// UTXO lookup lifetime (seconds)
+pub const UTXO_TTL_SECS: u64 = 30;
+
+/// Block lookup lifetime (seconds)
+pub const BLOCK_TTL_SECS: u64 = 300;
+
+/// Mempool query lifetime (seconds)
+pub const MEMPOOL_TTL_SECS: u64 = 2;
+
+/// Cache hit/miss counters
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheStats {
+    /// Queries answered from the cache
+    pub hits: u64,
+    /// Queries passed to the wrapped NodeAPI
+    pub misses: u64,
+    /// Entries dropped to make room for new ones
+    pub evictions: u64,
+}
+
+/// Request type and arguments of a cached query
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum CacheKey {
+    ChainTip,
+    BlockHeight,
+    /// Bincode-encoded outpoint
+    Utxo(Vec<u8>),
+    Block(Hash),
+    MempoolTransactions,
+    MempoolTransaction(Hash),
+    InMempool(Hash),
+}
+
+impl CacheKey {
+    fn ttl_secs(&self) -> u64 {
+        match self {
+            CacheKey::ChainTip => CHAIN_TIP_TTL_SECS,
+            CacheKey::BlockHeight => BLOCK_HEIGHT_TTL_SECS,
+            CacheKey::Utxo(_) => UTXO_TTL_SECS,
+            CacheKey::Block(_) => BLOCK_TTL_SECS,
+            CacheKey::MempoolTransactions | CacheKey::MempoolTransaction(_) | CacheKey::InMempool(_) => {
+                MEMPOOL_TTL_SECS
+            }
+        }
+    }
+}
+
+struct CacheEntry {
+    expires_at: u64,
+    value: Arc<dyn Any + Send + Sync>,
+}
+
+struct CacheState {
+    entries: LruCache<CacheKey, CacheEntry>,
+    stats: CacheStats,
+}
+
+/// NodeAPI wrapper caching chain and mempool queries
+pub struct NodeApiIpcCached {
+    inner: Arc<dyn NodeAPI>,
+    cache: Mutex<CacheState>,
+    clock: Arc<dyn Clock>,
+}
+
+impl NodeApiIpcCached {
+    /// Wrap a NodeAPI with a cache of `DEFAULT_CACHE_CAPACITY` entries
+    pub fn new(inner: Arc<dyn NodeAPI>) -> Self {
+        Self::with_capacity(inner, DEFAULT_CACHE_CAPACITY)
+    }
+
+    /// Wrap a NodeAPI with a cache of `capacity` entries (at least 1)
+    pub fn with_capacity(inner: Arc<dyn NodeAPI>, capacity: usize) -> Self {
+        Self {
+            inner,
+            cache: Mutex::new(CacheState {
+                entries: LruCache::new(NonZeroUsize::new(capacity.max(1)).unwrap()),
+                stats: CacheStats::default(),
+            }),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Expire entries by the given clock
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Cache hit/miss counters
+    pub fn get_cache_stats(&self) -> CacheStats {
+        self.cache.lock().unwrap().stats
+    }
+
+    /// Drop all cached entries
+    pub fn clear_cache(&self) {
+        self.cache.lock().unwrap().entries.clear();
+    }
+
+    /// Answer from the cache, or run `fetch` and cache a successful result
+    async fn cached<T, F>(&self, key: CacheKey, fetch: F) -> Result<T, ModuleError>
+    where
+        T: Clone + Send + Sync + 'static,
+        F: std::future::Future<Output = Result<T, ModuleError>>,
+    {
+        if let Some(value) = self.lookup::<T>(&key) {
+            return Ok(value);
+        }
+        let value = fetch.await?;
+        self.store(key, value.clone());
+        Ok(value)
+    }
+
+    fn lookup<T: Clone + 'static>(&self, key: &CacheKey) -> Option<T> {
+        let now = self.clock.now_secs();
+        let mut cache = self.cache.lock().unwrap();
+        let hit = match cache.entries.get(key) {
+            Some(entry) if entry.expires_at > now => entry.value.downcast_ref::<T>().cloned(),
+            Some(_) => {
+                cache.entries.pop(key);
+                None
+            }
+            None => None,
+        };
+        if hit.is_some() {
+            cache.stats.hits += 1;
+        } else {
+            cache.stats.misses += 1;
+        }
+        hit
+    }
+
+    fn store<T: Send + Sync + 'static>(&self, key: CacheKey, value: T) {
+        let expires_at = self.clock.now_secs().saturating_add(key.ttl_secs());
+        let mut cache = self.cache.lock().unwrap();
+        let entry = CacheEntry {
+            expires_at,
+            value: Arc::new(value),
+        };
+        if let Some((evicted, _)) = cache.entries.push(key.clone(), entry) {
+            if evicted != key {
+                cache.stats.evictions += 1;
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl NodeAPI for NodeApiIpcCached {
+    async fn get_block(&self, hash: &Hash) -> Result<Option<Block>, ModuleError> {
+        self.cached(CacheKey::Block(*hash), self.inner.get_block(hash)).await
+    }
+    async fn get_block_header(&self, hash: &Hash) -> Result<Option<BlockHeader>, ModuleError> { self.inner.get_block_header(hash).await }
+    async fn get_transaction(&self, hash: &Hash) -> Result<Option<Transaction>, ModuleError> { self.inner.get_transaction(hash).await }
+    async fn has_transaction(&self, hash: &Hash) -> Result<bool, ModuleError> { self.inner.has_transaction(hash).await }
+    async fn get_chain_tip(&self) -> Result<Hash, ModuleError> {
+        self.cached(CacheKey::ChainTip, self.inner.get_chain_tip()).await
+    }
+    async fn get_block_height(&self) -> Result<u64, ModuleError> {
+        self.cached(CacheKey::BlockHeight, self.inner.get_block_height()).await
+    }
+    async fn get_utxo(&self, outpoint: &OutPoint) -> Result<Option<UTXO>, ModuleError> {
+        let Ok(key) = bincode::serialize(outpoint) else {
+            return self.inner.get_utxo(outpoint).await;
+        };
+        self.cached(CacheKey::Utxo(key), self.inner.get_utxo(outpoint)).await
+    }
+    async fn subscribe_events(&self, event_types: Vec<EventType>) -> Result<mpsc::Receiver<ModuleMessage>, ModuleError> {
+        self.inner.subscribe_events(event_types).await
+    }
+    async fn get_mempool_transactions(&self) -> Result<Vec<Hash>, ModuleError> {
+        self.cached(CacheKey::MempoolTransactions, self.inner.get_mempool_transactions()).await
+    }
+    async fn get_mempool_transaction(&self, tx_hash: &Hash) -> Result<Option<Transaction>, ModuleError> {
+        self.cached(CacheKey::MempoolTransaction(*tx_hash), self.inner.get_mempool_transaction(tx_hash)).await
+    }
+    async fn get_mempool_size(&self) -> Result<MempoolSize, ModuleError> { self.inner.get_mempool_size().await }
+    async fn get_network_stats(&self) -> Result<NetworkStats, ModuleError> { self.inner.get_network_stats().await }
+    async fn get_network_peers(&self) -> Result<Vec<PeerInfo>, ModuleError> { self.inner.get_network_peers().await }
+    async fn get_chain_info(&self) -> Result<ChainInfo, ModuleError> { self.inner.get_chain_info().await }
+    async fn get_block_by_height(&self, height: u64) -> Result<Option<Block>, ModuleError> { self.inner.get_block_by_height(height).await }
+    async fn get_lightning_node_url(&self) -> Result<Option<String>, ModuleError> { self.inner.get_lightning_node_url().await }
+    async fn get_lightning_info(&self) -> Result<Option<LightningInfo>, ModuleError> { self.inner.get_lightning_info().await }
+    async fn get_payment_state(&self, payment_id: &str) -> Result<Option<PaymentState>, ModuleError> { self.inner.get_payment_state(payment_id).await }
+    async fn check_transaction_in_mempool(&self, tx_hash: &Hash) -> Result<bool, ModuleError> {
+        self.cached(CacheKey::InMempool(*tx_hash), self.inner.check_transaction_in_mempool(tx_hash)).await
+    }
+    async fn get_fee_estimate(&self, target_blocks: u32) -> Result<u64, ModuleError> { self.inner.get_fee_estimate(target_blocks).await }
+    async fn read_file(&self, path: String) -> Result<Vec<u8>, ModuleError> { self.inner.read_file(path).await }
+    async fn write_file(&self, path: String, data: Vec<u8>) -> Result<(), ModuleError> { self.inner.write_file(path, data).await }
+    async fn delete_file(&self, path: String) -> Result<(), ModuleError> { self.inner.delete_file(path).await }
+    async fn list_directory(&self, path: String) -> Result<Vec<String>, ModuleError> { self.inner.list_directory(path).await }
+    async fn create_directory(&self, path: String) -> Result<(), ModuleError> { self.inner.create_directory(path).await }
+    async fn get_file_metadata(&self, path: String) -> Result<bllvm_node::module::ipc::protocol::FileMetadata, ModuleError> { self.inner.get_file_metadata(path).await }
+    async fn storage_open_tree(&self, name: String) -> Result<String, ModuleError> { self.inner.storage_open_tree(name).await }
+    async fn storage_insert(&self, tree_id: String, key: Vec<u8>, value: Vec<u8>) -> Result<(), ModuleError> { self.inner.storage_insert(tree_id, key, value).await }
+    async fn storage_get(&self, tree_id: String, key: Vec<u8>) -> Result<Option<Vec<u8>>, ModuleError> { self.inner.storage_get(tree_id, key).await }
+    async fn storage_remove(&self, tree_id: String, key: Vec<u8>) -> Result<(), ModuleError> { self.inner.storage_remove(tree_id, key).await }
+    async fn storage_contains_key(&self, tree_id: String, key: Vec<u8>) -> Result<bool, ModuleError> { self.inner.storage_contains_key(tree_id, key).await }
+    async fn storage_iter(&self, tree_id: String) -> Result<Vec<(Vec<u8>, Vec<u8>)>, ModuleError> { self.inner.storage_iter(tree_id).await }
+    async fn storage_transaction(&self, tree_id: String, operations: Vec<bllvm_node::module::ipc::protocol::StorageOperation>) -> Result<(), ModuleError> { self.inner.storage_transaction(tree_id, operations).await }
+    async fn register_rpc_endpoint(&self, method: String, description: String) -> Result<(), ModuleError> { self.inner.register_rpc_endpoint(method, description).await }
+    async fn unregister_rpc_endpoint(&self, method: &str) -> Result<(), ModuleError> { self.inner.unregister_rpc_endpoint(method).await }
+    async fn register_timer(&self, interval_seconds: u64, callback: Arc<dyn bllvm_node::module::timers::manager::TimerCallback>) -> Result<bllvm_node::module::timers::manager::TimerId, ModuleError> { self.inner.register_timer(interval_seconds, callback).await }
+    async fn cancel_timer(&self, timer_id: bllvm_node::module::timers::manager::TimerId) -> Result<(), ModuleError> { self.inner.cancel_timer(timer_id).await }
+    async fn schedule_task(&self, delay_seconds: u64, callback: Arc<dyn bllvm_node::module::timers::manager::TaskCallback>) -> Result<bllvm_node::module::timers::manager::TaskId, ModuleError> { self.inner.schedule_task(delay_seconds, callback).await }
+    async fn report_metric(&self, metric: bllvm_node::module::metrics::manager::Metric) -> Result<(), ModuleError> { self.inner.report_metric(metric).await }
+    async fn get_module_metrics(&self, module_id: &str) -> Result<Vec<bllvm_node::module::metrics::manager::Metric>, ModuleError> { self.inner.get_module_metrics(module_id).await }
+    async fn initialize_module(&self, module_id: &str, manifest: bllvm_node::module::traits::ModuleManifest) -> Result<(), ModuleError> { self.inner.initialize_module(module_id, manifest).await }
+    async fn discover_modules(&self) -> Result<Vec<bllvm_node::module::traits::ModuleInfo>, ModuleError> { self.inner.discover_modules().await }
+    async fn get_module_info(&self, module_id: &str) -> Result<Option<bllvm_node::module::traits::ModuleInfo>, ModuleError> { self.inner.get_module_info(module_id).await }
+    async fn is_module_available(&self, module_id: &str) -> Result<bool, ModuleError> { self.inner.is_module_available(module_id).await }
+    async fn publish_event(&self, event_type: EventType, payload: EventPayload) -> Result<(), ModuleError> { self.inner.publish_event(event_type, payload).await }
+    async fn call_module(&self, target: Option<&str>, method: &str, params: Vec<u8>) -> Result<Vec<u8>, ModuleError> { self.inner.call_module(target, method, params).await }
+    async fn register_module_api(&self, methods: Vec<String>, version: u32) -> Result<(), ModuleError> { self.inner.register_module_api(methods, version).await }
+    async fn unregister_module_api(&self) -> Result<(), ModuleError> { self.inner.unregister_module_api().await }
+    async fn get_module_health(&self, module_id: &str) -> Result<Option<bllvm_node::module::process::monitor::ModuleHealth>, ModuleError> { self.inner.get_module_health(module_id).await }
+    async fn get_all_module_health(&self) -> Result<Vec<(String, bllvm_node::module::process::monitor::ModuleHealth)>, ModuleError> { self.inner.get_all_module_health().await }
+    async fn report_module_health(&self, health: bllvm_node::module::process::monitor::ModuleHealth) -> Result<(), ModuleError> { self.inner.report_module_health(health).await }
+    async fn send_mesh_packet_to_module(&self, module_id: &str, packet_data: Vec<u8>, peer_addr: String) -> Result<(), ModuleError> { self.inner.send_mesh_packet_to_module(module_id, packet_data, peer_addr).await }
+    async fn send_mesh_packet_to_peer(&self, peer_addr: String, packet_data: Vec<u8>) -> Result<(), ModuleError> { self.inner.send_mesh_packet_to_peer(peer_addr, packet_data).await }
+    async fn send_stratum_v2_message_to_peer(&self, peer_addr: String, message_data: Vec<u8>) -> Result<(), ModuleError> { self.inner.send_stratum_v2_message_to_peer(peer_addr, message_data).await }
+    async fn get_node_public_key(&self) -> Result<Option<Vec<u8>>, ModuleError> { self.inner.get_node_public_key().await }
+    async fn get_event_publisher(&self) -> Result<Option<Arc<bllvm_node::node::event_publisher::EventPublisher>>, ModuleError> { self.inner.get_event_publisher().await }
+}
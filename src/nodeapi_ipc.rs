@@ -3,6 +3,8 @@
 //! This module provides a NodeAPI trait implementation that translates
 //! method calls into IPC requests to the node. This can be reused by all modules.
 
+use crate::error::MeshError;
+use crate::storage_schema::PrefixStorage;
 use async_trait::async_trait;
 use bllvm_node::module::ipc::client::ModuleIpcClient;
 use bllvm_node::module::ipc::protocol::{
@@ -13,10 +15,14 @@ use bllvm_node::module::traits::{
     PaymentState, PeerInfo,
 };
 use bllvm_node::{Block, BlockHeader, Hash, OutPoint, Transaction, UTXO};
+use std::future::Future;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use tokio::sync::mpsc;
 
+/// Key-value pairs requested per page by `storage_scan`
+pub const STORAGE_SCAN_PAGE_SIZE: usize = 256;
+
 /// NodeAPI implementation that uses IPC to communicate with the node
 pub struct NodeApiIpc {
     /// IPC client (wrapped in Arc<Mutex> for thread safety)
@@ -96,6 +102,8 @@ impl NodeApiIpc {
             RequestPayload::StorageRemove { .. } => MessageType::StorageRemove,
             RequestPayload::StorageContainsKey { .. } => MessageType::StorageContainsKey,
             RequestPayload::StorageIter { .. } => MessageType::StorageIter,
+            RequestPayload::StorageIterRange { .. } => MessageType::StorageIterRange,
+            RequestPayload::StorageIterPrefix { .. } => MessageType::StorageIterPrefix,
             RequestPayload::StorageTransaction { .. } => MessageType::StorageTransaction,
             RequestPayload::SubscribeEvents { .. } => MessageType::SubscribeEvents,
             RequestPayload::Handshake { .. } => MessageType::Handshake,
@@ -106,6 +114,117 @@ impl NodeApiIpc {
             _ => MessageType::Response, // Fallback
         }
     }
+
+    /// Fetch one page of a storage tree
+    ///
+    /// Returns up to `limit` pairs in key order starting at `start_key`
+    /// (inclusive; `None` = first key), plus the cursor to pass as
+    /// `start_key` for the next page (`None` once the tree is exhausted).
+    pub async fn storage_iter_range(
+        &self,
+        tree_id: String,
+        start_key: Option<Vec<u8>>,
+        limit: usize,
+    ) -> Result<(Vec<(Vec<u8>, Vec<u8>)>, Option<Vec<u8>>), MeshError> {
+        let page = self
+            .request(
+                RequestPayload::StorageIterRange {
+                    tree_id,
+                    start_key,
+                    limit: limit.max(1),
+                },
+                |payload| match payload {
+                    ResponsePayload::StorageKeyValuePage { pairs, next_cursor } => Ok(Some((pairs, next_cursor))),
+                    _ => Ok(None),
+                },
+            )
+            .await?;
+        page.ok_or_else(|| MeshError::IpcError("Unexpected response type for StorageIterRange".to_string()))
+    }
+
+    /// Fetch the pairs of a storage tree whose keys start with `prefix`
+    ///
+    /// The node filters the tree, so unrelated keys never cross the IPC
+    /// channel. Pairs come back in key order.
+    pub async fn storage_iter_prefix(
+        &self,
+        tree_id: String,
+        prefix: Vec<u8>,
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>, ModuleError> {
+        self.request(
+            RequestPayload::StorageIterPrefix { tree_id, prefix },
+            |payload| match payload {
+                ResponsePayload::StorageKeyValuePairs(pairs) => Ok(pairs),
+                _ => Err(ModuleError::OperationError("Unexpected response type for StorageIterPrefix".to_string())),
+            },
+        )
+        .await
+    }
+
+    /// Stream every pair of a storage tree through `sender`
+    ///
+    /// Drives `storage_iter_range` page by page, so neither side holds the
+    /// whole tree at once. Stops early if the receiver is dropped, and fails
+    /// if the node hands back a cursor that does not move forward. Returns
+    /// the number of pairs sent.
+    pub async fn storage_scan(
+        &self,
+        tree_id: String,
+        sender: mpsc::Sender<(Vec<u8>, Vec<u8>)>,
+    ) -> Result<usize, MeshError> {
+        scan_pages(
+            |cursor| self.storage_iter_range(tree_id.clone(), cursor, STORAGE_SCAN_PAGE_SIZE),
+            sender,
+        )
+        .await
+    }
+}
+
+/// Send the pairs of each page from `fetch_page` until the cursor runs out
+///
+/// Cursors are keys, so each must sort after the one before it; a node
+/// returning the same (or an earlier) cursor would otherwise be polled
+/// forever.
+async fn scan_pages<F, Fut>(
+    mut fetch_page: F,
+    sender: mpsc::Sender<(Vec<u8>, Vec<u8>)>,
+) -> Result<usize, MeshError>
+where
+    F: FnMut(Option<Vec<u8>>) -> Fut,
+    Fut: Future<Output = Result<(Vec<(Vec<u8>, Vec<u8>)>, Option<Vec<u8>>), MeshError>>,
+{
+    let mut cursor: Option<Vec<u8>> = None;
+    let mut sent = 0;
+    loop {
+        let (pairs, next_cursor) = fetch_page(cursor.clone()).await?;
+        for pair in pairs {
+            if sender.send(pair).await.is_err() {
+                return Ok(sent);
+            }
+            sent += 1;
+        }
+        match next_cursor {
+            Some(next) if matches!(cursor, Some(ref current) if next <= *current) => {
+                return Err(MeshError::IpcError(format!(
+                    "Storage scan cursor did not advance past {}",
+                    hex::encode(&next)
+                )));
+            }
+            Some(next) => cursor = Some(next),
+            None => return Ok(sent),
+        }
+    }
+}
+
+#[async_trait]
+impl PrefixStorage for NodeApiIpc {
+    async fn storage_iter_prefix(
+        &self,
+        tree_id: String,
+        prefix: Vec<u8>,
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>, ModuleError> {
+        NodeApiIpc::storage_iter_prefix(self, tree_id, prefix).await
+    }
 }
 
 #[async_trait]
@@ -683,3 +802,73 @@ impl NodeAPI for NodeApiIpc {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    type Page = (Vec<(Vec<u8>, Vec<u8>)>, Option<Vec<u8>>);
+
+    /// Fetch scripted pages in order, recording the cursors asked for
+    fn scripted<'a>(
+        pages: Vec<Page>,
+        cursors: &'a std::sync::Mutex<Vec<Option<Vec<u8>>>>,
+    ) -> impl FnMut(Option<Vec<u8>>) -> std::future::Ready<Result<Page, MeshError>> + 'a {
+        let mut pages = VecDeque::from(pages);
+        move |cursor| {
+            cursors.lock().unwrap().push(cursor);
+            std::future::ready(
+                pages
+                    .pop_front()
+                    .ok_or_else(|| MeshError::IpcError("No more scripted pages".to_string())),
+            )
+        }
+    }
+
+    fn pair(key: u8) -> (Vec<u8>, Vec<u8>) {
+        (vec![key], vec![key])
+    }
+
+    #[tokio::test]
+    async fn test_scan_follows_cursor_to_the_end() {
+        let cursors = std::sync::Mutex::new(Vec::new());
+        let pages = vec![
+            (vec![pair(1), pair(2)], Some(vec![3])),
+            (vec![pair(3), pair(4)], Some(vec![5])),
+            (vec![pair(5)], None),
+        ];
+        let (sender, mut receiver) = mpsc::channel(16);
+        let sent = scan_pages(scripted(pages, &cursors), sender).await.unwrap();
+
+        assert_eq!(sent, 5);
+        assert_eq!(*cursors.lock().unwrap(), vec![None, Some(vec![3]), Some(vec![5])]);
+        let mut keys = Vec::new();
+        while let Some((key, _)) = receiver.recv().await {
+            keys.push(key[0]);
+        }
+        assert_eq!(keys, vec![1, 2, 3, 4, 5]);
+    }
+
+    #[tokio::test]
+    async fn test_scan_fails_when_cursor_does_not_advance() {
+        let cursors = std::sync::Mutex::new(Vec::new());
+        let pages = vec![
+            (vec![pair(1)], Some(vec![2])),
+            (vec![pair(2)], Some(vec![2])),
+            (vec![pair(2)], Some(vec![2])),
+        ];
+        let (sender, _receiver) = mpsc::channel(16);
+        let result = scan_pages(scripted(pages, &cursors), sender).await;
+
+        assert!(matches!(result, Err(MeshError::IpcError(_))));
+        assert_eq!(cursors.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_scan_fails_when_cursor_moves_backwards() {
+        let cursors = std::sync::Mutex::new(Vec::new());
+        let pages = vec![(vec![pair(5)], Some(vec![6])), (Vec::new(), Some(vec![1]))];
+        let (sender, _receiver) = mpsc::channel(16);
+        assert!(scan_pages(scripted(pages, &cursors), sender).await.is_err());
+    }
+}
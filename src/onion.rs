@@ -0,0 +1,419 @@
+//! Onion routing (layered encryption) for mesh packets
+//!
+//! A sender wraps the payload in one layer per hop, innermost first: each
+//! layer is encrypted to that hop's X25519 key (ephemeral ECDH +
+//! ChaCha20-Poly1305) and holds the hop's forwarding instruction together
+//! with the sealed inner onion, or the payload for the final hop. A hop can
+//! only open its own layer and cannot see how many layers remain inside.
+//!
+//! `PacketType::Encrypted` packets carry the onion padded with random bytes
+//! to `ONION_PACKET_SIZE`; every hop peels its layer, re-pads the inner
+//! onion and re-originates the packet to the next hop, so relays learn
+//! neither the payload, the sender, nor the route length from the size. The
+//! destination's layer is padded by a random amount, so the sealed length
+//! does not give away a hop's position either.
+//!
+//! The same per-node key also receives end-to-end encrypted payloads
+//! (`PacketType::PaidEncrypted`): the sender seals the payload to the
+//! destination's key and relays forward the ciphertext untouched.
+
+use crate::error::MeshError;
+use crate::packet::{MeshPacket, MeshPacketBuilder, PacketType};
+use crate::routing::NodeId;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use x25519_dalek::{EphemeralSecret, PublicKey, StaticSecret};
+
+/// Domain separator for layer key derivation
+const LAYER_KEY_CONTEXT: &[u8] = b"bllvm-mesh-onion-v1";
+
+/// Size of the onion carried by every encrypted packet
+pub const ONION_PACKET_SIZE: usize = 16 * 1024;
+
+/// X25519 public key of a hop (32 bytes)
+pub type OnionPublicKey = [u8; 32];
+
+/// Decrypted contents of one onion layer
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OnionLayer {
+    /// Hop to forward the inner onion to (None = this node is the destination)
+    pub next_hop: Option<NodeId>,
+    /// Application payload (final layer only)
+    pub payload: Vec<u8>,
+    /// Sealed next layer, unpadded (forwarding layers only)
+    pub inner: Vec<u8>,
+    /// Random filler (final layer only)
+    pub padding: Vec<u8>,
+}
+
+/// One layer, encrypted to a single hop
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EncryptedLayer {
+    /// Sender's ephemeral X25519 public key
+    pub ephemeral_public: OnionPublicKey,
+    /// ChaCha20-Poly1305 nonce
+    pub nonce: [u8; 12],
+    /// Encrypted `OnionLayer`
+    pub ciphertext: Vec<u8>,
+}
+
+impl EncryptedLayer {
+    fn encode(&self) -> Result<Vec<u8>, MeshError> {
+        bincode::serialize(self)
+            .map_err(|e| MeshError::InvalidPacket(format!("Failed to encode onion layer: {}", e)))
+    }
+}
+
+/// Result of peeling this node's layer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PeeledOnion {
+    /// Forward the inner onion (padded to `ONION_PACKET_SIZE`) to the next hop
+    Forward { next_hop: NodeId, onion: Vec<u8> },
+    /// This node is the destination
+    Deliver { payload: Vec<u8> },
+}
+
+/// This node's onion key pair
+pub struct OnionKey {
+    secret: StaticSecret,
+    public: PublicKey,
+}
+
+impl OnionKey {
+    /// Generate a fresh key pair
+    pub fn generate() -> Self {
+        Self::from_secret_bytes(StaticSecret::random_from_rng(OsRng).to_bytes())
+    }
+
+    /// Restore a key pair from its secret bytes
+    pub fn from_secret_bytes(bytes: [u8; 32]) -> Self {
+        let secret = StaticSecret::from(bytes);
+        let public = PublicKey::from(&secret);
+        Self { secret, public }
+    }
+
+    /// Secret bytes (for persistence)
+    pub fn secret_bytes(&self) -> [u8; 32] {
+        self.secret.to_bytes()
+    }
+
+    /// Public key to hand out to senders
+    pub fn public_key(&self) -> OnionPublicKey {
+        self.public.to_bytes()
+    }
+
+    /// Decrypt the outermost layer of a padded onion addressed to this node
+    pub fn peel(&self, onion: &[u8]) -> Result<PeeledOnion, MeshError> {
+        if onion.len() != ONION_PACKET_SIZE {
+            return Err(MeshError::InvalidPacket(format!(
+                "Onion is {} bytes, expected {}",
+                onion.len(),
+                ONION_PACKET_SIZE
+            )));
+        }
+        // The sealed layer is length-prefixed; the padding after it is ignored
+        let layer: EncryptedLayer = bincode::deserialize(onion)
+            .map_err(|e| MeshError::InvalidPacket(format!("Malformed onion: {}", e)))?;
+
+        let plaintext = self.open(&layer)?;
+        let decrypted: OnionLayer = bincode::deserialize(&plaintext)
+            .map_err(|e| MeshError::InvalidPacket(format!("Malformed onion layer: {}", e)))?;
+
+        match decrypted.next_hop {
+            Some(next_hop) if !decrypted.inner.is_empty() => Ok(PeeledOnion::Forward {
+                next_hop,
+                onion: pad_onion(decrypted.inner)?,
+            }),
+            None if decrypted.inner.is_empty() => Ok(PeeledOnion::Deliver {
+                payload: decrypted.payload,
+            }),
+            _ => Err(MeshError::InvalidPacket(
+                "Onion layer instruction does not match its inner onion".to_string(),
+            )),
+        }
+    }
+
+    /// Decrypt data sealed to this node's public key
+    pub fn open(&self, sealed: &EncryptedLayer) -> Result<Vec<u8>, MeshError> {
+        let ephemeral = PublicKey::from(sealed.ephemeral_public);
+        let shared = self.secret.diffie_hellman(&ephemeral);
+        let cipher = layer_cipher(shared.as_bytes(), &sealed.ephemeral_public, &self.public_key());
+        cipher
+            .decrypt(Nonce::from_slice(&sealed.nonce), sealed.ciphertext.as_slice())
+            .map_err(|_| MeshError::InvalidPacket("Failed to decrypt sealed data".to_string()))
+    }
+}
+
+/// Builds an onion packet for a route
+///
+/// `route` lists the hops after the sender, ending with the destination;
+/// `pubkeys` holds each hop's onion public key in the same order.
+pub struct OnionPacketBuilder {
+    route: Vec<NodeId>,
+    payload: Vec<u8>,
+    pubkeys: Vec<OnionPublicKey>,
+}
+
+impl OnionPacketBuilder {
+    /// Create a builder
+    pub fn new(route: Vec<NodeId>, payload: Vec<u8>, pubkeys: Vec<OnionPublicKey>) -> Self {
+        Self {
+            route,
+            payload,
+            pubkeys,
+        }
+    }
+
+    /// Nest one layer per hop (first hop outermost), padded to `ONION_PACKET_SIZE`
+    ///
+    /// Fails if the payload and route do not fit.
+    pub fn build_onion(&self) -> Result<Vec<u8>, MeshError> {
+        if self.route.is_empty() {
+            return Err(MeshError::InvalidRequest("Onion route is empty".to_string()));
+        }
+        if self.route.len() != self.pubkeys.len() {
+            return Err(MeshError::InvalidRequest(format!(
+                "Onion route has {} hops but {} public keys",
+                self.route.len(),
+                self.pubkeys.len()
+            )));
+        }
+
+        // Every layer grows by the same amount as the filler, so the
+        // unpadded size bounds how much filler fits
+        let unpadded = self.nest(Vec::new())?.len();
+        if unpadded > ONION_PACKET_SIZE {
+            return Err(MeshError::InvalidRequest(format!(
+                "Onion payload and {} hops need {} bytes, more than {}",
+                self.route.len(),
+                unpadded,
+                ONION_PACKET_SIZE
+            )));
+        }
+        let spare = ONION_PACKET_SIZE - unpadded;
+        let filler_len = OsRng.next_u32() as usize % (spare + 1);
+        pad_onion(self.nest(random_bytes(filler_len))?)
+    }
+
+    /// Seal the layers from the destination outwards, returning the first
+    /// hop's sealed layer
+    fn nest(&self, padding: Vec<u8>) -> Result<Vec<u8>, MeshError> {
+        let last = self.route.len() - 1;
+        let mut inner = encrypt_layer(
+            &OnionLayer {
+                next_hop: None,
+                payload: self.payload.clone(),
+                inner: Vec::new(),
+                padding,
+            },
+            &self.pubkeys[last],
+        )?;
+        for index in (0..last).rev() {
+            let layer = OnionLayer {
+                next_hop: Some(self.route[index + 1]),
+                payload: Vec::new(),
+                inner,
+                padding: Vec::new(),
+            };
+            inner = encrypt_layer(&layer, &self.pubkeys[index])?;
+        }
+        Ok(inner)
+    }
+
+    /// Build the packet sent by `source` to the first hop
+    pub fn build(&self, source: NodeId) -> Result<MeshPacket, MeshError> {
+        let onion = self.build_onion()?;
+        MeshPacketBuilder::new(source, self.route[0])
+            .packet_type(PacketType::Encrypted)
+            .payload(onion)
+            .build()
+    }
+}
+
+/// Encrypt a layer to a hop's public key, returning the encoded sealed layer
+fn encrypt_layer(layer: &OnionLayer, hop_public: &OnionPublicKey) -> Result<Vec<u8>, MeshError> {
+    let plaintext = bincode::serialize(layer)
+        .map_err(|e| MeshError::InvalidPacket(format!("Failed to encode onion layer: {}", e)))?;
+    seal(&plaintext, hop_public)?.encode()
+}
+
+/// Fill an encoded sealed layer up to `ONION_PACKET_SIZE` with random bytes
+fn pad_onion(mut onion: Vec<u8>) -> Result<Vec<u8>, MeshError> {
+    if onion.len() > ONION_PACKET_SIZE {
+        return Err(MeshError::InvalidPacket(format!(
+            "Onion layer is {} bytes, more than {}",
+            onion.len(),
+            ONION_PACKET_SIZE
+        )));
+    }
+    onion.extend(random_bytes(ONION_PACKET_SIZE - onion.len()));
+    Ok(onion)
+}
+
+fn random_bytes(len: usize) -> Vec<u8> {
+    let mut bytes = vec![0u8; len];
+    OsRng.fill_bytes(&mut bytes);
+    bytes
+}
+
+/// Encrypt data to a node's public key with a fresh ephemeral key
+pub fn seal(plaintext: &[u8], recipient: &OnionPublicKey) -> Result<EncryptedLayer, MeshError> {
+    let ephemeral = EphemeralSecret::random_from_rng(OsRng);
+    let ephemeral_public = PublicKey::from(&ephemeral).to_bytes();
+    let shared = ephemeral.diffie_hellman(&PublicKey::from(*recipient));
+
+    let cipher = layer_cipher(shared.as_bytes(), &ephemeral_public, recipient);
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext)
+        .map_err(|_| MeshError::InvalidPacket("Failed to encrypt sealed data".to_string()))?;
+
+    Ok(EncryptedLayer {
+        ephemeral_public,
+        nonce: nonce.into(),
+        ciphertext,
+    })
+}
+
+/// Derive the layer cipher from the ECDH secret and both public keys
+fn layer_cipher(shared: &[u8; 32], ephemeral_public: &OnionPublicKey, hop_public: &OnionPublicKey) -> ChaCha20Poly1305 {
+    let mut hasher = Sha256::new();
+    hasher.update(LAYER_KEY_CONTEXT);
+    hasher.update(shared);
+    hasher.update(ephemeral_public);
+    hasher.update(hop_public);
+    ChaCha20Poly1305::new(Key::from_slice(&hasher.finalize()))
+}
+
+impl MeshPacketBuilder {
+    /// Make this a paid packet whose payload only the destination can read
+    ///
+    /// Seals `plaintext` to `destination_key` and sets the type to
+    /// `PaidEncrypted`; the payment proof is still attached separately.
+    pub fn sealed_payload(self, plaintext: &[u8], destination_key: &OnionPublicKey) -> Result<Self, MeshError> {
+        let payload = bincode::serialize(&seal(plaintext, destination_key)?)
+            .map_err(|e| MeshError::InvalidPacket(format!("Failed to encode sealed payload: {}", e)))?;
+        Ok(self.packet_type(PacketType::PaidEncrypted).payload(payload))
+    }
+}
+
+impl MeshPacket {
+    /// Decrypt an end-to-end encrypted payload addressed to `key`
+    pub fn decrypt_payload(&self, key: &OnionKey) -> Result<Vec<u8>, MeshError> {
+        if self.packet_type != PacketType::PaidEncrypted {
+            return Err(MeshError::InvalidPacket("Not an end-to-end encrypted packet".to_string()));
+        }
+        let sealed: EncryptedLayer = bincode::deserialize(&self.payload)
+            .map_err(|e| MeshError::InvalidPacket(format!("Malformed sealed payload: {}", e)))?;
+        key.open(&sealed)
+    }
+
+    /// Padded onion carried by an encrypted packet
+    pub fn onion(&self) -> Result<&[u8], MeshError> {
+        if self.packet_type != PacketType::Encrypted {
+            return Err(MeshError::InvalidPacket("Not an encrypted packet".to_string()));
+        }
+        Ok(&self.payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::payment_proof::PaymentProof;
+
+    #[test]
+    fn test_each_hop_peels_only_its_layer() {
+        let keys: Vec<OnionKey> = (0..3).map(|_| OnionKey::generate()).collect();
+        let route = vec![[1u8; 32], [2u8; 32], [3u8; 32]];
+        let pubkeys = keys.iter().map(OnionKey::public_key).collect();
+        let onion = OnionPacketBuilder::new(route.clone(), b"secret".to_vec(), pubkeys)
+            .build_onion()
+            .unwrap();
+        assert_eq!(onion.len(), ONION_PACKET_SIZE);
+
+        // Neither a later hop nor the destination can open the outer layer
+        assert!(keys[1].peel(&onion).is_err());
+        assert!(keys[2].peel(&onion).is_err());
+
+        let PeeledOnion::Forward { next_hop, onion } = keys[0].peel(&onion).unwrap() else {
+            panic!("first hop should forward");
+        };
+        assert_eq!(next_hop, route[1]);
+        assert_eq!(onion.len(), ONION_PACKET_SIZE);
+        let PeeledOnion::Forward { next_hop, onion } = keys[1].peel(&onion).unwrap() else {
+            panic!("second hop should forward");
+        };
+        assert_eq!(next_hop, route[2]);
+        assert_eq!(onion.len(), ONION_PACKET_SIZE);
+        assert_eq!(
+            keys[2].peel(&onion).unwrap(),
+            PeeledOnion::Deliver {
+                payload: b"secret".to_vec()
+            }
+        );
+    }
+
+    #[test]
+    fn test_tampered_layer_rejected() {
+        let key = OnionKey::generate();
+        let mut onion = OnionPacketBuilder::new(vec![[1u8; 32]], vec![1, 2, 3], vec![key.public_key()])
+            .build_onion()
+            .unwrap();
+        // Past the ephemeral key, nonce and length prefix
+        onion[60] ^= 1;
+        assert!(key.peel(&onion).is_err());
+    }
+
+    #[test]
+    fn test_onion_size_independent_of_route_length() {
+        let keys: Vec<OnionKey> = (0..4).map(|_| OnionKey::generate()).collect();
+        for hops in 1..=keys.len() {
+            let route = (0..hops).map(|i| [i as u8 + 1; 32]).collect();
+            let pubkeys = keys[..hops].iter().map(OnionKey::public_key).collect();
+            let onion = OnionPacketBuilder::new(route, b"secret".to_vec(), pubkeys)
+                .build_onion()
+                .unwrap();
+            assert_eq!(onion.len(), ONION_PACKET_SIZE);
+        }
+    }
+
+    #[test]
+    fn test_oversized_onion_rejected() {
+        let key = OnionKey::generate();
+        let builder = OnionPacketBuilder::new(vec![[1u8; 32]], vec![0u8; ONION_PACKET_SIZE], vec![key.public_key()]);
+        assert!(matches!(builder.build_onion(), Err(MeshError::InvalidRequest(_))));
+        assert!(key.peel(&[0u8; 16]).is_err());
+    }
+
+    #[test]
+    fn test_sealed_payload_round_trip() {
+        let key = OnionKey::generate();
+        let proof = PaymentProof::Lightning {
+            invoice: "lnbc1test".to_string(),
+            preimage: [7u8; 32],
+            amount_msats: 1_000,
+            timestamp: 0,
+            expires_at: 0,
+        };
+        let packet = MeshPacketBuilder::new([1u8; 32], [2u8; 32])
+            .sealed_payload(b"for your eyes only", &key.public_key())
+            .unwrap()
+            .payment_proof(proof)
+            .build()
+            .unwrap();
+
+        assert_eq!(packet.decrypt_payload(&key).unwrap(), b"for your eyes only");
+        assert!(packet.decrypt_payload(&OnionKey::generate()).is_err());
+    }
+
+    #[test]
+    fn test_key_restores_from_secret() {
+        let key = OnionKey::generate();
+        let restored = OnionKey::from_secret_bytes(key.secret_bytes());
+        assert_eq!(key.public_key(), restored.public_key());
+    }
+}
@@ -3,6 +3,10 @@
 //! Defines the packet format for mesh networking, including headers,
 //! routing information, and payment proofs.
 
+use crate::balance::PAYMENT_SESSION_FIELD;
+use crate::delivery_tracker::ACK_REQUESTED_FIELD;
+use crate::error::MeshError;
+use crate::metadata_schema::SchemaRegistry;
 use crate::payment_proof::PaymentProof;
 use crate::routing::NodeId;
 use serde::{Deserialize, Serialize};
@@ -11,12 +15,28 @@ use std::time::{SystemTime, UNIX_EPOCH};
 /// Mesh packet magic bytes
 pub const MESH_PACKET_MAGIC: [u8; 4] = [0x4D, 0x45, 0x53, 0x48]; // "MESH"
 
-/// Mesh packet version
-pub const MESH_PACKET_VERSION: u8 = 1;
+/// Mesh packet version (v2: TTL moved from the body into the wire header,
+/// v3: source routing fields, v4: body checksum, v5: payload compression)
+pub const MESH_PACKET_VERSION: u8 = 5;
+
+/// Encoded size of `MeshPacket::checksum`, the last field of the body
+pub const CHECKSUM_LEN: usize = 4;
 
 /// Maximum packet size (1MB)
 pub const MAX_PACKET_SIZE: usize = 1_000_000;
 
+/// Default hop budget for new packets (`mesh.packet.default_ttl`)
+pub const DEFAULT_TTL: u8 = 64;
+
+/// Default maximum accepted hop budget (`mesh.max_ttl`)
+pub const DEFAULT_MAX_TTL: u8 = 128;
+
+/// Default largest frame handed to the transport (`mesh.mtu`)
+pub const DEFAULT_MTU: usize = 16 * 1024;
+
+/// Smallest accepted MTU (leaves room for a fragment's own header)
+pub const MIN_MTU: usize = 512;
+
 /// Mesh packet type
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum PacketType {
@@ -28,6 +48,18 @@ pub enum PacketType {
     StratumV2,
     /// Paid mesh packet (arbitrary data, messaging, IPFS)
     Paid,
+    /// Link control between direct peers (keepalives)
+    Control,
+    /// Onion-routed packet (payload is the remaining encrypted layers)
+    Encrypted,
+    /// Piece of a packet that exceeded the link MTU (see `FragmentHeader`)
+    Fragment,
+    /// Paid mesh packet with an end-to-end encrypted payload (see `onion`)
+    PaidEncrypted,
+    /// Route discovery message (payload is an encoded `DiscoveryMessage`)
+    Discovery,
+    /// Delivery confirmation of a paid packet (payload is an encoded `AckPayload`)
+    Ack,
 }
 
 /// Mesh packet for routing through the network
@@ -45,6 +77,11 @@ pub struct MeshPacket {
     pub route: Vec<NodeId>,
     /// Sequence number (for ordering and duplicate detection)
     pub sequence: u64,
+    /// Remaining hop budget (decremented on every send, dropped at zero)
+    ///
+    /// Carried in the wire header rather than the body (see `network`).
+    #[serde(skip)]
+    pub ttl: u8,
     /// Timestamp (Unix epoch seconds)
     pub timestamp: u64,
     /// Payment proof (required for Paid packets)
@@ -53,15 +90,108 @@ pub struct MeshPacket {
     pub payload: Vec<u8>,
     /// Optional metadata (protocol-specific)
     pub metadata: Option<PacketMetadata>,
+    /// Source's Schnorr signature over the immutable fields (see `signing`)
+    pub signature: Option<Vec<u8>>,
+    /// Relays must follow `fixed_route` instead of their routing tables
+    pub source_routed: bool,
+    /// Sender-chosen path (source through destination, source-routed only)
+    pub fixed_route: Vec<NodeId>,
+    /// Payload travels zstd-compressed (see `compression`)
+    ///
+    /// The payload held in memory is always uncompressed; it is compressed
+    /// when the packet is serialized and restored when it is decoded.
+    pub compressed: bool,
+    /// zstd level used when serializing a compressed payload (0: zstd default)
+    #[serde(skip)]
+    pub compression_level: i32,
+    /// CRC32 of the encoded fields before it (version through compressed)
+    ///
+    /// Set when the packet is serialized and checked when it is decoded;
+    /// zero on packets built locally. Must stay the last field.
+    pub checksum: u32,
 }
 
 /// Packet metadata (optional, protocol-specific)
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct PacketMetadata {
     /// Protocol identifier (e.g., "bitcoin-p2p", "commons-governance", "stratum-v2", "mesh-packet")
     pub protocol: Option<String>,
     /// Additional protocol-specific fields
     pub fields: std::collections::HashMap<String, String>,
+    /// Sender route preferences (honored at the origin and by relays)
+    pub route_constraints: Option<RouteConstraints>,
+    /// Position of this fragment (`PacketType::Fragment` only)
+    pub fragment: Option<FragmentHeader>,
+}
+
+impl PacketMetadata {
+    /// Check the fields against the schema of the packet's protocol
+    ///
+    /// Protocols without a schema in `registry` are accepted as-is.
+    pub fn validate(&self, registry: &SchemaRegistry) -> Result<(), MeshError> {
+        let Some(protocol) = self.protocol.as_deref() else {
+            return Ok(());
+        };
+        match registry.get(protocol) {
+            Some(schema) => schema.validate(protocol, &self.fields),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Identifies one fragment of a serialized packet
+///
+/// Fragments travel a single link: the payload is a slice of the original
+/// packet's wire bytes, reassembled by the next hop before it is handled
+/// (see `reassembly`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FragmentHeader {
+    /// Sender-chosen ID shared by all fragments of one packet
+    pub stream_id: u64,
+    /// Zero-based fragment index
+    pub fragment_index: u32,
+    /// Number of fragments in the stream
+    pub total_fragments: u32,
+}
+
+/// What route selection should optimize for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum CostOrLatency {
+    /// Prefer the cheapest route
+    #[default]
+    Cost,
+    /// Prefer the route with the fewest hops
+    Latency,
+}
+
+/// Sender-side route preference hints
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RouteConstraints {
+    /// Relays the packet must not traverse
+    pub avoid: Vec<NodeId>,
+    /// Maximum total hops from source to destination
+    pub max_hops: Option<u8>,
+    /// Optimization goal among compliant routes
+    pub optimize: CostOrLatency,
+}
+
+impl RouteConstraints {
+    /// Check if a remaining path satisfies the constraints
+    ///
+    /// `path` lists the nodes still to be traversed (next hop through
+    /// destination), `hops_taken` is the number of hops already travelled.
+    /// The destination itself is never treated as an avoided relay.
+    pub fn allows(&self, path: &[NodeId], destination: &NodeId, hops_taken: usize) -> bool {
+        if let Some(max_hops) = self.max_hops {
+            if hops_taken + path.len() > max_hops as usize {
+                return false;
+            }
+        }
+
+        !path
+            .iter()
+            .any(|node| node != destination && self.avoid.contains(node))
+    }
 }
 
 impl MeshPacket {
@@ -82,34 +212,55 @@ impl MeshPacket {
             packet_type,
             source,
             destination,
-            route: vec![source], // Initial route starts with source
+            route: vec![source, destination], // Relays are inserted between source and destination
             sequence: 0, // Will be set by sender
+            ttl: DEFAULT_TTL,
             timestamp: now,
             payment_proof: None,
             payload,
             metadata: None,
+            signature: None,
+            source_routed: false,
+            fixed_route: Vec::new(),
+            compressed: false,
+            compression_level: 0,
+            checksum: 0,
         }
     }
 
-    /// Create a paid mesh packet (with payment proof)
-    pub fn new_paid(
-        source: NodeId,
-        destination: NodeId,
-        payload: Vec<u8>,
-        payment_proof: PaymentProof,
-    ) -> Self {
-        let mut packet = Self::new(PacketType::Paid, source, destination, payload);
-        packet.payment_proof = Some(payment_proof);
-        packet
-    }
-
     /// Validate packet structure
     pub fn validate(&self) -> Result<(), String> {
+        self.validate_with_max_ttl(DEFAULT_MAX_TTL)
+    }
+
+    /// Validate packet structure, accepting hop budgets up to `max_ttl`
+    ///
+    /// A non-zero checksum must match the packet's fields.
+    pub fn validate_with_max_ttl(&self, max_ttl: u8) -> Result<(), String> {
+        self.validate_structure(max_ttl)?;
+
+        if self.checksum != 0 && self.checksum != self.compute_checksum() {
+            return Err("Packet checksum mismatch".to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Validate everything but the checksum (see `validate_with_max_ttl`)
+    pub(crate) fn validate_structure(&self, max_ttl: u8) -> Result<(), String> {
         // Check version
         if self.version != MESH_PACKET_VERSION {
             return Err(format!("Invalid packet version: {}", self.version));
         }
 
+        // Check hop budget
+        if self.ttl == 0 {
+            return Err("Packet TTL is zero".to_string());
+        }
+        if self.ttl > max_ttl {
+            return Err(format!("Packet TTL exceeds maximum: {} > {}", self.ttl, max_ttl));
+        }
+
         // Check size
         let size = self.serialized_size();
         if size > MAX_PACKET_SIZE {
@@ -134,24 +285,59 @@ impl MeshPacket {
             return Err("Route must end with destination node".to_string());
         }
 
+        // Check the sender-chosen path
+        if self.source_routed {
+            if self.fixed_route.len() < 2 {
+                return Err("Source route must contain at least source and destination".to_string());
+            }
+            if self.fixed_route[0] != self.source || self.fixed_route.last() != Some(&self.destination) {
+                return Err("Source route must run from source to destination".to_string());
+            }
+            let unique: std::collections::HashSet<&NodeId> = self.fixed_route.iter().collect();
+            if unique.len() != self.fixed_route.len() {
+                return Err("Source route visits a node twice".to_string());
+            }
+        } else if !self.fixed_route.is_empty() {
+            return Err("Fixed route set on a packet that is not source-routed".to_string());
+        }
+
         // Check payment proof for paid packets
-        if self.packet_type == PacketType::Paid && self.payment_proof.is_none() {
+        if self.is_paid() && self.payment_proof.is_none() {
             return Err("Paid packets require payment proof".to_string());
         }
 
+        // Check protocol metadata fields
+        if let Some(ref metadata) = self.metadata {
+            metadata
+                .validate(SchemaRegistry::builtin())
+                .map_err(|e| match e {
+                    MeshError::InvalidPacket(reason) => reason,
+                    other => other.to_string(),
+                })?;
+        }
+
         Ok(())
     }
 
+    /// CRC32 of the encoded fields before `checksum`
+    pub fn compute_checksum(&self) -> u32 {
+        match bincode::serialize(self) {
+            Ok(body) => checksum_body(&body),
+            Err(_) => 0,
+        }
+    }
+
     /// Calculate serialized size
     pub fn serialized_size(&self) -> usize {
-        // Header: version (1) + packet_type (1) + source (32) + destination (32) + sequence (8) + timestamp (8) = 82 bytes
+        // Header: version (1) + packet_type (1) + source (32) + destination (32) + sequence (8) + ttl (1) + timestamp (8) + compressed (1) + checksum (4) = 88 bytes
         // Route: route.len() * 32
         // Payment proof: variable (if present)
         // Payload: payload.len()
         // Metadata: variable (if present)
         
-        let mut size = 82;
+        let mut size = 88;
         size += self.route.len() * 32;
+        size += 1 + self.fixed_route.len() * 32;
         
         if let Some(ref proof) = self.payment_proof {
             // Estimate payment proof size (Lightning: ~500 bytes, CTV: ~200 bytes)
@@ -165,23 +351,102 @@ impl MeshPacket {
             size += 100; // Conservative estimate
         }
         
+        if let Some(ref signature) = self.signature {
+            size += signature.len();
+        }
+        
         size
     }
 
+    /// Check if this is a paid packet (cleartext or encrypted)
+    pub fn is_paid(&self) -> bool {
+        matches!(self.packet_type, PacketType::Paid | PacketType::PaidEncrypted)
+    }
+
+    /// Get the fragment header (fragments only)
+    pub fn fragment_header(&self) -> Option<FragmentHeader> {
+        self.metadata.as_ref()?.fragment
+    }
+
+    /// Get sender route constraints (if any)
+    pub fn route_constraints(&self) -> Option<&RouteConstraints> {
+        self.metadata.as_ref()?.route_constraints.as_ref()
+    }
+
+    /// Proof hash of the prepaid balance the packet is paid from (if any)
+    ///
+    /// A malformed reference counts as none.
+    pub fn payment_session(&self) -> Option<[u8; 32]> {
+        let field = self.metadata.as_ref()?.fields.get(PAYMENT_SESSION_FIELD)?;
+        hex::decode(field).ok()?.try_into().ok()
+    }
+
+    /// Check if the destination must ack this packet (paid, or requested
+    /// by the sender)
+    pub fn wants_ack(&self) -> bool {
+        self.is_paid()
+            || self
+                .metadata
+                .as_ref()
+                .and_then(|metadata| metadata.fields.get(ACK_REQUESTED_FIELD))
+                .map(|ack| ack == "1")
+                .unwrap_or(false)
+    }
+
+    /// Check if the sender marked this packet as bulk priority
+    pub fn is_bulk(&self) -> bool {
+        self.metadata
+            .as_ref()
+            .and_then(|metadata| metadata.fields.get("priority"))
+            .map(|priority| priority == "bulk")
+            .unwrap_or(false)
+    }
+
+    /// Position this node must hold in the fixed route
+    ///
+    /// The source sends from index 0; a relay must follow the node that
+    /// handed it the packet. `None` if the packet is not source-routed or
+    /// the previous hop is not on the fixed route.
+    pub fn expected_source_route_index(&self, my_node_id: &NodeId) -> Option<usize> {
+        if !self.source_routed {
+            return None;
+        }
+        if self.source == *my_node_id {
+            return Some(0);
+        }
+        let previous = self.previous_hop()?;
+        let index = self.fixed_route.iter().position(|node| *node == previous)?;
+        Some(index + 1)
+    }
+
+    /// Number of hops already travelled (relays recorded in the route)
+    pub fn hops_taken(&self) -> usize {
+        self.route.len().saturating_sub(2)
+    }
+
+    /// Node that handed this packet to us (last relay, or the source)
+    pub fn previous_hop(&self) -> Option<NodeId> {
+        let index = self.route.len().checked_sub(2)?;
+        self.route.get(index).copied()
+    }
+
     /// Check if packet is for this node
     pub fn is_for_me(&self, my_node_id: &NodeId) -> bool {
         self.destination == *my_node_id
     }
 
     /// Check if packet should be forwarded
+    ///
+    /// Relays record themselves in the route as they forward, so finding
+    /// this node in the route already means the packet is looping.
     pub fn should_forward(&self, my_node_id: &NodeId) -> bool {
         // If destination is this node, don't forward
         if self.is_for_me(my_node_id) {
             return false;
         }
 
-        // If this node is in the route, forward
-        self.route.contains(my_node_id)
+        // Forward unless we already relayed (or originated) this packet
+        !self.route.contains(my_node_id)
     }
 
     /// Get next hop in route
@@ -208,3 +473,119 @@ impl MeshPacket {
     }
 }
 
+/// CRC32 of an encoded packet body, excluding its trailing checksum
+pub(crate) fn checksum_body(body: &[u8]) -> u32 {
+    crc32fast::hash(&body[..body.len().saturating_sub(CHECKSUM_LEN)])
+}
+
+
+/// Builds a mesh packet from optional parts
+///
+/// `MeshPacketBuilder::new(source, destination)` starts from
+/// `MeshPacket::new` (current version, route from source to destination,
+/// default hop budget, empty `BitcoinP2P` packet); `build` validates the
+/// finished packet, so callers never hand out a packet that
+/// `MeshPacket::validate` would reject.
+///
+/// ```ignore
+/// let packet = MeshPacketBuilder::new(source, destination)
+///     .packet_type(PacketType::Paid)
+///     .payload(bytes)
+///     .payment_proof(proof)
+///     .ttl(32)
+///     .build()?;
+/// ```
+pub struct MeshPacketBuilder {
+    packet: MeshPacket,
+}
+
+impl MeshPacketBuilder {
+    /// Start a packet from `source` to `destination`
+    pub fn new(source: NodeId, destination: NodeId) -> Self {
+        Self {
+            packet: MeshPacket::new(PacketType::BitcoinP2P, source, destination, Vec::new()),
+        }
+    }
+
+    /// Set the packet type
+    pub fn packet_type(mut self, packet_type: PacketType) -> Self {
+        self.packet.packet_type = packet_type;
+        self
+    }
+
+    /// Set the payload
+    pub fn payload(mut self, payload: Vec<u8>) -> Self {
+        self.packet.payload = payload;
+        self
+    }
+
+    /// Attach a payment proof
+    pub fn payment_proof(mut self, payment_proof: PaymentProof) -> Self {
+        self.packet.payment_proof = Some(payment_proof);
+        self
+    }
+
+    /// Set the sequence number
+    pub fn sequence(mut self, sequence: u64) -> Self {
+        self.packet.sequence = sequence;
+        self
+    }
+
+    /// Set the hop budget
+    pub fn ttl(mut self, ttl: u8) -> Self {
+        self.packet.ttl = ttl;
+        self
+    }
+
+    /// Attach protocol metadata
+    pub fn metadata(mut self, metadata: PacketMetadata) -> Self {
+        self.packet.metadata = Some(metadata);
+        self
+    }
+
+    /// Pay from the prepaid balance opened by the proof with `proof_hash`
+    /// (after `metadata`, which replaces the metadata)
+    pub fn payment_session(mut self, proof_hash: [u8; 32]) -> Self {
+        self.packet
+            .metadata
+            .get_or_insert_with(PacketMetadata::default)
+            .fields
+            .insert(PAYMENT_SESSION_FIELD.to_string(), hex::encode(proof_hash));
+        self
+    }
+
+    /// Ask the destination to ack the packet (paid packets always are)
+    /// (after `metadata`, which replaces the metadata)
+    pub fn ack_requested(mut self) -> Self {
+        self.packet
+            .metadata
+            .get_or_insert_with(PacketMetadata::default)
+            .fields
+            .insert(ACK_REQUESTED_FIELD.to_string(), "1".to_string());
+        self
+    }
+
+    /// Pin the packet to `route` (source through destination)
+    ///
+    /// Relays forward strictly along the route and never consult their
+    /// routing tables.
+    pub fn source_route(mut self, route: Vec<NodeId>) -> Self {
+        self.packet.source_routed = true;
+        self.packet.fixed_route = route;
+        self
+    }
+
+    /// Finish and validate the packet
+    pub fn build(self) -> Result<MeshPacket, MeshError> {
+        self.build_with_max_ttl(DEFAULT_MAX_TTL)
+    }
+
+    /// Finish and validate the packet, accepting hop budgets up to `max_ttl`
+    /// (see `MeshPacket::validate_with_max_ttl`)
+    pub fn build_with_max_ttl(self, max_ttl: u8) -> Result<MeshPacket, MeshError> {
+        self.packet
+            .validate_with_max_ttl(max_ttl)
+            .map_err(MeshError::InvalidPacket)?;
+        Ok(self.packet)
+    }
+}
@@ -1,10 +1,35 @@
 //! Payment proof structures for mesh routing
 //!
-//! Defines payment proof types (Lightning and CTV) for payment-gated mesh routing.
+//! Defines payment proof types (Lightning, Taproot and CTV) for payment-gated mesh routing,
+//! and BIP-352 silent payment addresses for collecting routing fees on-chain.
 
+use crate::error::MeshError;
+use crate::routing::NodeId;
+use bech32::{FromBase32, ToBase32, Variant};
+use secp256k1::{PublicKey, Scalar, Secp256k1, SecretKey, XOnlyPublicKey};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::time::{SystemTime, UNIX_EPOCH};
 
+/// How long a BOLT12 proof is accepted after payment
+///
+/// Offers are reusable and carry no per-payment expiry, so proofs age out
+/// instead.
+pub const BOLT12_PROOF_MAX_AGE_SECONDS: u64 = 60 * 60; // 1 hour
+
+/// Default time a keysend proof is accepted after payment
+///
+/// Keysend payments have no invoice and so no expiry of their own.
+pub const DEFAULT_KEYSEND_MAX_AGE_SECONDS: u64 = 60 * 60; // 1 hour
+
+/// How long a Taproot script proof is accepted after it was made
+///
+/// The output stays on-chain, so proofs age out instead of expiring.
+pub const TAPROOT_PROOF_MAX_AGE_SECONDS: u64 = 24 * 60 * 60; // 24 hours
+
+/// TLV record type carrying the preimage of a keysend payment
+pub const KEYSEND_PREIMAGE_TLV_TYPE: u64 = 5_482_373_484;
+
 /// Payment proof for mesh routing
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum PaymentProof {
@@ -21,6 +46,85 @@ pub enum PaymentProof {
         /// Invoice expiry timestamp
         expires_at: u64,
     },
+    /// Lightning payment proof for a BOLT12 offer
+    LightningBolt12 {
+        /// Offer the payment was made against (bech32 `lno1...`)
+        offer: String,
+        /// Signed invoice request sent to the offer's issuer (TLV bytes)
+        invoice_request: Vec<u8>,
+        /// Payment preimage (32 bytes)
+        preimage: [u8; 32],
+        /// Amount in millisatoshis
+        amount_msats: u64,
+        /// Payment timestamp
+        timestamp: u64,
+    },
+    /// Spontaneous Lightning payment (keysend), made without an invoice
+    ///
+    /// The sender picks the preimage; the receiver only needs its hash.
+    Keysend {
+        /// SHA256 of the preimage
+        payment_hash: [u8; 32],
+        /// Payment preimage chosen by the sender (32 bytes)
+        preimage: [u8; 32],
+        /// Amount in millisatoshis
+        amount_msats: u64,
+        /// Payment timestamp
+        timestamp: u64,
+        /// Custom TLV records sent with the payment (type, value)
+        custom_tlv: Vec<(u64, Vec<u8>)>,
+        /// Lightning node public key the payment was sent to (33 bytes,
+        /// compressed)
+        destination_pubkey: Vec<u8>,
+    },
+    /// Routing fee locked in a Lightning HTLC, claimed after delivery
+    ///
+    /// The source holds the preimage and reveals it to the relays once the
+    /// destination acks the packet (see `verifier::HtlcVerifier`).
+    HtlcEscrow {
+        /// Hash the HTLC is locked to
+        payment_hash: [u8; 32],
+        /// Unix time at which the HTLC times out and refunds the source
+        htlc_expiry: u64,
+        /// Amount locked in millisatoshis
+        amount_msats: u64,
+    },
+    /// On-chain Taproot output whose script path the sender can satisfy
+    ///
+    /// Gates access on conditions beyond payment (see `taproot`).
+    TaprootScript {
+        /// BIP-341 control block (leaf version and parity, internal key,
+        /// Merkle path)
+        control_block: Vec<u8>,
+        /// Tapscript leaf committed to by the output
+        script: Vec<u8>,
+        /// Witness stack satisfying the script (last item on top)
+        witness: Vec<Vec<u8>>,
+        /// Transaction holding the output
+        txid: [u8; 32],
+        /// Output index
+        vout: u32,
+        /// Amount claimed in satoshis (at most the output's value)
+        amount_sats: u64,
+        /// Proof timestamp
+        timestamp: u64,
+    },
+    /// Proof for a settlement scheme verified by a registered backend
+    ///
+    /// `blob` goes to the `proof_backend::ProofBackend` whose kind is
+    /// `kind`; without one the proof fails.
+    Custom {
+        /// Backend kind (e.g. "ecash")
+        kind: String,
+        /// Proof in the backend's own encoding
+        blob: Vec<u8>,
+        /// Amount claimed in satoshis
+        amount_sats: u64,
+        /// Payment timestamp
+        timestamp: u64,
+        /// Unix time after which the proof is no longer accepted
+        expires_at: u64,
+    },
     /// CTV instant settlement proof (future, when CTV is activated)
     #[cfg(feature = "ctv")]
     InstantSettlement {
@@ -28,8 +132,15 @@ pub enum PaymentProof {
         covenant_proof: Vec<u8>, // Serialized CovenantProof from bllvm-node
         /// Output index in the covenant transaction
         output_index: u32,
-        /// Merkle proof (if needed for verification)
+        /// Merkle branch of the covenant transaction in `block_hash`,
+        /// leaf first
         merkle_proof: Vec<[u8; 32]>,
+        /// Block the Merkle branch leads to
+        #[serde(default)]
+        block_hash: Option<[u8; 32]>,
+        /// Position of the transaction in that block
+        #[serde(default)]
+        tx_index: u32,
         /// Amount in satoshis
         amount_sats: u64,
         /// Proof timestamp
@@ -42,22 +153,42 @@ impl PaymentProof {
     pub fn amount_sats(&self) -> u64 {
         match self {
             PaymentProof::Lightning { amount_msats, .. } => amount_msats / 1000,
+            PaymentProof::LightningBolt12 { amount_msats, .. } => amount_msats / 1000,
+            PaymentProof::Keysend { amount_msats, .. } => amount_msats / 1000,
+            PaymentProof::HtlcEscrow { amount_msats, .. } => amount_msats / 1000,
+            PaymentProof::TaprootScript { amount_sats, .. } => *amount_sats,
+            PaymentProof::Custom { amount_sats, .. } => *amount_sats,
             #[cfg(feature = "ctv")]
             PaymentProof::InstantSettlement { amount_sats, .. } => *amount_sats,
         }
     }
 
     /// Get payment timestamp
+    ///
+    /// 0 for HTLC escrows, which are not paid until claimed.
     pub fn timestamp(&self) -> u64 {
         match self {
             PaymentProof::Lightning { timestamp, .. } => *timestamp,
+            PaymentProof::LightningBolt12 { timestamp, .. } => *timestamp,
+            PaymentProof::Keysend { timestamp, .. } => *timestamp,
+            PaymentProof::HtlcEscrow { .. } => 0,
+            PaymentProof::TaprootScript { timestamp, .. } => *timestamp,
+            PaymentProof::Custom { timestamp, .. } => *timestamp,
             #[cfg(feature = "ctv")]
             PaymentProof::InstantSettlement { timestamp, .. } => *timestamp,
         }
     }
 
     /// Check if payment proof is expired
+    ///
+    /// Keysend proofs age out after `DEFAULT_KEYSEND_MAX_AGE_SECONDS`.
     pub fn is_expired(&self) -> bool {
+        self.is_expired_with_keysend_max_age(DEFAULT_KEYSEND_MAX_AGE_SECONDS)
+    }
+
+    /// Check if payment proof is expired, with keysend proofs accepted for
+    /// `keysend_max_age_seconds` after payment
+    pub fn is_expired_with_keysend_max_age(&self, keysend_max_age_seconds: u64) -> bool {
         let now = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .unwrap()
@@ -65,6 +196,17 @@ impl PaymentProof {
 
         match self {
             PaymentProof::Lightning { expires_at, .. } => now > *expires_at,
+            PaymentProof::LightningBolt12 { timestamp, .. } => {
+                now > timestamp.saturating_add(BOLT12_PROOF_MAX_AGE_SECONDS)
+            }
+            PaymentProof::Keysend { timestamp, .. } => {
+                now > timestamp.saturating_add(keysend_max_age_seconds)
+            }
+            PaymentProof::HtlcEscrow { htlc_expiry, .. } => now >= *htlc_expiry,
+            PaymentProof::TaprootScript { timestamp, .. } => {
+                now > timestamp.saturating_add(TAPROOT_PROOF_MAX_AGE_SECONDS)
+            }
+            PaymentProof::Custom { expires_at, .. } => now > *expires_at,
             #[cfg(feature = "ctv")]
             PaymentProof::InstantSettlement { timestamp, .. } => {
                 // CTV proofs don't expire (they're on-chain commitments)
@@ -76,11 +218,31 @@ impl PaymentProof {
     }
 
     /// Calculate hash of payment proof (for replay prevention)
+    ///
+    /// BOLT12, keysend, escrow, Taproot and custom proofs hash only the
+    /// payment they prove (offer, invoice request, preimage / payment hash,
+    /// preimage / payment hash / outpoint / kind and blob), so restamping the
+    /// timestamp, amount, expiry, TLV records or witness of a used proof does
+    /// not make it look new.
     pub fn hash(&self) -> [u8; 32] {
-        use sha2::{Digest, Sha256};
-        
-        let serialized = bincode::serialize(self)
-            .expect("Payment proof should be serializable");
+        let serialized = match self {
+            PaymentProof::LightningBolt12 {
+                offer,
+                invoice_request,
+                preimage,
+                ..
+            } => bincode::serialize(&("bolt12", offer, invoice_request, preimage)),
+            PaymentProof::Keysend {
+                payment_hash,
+                preimage,
+                ..
+            } => bincode::serialize(&("keysend", payment_hash, preimage)),
+            PaymentProof::HtlcEscrow { payment_hash, .. } => bincode::serialize(&("htlc", payment_hash)),
+            PaymentProof::TaprootScript { txid, vout, .. } => bincode::serialize(&("taproot", txid, vout)),
+            PaymentProof::Custom { kind, blob, .. } => bincode::serialize(&("custom", kind, blob)),
+            _ => bincode::serialize(self),
+        }
+        .expect("Payment proof should be serializable");
         let hash = Sha256::digest(&serialized);
         let mut result = [0u8; 32];
         result.copy_from_slice(&hash);
@@ -127,3 +289,205 @@ impl VerificationResult {
     }
 }
 
+
+/// Human-readable part of mainnet silent payment addresses
+pub const SILENT_PAYMENT_HRP: &str = "sp";
+
+/// Silent payment address version this module encodes and accepts
+const SILENT_PAYMENT_VERSION: u8 = 0;
+
+/// Outputs per transaction checked when verifying a silent payment
+///
+/// BIP-352 numbers the outputs paying one address `k = 0, 1, ...`; a fee
+/// payment rarely needs more than one.
+pub const MAX_SILENT_PAYMENT_OUTPUTS: u32 = 16;
+
+/// BIP-352 silent payment address
+///
+/// Payers derive a fresh taproot output for every payment from the
+/// address and their own input keys, so fee payments to one address cannot
+/// be linked on-chain. Only the holder of the scan key can find them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SilentPaymentAddress {
+    /// Scan public key (`B_scan`)
+    pub scan_pubkey: PublicKey,
+    /// Spend public key (`B_spend`, or `B_m` for a labelled address)
+    pub spend_pubkey: PublicKey,
+}
+
+impl SilentPaymentAddress {
+    /// Address of a scan and spend key pair
+    pub fn new(scan_key: &SecretKey, spend_key: &SecretKey) -> Self {
+        let secp = Secp256k1::new();
+        Self {
+            scan_pubkey: PublicKey::from_secret_key(&secp, scan_key),
+            spend_pubkey: PublicKey::from_secret_key(&secp, spend_key),
+        }
+    }
+
+    /// Labelled address: `B_m = B_spend + hash_BIP0352/Label(b_scan || m)·G`
+    ///
+    /// Addresses with different labels share the scan key, so one scan
+    /// finds payments to all of them.
+    pub fn with_label(scan_key: &SecretKey, spend_key: &SecretKey, label: u32) -> Result<Self, MeshError> {
+        let unlabelled = Self::new(scan_key, spend_key);
+        Ok(Self {
+            scan_pubkey: unlabelled.scan_pubkey,
+            spend_pubkey: label_spend_key(scan_key, &unlabelled.spend_pubkey, label)?,
+        })
+    }
+
+    /// Bech32m encoding (`sp1q...`)
+    pub fn encode(&self) -> String {
+        let mut data = vec![bech32::u5::try_from_u8(SILENT_PAYMENT_VERSION).expect("version fits in 5 bits")];
+        let mut keys = self.scan_pubkey.serialize().to_vec();
+        keys.extend_from_slice(&self.spend_pubkey.serialize());
+        data.extend(keys.to_base32());
+        bech32::encode(SILENT_PAYMENT_HRP, data, Variant::Bech32m).expect("valid silent payment address")
+    }
+
+    /// Parse a bech32m `sp1q...` address
+    pub fn decode(address: &str) -> Result<Self, MeshError> {
+        let invalid = |reason: &str| MeshError::PaymentError(format!("Invalid silent payment address: {}", reason));
+        let (hrp, data, variant) = bech32::decode(address).map_err(|e| invalid(&e.to_string()))?;
+        if hrp != SILENT_PAYMENT_HRP || variant != Variant::Bech32m {
+            return Err(invalid("not a bech32m sp address"));
+        }
+        let (version, keys) = data.split_first().ok_or_else(|| invalid("missing version"))?;
+        if version.to_u8() != SILENT_PAYMENT_VERSION {
+            return Err(invalid("unsupported version"));
+        }
+        let keys = Vec::<u8>::from_base32(keys).map_err(|e| invalid(&e.to_string()))?;
+        if keys.len() != 66 {
+            return Err(invalid("expected two 33-byte keys"));
+        }
+        let key = |bytes: &[u8]| PublicKey::from_slice(bytes).map_err(|e| invalid(&e.to_string()));
+        Ok(Self {
+            scan_pubkey: key(&keys[..33])?,
+            spend_pubkey: key(&keys[33..])?,
+        })
+    }
+
+    /// Taproot output key of the `k`-th output paying this address (payer side)
+    ///
+    /// `input_key_sum` is the sum of the secret keys of the paying
+    /// transaction's eligible inputs and `smallest_outpoint` the
+    /// lexicographically smallest outpoint it spends (txid || vout, 36 bytes).
+    pub fn derive_output(
+        &self,
+        input_key_sum: &SecretKey,
+        smallest_outpoint: &[u8; 36],
+        k: u32,
+    ) -> Result<XOnlyPublicKey, MeshError> {
+        let secp = Secp256k1::new();
+        let input_pubkey_sum = PublicKey::from_secret_key(&secp, input_key_sum);
+        let input_hash = scalar(tagged_hash(
+            "BIP0352/Inputs",
+            &[&smallest_outpoint[..], &input_pubkey_sum.serialize()[..]],
+        ))?;
+        let tweak = input_key_sum.mul_tweak(&input_hash).map_err(crypto_error)?;
+        let shared_secret = self.scan_pubkey.mul_tweak(&secp, &Scalar::from(tweak)).map_err(crypto_error)?;
+        output_key(&shared_secret, &self.spend_pubkey, k)
+    }
+}
+
+/// A taproot output claimed to pay a silent payment address
+#[derive(Debug, Clone)]
+pub struct SilentPaymentOutput {
+    /// Sum of the paying transaction's eligible input public keys (`A`)
+    pub input_pubkey_sum: PublicKey,
+    /// Lexicographically smallest outpoint the transaction spends (txid || vout, 36 bytes)
+    pub smallest_outpoint: [u8; 36],
+    /// Taproot output key of the payment
+    pub output_key: XOnlyPublicKey,
+    /// Output amount in satoshis
+    pub amount_sats: u64,
+    /// Payment timestamp
+    pub timestamp: u64,
+}
+
+impl SilentPaymentOutput {
+    /// Whether this output pays the address of `scan_key` and `spend_pubkey`
+    ///
+    /// Computes the shared secret `input_hash·b_scan·A` and checks the first
+    /// `MAX_SILENT_PAYMENT_OUTPUTS` output keys derived from it.
+    pub fn pays(&self, scan_key: &SecretKey, spend_pubkey: &PublicKey) -> Result<bool, MeshError> {
+        let secp = Secp256k1::new();
+        let input_hash = scalar(tagged_hash(
+            "BIP0352/Inputs",
+            &[&self.smallest_outpoint[..], &self.input_pubkey_sum.serialize()[..]],
+        ))?;
+        let tweak = scan_key.mul_tweak(&input_hash).map_err(crypto_error)?;
+        let shared_secret = self.input_pubkey_sum.mul_tweak(&secp, &Scalar::from(tweak)).map_err(crypto_error)?;
+        for k in 0..MAX_SILENT_PAYMENT_OUTPUTS {
+            if output_key(&shared_secret, spend_pubkey, k)? == self.output_key {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+/// Silent payment addresses unique to a node
+pub trait ToSilentPaymentAddress {
+    /// Labelled silent payment address for this node (bech32m `sp1q...`)
+    ///
+    /// A forwarding node advertises a different address to every route
+    /// peer, all found with one scan key.
+    fn to_silent_payment_address(&self, scan_key: &SecretKey, spend_key: &SecretKey) -> String;
+}
+
+impl ToSilentPaymentAddress for NodeId {
+    fn to_silent_payment_address(&self, scan_key: &SecretKey, spend_key: &SecretKey) -> String {
+        SilentPaymentAddress::with_label(scan_key, spend_key, silent_payment_label(self))
+            .expect("label tweak of a valid key")
+            .encode()
+    }
+}
+
+/// BIP-352 label (`m`) of a node's address
+///
+/// Never 0, which BIP-352 reserves for change.
+pub fn silent_payment_label(node_id: &NodeId) -> u32 {
+    let hash = Sha256::digest(node_id);
+    u32::from_be_bytes([hash[0], hash[1], hash[2], hash[3]]).max(1)
+}
+
+/// Spend key of a labelled address: `B_spend + hash_BIP0352/Label(b_scan || m)·G`
+pub fn label_spend_key(scan_key: &SecretKey, spend_pubkey: &PublicKey, label: u32) -> Result<PublicKey, MeshError> {
+    let tweak = scalar(tagged_hash(
+        "BIP0352/Label",
+        &[&scan_key.secret_bytes()[..], &label.to_be_bytes()[..]],
+    ))?;
+    spend_pubkey.add_exp_tweak(&Secp256k1::new(), &tweak).map_err(crypto_error)
+}
+
+/// `P_k = B_spend + hash_BIP0352/SharedSecret(ecdh_shared_secret || k)·G`
+fn output_key(shared_secret: &PublicKey, spend_pubkey: &PublicKey, k: u32) -> Result<XOnlyPublicKey, MeshError> {
+    let t_k = scalar(tagged_hash(
+        "BIP0352/SharedSecret",
+        &[&shared_secret.serialize()[..], &k.to_be_bytes()[..]],
+    ))?;
+    let output = spend_pubkey.add_exp_tweak(&Secp256k1::new(), &t_k).map_err(crypto_error)?;
+    Ok(output.x_only_public_key().0)
+}
+
+/// BIP-340 tagged hash: `SHA256(SHA256(tag) || SHA256(tag) || data)`
+pub(crate) fn tagged_hash(tag: &str, data: &[&[u8]]) -> [u8; 32] {
+    let tag_hash = Sha256::digest(tag.as_bytes());
+    let mut hasher = Sha256::new();
+    hasher.update(tag_hash);
+    hasher.update(tag_hash);
+    for part in data {
+        hasher.update(part);
+    }
+    hasher.finalize().into()
+}
+
+fn scalar(bytes: [u8; 32]) -> Result<Scalar, MeshError> {
+    Scalar::from_be_bytes(bytes).map_err(|_| MeshError::PaymentError("Tweak out of range".to_string()))
+}
+
+fn crypto_error(e: secp256k1::Error) -> MeshError {
+    MeshError::PaymentError(format!("Silent payment derivation failed: {}", e))
+}
@@ -0,0 +1,215 @@
+//! Bounded priority queue for outgoing packets
+//!
+//! Packets are queued in one of three classes, each a bounded channel
+//! (`mesh.queue.high_depth`, `mesh.queue.medium_depth`,
+//! `mesh.queue.low_depth`). `pop` always takes from the highest non-empty
+//! class, so a flood of unclassified traffic cannot delay Bitcoin P2P or
+//! governance messages queued behind it. A full class refuses new packets
+//! with `CapacityExceeded` instead of growing without bound. Queued packets
+//! are routed by `mesh.queue.workers` concurrent workers, so the class only
+//! decides which packet starts next, not how long it waits for slow ones.
+
+use crate::error::MeshError;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{mpsc, Mutex};
+
+/// Priority class of a queued packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    /// Bitcoin P2P, Commons governance and mesh control traffic
+    High,
+    /// Stratum V2 and paid packets carrying a payment proof
+    Medium,
+    /// Unclassified traffic
+    Low,
+}
+
+impl Priority {
+    /// All classes, highest first
+    pub const ALL: [Priority; 3] = [Priority::High, Priority::Medium, Priority::Low];
+
+    /// Name used in config keys and metrics labels
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Priority::High => "high",
+            Priority::Medium => "medium",
+            Priority::Low => "low",
+        }
+    }
+
+    fn index(&self) -> usize {
+        match self {
+            Priority::High => 0,
+            Priority::Medium => 1,
+            Priority::Low => 2,
+        }
+    }
+}
+
+/// Queue depth of each priority class
+#[derive(Debug, Clone)]
+pub struct QueueConfig {
+    /// Packets the High class holds before refusing more
+    pub high_depth: usize,
+    /// Packets the Medium class holds before refusing more
+    pub medium_depth: usize,
+    /// Packets the Low class holds before refusing more
+    pub low_depth: usize,
+    /// Packets routed concurrently
+    pub workers: usize,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        Self {
+            high_depth: 1024,
+            medium_depth: 512,
+            low_depth: 256,
+            workers: 8,
+        }
+    }
+}
+
+impl QueueConfig {
+    /// Load from module context config keys
+    pub fn from_context(ctx: &bllvm_node::module::traits::ModuleContext) -> Self {
+        let defaults = Self::default();
+        let depth = |priority: Priority, default: usize| {
+            ctx.get_config_or(&format!("mesh.queue.{}_depth", priority.as_str()), &default.to_string())
+                .parse::<usize>()
+                .unwrap_or(default)
+        };
+        Self {
+            high_depth: depth(Priority::High, defaults.high_depth),
+            medium_depth: depth(Priority::Medium, defaults.medium_depth),
+            low_depth: depth(Priority::Low, defaults.low_depth),
+            workers: ctx
+                .get_config_or("mesh.queue.workers", &defaults.workers.to_string())
+                .parse::<usize>()
+                .unwrap_or(defaults.workers)
+                .max(1),
+        }
+    }
+
+    /// Depth of one class
+    pub fn depth(&self, priority: Priority) -> usize {
+        match priority {
+            Priority::High => self.high_depth,
+            Priority::Medium => self.medium_depth,
+            Priority::Low => self.low_depth,
+        }
+    }
+}
+
+/// Three bounded channels drained highest class first
+pub struct PriorityQueue<T> {
+    /// Senders by class index (High, Medium, Low)
+    senders: [mpsc::Sender<T>; 3],
+    /// Receivers by class index; one `pop` at a time
+    receivers: Mutex<[mpsc::Receiver<T>; 3]>,
+}
+
+impl<T> PriorityQueue<T> {
+    /// Create a queue with the configured class depths (at least 1 each)
+    pub fn new(config: &QueueConfig) -> Self {
+        let [high, medium, low] = Priority::ALL.map(|priority| mpsc::channel(config.depth(priority).max(1)));
+        Self {
+            senders: [high.0, medium.0, low.0],
+            receivers: Mutex::new([high.1, medium.1, low.1]),
+        }
+    }
+
+    /// Queue an item, failing with `CapacityExceeded` if its class is full
+    pub fn push(&self, item: T, priority: Priority) -> Result<(), MeshError> {
+        self.senders[priority.index()].try_send(item).map_err(|_| {
+            MeshError::CapacityExceeded(format!("{} priority queue is full", priority.as_str()))
+        })
+    }
+
+    /// Wait for the next item, taking from the highest non-empty class
+    pub async fn pop(&self) -> T {
+        let mut receivers = self.receivers.lock().await;
+        let [high, medium, low] = &mut *receivers;
+        // The queue holds a sender of every class, so no channel ever closes
+        tokio::select! {
+            biased;
+            Some(item) = high.recv() => item,
+            Some(item) = medium.recv() => item,
+            Some(item) = low.recv() => item,
+        }
+    }
+
+    /// Take the next item without waiting, highest class first
+    ///
+    /// Returns `None` if the queue is empty or a `pop` is waiting (that
+    /// `pop` will take the next item).
+    pub fn try_pop(&self) -> Option<T> {
+        let mut receivers = self.receivers.try_lock().ok()?;
+        receivers.iter_mut().find_map(|receiver| receiver.try_recv().ok())
+    }
+
+    /// Items waiting in one class
+    pub fn len(&self, priority: Priority) -> usize {
+        let sender = &self.senders[priority.index()];
+        sender.max_capacity() - sender.capacity()
+    }
+
+    /// Whether no items are waiting in any class
+    pub fn is_empty(&self) -> bool {
+        Priority::ALL.iter().all(|priority| self.len(*priority) == 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue(depth: usize) -> PriorityQueue<u32> {
+        PriorityQueue::new(&QueueConfig {
+            high_depth: depth,
+            medium_depth: depth,
+            low_depth: depth,
+            workers: 1,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_pops_highest_class_first() {
+        let queue = queue(8);
+        queue.push(1, Priority::Low).unwrap();
+        queue.push(2, Priority::Medium).unwrap();
+        queue.push(3, Priority::Low).unwrap();
+        queue.push(4, Priority::High).unwrap();
+
+        let mut popped = Vec::new();
+        while !queue.is_empty() {
+            popped.push(queue.pop().await);
+        }
+        assert_eq!(popped, vec![4, 2, 1, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_try_pop_does_not_wait() {
+        let queue = queue(8);
+        assert_eq!(queue.try_pop(), None);
+        queue.push(1, Priority::Low).unwrap();
+        queue.push(2, Priority::High).unwrap();
+        assert_eq!(queue.try_pop(), Some(2));
+        assert_eq!(queue.try_pop(), Some(1));
+        assert_eq!(queue.try_pop(), None);
+    }
+
+    #[tokio::test]
+    async fn test_full_class_refuses_items() {
+        let queue = queue(2);
+        queue.push(1, Priority::Low).unwrap();
+        queue.push(2, Priority::Low).unwrap();
+
+        assert!(matches!(queue.push(3, Priority::Low), Err(MeshError::CapacityExceeded(_))));
+        // Other classes are unaffected
+        queue.push(4, Priority::High).unwrap();
+        assert_eq!(queue.len(Priority::Low), 2);
+        assert_eq!(queue.len(Priority::High), 1);
+    }
+}
@@ -0,0 +1,50 @@
+//! Pluggable payment proof verification
+//!
+//! `PaymentVerifier` hands Lightning, CTV and custom proofs to the
+//! `ProofBackend` registered for their kind. The built-in Lightning
+//! (`"lightning"`) and CTV (`"ctv"`) backends get the bincode-encoded
+//! `PaymentProof`; `PaymentProof::Custom` proofs (ecash mints, sidechain
+//! proofs, ...) hand their opaque blob to the backend of their `kind`.
+//! More backends are added with `PaymentVerifier::with_backend`. A proof of
+//! a kind no backend is registered for fails verification.
+//!
+//! HTLC escrow and Taproot script proofs keep their dedicated verifiers,
+//! which hold state the manager drives directly.
+
+use crate::error::MeshError;
+use crate::payment_proof::VerificationResult;
+use async_trait::async_trait;
+use secp256k1::XOnlyPublicKey;
+
+/// Kind of the built-in Lightning backend (BOLT11, BOLT12 and keysend proofs)
+pub const LIGHTNING_PROOF_KIND: &str = "lightning";
+
+/// Kind of the built-in CTV backend (instant settlement proofs)
+pub const CTV_PROOF_KIND: &str = "ctv";
+
+/// What a backend is told about a proof besides its bytes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct VerifyContext {
+    /// Key of the packet destination, if known (see
+    /// `PaymentVerifier::verify_for_destination`)
+    pub destination: Option<XOnlyPublicKey>,
+    /// Amount the proof claims in satoshis
+    pub amount_sats: u64,
+    /// Payment timestamp the proof claims
+    pub timestamp: u64,
+    /// Expiry the proof claims, if it has one
+    pub expires_at: Option<u64>,
+}
+
+/// Verifies payment proofs of one kind
+#[async_trait]
+pub trait ProofBackend: Send + Sync {
+    /// Proof kind this backend verifies (`PaymentProof::Custom::kind`)
+    fn kind(&self) -> &str;
+
+    /// Verify the bytes of a proof
+    ///
+    /// Returns a failed result for a proof that does not hold up, and an
+    /// error only if it could not be checked (e.g. the node is unreachable).
+    async fn verify(&self, proof_bytes: &[u8], ctx: &VerifyContext) -> Result<VerificationResult, MeshError>;
+}
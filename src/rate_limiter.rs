@@ -0,0 +1,261 @@
+//! Per-source rate limiting for routed packets
+//!
+//! Each source node ID gets a token bucket per traffic class; a packet takes
+//! one token and is rejected with `RateLimited` when the bucket is empty.
+//! `PaymentRequired` packets use the `mesh.rate_limit.burst` /
+//! `mesh.rate_limit.refill_per_second` bucket. Free (consensus) packets use a
+//! separate bucket `mesh.rate_limit.free_multiplier` times larger, so paid
+//! floods cannot starve them.
+
+use crate::error::MeshError;
+use crate::routing::NodeId;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use tracing::debug;
+
+/// Rate limit configuration
+#[derive(Debug, Clone)]
+pub struct RateLimitConfig {
+    /// Bucket capacity for paid packets (0 = rate limiting disabled)
+    pub burst: u64,
+    /// Tokens added per second to paid buckets
+    pub refill_per_second: u64,
+    /// Free bucket capacity and refill rate, as a multiple of the paid ones
+    pub free_multiplier: u64,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            burst: 100,
+            refill_per_second: 20,
+            free_multiplier: 4,
+        }
+    }
+}
+
+impl RateLimitConfig {
+    /// Load from module context config keys
+    pub fn from_context(ctx: &bllvm_node::module::traits::ModuleContext) -> Self {
+        let defaults = Self::default();
+        Self {
+            burst: ctx
+                .get_config_or("mesh.rate_limit.burst", &defaults.burst.to_string())
+                .parse::<u64>()
+                .unwrap_or(defaults.burst),
+            refill_per_second: ctx
+                .get_config_or(
+                    "mesh.rate_limit.refill_per_second",
+                    &defaults.refill_per_second.to_string(),
+                )
+                .parse::<u64>()
+                .unwrap_or(defaults.refill_per_second),
+            free_multiplier: ctx
+                .get_config_or(
+                    "mesh.rate_limit.free_multiplier",
+                    &defaults.free_multiplier.to_string(),
+                )
+                .parse::<u64>()
+                .unwrap_or(defaults.free_multiplier)
+                .max(1),
+        }
+    }
+
+    /// Whether rate limiting is enabled
+    pub fn enabled(&self) -> bool {
+        self.burst > 0
+    }
+
+    /// (capacity, refill per second) of a traffic class
+    fn bucket(&self, paid: bool) -> (u64, u64) {
+        if paid {
+            (self.burst, self.refill_per_second)
+        } else {
+            (
+                self.burst.saturating_mul(self.free_multiplier),
+                self.refill_per_second.saturating_mul(self.free_multiplier),
+            )
+        }
+    }
+}
+
+/// Token bucket of one source
+#[derive(Debug, Clone)]
+struct TokenBucket {
+    /// Tokens available
+    tokens: u64,
+    /// Last time tokens were added
+    last_refill: u64,
+}
+
+impl TokenBucket {
+    fn full(capacity: u64, now: u64) -> Self {
+        Self {
+            tokens: capacity,
+            last_refill: now,
+        }
+    }
+
+    fn refill(&mut self, capacity: u64, refill_per_second: u64, now: u64) {
+        let elapsed = now.saturating_sub(self.last_refill);
+        if elapsed > 0 {
+            self.tokens = self
+                .tokens
+                .saturating_add(elapsed.saturating_mul(refill_per_second))
+                .min(capacity);
+            self.last_refill = now;
+        }
+    }
+}
+
+/// Token-bucket rate limiter keyed by source node ID
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    /// Buckets for free packets (lock-free with DashMap)
+    free: DashMap<NodeId, TokenBucket>,
+    /// Buckets for paid packets (lock-free with DashMap)
+    paid: DashMap<NodeId, TokenBucket>,
+    /// Packets rejected so far
+    rate_limited: AtomicU64,
+}
+
+impl RateLimiter {
+    /// Create a new rate limiter
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config,
+            free: DashMap::new(),
+            paid: DashMap::new(),
+            rate_limited: AtomicU64::new(0),
+        }
+    }
+
+    /// Rate limit configuration
+    pub fn config(&self) -> &RateLimitConfig {
+        &self.config
+    }
+
+    /// Take a token from the source's bucket for the packet's traffic class
+    pub fn check(&self, source: &NodeId, paid: bool, now: u64) -> Result<(), MeshError> {
+        if !self.config.enabled() {
+            return Ok(());
+        }
+
+        let (capacity, refill_per_second) = self.config.bucket(paid);
+        let buckets = if paid { &self.paid } else { &self.free };
+        let mut bucket = buckets
+            .entry(*source)
+            .or_insert_with(|| TokenBucket::full(capacity, now));
+        bucket.refill(capacity, refill_per_second, now);
+
+        if bucket.tokens == 0 {
+            self.rate_limited.fetch_add(1, Ordering::Relaxed);
+            debug!("Rate limited: source={:x?}, paid={}", &source[..8], paid);
+            return Err(MeshError::RateLimited(*source));
+        }
+        bucket.tokens -= 1;
+        Ok(())
+    }
+
+    /// Forget sources whose buckets have refilled completely
+    ///
+    /// A full bucket behaves exactly like a new one, so dropping it only
+    /// frees memory.
+    pub fn cleanup_idle(&self, now: u64) {
+        for paid in [false, true] {
+            let (capacity, refill_per_second) = self.config.bucket(paid);
+            let buckets = if paid { &self.paid } else { &self.free };
+            buckets.retain(|_, bucket| {
+                bucket.refill(capacity, refill_per_second, now);
+                bucket.tokens < capacity
+            });
+        }
+    }
+
+    /// Get rate limiting statistics
+    pub fn stats(&self) -> RateLimitStats {
+        RateLimitStats {
+            tracked_sources: self.free.len() + self.paid.len(),
+            rate_limited: self.rate_limited.load(Ordering::Relaxed),
+            burst: self.config.burst,
+            refill_per_second: self.config.refill_per_second,
+        }
+    }
+}
+
+/// Rate limiting statistics
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RateLimitStats {
+    /// Buckets currently tracked (free and paid)
+    pub tracked_sources: usize,
+    /// Packets rejected so far
+    pub rate_limited: u64,
+    /// Paid bucket capacity
+    pub burst: u64,
+    /// Paid bucket refill rate
+    pub refill_per_second: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(burst: u64, refill_per_second: u64) -> RateLimiter {
+        RateLimiter::new(RateLimitConfig {
+            burst,
+            refill_per_second,
+            free_multiplier: 4,
+        })
+    }
+
+    #[test]
+    fn test_burst_then_refill() {
+        let limiter = limiter(3, 1);
+        let source = [1u8; 32];
+        for _ in 0..3 {
+            limiter.check(&source, true, 100).unwrap();
+        }
+        assert!(matches!(
+            limiter.check(&source, true, 100),
+            Err(MeshError::RateLimited(id)) if id == source
+        ));
+
+        // Other sources have their own bucket
+        assert!(limiter.check(&[2u8; 32], true, 100).is_ok());
+
+        limiter.check(&source, true, 101).unwrap();
+        assert!(limiter.check(&source, true, 101).is_err());
+        assert_eq!(limiter.stats().rate_limited, 2);
+    }
+
+    #[test]
+    fn test_free_traffic_has_larger_separate_bucket() {
+        let limiter = limiter(2, 1);
+        let source = [1u8; 32];
+        limiter.check(&source, true, 100).unwrap();
+        limiter.check(&source, true, 100).unwrap();
+        assert!(limiter.check(&source, true, 100).is_err());
+
+        // Paid exhaustion does not touch the free bucket (4x capacity)
+        for _ in 0..8 {
+            limiter.check(&source, false, 100).unwrap();
+        }
+        assert!(limiter.check(&source, false, 100).is_err());
+    }
+
+    #[test]
+    fn test_disabled_and_idle_cleanup() {
+        let disabled = limiter(0, 0);
+        for _ in 0..1000 {
+            disabled.check(&[1u8; 32], true, 100).unwrap();
+        }
+
+        let limiter = limiter(2, 1);
+        limiter.check(&[1u8; 32], true, 100).unwrap();
+        limiter.cleanup_idle(100);
+        assert_eq!(limiter.stats().tracked_sources, 1);
+        limiter.cleanup_idle(101);
+        assert_eq!(limiter.stats().tracked_sources, 0);
+    }
+}
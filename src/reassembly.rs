@@ -0,0 +1,527 @@
+//! Packet fragmentation and reassembly for links with a small MTU
+//!
+//! A packet whose wire frame exceeds the link MTU is sent as a stream of
+//! `PacketType::Fragment` packets, each carrying a slice of the frame and a
+//! `FragmentHeader` in its metadata. Fragments travel a single link: the
+//! next hop feeds them to its `FragmentReassembler`, which emits the original
+//! packet once every fragment has arrived, and only then routes it further.
+
+use crate::error::MeshError;
+use crate::network::{deserialize_mesh_packet, serialize_mesh_packet, MESH_HEADER_LEN};
+use crate::packet::{
+    FragmentHeader, MeshPacket, MeshPacketBuilder, PacketMetadata, PacketType, MAX_PACKET_SIZE,
+};
+use crate::routing::NodeId;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use tracing::{debug, warn};
+
+/// Largest fragment count accepted for one stream
+pub const MAX_FRAGMENTS: u32 = 4096;
+
+/// Default time an incomplete stream is buffered (`mesh.reassembly_timeout_secs`)
+pub const DEFAULT_REASSEMBLY_TIMEOUT_SECS: u64 = 30;
+
+/// Default concurrent reassemblies per sender (`mesh.max_reassemblies_per_source`)
+pub const DEFAULT_MAX_REASSEMBLIES_PER_SOURCE: usize = 8;
+
+/// Default concurrent reassemblies across all peers (`mesh.max_reassemblies`)
+pub const DEFAULT_MAX_REASSEMBLIES: usize = 256;
+
+/// Default bytes buffered across all reassemblies (`mesh.max_reassembly_bytes`)
+pub const DEFAULT_MAX_REASSEMBLY_BYTES: usize = 16 * 1024 * 1024;
+
+impl MeshPacket {
+    /// Split the packet into fragments whose frames fit in `mtu` bytes
+    ///
+    /// Fragments go from `source` to `destination` over one link, so this is
+    /// for packets addressed to a direct peer. The stream ID is derived from
+    /// the packet's bytes, making retransmitted fragments of the same packet
+    /// interchangeable. A packet that already fits is returned unchanged.
+    pub fn fragment(&self, mtu: usize) -> Result<Vec<MeshPacket>, MeshError> {
+        let frame = serialize_mesh_packet(self)?;
+        if frame.len() <= mtu {
+            return Ok(vec![self.clone()]);
+        }
+
+        let digest = Sha256::digest(&frame);
+        let stream_id = u64::from_be_bytes(digest[..8].try_into().expect("digest is 32 bytes"));
+        fragment_packets(self.source, self.destination, &frame, stream_id, mtu)
+    }
+}
+
+/// Split a serialized packet into fragment frames of at most `mtu` bytes
+///
+/// Fragments are addressed from `source` to `next_hop` only; the next hop
+/// reassembles the original frame before routing it any further.
+pub fn fragment_frame(
+    source: NodeId,
+    next_hop: NodeId,
+    frame: &[u8],
+    stream_id: u64,
+    mtu: usize,
+) -> Result<Vec<Vec<u8>>, MeshError> {
+    fragment_packets(source, next_hop, frame, stream_id, mtu)?
+        .iter()
+        .map(serialize_mesh_packet)
+        .collect()
+}
+
+fn fragment_packets(
+    source: NodeId,
+    next_hop: NodeId,
+    frame: &[u8],
+    stream_id: u64,
+    mtu: usize,
+) -> Result<Vec<MeshPacket>, MeshError> {
+    // Fixed-width encoding: every fragment has the same overhead
+    let overhead = serialize_mesh_packet(&fragment_packet(source, next_hop, stream_id, 0, 0, Vec::new())?)?.len();
+    let chunk_size = mtu.saturating_sub(overhead);
+    if chunk_size == 0 {
+        return Err(MeshError::InvalidRequest(format!(
+            "MTU {} leaves no room for fragment payload ({} bytes of overhead)",
+            mtu, overhead
+        )));
+    }
+
+    let total_fragments = u32::try_from(frame.len().div_ceil(chunk_size))
+        .ok()
+        .filter(|total| *total <= MAX_FRAGMENTS)
+        .ok_or_else(|| {
+            MeshError::InvalidPacket(format!(
+                "Packet of {} bytes needs more than {} fragments at MTU {}",
+                frame.len(),
+                MAX_FRAGMENTS,
+                mtu
+            ))
+        })?;
+
+    debug!(
+        "Fragmenting {} byte packet into {} fragments (stream_id={})",
+        frame.len(),
+        total_fragments,
+        stream_id
+    );
+    frame
+        .chunks(chunk_size)
+        .enumerate()
+        .map(|(index, chunk)| {
+            fragment_packet(source, next_hop, stream_id, index as u32, total_fragments, chunk.to_vec())
+        })
+        .collect()
+}
+
+fn fragment_packet(
+    source: NodeId,
+    next_hop: NodeId,
+    stream_id: u64,
+    fragment_index: u32,
+    total_fragments: u32,
+    chunk: Vec<u8>,
+) -> Result<MeshPacket, MeshError> {
+    MeshPacketBuilder::new(source, next_hop)
+        .packet_type(PacketType::Fragment)
+        .payload(chunk)
+        // Single link, never forwarded
+        .ttl(1)
+        .metadata(PacketMetadata {
+            fragment: Some(FragmentHeader {
+                stream_id,
+                fragment_index,
+                total_fragments,
+            }),
+            ..Default::default()
+        })
+        .build()
+}
+
+/// Fragments received so far for one stream
+struct PartialStream {
+    fragments: Vec<Option<Vec<u8>>>,
+    received: u32,
+    bytes: usize,
+    started_at: u64,
+}
+
+/// Buffers fragments until a whole packet has arrived
+///
+/// Streams are keyed by (link peer, stream ID), where the link peer is the
+/// authenticated node the fragment arrived from. Each peer may only have
+/// `max_per_source` streams in flight (the oldest is evicted to make room),
+/// and new streams are refused once `max_streams` streams or
+/// `max_buffered_bytes` bytes are buffered in total. Incomplete streams
+/// older than `timeout_secs` are dropped by `evict_expired`, which the
+/// owner calls periodically.
+pub struct FragmentReassembler {
+    partial: HashMap<(NodeId, u64), PartialStream>,
+    buffered_bytes: usize,
+    timeout_secs: u64,
+    max_per_source: usize,
+    max_streams: usize,
+    max_buffered_bytes: usize,
+}
+
+impl FragmentReassembler {
+    /// Create a reassembler
+    pub fn new(timeout_secs: u64, max_per_source: usize) -> Self {
+        Self {
+            partial: HashMap::new(),
+            buffered_bytes: 0,
+            timeout_secs,
+            max_per_source: max_per_source.max(1),
+            max_streams: DEFAULT_MAX_REASSEMBLIES,
+            max_buffered_bytes: DEFAULT_MAX_REASSEMBLY_BYTES,
+        }
+    }
+
+    /// Set the cap on streams buffered across all peers
+    pub fn with_max_streams(mut self, max_streams: usize) -> Self {
+        self.max_streams = max_streams.max(1);
+        self
+    }
+
+    /// Set the cap on bytes buffered across all streams
+    pub fn with_max_buffered_bytes(mut self, max_buffered_bytes: usize) -> Self {
+        self.max_buffered_bytes = max_buffered_bytes;
+        self
+    }
+
+    /// Add a fragment received from the direct peer `link_peer`
+    ///
+    /// Fragments travel a single link, so their source must be the peer
+    /// they arrived from. Returns the reassembled packet once complete.
+    pub fn accept(
+        &mut self,
+        link_peer: NodeId,
+        fragment: &MeshPacket,
+        now: u64,
+    ) -> Result<Option<MeshPacket>, MeshError> {
+        if fragment.source != link_peer {
+            return Err(MeshError::InvalidPacket(format!(
+                "Fragment claims source {:x?} but arrived from {:x?}",
+                &fragment.source[..8],
+                &link_peer[..8]
+            )));
+        }
+        let header = fragment
+            .fragment_header()
+            .ok_or_else(|| MeshError::InvalidPacket("Fragment without fragment header".to_string()))?;
+        let Some(frame) = self.accept_chunk(link_peer, header, &fragment.payload, now)? else {
+            return Ok(None);
+        };
+
+        let packet = deserialize_mesh_packet(&frame)?;
+        if packet.packet_type == PacketType::Fragment {
+            return Err(MeshError::InvalidPacket("Nested fragment".to_string()));
+        }
+        debug!(
+            "Reassembled {} byte packet from {:x?}",
+            frame.len(),
+            &fragment.source[..8]
+        );
+        Ok(Some(packet))
+    }
+
+    /// Add one fragment's bytes from `link_peer`, returning the reassembled
+    /// frame once complete
+    pub fn accept_chunk(
+        &mut self,
+        link_peer: NodeId,
+        header: FragmentHeader,
+        chunk: &[u8],
+        now: u64,
+    ) -> Result<Option<Vec<u8>>, MeshError> {
+        if header.total_fragments == 0
+            || header.total_fragments > MAX_FRAGMENTS
+            || header.fragment_index >= header.total_fragments
+        {
+            return Err(MeshError::InvalidPacket(format!(
+                "Invalid fragment {}/{}",
+                header.fragment_index, header.total_fragments
+            )));
+        }
+
+        let key = (link_peer, header.stream_id);
+        if !self.partial.contains_key(&key) {
+            self.make_room(&link_peer);
+            if self.partial.len() >= self.max_streams {
+                return Err(MeshError::InvalidPacket(format!(
+                    "Too many fragment streams in progress ({})",
+                    self.partial.len()
+                )));
+            }
+            self.partial.insert(
+                key,
+                PartialStream {
+                    fragments: vec![None; header.total_fragments as usize],
+                    received: 0,
+                    bytes: 0,
+                    started_at: now,
+                },
+            );
+        }
+
+        let partial = self.partial.get_mut(&key).expect("inserted above");
+        if partial.fragments.len() != header.total_fragments as usize {
+            self.remove_stream(&key);
+            return Err(MeshError::InvalidPacket(
+                "Fragment count changed mid-stream".to_string(),
+            ));
+        }
+        if partial.fragments[header.fragment_index as usize].is_some() {
+            // Retransmitted fragment
+            return Ok(None);
+        }
+        if partial.bytes + chunk.len() > MESH_HEADER_LEN + MAX_PACKET_SIZE {
+            self.remove_stream(&key);
+            return Err(MeshError::InvalidPacket(
+                "Reassembled packet exceeds maximum size".to_string(),
+            ));
+        }
+        if self.buffered_bytes + chunk.len() > self.max_buffered_bytes {
+            self.remove_stream(&key);
+            return Err(MeshError::InvalidPacket(format!(
+                "Fragment buffer full ({} bytes)",
+                self.buffered_bytes
+            )));
+        }
+
+        partial.fragments[header.fragment_index as usize] = Some(chunk.to_vec());
+        partial.received += 1;
+        partial.bytes += chunk.len();
+        self.buffered_bytes += chunk.len();
+        if partial.received < header.total_fragments {
+            return Ok(None);
+        }
+
+        let partial = self.remove_stream(&key).expect("present above");
+        Ok(Some(partial.fragments.into_iter().flatten().flatten().collect()))
+    }
+
+    /// Drop incomplete streams older than the timeout
+    ///
+    /// Returns the number of streams evicted.
+    pub fn evict_expired(&mut self, now: u64) -> usize {
+        let before = self.partial.len();
+        let timeout_secs = self.timeout_secs;
+        let mut freed = 0;
+        self.partial.retain(|_, partial| {
+            let live = now.saturating_sub(partial.started_at) < timeout_secs;
+            if !live {
+                freed += partial.bytes;
+            }
+            live
+        });
+        self.buffered_bytes -= freed;
+        let evicted = before - self.partial.len();
+        if evicted > 0 {
+            debug!("Evicted {} incomplete fragment streams", evicted);
+        }
+        evicted
+    }
+
+    /// Number of streams currently being reassembled
+    pub fn in_progress(&self) -> usize {
+        self.partial.len()
+    }
+
+    /// Age at which an incomplete stream is dropped
+    pub fn timeout_secs(&self) -> u64 {
+        self.timeout_secs
+    }
+
+    /// Bytes currently buffered across all streams
+    pub fn buffered_bytes(&self) -> usize {
+        self.buffered_bytes
+    }
+
+    /// Remove a stream, releasing its bytes from the global count
+    fn remove_stream(&mut self, key: &(NodeId, u64)) -> Option<PartialStream> {
+        let partial = self.partial.remove(key)?;
+        self.buffered_bytes -= partial.bytes;
+        Some(partial)
+    }
+
+    /// Evict the sender's oldest stream if it is at its concurrency cap
+    fn make_room(&mut self, source: &NodeId) {
+        let mut in_flight: Vec<(u64, u64)> = self
+            .partial
+            .iter()
+            .filter(|((from, _), _)| from == source)
+            .map(|((_, stream_id), partial)| (partial.started_at, *stream_id))
+            .collect();
+        if in_flight.len() < self.max_per_source {
+            return;
+        }
+
+        in_flight.sort_unstable();
+        let excess = in_flight.len() + 1 - self.max_per_source;
+        for (_, stream_id) in in_flight.into_iter().take(excess) {
+            warn!(
+                "Too many fragment streams from {:x?}, evicting stream_id={}",
+                &source[..8],
+                stream_id
+            );
+            self.remove_stream(&(*source, stream_id));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet_of(len: usize) -> MeshPacket {
+        MeshPacketBuilder::new([1u8; 32], [9u8; 32])
+            .packet_type(PacketType::BitcoinP2P)
+            .payload((0..len).map(|i| i as u8).collect())
+            .build()
+            .unwrap()
+    }
+
+    fn header(stream_id: u64, fragment_index: u32, total_fragments: u32) -> FragmentHeader {
+        FragmentHeader {
+            stream_id,
+            fragment_index,
+            total_fragments,
+        }
+    }
+
+    #[test]
+    fn test_fragments_fit_mtu_and_reassemble_out_of_order() {
+        let packet = packet_of(5_000);
+        let fragments = packet.fragment(1_024).unwrap();
+        assert!(fragments.len() > 1);
+
+        let mut reassembler = FragmentReassembler::new(30, 4);
+        let mut result = None;
+        for fragment in fragments.iter().rev() {
+            assert_eq!(fragment.packet_type, PacketType::Fragment);
+            assert!(serialize_mesh_packet(fragment).unwrap().len() <= 1_024);
+            assert!(result.is_none());
+            result = reassembler.accept([1u8; 32], fragment, 100).unwrap();
+        }
+        assert_eq!(result.unwrap().payload, packet.payload);
+        assert_eq!(reassembler.in_progress(), 0);
+    }
+
+    #[test]
+    fn test_small_packet_is_not_fragmented() {
+        let packet = packet_of(100);
+        let fragments = packet.fragment(1_024).unwrap();
+        assert_eq!(fragments.len(), 1);
+        assert_eq!(fragments[0].packet_type, PacketType::BitcoinP2P);
+    }
+
+    #[test]
+    fn test_duplicate_fragment_is_ignored() {
+        let packet = packet_of(3_000);
+        let fragments = packet.fragment(1_024).unwrap();
+
+        let mut reassembler = FragmentReassembler::new(30, 4);
+        assert!(reassembler.accept([1u8; 32], &fragments[0], 0).unwrap().is_none());
+        assert!(reassembler.accept([1u8; 32], &fragments[0], 0).unwrap().is_none());
+
+        let mut result = None;
+        for fragment in &fragments[1..] {
+            result = reassembler.accept([1u8; 32], fragment, 0).unwrap();
+        }
+        assert_eq!(result.unwrap().payload, packet.payload);
+    }
+
+    #[test]
+    fn test_frame_fragments_carry_frame_bytes() {
+        let frame = serialize_mesh_packet(&packet_of(3_000)).unwrap();
+        let fragments = fragment_frame([1u8; 32], [2u8; 32], &frame, 7, 1_024).unwrap();
+        assert!(fragments.iter().all(|fragment| fragment.len() <= 1_024));
+
+        let mut reassembler = FragmentReassembler::new(30, 4);
+        let mut result = None;
+        for bytes in &fragments {
+            let fragment = deserialize_mesh_packet(bytes).unwrap();
+            assert_eq!(fragment.fragment_header().unwrap().stream_id, 7);
+            result = reassembler
+                .accept_chunk(fragment.source, fragment.fragment_header().unwrap(), &fragment.payload, 0)
+                .unwrap();
+        }
+        assert_eq!(result.unwrap(), frame);
+    }
+
+    #[test]
+    fn test_incomplete_streams_expire() {
+        let mut reassembler = FragmentReassembler::new(30, 4);
+        reassembler.accept_chunk([1u8; 32], header(1, 0, 2), &[1], 100).unwrap();
+        assert_eq!(reassembler.in_progress(), 1);
+
+        assert_eq!(reassembler.evict_expired(129), 0);
+        assert_eq!(reassembler.evict_expired(130), 1);
+
+        // The late second half starts a new, incomplete stream
+        assert!(reassembler.accept_chunk([1u8; 32], header(1, 1, 2), &[2], 131).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_per_source_cap_evicts_oldest() {
+        let mut reassembler = FragmentReassembler::new(30, 2);
+        for stream_id in 0..3 {
+            reassembler.accept_chunk([1u8; 32], header(stream_id, 0, 2), &[0], stream_id).unwrap();
+        }
+        assert_eq!(reassembler.in_progress(), 2);
+
+        // Stream 0 was evicted; its second half no longer completes it
+        assert!(reassembler.accept_chunk([1u8; 32], header(0, 1, 2), &[0], 3).unwrap().is_none());
+
+        // Other senders are unaffected
+        reassembler.accept_chunk([2u8; 32], header(0, 0, 2), &[0], 3).unwrap();
+        assert_eq!(reassembler.in_progress(), 3);
+    }
+
+    #[test]
+    fn test_fragment_from_another_link_rejected() {
+        let fragments = packet_of(3_000).fragment(1_024).unwrap();
+
+        let mut reassembler = FragmentReassembler::new(30, 4);
+        assert!(reassembler.accept([2u8; 32], &fragments[0], 0).is_err());
+        assert_eq!(reassembler.in_progress(), 0);
+    }
+
+    #[test]
+    fn test_global_caps_refuse_new_streams() {
+        let mut reassembler = FragmentReassembler::new(30, 4).with_max_streams(2);
+        reassembler.accept_chunk([1u8; 32], header(0, 0, 2), &[0], 0).unwrap();
+        reassembler.accept_chunk([2u8; 32], header(0, 0, 2), &[0], 0).unwrap();
+        assert!(reassembler.accept_chunk([3u8; 32], header(0, 0, 2), &[0], 0).is_err());
+
+        // Existing streams still complete
+        assert!(reassembler.accept_chunk([1u8; 32], header(0, 1, 2), &[0], 0).unwrap().is_some());
+        reassembler.accept_chunk([3u8; 32], header(0, 0, 2), &[0], 0).unwrap();
+
+        let mut reassembler = FragmentReassembler::new(30, 4).with_max_buffered_bytes(4);
+        reassembler.accept_chunk([1u8; 32], header(0, 0, 2), &[0; 3], 0).unwrap();
+        assert!(reassembler.accept_chunk([2u8; 32], header(0, 0, 2), &[0; 2], 0).is_err());
+        assert_eq!(reassembler.buffered_bytes(), 3);
+
+        // Expiry releases the bytes
+        reassembler.evict_expired(30);
+        assert_eq!(reassembler.buffered_bytes(), 0);
+        reassembler.accept_chunk([2u8; 32], header(0, 0, 2), &[0; 2], 30).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_fragment_headers_rejected() {
+        let mut reassembler = FragmentReassembler::new(30, 4);
+        for (index, total) in [(0, 0), (2, 2), (0, MAX_FRAGMENTS + 1)] {
+            assert!(reassembler.accept_chunk([1u8; 32], header(1, index, total), &[0], 0).is_err());
+        }
+
+        // Total changing mid-stream drops the stream
+        reassembler.accept_chunk([1u8; 32], header(2, 0, 3), &[0], 0).unwrap();
+        assert!(reassembler.accept_chunk([1u8; 32], header(2, 1, 2), &[0], 0).is_err());
+        assert_eq!(reassembler.in_progress(), 0);
+    }
+
+    #[test]
+    fn test_mtu_too_small_for_fragment_header() {
+        assert!(packet_of(100).fragment(64).is_err());
+    }
+}
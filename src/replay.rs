@@ -1,14 +1,249 @@
 //! Replay prevention for mesh payment proofs
 //!
 //! Prevents reuse of payment proofs using hash tracking, sequence numbers, and expiry.
+//!
+//! Sequence numbers are checked against a per-peer sliding window (as in
+//! IPsec anti-replay) so reordering across transports is tolerated while
+//! each sequence is still accepted at most once.
+//!
+//! Proof hashes and tracked peers are each capped at `max_entries`; when
+//! full, the oldest entries are evicted or new proofs are rejected with
+//! `CapacityExceeded`, depending on the `ReplayOverflowPolicy`.
+//!
+//! Hashes are also queued in insertion order, so `check_replay` expires and
+//! evicts from the front of the queue in amortized O(1) instead of scanning
+//! the whole table; the full `cleanup_expired` sweep remains as a backstop.
+//!
+//! Payment verification is slow and can fail transiently, so callers
+//! `reserve` a proof before verifying it and `commit` or `release` the
+//! reservation afterwards; only committed proofs burn their hash and
+//! sequence.
+//!
+//! With `load_from_storage`, used proof hashes and sequence windows are
+//! persisted to the `mesh_replay_hashes` and `mesh_replay_sequences` trees so
+//! a restart does not reopen the replay window. Writes are batched: changes
+//! accumulate until `flush` (every `flush_batch` changes, or periodically).
 
-use crate::payment_proof::PaymentProof;
+use crate::error::MeshError;
+use crate::payment_proof::{PaymentProof, DEFAULT_KEYSEND_MAX_AGE_SECONDS};
+use crate::storage_schema::{open_versioned_tree, TreeSchema};
+use bllvm_node::module::ipc::protocol::StorageOperation;
+use bllvm_node::module::traits::NodeAPI;
 use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::{SystemTime, UNIX_EPOCH};
 use tracing::{debug, warn};
 
+/// Default number of recent sequence numbers tracked per peer
+pub const DEFAULT_REPLAY_WINDOW_SIZE: u64 = 64;
+
+/// Default expiry for payment proof hashes (24 hours)
+pub const DEFAULT_REPLAY_EXPIRY_SECONDS: u64 = 24 * 60 * 60;
+
+/// Default cap on remembered proof hashes (and on tracked peers)
+pub const DEFAULT_REPLAY_MAX_ENTRIES: usize = 1_000_000;
+
+/// Default number of unsaved changes that triggers a flush
+pub const DEFAULT_REPLAY_FLUSH_BATCH: usize = 32;
+
+/// Schema of the used proof hash tree (v1: bincode `ReplayEntry` by proof hash)
+pub const REPLAY_HASHES_SCHEMA: TreeSchema = TreeSchema {
+    tree: "mesh_replay_hashes",
+    version: 1,
+    migrations: &[],
+};
+
+/// Schema of the sequence window tree (v1: bincode `SequenceWindow` by peer ID)
+pub const REPLAY_SEQUENCES_SCHEMA: TreeSchema = TreeSchema {
+    tree: "mesh_replay_sequences",
+    version: 1,
+    migrations: &[],
+};
+
+/// What to do with a new proof when the replay cache is full
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReplayOverflowPolicy {
+    /// Evict the oldest entries to make room
+    #[default]
+    EvictOldest,
+    /// Reject the proof with `CapacityExceeded` (caller applies backpressure)
+    Reject,
+}
+
+impl From<&str> for ReplayOverflowPolicy {
+    fn from(s: &str) -> Self {
+        match s {
+            "reject" => ReplayOverflowPolicy::Reject,
+            _ => ReplayOverflowPolicy::EvictOldest,
+        }
+    }
+}
+
+/// Replay window configuration
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReplayWindowConfig {
+    /// Number of recent sequence numbers accepted out of order
+    pub window_size: u64,
+    /// Expiry time for payment proof hashes
+    pub expiry_seconds: u64,
+    /// Unsaved changes that make `needs_flush` true (persistent mode only)
+    pub flush_batch: usize,
+    /// Cap on remembered proof hashes, and separately on tracked peers
+    pub max_entries: usize,
+    /// Behavior when a cap is reached
+    pub overflow: ReplayOverflowPolicy,
+    /// How long keysend proofs are accepted after payment
+    pub keysend_max_age_seconds: u64,
+}
+
+impl Default for ReplayWindowConfig {
+    fn default() -> Self {
+        Self {
+            window_size: DEFAULT_REPLAY_WINDOW_SIZE,
+            expiry_seconds: DEFAULT_REPLAY_EXPIRY_SECONDS,
+            flush_batch: DEFAULT_REPLAY_FLUSH_BATCH,
+            max_entries: DEFAULT_REPLAY_MAX_ENTRIES,
+            overflow: ReplayOverflowPolicy::default(),
+            keysend_max_age_seconds: DEFAULT_KEYSEND_MAX_AGE_SECONDS,
+        }
+    }
+}
+
+impl ReplayWindowConfig {
+    /// Set the sequence window size (at least 1)
+    pub fn with_window_size(mut self, window_size: u64) -> Self {
+        self.window_size = window_size.max(1);
+        self
+    }
+
+    /// Set the payment proof hash expiry
+    pub fn with_expiry_seconds(mut self, expiry_seconds: u64) -> Self {
+        self.expiry_seconds = expiry_seconds;
+        self
+    }
+
+    /// Set how many unsaved changes trigger a flush (at least 1)
+    pub fn with_flush_batch(mut self, flush_batch: usize) -> Self {
+        self.flush_batch = flush_batch.max(1);
+        self
+    }
+
+    /// Set the entry cap (at least 1)
+    pub fn with_max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = max_entries.max(1);
+        self
+    }
+
+    /// Set the behavior when the cap is reached
+    pub fn with_overflow_policy(mut self, overflow: ReplayOverflowPolicy) -> Self {
+        self.overflow = overflow;
+        self
+    }
+
+    /// Set how long keysend proofs are accepted after payment
+    pub fn with_keysend_max_age(mut self, keysend_max_age_seconds: u64) -> Self {
+        self.keysend_max_age_seconds = keysend_max_age_seconds;
+        self
+    }
+}
+
+/// Per-peer anti-replay window
+///
+/// Bit `sequence % window_size` marks whether that sequence (within the
+/// window ending at `last_sequence`) was already accepted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SequenceWindow {
+    /// Highest sequence accepted
+    last_sequence: u64,
+    /// Seen bitmask over the window
+    seen: Vec<u64>,
+    /// When the peer last had a sequence accepted (eviction order, not persisted)
+    #[serde(skip)]
+    last_seen: u64,
+}
+
+impl SequenceWindow {
+    fn new(sequence: u64, window_size: u64) -> Self {
+        let mut window = Self {
+            last_sequence: sequence,
+            seen: vec![0; window_size.div_ceil(64) as usize],
+            last_seen: now_secs(),
+        };
+        window.set(sequence, window_size);
+        window
+    }
+
+    /// Restore a stored window, conservatively if the window size changed
+    fn restore(mut self, window_size: u64) -> Self {
+        self.last_seen = now_secs();
+        let words = window_size.div_ceil(64) as usize;
+        if self.seen.len() != words {
+            // Bit positions are meaningless under the new size: treat the whole window as used
+            self.seen = vec![u64::MAX; words];
+        }
+        self
+    }
+
+    /// Check whether a sequence may be accepted (without recording it)
+    fn check(&self, sequence: u64, window_size: u64) -> Result<(), String> {
+        if sequence > self.last_sequence {
+            return Ok(());
+        }
+        if self.last_sequence - sequence >= window_size {
+            return Err(format!(
+                "Sequence number too old: got {}, window starts after {}",
+                sequence,
+                self.last_sequence - window_size
+            ));
+        }
+        if self.is_set(sequence, window_size) {
+            return Err(format!("Sequence number already used: {}", sequence));
+        }
+        Ok(())
+    }
+
+    /// Record an accepted sequence, sliding the window forward if needed
+    fn accept(&mut self, sequence: u64, window_size: u64) {
+        if sequence > self.last_sequence {
+            let advance = sequence - self.last_sequence;
+            if advance >= window_size {
+                self.seen.iter_mut().for_each(|word| *word = 0);
+            } else {
+                for skipped in self.last_sequence + 1..sequence {
+                    self.clear(skipped, window_size);
+                }
+            }
+            self.last_sequence = sequence;
+        }
+        self.set(sequence, window_size);
+    }
+
+    fn position(sequence: u64, window_size: u64) -> (usize, u64) {
+        let bit = sequence % window_size;
+        ((bit / 64) as usize, 1 << (bit % 64))
+    }
+
+    fn is_set(&self, sequence: u64, window_size: u64) -> bool {
+        let (word, mask) = Self::position(sequence, window_size);
+        self.seen[word] & mask != 0
+    }
+
+    fn set(&mut self, sequence: u64, window_size: u64) {
+        let (word, mask) = Self::position(sequence, window_size);
+        self.seen[word] |= mask;
+    }
+
+    fn clear(&mut self, sequence: u64, window_size: u64) {
+        let (word, mask) = Self::position(sequence, window_size);
+        self.seen[word] &= !mask;
+    }
+}
+
 /// Replay prevention entry (combined structure)
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct ReplayEntry {
     /// Timestamp when hash was first seen
     timestamp: u64,
@@ -18,6 +253,16 @@ struct ReplayEntry {
     sequence: u64,
 }
 
+/// A payment proof held by `ReplayPrevention::reserve` during verification
+#[derive(Debug)]
+#[must_use = "a reservation must be committed or released"]
+pub struct ReplayReservation {
+    proof_hash: [u8; 32],
+    peer_id: [u8; 32],
+    sequence: u64,
+    timestamp: u64,
+}
+
 /// Replay prevention for payment proofs
 ///
 /// Uses DashMap for lock-free concurrent access and combines multiple HashMaps
@@ -26,72 +271,323 @@ pub struct ReplayPrevention {
     /// Combined replay data: hash -> (timestamp, peer_id, sequence)
     /// Lock-free concurrent access using DashMap
     replay_data: DashMap<[u8; 32], ReplayEntry>,
-    /// Per-peer sequence numbers (to detect out-of-order proofs)
+    /// Per-peer sequence windows (to detect reused sequence numbers)
     /// Lock-free concurrent access using DashMap
-    used_sequences: DashMap<[u8; 32], u64>, // peer_id -> last_sequence
+    used_sequences: DashMap<[u8; 32], SequenceWindow>, // peer_id -> window
+    /// (timestamp, hash) in insertion order, oldest first
+    ///
+    /// May hold stale pairs for hashes already removed; those are skipped
+    /// when popped.
+    expiry_queue: Mutex<VecDeque<(u64, [u8; 32])>>,
+    /// Number of recent sequences accepted out of order
+    window_size: u64,
     /// Expiry time for hashes (default: 24 hours)
     expiry_seconds: u64,
+    /// Storage trees (None = in memory only)
+    storage: Option<ReplayStorage>,
+    /// Changes not yet written to storage
+    pending: Mutex<PendingWrites>,
+    /// Unsaved changes that make `needs_flush` true
+    flush_batch: usize,
+    /// Cap on proof hashes and on tracked peers
+    max_entries: usize,
+    /// Behavior when a cap is reached
+    overflow: ReplayOverflowPolicy,
+    /// How long keysend proofs are accepted after payment
+    keysend_max_age_seconds: u64,
+    /// Entries evicted to stay under the cap
+    evictions: AtomicU64,
+    /// Sequences accepted below the peer's highest (reordered in transit)
+    out_of_order: AtomicU64,
+    /// Sequences rejected as already seen within the window
+    duplicate_sequences: AtomicU64,
+    /// Sequences rejected as older than the window
+    stale_sequences: AtomicU64,
+}
+
+/// Storage backing a persistent `ReplayPrevention`
+struct ReplayStorage {
+    node_api: Arc<dyn NodeAPI>,
+    hashes_tree: String,
+    sequences_tree: String,
+}
+
+/// Changes accumulated between flushes
+#[derive(Default)]
+struct PendingWrites {
+    /// Proof hashes inserted or removed since the last flush
+    hashes: HashSet<[u8; 32]>,
+    /// Peers whose sequence window changed since the last flush
+    peers: HashSet<[u8; 32]>,
+}
+
+impl PendingWrites {
+    fn len(&self) -> usize {
+        self.hashes.len() + self.peers.len()
+    }
 }
 
 impl ReplayPrevention {
-    /// Create a new replay prevention system
-    pub fn new(expiry_seconds: u64) -> Self {
+    /// Create a new in-memory replay prevention system
+    pub fn new(config: ReplayWindowConfig) -> Self {
         Self {
             replay_data: DashMap::new(),
             used_sequences: DashMap::new(),
-            expiry_seconds,
+            expiry_queue: Mutex::new(VecDeque::new()),
+            window_size: config.window_size.max(1),
+            expiry_seconds: config.expiry_seconds,
+            storage: None,
+            pending: Mutex::new(PendingWrites::default()),
+            flush_batch: config.flush_batch.max(1),
+            max_entries: config.max_entries.max(1),
+            overflow: config.overflow,
+            keysend_max_age_seconds: config.keysend_max_age_seconds,
+            evictions: AtomicU64::new(0),
+            out_of_order: AtomicU64::new(0),
+            duplicate_sequences: AtomicU64::new(0),
+            stale_sequences: AtomicU64::new(0),
         }
     }
 
-    /// Check if payment proof is a replay
+    /// Create a persistent replay prevention system, restoring saved state
     ///
-    /// Returns Ok(true) if proof is valid (not a replay), Err if replay detected.
-    /// Lock-free operation using DashMap - no mut needed.
+    /// Expired proof hashes are dropped. Falls back to memory only if
+    /// storage is unavailable; fails only if a stored schema is newer than
+    /// this build.
+    pub async fn load_from_storage(
+        node_api: Arc<dyn NodeAPI>,
+        config: ReplayWindowConfig,
+    ) -> Result<Self, MeshError> {
+        let mut replay = Self::new(config);
+
+        let trees = match open_versioned_tree(node_api.as_ref(), &REPLAY_HASHES_SCHEMA).await {
+            Ok(hashes_tree) => open_versioned_tree(node_api.as_ref(), &REPLAY_SEQUENCES_SCHEMA)
+                .await
+                .map(|sequences_tree| (hashes_tree, sequences_tree)),
+            Err(e) => Err(e),
+        };
+        let (hashes_tree, sequences_tree) = match trees {
+            Ok(trees) => trees,
+            Err(e @ MeshError::UnsupportedVersion(_)) => return Err(e),
+            Err(e) => {
+                warn!("Replay storage unavailable, tracking proofs in memory only: {}", e);
+                return Ok(replay);
+            }
+        };
+
+        let now = now_secs();
+        match node_api.storage_iter(hashes_tree.clone()).await {
+            Ok(stored) => {
+                let mut restored = Vec::new();
+                for (key, value) in stored {
+                    let (Ok(hash), Ok(entry)) = (
+                        <[u8; 32]>::try_from(key.as_slice()),
+                        bincode::deserialize::<ReplayEntry>(&value),
+                    ) else {
+                        continue;
+                    };
+                    if now > entry.timestamp + replay.expiry_seconds {
+                        // Pruned from storage on the next flush
+                        replay.pending.get_mut().unwrap().hashes.insert(hash);
+                    } else {
+                        restored.push((entry.timestamp, hash));
+                        replay.replay_data.insert(hash, entry);
+                    }
+                }
+                restored.sort_unstable();
+                replay.expiry_queue.get_mut().unwrap().extend(restored);
+            }
+            Err(e) => warn!("Failed to restore used payment proofs: {}", e),
+        }
+        match node_api.storage_iter(sequences_tree.clone()).await {
+            Ok(stored) => {
+                for (key, value) in stored {
+                    let (Ok(peer_id), Ok(window)) = (
+                        <[u8; 32]>::try_from(key.as_slice()),
+                        bincode::deserialize::<SequenceWindow>(&value),
+                    ) else {
+                        continue;
+                    };
+                    replay.used_sequences.insert(peer_id, window.restore(replay.window_size));
+                }
+            }
+            Err(e) => warn!("Failed to restore sequence windows: {}", e),
+        }
+        debug!(
+            "Restored replay state: {} proof hashes, {} peers",
+            replay.replay_data.len(),
+            replay.used_sequences.len()
+        );
+
+        replay.storage = Some(ReplayStorage {
+            node_api,
+            hashes_tree,
+            sequences_tree,
+        });
+        Ok(replay)
+    }
+
+    /// Whether enough changes accumulated to warrant a flush
+    pub fn needs_flush(&self) -> bool {
+        self.storage.is_some() && self.pending.lock().unwrap().len() >= self.flush_batch
+    }
+
+    /// Write accumulated changes to storage
+    ///
+    /// Returns the number of entries written or removed (0 when in memory only).
+    pub async fn flush(&self) -> Result<usize, MeshError> {
+        let Some(ref storage) = self.storage else {
+            return Ok(0);
+        };
+        let pending = std::mem::take(&mut *self.pending.lock().unwrap());
+        if pending.len() == 0 {
+            return Ok(0);
+        }
+
+        let mut hash_operations = Vec::with_capacity(pending.hashes.len());
+        for hash in &pending.hashes {
+            hash_operations.push(match self.replay_data.get(hash) {
+                Some(entry) => StorageOperation::Insert {
+                    key: hash.to_vec(),
+                    value: encode(entry.value())?,
+                },
+                None => StorageOperation::Remove { key: hash.to_vec() },
+            });
+        }
+        let mut sequence_operations = Vec::with_capacity(pending.peers.len());
+        for peer_id in &pending.peers {
+            sequence_operations.push(match self.used_sequences.get(peer_id) {
+                Some(window) => StorageOperation::Insert {
+                    key: peer_id.to_vec(),
+                    value: encode(window.value())?,
+                },
+                None => StorageOperation::Remove { key: peer_id.to_vec() },
+            });
+        }
+
+        let written = pending.len();
+        let result = async {
+            storage
+                .node_api
+                .storage_transaction(storage.hashes_tree.clone(), hash_operations)
+                .await?;
+            storage
+                .node_api
+                .storage_transaction(storage.sequences_tree.clone(), sequence_operations)
+                .await
+        }
+        .await;
+        if let Err(e) = result {
+            // Keep the changes for the next attempt
+            let mut retry = self.pending.lock().unwrap();
+            retry.hashes.extend(pending.hashes);
+            retry.peers.extend(pending.peers);
+            return Err(MeshError::ModuleError(format!("Failed to save replay state: {}", e)));
+        }
+
+        debug!("Flushed {} replay prevention changes", written);
+        Ok(written)
+    }
+
+    /// Check if payment proof is a replay and record it as used
+    ///
+    /// Returns Ok(true) if proof is valid (not a replay), `ReplayDetected` if
+    /// replay detected, and `CapacityExceeded` if the cache is full under
+    /// `ReplayOverflowPolicy::Reject`. Equivalent to `reserve` then `commit`.
     pub fn check_replay(
         &self,
         proof: &PaymentProof,
         peer_id: &[u8; 32],
         sequence: u64,
-    ) -> Result<bool, String> {
-        // Clean up expired hashes first
-        self.cleanup_expired();
+    ) -> Result<bool, MeshError> {
+        let reservation = self.reserve(proof, peer_id, sequence)?;
+        self.commit(reservation)?;
+        Ok(true)
+    }
+
+    /// Reserve a payment proof while it is being verified
+    ///
+    /// Runs the checks of `check_replay` and holds the proof hash, so a
+    /// concurrent copy of the proof is rejected, but neither records the
+    /// sequence nor persists anything. Follow with `commit` once the proof
+    /// verified, or `release` if it did not, so a failed (possibly
+    /// transient) verification does not burn the proof.
+    pub fn reserve(
+        &self,
+        proof: &PaymentProof,
+        peer_id: &[u8; 32],
+        sequence: u64,
+    ) -> Result<ReplayReservation, MeshError> {
+        // Expire hashes from the front of the queue first (amortized O(1))
+        let now = now_secs();
+        self.expire_queued(now);
 
-        // Check payment hash not reused (lock-free)
+        // Check payment hash not reused (or reserved)
         let proof_hash = proof.hash();
         if self.replay_data.contains_key(&proof_hash) {
-            return Err("Payment proof already used (replay detected)".to_string());
+            return Err(MeshError::ReplayDetected(
+                "Payment proof already used (replay detected)".to_string(),
+            ));
+        }
+
+        // Check sequence number against the peer's window
+        self.check_window(peer_id, sequence)?;
+
+        // Check expiry (proof itself checks this, but double-check)
+        if proof.is_expired_with_keysend_max_age(self.keysend_max_age_seconds) {
+            return Err(MeshError::ReplayDetected("Payment proof expired".to_string()));
         }
 
-        // Check sequence number (FIBRE-inspired) - lock-free
-        if let Some(entry) = self.used_sequences.get(peer_id) {
-            if sequence <= *entry.value() {
-                return Err(format!(
-                    "Sequence number out of order: got {}, expected > {}",
-                    sequence, entry.value()
+        self.make_room(peer_id)?;
+
+        // Hold the hash; a concurrent reservation of the same proof loses
+        let entry = ReplayEntry {
+            timestamp: now,
+            peer_id: *peer_id,
+            sequence,
+        };
+        match self.replay_data.entry(proof_hash) {
+            dashmap::mapref::entry::Entry::Occupied(_) => {
+                return Err(MeshError::ReplayDetected(
+                    "Payment proof already used (replay detected)".to_string(),
                 ));
             }
+            dashmap::mapref::entry::Entry::Vacant(vacant) => {
+                vacant.insert(entry);
+            }
         }
+        self.expiry_queue.lock().unwrap().push_back((now, proof_hash));
 
-        // Check expiry (proof itself checks this, but double-check)
-        if proof.is_expired() {
-            return Err("Payment proof expired".to_string());
-        }
+        Ok(ReplayReservation {
+            proof_hash,
+            peer_id: *peer_id,
+            sequence,
+            timestamp: now,
+        })
+    }
 
-        // Mark as used (lock-free inserts)
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
-        
-        self.replay_data.insert(
+    /// Record a reserved proof and its sequence as used
+    ///
+    /// Fails with `ReplayDetected` (releasing the reservation) if another
+    /// packet took the sequence while the proof was being verified.
+    pub fn commit(&self, reservation: ReplayReservation) -> Result<(), MeshError> {
+        let ReplayReservation {
             proof_hash,
-            ReplayEntry {
-                timestamp: now,
-                peer_id: *peer_id,
-                sequence,
-            },
-        );
-        self.used_sequences.insert(*peer_id, sequence);
+            peer_id,
+            sequence,
+            timestamp,
+        } = reservation;
+        let reordered = match self.check_window(&peer_id, sequence) {
+            Ok(reordered) => reordered,
+            Err(e) => {
+                self.remove_queued(timestamp, &proof_hash);
+                return Err(e);
+            }
+        };
+        self.accept_sequence(&peer_id, sequence, timestamp, reordered);
+        if self.storage.is_some() {
+            self.pending.lock().unwrap().hashes.insert(proof_hash);
+        }
 
         debug!(
             "Payment proof accepted: peer_id={}, sequence={}, hash={:x?}",
@@ -99,19 +595,159 @@ impl ReplayPrevention {
             sequence,
             &proof_hash[..8]
         );
+        Ok(())
+    }
 
-        Ok(true)
+    /// Drop a reservation whose proof failed verification
+    ///
+    /// The proof may be presented again.
+    pub fn release(&self, reservation: ReplayReservation) {
+        self.remove_queued(reservation.timestamp, &reservation.proof_hash);
+    }
+
+    /// Check a packet paid from a prepaid balance (see `crate::balance`)
+    ///
+    /// Its proof was marked used when the balance opened, so only the
+    /// peer's sequence window applies. Errors as `check_replay`.
+    pub fn check_sequence(&self, peer_id: &[u8; 32], sequence: u64) -> Result<(), MeshError> {
+        let now = now_secs();
+        self.expire_queued(now);
+        let reordered = self.check_window(peer_id, sequence)?;
+        self.make_room(peer_id)?;
+        self.accept_sequence(peer_id, sequence, now, reordered);
+        Ok(())
+    }
+
+    /// Check a sequence against the peer's window (without recording it)
+    ///
+    /// Returns whether it arrived out of order.
+    fn check_window(&self, peer_id: &[u8; 32], sequence: u64) -> Result<bool, MeshError> {
+        let Some(window) = self.used_sequences.get(peer_id) else {
+            return Ok(false);
+        };
+        if let Err(reason) = window.check(sequence, self.window_size) {
+            // Rejected sequences are never above the window's highest
+            let counter = if window.last_sequence - sequence >= self.window_size {
+                &self.stale_sequences
+            } else {
+                &self.duplicate_sequences
+            };
+            counter.fetch_add(1, Ordering::Relaxed);
+            return Err(MeshError::ReplayDetected(reason));
+        }
+        Ok(sequence < window.last_sequence)
+    }
+
+    /// Record an accepted sequence in the peer's window
+    fn accept_sequence(&self, peer_id: &[u8; 32], sequence: u64, now: u64, reordered: bool) {
+        self.used_sequences
+            .entry(*peer_id)
+            .and_modify(|window| {
+                window.accept(sequence, self.window_size);
+                window.last_seen = now;
+            })
+            .or_insert_with(|| SequenceWindow::new(sequence, self.window_size));
+        if reordered {
+            self.out_of_order.fetch_add(1, Ordering::Relaxed);
+        }
+        if self.storage.is_some() {
+            self.pending.lock().unwrap().peers.insert(*peer_id);
+        }
+    }
+
+    /// Make room for a new proof hash (and peer) under the entry cap
+    fn make_room(&self, peer_id: &[u8; 32]) -> Result<(), MeshError> {
+        let hashes_full = self.replay_data.len() >= self.max_entries;
+        let peers_full =
+            !self.used_sequences.contains_key(peer_id) && self.used_sequences.len() >= self.max_entries;
+        if !hashes_full && !peers_full {
+            return Ok(());
+        }
+        if self.overflow == ReplayOverflowPolicy::Reject {
+            return Err(MeshError::CapacityExceeded(format!(
+                "Replay cache full ({} entries)",
+                self.max_entries
+            )));
+        }
+
+        let mut queue = self.expiry_queue.lock().unwrap();
+        while self.replay_data.len() >= self.max_entries {
+            let Some((timestamp, oldest)) = queue.pop_front() else {
+                break;
+            };
+            if self.remove_queued(timestamp, &oldest) {
+                self.record_eviction(|pending| {
+                    pending.hashes.insert(oldest);
+                });
+            }
+        }
+        drop(queue);
+        while !self.used_sequences.contains_key(peer_id) && self.used_sequences.len() >= self.max_entries {
+            let Some(stalest) = self
+                .used_sequences
+                .iter()
+                .min_by_key(|entry| entry.value().last_seen)
+                .map(|entry| *entry.key())
+            else {
+                break;
+            };
+            self.used_sequences.remove(&stalest);
+            self.record_eviction(|pending| {
+                pending.peers.insert(stalest);
+            });
+        }
+        Ok(())
+    }
+
+    /// Count an eviction, queueing its removal from storage
+    fn record_eviction(&self, mark: impl FnOnce(&mut PendingWrites)) {
+        self.evictions.fetch_add(1, Ordering::Relaxed);
+        if self.storage.is_some() {
+            mark(&mut *self.pending.lock().unwrap());
+        }
+    }
+
+    /// Remove hashes at the front of the queue that have expired
+    fn expire_queued(&self, now: u64) {
+        let mut queue = self.expiry_queue.lock().unwrap();
+        let mut expired = Vec::new();
+        while let Some(&(timestamp, hash)) = queue.front() {
+            if now <= timestamp + self.expiry_seconds {
+                break;
+            }
+            queue.pop_front();
+            if self.remove_queued(timestamp, &hash) {
+                expired.push(hash);
+            }
+        }
+        drop(queue);
+
+        if !expired.is_empty() {
+            debug!("Expired {} payment proof hashes", expired.len());
+            if self.storage.is_some() {
+                self.pending.lock().unwrap().hashes.extend(expired);
+            }
+        }
+    }
+
+    /// Remove a queued hash unless it was already removed or re-inserted later
+    fn remove_queued(&self, timestamp: u64, hash: &[u8; 32]) -> bool {
+        self.replay_data
+            .remove_if(hash, |_, entry| entry.timestamp == timestamp)
+            .is_some()
     }
 
     /// Clean up expired hashes
     ///
-    /// Removes hashes that are older than expiry_seconds.
+    /// Removes hashes that are older than expiry_seconds. This scans the
+    /// whole table; `check_replay` expires hashes incrementally, so this is
+    /// only a periodic backstop.
     /// Lock-free operation using DashMap - no mut needed.
+    ///
+    /// In persistent mode the removals are pruned from storage on the next
+    /// flush.
     pub fn cleanup_expired(&self) {
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
+        let now = now_secs();
 
         let mut expired_hashes = Vec::new();
         // Lock-free iteration
@@ -127,6 +763,9 @@ impl ReplayPrevention {
             for hash in &expired_hashes {
                 self.replay_data.remove(hash);
             }
+            if self.storage.is_some() {
+                self.pending.lock().unwrap().hashes.extend(expired_hashes);
+            }
         }
     }
 
@@ -134,22 +773,285 @@ impl ReplayPrevention {
     ///
     /// Lock-free reads using DashMap - no mut needed.
     pub fn stats(&self) -> ReplayStats {
+        let active_hashes = self.replay_data.len();
+        let tracked_peers = self.used_sequences.len();
         ReplayStats {
-            active_hashes: self.replay_data.len(),
-            tracked_peers: self.used_sequences.len(),
+            active_hashes,
+            tracked_peers,
+            window_size: self.window_size,
             expiry_seconds: self.expiry_seconds,
+            max_entries: self.max_entries,
+            evictions: self.evictions.load(Ordering::Relaxed),
+            utilization: active_hashes.max(tracked_peers) as f64 / self.max_entries as f64,
+            out_of_order: self.out_of_order.load(Ordering::Relaxed),
+            duplicate_sequences: self.duplicate_sequences.load(Ordering::Relaxed),
+            stale_sequences: self.stale_sequences.load(Ordering::Relaxed),
         }
     }
 }
 
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>, MeshError> {
+    bincode::serialize(value)
+        .map_err(|e| MeshError::ModuleError(format!("Failed to encode replay state: {}", e)))
+}
+
 /// Statistics about replay prevention
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ReplayStats {
     /// Number of active (non-expired) payment proof hashes
     pub active_hashes: usize,
     /// Number of tracked peers
     pub tracked_peers: usize,
+    /// Per-peer sequence window size
+    pub window_size: u64,
     /// Expiry time in seconds
     pub expiry_seconds: u64,
+    /// Cap on proof hashes and on tracked peers
+    pub max_entries: usize,
+    /// Entries evicted to stay under the cap
+    pub evictions: u64,
+    /// Fill level of the fuller of the two caches (0.0 to 1.0)
+    pub utilization: f64,
+    /// Sequences accepted out of order within the window
+    pub out_of_order: u64,
+    /// Sequences rejected as already seen
+    pub duplicate_sequences: u64,
+    /// Sequences rejected as older than the window
+    pub stale_sequences: u64,
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WINDOW: u64 = 64;
+
+    fn accept(window: &mut SequenceWindow, sequence: u64) -> Result<(), String> {
+        window.check(sequence, WINDOW)?;
+        window.accept(sequence, WINDOW);
+        Ok(())
+    }
+
+    #[test]
+    fn test_reordering_within_window_accepted() {
+        let mut window = SequenceWindow::new(5, WINDOW);
+        for sequence in [6, 8, 7] {
+            assert!(accept(&mut window, sequence).is_ok());
+        }
+        assert_eq!(window.last_sequence, 8);
+    }
+
+    #[test]
+    fn test_duplicates_rejected() {
+        let mut window = SequenceWindow::new(5, WINDOW);
+        accept(&mut window, 8).unwrap();
+        assert!(accept(&mut window, 8).is_err());
+        assert!(accept(&mut window, 5).is_err());
+        assert!(accept(&mut window, 6).is_ok());
+        assert!(accept(&mut window, 6).is_err());
+    }
+
+    #[test]
+    fn test_too_old_rejected_and_window_slides() {
+        let mut window = SequenceWindow::new(100, WINDOW);
+        assert!(accept(&mut window, 100 - WINDOW).is_err());
+        assert!(accept(&mut window, 100 - WINDOW + 1).is_ok());
+
+        // Advancing past a whole window clears stale bits
+        accept(&mut window, 100 + 3 * WINDOW).unwrap();
+        assert!(accept(&mut window, 100 + 2 * WINDOW + 1).is_ok());
+        assert!(accept(&mut window, 100 + 2 * WINDOW).is_err());
+    }
+
+    #[test]
+    fn test_non_power_of_two_window() {
+        let mut window = SequenceWindow::new(0, 100);
+        for sequence in (51..=150).rev().step_by(3) {
+            window.check(sequence, 100).unwrap();
+            window.accept(sequence, 100);
+        }
+        assert!(window.check(150, 100).is_err());
+        assert!(window.check(149, 100).is_ok());
+        assert!(window.check(50, 100).is_err());
+    }
+
+    fn proof(index: u32) -> PaymentProof {
+        let now = now_secs();
+        PaymentProof::Lightning {
+            invoice: format!("lnbc10n1test{}", index),
+            preimage: [0u8; 32],
+            amount_msats: 10_000,
+            timestamp: now,
+            expires_at: now + 3600,
+        }
+    }
+
+    fn capped(max_entries: usize, overflow: ReplayOverflowPolicy) -> ReplayPrevention {
+        ReplayPrevention::new(
+            ReplayWindowConfig::default()
+                .with_max_entries(max_entries)
+                .with_overflow_policy(overflow),
+        )
+    }
+
+    #[test]
+    fn test_eviction_bounds_memory() {
+        let replay = capped(10, ReplayOverflowPolicy::EvictOldest);
+        for index in 0..15 {
+            replay.check_replay(&proof(index), &[1u8; 32], index as u64).unwrap();
+        }
+
+        let stats = replay.stats();
+        assert_eq!(stats.active_hashes, 10);
+        assert_eq!(stats.evictions, 5);
+        assert_eq!(stats.utilization, 1.0);
+
+        // The most recent proof is still remembered
+        assert!(matches!(
+            replay.check_replay(&proof(14), &[1u8; 32], 100),
+            Err(MeshError::ReplayDetected(_))
+        ));
+    }
+
+    #[test]
+    fn test_tracked_peers_capped() {
+        let replay = capped(3, ReplayOverflowPolicy::EvictOldest);
+        replay.check_replay(&proof(0), &[0u8; 32], 0).unwrap();
+        for peer in 1..5u8 {
+            replay.check_replay(&proof(peer as u32), &[peer; 32], 0).unwrap();
+        }
+        assert_eq!(replay.stats().tracked_peers, 3);
+    }
+
+    #[test]
+    fn test_reject_policy_applies_backpressure() {
+        let replay = capped(2, ReplayOverflowPolicy::Reject);
+        replay.check_replay(&proof(0), &[1u8; 32], 0).unwrap();
+        replay.check_replay(&proof(1), &[1u8; 32], 1).unwrap();
+
+        let full = replay.check_replay(&proof(2), &[1u8; 32], 2).unwrap_err();
+        assert!(matches!(full, MeshError::CapacityExceeded(_)));
+        assert!(full.is_retriable());
+        assert_eq!(replay.stats().evictions, 0);
+        // Replays are still reported as such
+        assert!(matches!(
+            replay.check_replay(&proof(0), &[1u8; 32], 3),
+            Err(MeshError::ReplayDetected(_))
+        ));
+    }
+
+    #[test]
+    fn test_config_builder() {
+        let config = ReplayWindowConfig::default()
+            .with_window_size(0)
+            .with_expiry_seconds(60);
+        assert_eq!(config.window_size, 1);
+        assert_eq!(config.expiry_seconds, 60);
+        assert_eq!(ReplayPrevention::new(config).stats().window_size, 1);
+    }
+
+    #[test]
+    fn test_check_cost_independent_of_table_size() {
+        fn time_checks(replay: &ReplayPrevention, first: u32) -> std::time::Duration {
+            let start = std::time::Instant::now();
+            for index in first..first + 500 {
+                replay.check_replay(&proof(index), &[2u8; 32], index as u64).unwrap();
+            }
+            start.elapsed()
+        }
+
+        let small = ReplayPrevention::new(ReplayWindowConfig::default());
+        let large = ReplayPrevention::new(ReplayWindowConfig::default());
+        for index in 0..50_000 {
+            large.check_replay(&proof(index), &[1u8; 32], index as u64).unwrap();
+        }
+
+        let small_elapsed = time_checks(&small, 100_000);
+        let large_elapsed = time_checks(&large, 100_000);
+        // A full scan per check would make the large table ~100x slower
+        assert!(
+            large_elapsed < small_elapsed * 10 + std::time::Duration::from_millis(20),
+            "small={:?} large={:?}",
+            small_elapsed,
+            large_elapsed
+        );
+    }
+
+    #[test]
+    fn test_expired_hashes_leave_queue_and_table() {
+        let replay = ReplayPrevention::new(ReplayWindowConfig::default().with_expiry_seconds(60));
+        replay.check_replay(&proof(0), &[1u8; 32], 0).unwrap();
+        replay.check_replay(&proof(1), &[1u8; 32], 1).unwrap();
+
+        replay.expire_queued(now_secs() + 61);
+        assert_eq!(replay.stats().active_hashes, 0);
+        assert!(replay.expiry_queue.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_sequence_check_shares_window() {
+        let replay = ReplayPrevention::new(ReplayWindowConfig::default());
+        let peer = [1u8; 32];
+        replay.check_replay(&proof(0), &peer, 1).unwrap();
+
+        // Balance packets carry no proof but still use up sequences
+        replay.check_sequence(&peer, 2).unwrap();
+        assert!(matches!(replay.check_sequence(&peer, 2), Err(MeshError::ReplayDetected(_))));
+        assert!(matches!(replay.check_sequence(&peer, 1), Err(MeshError::ReplayDetected(_))));
+        assert!(replay.check_replay(&proof(1), &peer, 2).is_err());
+        assert_eq!(replay.stats().active_hashes, 1);
+    }
+
+    #[test]
+    fn test_released_reservation_leaves_proof_unused() {
+        let replay = ReplayPrevention::new(ReplayWindowConfig::default());
+        let peer = [1u8; 32];
+
+        let reservation = replay.reserve(&proof(0), &peer, 1).unwrap();
+        // Held while verifying: a concurrent copy is refused
+        assert!(matches!(replay.reserve(&proof(0), &peer, 2), Err(MeshError::ReplayDetected(_))));
+        replay.release(reservation);
+        assert_eq!(replay.stats().active_hashes, 0);
+
+        // Neither the proof nor the sequence was burned
+        let reservation = replay.reserve(&proof(0), &peer, 1).unwrap();
+        replay.commit(reservation).unwrap();
+        assert!(replay.check_replay(&proof(0), &peer, 2).is_err());
+        assert!(replay.check_sequence(&peer, 1).is_err());
+    }
+
+    #[test]
+    fn test_commit_fails_if_sequence_taken_meanwhile() {
+        let replay = ReplayPrevention::new(ReplayWindowConfig::default());
+        let peer = [1u8; 32];
+
+        let reservation = replay.reserve(&proof(0), &peer, 1).unwrap();
+        replay.check_sequence(&peer, 1).unwrap();
+        assert!(matches!(replay.commit(reservation), Err(MeshError::ReplayDetected(_))));
+        assert_eq!(replay.stats().active_hashes, 0);
+    }
+
+    #[test]
+    fn test_window_statistics() {
+        let replay = ReplayPrevention::new(ReplayWindowConfig::default().with_window_size(8));
+        let peer = [1u8; 32];
+        for (index, sequence) in [10u64, 12, 11].into_iter().enumerate() {
+            replay.check_replay(&proof(index as u32), &peer, sequence).unwrap();
+        }
+        assert!(replay.check_replay(&proof(3), &peer, 11).is_err());
+        assert!(replay.check_replay(&proof(4), &peer, 2).is_err());
+
+        let stats = replay.stats();
+        assert_eq!(stats.window_size, 8);
+        assert_eq!(stats.out_of_order, 1);
+        assert_eq!(stats.duplicate_sequences, 1);
+        assert_eq!(stats.stale_sequences, 1);
+    }
+}
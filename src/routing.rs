@@ -4,16 +4,100 @@
 //! fee calculation, and multi-hop routing.
 
 use crate::error::MeshError;
+use crate::gossip::{false_positive_rate, BloomFilter};
+use crate::routing_policy::FeePolicy;
+use crate::storage_schema::{iter_prefix, open_versioned_tree, Migration, PrefixStorage, TreeSchema};
+use bllvm_node::module::ipc::protocol::StorageOperation;
+use bllvm_node::module::traits::NodeAPI;
 use dashmap::DashMap;
-use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::sync::{Arc, Mutex};
 use std::time::{SystemTime, UNIX_EPOCH};
 use tracing::{debug, info, warn};
 
 /// Node ID (32 bytes, SHA256 of public key)
 pub type NodeId = [u8; 32];
 
+/// Maximum number of computed routes offered by `route_candidates`
+const MAX_ROUTE_CANDIDATES: usize = 4;
+
+/// Weight of the latest delivery outcome in a route's quality score (EWMA)
+const QUALITY_EWMA_WEIGHT: f64 = 0.5;
+
+/// Default false-positive rate the route filter is sized for
+/// (`mesh.route_filter_false_positive_rate`)
+pub const DEFAULT_BLOOM_FALSE_POSITIVE_RATE: f64 = 0.001;
+
+/// Nodes the route filter is sized for at first; it is rebuilt for twice
+/// the known nodes whenever it fills up
+const ROUTE_FILTER_MIN_CAPACITY: usize = 1024;
+
+/// Default quality below which learned routes are dropped
+pub const DEFAULT_QUALITY_FLOOR: f64 = 0.2;
+
+/// Default quality below which idle learned routes are pruned (`mesh.route_prune_quality`)
+pub const DEFAULT_PRUNE_QUALITY: f64 = 0.3;
+
+/// Default time a learned route must go unrefreshed before it can be pruned (`mesh.route_prune_min_age_secs`)
+pub const DEFAULT_PRUNE_MIN_AGE_SECONDS: u64 = 10 * 60;
+
+/// Default number of candidate routes kept per destination
+pub const DEFAULT_MAX_ROUTES_PER_DESTINATION: usize = 3;
+
+/// Cost added per hop when ranking candidate routes
+const ROUTE_HOP_PENALTY: u64 = 10;
+
+/// Round trip at which latency neither raises nor lowers route quality
+pub const LATENCY_REFERENCE_MS: f64 = 100.0;
+
+/// Largest quality change from one latency measurement
+const MAX_LATENCY_QUALITY_STEP: f64 = 0.05;
+
+/// Storage tree for learned routes
+const ROUTES_TREE: &str = "mesh_routes";
+
+/// Key prefix of routes in the routes tree (followed by the hex node ID)
+pub const ROUTE_KEY_PREFIX: &[u8] = b"route/";
+
+/// Schema of the routes tree
+///
+/// v3: a bincode `Vec<RoutingEntry>` of candidate routes per destination,
+/// keyed `route/{node_id_hex}` (v2 keyed by the raw node ID, v1 held a
+/// single `RoutingEntry`).
+pub const ROUTES_SCHEMA: TreeSchema = TreeSchema {
+    tree: ROUTES_TREE,
+    version: 3,
+    migrations: &[
+        Migration {
+            from: 1,
+            description: "store candidate routes per destination",
+            migrate: migrate_single_routes_v1,
+        },
+        Migration {
+            from: 2,
+            description: "key routes by route/{node_id_hex}",
+            migrate: migrate_route_keys_v2,
+        },
+    ],
+};
+
+/// Storage key of a destination's routes
+fn route_key(node_id: &NodeId) -> Vec<u8> {
+    let mut key = ROUTE_KEY_PREFIX.to_vec();
+    key.extend_from_slice(hex::encode(node_id).as_bytes());
+    key
+}
+
+/// Destination of a `route/{node_id_hex}` key
+fn route_key_node_id(key: &[u8]) -> Option<NodeId> {
+    let hex_id = key.strip_prefix(ROUTE_KEY_PREFIX)?;
+    hex::decode(hex_id).ok()?.try_into().ok()
+}
+
 /// Routing entry for a mesh node
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RoutingEntry {
     /// Node ID
     pub node_id: NodeId,
@@ -31,24 +115,117 @@ pub struct RoutingEntry {
     pub quality_score: f64,
 }
 
+/// Order of candidate routes whose scores are equal
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TieBreak {
+    /// Prefer the most recently updated route
+    #[default]
+    Newest,
+    /// Prefer the route with fewer hops
+    FewestHops,
+}
+
+impl TieBreak {
+    /// Config name of the policy (`mesh.route_tie_break`)
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TieBreak::Newest => "newest",
+            TieBreak::FewestHops => "fewest_hops",
+        }
+    }
+
+    /// Parse a config name
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "newest" => Some(TieBreak::Newest),
+            "fewest_hops" => Some(TieBreak::FewestHops),
+            _ => None,
+        }
+    }
+
+    fn compare(&self, a: &RoutingEntry, b: &RoutingEntry) -> Ordering {
+        match self {
+            TieBreak::Newest => b.last_updated.cmp(&a.last_updated),
+            TieBreak::FewestHops => a.route_path.len().cmp(&b.route_path.len()),
+        }
+    }
+}
+
 /// Routing table for mesh networking
 ///
+/// Keeps up to `max_routes_per_destination` candidate routes per
+/// destination, best first: direct peers, then the lowest score
+/// (cost plus a per-hop penalty, divided by quality), then the tie-break
+/// policy. A better route learned later no longer overwrites a working
+/// one, and a failing best route hands over to the next candidate.
+///
 /// Uses DashMap for lock-free concurrent access, providing better performance
-/// than RwLock<HashMap> for read-heavy workloads.
+/// than RwLock<HashMap> for read-heavy workloads. A bloom filter of every
+/// node on a known route answers lookups of unknown destinations without
+/// scanning the routes.
 pub struct RoutingTable {
-    /// Routing entries (node_id -> RoutingEntry)
+    /// Candidate routes, best first (node_id -> [RoutingEntry])
     /// Lock-free concurrent reads, no async needed
-    routes: Arc<DashMap<NodeId, RoutingEntry>>,
+    routes: Arc<DashMap<NodeId, Vec<RoutingEntry>>>,
     /// Direct peers (node_id -> address)
     /// Lock-free concurrent reads, no async needed
     direct_peers: Arc<DashMap<NodeId, Vec<u8>>>,
     /// Route discovery cache (destination -> route)
     /// Lock-free concurrent reads, no async needed
     route_cache: Arc<DashMap<NodeId, Vec<NodeId>>>,
+    /// Cached destinations by the nodes on their cached route (node_id -> destinations)
+    cache_index: Arc<DashMap<NodeId, HashSet<NodeId>>>,
     /// Route expiry time (default: 1 hour)
     route_expiry_seconds: u64,
+    /// This node's ID (leading element of multi-hop route paths)
+    local_node_id: Option<NodeId>,
+    /// Delivery outcomes per destination (node_id -> counts)
+    outcomes: Arc<DashMap<NodeId, RouteOutcomes>>,
+    /// Quality below which learned routes are dropped
+    quality_floor: f64,
+    /// Candidate routes kept per destination
+    max_routes_per_destination: usize,
+    /// Order of equally scored candidates
+    tie_break: TieBreak,
+    /// Nodes on known routes (no false negatives; removed nodes linger
+    /// until the next cleanup rebuilds it)
+    route_filter: Mutex<RouteFilter>,
+    /// False-positive rate the route filter is sized for
+    bloom_false_positive_rate: f64,
+    /// Node-side prefix scans for `save`/`load` (None = filter here)
+    prefix_storage: Option<Arc<dyn PrefixStorage>>,
+}
+
+/// Bloom filter of routable nodes and the node count it was sized for
+struct RouteFilter {
+    filter: BloomFilter,
+    capacity: usize,
+}
+
+impl RouteFilter {
+    fn new(capacity: usize, false_positive_rate: f64) -> Self {
+        Self {
+            filter: BloomFilter::with_false_positive_rate(capacity, false_positive_rate),
+            capacity,
+        }
+    }
 }
 
+/// Delivery outcomes recorded for a destination
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RouteOutcomes {
+    /// Packets forwarded or acknowledged
+    pub successes: u64,
+    /// Packets that could not be forwarded
+    pub failures: u64,
+}
+
+/// Vertex in the route graph (None = this node)
+pub(crate) type Vertex = Option<NodeId>;
+
+/// Route graph built from routing entries (vertex -> [(neighbor, link cost)])
+pub(crate) type RouteGraph = HashMap<Vertex, Vec<(Vertex, u64)>>;
+
 impl RoutingTable {
     /// Create a new routing table
     pub fn new(route_expiry_seconds: u64) -> Self {
@@ -56,10 +233,62 @@ impl RoutingTable {
             routes: Arc::new(DashMap::new()),
             direct_peers: Arc::new(DashMap::new()),
             route_cache: Arc::new(DashMap::new()),
+            cache_index: Arc::new(DashMap::new()),
             route_expiry_seconds,
+            local_node_id: None,
+            outcomes: Arc::new(DashMap::new()),
+            quality_floor: DEFAULT_QUALITY_FLOOR,
+            max_routes_per_destination: DEFAULT_MAX_ROUTES_PER_DESTINATION,
+            tie_break: TieBreak::default(),
+            route_filter: Mutex::new(RouteFilter::new(
+                ROUTE_FILTER_MIN_CAPACITY,
+                DEFAULT_BLOOM_FALSE_POSITIVE_RATE,
+            )),
+            bloom_false_positive_rate: DEFAULT_BLOOM_FALSE_POSITIVE_RATE,
+            prefix_storage: None,
         }
     }
 
+    /// Read stored routes with node-side prefix scans
+    pub fn with_prefix_storage(mut self, prefix_storage: Option<Arc<dyn PrefixStorage>>) -> Self {
+        self.prefix_storage = prefix_storage;
+        self
+    }
+
+    /// Set the false-positive rate the route filter is sized for
+    pub fn with_bloom_false_positive_rate(mut self, rate: f64) -> Self {
+        self.bloom_false_positive_rate = rate.clamp(1e-6, 0.5);
+        self.rebuild_route_filter();
+        self
+    }
+
+    /// Set how many candidate routes are kept per destination (at least 1)
+    pub fn with_max_routes_per_destination(mut self, max_routes: usize) -> Self {
+        self.max_routes_per_destination = max_routes.max(1);
+        self
+    }
+
+    /// Set the order of candidate routes whose scores are equal
+    pub fn with_tie_break(mut self, tie_break: TieBreak) -> Self {
+        self.tie_break = tie_break;
+        self
+    }
+
+    /// Set the quality below which learned routes are dropped
+    pub fn with_quality_floor(mut self, quality_floor: f64) -> Self {
+        self.quality_floor = quality_floor.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Set this node's ID, so route paths starting with it are recognized
+    ///
+    /// Without it, the first element of a multi-hop path is assumed to be
+    /// this node when it has no routing entry of its own.
+    pub fn with_local_node_id(mut self, node_id: NodeId) -> Self {
+        self.local_node_id = Some(node_id);
+        self
+    }
+
     /// Add or update a direct peer
     ///
     /// Lock-free operation using DashMap - no async needed
@@ -73,18 +302,15 @@ impl RoutingTable {
             .unwrap()
             .as_secs();
         
-        self.routes.insert(
+        self.insert_candidate(RoutingEntry {
             node_id,
-            RoutingEntry {
-                node_id,
-                direct_address: Some(address),
-                next_hop: None, // Direct connection
-                route_path: vec![node_id],
-                route_cost: 0, // Direct connections have no routing cost
-                last_updated: now,
-                quality_score: 1.0, // Direct connections have perfect quality
-            },
-        );
+            direct_address: Some(address),
+            next_hop: None, // Direct connection
+            route_path: vec![node_id],
+            route_cost: 0, // Direct connections have no routing cost
+            last_updated: now,
+            quality_score: 1.0, // Direct connections have perfect quality
+        });
         
         debug!("Added direct peer: node_id={:x?}", &node_id[..8]);
     }
@@ -95,86 +321,707 @@ impl RoutingTable {
     pub fn remove_direct_peer(&self, node_id: &NodeId) {
         // Lock-free remove
         self.direct_peers.remove(node_id);
+        self.invalidate_cached_routes(node_id);
         
-        // Remove routing entry if it was direct-only (lock-free)
-        if let Some(entry) = self.routes.get(node_id) {
-            if entry.direct_address.is_some() && entry.next_hop.is_none() {
-                self.routes.remove(node_id);
-                debug!("Removed direct peer: node_id={:x?}", &node_id[..8]);
+        // Keep learned routes to the node (lock-free)
+        if let Some(mut candidates) = self.routes.get_mut(node_id) {
+            candidates.retain(is_learned);
+        }
+        if self.routes.remove_if(node_id, |_, candidates| candidates.is_empty()).is_some() {
+            self.outcomes.remove(node_id);
+        }
+        debug!("Removed direct peer: node_id={:x?}", &node_id[..8]);
+    }
+
+    /// Remove every learned route whose next hop is `next_hop`
+    ///
+    /// Used when the next hop is gone. Returns the destinations left
+    /// without any route.
+    pub fn remove_routes_via(&self, next_hop: &NodeId) -> Vec<NodeId> {
+        let destinations: Vec<NodeId> = self.routes.iter().map(|candidates| *candidates.key()).collect();
+        self.remove_routes_to_via(&destinations, next_hop)
+    }
+
+    /// Remove the learned routes to `destinations` whose next hop is `next_hop`
+    ///
+    /// Used when the next hop reports it can no longer reach them. Returns
+    /// the destinations that had such a route and are now left without any.
+    pub fn remove_routes_to_via(&self, destinations: &[NodeId], next_hop: &NodeId) -> Vec<NodeId> {
+        let (removed, unreachable) =
+            self.remove_learned_routes(destinations, |entry| entry.next_hop.as_ref() == Some(next_hop));
+        self.invalidate_cached_routes(next_hop);
+
+        if removed > 0 {
+            debug!(
+                "Removed {} routes via {:x?}, {} destinations unreachable",
+                removed,
+                &next_hop[..8],
+                unreachable.len()
+            );
+        }
+        unreachable
+    }
+
+    /// Remove the learned routes to `destinations` that pass through `node`
+    ///
+    /// Used when `node` withdraws its routes before going offline. Returns
+    /// the destinations left without any route.
+    pub fn remove_routes_through(&self, destinations: &[NodeId], node: &NodeId) -> Vec<NodeId> {
+        let (removed, unreachable) = self.remove_learned_routes(destinations, |entry| {
+            entry.node_id == *node || self.route_nodes(&entry.route_path).contains(node)
+        });
+        self.invalidate_cached_routes(node);
+
+        if removed > 0 {
+            debug!(
+                "Removed {} routes through {:x?}, {} destinations unreachable",
+                removed,
+                &node[..8],
+                unreachable.len()
+            );
+        }
+        unreachable
+    }
+
+    /// Remove the learned routes to `destination` advertised by `advertiser`
+    ///
+    /// Used when the advertiser withdraws its route. Returns whether any
+    /// route was removed.
+    pub fn withdraw_routes(&self, destination: &NodeId, advertiser: &NodeId) -> bool {
+        let (removed, _) = self.remove_learned_routes(&[*destination], |entry| {
+            entry.route_path.first() == Some(advertiser)
+        });
+        if removed > 0 {
+            debug!(
+                "Withdrew {} routes to {:x?} advertised by {:x?}",
+                removed,
+                &destination[..8],
+                &advertiser[..8]
+            );
+        }
+        removed > 0
+    }
+
+    /// Remove the learned routes to `destinations` matching `remove`
+    ///
+    /// Returns the number of routes removed and the destinations left
+    /// without any route.
+    fn remove_learned_routes(
+        &self,
+        destinations: &[NodeId],
+        remove: impl Fn(&RoutingEntry) -> bool,
+    ) -> (usize, Vec<NodeId>) {
+        let mut unreachable = Vec::new();
+        let mut removed = Vec::new();
+        for destination in destinations {
+            let Some(mut candidates) = self.routes.get_mut(destination) else {
+                continue;
+            };
+            let before = candidates.len();
+            candidates.retain(|entry| {
+                let keep = !is_learned(entry) || !remove(entry);
+                if !keep {
+                    removed.push(entry.route_path.clone());
+                }
+                keep
+            });
+            let emptied = candidates.is_empty() && before > 0;
+            drop(candidates);
+            if emptied && self.routes.remove_if(destination, |_, candidates| candidates.is_empty()).is_some() {
+                self.outcomes.remove(destination);
+                unreachable.push(*destination);
+            }
+        }
+
+        for route_path in &removed {
+            for node_id in self.route_nodes(route_path) {
+                self.invalidate_cached_routes(node_id);
             }
         }
+        (removed.len(), unreachable)
     }
 
-    /// Add or update a routing entry
+    /// Get all direct peers with their addresses
     ///
+    /// Lock-free reads using DashMap - no async needed
+    pub fn direct_peer_addresses(&self) -> Vec<(NodeId, Vec<u8>)> {
+        self.direct_peers
+            .iter()
+            .map(|entry| (*entry.key(), entry.value().clone()))
+            .collect()
+    }
+
+    /// Add or update a candidate route
+    ///
+    /// A candidate with the same route path is replaced. Once the
+    /// destination has `max_routes_per_destination` candidates, the new
+    /// route replaces the worst one if it ranks better and is dropped
+    /// otherwise.
     /// Lock-free operation using DashMap - no async needed
     pub fn add_route(&self, entry: RoutingEntry) {
-        // Lock-free insert
-        self.routes.insert(entry.node_id, entry.clone());
-        debug!("Added route: node_id={:x?}", &entry.node_id[..8]);
+        let node_id = entry.node_id;
+        if self.insert_candidate(entry) {
+            debug!("Added route: node_id={:x?}", &node_id[..8]);
+        } else {
+            debug!("Ignored route worse than all candidates: node_id={:x?}", &node_id[..8]);
+        }
     }
 
-    /// Get routing entry for a node
+    /// Insert a candidate route, returning whether it was kept
+    ///
+    /// Cached routes to or through the nodes on its path are forgotten,
+    /// since the new links may rank differently.
+    fn insert_candidate(&self, entry: RoutingEntry) -> bool {
+        let destination = entry.node_id;
+        let route_path = entry.route_path.clone();
+        let mut candidates = self.routes.entry(entry.node_id).or_default();
+        if let Some(existing) = candidates.iter_mut().find(|existing| existing.route_path == entry.route_path) {
+            *existing = entry;
+        } else if candidates.len() < self.max_routes_per_destination {
+            candidates.push(entry);
+        } else {
+            let worst = candidates.last_mut().expect("candidate list is full");
+            if self.rank(&entry, worst) != Ordering::Less {
+                return false;
+            }
+            *worst = entry;
+        }
+        candidates.sort_by(|a, b| self.rank(a, b));
+        drop(candidates);
+        // After the insert, so a concurrent rebuild cannot drop these nodes
+        self.remember_route_nodes(&destination, &route_path);
+        for node_id in self.route_nodes(&route_path) {
+            self.invalidate_cached_routes(node_id);
+        }
+        true
+    }
+
+    /// Whether any route may reach `destination` (false: certainly none)
+    fn may_reach(&self, destination: &NodeId) -> bool {
+        self.route_filter.lock().unwrap().filter.contains(destination)
+    }
+
+    /// Add a destination and the nodes on its route to the route filter,
+    /// growing the filter once it holds more nodes than it was sized for
+    fn remember_route_nodes(&self, destination: &NodeId, route_path: &[NodeId]) {
+        let mut route_filter = self.route_filter.lock().unwrap();
+        for node_id in std::iter::once(destination).chain(route_path) {
+            if !route_filter.filter.contains(node_id) {
+                route_filter.filter.insert(node_id);
+            }
+        }
+        if route_filter.filter.len() > route_filter.capacity {
+            self.fill_route_filter(&mut route_filter);
+        }
+    }
+
+    /// Rebuild the route filter from the current routes, dropping removed
+    /// nodes
+    fn rebuild_route_filter(&self) {
+        let mut route_filter = self.route_filter.lock().unwrap();
+        self.fill_route_filter(&mut route_filter);
+    }
+
+    /// Replace the route filter with one sized for twice the routable nodes
+    ///
+    /// Called with the filter locked, so routes inserted meanwhile wait and
+    /// land in the new filter.
+    fn fill_route_filter(&self, route_filter: &mut RouteFilter) {
+        let nodes: HashSet<NodeId> = self
+            .routes
+            .iter()
+            .flat_map(|candidates| {
+                candidates
+                    .value()
+                    .iter()
+                    .flat_map(|entry| std::iter::once(entry.node_id).chain(entry.route_path.iter().copied()))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        *route_filter = RouteFilter::new(
+            (nodes.len() * 2).max(ROUTE_FILTER_MIN_CAPACITY),
+            self.bloom_false_positive_rate,
+        );
+        for node_id in &nodes {
+            route_filter.filter.insert(node_id);
+        }
+    }
+
+    /// Order two candidate routes to the same destination, best first
+    fn rank(&self, a: &RoutingEntry, b: &RoutingEntry) -> Ordering {
+        is_learned(a)
+            .cmp(&is_learned(b))
+            .then_with(|| candidate_score(a).total_cmp(&candidate_score(b)))
+            .then_with(|| self.tie_break.compare(a, b))
+    }
+
+    /// Record a successful delivery toward `node_id`
+    ///
+    /// Moves the best candidate's quality score toward 1.0 (exponentially
+    /// weighted). Returns the new score, or `None` if there is no route.
+    pub fn record_success(&self, node_id: &NodeId) -> Option<f64> {
+        let mut candidates = self.routes.get_mut(node_id)?;
+        let best = candidates.first_mut()?;
+        best.quality_score += QUALITY_EWMA_WEIGHT * (1.0 - best.quality_score);
+        let quality = best.quality_score;
+        self.outcomes.entry(*node_id).or_default().successes += 1;
+        Some(quality)
+    }
+
+    /// Record a failed delivery toward `node_id`
+    ///
+    /// Moves the best candidate's quality score toward 0.0, re-ranks the
+    /// candidates and forgets cached routes to or through the node, which
+    /// may now rank differently. A learned candidate whose score falls below the quality
+    /// floor is removed, so traffic fails over to the next candidate (or
+    /// discovery starts if none is left). Returns the failed candidate's
+    /// new score, or `None` if it was removed.
+    pub fn record_failure(&self, node_id: &NodeId) -> Option<f64> {
+        self.invalidate_cached_routes(node_id);
+        let quality = {
+            let mut candidates = self.routes.get_mut(node_id)?;
+            let best = candidates.first_mut()?;
+            best.quality_score -= QUALITY_EWMA_WEIGHT * best.quality_score;
+            let quality = best.quality_score;
+            self.outcomes.entry(*node_id).or_default().failures += 1;
+            if quality >= self.quality_floor || !is_learned(best) {
+                candidates.sort_by(|a, b| self.rank(a, b));
+                return Some(quality);
+            }
+            candidates.remove(0);
+            quality
+        };
+        if self.routes.remove_if(node_id, |_, candidates| candidates.is_empty()).is_some() {
+            self.outcomes.remove(node_id);
+        }
+        debug!(
+            "Dropped failing route: node_id={:x?}, quality={:.2}",
+            &node_id[..8],
+            quality
+        );
+        None
+    }
+
+    /// Adjust the best candidate's quality score toward `node_id` by `delta`
+    ///
+    /// The score is clamped to 0.0..=1.0 and the candidates are re-ranked;
+    /// cached routes to or through the node are forgotten. Unlike
+    /// `record_failure`, a route is never removed here (see
+    /// `prune_low_quality_routes`). Returns the new score, or `None` if
+    /// there is no route.
+    pub fn update_quality_score(&self, node_id: &NodeId, delta: f64) -> Option<f64> {
+        let quality = {
+            let mut candidates = self.routes.get_mut(node_id)?;
+            let best = candidates.first_mut()?;
+            best.quality_score = (best.quality_score + delta).clamp(0.0, 1.0);
+            let quality = best.quality_score;
+            candidates.sort_by(|a, b| self.rank(a, b));
+            quality
+        };
+        self.invalidate_cached_routes(node_id);
+        Some(quality)
+    }
+
+    /// Adjust the quality of the route toward `node_id` by its measured round trip
+    ///
+    /// A round trip of `LATENCY_REFERENCE_MS` leaves the score unchanged;
+    /// faster links raise it and slower ones lower it, proportionally and by
+    /// at most `MAX_LATENCY_QUALITY_STEP` per measurement, so a single slow
+    /// sample cannot sink a route. Returns the new score, or `None` if there
+    /// is no route.
+    pub fn update_route_latency(&self, node_id: &NodeId, rtt_ms: f64) -> Option<f64> {
+        let delta = ((LATENCY_REFERENCE_MS - rtt_ms) / LATENCY_REFERENCE_MS * MAX_LATENCY_QUALITY_STEP)
+            .clamp(-MAX_LATENCY_QUALITY_STEP, MAX_LATENCY_QUALITY_STEP);
+        self.update_quality_score(node_id, delta)
+    }
+
+    /// Remove learned routes that score poorly and have not been refreshed
+    ///
+    /// A candidate goes when its quality is below `min_quality` and it was
+    /// last updated more than `min_age_seconds` ago, so a route that just
+    /// had a bad run but is still being advertised survives. Direct peers
+    /// are never pruned. Returns the number of candidates removed.
+    pub fn prune_low_quality_routes(&self, min_quality: f64, min_age_seconds: u64) -> usize {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        let mut pruned = Vec::new();
+        self.routes.retain(|_, candidates| {
+            candidates.retain(|entry| {
+                let keep = !is_learned(entry)
+                    || entry.quality_score >= min_quality
+                    || now.saturating_sub(entry.last_updated) <= min_age_seconds;
+                if !keep {
+                    pruned.push(entry.route_path.clone());
+                }
+                keep
+            });
+            !candidates.is_empty()
+        });
+        self.outcomes.retain(|node_id, _| self.routes.contains_key(node_id));
+
+        for route_path in &pruned {
+            for node_id in self.route_nodes(route_path) {
+                self.invalidate_cached_routes(node_id);
+            }
+        }
+
+        if !pruned.is_empty() {
+            self.rebuild_route_filter();
+            debug!("Pruned {} low quality routes", pruned.len());
+        }
+        pruned.len()
+    }
+
+    /// Delivery outcomes recorded for `node_id`
+    pub fn outcomes(&self, node_id: &NodeId) -> RouteOutcomes {
+        self.outcomes
+            .get(node_id)
+            .map(|outcomes| *outcomes)
+            .unwrap_or_default()
+    }
+
+    /// Snapshot the best routing entry of every destination
+    ///
+    /// Lock-free reads using DashMap - no async needed
+    pub fn entries(&self) -> Vec<RoutingEntry> {
+        self.routes
+            .iter()
+            .filter_map(|candidates| candidates.value().first().cloned())
+            .collect()
+    }
+
+    /// Get the best routing entry for a node
     ///
     /// Lock-free read using DashMap - no async needed
     pub fn get_route(&self, node_id: &NodeId) -> Option<RoutingEntry> {
         // Lock-free get
-        self.routes.get(node_id).map(|entry| entry.value().clone())
+        self.routes.get(node_id).and_then(|candidates| candidates.value().first().cloned())
     }
 
-    /// Find route to destination (with route discovery if needed)
+    /// Get all candidate routes for a node, best first
+    pub fn candidates(&self, node_id: &NodeId) -> Vec<RoutingEntry> {
+        self.routes
+            .get(node_id)
+            .map(|candidates| candidates.value().clone())
+            .unwrap_or_default()
+    }
+
+    /// Find the best route to a destination
     ///
+    /// Runs Dijkstra over the links of all unexpired routing entries, so a
+    /// stored path is only used when no cheaper combination of known links
+    /// exists. The cheapest few routes are then ranked by cost divided by
+    /// their quality (the lowest quality score along the path), so a route
+    /// through nodes that keep failing loses to a slightly dearer one.
+    /// Results are cached until a route to or through a node on the cached
+    /// path is added, replaced, fails or is removed.
     /// Lock-free reads using DashMap - no async needed
     pub fn find_route(&self, destination: &NodeId) -> Option<Vec<NodeId>> {
+        // Unknown destinations are answered without touching the routes
+        if !self.may_reach(destination) {
+            return None;
+        }
+
         // Check cache first (lock-free)
         if let Some(route) = self.route_cache.get(destination) {
             return Some(route.value().clone());
         }
 
-        // Check direct routes (lock-free)
-        if let Some(entry) = self.routes.get(destination) {
-            // Check if route is still valid (not expired)
-            let now = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_secs();
-            
-            if now <= entry.last_updated + self.route_expiry_seconds {
-                let route = entry.route_path.clone();
-                
-                // Cache the route (lock-free insert)
-                self.route_cache.insert(*destination, route.clone());
-                
-                return Some(route);
+        let (route, _) = self
+            .find_k_routes(destination, MAX_ROUTE_CANDIDATES)
+            .into_iter()
+            .map(|(path, cost)| {
+                let score = (cost + 1) as f64 / self.path_quality(&path);
+                (path, score)
+            })
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))?;
+
+        // Cache the route (lock-free insert)
+        self.cache_route(*destination, &route);
+        Some(route)
+    }
+
+    /// Cache the route to a destination, indexed by the nodes on it
+    fn cache_route(&self, destination: NodeId, route: &[NodeId]) {
+        self.uncache_route(&destination);
+        for node_id in self.route_nodes(route) {
+            self.cache_index.entry(*node_id).or_default().insert(destination);
+        }
+        self.route_cache.insert(destination, route.to_vec());
+    }
+
+    /// Forget the cached route to a destination
+    fn uncache_route(&self, destination: &NodeId) {
+        let Some((_, route)) = self.route_cache.remove(destination) else {
+            return;
+        };
+        for node_id in self.route_nodes(&route) {
+            if let Some(mut destinations) = self.cache_index.get_mut(node_id) {
+                destinations.remove(destination);
             }
+            self.cache_index.remove_if(node_id, |_, destinations| destinations.is_empty());
         }
+    }
 
-        // Route not found - would need route discovery
-        // For now, return None (route discovery to be implemented)
-        None
+    /// Forget the cached routes to or through `node_id`
+    fn invalidate_cached_routes(&self, node_id: &NodeId) {
+        self.uncache_route(node_id);
+        if let Some((_, destinations)) = self.cache_index.remove(node_id) {
+            for destination in destinations {
+                self.uncache_route(&destination);
+            }
+        }
+    }
+
+    /// Nodes a route path passes through, without this node
+    fn route_nodes<'a>(&self, route: &'a [NodeId]) -> &'a [NodeId] {
+        match route.split_first() {
+            Some((first, rest)) if route.len() > 1 && self.local_node_id.map_or(true, |local| local == *first) => rest,
+            _ => route,
+        }
     }
 
-    /// Calculate routing fee for a route
+    /// Find up to `k` cheapest distinct loop-free routes, sorted by cost
     ///
-    /// Fee calculation: 60% to destination, 30% to intermediate nodes, 10% to source
-    pub fn calculate_routing_fee(&self, route: &[NodeId], base_fee_sats: u64) -> RoutingFee {
-        let total_fee = base_fee_sats;
-        
-        // Split: 60% destination, 30% intermediate, 10% source
-        let destination_fee = (total_fee * 60) / 100;
-        let intermediate_fee = if route.len() > 2 {
-            (total_fee * 30) / 100 / (route.len() - 2) as u64
-        } else {
-            0
+    /// Uses Yen's algorithm over the same link graph as `find_route`.
+    pub fn find_k_routes(&self, destination: &NodeId, k: usize) -> Vec<(Vec<NodeId>, u64)> {
+        if k == 0 || !self.may_reach(destination) {
+            return Vec::new();
+        }
+
+        let (graph, origin_id) = self.route_graph();
+        let target = Some(*destination);
+        let Some(first) = shortest_path(&graph, &HashSet::new(), &HashSet::new(), None, target) else {
+            return Vec::new();
         };
-        let source_fee = (total_fee * 10) / 100;
+
+        let mut accepted: Vec<(Vec<Vertex>, u64)> = vec![first];
+        let mut candidates: Vec<(Vec<Vertex>, u64)> = Vec::new();
+        while accepted.len() < k {
+            let (previous, _) = accepted.last().unwrap().clone();
+            for spur_index in 0..previous.len() - 1 {
+                let root = &previous[..=spur_index];
+
+                // Don't repeat links already used by accepted paths sharing this root
+                let banned_links: HashSet<(Vertex, Vertex)> = accepted
+                    .iter()
+                    .filter(|(path, _)| path.len() > spur_index + 1 && path[..=spur_index] == *root)
+                    .map(|(path, _)| (path[spur_index], path[spur_index + 1]))
+                    .collect();
+                // Keep paths loop-free
+                let banned_vertices: HashSet<Vertex> = root[..spur_index].iter().copied().collect();
+
+                let Some((spur, _)) =
+                    shortest_path(&graph, &banned_links, &banned_vertices, root[spur_index], target)
+                else {
+                    continue;
+                };
+                let mut path = root[..spur_index].to_vec();
+                path.extend(spur);
+                let cost = path_cost(&graph, &path);
+                if !candidates.iter().chain(accepted.iter()).any(|(existing, _)| *existing == path) {
+                    candidates.push((path, cost));
+                }
+            }
+
+            let Some(best) = candidates
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, (path, cost))| (*cost, path.len()))
+                .map(|(index, _)| index)
+            else {
+                break;
+            };
+            accepted.push(candidates.swap_remove(best));
+        }
+
+        accepted
+            .into_iter()
+            .filter_map(|(path, cost)| Some((self.to_route_path(&path, origin_id)?, cost)))
+            .collect()
+    }
+
+    /// Build the link graph from unexpired routing entries
+    ///
+    /// Every consecutive pair in a stored route path is a link; its cost is
+    /// the entry's route cost spread evenly over the path's links, keeping
+    /// the cheapest estimate when several entries share a link. Direct peers
+    /// are links from this node at their own route cost. Also returns this
+    /// node's ID if known or inferred from route paths.
+    fn route_graph(&self) -> (RouteGraph, Option<NodeId>) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        let mut origin_id = self.local_node_id;
+        let mut links: HashMap<(Vertex, Vertex), u64> = HashMap::new();
+        let entries: Vec<RoutingEntry> = self
+            .routes
+            .iter()
+            .flat_map(|candidates| candidates.value().clone())
+            .collect();
+        for entry in entries {
+            let is_direct = entry.direct_address.is_some() && entry.next_hop.is_none();
+            // Direct peers never expire (see cleanup_expired)
+            if !is_direct && now > entry.last_updated + self.route_expiry_seconds {
+                continue;
+            }
+
+            if is_direct || entry.route_path.len() < 2 {
+                let link = (None, Some(entry.node_id));
+                let cost = links.entry(link).or_insert(entry.route_cost);
+                *cost = (*cost).min(entry.route_cost);
+                continue;
+            }
+
+            let vertices: Vec<Vertex> = entry
+                .route_path
+                .iter()
+                .enumerate()
+                .map(|(index, node_id)| {
+                    let is_origin = match self.local_node_id {
+                        Some(local) => *node_id == local,
+                        None => index == 0 && !self.routes.contains_key(node_id),
+                    };
+                    if is_origin {
+                        origin_id.get_or_insert(*node_id);
+                        None
+                    } else {
+                        Some(*node_id)
+                    }
+                })
+                .collect();
+            let link_cost = entry.route_cost / (vertices.len() as u64 - 1);
+            for pair in vertices.windows(2) {
+                // Never route back through this node
+                if pair[0] == pair[1] || pair[1].is_none() {
+                    continue;
+                }
+                let cost = links.entry((pair[0], pair[1])).or_insert(link_cost);
+                *cost = (*cost).min(link_cost);
+            }
+        }
+
+        let mut graph = RouteGraph::new();
+        for ((from, to), cost) in links {
+            graph.entry(from).or_default().push((to, cost));
+        }
+        (graph, origin_id)
+    }
+
+    /// Lowest quality score of the nodes a route passes through
+    ///
+    /// A node counts with the quality of its candidate following exactly
+    /// this route, else its best candidate; nodes without an entry of
+    /// their own count as 1.0.
+    fn path_quality(&self, route: &[NodeId]) -> f64 {
+        let hops = if route.len() > 1 { &route[1..] } else { route };
+        hops.iter()
+            .filter_map(|node_id| {
+                let candidates = self.routes.get(node_id)?;
+                candidates
+                    .iter()
+                    .find(|entry| entry.route_path == route)
+                    .or_else(|| candidates.first())
+                    .map(|entry| entry.quality_score)
+            })
+            .fold(1.0, f64::min)
+            .max(0.01)
+    }
+
+    /// Convert a graph path to the route path convention
+    ///
+    /// Direct peers are `[peer]`; anything else is `[self, next, ..., dest]`.
+    fn to_route_path(&self, path: &[Vertex], origin_id: Option<NodeId>) -> Option<Vec<NodeId>> {
+        let hops: Vec<NodeId> = path.iter().flatten().copied().collect();
+        if hops.len() == 1 && self.direct_peers.contains_key(&hops[0]) {
+            return Some(hops);
+        }
+        let mut route = vec![origin_id?];
+        route.extend(hops);
+        Some(route)
+    }
+
+    /// Get all known candidate routes to a destination with their costs
+    ///
+    /// Includes the cheapest computed routes, the cached route and the
+    /// stored candidate routes (deduplicated).
+    /// Lock-free reads using DashMap - no async needed
+    pub fn route_candidates(&self, destination: &NodeId) -> Vec<(Vec<NodeId>, u64)> {
+        let mut candidates: Vec<(Vec<NodeId>, u64)> = self.find_k_routes(destination, MAX_ROUTE_CANDIDATES);
+
+        for entry in self.candidates(destination) {
+            if !candidates.iter().any(|(path, _)| *path == entry.route_path) {
+                candidates.push((entry.route_path, entry.route_cost));
+            }
+        }
+
+        if let Some(cached) = self.route_cache.get(destination) {
+            if !candidates.iter().any(|(path, _)| path == cached.value()) {
+                // Cached routes carry no cost of their own; use the hop count
+                let cost = cached.value().len() as u64 * 100;
+                candidates.push((cached.value().clone(), cost));
+            }
+        }
+
+        candidates
+    }
+
+    /// Calculate routing fee over one or more equal-cost routes
+    ///
+    /// Each route runs `[source, relays.., destination]`; the first one sets
+    /// the source, destination and `hop_count`. The fee is split between
+    /// destination, intermediate nodes and source according to `policy`
+    /// (default 60/30/10). The intermediate share is divided equally between
+    /// the routes that have relays, then evenly between each route's relays,
+    /// so parallel next hops are paid alike. The destination also receives
+    /// whatever no relay takes (rounding, direct routes), so `per_hop_fees`
+    /// always sums to `total`. The total is at least `policy.min_fee_sats`.
+    pub fn calculate_routing_fee(&self, routes: &[Vec<NodeId>], base_fee_sats: u64, policy: &FeePolicy) -> RoutingFee {
+        let total_fee = base_fee_sats.max(policy.min_fee_sats);
+        let routes: Vec<&Vec<NodeId>> = routes.iter().filter(|route| !route.is_empty()).collect();
+        let Some(first) = routes.first() else {
+            return RoutingFee {
+                total: total_fee,
+                destination: 0,
+                intermediate: 0,
+                source: 0,
+                hop_count: 0,
+                per_hop_fees: Vec::new(),
+            };
+        };
+        
+        let source_fee = (total_fee * u64::from(policy.source_pct)) / 100;
+        let intermediate_pool = (total_fee * u64::from(policy.intermediate_pct)) / 100;
+        let relay_routes = routes.iter().filter(|route| route.len() > 2).count() as u64;
+        
+        let mut per_hop_fees = Vec::new();
+        credit_fee(&mut per_hop_fees, first[0], source_fee);
+        let mut intermediate_fee = None;
+        let mut intermediates_paid = 0;
+        for route in routes.iter().filter(|route| route.len() > 2) {
+            let relays = &route[1..route.len() - 1];
+            let share = intermediate_pool / relay_routes / relays.len() as u64;
+            intermediate_fee.get_or_insert(share);
+            for relay in relays {
+                credit_fee(&mut per_hop_fees, *relay, share);
+                intermediates_paid += share;
+            }
+        }
+        let destination_fee = total_fee - source_fee - intermediates_paid;
+        credit_fee(&mut per_hop_fees, first[first.len() - 1], destination_fee);
 
         RoutingFee {
             total: total_fee,
             destination: destination_fee,
-            intermediate: intermediate_fee,
+            intermediate: intermediate_fee.unwrap_or(0),
             source: source_fee,
-            hop_count: route.len(),
+            hop_count: first.len(),
+            per_hop_fees,
         }
     }
 
@@ -189,40 +1036,241 @@ impl RoutingTable {
 
         let mut expired = Vec::new();
 
-        // Lock-free iteration
-        for entry in self.routes.iter() {
-            if now > entry.value().last_updated + self.route_expiry_seconds {
-                // Don't expire direct peers
-                if entry.value().direct_address.is_none() || entry.value().next_hop.is_some() {
-                    expired.push(*entry.key());
+        // Lock-free iteration; direct peers never expire
+        self.routes.retain(|_, candidates| {
+            candidates.retain(|entry| {
+                let keep = !is_learned(entry) || now <= entry.last_updated + self.route_expiry_seconds;
+                if !keep {
+                    expired.push(entry.route_path.clone());
                 }
-            }
-        }
+                keep
+            });
+            !candidates.is_empty()
+        });
+        self.outcomes.retain(|node_id, _| self.routes.contains_key(node_id));
 
-        // Lock-free removal
-        for node_id in &expired {
-            self.routes.remove(node_id);
+        // Only cached routes that used the expired links are recomputed
+        for route_path in &expired {
+            for node_id in self.route_nodes(route_path) {
+                self.invalidate_cached_routes(node_id);
+            }
         }
 
         if !expired.is_empty() {
+            self.rebuild_route_filter();
             debug!("Cleaned up {} expired routes", expired.len());
         }
+    }
+
+    /// Persist discovered and advertised routes to storage
+    ///
+    /// Direct peers are not saved; they are re-learned from PeerConnected
+    /// events. Destinations no longer in the table are removed from storage
+    /// in the same transaction. Returns the number of candidate routes saved.
+    pub async fn save(&self, node_api: &dyn NodeAPI) -> Result<usize, MeshError> {
+        let tree_id = open_versioned_tree(node_api, &ROUTES_SCHEMA).await?;
+        let stored = iter_prefix(node_api, self.prefix_storage.as_deref(), tree_id.clone(), ROUTE_KEY_PREFIX)
+            .await
+            .map_err(|e| MeshError::ModuleError(format!("Failed to read routes tree: {}", e)))?;
+
+        let mut operations = Vec::new();
+        let mut saved = HashSet::new();
+        let mut saved_routes = 0;
+        for candidates in self.routes.iter() {
+            let learned: Vec<&RoutingEntry> = candidates.value().iter().filter(|entry| is_learned(entry)).collect();
+            if learned.is_empty() {
+                continue;
+            }
+            let value = bincode::serialize(&learned)
+                .map_err(|e| MeshError::ModuleError(format!("Failed to encode route: {}", e)))?;
+            let key = route_key(candidates.key());
+            saved.insert(key.clone());
+            saved_routes += learned.len();
+            operations.push(StorageOperation::Insert { key, value });
+        }
+        for (key, _) in stored {
+            if !saved.contains(&key) {
+                operations.push(StorageOperation::Remove { key });
+            }
+        }
+
+        node_api
+            .storage_transaction(tree_id, operations)
+            .await
+            .map_err(|e| MeshError::ModuleError(format!("Failed to save routes: {}", e)))?;
+        debug!("Saved {} routes", saved_routes);
+        Ok(saved_routes)
+    }
+
+    /// Restore routes saved by `save`
+    ///
+    /// Routes older than the route expiry are dropped, and destinations the
+    /// table already knows (e.g. peers that connected first) are kept as
+    /// they are. Returns the number of candidate routes restored. Fails only
+    /// if the stored schema is newer than this build.
+    pub async fn load(&self, node_api: &dyn NodeAPI) -> Result<usize, MeshError> {
+        let tree_id = match open_versioned_tree(node_api, &ROUTES_SCHEMA).await {
+            Ok(tree_id) => tree_id,
+            Err(e @ MeshError::UnsupportedVersion(_)) => return Err(e),
+            Err(e) => {
+                warn!("Route storage unavailable: {}", e);
+                return Ok(0);
+            }
+        };
+        let stored = match iter_prefix(node_api, self.prefix_storage.as_deref(), tree_id, ROUTE_KEY_PREFIX).await {
+            Ok(stored) => stored,
+            Err(e) => {
+                warn!("Failed to restore routes: {}", e);
+                return Ok(0);
+            }
+        };
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let mut restored = 0;
+        for (key, value) in stored {
+            let Some(node_id) = route_key_node_id(&key) else {
+                continue;
+            };
+            let candidates = match bincode::deserialize::<Vec<RoutingEntry>>(&value) {
+                Ok(candidates) => candidates,
+                Err(e) => {
+                    warn!("Failed to decode stored route: {}", e);
+                    continue;
+                }
+            };
+            if self.routes.contains_key(&node_id) {
+                continue;
+            }
+            for entry in candidates {
+                if entry.node_id != node_id || !is_learned(&entry) || now > entry.last_updated + self.route_expiry_seconds {
+                    continue;
+                }
+                if self.insert_candidate(entry) {
+                    restored += 1;
+                }
+            }
+        }
 
-        // Clean up route cache (lock-free clear)
-        self.route_cache.clear(); // Clear cache on cleanup (will be repopulated as needed)
+        if restored > 0 {
+            info!("Restored {} routes from storage", restored);
+        }
+        Ok(restored)
     }
 
     /// Get routing statistics
     ///
     /// Lock-free reads using DashMap - no async needed
     pub fn stats(&self) -> RoutingStats {
+        let route_filter = self.route_filter.lock().unwrap();
         RoutingStats {
             total_routes: self.routes.len(),
             direct_peers: self.direct_peers.len(),
             cached_routes: self.route_cache.len(),
             route_expiry_seconds: self.route_expiry_seconds,
+            route_filter_nodes: route_filter.filter.len(),
+            route_filter_bits: route_filter.filter.bit_len(),
+            route_filter_hashes: route_filter.filter.hash_count(),
+        }
+    }
+}
+
+/// Shortest path with Dijkstra, skipping banned links and vertices
+pub(crate) fn shortest_path(
+    graph: &RouteGraph,
+    banned_links: &HashSet<(Vertex, Vertex)>,
+    banned_vertices: &HashSet<Vertex>,
+    from: Vertex,
+    to: Vertex,
+) -> Option<(Vec<Vertex>, u64)> {
+    let mut distances: HashMap<Vertex, u64> = HashMap::from([(from, 0)]);
+    let mut previous: HashMap<Vertex, Vertex> = HashMap::new();
+    let mut queue = BinaryHeap::from([Reverse((0u64, from))]);
+
+    while let Some(Reverse((distance, vertex))) = queue.pop() {
+        if vertex == to {
+            let mut path = vec![to];
+            while let Some(prior) = previous.get(path.last().unwrap()) {
+                path.push(*prior);
+            }
+            path.reverse();
+            return Some((path, distance));
+        }
+        if distances.get(&vertex).is_some_and(|best| distance > *best) {
+            continue;
         }
+        for (neighbor, cost) in graph.get(&vertex).into_iter().flatten() {
+            if banned_vertices.contains(neighbor) || banned_links.contains(&(vertex, *neighbor)) {
+                continue;
+            }
+            let candidate = distance.saturating_add(*cost);
+            if distances.get(neighbor).map_or(true, |best| candidate < *best) {
+                distances.insert(*neighbor, candidate);
+                previous.insert(*neighbor, vertex);
+                queue.push(Reverse((candidate, *neighbor)));
+            }
+        }
+    }
+
+    None
+}
+
+/// Total link cost of a graph path
+fn path_cost(graph: &RouteGraph, path: &[Vertex]) -> u64 {
+    path.windows(2)
+        .map(|pair| {
+            graph
+                .get(&pair[0])
+                .and_then(|links| links.iter().find(|(to, _)| *to == pair[1]))
+                .map(|(_, cost)| *cost)
+                .unwrap_or(u64::MAX)
+        })
+        .fold(0u64, u64::saturating_add)
+}
+
+/// Whether an entry was learned (discovered or advertised) rather than a direct peer
+fn is_learned(entry: &RoutingEntry) -> bool {
+    entry.direct_address.is_none() || entry.next_hop.is_some()
+}
+
+/// Ranking score of a candidate route (lower is better)
+///
+/// Route cost plus a penalty per hop, divided by the route's quality.
+fn candidate_score(entry: &RoutingEntry) -> f64 {
+    let hop_penalty = ROUTE_HOP_PENALTY * entry.route_path.len() as u64;
+    (entry.route_cost + hop_penalty + 1) as f64 / entry.quality_score.max(0.01)
+}
+
+/// v1 -> v2: wrap each stored route in a one-candidate list
+fn migrate_single_routes_v1(entries: &[(Vec<u8>, Vec<u8>)]) -> Result<Vec<StorageOperation>, MeshError> {
+    let mut operations = Vec::new();
+    for (key, value) in entries {
+        let entry: RoutingEntry = bincode::deserialize(value)
+            .map_err(|e| MeshError::ModuleError(format!("Failed to decode v1 route: {}", e)))?;
+        operations.push(StorageOperation::Insert {
+            key: key.clone(),
+            value: bincode::serialize(&vec![entry])
+                .map_err(|e| MeshError::ModuleError(format!("Failed to encode route: {}", e)))?,
+        });
+    }
+    Ok(operations)
+}
+
+/// v2 -> v3: move each destination's routes under `route/{node_id_hex}`
+fn migrate_route_keys_v2(entries: &[(Vec<u8>, Vec<u8>)]) -> Result<Vec<StorageOperation>, MeshError> {
+    let mut operations = Vec::new();
+    for (key, value) in entries {
+        let node_id = <NodeId>::try_from(key.as_slice())
+            .map_err(|_| MeshError::ModuleError(format!("Unexpected v2 route key of {} bytes", key.len())))?;
+        operations.push(StorageOperation::Remove { key: key.clone() });
+        operations.push(StorageOperation::Insert {
+            key: route_key(&node_id),
+            value: value.clone(),
+        });
     }
+    Ok(operations)
 }
 
 /// Routing fee breakdown
@@ -230,18 +1278,28 @@ impl RoutingTable {
 pub struct RoutingFee {
     /// Total fee in satoshis
     pub total: u64,
-    /// Fee to destination (60%)
+    /// Fee to destination (60% by default, plus any share no relay takes)
     pub destination: u64,
-    /// Fee per intermediate node (30% split)
+    /// Fee per intermediate node of the first route (30% split by default)
     pub intermediate: u64,
-    /// Fee to source node (10%)
+    /// Fee to source node (10% by default)
     pub source: u64,
     /// Number of hops
     pub hop_count: usize,
+    /// Fee owed to each node across all routes, source first (sums to `total`)
+    pub per_hop_fees: Vec<(NodeId, u64)>,
+}
+
+/// Add `amount` to a node's entry in a per-hop fee list
+fn credit_fee(fees: &mut Vec<(NodeId, u64)>, node_id: NodeId, amount: u64) {
+    match fees.iter_mut().find(|(id, _)| *id == node_id) {
+        Some((_, owed)) => *owed += amount,
+        None => fees.push((node_id, amount)),
+    }
 }
 
 /// Routing statistics
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RoutingStats {
     /// Total number of routes
     pub total_routes: usize,
@@ -251,6 +1309,20 @@ pub struct RoutingStats {
     pub cached_routes: usize,
     /// Route expiry time in seconds
     pub route_expiry_seconds: u64,
+    /// Nodes in the route filter
+    pub route_filter_nodes: usize,
+    /// Size of the route filter in bits
+    pub route_filter_bits: usize,
+    /// Hash functions of the route filter
+    pub route_filter_hashes: u8,
+}
+
+impl RoutingStats {
+    /// Expected false-positive rate of the route filter: the share of
+    /// unknown destinations that still cost a route scan
+    pub fn bloom_false_positive_rate(&self) -> f64 {
+        false_positive_rate(self.route_filter_bits, self.route_filter_hashes, self.route_filter_nodes)
+    }
 }
 
 #[cfg(test)]
@@ -283,17 +1355,344 @@ mod tests {
         assert!(route.is_none());
     }
 
+    fn node(i: u8) -> NodeId {
+        [i; 32]
+    }
+
+    fn add_path(table: &RoutingTable, path: &[u8], cost: u64) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let route_path: Vec<NodeId> = path.iter().map(|i| node(*i)).collect();
+        table.add_route(RoutingEntry {
+            node_id: *route_path.last().unwrap(),
+            direct_address: None,
+            next_hop: Some(route_path[1]),
+            route_path,
+            route_cost: cost,
+            last_updated: now,
+            quality_score: 0.8,
+        });
+    }
+
+    /// Ten nodes around this node (0): a long, expensive stored route to
+    /// node 10 and cheap links learned from other entries
+    fn ten_node_table() -> RoutingTable {
+        let table = RoutingTable::new(3600).with_local_node_id(node(0));
+        table.add_direct_peer(node(1), b"peer-1".to_vec());
+        table.add_direct_peer(node(2), b"peer-2".to_vec());
+        add_path(&table, &[0, 1, 3, 4, 5, 6, 10], 600);
+        table
+    }
+
+    #[test]
+    fn test_route_filter_tracks_routable_nodes() {
+        let table = ten_node_table();
+        // Destinations and nodes on the way are routable, others are not
+        assert!(table.may_reach(&node(10)));
+        assert!(table.may_reach(&node(4)));
+        assert!(!table.may_reach(&node(42)));
+        assert_eq!(table.find_route(&node(42)), None);
+        assert_eq!(table.find_route(&node(4)).unwrap().last(), Some(&node(4)));
+
+        // Growing past the initial capacity rebuilds a larger filter
+        for i in 0..ROUTE_FILTER_MIN_CAPACITY as u32 {
+            let mut peer = [0xeeu8; 32];
+            peer[..4].copy_from_slice(&i.to_be_bytes());
+            table.add_direct_peer(peer, b"peer".to_vec());
+        }
+        let stats = table.stats();
+        assert_eq!(stats.route_filter_nodes, ROUTE_FILTER_MIN_CAPACITY + 8);
+        assert!(stats.route_filter_bits > BloomFilter::with_false_positive_rate(ROUTE_FILTER_MIN_CAPACITY, 0.001).bit_len());
+        assert!(stats.bloom_false_positive_rate() < DEFAULT_BLOOM_FALSE_POSITIVE_RATE);
+        assert!(table.may_reach(&node(10)));
+    }
+
+    #[test]
+    fn test_route_filter_rebuilt_on_cleanup() {
+        let table = RoutingTable::new(3600).with_local_node_id(node(0));
+        add_path(&table, &[0, 1, 2], 100);
+        assert!(table.may_reach(&node(2)));
+
+        // Expire the route by hand
+        table.routes.get_mut(&node(2)).unwrap()[0].last_updated = 0;
+        table.cleanup_expired();
+        assert!(!table.may_reach(&node(2)));
+        assert_eq!(table.stats().route_filter_nodes, 0);
+    }
+
+    #[test]
+    fn test_dijkstra_beats_suboptimal_stored_route() {
+        let table = ten_node_table();
+        let long = vec![node(0), node(1), node(3), node(4), node(5), node(6), node(10)];
+        assert_eq!(table.find_route(&node(10)), Some(long));
+
+        // Cheaper links 2 -> 7 -> 8 -> 9 -> 10 become known through other entries
+        add_path(&table, &[0, 2, 7], 20);
+        add_path(&table, &[0, 2, 7, 8], 30);
+        add_path(&table, &[0, 2, 7, 8, 9, 10, 6], 60);
+
+        let best = vec![node(0), node(2), node(7), node(8), node(9), node(10)];
+        assert_eq!(table.find_route(&node(10)), Some(best));
+        // The stored entry for node 10 is unchanged; only selection improved
+        assert_eq!(table.get_route(&node(10)).unwrap().route_cost, 600);
+    }
+
+    #[test]
+    fn test_find_k_routes_sorted_and_distinct() {
+        let table = ten_node_table();
+        add_path(&table, &[0, 2, 7, 8, 9, 10, 6], 60);
+
+        let routes = table.find_k_routes(&node(10), 3);
+        let costs: Vec<u64> = routes.iter().map(|(_, cost)| *cost).collect();
+        assert_eq!(costs, vec![40, 500]);
+        assert_eq!(routes[0].0[1], node(2));
+        assert_eq!(routes[1].0[1], node(1));
+        assert_eq!(Some(routes[0].0.clone()), table.find_route(&node(10)));
+    }
+
+    #[test]
+    fn test_direct_peer_route_convention() {
+        let table = ten_node_table();
+        assert_eq!(table.find_route(&node(1)), Some(vec![node(1)]));
+        assert!(table.find_k_routes(&node(1), 0).is_empty());
+    }
+
+    #[test]
+    fn test_find_route_avoids_low_quality_nodes() {
+        let table = ten_node_table().with_quality_floor(0.01);
+        add_path(&table, &[0, 2, 7], 20);
+        add_path(&table, &[0, 2, 7, 8], 30);
+        add_path(&table, &[0, 2, 7, 8, 9, 10, 6], 60);
+        assert_eq!(table.find_route(&node(10)).unwrap()[1], node(2));
+
+        // Node 7 keeps failing: the dearer route through node 1 wins
+        for _ in 0..4 {
+            table.record_failure(&node(7));
+        }
+        assert_eq!(table.find_route(&node(10)).unwrap()[1], node(1));
+    }
+
+    #[test]
+    fn test_quality_follows_outcomes_and_floor_drops_route() {
+        let table = ten_node_table();
+        assert!(table.find_route(&node(10)).is_some());
+        let close = |quality: Option<f64>, expected: f64| (quality.unwrap() - expected).abs() < 1e-9;
+
+        assert!(close(table.record_success(&node(10)), 0.9));
+        assert!(close(table.record_failure(&node(10)), 0.45));
+        assert_eq!(
+            table.outcomes(&node(10)),
+            RouteOutcomes {
+                successes: 1,
+                failures: 1
+            }
+        );
+        assert!(close(table.record_failure(&node(10)), 0.225));
+        assert_eq!(table.record_failure(&node(10)), None);
+        assert!(table.get_route(&node(10)).is_none());
+        assert!(table.find_route(&node(10)).is_none());
+
+        // Direct peers lose quality but stay
+        table.record_failure(&node(1));
+        table.record_failure(&node(1));
+        table.record_failure(&node(1));
+        assert!(table.get_route(&node(1)).is_some());
+    }
+
+    #[test]
+    fn test_prune_low_quality_routes() {
+        let table = ten_node_table();
+        let close = |quality: Option<f64>, expected: f64| (quality.unwrap() - expected).abs() < 1e-9;
+        assert!(close(table.update_quality_score(&node(10), -0.6), 0.2));
+        assert!(close(table.update_quality_score(&node(1), -5.0), 0.0));
+        assert_eq!(table.update_quality_score(&node(11), 0.1), None);
+
+        // A poor route refreshed an hour ago, and a poor but fresh one
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        table.add_route(RoutingEntry {
+            node_id: node(11),
+            direct_address: None,
+            next_hop: Some(node(2)),
+            route_path: vec![node(0), node(2), node(11)],
+            route_cost: 200,
+            last_updated: now - 3600,
+            quality_score: 0.1,
+        });
+        assert_eq!(table.find_route(&node(11)).unwrap()[1], node(2));
+
+        assert_eq!(table.prune_low_quality_routes(0.3, 600), 1);
+        assert!(table.get_route(&node(11)).is_none());
+        assert!(table.find_route(&node(11)).is_none());
+        // Fresh routes and direct peers stay, however poor
+        assert!(table.get_route(&node(10)).is_some());
+        assert!(table.get_route(&node(1)).is_some());
+
+        assert!(close(table.update_quality_score(&node(10), 5.0), 1.0));
+    }
+
+    #[test]
+    fn test_backup_candidate_takes_over_when_best_fails() {
+        let table = ten_node_table();
+        add_path(&table, &[0, 1, 11], 100);
+        add_path(&table, &[0, 2, 11], 300);
+        assert_eq!(table.candidates(&node(11)).len(), 2);
+        assert_eq!(table.find_route(&node(11)).unwrap()[1], node(1));
+
+        // No new discovery: the stored backup becomes the best route
+        table.record_failure(&node(11));
+        table.record_failure(&node(11));
+        assert_eq!(table.get_route(&node(11)).unwrap().next_hop, Some(node(2)));
+        assert_eq!(table.find_route(&node(11)).unwrap()[1], node(2));
+        assert_eq!(table.candidates(&node(11)).len(), 2);
+    }
+
+    #[test]
+    fn test_full_candidate_list_replaces_worst() {
+        let table = RoutingTable::new(3600)
+            .with_local_node_id(node(0))
+            .with_max_routes_per_destination(2);
+        add_path(&table, &[0, 1, 11], 100);
+        add_path(&table, &[0, 2, 11], 300);
+        add_path(&table, &[0, 3, 11], 200);
+        let costs: Vec<u64> = table.candidates(&node(11)).iter().map(|entry| entry.route_cost).collect();
+        assert_eq!(costs, vec![100, 200]);
+
+        // Worse than every candidate: ignored
+        add_path(&table, &[0, 4, 11], 500);
+        assert!(table.candidates(&node(11)).iter().all(|entry| entry.route_cost < 500));
+
+        // A direct connection always ranks first and keeps the learned routes
+        table.add_direct_peer(node(11), b"peer-11".to_vec());
+        assert_eq!(table.get_route(&node(11)).unwrap().route_path, vec![node(11)]);
+        table.remove_direct_peer(&node(11));
+        assert_eq!(table.get_route(&node(11)).unwrap().route_cost, 100);
+    }
+
+    #[test]
+    fn test_tie_break_orders_equal_scores() {
+        // 110 + 3 hops and 100 + 4 hops score the same
+        let table = RoutingTable::new(3600)
+            .with_local_node_id(node(0))
+            .with_tie_break(TieBreak::FewestHops);
+        add_path(&table, &[0, 2, 3, 11], 100);
+        add_path(&table, &[0, 1, 11], 110);
+        assert_eq!(table.get_route(&node(11)).unwrap().route_path.len(), 3);
+        assert_eq!(TieBreak::from_name("fewest_hops"), Some(TieBreak::FewestHops));
+        assert_eq!(TieBreak::from_name("random"), None);
+    }
+
+    #[test]
+    fn test_disconnected_relay_invalidates_cached_route() {
+        let table = RoutingTable::new(3600).with_local_node_id(node(0));
+        table.add_direct_peer(node(1), b"peer-1".to_vec());
+        table.add_direct_peer(node(2), b"peer-2".to_vec());
+        add_path(&table, &[0, 2, 5], 100);
+        // Stored under node 6, teaches the 1 -> 5 link
+        add_path(&table, &[0, 1, 5, 6], 90);
+        assert_eq!(table.find_route(&node(5)), Some(vec![node(0), node(1), node(5)]));
+        assert_eq!(table.find_route(&node(2)), Some(vec![node(2)]));
+
+        // Unexpired routes stay cached through cleanup
+        table.cleanup_expired();
+        assert_eq!(table.stats().cached_routes, 2);
+
+        // Without the direct link, the cached path through node 1 is stale
+        table.remove_direct_peer(&node(1));
+        assert_eq!(table.stats().cached_routes, 1);
+        assert_eq!(table.find_route(&node(5)), Some(vec![node(0), node(2), node(5)]));
+        assert_eq!(table.find_route(&node(2)), Some(vec![node(2)]));
+    }
+
     #[tokio::test]
     async fn test_fee_calculation() {
         let table = RoutingTable::new(3600);
         let route = vec![[1u8; 32], [2u8; 32], [3u8; 32]]; // 3-hop route
         let base_fee = 1000; // 1000 sats
 
-        let fee = table.calculate_routing_fee(&route, base_fee);
+        let fee = table.calculate_routing_fee(&[route], base_fee, &FeePolicy::default());
         assert_eq!(fee.total, 1000);
         assert_eq!(fee.destination, 600); // 60%
         assert_eq!(fee.intermediate, 300); // 30% / 1 intermediate
         assert_eq!(fee.source, 100); // 10%
         assert_eq!(fee.hop_count, 3);
+
+        // Relay-heavy split, shared by two intermediates
+        let route = vec![[1u8; 32], [2u8; 32], [3u8; 32], [4u8; 32]];
+        let fee = table.calculate_routing_fee(&[route], base_fee, &FeePolicy::new(40, 50, 10));
+        assert_eq!(fee.destination, 400);
+        assert_eq!(fee.intermediate, 250);
+        assert_eq!(fee.source, 100);
+    }
+
+    #[test]
+    fn test_fee_split_sums_exactly() {
+        let table = RoutingTable::new(3600);
+        let policy = FeePolicy::new(50, 35, 15);
+        let sums_to_total = |fee: &RoutingFee| fee.per_hop_fees.iter().map(|(_, fee)| fee).sum::<u64>() == fee.total;
+
+        // Two hops: no relays, the intermediate share goes to the destination
+        let fee = table.calculate_routing_fee(&[vec![node(1), node(9)]], 999, &policy);
+        assert_eq!(fee.intermediate, 0);
+        assert_eq!(fee.per_hop_fees, vec![(node(1), 149), (node(9), 850)]);
+        assert!(sums_to_total(&fee));
+
+        // Seven relays sharing 35% of 1003 = 351: 50 each, the odd satoshi to the destination
+        let long: Vec<NodeId> = (1..=9).map(node).collect();
+        let fee = table.calculate_routing_fee(&[long], 1003, &policy);
+        assert_eq!(fee.intermediate, 50);
+        assert_eq!(fee.source, 150);
+        assert_eq!(fee.destination, 1003 - 150 - 350);
+        assert!(sums_to_total(&fee));
+
+        // Totals below the minimum are raised to it
+        let fee = table.calculate_routing_fee(&[vec![node(1), node(2), node(9)]], 3, &policy.with_min_fee_sats(100));
+        assert_eq!(fee.total, 100);
+        assert_eq!(fee.per_hop_fees, vec![(node(1), 15), (node(2), 35), (node(9), 50)]);
+    }
+
+    #[test]
+    fn test_fee_split_across_equal_cost_routes() {
+        let table = RoutingTable::new(3600);
+        let (source, destination) = ([1u8; 32], [9u8; 32]);
+        let routes = vec![
+            vec![source, [2u8; 32], destination],
+            vec![source, [3u8; 32], destination],
+        ];
+
+        let fee = table.calculate_routing_fee(&routes, 1001, &FeePolicy::default());
+        // 30% of 1001 = 300, half to each parallel next hop
+        assert_eq!(fee.intermediate, 150);
+        assert_eq!(
+            fee.per_hop_fees,
+            vec![(source, 100), ([2u8; 32], 150), ([3u8; 32], 150), (destination, 601)]
+        );
+        assert_eq!(fee.per_hop_fees.iter().map(|(_, fee)| fee).sum::<u64>(), fee.total);
+
+        // A relay shared by both routes collects both halves
+        let routes = vec![
+            vec![source, [2u8; 32], [4u8; 32], destination],
+            vec![source, [3u8; 32], [4u8; 32], destination],
+        ];
+        let fee = table.calculate_routing_fee(&routes, 1000, &FeePolicy::default());
+        assert_eq!(
+            fee.per_hop_fees,
+            vec![(source, 100), ([2u8; 32], 75), ([4u8; 32], 150), ([3u8; 32], 75), (destination, 600)]
+        );
+        assert_eq!(fee.per_hop_fees.iter().map(|(_, fee)| fee).sum::<u64>(), fee.total);
+    }
+
+    #[test]
+    fn test_update_route_latency() {
+        let table = ten_node_table();
+        let close = |quality: Option<f64>, expected: f64| (quality.unwrap() - expected).abs() < 1e-9;
+        assert!(close(table.update_route_latency(&node(10), 100.0), 0.8));
+        assert!(close(table.update_route_latency(&node(10), 50.0), 0.825));
+        // Slow links lose quality, at most one step per measurement
+        assert!(close(table.update_route_latency(&node(10), 150.0), 0.8));
+        assert!(close(table.update_route_latency(&node(10), 5000.0), 0.75));
+        assert!(close(table.update_route_latency(&node(10), 0.0), 0.8));
+        assert_eq!(table.update_route_latency(&node(11), 10.0), None);
     }
 }
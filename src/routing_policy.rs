@@ -3,8 +3,12 @@
 //! This module determines whether a message requires payment based on protocol detection.
 //! It leverages existing Bitcoin protocol detection rather than creating duplicate logic.
 
+use crate::access_control::AccessControl;
 use crate::error::MeshError;
-use std::sync::Arc;
+use crate::routing::NodeId;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
 use tracing::{debug, trace};
 
 /// Routing policy for mesh messages
@@ -16,8 +20,18 @@ pub enum RoutingPolicy {
     PaymentRequired,
 }
 
+impl RoutingPolicy {
+    /// Short name used in logs
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RoutingPolicy::Free => "free",
+            RoutingPolicy::PaymentRequired => "payment_required",
+        }
+    }
+}
+
 /// Detected protocol from message analysis
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum DetectedProtocol {
     /// Bitcoin P2P protocol (detected via magic bytes + command)
     BitcoinP2P,
@@ -31,8 +45,21 @@ pub enum DetectedProtocol {
     Unknown,
 }
 
+/// Stratum V2 frame header length (extension type, message type, message length)
+const SV2_FRAME_HEADER_LEN: usize = 6;
+
+/// Channel message flag in the Stratum V2 extension type
+const SV2_CHANNEL_MSG_BIT: u16 = 0x8000;
+
+/// Stratum V2 message type ranges: common setup, mining, job negotiation
+const SV2_MESSAGE_TYPES: [std::ops::RangeInclusive<u8>; 3] = [0x00..=0x0F, 0x10..=0x1F, 0x60..=0x6F];
+
+/// Leading bytes of a Stratum V2 Noise_NK handshake frame (before the 2-byte length)
+const SV2_NOISE_HEADER: [u8; 4] = [0x00, 0x00, 0x00, 0x00];
+
 /// Mesh operating mode
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum MeshMode {
     /// Bitcoin-only mode (only Bitcoin P2P allowed, no mesh)
     BitcoinOnly,
@@ -42,15 +69,182 @@ pub enum MeshMode {
     Open,
 }
 
+/// Split of a routing fee between the nodes on a route (percentages)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeePolicy {
+    /// Share paid to the destination
+    pub destination_pct: u8,
+    /// Share split between intermediate nodes
+    pub intermediate_pct: u8,
+    /// Share kept by the source node
+    pub source_pct: u8,
+    /// Smallest total fee charged, whatever the base fee
+    pub min_fee_sats: u64,
+    /// Splits used instead of this one for specific protocols
+    pub per_protocol_overrides: HashMap<DetectedProtocol, FeePolicy>,
+}
+
+impl Default for FeePolicy {
+    fn default() -> Self {
+        Self {
+            destination_pct: 60,
+            intermediate_pct: 30,
+            source_pct: 10,
+            min_fee_sats: 0,
+            per_protocol_overrides: HashMap::new(),
+        }
+    }
+}
+
+impl FeePolicy {
+    /// Create a fee split without protocol overrides
+    pub fn new(destination_pct: u8, intermediate_pct: u8, source_pct: u8) -> Self {
+        Self {
+            destination_pct,
+            intermediate_pct,
+            source_pct,
+            min_fee_sats: 0,
+            per_protocol_overrides: HashMap::new(),
+        }
+    }
+
+    /// Set the smallest total fee charged
+    pub fn with_min_fee_sats(mut self, min_fee_sats: u64) -> Self {
+        self.min_fee_sats = min_fee_sats;
+        self
+    }
+
+    /// Load from module context config keys (`mesh.fee.*_pct`, `mesh.fee.min_fee_sats`)
+    pub fn from_context(ctx: &bllvm_node::module::traits::ModuleContext) -> Self {
+        let defaults = Self::default();
+        let pct = |key: &str, default: u8| {
+            ctx.get_config_or(key, &default.to_string())
+                .parse::<u8>()
+                .unwrap_or(default)
+        };
+        Self::new(
+            pct("mesh.fee.destination_pct", defaults.destination_pct),
+            pct("mesh.fee.intermediate_pct", defaults.intermediate_pct),
+            pct("mesh.fee.source_pct", defaults.source_pct),
+        )
+        .with_min_fee_sats(
+            ctx.get_config_or("mesh.fee.min_fee_sats", &defaults.min_fee_sats.to_string())
+                .parse::<u64>()
+                .unwrap_or(defaults.min_fee_sats),
+        )
+    }
+
+    /// Check that this split and every override sum to 100%
+    pub fn validate(&self) -> Result<(), MeshError> {
+        let total = u16::from(self.destination_pct)
+            + u16::from(self.intermediate_pct)
+            + u16::from(self.source_pct);
+        if total != 100 {
+            return Err(MeshError::ConfigError(format!(
+                "Fee split must sum to 100%, got {}% ({}/{}/{})",
+                total, self.destination_pct, self.intermediate_pct, self.source_pct
+            )));
+        }
+        for (protocol, policy) in &self.per_protocol_overrides {
+            policy.validate().map_err(|e| {
+                MeshError::ConfigError(format!("Fee override for {:?}: {}", protocol, e))
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Fee split for a protocol (its override, or this split)
+    pub fn for_protocol(&self, protocol: DetectedProtocol) -> &FeePolicy {
+        self.per_protocol_overrides.get(&protocol).unwrap_or(self)
+    }
+}
+
+/// Price of carrying a paid packet
+///
+/// The base fee is `rate_sats_per_kb` per started kilobyte of payload plus
+/// `hop_surcharge_sats`, both per hop. The smallest total comes from
+/// `FeePolicy::min_fee_sats` when the fee is split with
+/// `RoutingTable::calculate_routing_fee`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FeeSchedule {
+    /// Fee per started kilobyte of payload, per hop
+    pub rate_sats_per_kb: u64,
+    /// Flat fee per hop, whatever the payload size
+    pub hop_surcharge_sats: u64,
+}
+
+impl Default for FeeSchedule {
+    fn default() -> Self {
+        Self {
+            rate_sats_per_kb: 1,
+            hop_surcharge_sats: 0,
+        }
+    }
+}
+
+impl FeeSchedule {
+    /// Load from module context config keys (`mesh.fee.rate_sats_per_kb`,
+    /// `mesh.fee.hop_surcharge_sats`)
+    pub fn from_context(ctx: &bllvm_node::module::traits::ModuleContext) -> Self {
+        let defaults = Self::default();
+        let sats = |key: &str, default: u64| {
+            ctx.get_config_or(key, &default.to_string())
+                .parse::<u64>()
+                .unwrap_or(default)
+        };
+        Self {
+            rate_sats_per_kb: sats("mesh.fee.rate_sats_per_kb", defaults.rate_sats_per_kb),
+            hop_surcharge_sats: sats("mesh.fee.hop_surcharge_sats", defaults.hop_surcharge_sats),
+        }
+    }
+
+    /// Base fee for a payload carried over `hops` hops (at least one of each)
+    pub fn base_fee_sats(&self, payload_len: usize, hops: usize) -> u64 {
+        let kilobytes = (payload_len as u64).div_ceil(1024).max(1);
+        let per_hop = kilobytes
+            .saturating_mul(self.rate_sats_per_kb)
+            .saturating_add(self.hop_surcharge_sats);
+        per_hop.saturating_mul(hops.max(1) as u64)
+    }
+}
+
 /// Routing policy engine
+///
+/// The mode can be switched at runtime (`set_mode`) while the engine is
+/// shared; decisions made after the switch use the new mode.
 pub struct RoutingPolicyEngine {
-    mode: MeshMode,
+    mode: RwLock<MeshMode>,
+    /// Routing fee split
+    fee_policy: FeePolicy,
+    /// Node blacklist / whitelist (replaceable at runtime)
+    access_control: RwLock<AccessControl>,
 }
 
 impl RoutingPolicyEngine {
-    /// Create a new routing policy engine
+    /// Create a new routing policy engine (default 60/30/10 fee split)
     pub fn new(mode: MeshMode) -> Self {
-        Self { mode }
+        Self {
+            mode: RwLock::new(mode),
+            fee_policy: FeePolicy::default(),
+            access_control: RwLock::new(AccessControl::default()),
+        }
+    }
+
+    /// Use a custom fee split (validated)
+    pub fn with_fee_policy(mut self, fee_policy: FeePolicy) -> Result<Self, MeshError> {
+        fee_policy.validate()?;
+        self.fee_policy = fee_policy;
+        Ok(self)
+    }
+
+    /// Get the configured fee split
+    pub fn fee_policy(&self) -> &FeePolicy {
+        &self.fee_policy
+    }
+
+    /// Fee split that applies to a message (by detected protocol)
+    pub fn fee_policy_for(&self, message: &[u8]) -> &FeePolicy {
+        self.fee_policy.for_protocol(self.detect_protocol(message))
     }
 
     /// Detect protocol from message bytes
@@ -92,9 +286,8 @@ impl RoutingPolicyEngine {
         }
         
         // Check for Stratum V2 protocol (if message is long enough)
-        if message.len() >= 2 {
-            // Stratum V2 uses specific message type tags
-            // This is a simplified check - full detection would parse the protocol
+        if message.len() >= SV2_FRAME_HEADER_LEN {
+            // Stratum V2 frames carry a message type from the spec's ranges
             if self.is_stratum_v2_message(message) {
                 trace!("Detected Stratum V2 protocol");
                 return DetectedProtocol::StratumV2;
@@ -115,7 +308,7 @@ impl RoutingPolicyEngine {
 
     /// Determine routing policy based on protocol detection and mode
     pub fn determine_policy(&self, protocol: DetectedProtocol) -> RoutingPolicy {
-        match (protocol, self.mode) {
+        match (protocol, self.mode()) {
             // Bitcoin P2P is always free
             (DetectedProtocol::BitcoinP2P, _) => {
                 trace!("Bitcoin P2P → Free routing");
@@ -186,44 +379,89 @@ impl RoutingPolicyEngine {
     }
 
     /// Check if message is Stratum V2 protocol
+    ///
+    /// Matches a complete frame (`extension_type` u16, `msg_type` u8,
+    /// `msg_length` u24, all little-endian) of a core-protocol message type,
+    /// or a Noise_NK handshake frame.
     fn is_stratum_v2_message(&self, message: &[u8]) -> bool {
-        // Stratum V2 uses specific message type tags
-        // This is a simplified check - in production, would parse the protocol properly
-        // Stratum V2 messages typically start with a message type tag (u16)
-        if message.len() >= 2 {
-            let tag = u16::from_le_bytes([message[0], message[1]]);
-            // Stratum V2 message type tags are in specific ranges
-            // This is a placeholder - would need actual Stratum V2 protocol parsing
-            (0x0100..=0x01FF).contains(&tag) || (0x0200..=0x02FF).contains(&tag)
-        } else {
-            false
+        if message.len() < SV2_FRAME_HEADER_LEN {
+            return false;
+        }
+        let body_len = message.len() - SV2_FRAME_HEADER_LEN;
+
+        // Handshake frames: zero extension type and message type + 2-byte length
+        if message[..4] == SV2_NOISE_HEADER
+            && u16::from_le_bytes([message[4], message[5]]) as usize == body_len
+        {
+            return true;
         }
+
+        // The high bit of the extension type flags channel messages
+        let extension_type = u16::from_le_bytes([message[0], message[1]]) & !SV2_CHANNEL_MSG_BIT;
+        let msg_type = message[2];
+        let msg_length = u32::from_le_bytes([message[3], message[4], message[5], 0]) as usize;
+        extension_type == 0
+            && msg_length == body_len
+            && SV2_MESSAGE_TYPES.iter().any(|range| range.contains(&msg_type))
     }
 
     /// Get current mesh mode
     pub fn mode(&self) -> MeshMode {
-        self.mode
+        *self.mode.read().unwrap()
+    }
+
+    /// Set mesh mode (for runtime configuration changes), returning the previous mode
+    pub fn set_mode(&self, mode: MeshMode) -> MeshMode {
+        let previous = std::mem::replace(&mut *self.mode.write().unwrap(), mode);
+        debug!("Routing policy mode changed: {:?} → {:?}", previous, mode);
+        previous
     }
 
-    /// Set mesh mode (for runtime configuration changes)
-    pub fn set_mode(&mut self, mode: MeshMode) {
-        debug!("Routing policy mode changed: {:?} → {:?}", self.mode, mode);
-        self.mode = mode;
+    /// Current node access list
+    pub fn access_control(&self) -> AccessControl {
+        self.access_control.read().unwrap().clone()
+    }
+
+    /// Replace the node access list
+    pub fn set_access_control(&self, ac: AccessControl) {
+        debug!("Access control set: {:?} with {} nodes", ac.mode, ac.list.len());
+        *self.access_control.write().unwrap() = ac;
+    }
+
+    /// Fail with `AccessDenied` unless the access list allows `node_id`
+    pub fn check_access(&self, node_id: &NodeId) -> Result<(), MeshError> {
+        self.access_control.read().unwrap().check(node_id)
+    }
+}
+
+impl MeshMode {
+    /// Config name of the mode (`mesh.mode`)
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MeshMode::BitcoinOnly => "bitcoin_only",
+            MeshMode::PaymentGated => "payment_gated",
+            MeshMode::Open => "open",
+        }
+    }
+
+    /// Parse a mode name, or None if it is not one
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "bitcoin_only" | "bitcoin-only" => Some(MeshMode::BitcoinOnly),
+            "payment_gated" | "payment-gated" | "paymentgated" => Some(MeshMode::PaymentGated),
+            "open" => Some(MeshMode::Open),
+            _ => None,
+        }
     }
 }
 
 /// Convert string mode to MeshMode enum
 impl From<&str> for MeshMode {
     fn from(s: &str) -> Self {
-        match s.to_lowercase().as_str() {
-            "bitcoin_only" | "bitcoin-only" => MeshMode::BitcoinOnly,
-            "payment_gated" | "payment-gated" | "paymentgated" => MeshMode::PaymentGated,
-            "open" => MeshMode::Open,
-            _ => {
-                debug!("Unknown mesh mode '{}', defaulting to payment_gated", s);
-                MeshMode::PaymentGated
-            }
-        }
+        MeshMode::from_name(s).unwrap_or_else(|| {
+            debug!("Unknown mesh mode '{}', defaulting to payment_gated", s);
+            MeshMode::PaymentGated
+        })
     }
 }
 
@@ -250,6 +488,60 @@ mod tests {
         assert_eq!(policy, RoutingPolicy::Free);
     }
 
+    /// Stratum V2 frame: extension type, message type, u24 length, payload
+    fn sv2_frame(extension_type: u16, msg_type: u8, payload: &[u8]) -> Vec<u8> {
+        let mut frame = extension_type.to_le_bytes().to_vec();
+        frame.push(msg_type);
+        frame.extend_from_slice(&(payload.len() as u32).to_le_bytes()[..3]);
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    #[test]
+    fn test_stratum_v2_detection() {
+        let engine = RoutingPolicyEngine::new(MeshMode::PaymentGated);
+
+        // SetupConnection (0x00): protocol 0 (mining), versions 2..2, flags,
+        // endpoint "0.0.0.0":8545, empty vendor/hardware/firmware/device strings
+        let mut setup_connection = vec![0x00, 0x02, 0x00, 0x02, 0x00, 0x01, 0x00, 0x00, 0x00, 0x07];
+        setup_connection.extend_from_slice(b"0.0.0.0");
+        setup_connection.extend_from_slice(&[0x61, 0x21, 0x00, 0x00, 0x00, 0x00]);
+        // SetupConnection.Success (0x01): used_version 2, flags 0
+        let setup_success = [0x02, 0x00, 0x00, 0x00, 0x00, 0x00];
+        // OpenStandardMiningChannel (0x10): request_id 1, user "u", hash rate, max target
+        let mut open_channel = vec![0x01, 0x00, 0x00, 0x00, 0x01, b'u', 0x00, 0x00, 0x80, 0x3f];
+        open_channel.extend_from_slice(&[0xff; 32]);
+        // NewMiningJob (0x15) on channel 1, flagged as a channel message
+        let new_mining_job = [0x01, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00];
+
+        for frame in [
+            sv2_frame(0x0000, 0x00, &setup_connection),
+            sv2_frame(0x0000, 0x01, &setup_success),
+            sv2_frame(0x0000, 0x10, &open_channel),
+            sv2_frame(0x8000, 0x15, &new_mining_job),
+            sv2_frame(0x0000, 0x60, &[0x01, 0x00, 0x00, 0x00]),
+        ] {
+            assert_eq!(engine.detect_protocol(&frame), DetectedProtocol::StratumV2);
+        }
+        assert_eq!(
+            engine.determine_policy(DetectedProtocol::StratumV2),
+            RoutingPolicy::Free
+        );
+
+        // Noise_NK handshake: zero header bytes, 2-byte length, ElligatorSwift key
+        let mut handshake = vec![0x00, 0x00, 0x00, 0x00, 0x40, 0x00];
+        handshake.extend_from_slice(&[0x5a; 64]);
+        assert_eq!(engine.detect_protocol(&handshake), DetectedProtocol::StratumV2);
+
+        // Message types outside the spec ranges, truncated frames and extensions are not
+        assert_eq!(engine.detect_protocol(&sv2_frame(0x0000, 0x30, &[1, 2])), DetectedProtocol::Unknown);
+        let mut truncated = sv2_frame(0x0000, 0x10, &open_channel);
+        truncated.pop();
+        assert_eq!(engine.detect_protocol(&truncated), DetectedProtocol::Unknown);
+        assert_eq!(engine.detect_protocol(&sv2_frame(0x0001, 0x00, &[1])), DetectedProtocol::Unknown);
+        assert_eq!(engine.detect_protocol(&[0x00, 0x01]), DetectedProtocol::Unknown);
+    }
+
     #[test]
     fn test_mesh_packet_detection() {
         let engine = RoutingPolicyEngine::new(MeshMode::PaymentGated);
@@ -281,6 +573,50 @@ mod tests {
         assert_eq!(policy, RoutingPolicy::PaymentRequired);
     }
 
+    #[test]
+    fn test_set_mode_on_shared_engine() {
+        let engine = Arc::new(RoutingPolicyEngine::new(MeshMode::Open));
+        assert_eq!(engine.determine_policy(DetectedProtocol::Unknown), RoutingPolicy::Free);
+
+        assert_eq!(engine.set_mode(MeshMode::PaymentGated), MeshMode::Open);
+        assert_eq!(engine.mode(), MeshMode::PaymentGated);
+        assert_eq!(engine.determine_policy(DetectedProtocol::Unknown), RoutingPolicy::PaymentRequired);
+
+        for mode in [MeshMode::BitcoinOnly, MeshMode::PaymentGated, MeshMode::Open] {
+            assert_eq!(MeshMode::from_name(mode.as_str()), Some(mode));
+        }
+        assert_eq!(MeshMode::from_name("closed"), None);
+    }
+
+    #[test]
+    fn test_fee_policy_validation() {
+        assert!(FeePolicy::default().validate().is_ok());
+        assert!(FeePolicy::new(50, 40, 10).validate().is_ok());
+        assert!(FeePolicy::new(60, 30, 20).validate().is_err());
+        assert!(FeePolicy::new(255, 0, 0).validate().is_err());
+
+        let mut policy = FeePolicy::default();
+        policy
+            .per_protocol_overrides
+            .insert(DetectedProtocol::Unknown, FeePolicy::new(70, 20, 20));
+        assert!(policy.validate().is_err());
+        assert!(RoutingPolicyEngine::new(MeshMode::Open).with_fee_policy(policy).is_err());
+    }
+
+    #[test]
+    fn test_fee_policy_protocol_override() {
+        let mut policy = FeePolicy::default();
+        policy
+            .per_protocol_overrides
+            .insert(DetectedProtocol::Unknown, FeePolicy::new(40, 50, 10));
+        let engine = RoutingPolicyEngine::new(MeshMode::PaymentGated)
+            .with_fee_policy(policy)
+            .unwrap();
+
+        assert_eq!(engine.fee_policy_for(&[0x12, 0x34, 0x56, 0x78]).intermediate_pct, 50);
+        assert_eq!(engine.fee_policy_for(b"MESH").intermediate_pct, 30);
+    }
+
     #[test]
     fn test_open_mode() {
         let engine = RoutingPolicyEngine::new(MeshMode::Open);
@@ -290,5 +626,17 @@ mod tests {
         let policy = engine.determine_policy(protocol);
         assert_eq!(policy, RoutingPolicy::Free);
     }
-}
 
+    #[test]
+    fn test_fee_schedule_scales_with_size_and_hops() {
+        let schedule = FeeSchedule {
+            rate_sats_per_kb: 2,
+            hop_surcharge_sats: 5,
+        };
+        assert_eq!(schedule.base_fee_sats(0, 0), 7);
+        assert_eq!(schedule.base_fee_sats(1024, 1), 7);
+        assert_eq!(schedule.base_fee_sats(1025, 1), 9);
+        assert_eq!(schedule.base_fee_sats(1024 * 1024, 10), (2048 + 5) * 10);
+        assert_eq!(FeeSchedule::default().base_fee_sats(100, 2), 2);
+    }
+}
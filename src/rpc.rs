@@ -0,0 +1,420 @@
+//! Operator RPC endpoints and module-to-module calls over the mesh
+//!
+//! Registered with the node via `register_rpc_endpoint` when the manager
+//! starts and unregistered on shutdown; requests and responses are JSON.
+//! The node delivers invocations as `RpcCall` request messages, which
+//! `answer_request` turns into response messages; `call_module` calls to the
+//! module API (`crate::api`) arrive the same way.
+//!
+//! `MeshRpc` correlates mesh packets instead: a call tags its packet with a
+//! `correlation_id` metadata field and waits for a reply packet carrying the
+//! same ID back from the destination.
+
+use crate::error::MeshError;
+use crate::manager::MeshManager;
+use crate::packet::{MeshPacket, PacketMetadata};
+use crate::payment_proof::PaymentProof;
+use crate::routing::NodeId;
+use crate::routing_policy::MeshMode;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use bllvm_node::module::ipc::protocol::{RequestMessage, RequestPayload, ResponseMessage, ResponsePayload};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::OsRng;
+use dashmap::DashMap;
+use serde_json::{json, Value};
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::sync::oneshot;
+use tracing::debug;
+
+/// Dump captured packets
+pub const RPC_CAPTURE_DUMP: &str = "mesh.capture_dump";
+/// Discard captured packets
+pub const RPC_CAPTURE_CLEAR: &str = "mesh.capture_clear";
+/// Start capturing (optional `duration_secs`)
+pub const RPC_CAPTURE_START: &str = "mesh.capture_start";
+/// Stop capturing
+pub const RPC_CAPTURE_STOP: &str = "mesh.capture_stop";
+/// Manager statistics (`MeshStats`)
+pub const RPC_GET_STATS: &str = "mesh_getstats";
+/// Page through routing table entries (optional `offset`, `limit`)
+pub const RPC_LIST_ROUTES: &str = "mesh_listroutes";
+/// Route a packet (`destination` hex, `payload` base64, optional `payment_proof`)
+pub const RPC_SEND_PACKET: &str = "mesh_sendpacket";
+/// Switch the operating mode (`mode`)
+pub const RPC_SET_MODE: &str = "mesh_setmode";
+/// Turn the mesh on
+pub const RPC_ENABLE: &str = "mesh_enable";
+/// Turn the mesh off (Bitcoin P2P is unaffected)
+pub const RPC_DISABLE: &str = "mesh_disable";
+/// Delivery status of a paid packet sent by this node (`sequence`)
+pub const RPC_GET_DELIVERY: &str = "mesh_getdelivery";
+/// Prepaid session balances left on overpaying proofs
+pub const RPC_GET_BALANCES: &str = "mesh_getbalances";
+/// Earnings from accepted payments (optional `from`, `to`, `limit`)
+pub const RPC_GET_EARNINGS: &str = "mesh_getearnings";
+
+/// All RPC endpoints with their descriptions
+pub const MESH_RPC_METHODS: [(&str, &str); 13] = [
+    (RPC_CAPTURE_DUMP, "Dump captured mesh packet headers as JSON"),
+    (RPC_CAPTURE_CLEAR, "Discard captured mesh packets"),
+    (RPC_CAPTURE_START, "Start mesh packet capture"),
+    (RPC_CAPTURE_STOP, "Stop mesh packet capture"),
+    (RPC_GET_STATS, "Mesh routing, replay and rate limit statistics"),
+    (RPC_LIST_ROUTES, "List mesh routing table entries (paginated)"),
+    (RPC_SEND_PACKET, "Send a mesh packet to a destination node"),
+    (RPC_SET_MODE, "Switch the mesh mode (bitcoin_only, payment_gated, open)"),
+    (RPC_ENABLE, "Enable the mesh"),
+    (RPC_DISABLE, "Disable the mesh"),
+    (RPC_GET_DELIVERY, "Delivery confirmation status of a sent paid packet"),
+    (RPC_GET_BALANCES, "Remaining prepaid session balances"),
+    (RPC_GET_EARNINGS, "Routing earnings totals per day and recent payments"),
+];
+
+/// Routes returned by `mesh_listroutes` when no `limit` is given
+pub const DEFAULT_LIST_ROUTES_LIMIT: usize = 100;
+
+/// Most routes returned by one `mesh_listroutes` call
+pub const MAX_LIST_ROUTES_LIMIT: usize = 1000;
+
+/// Entries returned by `mesh_getearnings` when no `limit` is given
+pub const DEFAULT_EARNINGS_LIMIT: usize = 100;
+
+/// Most entries returned by one `mesh_getearnings` call
+pub const MAX_EARNINGS_LIMIT: usize = 1000;
+
+/// Dispatch an RPC call to the manager
+///
+/// Module API methods (`mesh.v<N>.*`, see `crate::api`) take the
+/// bincode-encoded request as base64 in `request` and answer with the
+/// encoded response as base64 in `response`.
+pub async fn dispatch(manager: &MeshManager, method: &str, params: &Value) -> Result<Value, MeshError> {
+    debug!("Dispatching mesh RPC call: method={}", method);
+
+    if crate::api::is_api_method(method) {
+        let request = params
+            .get("request")
+            .and_then(Value::as_str)
+            .and_then(|encoded| BASE64.decode(encoded).ok())
+            .ok_or_else(|| MeshError::InvalidRequest("request must be base64".to_string()))?;
+        let response = manager.handle_api_call(method, &request).await?;
+        return Ok(json!({ "response": BASE64.encode(response) }));
+    }
+
+    let capture = manager.capture();
+    let now = manager.now_secs();
+    match method {
+        RPC_CAPTURE_DUMP => Ok(capture.dump(now)),
+        RPC_CAPTURE_CLEAR => Ok(json!({ "cleared": capture.clear() })),
+        RPC_CAPTURE_START => {
+            let duration_secs = match params.get("duration_secs") {
+                None | Some(Value::Null) => None,
+                Some(value) => Some(value.as_u64().ok_or_else(|| {
+                    MeshError::InvalidRequest("duration_secs must be a non-negative integer".to_string())
+                })?),
+            };
+            capture.enable(now, duration_secs);
+            Ok(json!({ "enabled": true }))
+        }
+        RPC_CAPTURE_STOP => {
+            capture.disable();
+            Ok(json!({ "enabled": false }))
+        }
+        RPC_GET_STATS => Ok(manager.stats_json().await),
+        RPC_LIST_ROUTES => list_routes(manager, params, now),
+        RPC_SEND_PACKET => send_packet(manager, params).await,
+        RPC_SET_MODE => {
+            let mode = params
+                .get("mode")
+                .and_then(Value::as_str)
+                .and_then(MeshMode::from_name)
+                .ok_or_else(|| {
+                    MeshError::InvalidRequest("mode must be bitcoin_only, payment_gated or open".to_string())
+                })?;
+            let previous = manager.set_mode(mode).await;
+            Ok(json!({ "previous": previous, "mode": mode }))
+        }
+        RPC_ENABLE => Ok(json!({ "previous": manager.set_enabled(true).await, "enabled": true })),
+        RPC_DISABLE => Ok(json!({ "previous": manager.set_enabled(false).await, "enabled": false })),
+        RPC_GET_DELIVERY => {
+            let sequence = optional_u64(params, "sequence")?
+                .ok_or_else(|| MeshError::InvalidRequest("sequence is required".to_string()))?;
+            let (status, destination) = manager.delivery_status(sequence).ok_or_else(|| {
+                MeshError::InvalidRequest(format!("No paid packet tracked with sequence {}", sequence))
+            })?;
+            Ok(json!({ "sequence": sequence, "destination": hex::encode(destination), "status": status }))
+        }
+        RPC_GET_BALANCES => Ok(json!({ "balances": manager.session_balances() })),
+        RPC_GET_EARNINGS => get_earnings(manager, params, now),
+        other => Err(MeshError::InvalidRequest(format!("Unknown mesh RPC method: {}", other))),
+    }
+}
+
+/// Answer an RPC invocation delivered by the node
+///
+/// Requests other than `RpcCall` are refused; errors become unsuccessful
+/// responses carrying the error text.
+pub async fn answer_request(manager: &MeshManager, request: &RequestMessage) -> ResponseMessage {
+    let result = match &request.payload {
+        RequestPayload::RpcCall { method, params } => dispatch(manager, method, params).await,
+        _ => Err(MeshError::InvalidRequest("Not an RPC call".to_string())),
+    };
+    match result {
+        Ok(value) => ResponseMessage {
+            correlation_id: request.correlation_id,
+            success: true,
+            payload: Some(ResponsePayload::RpcResult(value)),
+            error: None,
+        },
+        Err(e) => ResponseMessage {
+            correlation_id: request.correlation_id,
+            success: false,
+            payload: None,
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+/// One page of routing table entries, ordered by node ID
+fn list_routes(manager: &MeshManager, params: &Value, now: u64) -> Result<Value, MeshError> {
+    let offset = optional_u64(params, "offset")?.unwrap_or(0) as usize;
+    let limit = optional_u64(params, "limit")?
+        .map(|limit| limit as usize)
+        .unwrap_or(DEFAULT_LIST_ROUTES_LIMIT)
+        .clamp(1, MAX_LIST_ROUTES_LIMIT);
+
+    let mut entries = manager.routing_table().entries();
+    entries.sort_by(|a, b| a.node_id.cmp(&b.node_id));
+    let total = entries.len();
+    let routes: Vec<Value> = entries
+        .into_iter()
+        .skip(offset)
+        .take(limit)
+        .map(|entry| {
+            let outcomes = manager.routing_table().outcomes(&entry.node_id);
+            json!({
+                "node_id": hex::encode(entry.node_id),
+                "direct": entry.direct_address.is_some() && entry.next_hop.is_none(),
+                "next_hop": entry.next_hop.map(hex::encode),
+                "route_path": entry.route_path.iter().map(hex::encode).collect::<Vec<_>>(),
+                "cost": entry.route_cost,
+                "quality": entry.quality_score,
+                "successes": outcomes.successes,
+                "failures": outcomes.failures,
+                "age_secs": now.saturating_sub(entry.last_updated),
+            })
+        })
+        .collect();
+    let next_offset = (offset + routes.len() < total).then_some(offset + routes.len());
+    Ok(json!({ "total": total, "routes": routes, "next_offset": next_offset }))
+}
+
+/// Earnings between `from` (default 0) and `to` (default now), in Unix seconds
+fn get_earnings(manager: &MeshManager, params: &Value, now: u64) -> Result<Value, MeshError> {
+    let from = optional_u64(params, "from")?.unwrap_or(0);
+    let to = optional_u64(params, "to")?.unwrap_or(now);
+    if from > to {
+        return Err(MeshError::InvalidRequest("from must not be after to".to_string()));
+    }
+    let limit = optional_u64(params, "limit")?
+        .map(|limit| limit as usize)
+        .unwrap_or(DEFAULT_EARNINGS_LIMIT)
+        .clamp(1, MAX_EARNINGS_LIMIT);
+
+    let report = manager.earnings(from, to, limit);
+    let (payments, amount_sats, fee_sats) = report.totals();
+    Ok(json!({
+        "from": from,
+        "to": to,
+        "payments": payments,
+        "amount_sats": amount_sats,
+        "fee_sats": fee_sats,
+        "days": report.days,
+        "entries": report.entries.iter().map(|entry| entry.to_json()).collect::<Vec<_>>(),
+    }))
+}
+
+/// Build a packet from RPC parameters and route it from this node
+async fn send_packet(manager: &MeshManager, params: &Value) -> Result<Value, MeshError> {
+    let destination = params
+        .get("destination")
+        .and_then(Value::as_str)
+        .and_then(|hex_id| hex::decode(hex_id.trim()).ok())
+        .and_then(|bytes| NodeId::try_from(bytes.as_slice()).ok())
+        .ok_or_else(|| MeshError::InvalidRequest("destination must be a 32-byte hex node ID".to_string()))?;
+    let payload = params
+        .get("payload")
+        .and_then(Value::as_str)
+        .and_then(|encoded| BASE64.decode(encoded).ok())
+        .ok_or_else(|| MeshError::InvalidRequest("payload must be base64".to_string()))?;
+    let payment_proof = match params.get("payment_proof") {
+        None | Some(Value::Null) => None,
+        Some(proof) => Some(
+            serde_json::from_value::<PaymentProof>(proof.clone())
+                .map_err(|e| MeshError::InvalidRequest(format!("Malformed payment_proof: {}", e)))?,
+        ),
+    };
+
+    let sequence = manager.send_packet(destination, payload, payment_proof).await?;
+    Ok(json!({ "sequence": sequence }))
+}
+
+/// A non-negative integer parameter, if present
+fn optional_u64(params: &Value, name: &str) -> Result<Option<u64>, MeshError> {
+    match params.get(name) {
+        None | Some(Value::Null) => Ok(None),
+        Some(value) => value
+            .as_u64()
+            .map(Some)
+            .ok_or_else(|| MeshError::InvalidRequest(format!("{} must be a non-negative integer", name))),
+    }
+}
+
+/// Metadata field carrying the ID that pairs a mesh RPC reply with its call
+pub const CORRELATION_ID_FIELD: &str = "correlation_id";
+
+/// Metadata field marking a packet as a mesh RPC request or reply
+pub const RPC_ROLE_FIELD: &str = "rpc";
+
+/// `RPC_ROLE_FIELD` value of a call
+pub const RPC_REQUEST: &str = "request";
+
+/// `RPC_ROLE_FIELD` value of a reply
+pub const RPC_REPLY: &str = "reply";
+
+impl MeshPacket {
+    /// Correlation ID of a mesh RPC request or reply
+    pub fn correlation_id(&self) -> Option<u64> {
+        self.metadata
+            .as_ref()
+            .and_then(|metadata| metadata.fields.get(CORRELATION_ID_FIELD))
+            .and_then(|id| id.parse().ok())
+    }
+
+    /// Whether this is a reply to a mesh RPC call
+    pub fn is_rpc_reply(&self) -> bool {
+        self.correlation_id().is_some() && self.rpc_role() == Some(RPC_REPLY)
+    }
+
+    /// Whether this is a mesh RPC call expecting a reply
+    pub fn is_rpc_request(&self) -> bool {
+        self.correlation_id().is_some() && self.rpc_role() == Some(RPC_REQUEST)
+    }
+
+    fn rpc_role(&self) -> Option<&str> {
+        self.metadata
+            .as_ref()
+            .and_then(|metadata| metadata.fields.get(RPC_ROLE_FIELD))
+            .map(String::as_str)
+    }
+
+    /// Tag the packet as the reply to the mesh RPC call `correlation_id`
+    pub fn set_rpc_reply(&mut self, correlation_id: u64) {
+        self.tag_rpc(correlation_id, RPC_REPLY);
+    }
+
+    /// Tag the packet as a mesh RPC request or reply
+    fn tag_rpc(&mut self, correlation_id: u64, role: &str) {
+        let metadata = self.metadata.get_or_insert_with(PacketMetadata::default);
+        metadata.fields.insert(CORRELATION_ID_FIELD.to_string(), correlation_id.to_string());
+        metadata.fields.insert(RPC_ROLE_FIELD.to_string(), role.to_string());
+    }
+}
+
+/// A call waiting for its reply
+struct PendingCall {
+    /// Node the call went to; replies from anyone else are ignored
+    destination: NodeId,
+    reply: oneshot::Sender<MeshPacket>,
+}
+
+/// Request-response calls between modules over mesh packets
+///
+/// `call` tags the request with a fresh correlation ID and waits for the
+/// reply, which `MeshManager::handle_incoming_packet` hands to `resolve`
+/// instead of delivering it to the application. The receiving module
+/// answers with `MeshManager::send_reply`.
+pub struct MeshRpc {
+    /// Calls waiting for a reply, by correlation ID
+    pending: DashMap<u64, PendingCall>,
+    /// Next correlation ID (randomly seeded, so IDs are not reused across restarts)
+    next_id: AtomicU64,
+}
+
+impl Default for MeshRpc {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MeshRpc {
+    /// Create a call tracker with no calls in flight
+    pub fn new() -> Self {
+        Self {
+            pending: DashMap::new(),
+            next_id: AtomicU64::new(OsRng.next_u64()),
+        }
+    }
+
+    /// Send `request` with `send` and wait up to `timeout` for its reply
+    ///
+    /// Fails with `Timeout` if no reply arrives in time; the call is
+    /// forgotten either way, so a late reply is dropped.
+    pub async fn call<F, Fut>(&self, mut request: MeshPacket, timeout: Duration, send: F) -> Result<MeshPacket, MeshError>
+    where
+        F: FnOnce(MeshPacket) -> Fut,
+        Fut: Future<Output = Result<(), MeshError>>,
+    {
+        let correlation_id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        request.tag_rpc(correlation_id, RPC_REQUEST);
+        let (reply, reply_rx) = oneshot::channel();
+        self.pending.insert(
+            correlation_id,
+            PendingCall {
+                destination: request.destination,
+                reply,
+            },
+        );
+
+        if let Err(e) = send(request).await {
+            self.pending.remove(&correlation_id);
+            return Err(e);
+        }
+        match tokio::time::timeout(timeout, reply_rx).await {
+            Ok(Ok(reply)) => Ok(reply),
+            Ok(Err(_)) => Err(MeshError::Timeout(format!("RPC call {} was cancelled", correlation_id))),
+            Err(_) => {
+                self.pending.remove(&correlation_id);
+                Err(MeshError::Timeout(format!(
+                    "No reply to RPC call {} within {:?}",
+                    correlation_id, timeout
+                )))
+            }
+        }
+    }
+
+    /// Hand a reply packet to its waiting call
+    ///
+    /// Returns false if no call is waiting for it (unknown or expired
+    /// correlation ID, or a reply from a node the call did not go to).
+    pub fn resolve(&self, reply: &MeshPacket) -> bool {
+        let Some(correlation_id) = reply.correlation_id().filter(|_| reply.is_rpc_reply()) else {
+            return false;
+        };
+        let Some((_, call)) = self
+            .pending
+            .remove_if(&correlation_id, |_, call| call.destination == reply.source)
+        else {
+            debug!("Dropping unexpected RPC reply: correlation_id={}", correlation_id);
+            return false;
+        };
+        call.reply.send(reply.clone()).is_ok()
+    }
+
+    /// Calls waiting for a reply
+    pub fn pending_calls(&self) -> usize {
+        self.pending.len()
+    }
+}
@@ -0,0 +1,264 @@
+//! Packet signing and source authentication
+//!
+//! Packets originated by a node carry a BIP340 Schnorr signature made with
+//! that node's mesh signing key. The signature covers the fields relays never
+//! change (version, type, source, destination, sequence, timestamp, the
+//! source route, the payment proof, the metadata and a hash of the payload),
+//! so a relay cannot strip or swap a proof or route constraints. `route` and
+//! `ttl` are rewritten at every hop, and `compressed` and `checksum` only
+//! describe the encoding on one link; they are excluded. Receivers look up
+//! the signer's key by source node ID in `PeerKeys`.
+
+use crate::error::MeshError;
+use crate::packet::MeshPacket;
+use crate::routing::NodeId;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::OsRng;
+use dashmap::DashMap;
+use secp256k1::schnorr::Signature;
+use secp256k1::{Keypair, Message, Secp256k1, XOnlyPublicKey};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+
+/// Domain separator for packet signatures (v2: payment proof and metadata covered)
+const SIGNATURE_CONTEXT: &[u8] = b"bllvm-mesh-packet-sig-v2";
+
+/// X-only public key of a signing node (32 bytes)
+pub type SigningPublicKey = [u8; 32];
+
+/// Generate a fresh signing key
+pub fn generate_signing_key() -> Keypair {
+    loop {
+        let mut secret = [0u8; 32];
+        OsRng.fill_bytes(&mut secret);
+        if let Ok(keypair) = signing_key_from_bytes(&secret) {
+            return keypair;
+        }
+    }
+}
+
+/// Restore a signing key from its secret bytes
+pub fn signing_key_from_bytes(secret: &[u8; 32]) -> Result<Keypair, MeshError> {
+    Keypair::from_seckey_slice(&Secp256k1::new(), secret)
+        .map_err(|e| MeshError::ConfigError(format!("Invalid signing key: {}", e)))
+}
+
+/// Public key to hand out to peers
+pub fn signing_public_key(keypair: &Keypair) -> SigningPublicKey {
+    keypair.x_only_public_key().0.serialize()
+}
+
+/// Node ID of a node with the given signing key
+///
+/// A node's ID is bound to its signing key in every mode (with Noise, the
+/// signed Hello binds the session's static key to it), so neither a Hello
+/// nor a link-state advertisement can claim another node's ID.
+pub fn node_id_from_signing_key(public_key: &SigningPublicKey) -> NodeId {
+    Sha256::digest(public_key).into()
+}
+
+/// Known signing keys of other nodes, by node ID
+#[derive(Default)]
+pub struct PeerKeys {
+    keys: DashMap<NodeId, XOnlyPublicKey>,
+}
+
+impl PeerKeys {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a node's signing key (replaces any previous key)
+    pub fn register(&self, node_id: NodeId, public_key: &SigningPublicKey) -> Result<(), MeshError> {
+        let key = XOnlyPublicKey::from_slice(public_key)
+            .map_err(|e| MeshError::InvalidRequest(format!("Invalid signing public key: {}", e)))?;
+        self.keys.insert(node_id, key);
+        Ok(())
+    }
+
+    /// Get a node's signing key
+    pub fn get(&self, node_id: &NodeId) -> Option<XOnlyPublicKey> {
+        self.keys.get(node_id).map(|entry| *entry)
+    }
+
+    /// Forget a node's signing key
+    pub fn remove(&self, node_id: &NodeId) -> bool {
+        self.keys.remove(node_id).is_some()
+    }
+
+    /// Number of known keys
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Whether no keys are known
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+}
+
+/// Digest of the immutable packet fields
+fn signing_digest(packet: &MeshPacket) -> Message {
+    let mut hasher = Sha256::new();
+    hasher.update(SIGNATURE_CONTEXT);
+    hasher.update([packet.version]);
+    hasher.update(bincode::serialize(&packet.packet_type).expect("packet type should be serializable"));
+    hasher.update(packet.source);
+    hasher.update(packet.destination);
+    hasher.update(packet.sequence.to_be_bytes());
+    hasher.update(packet.timestamp.to_be_bytes());
+    hasher.update([packet.source_routed as u8]);
+    for node in &packet.fixed_route {
+        hasher.update(node);
+    }
+    hasher.update(bincode::serialize(&packet.payment_proof).expect("payment proof should be serializable"));
+    // Metadata fields are a hash map: sort them so every node hashes the same bytes
+    let metadata = packet.metadata.as_ref().map(|metadata| {
+        (
+            &metadata.protocol,
+            metadata.fields.iter().collect::<BTreeMap<_, _>>(),
+            &metadata.route_constraints,
+            &metadata.fragment,
+        )
+    });
+    hasher.update(bincode::serialize(&metadata).expect("packet metadata should be serializable"));
+    hasher.update(Sha256::digest(&packet.payload));
+    Message::from_digest(hasher.finalize().into())
+}
+
+impl MeshPacket {
+    /// Sign the packet as its source
+    pub fn sign(&mut self, keypair: &Keypair) {
+        let signature = Secp256k1::new().sign_schnorr_no_aux_rand(&signing_digest(self), keypair);
+        self.signature = Some(signature.as_ref().to_vec());
+    }
+
+    /// Verify the packet's signature against the source's public key
+    pub fn verify_signature(&self, public_key: &XOnlyPublicKey) -> Result<(), MeshError> {
+        let bytes = self
+            .signature
+            .as_ref()
+            .ok_or_else(|| MeshError::InvalidSignature("Packet is not signed".to_string()))?;
+        let signature = Signature::from_slice(bytes)
+            .map_err(|e| MeshError::InvalidSignature(format!("Malformed signature: {}", e)))?;
+        Secp256k1::new()
+            .verify_schnorr(&signature, &signing_digest(self), public_key)
+            .map_err(|_| {
+                MeshError::InvalidSignature(format!(
+                    "Signature does not match source {:x?}",
+                    &self.source[..8]
+                ))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::{MeshPacketBuilder, PacketMetadata, PacketType, RouteConstraints};
+    use crate::payment_proof::PaymentProof;
+
+    fn signed_packet(keypair: &Keypair) -> MeshPacket {
+        let mut packet = MeshPacketBuilder::new([1u8; 32], [9u8; 32])
+            .packet_type(PacketType::BitcoinP2P)
+            .payload(vec![1, 2, 3])
+            .build()
+            .unwrap();
+        packet.sequence = 42;
+        packet.sign(keypair);
+        packet
+    }
+
+    #[test]
+    fn test_signature_survives_forwarding() {
+        let keypair = generate_signing_key();
+        let mut packet = signed_packet(&keypair);
+        let public_key = keypair.x_only_public_key().0;
+
+        // Relays rewrite the route and hop budget
+        packet.add_to_route([5u8; 32]);
+        packet.ttl -= 1;
+        assert!(packet.verify_signature(&public_key).is_ok());
+    }
+
+    #[test]
+    fn test_tampered_fields_rejected() {
+        let keypair = generate_signing_key();
+        let public_key = keypair.x_only_public_key().0;
+
+        let mut payload = signed_packet(&keypair);
+        payload.payload[0] ^= 1;
+        let mut source = signed_packet(&keypair);
+        source.source = [2u8; 32];
+        let mut sequence = signed_packet(&keypair);
+        sequence.sequence += 1;
+        let mut packet_type = signed_packet(&keypair);
+        packet_type.packet_type = PacketType::Control;
+        let mut fixed_route = signed_packet(&keypair);
+        fixed_route.source_routed = true;
+        fixed_route.fixed_route = vec![[1u8; 32], [9u8; 32]];
+        let mut payment_proof = signed_packet(&keypair);
+        payment_proof.payment_proof = Some(PaymentProof::HtlcEscrow {
+            payment_hash: [3u8; 32],
+            htlc_expiry: 1_000,
+            amount_msats: 1_000,
+        });
+        let mut metadata = signed_packet(&keypair);
+        metadata.metadata = Some(PacketMetadata {
+            route_constraints: Some(RouteConstraints {
+                avoid: vec![[5u8; 32]],
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+
+        for packet in [payload, source, sequence, packet_type, fixed_route, payment_proof, metadata] {
+            assert!(matches!(
+                packet.verify_signature(&public_key),
+                Err(MeshError::InvalidSignature(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_metadata_field_order_ignored() {
+        let keypair = generate_signing_key();
+        let fields: Vec<(String, String)> = (0..16).map(|i| (format!("key{}", i), i.to_string())).collect();
+        let mut packet = MeshPacketBuilder::new([1u8; 32], [9u8; 32])
+            .packet_type(PacketType::BitcoinP2P)
+            .payload(vec![1, 2, 3])
+            .build()
+            .unwrap();
+        packet.metadata = Some(PacketMetadata {
+            fields: fields.iter().cloned().collect(),
+            ..Default::default()
+        });
+        packet.sign(&keypair);
+
+        // A relay decoding the packet may rebuild the map in another order
+        packet.metadata.as_mut().unwrap().fields = fields.into_iter().rev().collect();
+        assert!(packet.verify_signature(&keypair.x_only_public_key().0).is_ok());
+    }
+
+    #[test]
+    fn test_wrong_key_and_unsigned_rejected() {
+        let packet = signed_packet(&generate_signing_key());
+        let other = generate_signing_key().x_only_public_key().0;
+        assert!(packet.verify_signature(&other).is_err());
+
+        let unsigned = MeshPacketBuilder::new([1u8; 32], [9u8; 32])
+            .packet_type(PacketType::BitcoinP2P)
+            .payload(vec![1])
+            .build()
+            .unwrap();
+        assert!(unsigned.verify_signature(&other).is_err());
+    }
+
+    #[test]
+    fn test_key_restores_from_secret() {
+        let keypair = generate_signing_key();
+        let restored = signing_key_from_bytes(&keypair.secret_bytes()).unwrap();
+        assert_eq!(signing_public_key(&keypair), signing_public_key(&restored));
+    }
+}
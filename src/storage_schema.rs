@@ -0,0 +1,210 @@
+//! Storage schema versioning and migrations
+//!
+//! Every persisted storage tree carries a schema version under
+//! `SCHEMA_VERSION_KEY`. On startup each subsystem opens its tree through
+//! `open_versioned_tree`, which runs registered migrations in order and
+//! bumps the version marker in the same storage transaction as each
+//! migration's writes. A tree written by a newer build is refused rather
+//! than misparsed.
+//!
+//! Trees that predate versioning (data present, no marker) are version 0.
+
+use crate::error::MeshError;
+use async_trait::async_trait;
+use bllvm_node::module::ipc::protocol::StorageOperation;
+use bllvm_node::module::traits::{ModuleError, NodeAPI};
+use tracing::info;
+
+/// Reserved key holding a tree's schema version (u32, big-endian)
+pub const SCHEMA_VERSION_KEY: &[u8] = b"__schema_version";
+
+/// Migration from one schema version to the next
+pub struct Migration {
+    /// Version this migration upgrades from (to `from + 1`)
+    pub from: u32,
+    /// What the migration changes (logged when it runs)
+    pub description: &'static str,
+    /// Rewrite the tree's entries (version marker excluded)
+    pub migrate: fn(&[(Vec<u8>, Vec<u8>)]) -> Result<Vec<StorageOperation>, MeshError>,
+}
+
+/// Schema of one storage tree
+pub struct TreeSchema {
+    /// Storage tree name
+    pub tree: &'static str,
+    /// Version written by this build
+    pub version: u32,
+    /// Migrations, one per version step below `version`
+    pub migrations: &'static [Migration],
+}
+
+/// Migration for steps that only start tracking the version (format unchanged)
+pub fn tag_only(_entries: &[(Vec<u8>, Vec<u8>)]) -> Result<Vec<StorageOperation>, MeshError> {
+    Ok(Vec::new())
+}
+
+/// Open a storage tree, migrating it to the schema version of this build
+///
+/// Returns the tree ID. Fails with `UnsupportedVersion` when the tree was
+/// written by a newer build.
+pub async fn open_versioned_tree(
+    node_api: &dyn NodeAPI,
+    schema: &TreeSchema,
+) -> Result<String, MeshError> {
+    let tree_id = node_api
+        .storage_open_tree(schema.tree.to_string())
+        .await
+        .map_err(|e| MeshError::ModuleError(format!("Failed to open storage tree {}: {}", schema.tree, e)))?;
+
+    let entries: Vec<(Vec<u8>, Vec<u8>)> = node_api
+        .storage_iter(tree_id.clone())
+        .await
+        .map_err(|e| MeshError::ModuleError(format!("Failed to read storage tree {}: {}", schema.tree, e)))?
+        .into_iter()
+        .filter(|(key, _)| key != SCHEMA_VERSION_KEY)
+        .collect();
+
+    let stored_version = match node_api
+        .storage_get(tree_id.clone(), SCHEMA_VERSION_KEY.to_vec())
+        .await
+        .map_err(|e| MeshError::ModuleError(format!("Failed to read schema version of {}: {}", schema.tree, e)))?
+    {
+        Some(value) => decode_version(schema.tree, &value)?,
+        // Fresh tree: nothing to migrate
+        None if entries.is_empty() => {
+            write_version(node_api, &tree_id, Vec::new(), schema.version).await?;
+            return Ok(tree_id);
+        }
+        // Written before versioning
+        None => 0,
+    };
+
+    if stored_version > schema.version {
+        return Err(MeshError::UnsupportedVersion(format!(
+            "Storage tree {} is at schema version {}, this build supports up to {}; refusing to start",
+            schema.tree, stored_version, schema.version
+        )));
+    }
+
+    let mut entries = entries;
+    for version in stored_version..schema.version {
+        let migration = schema
+            .migrations
+            .iter()
+            .find(|migration| migration.from == version)
+            .ok_or_else(|| {
+                MeshError::ModuleError(format!(
+                    "No migration for storage tree {} from schema version {}",
+                    schema.tree, version
+                ))
+            })?;
+
+        info!(
+            "Migrating storage tree {} from schema version {} to {}: {}",
+            schema.tree,
+            version,
+            version + 1,
+            migration.description
+        );
+        let operations = (migration.migrate)(&entries)?;
+        apply(&mut entries, &operations);
+        write_version(node_api, &tree_id, operations, version + 1).await?;
+    }
+
+    Ok(tree_id)
+}
+
+/// Storage that filters a tree by key prefix on the node
+///
+/// `NodeAPI` has no prefix call. Subsystems that scan by prefix take an
+/// optional `PrefixStorage` next to their `dyn NodeAPI` and read through
+/// `iter_prefix`, which falls back to `storage_iter_prefix` without one.
+/// Implemented by `NodeApiIpc`.
+#[async_trait]
+pub trait PrefixStorage: Send + Sync {
+    /// Pairs of a storage tree whose keys start with `prefix`, in key order
+    async fn storage_iter_prefix(
+        &self,
+        tree_id: String,
+        prefix: Vec<u8>,
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>, ModuleError>;
+}
+
+/// Pairs of a storage tree whose keys start with `prefix`
+///
+/// Filtered on the node through `prefix_storage` when given, otherwise by
+/// `storage_iter_prefix`.
+pub async fn iter_prefix(
+    node_api: &dyn NodeAPI,
+    prefix_storage: Option<&dyn PrefixStorage>,
+    tree_id: String,
+    prefix: &[u8],
+) -> Result<Vec<(Vec<u8>, Vec<u8>)>, ModuleError> {
+    match prefix_storage {
+        Some(storage) => storage.storage_iter_prefix(tree_id, prefix.to_vec()).await,
+        None => storage_iter_prefix(node_api, tree_id, prefix).await,
+    }
+}
+
+/// Pairs of a storage tree whose keys start with `prefix`, filtered here
+///
+/// Fallback for a `dyn NodeAPI` without `PrefixStorage`: the tree is read
+/// whole and filtered in the module. The version marker never matches a
+/// subsystem's key prefix.
+pub async fn storage_iter_prefix(
+    node_api: &dyn NodeAPI,
+    tree_id: String,
+    prefix: &[u8],
+) -> Result<Vec<(Vec<u8>, Vec<u8>)>, ModuleError> {
+    let mut pairs = node_api.storage_iter(tree_id).await?;
+    pairs.retain(|(key, _)| key.starts_with(prefix));
+    Ok(pairs)
+}
+
+/// Read a tree's schema version marker
+pub async fn stored_version(node_api: &dyn NodeAPI, tree_id: &str) -> Result<Option<u32>, MeshError> {
+    node_api
+        .storage_get(tree_id.to_string(), SCHEMA_VERSION_KEY.to_vec())
+        .await
+        .map_err(|e| MeshError::ModuleError(format!("Failed to read schema version of {}: {}", tree_id, e)))?
+        .map(|value| decode_version(tree_id, &value))
+        .transpose()
+}
+
+/// Apply operations together with the new version marker, atomically
+async fn write_version(
+    node_api: &dyn NodeAPI,
+    tree_id: &str,
+    mut operations: Vec<StorageOperation>,
+    version: u32,
+) -> Result<(), MeshError> {
+    operations.push(StorageOperation::Insert {
+        key: SCHEMA_VERSION_KEY.to_vec(),
+        value: version.to_be_bytes().to_vec(),
+    });
+    node_api
+        .storage_transaction(tree_id.to_string(), operations)
+        .await
+        .map_err(|e| MeshError::ModuleError(format!("Failed to migrate storage tree {}: {}", tree_id, e)))
+}
+
+/// Mirror a migration's writes so the next step sees the migrated entries
+fn apply(entries: &mut Vec<(Vec<u8>, Vec<u8>)>, operations: &[StorageOperation]) {
+    for operation in operations {
+        match operation {
+            StorageOperation::Insert { key, value } => {
+                match entries.iter_mut().find(|(existing, _)| existing == key) {
+                    Some(entry) => entry.1 = value.clone(),
+                    None => entries.push((key.clone(), value.clone())),
+                }
+            }
+            StorageOperation::Remove { key } => entries.retain(|(existing, _)| existing != key),
+        }
+    }
+}
+
+fn decode_version(tree: &str, value: &[u8]) -> Result<u32, MeshError> {
+    <[u8; 4]>::try_from(value)
+        .map(u32::from_be_bytes)
+        .map_err(|_| MeshError::ModuleError(format!("Corrupt schema version marker in {}", tree)))
+}
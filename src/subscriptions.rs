@@ -0,0 +1,240 @@
+//! Topic subscriptions of mesh peers
+//!
+//! A node asks another to push it data by sending it a
+//! `ControlMessage::Subscribe` with the topics it wants (an empty list
+//! unsubscribes). Subscriptions are kept per subscriber node ID and stored
+//! next to the routes in the `mesh_routes` tree under
+//! `peer/{node_id_hex}`, so they survive restarts.
+//!
+//! `MeshTopic::MempoolTransactions` subscribers are sent each transaction the
+//! node accepts into its mempool as a Bitcoin P2P `inv` + `tx` message pair,
+//! which the routing policy relays for free.
+
+use crate::error::MeshError;
+use crate::routing::{NodeId, ROUTES_SCHEMA};
+use crate::storage_schema::{iter_prefix, open_versioned_tree, PrefixStorage};
+use bllvm_node::module::traits::NodeAPI;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use tracing::{debug, warn};
+
+/// Key prefix of subscriptions in the routes tree (followed by the hex node ID)
+pub const SUBSCRIPTION_KEY_PREFIX: &[u8] = b"peer/";
+
+/// Bitcoin P2P inventory type of a transaction
+const MSG_TX: u32 = 1;
+
+/// Data a node can subscribe to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum MeshTopic {
+    /// Transactions accepted into the sender's mempool
+    MempoolTransactions,
+}
+
+/// Topics one node subscribed to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MeshSubscription {
+    /// Subscriber
+    pub node_id: NodeId,
+    /// Subscribed topics (empty = none)
+    pub topics: Vec<MeshTopic>,
+}
+
+/// Persistent topic subscriptions, by subscriber
+pub struct SubscriptionManager {
+    node_api: Arc<dyn NodeAPI>,
+    /// Storage tree ID (None if storage is unavailable)
+    tree_id: Option<String>,
+    subscriptions: DashMap<NodeId, Vec<MeshTopic>>,
+}
+
+impl SubscriptionManager {
+    /// Open the subscriptions, restoring stored ones
+    ///
+    /// Stored subscriptions are read with a node-side prefix scan when
+    /// `prefix_storage` is given. Fails only if the stored schema is newer
+    /// than this build.
+    pub async fn load(
+        node_api: Arc<dyn NodeAPI>,
+        prefix_storage: Option<Arc<dyn PrefixStorage>>,
+    ) -> Result<Self, MeshError> {
+        let tree_id = match open_versioned_tree(node_api.as_ref(), &ROUTES_SCHEMA).await {
+            Ok(tree_id) => Some(tree_id),
+            Err(e @ MeshError::UnsupportedVersion(_)) => return Err(e),
+            Err(e) => {
+                warn!("Subscription storage unavailable, keeping subscriptions in memory only: {}", e);
+                None
+            }
+        };
+
+        let manager = Self {
+            node_api,
+            tree_id,
+            subscriptions: DashMap::new(),
+        };
+
+        if let Some(ref tree_id) = manager.tree_id {
+            let stored = iter_prefix(
+                manager.node_api.as_ref(),
+                prefix_storage.as_deref(),
+                tree_id.clone(),
+                SUBSCRIPTION_KEY_PREFIX,
+            )
+            .await;
+            match stored {
+                Ok(stored) => {
+                    for (key, value) in stored {
+                        let (Some(node_id), Ok(topics)) = (
+                            subscription_key_node_id(&key),
+                            bincode::deserialize::<Vec<MeshTopic>>(&value),
+                        ) else {
+                            continue;
+                        };
+                        manager.subscriptions.insert(node_id, topics);
+                    }
+                    debug!("Restored {} subscriptions", manager.subscriptions.len());
+                }
+                Err(e) => warn!("Failed to restore subscriptions: {}", e),
+            }
+        }
+
+        Ok(manager)
+    }
+
+    /// Replace a node's subscription (no topics unsubscribes it)
+    pub async fn subscribe(&self, subscription: MeshSubscription) {
+        let MeshSubscription { node_id, mut topics } = subscription;
+        topics.sort();
+        topics.dedup();
+        let key = subscription_key(&node_id);
+
+        let result = if topics.is_empty() {
+            self.subscriptions.remove(&node_id);
+            debug!("Unsubscribed: node_id={:x?}", &node_id[..8]);
+            match self.tree_id {
+                Some(ref tree_id) => self.node_api.storage_remove(tree_id.clone(), key).await,
+                None => Ok(()),
+            }
+        } else {
+            debug!("Subscribed: node_id={:x?}, topics={:?}", &node_id[..8], topics);
+            let value = bincode::serialize(&topics).expect("subscription topics should be serializable");
+            self.subscriptions.insert(node_id, topics);
+            match self.tree_id {
+                Some(ref tree_id) => self.node_api.storage_insert(tree_id.clone(), key, value).await,
+                None => Ok(()),
+            }
+        };
+        if let Err(e) = result {
+            warn!("Failed to persist subscription: {}", e);
+        }
+    }
+
+    /// Nodes subscribed to a topic
+    pub fn subscribers(&self, topic: MeshTopic) -> Vec<NodeId> {
+        self.subscriptions
+            .iter()
+            .filter(|entry| entry.value().contains(&topic))
+            .map(|entry| *entry.key())
+            .collect()
+    }
+
+    /// A node's subscription, if it has one
+    pub fn get(&self, node_id: &NodeId) -> Option<MeshSubscription> {
+        self.subscriptions.get(node_id).map(|topics| MeshSubscription {
+            node_id: *node_id,
+            topics: topics.clone(),
+        })
+    }
+
+    /// Number of subscribed nodes
+    pub fn len(&self) -> usize {
+        self.subscriptions.len()
+    }
+
+    /// Whether no node is subscribed
+    pub fn is_empty(&self) -> bool {
+        self.subscriptions.is_empty()
+    }
+}
+
+/// Storage key of a node's subscription
+fn subscription_key(node_id: &NodeId) -> Vec<u8> {
+    let mut key = SUBSCRIPTION_KEY_PREFIX.to_vec();
+    key.extend_from_slice(hex::encode(node_id).as_bytes());
+    key
+}
+
+/// Subscriber of a `peer/{node_id_hex}` key
+fn subscription_key_node_id(key: &[u8]) -> Option<NodeId> {
+    let hex_id = key.strip_prefix(SUBSCRIPTION_KEY_PREFIX)?;
+    hex::decode(hex_id).ok()?.try_into().ok()
+}
+
+/// P2P message start bytes of a Bitcoin network (`mesh.bitcoin_network`)
+pub fn network_magic(network: &str) -> Option<[u8; 4]> {
+    match network {
+        "mainnet" => Some([0xf9, 0xbe, 0xb4, 0xd9]),
+        "testnet" => Some([0x0b, 0x11, 0x09, 0x07]),
+        "regtest" => Some([0xfa, 0xbf, 0xb5, 0xda]),
+        _ => None,
+    }
+}
+
+/// Frame a Bitcoin P2P message: start bytes, command, length and checksum
+pub fn bitcoin_message(magic: [u8; 4], command: &str, payload: &[u8]) -> Vec<u8> {
+    let mut command_bytes = [0u8; 12];
+    command_bytes[..command.len()].copy_from_slice(command.as_bytes());
+    let checksum = Sha256::digest(Sha256::digest(payload));
+
+    let mut message = Vec::with_capacity(24 + payload.len());
+    message.extend_from_slice(&magic);
+    message.extend_from_slice(&command_bytes);
+    message.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    message.extend_from_slice(&checksum[..4]);
+    message.extend_from_slice(payload);
+    message
+}
+
+/// `inv` announcing one transaction followed by the `tx` carrying it
+pub fn mempool_relay_payload(magic: [u8; 4], txid: &[u8; 32], raw_tx: &[u8]) -> Vec<u8> {
+    let mut inventory = Vec::with_capacity(37);
+    inventory.push(1); // CompactSize count
+    inventory.extend_from_slice(&MSG_TX.to_le_bytes());
+    inventory.extend_from_slice(txid);
+
+    let mut payload = bitcoin_message(magic, "inv", &inventory);
+    payload.extend_from_slice(&bitcoin_message(magic, "tx", raw_tx));
+    payload
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routing_policy::{DetectedProtocol, MeshMode, RoutingPolicyEngine};
+
+    #[test]
+    fn test_relay_payload_is_free_bitcoin_traffic() {
+        let magic = network_magic("mainnet").unwrap();
+        let payload = mempool_relay_payload(magic, &[7u8; 32], &[1, 2, 3]);
+
+        // 24-byte header + 37-byte inventory, then the tx message
+        assert_eq!(&payload[4..7], b"inv");
+        assert_eq!(&payload[16..20], &37u32.to_le_bytes());
+        assert_eq!(&payload[61..65], &magic);
+        assert_eq!(&payload[65..67], b"tx");
+        assert_eq!(&payload[85..], &[1, 2, 3]);
+
+        let engine = RoutingPolicyEngine::new(MeshMode::PaymentGated);
+        assert_eq!(engine.detect_protocol(&payload), DetectedProtocol::BitcoinP2P);
+    }
+
+    #[test]
+    fn test_subscription_keys_round_trip() {
+        let key = subscription_key(&[0xab; 32]);
+        assert!(key.starts_with(SUBSCRIPTION_KEY_PREFIX));
+        assert_eq!(subscription_key_node_id(&key), Some([0xab; 32]));
+        assert_eq!(subscription_key_node_id(b"route/ab"), None);
+    }
+}
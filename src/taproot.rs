@@ -0,0 +1,509 @@
+//! Taproot script path payment proofs
+//!
+//! A `PaymentProof::TaprootScript` shows that an on-chain Taproot output
+//! commits to a script (via the control block's Merkle path) and that the
+//! sender can satisfy that script (via the witness). This lets mesh
+//! applications gate access on conditions beyond "a payment was made", such
+//! as membership of a multisig group.
+//!
+//! Nothing is spent, so there is no transaction for signatures to commit to.
+//! Instead `OP_CHECKSIG` and friends check BIP-340 signatures over
+//! `proof_message()`, which binds the outpoint, amount and timestamp of the
+//! proof. Scripts are run by a small tapscript interpreter covering pushes,
+//! signature checks, hash locks and number comparisons; any other opcode
+//! fails the proof.
+
+use crate::error::MeshError;
+use crate::payment_proof::{tagged_hash, PaymentProof, VerificationResult, TAPROOT_PROOF_MAX_AGE_SECONDS};
+use bllvm_node::module::traits::NodeAPI;
+use secp256k1::schnorr::Signature;
+use secp256k1::{Message, Parity, Scalar, Secp256k1, XOnlyPublicKey};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use tracing::{debug, warn};
+
+/// Leaf version of BIP-342 tapscript
+pub const TAPSCRIPT_LEAF_VERSION: u8 = 0xc0;
+
+/// Longest Merkle path a control block may carry
+const TAPROOT_CONTROL_MAX_NODE_COUNT: usize = 128;
+
+/// Largest stack a proof script may build
+const MAX_STACK_SIZE: usize = 1000;
+
+const OP_0: u8 = 0x00;
+const OP_PUSHDATA1: u8 = 0x4c;
+const OP_PUSHDATA2: u8 = 0x4d;
+const OP_PUSHDATA4: u8 = 0x4e;
+const OP_1NEGATE: u8 = 0x4f;
+const OP_1: u8 = 0x51;
+const OP_16: u8 = 0x60;
+const OP_VERIFY: u8 = 0x69;
+const OP_DROP: u8 = 0x75;
+const OP_DUP: u8 = 0x76;
+const OP_EQUAL: u8 = 0x87;
+const OP_EQUALVERIFY: u8 = 0x88;
+const OP_NUMEQUAL: u8 = 0x9c;
+const OP_NUMEQUALVERIFY: u8 = 0x9d;
+const OP_GREATERTHANOREQUAL: u8 = 0xa2;
+const OP_SHA256: u8 = 0xa8;
+const OP_CHECKSIG: u8 = 0xac;
+const OP_CHECKSIGVERIFY: u8 = 0xad;
+const OP_CHECKSIGADD: u8 = 0xba;
+
+/// Message proof scripts sign: `hash_blvm-mesh/TaprootProof(txid || vout || amount_sats || timestamp)`
+pub fn proof_message(txid: &[u8; 32], vout: u32, amount_sats: u64, timestamp: u64) -> [u8; 32] {
+    tagged_hash(
+        "blvm-mesh/TaprootProof",
+        &[
+            &txid[..],
+            &vout.to_le_bytes()[..],
+            &amount_sats.to_le_bytes()[..],
+            &timestamp.to_le_bytes()[..],
+        ],
+    )
+}
+
+/// BIP-341 leaf hash of a tapscript
+pub fn tapleaf_hash(leaf_version: u8, script: &[u8]) -> [u8; 32] {
+    let mut prefix = vec![leaf_version];
+    write_compact_size(&mut prefix, script.len() as u64);
+    tagged_hash("TapLeaf", &[&prefix[..], script])
+}
+
+/// BIP-341 branch hash of two Merkle nodes (in either order)
+pub fn tapbranch_hash(a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
+    let (left, right) = if a <= b { (a, b) } else { (b, a) };
+    tagged_hash("TapBranch", &[&left[..], &right[..]])
+}
+
+/// Output key committing to `merkle_root` under `internal_key`, with its parity
+pub fn taproot_output_key(
+    internal_key: &XOnlyPublicKey,
+    merkle_root: &[u8; 32],
+) -> Result<(XOnlyPublicKey, Parity), MeshError> {
+    let tweak = tagged_hash("TapTweak", &[&internal_key.serialize()[..], &merkle_root[..]]);
+    let tweak = Scalar::from_be_bytes(tweak)
+        .map_err(|_| MeshError::PaymentVerification("Taproot tweak out of range".to_string()))?;
+    internal_key
+        .add_tweak(&Secp256k1::verification_only(), &tweak)
+        .map_err(|e| MeshError::PaymentVerification(format!("Taproot tweak failed: {}", e)))
+}
+
+/// Checks Taproot script path proofs against the UTXO set
+pub struct TaprootVerifier {
+    node_api: Arc<dyn NodeAPI>,
+}
+
+impl TaprootVerifier {
+    /// Create a Taproot verifier
+    pub fn new(node_api: Arc<dyn NodeAPI>) -> Self {
+        Self { node_api }
+    }
+
+    /// Verify a `PaymentProof::TaprootScript`
+    ///
+    /// The control block must commit `script` to the output key of the
+    /// unspent output `txid:vout`, which must hold at least `amount_sats`,
+    /// and the witness must satisfy the script. Fails with
+    /// `InvalidRequest` for other proof types.
+    pub async fn verify(&self, proof: &PaymentProof) -> Result<VerificationResult, MeshError> {
+        let PaymentProof::TaprootScript {
+            control_block,
+            script,
+            witness,
+            txid,
+            vout,
+            amount_sats,
+            timestamp,
+        } = proof
+        else {
+            return Err(MeshError::InvalidRequest("Not a Taproot script proof".to_string()));
+        };
+
+        let output_key = match commitment_output_key(control_block, script) {
+            Ok(output_key) => output_key,
+            Err(reason) => {
+                warn!("Invalid Taproot control block: {}", reason);
+                return Ok(VerificationResult::failure(reason));
+            }
+        };
+
+        let outpoint = bllvm_protocol::OutPoint {
+            hash: *txid,
+            index: (*vout).into(),
+        };
+        let utxo = match self.node_api.get_utxo(&outpoint).await {
+            Ok(Some(utxo)) => utxo,
+            Ok(None) => {
+                return Ok(VerificationResult::failure(
+                    "Taproot output not found or already spent".to_string(),
+                ));
+            }
+            Err(e) => {
+                return Err(MeshError::PaymentVerification(format!(
+                    "Could not look up Taproot output: {}",
+                    e
+                )));
+            }
+        };
+
+        let mut expected_script_pubkey = vec![OP_1, 32];
+        expected_script_pubkey.extend_from_slice(&output_key.serialize());
+        if utxo.script_pubkey.as_slice() != expected_script_pubkey.as_slice() {
+            warn!("Taproot script not committed to output {}:{}", hex::encode(txid), vout);
+            return Ok(VerificationResult::failure(
+                "Script is not committed to the Taproot output".to_string(),
+            ));
+        }
+        let value = u64::try_from(utxo.value).unwrap_or(0);
+        if value < *amount_sats {
+            return Ok(VerificationResult::failure(format!(
+                "Taproot output holds {} sats, proof claims {}",
+                value, amount_sats
+            )));
+        }
+
+        let message = proof_message(txid, *vout, *amount_sats, *timestamp);
+        if let Err(reason) = execute_tapscript(script, witness, &message) {
+            debug!("Taproot script not satisfied: {}", reason);
+            return Ok(VerificationResult::failure(format!(
+                "Taproot script not satisfied: {}",
+                reason
+            )));
+        }
+
+        Ok(VerificationResult::success(
+            *amount_sats,
+            *timestamp,
+            Some(timestamp.saturating_add(TAPROOT_PROOF_MAX_AGE_SECONDS)),
+        ))
+    }
+}
+
+/// Output key a control block and tapscript commit to
+fn commitment_output_key(control_block: &[u8], script: &[u8]) -> Result<XOnlyPublicKey, String> {
+    if control_block.len() < 33
+        || (control_block.len() - 33) % 32 != 0
+        || (control_block.len() - 33) / 32 > TAPROOT_CONTROL_MAX_NODE_COUNT
+    {
+        return Err(format!("Invalid control block length {}", control_block.len()));
+    }
+    let leaf_version = control_block[0] & 0xfe;
+    if leaf_version != TAPSCRIPT_LEAF_VERSION {
+        return Err(format!("Unsupported leaf version {:#04x}", leaf_version));
+    }
+    let internal_key = XOnlyPublicKey::from_slice(&control_block[1..33])
+        .map_err(|_| "Invalid internal key".to_string())?;
+
+    let merkle_root = control_block[33..].chunks_exact(32).fold(
+        tapleaf_hash(leaf_version, script),
+        |node, sibling| tapbranch_hash(&node, sibling.try_into().expect("chunks are 32 bytes")),
+    );
+    let (output_key, parity) = taproot_output_key(&internal_key, &merkle_root).map_err(|e| e.to_string())?;
+    if parity.to_u8() != control_block[0] & 1 {
+        return Err("Control block parity does not match output key".to_string());
+    }
+    Ok(output_key)
+}
+
+/// Run a tapscript on the witness stack (last item on top)
+///
+/// Succeeds if the script leaves exactly one true element.
+fn execute_tapscript(script: &[u8], witness: &[Vec<u8>], message: &[u8; 32]) -> Result<(), String> {
+    let secp = Secp256k1::verification_only();
+    let message = Message::from_digest(*message);
+    let mut stack: Vec<Vec<u8>> = witness.to_vec();
+    let mut pc = 0;
+
+    while pc < script.len() {
+        let opcode = script[pc];
+        pc += 1;
+        match opcode {
+            OP_0 => stack.push(Vec::new()),
+            0x01..=0x4b | OP_PUSHDATA1 | OP_PUSHDATA2 | OP_PUSHDATA4 => {
+                let (len, width) = match opcode {
+                    OP_PUSHDATA1 => (read_le(script, pc, 1)?, 1),
+                    OP_PUSHDATA2 => (read_le(script, pc, 2)?, 2),
+                    OP_PUSHDATA4 => (read_le(script, pc, 4)?, 4),
+                    _ => (opcode as usize, 0),
+                };
+                pc += width;
+                let data = script
+                    .get(pc..pc.saturating_add(len))
+                    .ok_or_else(|| "push past end of script".to_string())?;
+                stack.push(data.to_vec());
+                pc += len;
+            }
+            OP_1NEGATE => stack.push(encode_num(-1)),
+            OP_1..=OP_16 => stack.push(encode_num((opcode - OP_1 + 1) as i64)),
+            OP_VERIFY => {
+                if !cast_to_bool(&pop(&mut stack)?) {
+                    return Err("OP_VERIFY failed".to_string());
+                }
+            }
+            OP_DROP => {
+                pop(&mut stack)?;
+            }
+            OP_DUP => {
+                let top = stack.last().cloned().ok_or_else(|| "stack underflow".to_string())?;
+                stack.push(top);
+            }
+            OP_EQUAL | OP_EQUALVERIFY => {
+                let (b, a) = (pop(&mut stack)?, pop(&mut stack)?);
+                if opcode == OP_EQUALVERIFY {
+                    if a != b {
+                        return Err("OP_EQUALVERIFY failed".to_string());
+                    }
+                } else {
+                    stack.push(encode_bool(a == b));
+                }
+            }
+            OP_NUMEQUAL | OP_NUMEQUALVERIFY | OP_GREATERTHANOREQUAL => {
+                let (b, a) = (decode_num(&pop(&mut stack)?)?, decode_num(&pop(&mut stack)?)?);
+                let result = if opcode == OP_GREATERTHANOREQUAL { a >= b } else { a == b };
+                if opcode == OP_NUMEQUALVERIFY {
+                    if !result {
+                        return Err("OP_NUMEQUALVERIFY failed".to_string());
+                    }
+                } else {
+                    stack.push(encode_bool(result));
+                }
+            }
+            OP_SHA256 => {
+                let data = pop(&mut stack)?;
+                stack.push(Sha256::digest(data).to_vec());
+            }
+            OP_CHECKSIG | OP_CHECKSIGVERIFY | OP_CHECKSIGADD => {
+                let pubkey = pop(&mut stack)?;
+                let n = if opcode == OP_CHECKSIGADD {
+                    Some(decode_num(&pop(&mut stack)?)?)
+                } else {
+                    None
+                };
+                let signature = pop(&mut stack)?;
+                let valid = check_signature(&secp, &signature, &pubkey, &message)?;
+                match (opcode, n) {
+                    (OP_CHECKSIGADD, Some(n)) => stack.push(encode_num(n + valid as i64)),
+                    (OP_CHECKSIGVERIFY, _) if !valid => return Err("OP_CHECKSIGVERIFY failed".to_string()),
+                    (OP_CHECKSIGVERIFY, _) => {}
+                    _ => stack.push(encode_bool(valid)),
+                }
+            }
+            _ => return Err(format!("unsupported opcode {:#04x}", opcode)),
+        }
+        if stack.len() > MAX_STACK_SIZE {
+            return Err("stack size limit exceeded".to_string());
+        }
+    }
+
+    match stack.as_slice() {
+        [top] if cast_to_bool(top) => Ok(()),
+        [_] => Err("script evaluated to false".to_string()),
+        _ => Err(format!("script left {} stack elements", stack.len())),
+    }
+}
+
+/// BIP-342 signature check: an empty signature is false, an invalid one
+/// fails the script
+fn check_signature(
+    secp: &Secp256k1<secp256k1::VerifyOnly>,
+    signature: &[u8],
+    pubkey: &[u8],
+    message: &Message,
+) -> Result<bool, String> {
+    if signature.is_empty() {
+        return Ok(false);
+    }
+    let pubkey = XOnlyPublicKey::from_slice(pubkey).map_err(|_| "invalid public key".to_string())?;
+    let signature = Signature::from_slice(signature).map_err(|_| "invalid signature encoding".to_string())?;
+    secp.verify_schnorr(&signature, message, &pubkey)
+        .map(|_| true)
+        .map_err(|_| "signature check failed".to_string())
+}
+
+fn pop(stack: &mut Vec<Vec<u8>>) -> Result<Vec<u8>, String> {
+    stack.pop().ok_or_else(|| "stack underflow".to_string())
+}
+
+fn read_le(script: &[u8], pc: usize, width: usize) -> Result<usize, String> {
+    let bytes = script
+        .get(pc..pc + width)
+        .ok_or_else(|| "push length past end of script".to_string())?;
+    Ok(bytes.iter().rev().fold(0usize, |value, byte| (value << 8) | *byte as usize))
+}
+
+fn cast_to_bool(value: &[u8]) -> bool {
+    match value.split_last() {
+        None => false,
+        Some((last, rest)) => rest.iter().any(|b| *b != 0) || (*last != 0 && *last != 0x80),
+    }
+}
+
+fn encode_bool(value: bool) -> Vec<u8> {
+    if value {
+        vec![1]
+    } else {
+        Vec::new()
+    }
+}
+
+/// Minimal little-endian sign-magnitude script number
+fn encode_num(value: i64) -> Vec<u8> {
+    let mut magnitude = value.unsigned_abs();
+    let mut bytes = Vec::new();
+    while magnitude > 0 {
+        bytes.push((magnitude & 0xff) as u8);
+        magnitude >>= 8;
+    }
+    if let Some(last) = bytes.last_mut() {
+        if *last & 0x80 != 0 {
+            bytes.push(if value < 0 { 0x80 } else { 0 });
+        } else if value < 0 {
+            *last |= 0x80;
+        }
+    }
+    bytes
+}
+
+/// Script number of at most 4 bytes
+fn decode_num(bytes: &[u8]) -> Result<i64, String> {
+    if bytes.len() > 4 {
+        return Err("script number overflow".to_string());
+    }
+    let Some((last, _)) = bytes.split_last() else {
+        return Ok(0);
+    };
+    let magnitude = bytes
+        .iter()
+        .enumerate()
+        .fold(0i64, |value, (i, byte)| {
+            let byte = if i == bytes.len() - 1 { byte & 0x7f } else { *byte };
+            value | (byte as i64) << (8 * i)
+        });
+    Ok(if last & 0x80 != 0 { -magnitude } else { magnitude })
+}
+
+fn write_compact_size(out: &mut Vec<u8>, value: u64) {
+    match value {
+        0..=0xfc => out.push(value as u8),
+        0xfd..=0xffff => {
+            out.push(0xfd);
+            out.extend_from_slice(&(value as u16).to_le_bytes());
+        }
+        0x10000..=0xffff_ffff => {
+            out.push(0xfe);
+            out.extend_from_slice(&(value as u32).to_le_bytes());
+        }
+        _ => {
+            out.push(0xff);
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use secp256k1::Keypair;
+
+    fn keypair(byte: u8) -> Keypair {
+        Keypair::from_seckey_slice(&Secp256k1::new(), &[byte; 32]).unwrap()
+    }
+
+    fn checksig_script(keypair: &Keypair) -> Vec<u8> {
+        let mut script = vec![32];
+        script.extend_from_slice(&keypair.x_only_public_key().0.serialize());
+        script.push(OP_CHECKSIG);
+        script
+    }
+
+    fn sign(keypair: &Keypair, message: &[u8; 32]) -> Vec<u8> {
+        Secp256k1::new()
+            .sign_schnorr_no_aux_rand(&Message::from_digest(*message), keypair)
+            .as_ref()
+            .to_vec()
+    }
+
+    #[test]
+    fn test_script_numbers_round_trip() {
+        for value in [0, 1, -1, 16, 127, 128, -128, 255, 256, 0x7fff_ffff, -0x7fff_ffff] {
+            assert_eq!(decode_num(&encode_num(value)).unwrap(), value);
+        }
+        assert_eq!(encode_num(0), Vec::<u8>::new());
+        assert_eq!(encode_num(128), vec![0x80, 0x00]);
+        assert_eq!(encode_num(-1), vec![0x81]);
+    }
+
+    #[test]
+    fn test_checksig_script() {
+        let message = [3u8; 32];
+        let signer = keypair(1);
+        let script = checksig_script(&signer);
+
+        assert!(execute_tapscript(&script, &[sign(&signer, &message)], &message).is_ok());
+        assert!(execute_tapscript(&script, &[Vec::new()], &message).is_err());
+        assert!(execute_tapscript(&script, &[sign(&keypair(2), &message)], &message).is_err());
+        assert!(execute_tapscript(&script, &[sign(&signer, &[4u8; 32])], &message).is_err());
+    }
+
+    #[test]
+    fn test_two_of_three_multisig_script() {
+        let message = [3u8; 32];
+        let signers = [keypair(1), keypair(2), keypair(3)];
+        let mut script = Vec::new();
+        for (i, signer) in signers.iter().enumerate() {
+            script.push(32);
+            script.extend_from_slice(&signer.x_only_public_key().0.serialize());
+            script.push(if i == 0 { OP_CHECKSIG } else { OP_CHECKSIGADD });
+        }
+        script.extend_from_slice(&[OP_1 + 1, OP_NUMEQUAL]);
+
+        // Witness items are consumed last-key-first
+        let witness = vec![sign(&signers[2], &message), Vec::new(), sign(&signers[0], &message)];
+        assert!(execute_tapscript(&script, &witness, &message).is_ok());
+
+        let witness = vec![Vec::new(), Vec::new(), sign(&signers[0], &message)];
+        assert_eq!(
+            execute_tapscript(&script, &witness, &message),
+            Err("script evaluated to false".to_string())
+        );
+    }
+
+    #[test]
+    fn test_hash_lock_and_unsupported_opcodes() {
+        let preimage = b"mesh access".to_vec();
+        let mut script = vec![OP_SHA256, 32];
+        script.extend_from_slice(&Sha256::digest(&preimage));
+        script.push(OP_EQUAL);
+        assert!(execute_tapscript(&script, &[preimage], &[0u8; 32]).is_ok());
+        assert!(execute_tapscript(&script, &[b"guess".to_vec()], &[0u8; 32]).is_err());
+
+        // OP_RETURN is not in the supported subset
+        assert_eq!(
+            execute_tapscript(&[0x6a], &[], &[0u8; 32]),
+            Err("unsupported opcode 0x6a".to_string())
+        );
+    }
+
+    #[test]
+    fn test_control_block_commitment() {
+        let internal = keypair(9).x_only_public_key().0;
+        let script = checksig_script(&keypair(1));
+        let sibling = [0x55u8; 32];
+        let merkle_root = tapbranch_hash(&tapleaf_hash(TAPSCRIPT_LEAF_VERSION, &script), &sibling);
+        let (output_key, parity) = taproot_output_key(&internal, &merkle_root).unwrap();
+
+        let mut control_block = vec![TAPSCRIPT_LEAF_VERSION | parity.to_u8()];
+        control_block.extend_from_slice(&internal.serialize());
+        control_block.extend_from_slice(&sibling);
+        assert_eq!(commitment_output_key(&control_block, &script), Ok(output_key));
+
+        // Another script, the wrong parity or a truncated path commit elsewhere
+        assert_ne!(commitment_output_key(&control_block, &[OP_1]), Ok(output_key));
+        control_block[0] ^= 1;
+        assert!(commitment_output_key(&control_block, &script).is_err());
+        control_block[0] ^= 1;
+        assert!(commitment_output_key(&control_block[..40], &script).is_err());
+    }
+}
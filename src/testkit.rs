@@ -0,0 +1,597 @@
+//! Deterministic multi-node test harness (feature `testkit`)
+//!
+//! `MeshCluster` stands up N `MeshManager`s in one process, each with its own
+//! in-memory NodeAPI and storage. Mesh packets sent to a peer are queued on a
+//! shared in-memory network and delivered into the target manager's
+//! `handle_peer_frame` when the test calls `run_until_idle`, in FIFO
+//! order, so scenarios replay identically on every run.
+//!
+//! ```ignore
+//! let cluster = MeshCluster::line(5, &[("mesh.mode", "open")]).await?;
+//! cluster.send(0, 4, b"hello".to_vec()).await?;
+//! cluster.run_until_idle().await;
+//! assert_eq!(cluster.node(4).poll_delivered(10).len(), 1);
+//! ```
+
+use crate::clock::{Clock, ManualClock};
+use crate::config::CONFIG_TREE;
+use crate::error::MeshError;
+use crate::manager::{MeshManager, MeshStats};
+use crate::packet::{MeshPacketBuilder, PacketType};
+use crate::routing::{NodeId, RoutingEntry};
+use bllvm_node::module::traits::{EventPayload, EventType, ModuleContext, ModuleError, NodeAPI};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tracing::{debug, warn};
+
+/// Upper bound on deliveries per `run_until_idle` (guards against routing loops)
+const MAX_DELIVERIES_PER_RUN: usize = 100_000;
+
+/// Route cost per relay used for installed line routes
+const LINE_ROUTE_COST_PER_HOP: u64 = 100;
+
+/// Deterministic packet loss for the in-memory network
+#[derive(Debug, Default)]
+pub struct FaultInjector {
+    /// Drop one in every N packets (None = never drop)
+    drop_one_in: Option<u64>,
+    /// Packets seen so far
+    counter: AtomicU64,
+}
+
+impl FaultInjector {
+    /// Drop every `n`th packet (n = 1 drops everything)
+    pub fn drop_one_in(n: u64) -> Self {
+        Self {
+            drop_one_in: Some(n.max(1)),
+            counter: AtomicU64::new(0),
+        }
+    }
+
+    /// Decide whether the next packet is dropped
+    fn should_drop(&self) -> bool {
+        let Some(n) = self.drop_one_in else {
+            return false;
+        };
+        let seen = self.counter.fetch_add(1, Ordering::SeqCst) + 1;
+        seen % n == 0
+    }
+}
+
+/// A packet in flight on the in-memory network
+struct InFlight {
+    from: String,
+    to: String,
+    data: Vec<u8>,
+}
+
+/// Shared in-memory network connecting cluster nodes
+#[derive(Default)]
+pub struct ClusterNetwork {
+    /// Packets waiting to be delivered, oldest first
+    queue: Mutex<VecDeque<InFlight>>,
+    /// Connected links (unordered address pairs stored both ways)
+    links: Mutex<HashSet<(String, String)>>,
+    /// Optional packet loss
+    fault_injector: Mutex<Option<FaultInjector>>,
+    /// Packets dropped (no link or injected fault)
+    dropped: AtomicU64,
+}
+
+impl ClusterNetwork {
+    fn is_linked(&self, from: &str, to: &str) -> bool {
+        self.links
+            .lock()
+            .unwrap()
+            .contains(&(from.to_string(), to.to_string()))
+    }
+
+    fn enqueue(&self, from: &str, to: String, data: Vec<u8>) {
+        let faulted = self
+            .fault_injector
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|injector| injector.should_drop())
+            .unwrap_or(false);
+        if faulted || !self.is_linked(from, &to) {
+            debug!("Dropping in-flight packet: from={}, to={}, faulted={}", from, to, faulted);
+            self.dropped.fetch_add(1, Ordering::SeqCst);
+            return;
+        }
+        self.queue.lock().unwrap().push_back(InFlight {
+            from: from.to_string(),
+            to,
+            data,
+        });
+    }
+
+    fn pop(&self) -> Option<InFlight> {
+        self.queue.lock().unwrap().pop_front()
+    }
+}
+
+/// NodeAPI for one cluster node: in-memory storage plus the shared network
+pub struct ClusterNodeApi {
+    /// This node's peer address
+    addr: String,
+    /// Shared network
+    network: Arc<ClusterNetwork>,
+    /// Storage trees (tree name -> ordered key/value map)
+    storage: Mutex<HashMap<String, BTreeMap<Vec<u8>, Vec<u8>>>>,
+    /// Published events
+    published: Mutex<Vec<(EventType, EventPayload)>>,
+    /// Mesh packets sent by this node
+    sent: AtomicU64,
+}
+
+impl ClusterNodeApi {
+    fn new(addr: String, network: Arc<ClusterNetwork>) -> Self {
+        Self {
+            addr,
+            network,
+            storage: Mutex::new(HashMap::new()),
+            published: Mutex::new(Vec::new()),
+            sent: AtomicU64::new(0),
+        }
+    }
+
+    /// Number of mesh packets this node has sent
+    pub fn sent_count(&self) -> u64 {
+        self.sent.load(Ordering::SeqCst)
+    }
+
+    /// Events published by this node
+    pub fn published(&self) -> Vec<(EventType, EventPayload)> {
+        self.published.lock().unwrap().clone()
+    }
+
+    /// Connected peer addresses
+    fn peer_addrs(&self) -> Vec<String> {
+        let mut peers: Vec<String> = self
+            .network
+            .links
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(from, _)| *from == self.addr)
+            .map(|(_, to)| to.clone())
+            .collect();
+        peers.sort();
+        peers
+    }
+}
+
+/// One node of a cluster
+pub struct ClusterNode {
+    /// Peer address other nodes use to reach this node
+    pub addr: String,
+    /// Mesh node ID
+    pub node_id: NodeId,
+    /// This node's NodeAPI
+    pub node_api: Arc<ClusterNodeApi>,
+    /// This node's mesh manager
+    pub manager: Arc<MeshManager>,
+}
+
+/// N in-process mesh nodes wired through an in-memory network
+pub struct MeshCluster {
+    nodes: Vec<ClusterNode>,
+    network: Arc<ClusterNetwork>,
+    clock: Arc<ManualClock>,
+    /// Delivery failures (receiving node index, error)
+    delivery_errors: Mutex<Vec<(usize, String)>>,
+}
+
+impl MeshCluster {
+    /// Create `n` unconnected nodes sharing the given config
+    ///
+    /// `mesh.enabled` defaults to `true`; other keys use the module defaults.
+    /// Every node knows every other node's signing key up front.
+    pub async fn new(n: usize, config: &[(&str, &str)]) -> Result<Self, MeshError> {
+        Self::create(n, config, true).await
+    }
+
+    /// Create `n` unconnected nodes that know none of each other's signing keys
+    ///
+    /// Keys are only learned the way a real mesh learns them: from Hellos
+    /// of direct peers and from link-state advertisements.
+    pub async fn without_key_exchange(n: usize, config: &[(&str, &str)]) -> Result<Self, MeshError> {
+        Self::create(n, config, false).await
+    }
+
+    async fn create(n: usize, config: &[(&str, &str)], exchange_keys: bool) -> Result<Self, MeshError> {
+        let network = Arc::new(ClusterNetwork::default());
+        let clock = Arc::new(ManualClock::starting_now());
+
+        let mut settings: HashMap<String, String> = HashMap::new();
+        settings.insert("mesh.enabled".to_string(), "true".to_string());
+        for (key, value) in config {
+            settings.insert(key.to_string(), value.to_string());
+        }
+
+        let mut nodes = Vec::with_capacity(n);
+        for index in 0..n {
+            let addr = Self::node_addr(index);
+            let node_api = Arc::new(ClusterNodeApi::new(addr.clone(), Arc::clone(&network)));
+
+            // Pin the signing key, and with it the node ID, so runs replay identically
+            let signing_secret: [u8; 32] = Sha256::digest(addr.as_bytes()).into();
+            node_api
+                .storage
+                .lock()
+                .unwrap()
+                .entry(CONFIG_TREE.to_string())
+                .or_default()
+                .insert(b"signing_secret".to_vec(), signing_secret.to_vec());
+
+            let ctx = ModuleContext {
+                module_id: format!("bllvm-mesh-{}", index),
+                config: settings.clone(),
+                data_dir: PathBuf::from(format!("target/test-data/cluster/{}", index)),
+                socket_path: format!("target/test-data/cluster/{}.sock", index),
+            };
+            let manager = MeshManager::with_clock(
+                &ctx,
+                Arc::clone(&node_api) as Arc<dyn NodeAPI>,
+                Arc::clone(&clock) as Arc<dyn Clock>,
+            )
+            .await?;
+
+            nodes.push(ClusterNode {
+                addr,
+                node_id: manager.node_id(),
+                node_api,
+                manager: Arc::new(manager),
+            });
+        }
+
+        // Stand in for key exchange: every node knows every other node's signing key
+        if exchange_keys {
+            for node in &nodes {
+                for peer in nodes.iter().filter(|peer| peer.node_id != node.node_id) {
+                    node.manager
+                        .register_peer_key(peer.node_id, &peer.manager.signing_public_key())?;
+                }
+            }
+        }
+
+        Ok(Self {
+            nodes,
+            network,
+            clock,
+            delivery_errors: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// Create `n` nodes connected in a line (0 - 1 - ... - n-1) with
+    /// multi-hop routes installed along the line on every node
+    pub async fn line(n: usize, config: &[(&str, &str)]) -> Result<Self, MeshError> {
+        let cluster = Self::new(n, config).await?;
+        for index in 1..n {
+            cluster.connect(index - 1, index);
+        }
+        cluster.install_line_routes();
+        Ok(cluster)
+    }
+
+    /// Peer address of node `index`
+    pub fn node_addr(index: usize) -> String {
+        format!("cluster-node-{}:8334", index)
+    }
+
+    /// Number of nodes
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Whether the cluster has no nodes
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Mesh manager of node `index`
+    pub fn node(&self, index: usize) -> &MeshManager {
+        &self.nodes[index].manager
+    }
+
+    /// Full handle for node `index`
+    pub fn cluster_node(&self, index: usize) -> &ClusterNode {
+        &self.nodes[index]
+    }
+
+    /// Node ID of node `index`
+    pub fn node_id(&self, index: usize) -> NodeId {
+        self.nodes[index].node_id
+    }
+
+    /// Connect two nodes as direct peers under their node IDs, without a handshake
+    pub fn connect(&self, a: usize, b: usize) {
+        let (addr_a, addr_b) = (self.nodes[a].addr.clone(), self.nodes[b].addr.clone());
+        {
+            let mut links = self.network.links.lock().unwrap();
+            links.insert((addr_a.clone(), addr_b.clone()));
+            links.insert((addr_b.clone(), addr_a.clone()));
+        }
+        self.nodes[a].manager.add_direct_peer(self.nodes[b].node_id, &addr_b, "memory");
+        self.nodes[b].manager.add_direct_peer(self.nodes[a].node_id, &addr_a, "memory");
+    }
+
+    /// Connect two nodes through the peer handshake on both sides
+    ///
+    /// Runs the Noise handshake with `mesh.noise_handshake`, otherwise the
+    /// signed Hello exchange; the peers become direct peers once
+    /// `run_until_idle` has delivered it.
+    pub async fn connect_secure(&self, a: usize, b: usize) -> Result<(), MeshError> {
+        let (addr_a, addr_b) = (self.nodes[a].addr.clone(), self.nodes[b].addr.clone());
+        {
+            let mut links = self.network.links.lock().unwrap();
+            links.insert((addr_a.clone(), addr_b.clone()));
+            links.insert((addr_b.clone(), addr_a.clone()));
+        }
+        self.nodes[a].manager.connect_peer(&addr_b, "memory").await?;
+        self.nodes[b].manager.connect_peer(&addr_a, "memory").await
+    }
+
+    /// Disconnect two nodes (both sides see PeerDisconnected)
+    ///
+    /// Packets already in flight on the link are dropped on delivery.
+    pub fn disconnect(&self, a: usize, b: usize) {
+        let (addr_a, addr_b) = (self.nodes[a].addr.clone(), self.nodes[b].addr.clone());
+        {
+            let mut links = self.network.links.lock().unwrap();
+            links.remove(&(addr_a.clone(), addr_b.clone()));
+            links.remove(&(addr_b.clone(), addr_a.clone()));
+        }
+        self.nodes[a].manager.handle_peer_disconnected(&addr_b);
+        self.nodes[b].manager.handle_peer_disconnected(&addr_a);
+    }
+
+    /// Silently cut the link between two nodes (no PeerDisconnected)
+    ///
+    /// Models a peer that dies without the node noticing (NAT timeout, crash).
+    pub fn sever(&self, a: usize, b: usize) {
+        let (addr_a, addr_b) = (self.nodes[a].addr.clone(), self.nodes[b].addr.clone());
+        let mut links = self.network.links.lock().unwrap();
+        links.remove(&(addr_a.clone(), addr_b.clone()));
+        links.remove(&(addr_b, addr_a));
+    }
+
+    /// Install routes along a line topology on every node
+    pub fn install_line_routes(&self) {
+        let now = self.clock.now_secs();
+        let ids: Vec<NodeId> = self.nodes.iter().map(|node| node.node_id).collect();
+        for (from, node) in self.nodes.iter().enumerate() {
+            for to in 0..ids.len() {
+                if from.abs_diff(to) < 2 {
+                    continue;
+                }
+                let route_path: Vec<NodeId> = if from < to {
+                    ids[from..=to].to_vec()
+                } else {
+                    ids[to..=from].iter().rev().copied().collect()
+                };
+                node.manager.routing_table().add_route(RoutingEntry {
+                    node_id: ids[to],
+                    direct_address: None,
+                    next_hop: Some(route_path[1]),
+                    route_cost: (route_path.len() as u64 - 2) * LINE_ROUTE_COST_PER_HOP,
+                    route_path,
+                    last_updated: now,
+                    quality_score: 0.8,
+                });
+            }
+        }
+    }
+
+    /// Route a free Bitcoin-typed packet from one node to another
+    pub async fn send(&self, from: usize, to: usize, payload: Vec<u8>) -> Result<(), MeshError> {
+        let packet = MeshPacketBuilder::new(self.node_id(from), self.node_id(to))
+            .packet_type(PacketType::BitcoinP2P)
+            .payload(payload)
+            .build()?;
+        self.node(from).route_packet(&packet).await
+    }
+
+    /// Install a fault injector on the network
+    pub fn set_fault_injector(&self, injector: Option<FaultInjector>) {
+        *self.network.fault_injector.lock().unwrap() = injector;
+    }
+
+    /// Deliver queued packets until the network is idle
+    ///
+    /// Returns the number of packets delivered. Delivery errors are recorded
+    /// (see `delivery_errors`) rather than aborting the run.
+    pub async fn run_until_idle(&self) -> usize {
+        let mut delivered = 0;
+        while let Some(in_flight) = self.network.pop() {
+            if delivered >= MAX_DELIVERIES_PER_RUN {
+                warn!("Cluster delivery limit reached, possible routing loop");
+                break;
+            }
+            let Some(index) = self.nodes.iter().position(|node| node.addr == in_flight.to) else {
+                continue;
+            };
+            if !self.network.is_linked(&in_flight.from, &in_flight.to) {
+                self.network.dropped.fetch_add(1, Ordering::SeqCst);
+                continue;
+            }
+
+            delivered += 1;
+            let result = self.nodes[index]
+                .manager
+                .handle_peer_frame(&in_flight.from, &in_flight.data)
+                .await;
+            if let Err(e) = result {
+                debug!("Cluster delivery failed: node={}, error={}", index, e);
+                self.delivery_errors.lock().unwrap().push((index, e.to_string()));
+            }
+        }
+        delivered
+    }
+
+    /// Take recorded delivery errors (receiving node index, error)
+    pub fn delivery_errors(&self) -> Vec<(usize, String)> {
+        std::mem::take(&mut *self.delivery_errors.lock().unwrap())
+    }
+
+    /// Packets dropped by the network so far
+    pub fn dropped_packets(&self) -> u64 {
+        self.network.dropped.load(Ordering::SeqCst)
+    }
+
+    /// Advance the shared clock
+    pub fn advance(&self, secs: u64) {
+        self.clock.advance(secs);
+    }
+
+    /// Current time on the shared clock
+    pub fn now(&self) -> u64 {
+        self.clock.now_secs()
+    }
+
+    /// Snapshot every node's stats
+    pub async fn stats(&self) -> Vec<MeshStats> {
+        let mut stats = Vec::with_capacity(self.nodes.len());
+        for node in &self.nodes {
+            stats.push(node.manager.get_stats().await);
+        }
+        stats
+    }
+
+    /// Snapshot a node's routing table
+    pub fn routing_snapshot(&self, index: usize) -> Vec<RoutingEntry> {
+        self.nodes[index].manager.routing_table().entries()
+    }
+}
+
+#[async_trait::async_trait]
+impl NodeAPI for ClusterNodeApi {
+    async fn get_block(&self, _: &bllvm_protocol::Hash) -> Result<Option<bllvm_protocol::Block>, ModuleError> { Ok(None) }
+    async fn get_block_header(&self, _: &bllvm_protocol::Hash) -> Result<Option<bllvm_protocol::BlockHeader>, ModuleError> { Ok(None) }
+    async fn get_transaction(&self, _: &bllvm_protocol::Hash) -> Result<Option<bllvm_protocol::Transaction>, ModuleError> { Ok(None) }
+    async fn has_transaction(&self, _: &bllvm_protocol::Hash) -> Result<bool, ModuleError> { Ok(false) }
+    async fn get_chain_tip(&self) -> Result<bllvm_protocol::Hash, ModuleError> { Ok([0u8; 32]) }
+    async fn get_block_height(&self) -> Result<u64, ModuleError> { Ok(100) }
+    async fn get_utxo(&self, _: &bllvm_protocol::OutPoint) -> Result<Option<bllvm_protocol::UTXO>, ModuleError> { Ok(None) }
+    async fn subscribe_events(&self, _: Vec<EventType>) -> Result<tokio::sync::mpsc::Receiver<bllvm_node::module::ipc::protocol::ModuleMessage>, ModuleError> {
+        let (_tx, rx) = tokio::sync::mpsc::channel(100);
+        Ok(rx)
+    }
+    async fn get_mempool_transactions(&self) -> Result<Vec<bllvm_protocol::Hash>, ModuleError> { Ok(Vec::new()) }
+    async fn get_mempool_transaction(&self, _: &bllvm_protocol::Hash) -> Result<Option<bllvm_protocol::Transaction>, ModuleError> { Ok(None) }
+    async fn get_mempool_size(&self) -> Result<bllvm_node::module::traits::MempoolSize, ModuleError> {
+        Ok(bllvm_node::module::traits::MempoolSize { count: 0, size_bytes: 0 })
+    }
+    async fn get_network_stats(&self) -> Result<bllvm_node::module::traits::NetworkStats, ModuleError> {
+        Ok(bllvm_node::module::traits::NetworkStats { connected_peers: self.peer_addrs().len(), bytes_sent: 0, bytes_received: 0 })
+    }
+    async fn get_network_peers(&self) -> Result<Vec<bllvm_node::module::traits::PeerInfo>, ModuleError> {
+        Ok(self
+            .peer_addrs()
+            .into_iter()
+            .map(|addr| bllvm_node::module::traits::PeerInfo {
+                addr,
+                transport_type: "memory".to_string(),
+                services: 0,
+                version: 70016,
+                connected_since: 0,
+            })
+            .collect())
+    }
+    async fn get_chain_info(&self) -> Result<bllvm_node::module::traits::ChainInfo, ModuleError> {
+        Ok(bllvm_node::module::traits::ChainInfo { tip: [0u8; 32], height: 100, difficulty: 1.0 })
+    }
+    async fn get_block_by_height(&self, _: u64) -> Result<Option<bllvm_protocol::Block>, ModuleError> { Ok(None) }
+    async fn get_lightning_node_url(&self) -> Result<Option<String>, ModuleError> { Ok(None) }
+    async fn get_lightning_info(&self) -> Result<Option<bllvm_node::module::traits::LightningInfo>, ModuleError> { Ok(None) }
+    async fn get_payment_state(&self, _: &str) -> Result<Option<bllvm_node::module::traits::PaymentState>, ModuleError> { Ok(None) }
+    async fn check_transaction_in_mempool(&self, _: &bllvm_protocol::Hash) -> Result<bool, ModuleError> { Ok(false) }
+    async fn get_fee_estimate(&self, _: u32) -> Result<u64, ModuleError> { Ok(1) }
+    async fn read_file(&self, _: String) -> Result<Vec<u8>, ModuleError> { Ok(Vec::new()) }
+    async fn write_file(&self, _: String, _: Vec<u8>) -> Result<(), ModuleError> { Ok(()) }
+    async fn delete_file(&self, _: String) -> Result<(), ModuleError> { Ok(()) }
+    async fn list_directory(&self, _: String) -> Result<Vec<String>, ModuleError> { Ok(Vec::new()) }
+    async fn create_directory(&self, _: String) -> Result<(), ModuleError> { Ok(()) }
+    async fn get_file_metadata(&self, _: String) -> Result<bllvm_node::module::ipc::protocol::FileMetadata, ModuleError> {
+        Ok(bllvm_node::module::ipc::protocol::FileMetadata { size: 0, modified: 0, is_dir: false })
+    }
+    async fn storage_open_tree(&self, name: String) -> Result<String, ModuleError> {
+        self.storage.lock().unwrap().entry(name.clone()).or_default();
+        Ok(name)
+    }
+    async fn storage_insert(&self, tree_id: String, key: Vec<u8>, value: Vec<u8>) -> Result<(), ModuleError> {
+        self.storage.lock().unwrap().entry(tree_id).or_default().insert(key, value);
+        Ok(())
+    }
+    async fn storage_get(&self, tree_id: String, key: Vec<u8>) -> Result<Option<Vec<u8>>, ModuleError> {
+        Ok(self.storage.lock().unwrap().get(&tree_id).and_then(|tree| tree.get(&key).cloned()))
+    }
+    async fn storage_remove(&self, tree_id: String, key: Vec<u8>) -> Result<(), ModuleError> {
+        if let Some(tree) = self.storage.lock().unwrap().get_mut(&tree_id) {
+            tree.remove(&key);
+        }
+        Ok(())
+    }
+    async fn storage_contains_key(&self, tree_id: String, key: Vec<u8>) -> Result<bool, ModuleError> {
+        Ok(self.storage.lock().unwrap().get(&tree_id).map(|tree| tree.contains_key(&key)).unwrap_or(false))
+    }
+    async fn storage_iter(&self, tree_id: String) -> Result<Vec<(Vec<u8>, Vec<u8>)>, ModuleError> {
+        Ok(self
+            .storage
+            .lock()
+            .unwrap()
+            .get(&tree_id)
+            .map(|tree| tree.iter().map(|(k, v)| (k.clone(), v.clone())).collect())
+            .unwrap_or_default())
+    }
+    async fn storage_transaction(&self, tree_id: String, operations: Vec<bllvm_node::module::ipc::protocol::StorageOperation>) -> Result<(), ModuleError> {
+        use bllvm_node::module::ipc::protocol::StorageOperation;
+        let mut storage = self.storage.lock().unwrap();
+        let tree = storage.entry(tree_id).or_default();
+        for op in operations {
+            match op {
+                StorageOperation::Insert { key, value } => {
+                    tree.insert(key, value);
+                }
+                StorageOperation::Remove { key } => {
+                    tree.remove(&key);
+                }
+            }
+        }
+        Ok(())
+    }
+    async fn register_rpc_endpoint(&self, _: String, _: String) -> Result<(), ModuleError> { Ok(()) }
+    async fn unregister_rpc_endpoint(&self, _: &str) -> Result<(), ModuleError> { Ok(()) }
+    async fn register_timer(&self, _: u64, _: Arc<dyn bllvm_node::module::timers::manager::TimerCallback>) -> Result<bllvm_node::module::timers::manager::TimerId, ModuleError> { Ok(0) }
+    async fn cancel_timer(&self, _: bllvm_node::module::timers::manager::TimerId) -> Result<(), ModuleError> { Ok(()) }
+    async fn schedule_task(&self, _: u64, _: Arc<dyn bllvm_node::module::timers::manager::TaskCallback>) -> Result<bllvm_node::module::timers::manager::TaskId, ModuleError> { Ok(0) }
+    async fn report_metric(&self, _: bllvm_node::module::metrics::manager::Metric) -> Result<(), ModuleError> { Ok(()) }
+    async fn get_module_metrics(&self, _: &str) -> Result<Vec<bllvm_node::module::metrics::manager::Metric>, ModuleError> { Ok(Vec::new()) }
+    async fn initialize_module(&self, _: &str, _: bllvm_node::module::traits::ModuleManifest) -> Result<(), ModuleError> { Ok(()) }
+    async fn discover_modules(&self) -> Result<Vec<bllvm_node::module::traits::ModuleInfo>, ModuleError> { Ok(Vec::new()) }
+    async fn get_module_info(&self, _: &str) -> Result<Option<bllvm_node::module::traits::ModuleInfo>, ModuleError> { Ok(None) }
+    async fn is_module_available(&self, _: &str) -> Result<bool, ModuleError> { Ok(false) }
+    async fn publish_event(&self, event_type: EventType, payload: EventPayload) -> Result<(), ModuleError> {
+        self.published.lock().unwrap().push((event_type, payload));
+        Ok(())
+    }
+    async fn call_module(&self, _: Option<&str>, _: &str, _: Vec<u8>) -> Result<Vec<u8>, ModuleError> { Ok(Vec::new()) }
+    async fn register_module_api(&self, _: Vec<String>, _: u32) -> Result<(), ModuleError> { Ok(()) }
+    async fn unregister_module_api(&self) -> Result<(), ModuleError> { Ok(()) }
+    async fn get_module_health(&self, _: &str) -> Result<Option<bllvm_node::module::process::monitor::ModuleHealth>, ModuleError> { Ok(None) }
+    async fn get_all_module_health(&self) -> Result<Vec<(String, bllvm_node::module::process::monitor::ModuleHealth)>, ModuleError> { Ok(Vec::new()) }
+    async fn report_module_health(&self, _: bllvm_node::module::process::monitor::ModuleHealth) -> Result<(), ModuleError> { Ok(()) }
+    async fn send_mesh_packet_to_module(&self, _: &str, _: Vec<u8>, _: String) -> Result<(), ModuleError> { Ok(()) }
+    async fn send_mesh_packet_to_peer(&self, peer_addr: String, packet_data: Vec<u8>) -> Result<(), ModuleError> {
+        self.sent.fetch_add(1, Ordering::SeqCst);
+        self.network.enqueue(&self.addr, peer_addr, packet_data);
+        Ok(())
+    }
+    async fn send_stratum_v2_message_to_peer(&self, _: String, _: Vec<u8>) -> Result<(), ModuleError> { Ok(()) }
+    async fn get_node_public_key(&self) -> Result<Option<Vec<u8>>, ModuleError> { Ok(None) }
+    async fn get_event_publisher(&self) -> Result<Option<Arc<bllvm_node::node::event_publisher::EventPublisher>>, ModuleError> { Ok(None) }
+}
@@ -1,73 +1,787 @@
 //! Payment verification for mesh routing
 //!
-//! Verifies Lightning and CTV payment proofs for payment-gated mesh routing.
+//! Verifies Lightning, Taproot and CTV payment proofs for payment-gated mesh
+//! routing.
+//!
+//! Successful results are kept in an LRU cache keyed by `PaymentProof::hash()`,
+//! so a proof seen again (forwarding retries, fragments, several packets
+//! under one prepaid proof) is not re-parsed or checked with the node. A hit
+//! requires the identical proof and an unexpired result; failures are never
+//! cached, so a transient NodeAPI error does not stick.
+//!
+//! HTLC escrow proofs are checked by `HtlcVerifier` against the node's
+//! payment state on every call, since an HTLC can be failed back at any
+//! time. The source holds the preimage until the destination acks delivery,
+//! then reveals it to the relays, which keep it as their claim.
 
 use crate::error::MeshError;
-use crate::payment_proof::{PaymentProof, VerificationResult};
+use crate::payment_proof::{
+    label_spend_key, PaymentProof, SilentPaymentOutput, VerificationResult, DEFAULT_KEYSEND_MAX_AGE_SECONDS,
+    KEYSEND_PREIMAGE_TLV_TYPE,
+};
+use crate::proof_backend::{ProofBackend, VerifyContext, CTV_PROOF_KIND, LIGHTNING_PROOF_KIND};
+use crate::taproot::TaprootVerifier;
+use async_trait::async_trait;
+#[cfg(feature = "ctv")]
+use bllvm_node::module::traits::ModuleError;
 use bllvm_node::module::traits::NodeAPI;
-use std::sync::Arc;
+use dashmap::DashMap;
+use lru::LruCache;
+use secp256k1::XOnlyPublicKey;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
 use tracing::{debug, error, warn};
 
+/// Default number of cached verification results (`mesh.verifier.cache_size`)
+pub const DEFAULT_VERIFICATION_CACHE_SIZE: usize = 10_000;
+
+/// Default time an escrow HTLC must still be locked for when checked
+/// (`mesh.htlc.min_expiry_delta_seconds`), leaving room for delivery and
+/// the claim
+pub const DEFAULT_HTLC_MIN_EXPIRY_DELTA_SECONDS: u64 = 10 * 60;
+
+/// Default age past which a BOLT11 invoice is no longer accepted
+/// (`mesh.payment.max_invoice_age_seconds`)
+pub const DEFAULT_MAX_INVOICE_AGE_SECONDS: u64 = 24 * 60 * 60; // 24 hours
+
+/// Fewest distinct byte values a Lightning preimage must contain
+///
+/// A random preimage has about 26; fewer means it was picked by hand (all
+/// zeros, a repeated byte) and is likely known to others.
+const MIN_PREIMAGE_DISTINCT_BYTES: usize = 8;
+
+/// Tip difficulty below which the node's chain is taken to be regtest
+///
+/// `ChainInfo` does not name the network, so it is told from the tip
+/// difficulty: regtest's proof-of-work limit gives about 4.7e-10.
+const REGTEST_MAX_DIFFICULTY: f64 = 1e-6;
+
+/// Tip difficulty from which the node's chain is taken to be mainnet
+///
+/// Mainnet has been above it since 2019; test networks stay far below (and
+/// often report 1 for min-difficulty blocks). A mainnet node still syncing
+/// old blocks reads as testnet, and so rejects invoices until it catches up.
+const MAINNET_MIN_DIFFICULTY: f64 = 1e12;
+
+/// Invoice currency of the chain whose tip has `difficulty`
+fn chain_currency(difficulty: f64) -> lightning_invoice::Currency {
+    use lightning_invoice::Currency;
+    if difficulty < REGTEST_MAX_DIFFICULTY {
+        Currency::Regtest
+    } else if difficulty >= MAINNET_MIN_DIFFICULTY {
+        Currency::Bitcoin
+    } else {
+        Currency::BitcoinTestnet
+    }
+}
+
+/// How long a CTV transaction lookup that may still change is reused
+///
+/// Unseen, mempool and unknown-depth results are looked up again after
+/// this; confirmations with a known height are kept, as depth only grows.
+#[cfg(feature = "ctv")]
+const CTV_INCLUSION_CACHE_TTL_SECONDS: u64 = 30;
+
+/// Headers walked back from the tip beyond the required depth to find the
+/// block of a CTV Merkle proof
+#[cfg(feature = "ctv")]
+const CTV_HEADER_WALK_LIMIT: u64 = 144;
+
+/// Where CTV transactions must have been seen (`mesh.ctv.require_inclusion`)
+#[cfg(feature = "ctv")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CtvInclusion {
+    /// The covenant proof alone is enough
+    None,
+    /// In this node's mempool or confirmed
+    Mempool,
+    /// Confirmed with at least this many confirmations
+    Confirmed(u64),
+}
+
+#[cfg(feature = "ctv")]
+impl CtvInclusion {
+    /// Parse `none`, `mempool` or `confirmed:<depth>`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "none" => Some(Self::None),
+            "mempool" => Some(Self::Mempool),
+            _ => match name.strip_prefix("confirmed:")?.parse() {
+                Ok(depth) if depth > 0 => Some(Self::Confirmed(depth)),
+                _ => None,
+            },
+        }
+    }
+}
+
+/// What the node knows about a CTV transaction
+#[cfg(feature = "ctv")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TxInclusion {
+    /// Neither in the mempool nor in the chain
+    Unseen,
+    /// In the mempool
+    Mempool,
+    /// In the chain, at `height` if known (None once the output is spent)
+    Confirmed { height: Option<u64> },
+}
+
+/// Verification cache counters
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VerifierStats {
+    /// Verifications answered from the cache
+    pub cache_hits: u64,
+    /// Verifications of proofs not (or no longer) in the cache
+    pub cache_misses: u64,
+    /// Results currently cached
+    pub cached: usize,
+}
+
+/// Successful verification of one proof
+struct CachedVerification {
+    /// Digest of the whole proof, so a variant with the same payment hash
+    /// but another amount or timestamp is verified afresh
+    proof_digest: [u8; 32],
+    result: VerificationResult,
+}
+
+struct VerificationCache {
+    /// Results by `PaymentProof::hash()`
+    entries: LruCache<[u8; 32], CachedVerification>,
+    hits: u64,
+    misses: u64,
+}
+
 /// Payment verifier for mesh routing
+///
+/// Lightning, CTV and custom proofs are checked by the `ProofBackend`
+/// registered for their kind (see `proof_backend`); HTLC escrow and
+/// Taproot script proofs by their own verifiers.
 pub struct PaymentVerifier {
     /// Node API for querying payment state
     node_api: Arc<dyn NodeAPI>,
-    /// Whether Lightning verification is enabled
-    lightning_enabled: bool,
-    /// Whether CTV verification is enabled
+    /// How long keysend proofs are accepted after payment
+    keysend_max_age_seconds: u64,
+    /// Built-in Lightning backend (also registered in `backends`)
+    lightning: Arc<LightningBackend>,
+    /// Built-in CTV backend (also registered in `backends`)
     #[cfg(feature = "ctv")]
-    ctv_enabled: bool,
+    ctv: Arc<CtvBackend>,
+    /// Proof backends by kind
+    backends: HashMap<String, Arc<dyn ProofBackend>>,
+    /// Recent successful verifications
+    cache: Mutex<VerificationCache>,
+    /// HTLC escrow checks and claims
+    htlc: Arc<HtlcVerifier>,
+    /// Taproot script path checks
+    taproot: TaprootVerifier,
 }
 
 impl PaymentVerifier {
     /// Create a new payment verifier
     pub fn new(node_api: Arc<dyn NodeAPI>) -> Self {
+        let lightning = Arc::new(LightningBackend::new(Arc::clone(&node_api)));
+        #[cfg(feature = "ctv")]
+        let ctv = Arc::new(CtvBackend::new(Arc::clone(&node_api)));
+        let mut backends: HashMap<String, Arc<dyn ProofBackend>> = HashMap::new();
+        backends.insert(LIGHTNING_PROOF_KIND.to_string(), lightning.clone());
+        #[cfg(feature = "ctv")]
+        backends.insert(CTV_PROOF_KIND.to_string(), ctv.clone());
+
         Self {
-            node_api,
-            lightning_enabled: true, // Lightning is primary payment method
+            keysend_max_age_seconds: DEFAULT_KEYSEND_MAX_AGE_SECONDS,
+            lightning,
             #[cfg(feature = "ctv")]
-            ctv_enabled: true, // CTV enabled if feature flag is set
+            ctv,
+            backends,
+            cache: Mutex::new(VerificationCache {
+                entries: LruCache::new(NonZeroUsize::new(DEFAULT_VERIFICATION_CACHE_SIZE).unwrap()),
+                hits: 0,
+                misses: 0,
+            }),
+            htlc: Arc::new(HtlcVerifier::new(Arc::clone(&node_api))),
+            taproot: TaprootVerifier::new(Arc::clone(&node_api)),
+            node_api,
         }
     }
 
+    /// Cache up to `cache_size` verification results (at least 1)
+    pub fn with_cache_size(self, cache_size: usize) -> Self {
+        self.cache
+            .lock()
+            .unwrap()
+            .entries
+            .resize(NonZeroUsize::new(cache_size.max(1)).unwrap());
+        self
+    }
+
+    /// Verification cache counters
+    pub fn stats(&self) -> VerifierStats {
+        let cache = self.cache.lock().unwrap();
+        VerifierStats {
+            cache_hits: cache.hits,
+            cache_misses: cache.misses,
+            cached: cache.entries.len(),
+        }
+    }
+
+    /// Register a backend for `PaymentProof::Custom` proofs of its kind
+    ///
+    /// Replaces an earlier backend of the same kind. The built-in kinds
+    /// (`LIGHTNING_PROOF_KIND`, `CTV_PROOF_KIND`) cannot be replaced.
+    pub fn with_backend(mut self, backend: Arc<dyn ProofBackend>) -> Result<Self, MeshError> {
+        let kind = backend.kind().to_string();
+        if kind == LIGHTNING_PROOF_KIND || kind == CTV_PROOF_KIND {
+            return Err(MeshError::ConfigError(format!("Proof kind {} is built in", kind)));
+        }
+        self.backends.insert(kind, backend);
+        Ok(self)
+    }
+
+    /// Registered proof kinds, sorted
+    pub fn backend_kinds(&self) -> Vec<String> {
+        let mut kinds: Vec<String> = self.backends.keys().cloned().collect();
+        kinds.sort();
+        kinds
+    }
+
+    /// Change the built-in Lightning backend's settings
+    ///
+    /// Only callable while building, before the backend is shared.
+    fn configure_lightning(mut self, configure: impl FnOnce(&mut LightningBackend)) -> Self {
+        self.backends.remove(LIGHTNING_PROOF_KIND);
+        configure(Arc::get_mut(&mut self.lightning).expect("Lightning backend is not shared while building"));
+        self.backends
+            .insert(LIGHTNING_PROOF_KIND.to_string(), self.lightning.clone());
+        self
+    }
+
+    /// Set how long keysend proofs are accepted after payment
+    pub fn with_keysend_max_age(mut self, keysend_max_age_seconds: u64) -> Self {
+        self.keysend_max_age_seconds = keysend_max_age_seconds;
+        self.configure_lightning(|lightning| lightning.keysend_max_age_seconds = keysend_max_age_seconds)
+    }
+
+    /// Require keysend payments to have settled at this node
+    ///
+    /// On by default, and a keysend proof is refused while the Lightning
+    /// backend is unreachable. Off, proofs are checked offline (preimage and
+    /// amount only): anyone can mint such a proof for any amount, so only
+    /// turn it off where keysend proofs need not be worth anything.
+    pub fn with_keysend_settlement_check(self, enabled: bool) -> Self {
+        self.configure_lightning(|lightning| lightning.keysend_settlement_check = enabled)
+    }
+
+    /// Choose whose invoices Lightning proofs must pay
+    ///
+    /// On by default: the invoice payee must be this node's Lightning key.
+    /// Relays checking proofs that pay the destination turn it off; the payee
+    /// must then be the packet destination's key (its Lightning key and mesh
+    /// signing key are the same secp256k1 key).
+    pub fn with_require_local_payee(self, enabled: bool) -> Self {
+        self.configure_lightning(|lightning| lightning.require_local_payee = enabled)
+    }
+
+    /// Use `pubkey` (33 bytes) as this node's Lightning key instead of
+    /// asking the Lightning backend
+    pub fn with_local_payee(self, pubkey: Vec<u8>) -> Self {
+        *self.lightning.local_payee.lock().unwrap() = Some(pubkey);
+        self
+    }
+
+    /// Accept BOLT11 invoices for `max_invoice_age_seconds` after issue
+    pub fn with_max_invoice_age(self, max_invoice_age_seconds: u64) -> Self {
+        self.configure_lightning(|lightning| lightning.max_invoice_age_seconds = max_invoice_age_seconds)
+    }
+
+    /// Accept BOLT11 invoices that leave the amount to the payer
+    ///
+    /// Off by default: such an invoice proves nothing about the amount paid.
+    pub fn with_zero_amount_invoices(self, allowed: bool) -> Self {
+        self.configure_lightning(|lightning| lightning.allow_zero_amount_invoices = allowed)
+    }
+
+    /// Require BOLT11 invoices for `network` ("mainnet", "testnet" or
+    /// "regtest", as `mesh.bitcoin_network`)
+    ///
+    /// The node's chain (`get_chain_info`) must be the same network: while
+    /// it is not, or cannot be told, every BOLT11 proof fails.
+    pub fn with_network(self, network: &str) -> Result<Self, MeshError> {
+        use lightning_invoice::Currency;
+        let currency = match network {
+            "mainnet" => Currency::Bitcoin,
+            "testnet" => Currency::BitcoinTestnet,
+            "regtest" => Currency::Regtest,
+            _ => return Err(MeshError::ConfigError(format!("Unknown Bitcoin network: {}", network))),
+        };
+        Ok(self.configure_lightning(|lightning| lightning.invoice_currency = currency))
+    }
+
+    /// Require CTV transactions to be in the mempool or confirmed
+    #[cfg(feature = "ctv")]
+    pub fn with_ctv_inclusion(mut self, inclusion: CtvInclusion) -> Self {
+        self.backends.remove(CTV_PROOF_KIND);
+        Arc::get_mut(&mut self.ctv)
+            .expect("CTV backend is not shared while building")
+            .inclusion = inclusion;
+        self.backends.insert(CTV_PROOF_KIND.to_string(), self.ctv.clone());
+        self
+    }
+
+    /// Require escrow HTLCs to stay locked for `seconds` after they are checked
+    pub fn with_htlc_min_expiry_delta(mut self, seconds: u64) -> Self {
+        self.htlc = Arc::new(HtlcVerifier::new(Arc::clone(&self.node_api)).with_min_expiry_delta(seconds));
+        self
+    }
+
+    /// HTLC escrow verifier (held preimages and claims)
+    pub fn htlc(&self) -> &Arc<HtlcVerifier> {
+        &self.htlc
+    }
+
     /// Verify a payment proof
     ///
-    /// Verifies Lightning or CTV payment proofs for mesh routing.
-    /// Returns verification result with amount and validity.
+    /// Verifies Lightning, Taproot, CTV or custom payment proofs for mesh
+    /// routing. Returns verification result with amount and validity. A
+    /// proof verified before is answered from the cache until it expires;
+    /// escrow proofs never are.
     pub async fn verify(&self, proof: &PaymentProof) -> Result<VerificationResult, MeshError> {
+        self.verify_for_destination(proof, None).await
+    }
+
+    /// Verify a payment proof carried by a packet to `destination`
+    ///
+    /// `destination` is the destination's key learned via handshake, handed
+    /// to proof backends in `VerifyContext`; Lightning proofs need it when
+    /// they may pay the destination instead of this node. The payee is
+    /// checked before the cache is consulted, so a proof cached for one
+    /// destination does not pass for another.
+    pub async fn verify_for_destination(
+        &self,
+        proof: &PaymentProof,
+        destination: Option<&XOnlyPublicKey>,
+    ) -> Result<VerificationResult, MeshError> {
+        if let PaymentProof::HtlcEscrow { .. } = proof {
+            return self.htlc.verify(proof).await;
+        }
+        let key = proof.hash();
+
         // Check if proof is expired
-        if proof.is_expired() {
+        if proof.is_expired_with_keysend_max_age(self.keysend_max_age_seconds) {
+            self.cache.lock().unwrap().entries.pop(&key);
             return Ok(VerificationResult::failure(
                 "Payment proof expired".to_string(),
             ));
         }
 
+        let payee_failure = match proof {
+            PaymentProof::Lightning { invoice, .. } => self.lightning.check_payee(invoice, destination).await?,
+            PaymentProof::Keysend { destination_pubkey, .. } => {
+                self.lightning
+                    .check_keysend_destination(destination_pubkey, destination)
+                    .await?
+            }
+            _ => None,
+        };
+        if let Some(failure) = payee_failure {
+            return Ok(failure);
+        }
+
+        let proof_digest: [u8; 32] =
+            Sha256::digest(bincode::serialize(proof).expect("Payment proof should be serializable")).into();
+        if let Some(result) = self.cached(&key, &proof_digest) {
+            return Ok(result);
+        }
+
+        let result = self.verify_uncached(proof, destination).await?;
+        if result.verified {
+            self.cache.lock().unwrap().entries.put(
+                key,
+                CachedVerification {
+                    proof_digest,
+                    result: result.clone(),
+                },
+            );
+        }
+        Ok(result)
+    }
+
+    /// Cached result for a proof, dropping it once expired
+    fn cached(&self, key: &[u8; 32], proof_digest: &[u8; 32]) -> Option<VerificationResult> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let mut cache = self.cache.lock().unwrap();
+        let hit = cache.entries.get(key).and_then(|cached| {
+            let expired = cached.result.expires_at.is_some_and(|expires_at| expires_at < now);
+            (cached.proof_digest == *proof_digest).then(|| (cached.result.clone(), expired))
+        });
+        match hit {
+            Some((result, false)) => {
+                cache.hits += 1;
+                Some(result)
+            }
+            Some((_, true)) => {
+                cache.entries.pop(key);
+                cache.misses += 1;
+                None
+            }
+            None => {
+                cache.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Verify a payment proof without consulting the cache
+    async fn verify_uncached(
+        &self,
+        proof: &PaymentProof,
+        destination: Option<&XOnlyPublicKey>,
+    ) -> Result<VerificationResult, MeshError> {
+        let ctx = VerifyContext {
+            destination: destination.copied(),
+            amount_sats: proof.amount_sats(),
+            timestamp: proof.timestamp(),
+            expires_at: None,
+        };
         match proof {
-            PaymentProof::Lightning {
-                invoice,
-                preimage,
-                amount_msats,
-                timestamp,
-                expires_at,
-            } => {
-                self.verify_lightning(invoice, preimage, *amount_msats, *timestamp, *expires_at)
+            PaymentProof::Lightning { expires_at, .. } => {
+                let ctx = VerifyContext {
+                    expires_at: Some(*expires_at),
+                    ..ctx
+                };
+                self.verify_with_backend(LIGHTNING_PROOF_KIND, &encode_proof(proof), &ctx)
                     .await
             }
-            #[cfg(feature = "ctv")]
-            PaymentProof::InstantSettlement {
-                covenant_proof,
-                output_index,
-                amount_sats,
-                timestamp,
-                ..
+            PaymentProof::LightningBolt12 { .. } | PaymentProof::Keysend { .. } => {
+                self.verify_with_backend(LIGHTNING_PROOF_KIND, &encode_proof(proof), &ctx)
+                    .await
+            }
+            PaymentProof::HtlcEscrow { .. } => self.htlc.verify(proof).await,
+            PaymentProof::TaprootScript { .. } => self.taproot.verify(proof).await,
+            PaymentProof::Custom {
+                kind, blob, expires_at, ..
             } => {
-                self.verify_ctv(covenant_proof, *output_index, *amount_sats, *timestamp)
+                let ctx = VerifyContext {
+                    expires_at: Some(*expires_at),
+                    ..ctx
+                };
+                let mut result = self.verify_with_backend(kind, blob, &ctx).await?;
+                // Never cached past the expiry the proof claims
+                if result.verified {
+                    result.expires_at = Some(result.expires_at.map_or(*expires_at, |at| at.min(*expires_at)));
+                }
+                Ok(result)
+            }
+            #[cfg(feature = "ctv")]
+            PaymentProof::InstantSettlement { .. } => {
+                self.verify_with_backend(CTV_PROOF_KIND, &encode_proof(proof), &ctx)
                     .await
             }
         }
     }
 
+    /// Hand a proof to the backend registered for `kind`
+    ///
+    /// Fails the proof if there is none.
+    async fn verify_with_backend(
+        &self,
+        kind: &str,
+        proof_bytes: &[u8],
+        ctx: &VerifyContext,
+    ) -> Result<VerificationResult, MeshError> {
+        let Some(backend) = self.backends.get(kind) else {
+            warn!("No verification backend for proof kind {}", kind);
+            return Ok(VerificationResult::failure(format!(
+                "No verification backend for proof kind {}",
+                kind
+            )));
+        };
+        backend.verify(proof_bytes, ctx).await
+    }
+
+    /// Verify keysend (spontaneous) payment proof
+    ///
+    /// See `LightningBackend::verify_keysend`.
+    pub async fn verify_keysend(
+        &self,
+        payment_hash: &[u8; 32],
+        preimage: &[u8; 32],
+        amount_msats: u64,
+        timestamp: u64,
+        custom_tlv: &[(u64, Vec<u8>)],
+    ) -> Result<VerificationResult, MeshError> {
+        self.lightning
+            .verify_keysend(payment_hash, preimage, amount_msats, timestamp, custom_tlv)
+            .await
+    }
+
+    /// Verify an on-chain fee payment to this node's silent payment address
+    ///
+    /// Checks that the output was derived for the address of `scan_key` and
+    /// `spend_pubkey`, or for its labelled address when `label` is given
+    /// (see `ToSilentPaymentAddress`). Whether the transaction confirmed is
+    /// up to the caller.
+    pub fn verify_silent_payment(
+        &self,
+        output: &SilentPaymentOutput,
+        scan_key: &secp256k1::SecretKey,
+        spend_pubkey: &secp256k1::PublicKey,
+        label: Option<u32>,
+    ) -> Result<VerificationResult, MeshError> {
+        if output.amount_sats == 0 {
+            return Ok(VerificationResult::failure(
+                "Silent payment amount must be non-zero".to_string(),
+            ));
+        }
+
+        let spend_pubkey = match label {
+            Some(label) => label_spend_key(scan_key, spend_pubkey, label)?,
+            None => *spend_pubkey,
+        };
+        if !output.pays(scan_key, &spend_pubkey)? {
+            warn!("Silent payment output does not pay this node");
+            return Ok(VerificationResult::failure(
+                "Output does not pay this silent payment address".to_string(),
+            ));
+        }
+
+        debug!("Silent payment verified: amount={} sats", output.amount_sats);
+        Ok(VerificationResult::success(output.amount_sats, output.timestamp, None))
+    }
+
+    /// Check if minimum payment amount is met
+    pub fn check_minimum_payment(&self, amount_sats: u64, minimum_sats: u64) -> bool {
+        amount_sats >= minimum_sats
+    }
+
+    /// Calculate required payment amount for data size
+    pub fn calculate_payment_for_data(&self, data_size_bytes: usize, rate_sats_per_byte: u64) -> u64 {
+        (data_size_bytes as u64) * rate_sats_per_byte
+    }
+
+    /// Verify multiple payment proofs in parallel (batch operation)
+    ///
+    /// Processes multiple payment verifications concurrently for better performance.
+    /// Returns a vector of verification results in the same order as inputs.
+    pub async fn verify_batch(
+        &self,
+        proofs: &[&PaymentProof],
+    ) -> Result<Vec<VerificationResult>, MeshError> {
+        if proofs.is_empty() {
+            return Ok(Vec::new());
+        }
+        
+        // Verify all proofs in parallel
+        let futures: Vec<_> = proofs
+            .iter()
+            .map(|proof| self.verify(*proof))
+            .collect();
+        
+        // Wait for all verifications to complete
+        futures::future::join_all(futures)
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+    }
+}
+
+/// Built-in backend for BOLT11, BOLT12 and keysend proofs
+///
+/// Verifies bincode-encoded `PaymentProof`s (`LIGHTNING_PROOF_KIND`); it is
+/// configured through the `PaymentVerifier` builders.
+pub struct LightningBackend {
+    /// Node API for querying payment state
+    node_api: Arc<dyn NodeAPI>,
+    /// Whether Lightning verification is enabled
+    enabled: bool,
+    /// How long keysend proofs are accepted after payment
+    keysend_max_age_seconds: u64,
+    /// Whether keysend payments must be settled at this node's Lightning
+    /// backend
+    keysend_settlement_check: bool,
+    /// Whether Lightning invoices must pay this node (otherwise the
+    /// packet's destination)
+    require_local_payee: bool,
+    /// This node's Lightning public key (33 bytes), once known
+    local_payee: Mutex<Option<Vec<u8>>>,
+    /// How long after issue a BOLT11 invoice is accepted
+    max_invoice_age_seconds: u64,
+    /// Whether invoices without an amount are accepted
+    allow_zero_amount_invoices: bool,
+    /// Network BOLT11 invoices must be issued for
+    invoice_currency: lightning_invoice::Currency,
+    /// Whether the node's chain was seen to be `invoice_currency`'s
+    chain_checked: AtomicBool,
+}
+
+impl LightningBackend {
+    /// Create a Lightning backend with the default settings
+    pub fn new(node_api: Arc<dyn NodeAPI>) -> Self {
+        Self {
+            node_api,
+            enabled: true, // Lightning is primary payment method
+            keysend_max_age_seconds: DEFAULT_KEYSEND_MAX_AGE_SECONDS,
+            keysend_settlement_check: true,
+            require_local_payee: true,
+            local_payee: Mutex::new(None),
+            max_invoice_age_seconds: DEFAULT_MAX_INVOICE_AGE_SECONDS,
+            allow_zero_amount_invoices: false,
+            invoice_currency: lightning_invoice::Currency::Bitcoin,
+            chain_checked: AtomicBool::new(false),
+        }
+    }
+
+    /// Check that the node's chain is the configured network
+    ///
+    /// Returns the failure to report, or None if it is. A match is
+    /// remembered; a mismatch or an unreachable node is asked again next
+    /// time, so a syncing node is accepted once it reaches the tip.
+    async fn check_chain(&self) -> Option<VerificationResult> {
+        if self.chain_checked.load(Ordering::Relaxed) {
+            return None;
+        }
+        let info = match self.node_api.get_chain_info().await {
+            Ok(info) => info,
+            Err(e) => {
+                warn!("Cannot get chain info to check the invoice network: {}", e);
+                return Some(VerificationResult::failure(
+                    "Cannot check invoice network: the node's chain is unknown".to_string(),
+                ));
+            }
+        };
+        let currency = chain_currency(info.difficulty);
+        if currency != self.invoice_currency {
+            warn!(
+                "Node's chain looks like {:?} (difficulty {}), but invoices must be {:?}",
+                currency, info.difficulty, self.invoice_currency
+            );
+            return Some(VerificationResult::failure(
+                "Configured Bitcoin network does not match the node's chain".to_string(),
+            ));
+        }
+        self.chain_checked.store(true, Ordering::Relaxed);
+        None
+    }
+
+    /// Check that a BOLT11 invoice pays this node (or, when
+    /// `require_local_payee` is off, the packet destination)
+    ///
+    /// Returns the failure to report, or None if the payee matches. An
+    /// invoice that does not parse is left to `verify_lightning`.
+    async fn check_payee(
+        &self,
+        invoice: &str,
+        destination: Option<&XOnlyPublicKey>,
+    ) -> Result<Option<VerificationResult>, MeshError> {
+        use lightning_invoice::Invoice;
+        let Ok(parsed_invoice) = Invoice::from_str(invoice) else {
+            return Ok(None);
+        };
+        let payee = parsed_invoice
+            .payee_pub_key()
+            .copied()
+            .unwrap_or_else(|| parsed_invoice.recover_payee_pub_key())
+            .serialize();
+        self.check_payee_key(&payee, destination, "Invoice payee").await
+    }
+
+    /// Check that a keysend payment was sent to this node (or, when
+    /// `require_local_payee` is off, the packet destination)
+    async fn check_keysend_destination(
+        &self,
+        destination_pubkey: &[u8],
+        destination: Option<&XOnlyPublicKey>,
+    ) -> Result<Option<VerificationResult>, MeshError> {
+        self.check_payee_key(destination_pubkey, destination, "Keysend destination")
+            .await
+    }
+
+    /// Compare a payee's Lightning key (33 bytes) with this node's or the
+    /// packet destination's; `label` names the key in failures
+    ///
+    /// The destination is known by its x-only mesh signing key (from its
+    /// link-state advertisements), so it must receive Lightning payments
+    /// with that same key; the payee matches on its x coordinate. Without
+    /// the destination's key the payee cannot be checked and the proof fails.
+    async fn check_payee_key(
+        &self,
+        payee: &[u8],
+        destination: Option<&XOnlyPublicKey>,
+        label: &str,
+    ) -> Result<Option<VerificationResult>, MeshError> {
+        if payee.len() != 33 {
+            return Ok(Some(VerificationResult::failure(format!(
+                "{} is not a valid public key",
+                label
+            ))));
+        }
+        if !self.require_local_payee {
+            let Some(destination) = destination else {
+                warn!("Packet destination key unknown, cannot check {}", label.to_lowercase());
+                return Ok(Some(VerificationResult::failure(format!(
+                    "Cannot check {}: packet destination key is unknown",
+                    label.to_lowercase()
+                ))));
+            };
+            if payee[1..] != destination.serialize() {
+                warn!("{} {} is not the packet destination", label, hex::encode(payee));
+                return Ok(Some(VerificationResult::failure(format!(
+                    "{} is not the packet destination",
+                    label
+                ))));
+            }
+            return Ok(None);
+        }
+
+        let Some(local_payee) = self.local_payee().await? else {
+            warn!("Lightning backend unavailable, cannot check {}", label.to_lowercase());
+            return Ok(Some(VerificationResult::failure(format!(
+                "Cannot check {}: this node's Lightning key is unknown",
+                label.to_lowercase()
+            ))));
+        };
+        if payee != local_payee.as_slice() {
+            warn!("{} {} is not this node", label, hex::encode(payee));
+            return Ok(Some(VerificationResult::failure(format!(
+                "{} is not this node",
+                label
+            ))));
+        }
+        Ok(None)
+    }
+
+    /// This node's Lightning key, fetched from the backend on first use
+    ///
+    /// None while no Lightning backend is reachable (not cached, so the key
+    /// is picked up once the backend comes up).
+    async fn local_payee(&self) -> Result<Option<Vec<u8>>, MeshError> {
+        if let Some(ref pubkey) = *self.local_payee.lock().unwrap() {
+            return Ok(Some(pubkey.clone()));
+        }
+        let info = match self.node_api.get_lightning_info().await {
+            Ok(Some(info)) => info,
+            Ok(None) => return Ok(None),
+            Err(e) => {
+                debug!("Error querying Lightning info: {}", e);
+                return Ok(None);
+            }
+        };
+        let pubkey = hex::decode(&info.node_pubkey).map_err(|e| {
+            MeshError::PaymentVerification(format!("Invalid Lightning node public key: {}", e))
+        })?;
+        *self.local_payee.lock().unwrap() = Some(pubkey.clone());
+        Ok(Some(pubkey))
+    }
+
     /// Verify Lightning payment proof
     async fn verify_lightning(
         &self,
@@ -77,7 +791,7 @@ impl PaymentVerifier {
         timestamp: u64,
         expires_at: u64,
     ) -> Result<VerificationResult, MeshError> {
-        if !self.lightning_enabled {
+        if !self.enabled {
             return Ok(VerificationResult::failure(
                 "Lightning verification not enabled".to_string(),
             ));
@@ -85,6 +799,17 @@ impl PaymentVerifier {
 
         debug!("Verifying Lightning payment: invoice={}, amount={} msats", invoice, amount_msats);
 
+        // A hand-picked preimage is likely public, and so is its invoice
+        let mut distinct_bytes = preimage.to_vec();
+        distinct_bytes.sort_unstable();
+        distinct_bytes.dedup();
+        if distinct_bytes.len() < MIN_PREIMAGE_DISTINCT_BYTES {
+            warn!("Rejecting low-entropy Lightning preimage");
+            return Ok(VerificationResult::failure(
+                "Payment preimage has too little entropy".to_string(),
+            ));
+        }
+
         // Parse BOLT11 invoice
         use lightning_invoice::Invoice;
         let parsed_invoice = match Invoice::from_str(invoice) {
@@ -97,6 +822,50 @@ impl PaymentVerifier {
                 )));
             }
         };
+
+        if let Some(failure) = self.check_chain().await {
+            return Ok(failure);
+        }
+        if parsed_invoice.currency() != self.invoice_currency {
+            warn!("Invoice currency {:?} is not {:?}", parsed_invoice.currency(), self.invoice_currency);
+            return Ok(VerificationResult::failure(
+                "Invoice is for another Bitcoin network".to_string(),
+            ));
+        }
+
+        // Without a payment secret the payee accepts payments from anyone
+        // who learns the payment hash (probing, BOLT11 `s` field)
+        match parsed_invoice.payment_secret() {
+            Some(secret) if secret.0 != [0u8; 32] => {}
+            _ => {
+                warn!("Invoice has no payment secret");
+                return Ok(VerificationResult::failure(
+                    "Invoice has no payment secret".to_string(),
+                ));
+            }
+        }
+
+        if parsed_invoice.amount_milli_satoshis().is_none() && !self.allow_zero_amount_invoices {
+            return Ok(VerificationResult::failure(
+                "Invoice has no amount".to_string(),
+            ));
+        }
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let issued_at = parsed_invoice
+            .timestamp()
+            .duration_since(UNIX_EPOCH)
+            .map(|issued_at| issued_at.as_secs())
+            .unwrap_or(0);
+        if now.saturating_sub(issued_at) > self.max_invoice_age_seconds {
+            warn!("Invoice issued at {} is older than {} seconds", issued_at, self.max_invoice_age_seconds);
+            return Ok(VerificationResult::failure(
+                "Invoice is too old".to_string(),
+            ));
+        }
         
         // Verify payment hash matches preimage
         let payment_hash = parsed_invoice.payment_hash();
@@ -123,11 +892,6 @@ impl PaymentVerifier {
         }
         
         // Verify expiry
-        let now = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
-        
         if expires_at < now {
             warn!("Invoice expired: expires_at={}, now={}", expires_at, now);
             return Ok(VerificationResult::failure(
@@ -161,16 +925,352 @@ impl PaymentVerifier {
         ))
     }
 
-    /// Verify CTV instant settlement proof
-    #[cfg(feature = "ctv")]
+    /// Verify BOLT12 offer payment proof
+    ///
+    /// Checks that the offer was issued by this node's Lightning key, that
+    /// the invoice request carries a valid payer signature, was made against
+    /// the offer and covers the claimed amount. Offers and invoice requests
+    /// do not commit to a payment hash, so the payment itself must be known
+    /// to this node's Lightning backend as settled under SHA256(preimage);
+    /// without a reachable backend the proof is refused.
+    async fn verify_lightning_bolt12(
+        &self,
+        offer: &str,
+        invoice_request: &[u8],
+        preimage: &[u8; 32],
+        amount_msats: u64,
+        timestamp: u64,
+    ) -> Result<VerificationResult, MeshError> {
+        if !self.enabled {
+            return Ok(VerificationResult::failure(
+                "Lightning verification not enabled".to_string(),
+            ));
+        }
+
+        debug!("Verifying BOLT12 payment: offer={}, amount={} msats", offer, amount_msats);
+
+        use lightning::offers::invoice_request::InvoiceRequest;
+        use lightning::offers::offer::{Amount, Offer};
+
+        // Parse the offer
+        let parsed_offer = match Offer::from_str(offer) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                warn!("Failed to parse BOLT12 offer: {:?}", e);
+                return Ok(VerificationResult::failure(format!(
+                    "Invalid BOLT12 offer format: {:?}",
+                    e
+                )));
+            }
+        };
+
+        // Anyone can pay a public offer on paper: it must be ours
+        let Some(local_payee) = self.local_payee().await? else {
+            warn!("Lightning backend unavailable, cannot check BOLT12 offer issuer");
+            return Ok(VerificationResult::failure(
+                "Cannot check BOLT12 offer issuer: this node's Lightning key is unknown".to_string(),
+            ));
+        };
+        let issuer = parsed_offer.signing_pubkey().map(|pubkey| pubkey.serialize().to_vec());
+        if issuer.as_deref() != Some(local_payee.as_slice()) {
+            warn!("BOLT12 offer was not issued by this node");
+            return Ok(VerificationResult::failure(
+                "BOLT12 offer was not issued by this node".to_string(),
+            ));
+        }
+
+        // Parse the invoice request (verifies the payer signature)
+        let parsed_request = match InvoiceRequest::try_from(invoice_request.to_vec()) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                warn!("Failed to parse BOLT12 invoice request: {:?}", e);
+                return Ok(VerificationResult::failure(format!(
+                    "Invalid BOLT12 invoice request: {:?}",
+                    e
+                )));
+            }
+        };
+
+        // The request must be for this offer
+        if parsed_request.signing_pubkey() != parsed_offer.signing_pubkey()
+            || parsed_request.description() != parsed_offer.description()
+        {
+            warn!("BOLT12 invoice request does not match offer");
+            return Ok(VerificationResult::failure(
+                "Invoice request was not made against this offer".to_string(),
+            ));
+        }
+
+        // Verify amount: at least the offer's price, and what the payer requested
+        if let Some(Amount::Bitcoin { amount_msats: offer_amount }) = parsed_offer.amount() {
+            if amount_msats < offer_amount {
+                warn!("Amount below offer: offer={} msats, proof={} msats", offer_amount, amount_msats);
+                return Ok(VerificationResult::failure(
+                    "Payment amount is below the offer amount".to_string(),
+                ));
+            }
+        }
+        if let Some(requested) = parsed_request.amount_msats() {
+            if requested != amount_msats {
+                warn!("Amount mismatch: invoice request={} msats, proof={} msats", requested, amount_msats);
+                return Ok(VerificationResult::failure(
+                    "Payment amount does not match invoice request".to_string(),
+                ));
+            }
+        }
+
+        // Verify the offer has not expired
+        if parsed_offer.is_expired() {
+            warn!("BOLT12 offer expired");
+            return Ok(VerificationResult::failure(
+                "BOLT12 offer has expired".to_string(),
+            ));
+        }
+
+        // The preimage proves nothing on its own: the payment must have settled here
+        let payment_hash = {
+            use sha2::{Digest, Sha256};
+            Sha256::digest(preimage)
+        };
+        let payment_id = format!("bolt12_{}", hex::encode(payment_hash));
+        match self.node_api.get_payment_state(&payment_id).await {
+            Ok(Some(payment_state)) => {
+                debug!("BOLT12 payment settled: {:?}", payment_state);
+            }
+            Ok(None) => {
+                warn!("BOLT12 payment {} not settled to this node", hex::encode(payment_hash));
+                return Ok(VerificationResult::failure(
+                    "BOLT12 payment not settled to this node".to_string(),
+                ));
+            }
+            Err(e) => {
+                return Err(MeshError::PaymentVerification(format!(
+                    "Could not confirm BOLT12 settlement: {}",
+                    e
+                )));
+            }
+        }
+
+        Ok(VerificationResult::success(
+            amount_msats / 1000, // Convert to satoshis
+            timestamp,
+            Some(timestamp + crate::payment_proof::BOLT12_PROOF_MAX_AGE_SECONDS),
+        ))
+    }
+
+    /// Verify keysend (spontaneous) payment proof
+    ///
+    /// Checks that the preimage hashes to the payment hash and that the
+    /// amount is non-zero. A keysend preimage TLV record, if sent, must
+    /// carry the same preimage. With the settlement check on (the default)
+    /// the payment must also be known to the node's Lightning backend as
+    /// settled; a payment sent to another node never is, and without a
+    /// reachable backend the proof is refused. The destination key is
+    /// checked by `verify` (`check_keysend_destination`).
+    pub async fn verify_keysend(
+        &self,
+        payment_hash: &[u8; 32],
+        preimage: &[u8; 32],
+        amount_msats: u64,
+        timestamp: u64,
+        custom_tlv: &[(u64, Vec<u8>)],
+    ) -> Result<VerificationResult, MeshError> {
+        if !self.enabled {
+            return Ok(VerificationResult::failure(
+                "Lightning verification not enabled".to_string(),
+            ));
+        }
+
+        debug!(
+            "Verifying keysend payment: payment_hash={}, amount={} msats",
+            hex::encode(payment_hash),
+            amount_msats
+        );
+
+        // Verify payment hash matches preimage
+        let preimage_hash = {
+            use sha2::{Digest, Sha256};
+            Sha256::digest(preimage)
+        };
+        if payment_hash.as_slice() != preimage_hash.as_slice() {
+            warn!("Keysend payment hash mismatch: payment hash != preimage hash");
+            return Ok(VerificationResult::failure(
+                "Payment hash does not match preimage".to_string(),
+            ));
+        }
+
+        if amount_msats == 0 {
+            return Ok(VerificationResult::failure(
+                "Keysend payment amount is zero".to_string(),
+            ));
+        }
+
+        let preimage_record = custom_tlv
+            .iter()
+            .find(|(tlv_type, _)| *tlv_type == KEYSEND_PREIMAGE_TLV_TYPE);
+        if let Some((_, value)) = preimage_record {
+            if value.as_slice() != preimage.as_slice() {
+                warn!("Keysend preimage record does not match preimage");
+                return Ok(VerificationResult::failure(
+                    "Keysend preimage record does not match preimage".to_string(),
+                ));
+            }
+        }
+
+        let payment_id = format!("keysend_{}", hex::encode(payment_hash));
+        if self.keysend_settlement_check {
+            match self.node_api.get_lightning_info().await {
+                Ok(Some(_)) => match self.node_api.get_payment_state(&payment_id).await {
+                    Ok(Some(payment_state)) => {
+                        debug!("Keysend payment settled: {:?}", payment_state);
+                    }
+                    Ok(None) => {
+                        warn!("Keysend payment {} not settled to this node", hex::encode(payment_hash));
+                        return Ok(VerificationResult::failure(
+                            "Keysend payment not settled to this node".to_string(),
+                        ));
+                    }
+                    Err(e) => {
+                        return Err(MeshError::PaymentVerification(format!(
+                            "Could not confirm keysend settlement: {}",
+                            e
+                        )));
+                    }
+                },
+                Ok(None) | Err(_) => {
+                    warn!("Lightning backend unreachable, cannot confirm keysend settlement");
+                    return Ok(VerificationResult::failure(
+                        "Cannot confirm keysend settlement: Lightning backend unavailable".to_string(),
+                    ));
+                }
+            }
+        } else {
+            // Check if payment exists in node's payment system (optional verification)
+            match self.node_api.get_payment_state(&payment_id).await {
+                Ok(Some(payment_state)) => {
+                    debug!("Payment found in node state: {:?}", payment_state);
+                }
+                Ok(None) => {
+                    debug!("Payment not yet in node state, but preimage verification passed");
+                }
+                Err(e) => {
+                    debug!("Error querying payment state (non-fatal): {}", e);
+                }
+            }
+        }
+
+        Ok(VerificationResult::success(
+            amount_msats / 1000, // Convert to satoshis
+            timestamp,
+            Some(timestamp.saturating_add(self.keysend_max_age_seconds)),
+        ))
+    }
+}
+
+#[async_trait]
+impl ProofBackend for LightningBackend {
+    fn kind(&self) -> &str {
+        LIGHTNING_PROOF_KIND
+    }
+
+    async fn verify(&self, proof_bytes: &[u8], ctx: &VerifyContext) -> Result<VerificationResult, MeshError> {
+        let proof: PaymentProof = match bincode::deserialize(proof_bytes) {
+            Ok(proof) => proof,
+            Err(e) => {
+                return Ok(VerificationResult::failure(format!(
+                    "Malformed Lightning payment proof: {}",
+                    e
+                )));
+            }
+        };
+        match proof {
+            PaymentProof::Lightning {
+                invoice,
+                preimage,
+                amount_msats,
+                timestamp,
+                expires_at,
+            } => {
+                if let Some(failure) = self.check_payee(&invoice, ctx.destination.as_ref()).await? {
+                    return Ok(failure);
+                }
+                self.verify_lightning(&invoice, &preimage, amount_msats, timestamp, expires_at)
+                    .await
+            }
+            PaymentProof::LightningBolt12 {
+                offer,
+                invoice_request,
+                preimage,
+                amount_msats,
+                timestamp,
+            } => {
+                self.verify_lightning_bolt12(&offer, &invoice_request, &preimage, amount_msats, timestamp)
+                    .await
+            }
+            PaymentProof::Keysend {
+                payment_hash,
+                preimage,
+                amount_msats,
+                timestamp,
+                custom_tlv,
+                destination_pubkey,
+            } => {
+                if let Some(failure) = self
+                    .check_keysend_destination(&destination_pubkey, ctx.destination.as_ref())
+                    .await?
+                {
+                    return Ok(failure);
+                }
+                self.verify_keysend(&payment_hash, &preimage, amount_msats, timestamp, &custom_tlv)
+                    .await
+            }
+            _ => Ok(VerificationResult::failure(
+                "Not a Lightning payment proof".to_string(),
+            )),
+        }
+    }
+}
+
+/// Built-in backend for CTV instant settlement proofs
+///
+/// Verifies bincode-encoded `PaymentProof::InstantSettlement`s
+/// (`CTV_PROOF_KIND`).
+#[cfg(feature = "ctv")]
+pub struct CtvBackend {
+    /// Node API for transaction and header lookups
+    node_api: Arc<dyn NodeAPI>,
+    /// Whether CTV verification is enabled
+    enabled: bool,
+    /// Where CTV transactions must have been seen
+    inclusion: CtvInclusion,
+    /// Last inclusion lookup per CTV txid, with when it was made
+    inclusion_cache: Mutex<LruCache<[u8; 32], (TxInclusion, u64)>>,
+}
+
+#[cfg(feature = "ctv")]
+impl CtvBackend {
+    /// Create a CTV backend that does not require inclusion
+    pub fn new(node_api: Arc<dyn NodeAPI>) -> Self {
+        Self {
+            node_api,
+            enabled: true, // CTV enabled if feature flag is set
+            inclusion: CtvInclusion::None,
+            inclusion_cache: Mutex::new(LruCache::new(
+                NonZeroUsize::new(DEFAULT_VERIFICATION_CACHE_SIZE).unwrap(),
+            )),
+        }
+    }
+
+    /// Verify CTV instant settlement proof
     async fn verify_ctv(
         &self,
         covenant_proof: &[u8],
         output_index: u32,
+        inclusion_proof: Option<([u8; 32], u32, &[[u8; 32]])>,
         amount_sats: u64,
         timestamp: u64,
     ) -> Result<VerificationResult, MeshError> {
-        if !self.ctv_enabled {
+        if !self.enabled {
             return Ok(VerificationResult::failure(
                 "CTV verification not enabled".to_string(),
             ));
@@ -184,12 +1284,11 @@ impl PaymentVerifier {
         // CTV verification via NodeAPI
         // CTV (CheckTemplateVerify) payments are verified by checking on-chain transactions
         // This verifier checks CTV payment proofs that reference on-chain transactions
-        // This should:
         // 1. Deserialize CovenantProof from bytes
         // 2. Verify template hash matches expected structure
         // 3. Verify output amount matches
-        // 4. Check if transaction is in mempool or confirmed
-        // 5. Verify merkle proof (if provided)
+        // 4. Check the transaction is in the mempool or confirmed, per policy,
+        //    against the Merkle proof if provided
 
         // Deserialize covenant proof
         if covenant_proof.is_empty() {
@@ -254,46 +1353,438 @@ impl PaymentVerifier {
             }
         }
         
-        // Check if transaction is in mempool or confirmed (optional, via NodeAPI)
-        // For mesh routing, we accept mempool transactions
-        // The covenant proof itself is sufficient proof of payment commitment
-        
+        if self.inclusion != CtvInclusion::None {
+            let txid = template_txid(&proof.transaction_template);
+            if let Err(reason) = self.check_ctv_inclusion(&txid, output_index, inclusion_proof).await? {
+                warn!("CTV transaction {} rejected: {}", hex::encode(txid), reason);
+                return Ok(VerificationResult::failure(reason));
+            }
+        }
+
         debug!("CTV covenant proof verified successfully");
         Ok(VerificationResult::success(amount_sats, timestamp, None))
     }
 
-    /// Check if minimum payment amount is met
-    pub fn check_minimum_payment(&self, amount_sats: u64, minimum_sats: u64) -> bool {
-        amount_sats >= minimum_sats
+    /// Check a CTV transaction against `mesh.ctv.require_inclusion`
+    ///
+    /// With a Merkle proof, the transaction is checked against the header
+    /// of the block it names; otherwise the mempool and chain are asked.
+    /// Returns the failure reason if the policy is not met.
+    async fn check_ctv_inclusion(
+        &self,
+        txid: &[u8; 32],
+        output_index: u32,
+        inclusion_proof: Option<([u8; 32], u32, &[[u8; 32]])>,
+    ) -> Result<Result<(), String>, MeshError> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let tip_height = self
+            .node_api
+            .get_block_height()
+            .await
+            .map_err(|e| MeshError::PaymentVerification(format!("Could not get chain height: {}", e)))?;
+
+        let cached = self.inclusion_cache.lock().unwrap().get(txid).copied();
+        let inclusion = match cached {
+            Some((inclusion @ TxInclusion::Confirmed { height: Some(_) }, _)) => inclusion,
+            Some((inclusion, checked_at))
+                if now.saturating_sub(checked_at) < CTV_INCLUSION_CACHE_TTL_SECONDS
+                    && inclusion_proof.is_none() =>
+            {
+                inclusion
+            }
+            _ => {
+                let inclusion = match inclusion_proof {
+                    Some((block_hash, tx_index, branch)) => {
+                        match self.merkle_inclusion(txid, block_hash, tx_index, branch, tip_height).await? {
+                            Ok(inclusion) => inclusion,
+                            Err(reason) => return Ok(Err(reason)),
+                        }
+                    }
+                    None => self.lookup_inclusion(txid, output_index).await?,
+                };
+                self.inclusion_cache.lock().unwrap().put(*txid, (inclusion, now));
+                inclusion
+            }
+        };
+
+        Ok(match (self.inclusion, inclusion) {
+            (CtvInclusion::None, _) => Ok(()),
+            (_, TxInclusion::Unseen) => Err("CTV transaction not found in mempool or chain".to_string()),
+            (CtvInclusion::Mempool, _) => Ok(()),
+            (CtvInclusion::Confirmed(_), TxInclusion::Mempool) => Err("CTV transaction not confirmed".to_string()),
+            (CtvInclusion::Confirmed(_), TxInclusion::Confirmed { height: None }) => {
+                Err("CTV transaction confirmation depth unknown; a Merkle proof is needed".to_string())
+            }
+            (CtvInclusion::Confirmed(required), TxInclusion::Confirmed { height: Some(height) }) => {
+                let depth = (tip_height + 1).saturating_sub(height);
+                if depth >= required {
+                    Ok(())
+                } else {
+                    Err(format!("CTV transaction has {} confirmations, {} required", depth, required))
+                }
+            }
+        })
     }
 
-    /// Calculate required payment amount for data size
-    pub fn calculate_payment_for_data(&self, data_size_bytes: usize, rate_sats_per_byte: u64) -> u64 {
-        (data_size_bytes as u64) * rate_sats_per_byte
+    /// Ask the node whether a CTV transaction is in the mempool or chain
+    async fn lookup_inclusion(&self, txid: &[u8; 32], output_index: u32) -> Result<TxInclusion, MeshError> {
+        let lookup_error =
+            |e: ModuleError| MeshError::PaymentVerification(format!("Could not look up CTV transaction: {}", e));
+
+        if self.node_api.check_transaction_in_mempool(txid).await.map_err(lookup_error)? {
+            return Ok(TxInclusion::Mempool);
+        }
+        if self.node_api.get_transaction(txid).await.map_err(lookup_error)?.is_none() {
+            return Ok(TxInclusion::Unseen);
+        }
+
+        // The paid output's UTXO carries the confirmation height
+        let outpoint = bllvm_protocol::OutPoint {
+            hash: *txid,
+            index: output_index.into(),
+        };
+        let height = self
+            .node_api
+            .get_utxo(&outpoint)
+            .await
+            .map_err(lookup_error)?
+            .map(|utxo| u64::from(utxo.height));
+        Ok(TxInclusion::Confirmed { height })
     }
 
-    /// Verify multiple payment proofs in parallel (batch operation)
+    /// Check a CTV transaction's Merkle branch against its block header
     ///
-    /// Processes multiple payment verifications concurrently for better performance.
-    /// Returns a vector of verification results in the same order as inputs.
-    pub async fn verify_batch(
+    /// The block must be within `CTV_HEADER_WALK_LIMIT` headers (plus the
+    /// required depth) of the tip on the node's main chain.
+    async fn merkle_inclusion(
         &self,
-        proofs: &[&PaymentProof],
-    ) -> Result<Vec<VerificationResult>, MeshError> {
-        if proofs.is_empty() {
-            return Ok(Vec::new());
+        txid: &[u8; 32],
+        block_hash: [u8; 32],
+        tx_index: u32,
+        branch: &[[u8; 32]],
+        tip_height: u64,
+    ) -> Result<Result<TxInclusion, String>, MeshError> {
+        let header_error = |e: ModuleError| MeshError::PaymentVerification(format!("Could not get block header: {}", e));
+
+        let Some(header) = self.node_api.get_block_header(&block_hash).await.map_err(header_error)? else {
+            return Ok(Err("CTV Merkle proof names an unknown block".to_string()));
+        };
+        if merkle_root_from_branch(txid, tx_index, branch) != header.merkle_root {
+            return Ok(Err("CTV Merkle proof does not match the block's Merkle root".to_string()));
         }
-        
-        // Verify all proofs in parallel
-        let futures: Vec<_> = proofs
-            .iter()
-            .map(|proof| self.verify(*proof))
-            .collect();
-        
-        // Wait for all verifications to complete
-        futures::future::join_all(futures)
+
+        let walk_limit = match self.inclusion {
+            CtvInclusion::Confirmed(depth) => depth.saturating_add(CTV_HEADER_WALK_LIMIT),
+            _ => CTV_HEADER_WALK_LIMIT,
+        };
+        let mut hash = self.node_api.get_chain_tip().await.map_err(header_error)?;
+        for steps in 0..walk_limit.min(tip_height + 1) {
+            if hash == block_hash {
+                return Ok(Ok(TxInclusion::Confirmed {
+                    height: Some(tip_height - steps),
+                }));
+            }
+            match self.node_api.get_block_header(&hash).await.map_err(header_error)? {
+                Some(header) => hash = header.prev_block_hash,
+                None => break,
+            }
+        }
+        Ok(Err("CTV Merkle proof block is not in the recent main chain".to_string()))
+    }
+}
+
+#[cfg(feature = "ctv")]
+#[async_trait]
+impl ProofBackend for CtvBackend {
+    fn kind(&self) -> &str {
+        CTV_PROOF_KIND
+    }
+
+    async fn verify(&self, proof_bytes: &[u8], _ctx: &VerifyContext) -> Result<VerificationResult, MeshError> {
+        let Ok(PaymentProof::InstantSettlement {
+            covenant_proof,
+            output_index,
+            merkle_proof,
+            block_hash,
+            tx_index,
+            amount_sats,
+            timestamp,
+        }) = bincode::deserialize(proof_bytes)
+        else {
+            return Ok(VerificationResult::failure(
+                "Not a CTV instant settlement proof".to_string(),
+            ));
+        };
+        let inclusion_proof = block_hash.map(|hash| (hash, tx_index, merkle_proof.as_slice()));
+        self.verify_ctv(&covenant_proof, output_index, inclusion_proof, amount_sats, timestamp)
             .await
+    }
+}
+
+/// bincode encoding of a proof, as the built-in backends take it
+fn encode_proof(proof: &PaymentProof) -> Vec<u8> {
+    bincode::serialize(proof).expect("Payment proof should be serializable")
+}
+
+/// Txid of a CTV transaction template
+///
+/// Double SHA256 of the legacy (witness-free) serialization, which is all a
+/// template commits to.
+#[cfg(feature = "ctv")]
+pub fn template_txid(template: &bllvm_node::payment::covenant::TransactionTemplate) -> [u8; 32] {
+    let mut tx = Vec::new();
+    tx.extend_from_slice(&template.version.to_le_bytes());
+    write_compact_size(&mut tx, template.inputs.len() as u64);
+    for input in &template.inputs {
+        tx.extend_from_slice(&input.prevout.hash);
+        tx.extend_from_slice(&(input.prevout.index as u32).to_le_bytes());
+        write_compact_size(&mut tx, input.script_sig.len() as u64);
+        tx.extend_from_slice(&input.script_sig);
+        tx.extend_from_slice(&input.sequence.to_le_bytes());
+    }
+    write_compact_size(&mut tx, template.outputs.len() as u64);
+    for output in &template.outputs {
+        tx.extend_from_slice(&output.value.to_le_bytes());
+        write_compact_size(&mut tx, output.script_pubkey.len() as u64);
+        tx.extend_from_slice(&output.script_pubkey);
+    }
+    tx.extend_from_slice(&template.lock_time.to_le_bytes());
+    Sha256::digest(Sha256::digest(&tx)).into()
+}
+
+/// Merkle root a transaction's branch leads to
+///
+/// `branch` holds the sibling hashes from the leaf up; bit `i` of
+/// `tx_index` says whether the running hash is the right-hand node at
+/// level `i`.
+#[cfg(feature = "ctv")]
+pub fn merkle_root_from_branch(txid: &[u8; 32], tx_index: u32, branch: &[[u8; 32]]) -> [u8; 32] {
+    branch.iter().enumerate().fold(*txid, |hash, (level, sibling)| {
+        let (left, right) = if tx_index.checked_shr(level as u32).unwrap_or(0) & 1 == 0 {
+            (&hash, sibling)
+        } else {
+            (sibling, &hash)
+        };
+        let mut hasher = Sha256::new();
+        hasher.update(left);
+        hasher.update(right);
+        Sha256::digest(hasher.finalize()).into()
+    })
+}
+
+/// Append a Bitcoin CompactSize length
+#[cfg(feature = "ctv")]
+fn write_compact_size(out: &mut Vec<u8>, n: u64) {
+    match n {
+        0..=0xfc => out.push(n as u8),
+        0xfd..=0xffff => {
+            out.push(0xfd);
+            out.extend_from_slice(&(n as u16).to_le_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            out.push(0xfe);
+            out.extend_from_slice(&(n as u32).to_le_bytes());
+        }
+        _ => {
+            out.push(0xff);
+            out.extend_from_slice(&n.to_le_bytes());
+        }
+    }
+}
+
+/// Escrow HTLC seen by this node
+#[derive(Debug, Clone, Copy)]
+struct HeldEscrow {
+    /// Preimage (only known to the source until delivery is confirmed)
+    preimage: Option<[u8; 32]>,
+    /// When the HTLC times out
+    htlc_expiry: u64,
+}
+
+/// Checks HTLC escrow proofs and carries out their claims
+///
+/// Relays call `verify` before forwarding an escrowed packet: the HTLC must
+/// be known to the node's Lightning backend (`htlc_<payment hash>` in
+/// `get_payment_state`), lock a non-zero amount and stay locked for at least
+/// the minimum expiry delta. Unlike the other proofs, a failed lookup
+/// rejects the packet, since the escrow is the relay's only guarantee of
+/// being paid.
+///
+/// The source `hold`s the preimage of each escrow it opens and, once the
+/// destination acks the packet, `release`s it to the relays, which
+/// `accept_claim` it for escrows they checked. Claims are collected with
+/// `take_claims` and settled by the node's Lightning backend.
+pub struct HtlcVerifier {
+    node_api: Arc<dyn NodeAPI>,
+    min_expiry_delta_seconds: u64,
+    /// Escrows opened or checked by this node, by payment hash
+    escrows: DashMap<[u8; 32], HeldEscrow>,
+    /// Escrows of packets sent by this node awaiting their ack, by sequence
+    awaiting_delivery: DashMap<u64, [u8; 32]>,
+    /// Preimages revealed to this node for escrows it checked
+    claims: DashMap<[u8; 32], [u8; 32]>,
+}
+
+impl HtlcVerifier {
+    /// Create a verifier querying the node's payment state
+    pub fn new(node_api: Arc<dyn NodeAPI>) -> Self {
+        Self {
+            node_api,
+            min_expiry_delta_seconds: DEFAULT_HTLC_MIN_EXPIRY_DELTA_SECONDS,
+            escrows: DashMap::new(),
+            awaiting_delivery: DashMap::new(),
+            claims: DashMap::new(),
+        }
+    }
+
+    /// Require HTLCs to stay locked for `seconds` after they are checked
+    pub fn with_min_expiry_delta(mut self, seconds: u64) -> Self {
+        self.min_expiry_delta_seconds = seconds;
+        self
+    }
+
+    /// Verify an escrow proof (other proofs fail)
+    pub async fn verify(&self, proof: &PaymentProof) -> Result<VerificationResult, MeshError> {
+        let PaymentProof::HtlcEscrow {
+            payment_hash,
+            htlc_expiry,
+            amount_msats,
+        } = proof
+        else {
+            return Ok(VerificationResult::failure(
+                "Not an HTLC escrow proof".to_string(),
+            ));
+        };
+
+        debug!(
+            "Verifying HTLC escrow: payment_hash={}, amount={} msats, expiry={}",
+            hex::encode(payment_hash),
+            amount_msats,
+            htlc_expiry
+        );
+
+        if *amount_msats == 0 {
+            return Ok(VerificationResult::failure(
+                "HTLC escrow amount is zero".to_string(),
+            ));
+        }
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        if *htlc_expiry < now.saturating_add(self.min_expiry_delta_seconds) {
+            warn!("HTLC escrow expires too soon: expiry={}, now={}", htlc_expiry, now);
+            return Ok(VerificationResult::failure(
+                "HTLC escrow expires too soon".to_string(),
+            ));
+        }
+
+        // The HTLC must be locked in the node's Lightning backend
+        let payment_id = format!("htlc_{}", hex::encode(payment_hash));
+        match self.node_api.get_payment_state(&payment_id).await {
+            Ok(Some(payment_state)) => {
+                debug!("HTLC found in node state: {:?}", payment_state);
+            }
+            Ok(None) => {
+                warn!("HTLC escrow not found: payment_hash={}", hex::encode(payment_hash));
+                return Ok(VerificationResult::failure(
+                    "HTLC escrow not found".to_string(),
+                ));
+            }
+            Err(e) => {
+                warn!("Error querying HTLC state: {}", e);
+                return Ok(VerificationResult::failure(format!(
+                    "Could not confirm HTLC escrow: {}",
+                    e
+                )));
+            }
+        }
+
+        self.escrows.entry(*payment_hash).or_insert(HeldEscrow {
+            preimage: None,
+            htlc_expiry: *htlc_expiry,
+        });
+        Ok(VerificationResult::success(
+            amount_msats / 1000, // Convert to satoshis
+            0,
+            Some(*htlc_expiry),
+        ))
+    }
+
+    /// Keep the preimage of an escrow this node opens
+    ///
+    /// Returns the escrow proof to attach to packets.
+    pub fn hold(&self, preimage: [u8; 32], htlc_expiry: u64, amount_msats: u64) -> PaymentProof {
+        let payment_hash: [u8; 32] = Sha256::digest(preimage).into();
+        self.escrows.insert(
+            payment_hash,
+            HeldEscrow {
+                preimage: Some(preimage),
+                htlc_expiry,
+            },
+        );
+        PaymentProof::HtlcEscrow {
+            payment_hash,
+            htlc_expiry,
+            amount_msats,
+        }
+    }
+
+    /// Wait for the delivery ack of a sent packet before revealing its escrow
+    ///
+    /// Ignored for escrows whose preimage this node does not hold.
+    pub fn await_delivery(&self, sequence: u64, payment_hash: &[u8; 32]) {
+        let held = self
+            .escrows
+            .get(payment_hash)
+            .is_some_and(|escrow| escrow.preimage.is_some());
+        if held {
+            self.awaiting_delivery.insert(sequence, *payment_hash);
+        }
+    }
+
+    /// Preimage to reveal now that the packet with `sequence` was delivered
+    pub fn release(&self, sequence: u64) -> Option<([u8; 32], [u8; 32])> {
+        let (_, payment_hash) = self.awaiting_delivery.remove(&sequence)?;
+        let preimage = self.escrows.get(&payment_hash)?.preimage?;
+        Some((payment_hash, preimage))
+    }
+
+    /// Keep a revealed preimage as a claim on an escrow this node checked
+    ///
+    /// Returns false for unknown escrows and preimages that do not match.
+    pub fn accept_claim(&self, payment_hash: &[u8; 32], preimage: &[u8; 32]) -> bool {
+        if Sha256::digest(preimage).as_slice() != payment_hash.as_slice() {
+            warn!("HTLC claim preimage does not match: payment_hash={}", hex::encode(payment_hash));
+            return false;
+        }
+        if !self.escrows.contains_key(payment_hash) {
+            return false;
+        }
+        self.claims.insert(*payment_hash, *preimage);
+        true
+    }
+
+    /// Claims collected since the last call, as (payment hash, preimage)
+    pub fn take_claims(&self) -> Vec<([u8; 32], [u8; 32])> {
+        let hashes: Vec<[u8; 32]> = self.claims.iter().map(|claim| *claim.key()).collect();
+        hashes
             .into_iter()
-            .collect::<Result<Vec<_>, _>>()
+            .filter_map(|hash| self.claims.remove(&hash))
+            .collect()
+    }
+
+    /// Forget escrows whose HTLC timed out by `now`
+    pub fn expire(&self, now: u64) -> usize {
+        let before = self.escrows.len();
+        self.escrows.retain(|_, escrow| escrow.htlc_expiry > now);
+        self.awaiting_delivery
+            .retain(|_, payment_hash| self.escrows.contains_key(payment_hash));
+        self.claims.retain(|payment_hash, _| self.escrows.contains_key(payment_hash));
+        before - self.escrows.len()
     }
 }
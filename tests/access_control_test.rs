@@ -0,0 +1,118 @@
+//! Tests for node blacklist / whitelist filtering
+
+mod common;
+
+use bllvm_mesh::access_control::{AccessControl, AccessMode};
+use bllvm_mesh::error::MeshError;
+use bllvm_mesh::manager::MeshManager;
+use bllvm_mesh::packet::{MeshPacketBuilder, PacketType};
+use common::{test_context, MockNodeAPI};
+use std::sync::Arc;
+
+const PEER: [u8; 32] = [2u8; 32];
+const BANNED: [u8; 32] = [6u8; 32];
+
+async fn manager(node_api: Arc<MockNodeAPI>, config: &[(&str, &str)]) -> MeshManager {
+    let mut config = config.to_vec();
+    config.extend([("mesh.enabled", "true"), ("mesh.mode", "open")]);
+    let manager = MeshManager::new(&test_context(&config), node_api).await.unwrap();
+    manager.routing_table().add_direct_peer(PEER, b"10.0.0.2:8334".to_vec());
+    manager.routing_table().add_direct_peer(BANNED, b"10.0.0.6:8334".to_vec());
+    manager
+}
+
+#[tokio::test]
+async fn test_blacklisted_nodes_refused() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let banned = hex::encode(BANNED);
+    let manager = manager(node_api.clone(), &[("mesh.access.list", banned.as_str())]).await;
+
+    let to_banned = MeshPacketBuilder::new(manager.node_id(), BANNED)
+        .packet_type(PacketType::BitcoinP2P)
+        .payload(vec![1])
+        .build()
+        .unwrap();
+    assert!(matches!(
+        manager.route_packet(&to_banned).await,
+        Err(MeshError::AccessDenied(id)) if id == BANNED
+    ));
+
+    // Nothing from a banned source is relayed
+    let from_banned = MeshPacketBuilder::new(BANNED, PEER)
+        .packet_type(PacketType::BitcoinP2P)
+        .payload(vec![1])
+        .sequence(1)
+        .build()
+        .unwrap();
+    assert!(matches!(
+        manager.handle_incoming_packet(&from_banned).await,
+        Err(MeshError::AccessDenied(_))
+    ));
+
+    let to_peer = MeshPacketBuilder::new(manager.node_id(), PEER)
+        .packet_type(PacketType::BitcoinP2P)
+        .payload(vec![1])
+        .build()
+        .unwrap();
+    manager.route_packet(&to_peer).await.unwrap();
+    assert_eq!(node_api.take_sent().len(), 1);
+}
+
+#[tokio::test]
+async fn test_whitelist_allows_only_listed_nodes() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let peer = hex::encode(PEER);
+    let manager = manager(
+        node_api.clone(),
+        &[("mesh.access.mode", "whitelist"), ("mesh.access.list", peer.as_str())],
+    )
+    .await;
+
+    // This node itself needs no listing
+    let to_peer = MeshPacketBuilder::new(manager.node_id(), PEER)
+        .packet_type(PacketType::BitcoinP2P)
+        .payload(vec![1])
+        .build()
+        .unwrap();
+    manager.route_packet(&to_peer).await.unwrap();
+    let to_other = MeshPacketBuilder::new(manager.node_id(), BANNED)
+        .packet_type(PacketType::BitcoinP2P)
+        .payload(vec![1])
+        .build()
+        .unwrap();
+    assert!(matches!(
+        manager.route_packet(&to_other).await,
+        Err(MeshError::AccessDenied(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_access_list_survives_restart() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let manager = manager(node_api.clone(), &[]).await;
+    let access = AccessControl::new(AccessMode::Blacklist);
+    access.list.insert(BANNED);
+    manager.set_access_control(access).await;
+    drop(manager);
+
+    // The stored list wins over mesh.access.*
+    let restarted = self::manager(node_api.clone(), &[("mesh.access.mode", "whitelist")]).await;
+    let to_banned = MeshPacketBuilder::new(restarted.node_id(), BANNED)
+        .packet_type(PacketType::BitcoinP2P)
+        .payload(vec![1])
+        .build()
+        .unwrap();
+    assert!(restarted.route_packet(&to_banned).await.is_err());
+    let to_peer = MeshPacketBuilder::new(restarted.node_id(), PEER)
+        .packet_type(PacketType::BitcoinP2P)
+        .payload(vec![1])
+        .build()
+        .unwrap();
+    restarted.route_packet(&to_peer).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_invalid_access_config_rejected() {
+    let ctx = test_context(&[("mesh.enabled", "true"), ("mesh.access.list", "not-a-node-id")]);
+    assert!(MeshManager::new(&ctx, Arc::new(MockNodeAPI::new())).await.is_err());
+}
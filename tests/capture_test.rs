@@ -0,0 +1,137 @@
+//! Tests for ring-buffer packet capture and its RPC endpoints
+
+mod common;
+
+use bllvm_mesh::clock::ManualClock;
+use bllvm_mesh::manager::MeshManager;
+use bllvm_mesh::packet::{MeshPacketBuilder, PacketType};
+use bllvm_mesh::rpc::{RPC_CAPTURE_CLEAR, RPC_CAPTURE_DUMP, RPC_CAPTURE_START};
+use common::{test_context, MockNodeAPI};
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+const PEER_ADDR: &str = "10.0.0.7:8334";
+const PEER: [u8; 32] = [7u8; 32];
+
+async fn capture_manager(config: &[(&str, &str)]) -> (MeshManager, Arc<ManualClock>, Arc<MockNodeAPI>) {
+    let mut settings = vec![("mesh.enabled", "true"), ("mesh.mode", "open")];
+    settings.extend_from_slice(config);
+    let node_api = Arc::new(MockNodeAPI::new());
+    let clock = Arc::new(ManualClock::new(1_000));
+    let manager = MeshManager::with_clock(&test_context(&settings), node_api.clone(), clock.clone())
+        .await
+        .unwrap();
+    manager.routing_table().add_direct_peer(PEER, PEER_ADDR.as_bytes().to_vec());
+    (manager, clock, node_api)
+}
+
+/// Run one delivered, one forwarded and one dropped packet through the manager
+async fn run_traffic(manager: &MeshManager) {
+    let inbound = MeshPacketBuilder::new(PEER, manager.node_id())
+        .packet_type(PacketType::BitcoinP2P)
+        .payload(vec![0xAA; 4])
+        .build()
+        .unwrap();
+    manager.handle_incoming_packet(&inbound).await.unwrap();
+
+    let transit = MeshPacketBuilder::new([9u8; 32], PEER)
+        .packet_type(PacketType::BitcoinP2P)
+        .payload(vec![0xBB; 8])
+        .build()
+        .unwrap();
+    manager.handle_incoming_packet(&transit).await.unwrap();
+
+    let unroutable = MeshPacketBuilder::new(manager.node_id(), [5u8; 32])
+        .packet_type(PacketType::BitcoinP2P)
+        .payload(vec![0xCC])
+        .build()
+        .unwrap();
+    assert!(manager.route_packet(&unroutable).await.is_err());
+}
+
+async fn dump(manager: &MeshManager) -> Value {
+    manager.handle_rpc_call(RPC_CAPTURE_DUMP, &Value::Null).await.unwrap()
+}
+
+#[tokio::test]
+async fn test_capture_off_by_default() {
+    let (manager, _, _) = capture_manager(&[]).await;
+    run_traffic(&manager).await;
+
+    let dump = dump(&manager).await;
+    assert_eq!(dump["enabled"], false);
+    assert!(dump["packets"].as_array().unwrap().is_empty());
+    assert_eq!(dump["memory_bytes"], 0);
+}
+
+#[tokio::test]
+async fn test_dump_records_dispositions_without_payloads() {
+    let (manager, _, node_api) = capture_manager(&[]).await;
+    manager.handle_rpc_call(RPC_CAPTURE_START, &json!({})).await.unwrap();
+    run_traffic(&manager).await;
+
+    let dump = dump(&manager).await;
+    assert_eq!(dump["enabled"], true);
+    let packets = dump["packets"].as_array().unwrap();
+    assert_eq!(packets.len(), 3);
+
+    assert_eq!(packets[0]["disposition"], json!({ "disposition": "delivered" }));
+    assert_eq!(packets[0]["source"], hex::encode(PEER));
+    assert_eq!(packets[1]["disposition"], json!({ "disposition": "forwarded" }));
+    assert_eq!(packets[1]["destination"], hex::encode(PEER));
+    assert_eq!(packets[2]["disposition"]["disposition"], "dropped");
+    assert!(packets[2]["disposition"]["reason"]
+        .as_str()
+        .unwrap()
+        .contains("Route not found"));
+
+    // Headers only: lengths are kept, payloads are redacted
+    assert_eq!(packets[1]["payload_len"], 8);
+    assert!(packets.iter().all(|packet| packet["payload"].is_null()));
+    assert!(dump["memory_bytes"].as_u64().unwrap() > 0);
+
+    // Capture never touches what goes on the wire
+    assert!(!node_api.take_sent().is_empty());
+
+    let cleared = manager.handle_rpc_call(RPC_CAPTURE_CLEAR, &Value::Null).await.unwrap();
+    assert_eq!(cleared["cleared"], 3);
+    let dump = self::dump(&manager).await;
+    assert!(dump["packets"].as_array().unwrap().is_empty());
+    assert_eq!(dump["memory_bytes"], 0);
+}
+
+#[tokio::test]
+async fn test_include_payload_keeps_payload_bytes() {
+    let (manager, _, _) = capture_manager(&[
+        ("mesh.capture.enabled", "true"),
+        ("mesh.capture.include_payload", "true"),
+    ])
+    .await;
+    run_traffic(&manager).await;
+
+    let dump = dump(&manager).await;
+    let packets = dump["packets"].as_array().unwrap();
+    assert_eq!(packets[0]["payload"], hex::encode([0xAA; 4]));
+    assert_eq!(packets[1]["payload"], hex::encode([0xBB; 8]));
+}
+
+#[tokio::test]
+async fn test_capture_auto_disables_after_duration() {
+    let (manager, clock, _) = capture_manager(&[]).await;
+    manager
+        .handle_rpc_call(RPC_CAPTURE_START, &json!({ "duration_secs": 60 }))
+        .await
+        .unwrap();
+
+    clock.advance(59);
+    run_traffic(&manager).await;
+    assert_eq!(dump(&manager).await["packets"].as_array().unwrap().len(), 3);
+
+    clock.advance(1);
+    run_traffic(&manager).await;
+
+    // Expired: nothing new was captured, earlier packets are still dumpable
+    let dump = dump(&manager).await;
+    assert_eq!(dump["enabled"], false);
+    assert_eq!(dump["packets"].as_array().unwrap().len(), 3);
+}
@@ -0,0 +1,78 @@
+//! Tests for per-next-hop circuit breaking
+
+mod common;
+
+use bllvm_mesh::clock::{Clock, ManualClock};
+use bllvm_mesh::manager::MeshManager;
+use bllvm_mesh::routing::{NodeId, RoutingEntry};
+use common::{test_context, MockNodeAPI};
+use std::sync::Arc;
+
+const CHEAP_RELAY: NodeId = [2u8; 32];
+const OTHER_RELAY: NodeId = [4u8; 32];
+const DESTINATION: NodeId = [3u8; 32];
+const CHEAP_ADDR: &str = "10.0.0.2:8334";
+const OTHER_ADDR: &str = "10.0.0.4:8334";
+
+/// Two routes to the destination, the cheaper one through `CHEAP_RELAY`
+async fn manager(node_api: Arc<MockNodeAPI>, clock: Arc<ManualClock>) -> MeshManager {
+    let ctx = test_context(&[
+        ("mesh.enabled", "true"),
+        ("mesh.mode", "open"),
+        ("mesh.forward_retries", "0"),
+    ]);
+    let manager = MeshManager::with_clock(&ctx, node_api, clock).await.unwrap();
+    let table = manager.routing_table();
+    table.add_direct_peer(CHEAP_RELAY, CHEAP_ADDR.as_bytes().to_vec());
+    table.add_direct_peer(OTHER_RELAY, OTHER_ADDR.as_bytes().to_vec());
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let entry = |route_path: Vec<NodeId>, route_cost| RoutingEntry {
+        node_id: *route_path.last().unwrap(),
+        direct_address: None,
+        next_hop: Some(route_path[1]),
+        route_path,
+        route_cost,
+        last_updated: now,
+        quality_score: 0.8,
+    };
+    table.add_route(entry(vec![manager.node_id(), CHEAP_RELAY, DESTINATION], 100));
+    table.add_route(entry(vec![manager.node_id(), OTHER_RELAY, DESTINATION, [5u8; 32]], 300));
+    manager
+}
+
+fn sent_to(node_api: &MockNodeAPI) -> Vec<String> {
+    node_api.take_sent().into_iter().map(|(addr, _)| addr).collect()
+}
+
+#[tokio::test]
+async fn test_open_breaker_skips_failing_hop_until_reset() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let clock = Arc::new(ManualClock::starting_now());
+    let manager = manager(node_api.clone(), clock.clone()).await;
+    node_api.unreachable.lock().unwrap().insert(CHEAP_ADDR.to_string());
+
+    // Each packet fails on the cheap hop first, then takes the other route
+    for _ in 0..3 {
+        manager.send_packet(DESTINATION, vec![1], None).await.unwrap();
+        assert_eq!(sent_to(&node_api), vec![OTHER_ADDR]);
+    }
+
+    // Tripped: the cheap hop is not even tried
+    manager.send_packet(DESTINATION, vec![1], None).await.unwrap();
+    assert_eq!(sent_to(&node_api), vec![OTHER_ADDR]);
+
+    let text = manager.metrics_exporter().render().await;
+    assert!(text.contains("mesh_circuit_breaker_trips_total 1\n"));
+    assert!(text.contains("mesh_circuit_breaker_open_total 1\n"));
+
+    // After the reset timeout one trial goes through and closes the breaker
+    node_api.unreachable.lock().unwrap().clear();
+    clock.set(clock.now_secs() + 30);
+    manager.send_packet(DESTINATION, vec![1], None).await.unwrap();
+    assert_eq!(sent_to(&node_api), vec![CHEAP_ADDR]);
+    manager.send_packet(DESTINATION, vec![1], None).await.unwrap();
+    assert_eq!(sent_to(&node_api), vec![CHEAP_ADDR]);
+}
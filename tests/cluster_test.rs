@@ -0,0 +1,625 @@
+//! End-to-end scenarios on the in-process mesh cluster (feature `testkit`)
+
+use bllvm_mesh::control::ControlMessage;
+use bllvm_mesh::discovery::{DiscoveryMessage, LinkStateEntry, RouteAdvertisementEntry};
+use bllvm_mesh::error::MeshError;
+use bllvm_mesh::gossip::BloomFilter;
+use bllvm_mesh::network::serialize_mesh_packet;
+use bllvm_mesh::packet::{MeshPacketBuilder, PacketType};
+use bllvm_mesh::signing::{generate_signing_key, node_id_from_signing_key, signing_public_key};
+use bllvm_mesh::testkit::{FaultInjector, MeshCluster};
+
+/// Bitcoin mainnet `version` message header (always routed for free)
+fn bitcoin_version_message() -> Vec<u8> {
+    vec![
+        0xf9, 0xbe, 0xb4, 0xd9, // magic
+        0x76, 0x65, 0x72, 0x73, 0x69, 0x6f, 0x6e, 0x00, // "version\0"
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ]
+}
+
+#[tokio::test]
+async fn test_line_topology_delivers_end_to_end() {
+    let cluster = MeshCluster::line(5, &[("mesh.mode", "open")]).await.unwrap();
+
+    cluster.send(0, 4, vec![1, 2, 3]).await.unwrap();
+    // Four hops out; free packets are not acked unless the sender asks
+    assert_eq!(cluster.run_until_idle().await, 4);
+
+    let delivered = cluster.node(4).poll_delivered(10);
+    assert_eq!(delivered.len(), 1);
+    assert_eq!(delivered[0].source, cluster.node_id(0));
+    assert_eq!(delivered[0].payload, vec![1, 2, 3]);
+    assert!(cluster.delivery_errors().is_empty());
+
+    // Relays do not deliver locally
+    for relay in 1..4 {
+        assert!(cluster.node(relay).poll_delivered(10).is_empty());
+    }
+}
+
+#[tokio::test]
+async fn test_discovery_advertisement_round_trip() {
+    let cluster = MeshCluster::new(3, &[("mesh.mode", "open")]).await.unwrap();
+    cluster.connect(0, 1);
+    cluster.connect(1, 2);
+
+    // Node 0 only knows its direct peer
+    assert!(matches!(
+        cluster.send(0, 2, vec![7]).await,
+        Err(MeshError::RouteNotFound(_))
+    ));
+
+    // Node 1 advertises its direct route to node 2
+    let advertisement = DiscoveryMessage::RouteAdvertisement {
+        routes: vec![RouteAdvertisementEntry {
+            destination: cluster.node_id(2),
+            next_hop: cluster.node_id(1),
+            cost: 100,
+            hop_count: 1,
+        }],
+        source: cluster.node_id(1),
+        sequence: 1,
+        seen: BloomFilter::default(),
+    };
+    cluster
+        .node(0)
+        .route_discovery()
+        .handle_route_advertisement(&advertisement, cluster.node_id(1))
+        .await
+        .unwrap();
+
+    cluster.send(0, 2, vec![7]).await.unwrap();
+    cluster.run_until_idle().await;
+    assert_eq!(cluster.node(2).poll_delivered(10).len(), 1);
+    assert!(cluster
+        .routing_snapshot(0)
+        .iter()
+        .any(|entry| entry.node_id == cluster.node_id(2)));
+}
+
+#[tokio::test]
+async fn test_periodic_advertisements_converge_chain() {
+    let cluster = MeshCluster::line(3, &[("mesh.mode", "open")]).await.unwrap();
+    cluster.run_until_idle().await;
+
+    // One advertisement round teaches both ends the route through the middle
+    for node in 0..3 {
+        assert!(cluster.node(node).advertise_routes().await.unwrap() > 0);
+    }
+    cluster.run_until_idle().await;
+    for (from, to) in [(0, 2), (2, 0)] {
+        assert!(cluster.node(from).routing_table().get_route(&cluster.node_id(to)).is_some());
+    }
+
+    // Sends succeed without any route request
+    cluster.send(0, 2, vec![7]).await.unwrap();
+    cluster.send(2, 0, vec![8]).await.unwrap();
+    cluster.run_until_idle().await;
+    assert_eq!(cluster.node(2).poll_delivered(10).len(), 1);
+    assert_eq!(cluster.node(0).poll_delivered(10).len(), 1);
+    assert!(cluster.delivery_errors().is_empty());
+}
+
+#[tokio::test]
+async fn test_route_advertisement_gossiped_to_fanout_peers() {
+    // Node 0 is the hub of a star with five leaves
+    let cluster = MeshCluster::new(6, &[("mesh.mode", "open"), ("mesh.gossip.fanout", "3")])
+        .await
+        .unwrap();
+    for leaf in 1..6 {
+        cluster.connect(0, leaf);
+    }
+
+    let destination = [0xABu8; 32];
+    let advertisement = DiscoveryMessage::RouteAdvertisement {
+        routes: vec![RouteAdvertisementEntry {
+            destination,
+            next_hop: cluster.node_id(1),
+            cost: 100,
+            hop_count: 1,
+        }],
+        source: cluster.node_id(1),
+        sequence: 1,
+        seen: BloomFilter::default(),
+    };
+    cluster.node(1).broadcast_discovery(&advertisement, None).await.unwrap();
+    cluster.run_until_idle().await;
+
+    // The hub forwards it to three leaves other than the sender, once
+    assert!(cluster.node(0).routing_table().get_route(&destination).is_some());
+    let reached = (2..6)
+        .filter(|&leaf| cluster.node(leaf).routing_table().get_route(&destination).is_some())
+        .count();
+    assert_eq!(reached, 3);
+
+    cluster.node(1).broadcast_discovery(&advertisement, None).await.unwrap();
+    cluster.run_until_idle().await;
+    let reached_again = (2..6)
+        .filter(|&leaf| cluster.node(leaf).routing_table().get_route(&destination).is_some())
+        .count();
+    assert_eq!(reached_again, 3);
+    assert!(cluster.delivery_errors().is_empty());
+}
+
+#[tokio::test]
+async fn test_paid_forwarding_requires_payment() {
+    let cluster = MeshCluster::line(5, &[("mesh.mode", "payment_gated")]).await.unwrap();
+
+    // Arbitrary data needs a payment proof at the origin
+    assert!(matches!(
+        cluster.send(0, 4, vec![0x12, 0x34]).await,
+        Err(MeshError::PaymentVerification(_))
+    ));
+    assert_eq!(cluster.run_until_idle().await, 0);
+
+    // Bitcoin P2P traffic is forwarded for free across every relay
+    cluster.send(0, 4, bitcoin_version_message()).await.unwrap();
+    cluster.run_until_idle().await;
+    assert_eq!(cluster.node(4).poll_delivered(10).len(), 1);
+    assert!(cluster.delivery_errors().is_empty());
+}
+
+#[tokio::test]
+async fn test_disconnect_and_faults_stop_delivery() {
+    let cluster = MeshCluster::line(3, &[("mesh.mode", "open")]).await.unwrap();
+
+    cluster.send(0, 2, vec![1]).await.unwrap();
+    cluster.disconnect(1, 2);
+    cluster.run_until_idle().await;
+    assert!(cluster.node(2).poll_delivered(10).is_empty());
+    assert_eq!(cluster.delivery_errors().len(), 1);
+
+    cluster.connect(1, 2);
+    cluster.set_fault_injector(Some(FaultInjector::drop_one_in(1)));
+    cluster.send(0, 2, vec![2]).await.unwrap();
+    cluster.run_until_idle().await;
+    assert!(cluster.node(2).poll_delivered(10).is_empty());
+    assert!(cluster.dropped_packets() > 0);
+}
+
+#[tokio::test]
+async fn test_clock_and_stats_snapshots() {
+    let cluster = MeshCluster::line(5, &[("mesh.mode", "open")]).await.unwrap();
+
+    let start = cluster.now();
+    cluster.advance(3600);
+    assert_eq!(cluster.now(), start + 3600);
+
+    let stats = cluster.stats().await;
+    assert_eq!(stats.len(), 5);
+    assert_eq!(stats[0].routing.direct_peers, 1);
+    assert_eq!(stats[2].routing.direct_peers, 2);
+}
+
+#[tokio::test]
+async fn test_link_state_flooding_builds_routes() {
+    let cluster = MeshCluster::line(4, &[("mesh.mode", "open")]).await.unwrap();
+    let link = |index| LinkStateEntry {
+        neighbor: cluster.node_id(index),
+        latency_ms: 20,
+        capacity_sats: 0,
+    };
+
+    // Nodes 1 and 2 advertise their links; node 1 floods node 2's on to node 0
+    let from_one = cluster.node(1).originate_link_state(vec![link(0), link(2)]).unwrap();
+    let from_two = cluster.node(2).originate_link_state(vec![link(1), link(3)]).unwrap();
+    assert!(cluster
+        .node(1)
+        .handle_link_state_advertisement(&from_two, cluster.node_id(2))
+        .await
+        .unwrap());
+    for advertisement in [&from_one, &from_two] {
+        assert!(cluster
+            .node(0)
+            .handle_link_state_advertisement(advertisement, cluster.node_id(1))
+            .await
+            .unwrap());
+    }
+
+    // Duplicates arriving over another path are not flooded again
+    assert!(!cluster
+        .node(0)
+        .handle_link_state_advertisement(&from_two, cluster.node_id(1))
+        .await
+        .unwrap());
+
+    let topology = cluster.node(0).route_discovery().get_topology_snapshot();
+    assert_eq!(topology.len(), 2);
+    assert_eq!(topology[&cluster.node_id(2)], vec![link(1), link(3)]);
+
+    cluster.send(0, 3, vec![5]).await.unwrap();
+    cluster.run_until_idle().await;
+    assert_eq!(cluster.node(3).poll_delivered(10).len(), 1);
+}
+
+#[tokio::test]
+async fn test_link_state_rejects_forged_advertisements() {
+    let cluster = MeshCluster::line(3, &[("mesh.mode", "open")]).await.unwrap();
+    let advertisement = cluster
+        .node(1)
+        .originate_link_state(vec![LinkStateEntry {
+            neighbor: cluster.node_id(2),
+            latency_ms: 10,
+            capacity_sats: 0,
+        }])
+        .unwrap();
+
+    // Relays may not alter the advertised links
+    let DiscoveryMessage::LinkStateAdvertisement { origin, origin_key, sequence, mut links, signature } =
+        advertisement.clone()
+    else {
+        unreachable!();
+    };
+    links[0].latency_ms = 1;
+    let forged = DiscoveryMessage::LinkStateAdvertisement { origin, origin_key, sequence, links, signature };
+    assert!(matches!(
+        cluster.node(0).handle_link_state_advertisement(&forged, cluster.node_id(1)).await,
+        Err(MeshError::InvalidSignature(_))
+    ));
+    assert!(cluster.node(0).route_discovery().get_topology_snapshot().is_empty());
+
+    // The genuine advertisement is accepted once, older sequences never
+    let node = cluster.node(0);
+    assert!(node.handle_link_state_advertisement(&advertisement, origin).await.unwrap());
+    let DiscoveryMessage::LinkStateAdvertisement { origin, origin_key, links, signature, .. } = advertisement else {
+        unreachable!();
+    };
+    let replayed = DiscoveryMessage::LinkStateAdvertisement { origin, origin_key, sequence: sequence - 1, links, signature };
+    assert!(!node.handle_link_state_advertisement(&replayed, origin).await.unwrap());
+}
+
+#[tokio::test]
+async fn test_link_state_from_origin_beyond_direct_peers() {
+    let cluster = MeshCluster::line(2, &[("mesh.mode", "open")]).await.unwrap();
+    // A node whose key was never exchanged with the cluster
+    let far = MeshCluster::new(1, &[("mesh.mode", "open")]).await.unwrap();
+    let far_id = far.node_id(0);
+    let advertisement = far
+        .node(0)
+        .originate_link_state(vec![LinkStateEntry {
+            neighbor: cluster.node_id(1),
+            latency_ms: 10,
+            capacity_sats: 0,
+        }])
+        .unwrap();
+
+    // A key that does not hash to the origin's node ID is refused
+    let DiscoveryMessage::LinkStateAdvertisement { origin, sequence, links, signature, .. } = advertisement.clone()
+    else {
+        unreachable!();
+    };
+    let impostor = DiscoveryMessage::LinkStateAdvertisement {
+        origin,
+        origin_key: signing_public_key(&generate_signing_key()),
+        sequence,
+        links,
+        signature,
+    };
+    assert!(matches!(
+        cluster.node(0).handle_link_state_advertisement(&impostor, cluster.node_id(1)).await,
+        Err(MeshError::InvalidSignature(_))
+    ));
+
+    // The genuine one is verified against its carried key, which is learned
+    let node = cluster.node(0);
+    assert!(node.handle_link_state_advertisement(&advertisement, cluster.node_id(1)).await.unwrap());
+    assert_eq!(node.peer_keys().get(&far_id).unwrap().serialize(), far.node(0).signing_public_key());
+    assert!(node.route_discovery().get_topology_snapshot().contains_key(&far_id));
+
+    // With node 1's side of the link, node 0 routes to the far node through it
+    let from_one = cluster
+        .node(1)
+        .originate_link_state(vec![LinkStateEntry {
+            neighbor: far_id,
+            latency_ms: 10,
+            capacity_sats: 0,
+        }])
+        .unwrap();
+    assert!(node.handle_link_state_advertisement(&from_one, cluster.node_id(1)).await.unwrap());
+    let route = node.routing_table().get_route(&far_id).unwrap();
+    assert_eq!(route.route_path, vec![cluster.node_id(0), cluster.node_id(1), far_id]);
+}
+
+#[tokio::test]
+async fn test_link_state_advertised_to_direct_peers_and_flooded() {
+    let cluster = MeshCluster::line(3, &[("mesh.mode", "open")]).await.unwrap();
+    assert_eq!(cluster.node(0).advertise_link_state().await.unwrap(), 1);
+    cluster.run_until_idle().await;
+    assert!(cluster.delivery_errors().is_empty());
+
+    // Node 1 floods node 0's links on to node 2
+    for index in [1, 2] {
+        let topology = cluster.node(index).route_discovery().get_topology_snapshot();
+        let links = &topology[&cluster.node_id(0)];
+        assert_eq!(links.len(), 1);
+        assert_eq!(links[0].neighbor, cluster.node_id(1));
+    }
+}
+
+#[tokio::test]
+async fn test_route_discovery_through_relay() {
+    let cluster = MeshCluster::new(3, &[("mesh.mode", "open")]).await.unwrap();
+    cluster.connect(0, 1);
+    cluster.connect(1, 2);
+
+    // The first send has no route and broadcasts a route request instead
+    assert!(matches!(
+        cluster.send(0, 2, vec![7]).await,
+        Err(MeshError::RouteNotFound(_))
+    ));
+    cluster.run_until_idle().await;
+    assert_eq!(
+        cluster.node(0).routing_table().find_route(&cluster.node_id(2)),
+        Some(vec![cluster.node_id(0), cluster.node_id(1), cluster.node_id(2)])
+    );
+
+    cluster.send(0, 2, vec![7]).await.unwrap();
+    cluster.run_until_idle().await;
+    let delivered = cluster.node(2).poll_delivered(10);
+    assert_eq!(delivered.len(), 1);
+    assert_eq!(delivered[0].payload, vec![7]);
+    assert!(cluster.delivery_errors().is_empty());
+}
+
+#[tokio::test]
+async fn test_route_request_floods_past_unaware_relays() {
+    let cluster = MeshCluster::new(4, &[("mesh.mode", "open")]).await.unwrap();
+    for index in 0..3 {
+        cluster.connect(index, index + 1);
+    }
+
+    // Node 1 knows nothing about node 3 and floods the request on to node 2
+    assert!(cluster.send(0, 3, vec![9]).await.is_err());
+    cluster.run_until_idle().await;
+
+    cluster.send(0, 3, vec![9]).await.unwrap();
+    cluster.run_until_idle().await;
+    assert_eq!(cluster.node(3).poll_delivered(10).len(), 1);
+    assert!(cluster.node(1).poll_delivered(10).is_empty());
+}
+
+#[tokio::test]
+async fn test_noise_handshake_authenticates_and_encrypts() {
+    let config = [("mesh.mode", "open"), ("mesh.noise_handshake", "true")];
+    let cluster = MeshCluster::new(2, &config).await.unwrap();
+
+    // Both sides initiate; the collision resolves to a single session
+    cluster.connect_secure(0, 1).await.unwrap();
+    cluster.run_until_idle().await;
+    assert!(cluster.delivery_errors().is_empty());
+
+    // Peers are known by the node IDs their Hellos bound to the sessions
+    for (node, peer) in [(0, 1), (1, 0)] {
+        let direct: Vec<_> = cluster
+            .node(node)
+            .routing_table()
+            .direct_peer_addresses()
+            .into_iter()
+            .map(|(node_id, _)| node_id)
+            .collect();
+        assert_eq!(direct, vec![cluster.node_id(peer)]);
+    }
+
+    cluster.send(0, 1, vec![1, 2, 3]).await.unwrap();
+    cluster.run_until_idle().await;
+    let delivered = cluster.node(1).poll_delivered(10);
+    assert_eq!(delivered.len(), 1);
+    assert_eq!(delivered[0].payload, vec![1, 2, 3]);
+    assert!(cluster.delivery_errors().is_empty());
+
+    // Plaintext frames are refused once sessions are required
+    let packet = MeshPacketBuilder::new(cluster.node_id(0), cluster.node_id(1))
+        .packet_type(PacketType::BitcoinP2P)
+        .payload(vec![9])
+        .build()
+        .unwrap();
+    let plaintext = serialize_mesh_packet(&packet).unwrap();
+    assert!(matches!(
+        cluster
+            .node(1)
+            .handle_peer_frame(&MeshCluster::node_addr(0), &plaintext)
+            .await,
+        Err(MeshError::HandshakeFailed(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_noise_link_state_authenticates_multi_hop_origins() {
+    let config = [("mesh.mode", "payment_gated"), ("mesh.noise_handshake", "true")];
+    let cluster = MeshCluster::without_key_exchange(3, &config).await.unwrap();
+    cluster.connect_secure(0, 1).await.unwrap();
+    cluster.connect_secure(1, 2).await.unwrap();
+    cluster.run_until_idle().await;
+    assert!(cluster.delivery_errors().is_empty());
+
+    // Node IDs are bound to signing keys with Noise too
+    for index in 0..3 {
+        let key = cluster.node(index).signing_public_key();
+        assert_eq!(cluster.node_id(index), node_id_from_signing_key(&key));
+    }
+    // Nodes 0 and 2 only know each other through node 1
+    assert!(cluster.node(2).peer_keys().get(&cluster.node_id(0)).is_none());
+
+    let link = |index| LinkStateEntry {
+        neighbor: cluster.node_id(index),
+        latency_ms: 20,
+        capacity_sats: 0,
+    };
+    let from_zero = cluster.node(0).originate_link_state(vec![link(1)]).unwrap();
+    let from_one = cluster.node(1).originate_link_state(vec![link(0), link(2)]).unwrap();
+    let from_two = cluster.node(2).originate_link_state(vec![link(1)]).unwrap();
+    for (node, advertisements) in [(0, [&from_one, &from_two]), (2, [&from_one, &from_zero])] {
+        for advertisement in advertisements {
+            assert!(cluster
+                .node(node)
+                .handle_link_state_advertisement(advertisement, cluster.node_id(1))
+                .await
+                .unwrap());
+        }
+    }
+    let key = cluster.node(2).peer_keys().get(&cluster.node_id(0)).unwrap();
+    assert_eq!(key.serialize(), cluster.node(0).signing_public_key());
+
+    // Signed traffic from the multi-hop origin is authenticated and delivered
+    cluster.send(0, 2, bitcoin_version_message()).await.unwrap();
+    cluster.run_until_idle().await;
+    assert!(cluster.delivery_errors().is_empty());
+    assert_eq!(cluster.node(2).poll_delivered(10).len(), 1);
+}
+
+#[tokio::test]
+async fn test_hello_identifies_peers_by_announced_node_id() {
+    let cluster = MeshCluster::new(2, &[("mesh.mode", "open")]).await.unwrap();
+    cluster.connect_secure(0, 1).await.unwrap();
+    cluster.run_until_idle().await;
+    assert!(cluster.delivery_errors().is_empty());
+
+    for (node, peer) in [(0, 1), (1, 0)] {
+        let manager = cluster.node(node);
+        let direct: Vec<_> = manager
+            .routing_table()
+            .direct_peer_addresses()
+            .into_iter()
+            .map(|(node_id, _)| node_id)
+            .collect();
+        assert_eq!(direct, vec![cluster.node_id(peer)]);
+        // The Hello registered the peer's signing key
+        let key = manager.peer_keys().get(&cluster.node_id(peer)).unwrap();
+        assert_eq!(key.serialize(), cluster.node(peer).signing_public_key());
+    }
+
+    cluster.disconnect(0, 1);
+    assert!(cluster.node(0).routing_table().direct_peer_addresses().is_empty());
+}
+
+#[tokio::test]
+async fn test_hello_signed_by_other_key_rejected() {
+    let cluster = MeshCluster::new(2, &[("mesh.mode", "open")]).await.unwrap();
+    let claimed = generate_signing_key();
+    let hello = ControlMessage::Hello {
+        node_id: cluster.node_id(0),
+        pubkey: signing_public_key(&claimed),
+        listen_addrs: Vec::new(),
+        static_key: None,
+    };
+    let mut packet = MeshPacketBuilder::new(cluster.node_id(0), [0u8; 32])
+        .packet_type(PacketType::Control)
+        .payload(hello.encode().unwrap())
+        .ttl(1)
+        .build()
+        .unwrap();
+    packet.sign(&generate_signing_key());
+
+    let frame = serialize_mesh_packet(&packet).unwrap();
+    assert!(matches!(
+        cluster.node(1).handle_peer_frame(&MeshCluster::node_addr(0), &frame).await,
+        Err(MeshError::InvalidSignature(_))
+    ));
+    assert!(cluster.node(1).routing_table().direct_peer_addresses().is_empty());
+}
+
+#[tokio::test]
+async fn test_hello_claiming_another_nodes_id_rejected() {
+    let cluster = MeshCluster::new(2, &[("mesh.mode", "open")]).await.unwrap();
+    // A node the cluster has never exchanged keys with
+    let victim = MeshCluster::new(1, &[("mesh.mode", "open")]).await.unwrap().node_id(0);
+
+    // Validly signed by the impostor's own key, but announcing the victim's ID
+    let impostor = generate_signing_key();
+    let hello = ControlMessage::Hello {
+        node_id: victim,
+        pubkey: signing_public_key(&impostor),
+        listen_addrs: Vec::new(),
+        static_key: None,
+    };
+    let mut packet = MeshPacketBuilder::new(victim, [0u8; 32])
+        .packet_type(PacketType::Control)
+        .payload(hello.encode().unwrap())
+        .ttl(1)
+        .build()
+        .unwrap();
+    packet.sign(&impostor);
+
+    let frame = serialize_mesh_packet(&packet).unwrap();
+    assert!(matches!(
+        cluster.node(1).handle_peer_frame(&MeshCluster::node_addr(0), &frame).await,
+        Err(MeshError::InvalidSignature(_))
+    ));
+    assert!(cluster.node(1).routing_table().direct_peer_addresses().is_empty());
+    assert!(cluster.node(1).peer_keys().get(&victim).is_none());
+}
+
+#[tokio::test]
+async fn test_source_route_overrides_routing_table() {
+    let cluster = MeshCluster::new(4, &[("mesh.mode", "open")]).await.unwrap();
+    for (a, b) in [(0, 1), (1, 2), (2, 3), (0, 3)] {
+        cluster.connect(a, b);
+    }
+
+    // Node 3 is a direct peer of node 0, but the sender pins the long way round
+    let route: Vec<_> = (0..4).map(|index| cluster.node_id(index)).collect();
+    let packet = MeshPacketBuilder::new(route[0], route[3])
+        .packet_type(PacketType::BitcoinP2P)
+        .payload(vec![1, 2, 3])
+        .source_route(route.clone())
+        .ack_requested()
+        .build()
+        .unwrap();
+    cluster.node(0).route_packet(&packet).await.unwrap();
+
+    // Three hops out, one direct hop back for the delivery ack
+    assert_eq!(cluster.run_until_idle().await, 4);
+    assert!(cluster.delivery_errors().is_empty());
+    assert_eq!(cluster.node(3).poll_delivered(10).len(), 1);
+}
+
+#[tokio::test]
+async fn test_source_route_violation_rejected() {
+    let cluster = MeshCluster::new(4, &[("mesh.mode", "open")]).await.unwrap();
+    for (a, b) in [(0, 1), (1, 2), (2, 3)] {
+        cluster.connect(a, b);
+    }
+
+    // Node 1 receives a packet whose route skips it
+    let packet = MeshPacketBuilder::new(cluster.node_id(0), cluster.node_id(3))
+        .packet_type(PacketType::BitcoinP2P)
+        .payload(vec![1])
+        .source_route(vec![cluster.node_id(0), cluster.node_id(2), cluster.node_id(3)])
+        .build()
+        .unwrap();
+    let frame = serialize_mesh_packet(&packet).unwrap();
+    assert!(matches!(
+        cluster.node(1).handle_peer_frame(&MeshCluster::node_addr(0), &frame).await,
+        Err(MeshError::RoutingError(reason)) if reason == "source route violation"
+    ));
+    assert_eq!(cluster.run_until_idle().await, 0);
+}
+
+#[tokio::test]
+async fn test_relay_near_bandwidth_cap_penalizes_then_withdraws_routes() {
+    let cluster = MeshCluster::new(3, &[("mesh.mode", "open"), ("mesh.bandwidth_cap_gb_per_month", "1")])
+        .await
+        .unwrap();
+    cluster.connect(0, 1);
+    cluster.connect(1, 2);
+    let far = cluster.node_id(2);
+    let relay = cluster.node(1);
+
+    relay.advertise_routes().await.unwrap();
+    cluster.run_until_idle().await;
+    let normal_cost = cluster.node(0).routing_table().get_route(&far).unwrap().route_cost;
+
+    // Past the soft threshold the route through the relay costs more
+    relay.bandwidth().record(900_000_000, cluster.now());
+    relay.advertise_routes().await.unwrap();
+    cluster.run_until_idle().await;
+    let soft_cost = cluster.node(0).routing_table().get_route(&far).unwrap().route_cost;
+    assert!(soft_cost > normal_cost);
+
+    // At the cap the relay withdraws it
+    relay.bandwidth().record(100_000_000, cluster.now());
+    relay.advertise_routes().await.unwrap();
+    cluster.run_until_idle().await;
+    assert!(cluster.node(0).routing_table().get_route(&far).is_none());
+}
@@ -0,0 +1,246 @@
+//! Shared test helpers for integration tests
+//!
+//! Provides a recording mock NodeAPI with in-memory storage, a configurable
+//! peer list and a log of every mesh packet sent through it.
+
+#![allow(dead_code)]
+
+use bllvm_node::module::traits::{EventPayload, EventType, ModuleContext, ModuleError, NodeAPI};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+
+/// Mock NodeAPI that records outgoing traffic and keeps storage in memory
+#[derive(Default)]
+pub struct MockNodeAPI {
+    /// Peer addresses reported by get_network_peers
+    pub peers: Vec<String>,
+    /// Node public key reported by get_node_public_key
+    pub node_public_key: Option<Vec<u8>>,
+    /// Mesh packets sent to peers (peer address, bytes)
+    pub sent: Mutex<Vec<(String, Vec<u8>)>>,
+    /// Published events
+    pub published: Mutex<Vec<(EventType, EventPayload)>>,
+    /// Registered RPC endpoints
+    pub rpc_endpoints: Mutex<Vec<String>>,
+    /// Storage trees (tree name -> ordered key/value map)
+    pub storage: Mutex<HashMap<String, BTreeMap<Vec<u8>, Vec<u8>>>>,
+    /// Number of NodeAPI calls made (any method)
+    pub calls: Mutex<u64>,
+    /// Peer addresses whose mesh sends fail (not recorded in `sent`)
+    pub unreachable: Mutex<HashSet<String>>,
+    /// Channel taking mesh sends instead of `sent` (peer address, bytes)
+    pub outbox: Mutex<Option<mpsc::Sender<(String, Vec<u8>)>>>,
+    /// UTXOs reported by get_utxo, by txid (any output index)
+    pub utxos: Mutex<HashMap<bllvm_protocol::Hash, bllvm_protocol::UTXO>>,
+}
+
+impl MockNodeAPI {
+    /// Create a mock with no peers
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a mock reporting the given peer addresses
+    pub fn with_peers(peers: &[&str]) -> Self {
+        Self {
+            peers: peers.iter().map(|p| p.to_string()).collect(),
+            ..Self::default()
+        }
+    }
+
+    /// Create a mock whose mesh sends go to `outbox` instead of `sent`
+    pub fn with_outbox(outbox: mpsc::Sender<(String, Vec<u8>)>) -> Self {
+        Self {
+            outbox: Mutex::new(Some(outbox)),
+            ..Self::default()
+        }
+    }
+
+    /// Take all recorded sends, clearing the log
+    pub fn take_sent(&self) -> Vec<(String, Vec<u8>)> {
+        std::mem::take(&mut *self.sent.lock().unwrap())
+    }
+
+    /// Number of NodeAPI calls made so far
+    pub fn call_count(&self) -> u64 {
+        *self.calls.lock().unwrap()
+    }
+
+    fn record_call(&self) {
+        *self.calls.lock().unwrap() += 1;
+    }
+}
+
+/// Lightning key (hex) that keysend proofs in the tests pay (`destination_pubkey` 02..02)
+///
+/// Tests set it as `mesh.payment.local_payee` together with
+/// `mesh.keysend_verify_settlement = false`, as no Lightning backend is running.
+pub const TEST_LIGHTNING_KEY: &str = "020202020202020202020202020202020202020202020202020202020202020202";
+
+/// Build a module context with the given config entries
+pub fn test_context(config: &[(&str, &str)]) -> ModuleContext {
+    ModuleContext {
+        module_id: "bllvm-mesh-test".to_string(),
+        config: config
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+        data_dir: PathBuf::from("target/test-data/bllvm-mesh"),
+        socket_path: "target/test-data/modules.sock".to_string(),
+    }
+}
+
+#[async_trait::async_trait]
+impl NodeAPI for MockNodeAPI {
+    async fn get_block(&self, _: &bllvm_protocol::Hash) -> Result<Option<bllvm_protocol::Block>, ModuleError> { self.record_call(); Ok(None) }
+    async fn get_block_header(&self, _: &bllvm_protocol::Hash) -> Result<Option<bllvm_protocol::BlockHeader>, ModuleError> { self.record_call(); Ok(None) }
+    async fn get_transaction(&self, _: &bllvm_protocol::Hash) -> Result<Option<bllvm_protocol::Transaction>, ModuleError> { self.record_call(); Ok(None) }
+    async fn has_transaction(&self, _: &bllvm_protocol::Hash) -> Result<bool, ModuleError> { self.record_call(); Ok(false) }
+    async fn get_chain_tip(&self) -> Result<bllvm_protocol::Hash, ModuleError> { self.record_call(); Ok([0u8; 32]) }
+    async fn get_block_height(&self) -> Result<u64, ModuleError> { self.record_call(); Ok(100) }
+    async fn get_utxo(&self, outpoint: &bllvm_protocol::OutPoint) -> Result<Option<bllvm_protocol::UTXO>, ModuleError> {
+        self.record_call();
+        Ok(self.utxos.lock().unwrap().get(&outpoint.hash).cloned())
+    }
+    async fn subscribe_events(&self, _: Vec<EventType>) -> Result<tokio::sync::mpsc::Receiver<bllvm_node::module::ipc::protocol::ModuleMessage>, ModuleError> {
+        let (_tx, rx) = tokio::sync::mpsc::channel(100);
+        Ok(rx)
+    }
+    async fn get_mempool_transactions(&self) -> Result<Vec<bllvm_protocol::Hash>, ModuleError> { self.record_call(); Ok(Vec::new()) }
+    async fn get_mempool_transaction(&self, _: &bllvm_protocol::Hash) -> Result<Option<bllvm_protocol::Transaction>, ModuleError> { self.record_call(); Ok(None) }
+    async fn get_mempool_size(&self) -> Result<bllvm_node::module::traits::MempoolSize, ModuleError> {
+        self.record_call();
+        Ok(bllvm_node::module::traits::MempoolSize { count: 0, size_bytes: 0 })
+    }
+    async fn get_network_stats(&self) -> Result<bllvm_node::module::traits::NetworkStats, ModuleError> {
+        self.record_call();
+        Ok(bllvm_node::module::traits::NetworkStats { connected_peers: self.peers.len(), bytes_sent: 0, bytes_received: 0 })
+    }
+    async fn get_network_peers(&self) -> Result<Vec<bllvm_node::module::traits::PeerInfo>, ModuleError> {
+        self.record_call();
+        Ok(self
+            .peers
+            .iter()
+            .map(|addr| bllvm_node::module::traits::PeerInfo {
+                addr: addr.clone(),
+                transport_type: "tcp".to_string(),
+                services: 0,
+                version: 70016,
+                connected_since: 0,
+            })
+            .collect())
+    }
+    async fn get_chain_info(&self) -> Result<bllvm_node::module::traits::ChainInfo, ModuleError> {
+        self.record_call();
+        Ok(bllvm_node::module::traits::ChainInfo { tip: [0u8; 32], height: 100, difficulty: 1.0 })
+    }
+    async fn get_block_by_height(&self, _: u64) -> Result<Option<bllvm_protocol::Block>, ModuleError> { self.record_call(); Ok(None) }
+    async fn get_lightning_node_url(&self) -> Result<Option<String>, ModuleError> { self.record_call(); Ok(None) }
+    async fn get_lightning_info(&self) -> Result<Option<bllvm_node::module::traits::LightningInfo>, ModuleError> { self.record_call(); Ok(None) }
+    async fn get_payment_state(&self, _: &str) -> Result<Option<bllvm_node::module::traits::PaymentState>, ModuleError> { self.record_call(); Ok(None) }
+    async fn check_transaction_in_mempool(&self, _: &bllvm_protocol::Hash) -> Result<bool, ModuleError> { self.record_call(); Ok(false) }
+    async fn get_fee_estimate(&self, _: u32) -> Result<u64, ModuleError> { self.record_call(); Ok(1) }
+    async fn read_file(&self, _: String) -> Result<Vec<u8>, ModuleError> { Ok(Vec::new()) }
+    async fn write_file(&self, _: String, _: Vec<u8>) -> Result<(), ModuleError> { Ok(()) }
+    async fn delete_file(&self, _: String) -> Result<(), ModuleError> { Ok(()) }
+    async fn list_directory(&self, _: String) -> Result<Vec<String>, ModuleError> { Ok(Vec::new()) }
+    async fn create_directory(&self, _: String) -> Result<(), ModuleError> { Ok(()) }
+    async fn get_file_metadata(&self, _: String) -> Result<bllvm_node::module::ipc::protocol::FileMetadata, ModuleError> {
+        Ok(bllvm_node::module::ipc::protocol::FileMetadata { size: 0, modified: 0, is_dir: false })
+    }
+    async fn storage_open_tree(&self, name: String) -> Result<String, ModuleError> {
+        self.storage.lock().unwrap().entry(name.clone()).or_default();
+        Ok(name)
+    }
+    async fn storage_insert(&self, tree_id: String, key: Vec<u8>, value: Vec<u8>) -> Result<(), ModuleError> {
+        self.storage.lock().unwrap().entry(tree_id).or_default().insert(key, value);
+        Ok(())
+    }
+    async fn storage_get(&self, tree_id: String, key: Vec<u8>) -> Result<Option<Vec<u8>>, ModuleError> {
+        Ok(self.storage.lock().unwrap().get(&tree_id).and_then(|tree| tree.get(&key).cloned()))
+    }
+    async fn storage_remove(&self, tree_id: String, key: Vec<u8>) -> Result<(), ModuleError> {
+        if let Some(tree) = self.storage.lock().unwrap().get_mut(&tree_id) {
+            tree.remove(&key);
+        }
+        Ok(())
+    }
+    async fn storage_contains_key(&self, tree_id: String, key: Vec<u8>) -> Result<bool, ModuleError> {
+        Ok(self.storage.lock().unwrap().get(&tree_id).map(|tree| tree.contains_key(&key)).unwrap_or(false))
+    }
+    async fn storage_iter(&self, tree_id: String) -> Result<Vec<(Vec<u8>, Vec<u8>)>, ModuleError> {
+        Ok(self
+            .storage
+            .lock()
+            .unwrap()
+            .get(&tree_id)
+            .map(|tree| tree.iter().map(|(k, v)| (k.clone(), v.clone())).collect())
+            .unwrap_or_default())
+    }
+    async fn storage_transaction(&self, tree_id: String, operations: Vec<bllvm_node::module::ipc::protocol::StorageOperation>) -> Result<(), ModuleError> {
+        use bllvm_node::module::ipc::protocol::StorageOperation;
+        let mut storage = self.storage.lock().unwrap();
+        let tree = storage.entry(tree_id).or_default();
+        for op in operations {
+            match op {
+                StorageOperation::Insert { key, value } => {
+                    tree.insert(key, value);
+                }
+                StorageOperation::Remove { key } => {
+                    tree.remove(&key);
+                }
+            }
+        }
+        Ok(())
+    }
+    async fn register_rpc_endpoint(&self, method: String, _: String) -> Result<(), ModuleError> {
+        self.rpc_endpoints.lock().unwrap().push(method);
+        Ok(())
+    }
+    async fn unregister_rpc_endpoint(&self, method: &str) -> Result<(), ModuleError> {
+        self.rpc_endpoints.lock().unwrap().retain(|m| m != method);
+        Ok(())
+    }
+    async fn register_timer(&self, _: u64, _: Arc<dyn bllvm_node::module::timers::manager::TimerCallback>) -> Result<bllvm_node::module::timers::manager::TimerId, ModuleError> { Ok(0) }
+    async fn cancel_timer(&self, _: bllvm_node::module::timers::manager::TimerId) -> Result<(), ModuleError> { Ok(()) }
+    async fn schedule_task(&self, _: u64, _: Arc<dyn bllvm_node::module::timers::manager::TaskCallback>) -> Result<bllvm_node::module::timers::manager::TaskId, ModuleError> { Ok(0) }
+    async fn report_metric(&self, _: bllvm_node::module::metrics::manager::Metric) -> Result<(), ModuleError> { Ok(()) }
+    async fn get_module_metrics(&self, _: &str) -> Result<Vec<bllvm_node::module::metrics::manager::Metric>, ModuleError> { Ok(Vec::new()) }
+    async fn initialize_module(&self, _: &str, _: bllvm_node::module::traits::ModuleManifest) -> Result<(), ModuleError> { Ok(()) }
+    async fn discover_modules(&self) -> Result<Vec<bllvm_node::module::traits::ModuleInfo>, ModuleError> { Ok(Vec::new()) }
+    async fn get_module_info(&self, _: &str) -> Result<Option<bllvm_node::module::traits::ModuleInfo>, ModuleError> { Ok(None) }
+    async fn is_module_available(&self, _: &str) -> Result<bool, ModuleError> { Ok(false) }
+    async fn publish_event(&self, event_type: EventType, payload: EventPayload) -> Result<(), ModuleError> {
+        self.published.lock().unwrap().push((event_type, payload));
+        Ok(())
+    }
+    async fn call_module(&self, _: Option<&str>, _: &str, _: Vec<u8>) -> Result<Vec<u8>, ModuleError> { Ok(Vec::new()) }
+    async fn register_module_api(&self, _: Vec<String>, _: u32) -> Result<(), ModuleError> { Ok(()) }
+    async fn unregister_module_api(&self) -> Result<(), ModuleError> { Ok(()) }
+    async fn get_module_health(&self, _: &str) -> Result<Option<bllvm_node::module::process::monitor::ModuleHealth>, ModuleError> { Ok(None) }
+    async fn get_all_module_health(&self) -> Result<Vec<(String, bllvm_node::module::process::monitor::ModuleHealth)>, ModuleError> { Ok(Vec::new()) }
+    async fn report_module_health(&self, _: bllvm_node::module::process::monitor::ModuleHealth) -> Result<(), ModuleError> { Ok(()) }
+    async fn send_mesh_packet_to_module(&self, _: &str, _: Vec<u8>, _: String) -> Result<(), ModuleError> { Ok(()) }
+    async fn send_mesh_packet_to_peer(&self, peer_addr: String, packet_data: Vec<u8>) -> Result<(), ModuleError> {
+        self.record_call();
+        if self.unreachable.lock().unwrap().contains(&peer_addr) {
+            return Err(ModuleError::OperationError(format!("Peer unreachable: {}", peer_addr)));
+        }
+        let outbox = self.outbox.lock().unwrap().clone();
+        match outbox {
+            Some(outbox) => outbox
+                .send((peer_addr, packet_data))
+                .await
+                .map_err(|e| ModuleError::OperationError(format!("Outbox closed: {}", e))),
+            None => {
+                self.sent.lock().unwrap().push((peer_addr, packet_data));
+                Ok(())
+            }
+        }
+    }
+    async fn send_stratum_v2_message_to_peer(&self, _: String, _: Vec<u8>) -> Result<(), ModuleError> { Ok(()) }
+    async fn get_node_public_key(&self) -> Result<Option<Vec<u8>>, ModuleError> { self.record_call(); Ok(self.node_public_key.clone()) }
+    async fn get_event_publisher(&self) -> Result<Option<Arc<bllvm_node::node::event_publisher::EventPublisher>>, ModuleError> { Ok(None) }
+}
@@ -0,0 +1,154 @@
+//! Tests for delivery acks of paid and ack-requested packets
+
+mod common;
+
+use bllvm_mesh::clock::{Clock, ManualClock};
+use bllvm_mesh::delivery_tracker::{DeliveryStatus, DEFAULT_ACK_TIMEOUT_SECS};
+use bllvm_mesh::manager::MeshManager;
+use bllvm_mesh::network::deserialize_mesh_packet;
+use bllvm_mesh::packet::{MeshPacket, MeshPacketBuilder, PacketType};
+use bllvm_mesh::payment_proof::PaymentProof;
+use bllvm_mesh::routing::NodeId;
+use bllvm_mesh::rpc::RPC_GET_DELIVERY;
+use common::{test_context, MockNodeAPI};
+use serde_json::json;
+use std::sync::Arc;
+
+const SENDER_ADDR: &str = "10.0.0.1:8334";
+const RELAY_ADDR: &str = "10.0.0.2:8334";
+const DESTINATION_ADDR: &str = "10.0.0.3:8334";
+
+async fn node(clock: Arc<ManualClock>) -> (MeshManager, Arc<MockNodeAPI>) {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let ctx = test_context(&[("mesh.enabled", "true"), ("mesh.mode", "open")]);
+    let manager = MeshManager::with_clock(&ctx, node_api.clone(), clock).await.unwrap();
+    (manager, node_api)
+}
+
+fn proof() -> PaymentProof {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    PaymentProof::Lightning {
+        invoice: "lnbc1test".to_string(),
+        preimage: [7u8; 32],
+        amount_msats: 1_000,
+        timestamp: now,
+        expires_at: now + 3600,
+    }
+}
+
+/// Take the single frame a node sent, with the address it went to
+fn single_sent(node_api: &MockNodeAPI) -> (String, MeshPacket) {
+    let mut sent = node_api.take_sent();
+    assert_eq!(sent.len(), 1);
+    let (address, frame) = sent.remove(0);
+    (address, deserialize_mesh_packet(&frame).unwrap())
+}
+
+#[tokio::test]
+async fn test_ack_retraces_route_and_confirms_delivery() {
+    let clock = Arc::new(ManualClock::starting_now());
+    let (sender, sender_api) = node(clock.clone()).await;
+    let (relay, relay_api) = node(clock.clone()).await;
+    let (destination, destination_api) = node(clock.clone()).await;
+    let (sender_id, relay_id, destination_id) = (sender.node_id(), relay.node_id(), destination.node_id());
+    sender.routing_table().add_direct_peer(relay_id, RELAY_ADDR.as_bytes().to_vec());
+    sender.routing_table().add_route(bllvm_mesh::routing::RoutingEntry {
+        node_id: destination_id,
+        direct_address: None,
+        next_hop: Some(relay_id),
+        route_path: vec![sender_id, relay_id, destination_id],
+        route_cost: 100,
+        last_updated: clock.now_secs(),
+        quality_score: 0.8,
+    });
+    relay.routing_table().add_direct_peer(sender_id, SENDER_ADDR.as_bytes().to_vec());
+    relay.routing_table().add_direct_peer(destination_id, DESTINATION_ADDR.as_bytes().to_vec());
+    destination.routing_table().add_direct_peer(relay_id, RELAY_ADDR.as_bytes().to_vec());
+
+    let sequence = sender.send_packet(destination_id, vec![1, 2, 3], Some(proof())).await.unwrap();
+    assert_eq!(sender.delivery_status(sequence), Some((DeliveryStatus::Pending, destination_id)));
+
+    let (_, to_relay) = single_sent(&sender_api);
+    relay.handle_incoming_packet(&to_relay).await.unwrap();
+    let (_, to_destination) = single_sent(&relay_api);
+    destination.handle_incoming_packet(&to_destination).await.unwrap();
+    assert_eq!(destination.poll_delivered(10).len(), 1);
+
+    // The ack is free, source-routed along the reverse path
+    let (address, ack) = single_sent(&destination_api);
+    assert_eq!(address, RELAY_ADDR);
+    assert_eq!(ack.packet_type, PacketType::Ack);
+    assert!(ack.payment_proof.is_none());
+    assert!(ack.source_routed);
+    assert_eq!(ack.fixed_route, vec![destination_id, relay_id, sender_id]);
+
+    relay.handle_incoming_packet(&ack).await.unwrap();
+    let (address, ack) = single_sent(&relay_api);
+    assert_eq!(address, SENDER_ADDR);
+    sender.handle_incoming_packet(&ack).await.unwrap();
+    assert!(sender.poll_delivered(10).is_empty());
+
+    assert_eq!(sender.delivery_status(sequence), Some((DeliveryStatus::Confirmed, destination_id)));
+    assert_eq!(sender.get_stats().await.deliveries.confirmed, 1);
+    let status = sender
+        .handle_rpc_call(RPC_GET_DELIVERY, &json!({ "sequence": sequence }))
+        .await
+        .unwrap();
+    assert_eq!(status["status"], "confirmed");
+}
+
+#[tokio::test]
+async fn test_broken_reverse_route_falls_back_to_table() {
+    const SENDER: NodeId = [1u8; 32];
+    const RELAY: NodeId = [2u8; 32];
+    let clock = Arc::new(ManualClock::starting_now());
+    let (destination, destination_api) = node(clock).await;
+    destination.routing_table().add_direct_peer(SENDER, SENDER_ADDR.as_bytes().to_vec());
+
+    // Arrived via a relay the destination cannot reach any more
+    let mut packet = MeshPacketBuilder::new(SENDER, destination.node_id())
+        .packet_type(PacketType::Paid)
+        .payload(vec![1, 2, 3])
+        .payment_proof(proof())
+        .sequence(9)
+        .build()
+        .unwrap();
+    packet.add_to_route(RELAY);
+    destination.handle_incoming_packet(&packet).await.unwrap();
+
+    let (address, ack) = single_sent(&destination_api);
+    assert_eq!(address, SENDER_ADDR);
+    assert_eq!(ack.packet_type, PacketType::Ack);
+    assert!(!ack.source_routed);
+}
+
+#[tokio::test]
+async fn test_unacked_delivery_times_out() {
+    const DESTINATION: NodeId = [3u8; 32];
+    let clock = Arc::new(ManualClock::starting_now());
+    let (sender, _) = node(clock.clone()).await;
+    sender.routing_table().add_direct_peer(DESTINATION, DESTINATION_ADDR.as_bytes().to_vec());
+
+    let sequence = sender.send_packet(DESTINATION, vec![1], Some(proof())).await.unwrap();
+    clock.advance(DEFAULT_ACK_TIMEOUT_SECS);
+    assert_eq!(sender.delivery_status(sequence), Some((DeliveryStatus::TimedOut, DESTINATION)));
+    assert_eq!(sender.get_stats().await.deliveries.timed_out, 1);
+
+    // Free packets are not tracked unless they ask for an ack
+    let free = sender.send_packet(DESTINATION, vec![2], None).await.unwrap();
+    assert_eq!(sender.delivery_status(free), None);
+    let mut requested = MeshPacketBuilder::new(sender.node_id(), DESTINATION)
+        .packet_type(PacketType::BitcoinP2P)
+        .payload(vec![3])
+        .ack_requested()
+        .build()
+        .unwrap();
+    let requested = sender.originate(&mut requested).await.unwrap();
+    assert_eq!(
+        sender.delivery_status(requested),
+        Some((DeliveryStatus::Pending, DESTINATION))
+    );
+}
@@ -0,0 +1,102 @@
+//! Tests for at-most-once local delivery
+
+mod common;
+
+use bllvm_mesh::delivery_tracker::AckPayload;
+use bllvm_mesh::manager::MeshManager;
+use bllvm_mesh::network::deserialize_mesh_packet;
+use bllvm_mesh::packet::{MeshPacketBuilder, PacketType};
+use common::{test_context, MockNodeAPI};
+use std::sync::Arc;
+
+const PEER_ADDR: &str = "10.0.0.7:8334";
+
+async fn start_manager(node_api: &Arc<MockNodeAPI>) -> MeshManager {
+    let ctx = test_context(&[("mesh.enabled", "true"), ("mesh.mode", "open")]);
+    let manager = MeshManager::new(&ctx, node_api.clone()).await.unwrap();
+    manager
+        .routing_table()
+        .add_direct_peer([7u8; 32], PEER_ADDR.as_bytes().to_vec());
+    manager
+}
+
+/// Decode the delivery acks among recorded sends
+fn take_acks(node_api: &MockNodeAPI) -> Vec<AckPayload> {
+    node_api
+        .take_sent()
+        .into_iter()
+        .filter(|(addr, _)| addr == PEER_ADDR)
+        .map(|(_, data)| deserialize_mesh_packet(&data).unwrap())
+        .filter(|packet| packet.packet_type == PacketType::Ack)
+        .map(|packet| AckPayload::decode(&packet.payload).unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn test_retransmission_after_restart_delivered_once() {
+    let node_api = Arc::new(MockNodeAPI::new());
+
+    let manager = start_manager(&node_api).await;
+    let packet = MeshPacketBuilder::new([7u8; 32], manager.node_id())
+        .packet_type(PacketType::BitcoinP2P)
+        .payload(vec![1, 2, 3])
+        .sequence(42)
+        .ack_requested()
+        .build()
+        .unwrap();
+
+    manager.handle_incoming_packet(&packet).await.unwrap();
+    assert_eq!(manager.poll_delivered(10).len(), 1);
+    let first_acks = take_acks(&node_api);
+
+    // Crash before the ack is seen; the sender retransmits to the restarted node
+    drop(manager);
+    let restarted = start_manager(&node_api).await;
+    restarted.handle_incoming_packet(&packet).await.unwrap();
+    assert!(restarted.poll_delivered(10).is_empty());
+    let second_acks = take_acks(&node_api);
+
+    // Both attempts were acked
+    assert_eq!(first_acks.len(), 1);
+    assert_eq!(second_acks, first_acks);
+    assert_eq!(first_acks[0].sequence, 42);
+}
+
+#[tokio::test]
+async fn test_distinct_sequences_both_delivered() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let manager = start_manager(&node_api).await;
+
+    for sequence in [1, 2] {
+        let packet = MeshPacketBuilder::new([7u8; 32], manager.node_id())
+            .packet_type(PacketType::BitcoinP2P)
+            .payload(vec![9])
+            .sequence(sequence)
+            .ack_requested()
+            .build()
+            .unwrap();
+        manager.handle_incoming_packet(&packet).await.unwrap();
+    }
+
+    assert_eq!(manager.poll_delivered(10).len(), 2);
+    assert_eq!(take_acks(&node_api).len(), 2);
+}
+
+#[tokio::test]
+async fn test_free_packet_without_ack_request_not_acked() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let manager = start_manager(&node_api).await;
+
+    let packet = MeshPacketBuilder::new([7u8; 32], manager.node_id())
+        .packet_type(PacketType::BitcoinP2P)
+        .payload(vec![1])
+        .sequence(5)
+        .build()
+        .unwrap();
+    manager.handle_incoming_packet(&packet).await.unwrap();
+    // A retransmission is dropped without an ack as well
+    manager.handle_incoming_packet(&packet).await.unwrap();
+
+    assert_eq!(manager.poll_delivered(10).len(), 1);
+    assert!(node_api.take_sent().is_empty());
+}
@@ -0,0 +1,107 @@
+//! Tests for end-to-end encrypted payloads
+
+mod common;
+
+use bllvm_mesh::manager::MeshManager;
+use bllvm_mesh::network::deserialize_mesh_packet;
+use bllvm_mesh::packet::{MeshPacketBuilder, PacketType};
+use bllvm_mesh::payment_proof::PaymentProof;
+use bllvm_mesh::routing::RoutingEntry;
+use bllvm_mesh::routing_policy::RoutingPolicy;
+use common::{test_context, MockNodeAPI};
+use std::sync::Arc;
+
+const SECRET: &[u8] = b"application data the relay must not see";
+
+async fn node(mode: &str) -> (MeshManager, Arc<MockNodeAPI>) {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let ctx = test_context(&[("mesh.enabled", "true"), ("mesh.mode", mode)]);
+    let manager = MeshManager::new(&ctx, node_api.clone()).await.unwrap();
+    (manager, node_api)
+}
+
+fn proof() -> PaymentProof {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    PaymentProof::Lightning {
+        invoice: "lnbc1test".to_string(),
+        preimage: [7u8; 32],
+        amount_msats: 1_000,
+        timestamp: now,
+        expires_at: now + 3600,
+    }
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|window| window == needle)
+}
+
+#[tokio::test]
+async fn test_relay_forwards_ciphertext_destination_decrypts() {
+    let (sender, sender_api) = node("open").await;
+    let (relay, relay_api) = node("open").await;
+    let (destination, _) = node("open").await;
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    sender.routing_table().add_direct_peer(relay.node_id(), b"10.0.0.2:8334".to_vec());
+    sender.routing_table().add_route(RoutingEntry {
+        node_id: destination.node_id(),
+        direct_address: None,
+        next_hop: Some(relay.node_id()),
+        route_path: vec![sender.node_id(), relay.node_id(), destination.node_id()],
+        route_cost: 100,
+        last_updated: now,
+        quality_score: 0.8,
+    });
+    relay
+        .routing_table()
+        .add_direct_peer(destination.node_id(), b"10.0.0.3:8334".to_vec());
+
+    let packet = MeshPacketBuilder::new(sender.node_id(), destination.node_id())
+        .sealed_payload(SECRET, &destination.onion_public_key())
+        .unwrap()
+        .payment_proof(proof())
+        .build()
+        .unwrap();
+    sender.route_packet(&packet).await.unwrap();
+
+    let (_, to_relay) = sender_api.take_sent().remove(0);
+    assert!(!contains(&to_relay, SECRET));
+    relay
+        .handle_incoming_packet(&deserialize_mesh_packet(&to_relay).unwrap())
+        .await
+        .unwrap();
+    assert!(relay.poll_delivered(10).is_empty());
+
+    // The relay cannot open the payload and forwards it unchanged
+    let (_, to_destination) = relay_api.take_sent().remove(0);
+    let forwarded = deserialize_mesh_packet(&to_destination).unwrap();
+    assert_eq!(forwarded.payload, packet.payload);
+    assert!(forwarded.decrypt_payload(&bllvm_mesh::onion::OnionKey::generate()).is_err());
+
+    destination.handle_incoming_packet(&forwarded).await.unwrap();
+    let delivered = destination.poll_delivered(10);
+    assert_eq!(delivered.len(), 1);
+    assert_eq!(delivered[0].payload, SECRET);
+}
+
+#[tokio::test]
+async fn test_encrypted_packets_classified_by_type() {
+    let (manager, _) = node("payment_gated").await;
+
+    // Ciphertext that happens to start with Bitcoin magic is still paid traffic
+    let mut packet = MeshPacketBuilder::new([4u8; 32], [5u8; 32])
+        .packet_type(PacketType::PaidEncrypted)
+        .payload(vec![0xf9, 0xbe, 0xb4, 0xd9, 0, 0])
+        .payment_proof(proof())
+        .build()
+        .unwrap();
+    assert_eq!(manager.packet_routing_policy(&packet), RoutingPolicy::PaymentRequired);
+
+    packet.packet_type = PacketType::BitcoinP2P;
+    assert_eq!(manager.packet_routing_policy(&packet), RoutingPolicy::Free);
+}
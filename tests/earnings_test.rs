@@ -0,0 +1,193 @@
+//! Tests for the routing earnings ledger
+
+mod common;
+
+use bllvm_mesh::earnings::{EarningsEntry, EarningsRole, PaymentLedger, SECONDS_PER_DAY};
+use bllvm_mesh::error::MeshError;
+use bllvm_mesh::manager::MeshManager;
+use bllvm_mesh::packet::{MeshPacket, MeshPacketBuilder, PacketType};
+use bllvm_mesh::payment_proof::PaymentProof;
+use bllvm_mesh::routing::{NodeId, RoutingEntry};
+use bllvm_mesh::rpc::RPC_GET_EARNINGS;
+use common::{test_context, MockNodeAPI, TEST_LIGHTNING_KEY};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SOURCE: NodeId = [5u8; 32];
+const PEER: NodeId = [2u8; 32];
+const DESTINATION: NodeId = [3u8; 32];
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+/// Manager routing to DESTINATION through PEER
+async fn start_manager(node_api: &Arc<MockNodeAPI>) -> MeshManager {
+    let ctx = test_context(&[
+        ("mesh.enabled", "true"),
+        ("mesh.mode", "payment_gated"),
+        ("mesh.keysend_verify_settlement", "false"),
+        ("mesh.payment.local_payee", TEST_LIGHTNING_KEY),
+    ]);
+    let manager = MeshManager::new(&ctx, node_api.clone()).await.unwrap();
+    manager.routing_table().add_direct_peer(PEER, b"10.0.0.2:8334".to_vec());
+    manager.routing_table().add_route(RoutingEntry {
+        node_id: DESTINATION,
+        direct_address: None,
+        next_hop: Some(PEER),
+        route_path: vec![manager.node_id(), PEER, DESTINATION],
+        route_cost: 200,
+        last_updated: now(),
+        quality_score: 0.8,
+    });
+    manager
+}
+
+fn keysend_proof(preimage_byte: u8, amount_sats: u64) -> PaymentProof {
+    let preimage = [preimage_byte; 32];
+    PaymentProof::Keysend {
+        payment_hash: Sha256::digest(preimage).into(),
+        preimage,
+        amount_msats: amount_sats * 1000,
+        timestamp: now(),
+        custom_tlv: Vec::new(),
+        destination_pubkey: vec![2u8; 33],
+    }
+}
+
+fn paid_packet(source: NodeId, sequence: u64, proof: PaymentProof) -> MeshPacket {
+    MeshPacketBuilder::new(source, DESTINATION)
+        .packet_type(PacketType::BitcoinP2P)
+        .payload(vec![0x12; 100])
+        .payment_proof(proof)
+        .sequence(sequence)
+        .build()
+        .unwrap()
+}
+
+fn entry(proof_byte: u8, timestamp: u64, fee_sats: u64) -> EarningsEntry {
+    EarningsEntry {
+        proof_hash: [proof_byte; 32],
+        source: SOURCE,
+        destination: DESTINATION,
+        amount_sats: fee_sats * 10,
+        role: EarningsRole::Intermediate,
+        fee_sats,
+        timestamp,
+    }
+}
+
+#[tokio::test]
+async fn test_relayed_payment_recorded_once() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let manager = start_manager(&node_api).await;
+    let proof = keysend_proof(1, 100);
+
+    manager.route_packet(&paid_packet(SOURCE, 1, proof.clone())).await.unwrap();
+
+    // Path SOURCE -> this node -> PEER -> DESTINATION: the two relays split 30%
+    let report = manager.earnings(0, now(), 10);
+    assert_eq!(report.entries.len(), 1);
+    let recorded = &report.entries[0];
+    assert_eq!(recorded.proof_hash, proof.hash());
+    assert_eq!((recorded.source, recorded.destination), (SOURCE, DESTINATION));
+    assert_eq!(recorded.role, EarningsRole::Intermediate);
+    assert_eq!(recorded.amount_sats, 100);
+    assert_eq!(recorded.fee_sats, 15);
+
+    // The same proof again is refused and not counted
+    let result = manager.route_packet(&paid_packet(SOURCE, 2, proof)).await;
+    assert!(matches!(result, Err(MeshError::ReplayDetected(_))));
+    assert_eq!(manager.earnings(0, now(), 10).totals(), (1, 100, 15));
+}
+
+#[tokio::test]
+async fn test_own_payment_recorded_as_source() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let manager = start_manager(&node_api).await;
+
+    manager
+        .route_packet(&paid_packet(manager.node_id(), 1, keysend_proof(1, 100)))
+        .await
+        .unwrap();
+
+    let report = manager.earnings(0, now(), 10);
+    assert_eq!(report.entries[0].role, EarningsRole::Source);
+    assert_eq!(report.entries[0].fee_sats, 10);
+}
+
+#[tokio::test]
+async fn test_ledger_deduplicates_and_survives_restart() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let day = now() / SECONDS_PER_DAY * SECONDS_PER_DAY;
+
+    let ledger = PaymentLedger::load(node_api.clone(), 30, day).await.unwrap();
+    assert!(ledger.record(entry(1, day + 10, 5)).await);
+    assert!(!ledger.record(entry(1, day + 20, 5)).await);
+    assert!(ledger.record(entry(2, day + 30, 7)).await);
+    drop(ledger);
+
+    let restarted = PaymentLedger::load(node_api, 30, day + 60).await.unwrap();
+    assert!(restarted.contains(&[1u8; 32]));
+    assert!(!restarted.record(entry(2, day + 40, 7)).await);
+
+    let report = restarted.report(day, day + 60, 10);
+    assert_eq!(report.totals(), (2, 120, 12));
+    assert_eq!(report.days.len(), 1);
+    assert_eq!(report.days[0].day_start, day);
+    assert_eq!(report.entries[0].proof_hash, [2u8; 32], "newest first");
+}
+
+#[tokio::test]
+async fn test_entries_pruned_daily_totals_kept() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let today = now() / SECONDS_PER_DAY * SECONDS_PER_DAY;
+    let old_day = today - 10 * SECONDS_PER_DAY;
+
+    let ledger = PaymentLedger::load(node_api.clone(), 7, today).await.unwrap();
+    ledger.record(entry(1, old_day + 100, 5)).await;
+    ledger.record(entry(2, today + 100, 7)).await;
+
+    // Ranges select whole days for totals, exact times for entries
+    let report = ledger.report(old_day + 200, today + 200, 10);
+    assert_eq!(report.totals(), (2, 120, 12));
+    assert_eq!(report.entries.len(), 1);
+
+    ledger.cleanup_expired(today + 200).await;
+    assert!(!ledger.contains(&[1u8; 32]));
+    assert!(ledger.contains(&[2u8; 32]));
+    drop(ledger);
+
+    let restarted = PaymentLedger::load(node_api, 7, today + 200).await.unwrap();
+    let report = restarted.report(0, today + 200, 10);
+    assert_eq!(report.days.len(), 2);
+    assert_eq!(report.totals(), (2, 120, 12));
+    assert_eq!(report.entries.len(), 1);
+}
+
+#[tokio::test]
+async fn test_earnings_rpc() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let manager = start_manager(&node_api).await;
+    manager.route_packet(&paid_packet(SOURCE, 1, keysend_proof(1, 100))).await.unwrap();
+
+    let earnings = manager.handle_rpc_call(RPC_GET_EARNINGS, &json!({})).await.unwrap();
+    assert_eq!(earnings["payments"], 1);
+    assert_eq!(earnings["amount_sats"], 100);
+    assert_eq!(earnings["fee_sats"], 15);
+    assert_eq!(earnings["entries"][0]["role"], "intermediate");
+    assert_eq!(earnings["entries"][0]["source"], hex::encode(SOURCE));
+
+    // Nothing before the payment
+    let earlier = manager
+        .handle_rpc_call(RPC_GET_EARNINGS, &json!({ "from": 0, "to": 1_000 }))
+        .await
+        .unwrap();
+    assert_eq!(earlier["payments"], 0);
+    assert!(manager
+        .handle_rpc_call(RPC_GET_EARNINGS, &json!({ "from": 10, "to": 1 }))
+        .await
+        .is_err());
+}
@@ -0,0 +1,255 @@
+//! Tests for dispatching node events to the mesh manager
+
+mod common;
+
+use bllvm_mesh::manager::MeshManager;
+use bllvm_mesh::network::deserialize_mesh_packet;
+use bllvm_mesh::packet::{MeshPacketBuilder, PacketType};
+use bllvm_node::module::ipc::protocol::{EventMessage, ModuleMessage};
+use bllvm_node::module::traits::{EventPayload, EventType};
+use common::{test_context, MockNodeAPI};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+const PEER_A: &str = "10.0.0.1:8334";
+const PEER_B: &str = "10.0.0.2:8334";
+const PEER_C: &str = "10.0.0.3:8334";
+
+fn peer_connected(peer_addr: &str) -> ModuleMessage {
+    ModuleMessage::Event(EventMessage {
+        event_type: EventType::PeerConnected,
+        payload: EventPayload::PeerConnected {
+            peer_addr: peer_addr.to_string(),
+            transport_type: "tcp".to_string(),
+            services: 0,
+            version: 70016,
+        },
+    })
+}
+
+fn peer_disconnected(peer_addr: &str) -> ModuleMessage {
+    ModuleMessage::Event(EventMessage {
+        event_type: EventType::PeerDisconnected,
+        payload: EventPayload::PeerDisconnected {
+            peer_addr: peer_addr.to_string(),
+            reason: "closed".to_string(),
+        },
+    })
+}
+
+fn message_received(peer_addr: &str, data: Vec<u8>) -> ModuleMessage {
+    ModuleMessage::Event(EventMessage {
+        event_type: EventType::MessageReceived,
+        payload: EventPayload::MessageReceived {
+            peer_addr: peer_addr.to_string(),
+            data,
+        },
+    })
+}
+
+async fn node() -> (MeshManager, Arc<MockNodeAPI>) {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let ctx = test_context(&[("mesh.enabled", "true"), ("mesh.mode", "open")]);
+    let manager = MeshManager::new(&ctx, node_api.clone()).await.unwrap();
+    (manager, node_api)
+}
+
+/// The Hello a node sends when told a peer connected (at `PEER_A`)
+async fn hello_from(manager: &MeshManager, node_api: &MockNodeAPI) -> Vec<u8> {
+    manager.handle_event(&peer_connected(PEER_A), node_api).await.unwrap();
+    node_api.take_sent().pop().unwrap().1
+}
+
+/// Feed events through `run_event_loop` until the stream ends
+async fn run(manager: &MeshManager, node_api: &MockNodeAPI, events: Vec<ModuleMessage>) {
+    let (tx, mut rx) = mpsc::channel(events.len().max(1));
+    for event in events {
+        tx.send(event).await.unwrap();
+    }
+    drop(tx);
+    manager.run_event_loop(&mut rx, node_api).await;
+}
+
+fn direct_peers(manager: &MeshManager) -> Vec<[u8; 32]> {
+    manager
+        .routing_table()
+        .direct_peer_addresses()
+        .into_iter()
+        .map(|(node_id, _)| node_id)
+        .collect()
+}
+
+#[tokio::test]
+async fn test_events_add_and_remove_direct_peers() {
+    let (manager, node_api) = node().await;
+    let (peer, peer_api) = node().await;
+    let hello = hello_from(&peer, &peer_api).await;
+
+    run(
+        &manager,
+        &node_api,
+        vec![peer_connected(PEER_B), message_received(PEER_B, hello)],
+    )
+    .await;
+    assert_eq!(direct_peers(&manager), vec![peer.node_id()]);
+
+    // Our own Hello went out in reply to the connect
+    assert!(node_api.take_sent().iter().any(|(addr, _)| addr == PEER_B));
+
+    run(&manager, &node_api, vec![peer_disconnected(PEER_B)]).await;
+    assert!(direct_peers(&manager).is_empty());
+}
+
+#[tokio::test]
+async fn test_events_of_one_peer_stay_in_order() {
+    let (manager, node_api) = node().await;
+    let (peer_b, peer_b_api) = node().await;
+    let (peer_c, peer_c_api) = node().await;
+    let hello_b = hello_from(&peer_b, &peer_b_api).await;
+    let hello_c = hello_from(&peer_c, &peer_c_api).await;
+
+    // One batch, two peers interleaved: B ends disconnected, C connected
+    run(
+        &manager,
+        &node_api,
+        vec![
+            peer_connected(PEER_B),
+            peer_connected(PEER_C),
+            message_received(PEER_B, hello_b),
+            message_received(PEER_C, hello_c),
+            peer_disconnected(PEER_B),
+        ],
+    )
+    .await;
+    assert_eq!(direct_peers(&manager), vec![peer_c.node_id()]);
+}
+
+#[tokio::test]
+async fn test_event_errors_are_not_fatal() {
+    let (manager, node_api) = node().await;
+    let (peer, peer_api) = node().await;
+    let hello = hello_from(&peer, &peer_api).await;
+
+    // A malformed mesh frame fails, later events are still handled
+    run(
+        &manager,
+        &node_api,
+        vec![
+            message_received(PEER_C, b"MESH\x02garbage".to_vec()),
+            message_received(PEER_B, hello),
+        ],
+    )
+    .await;
+    assert_eq!(direct_peers(&manager), vec![peer.node_id()]);
+}
+
+#[tokio::test]
+async fn test_mesh_packet_in_message_received_is_delivered() {
+    let (manager, node_api) = node().await;
+    let (peer, peer_api) = node().await;
+
+    // Exchange Hellos so each side knows the other's node ID and key
+    let hello_a = hello_from(&manager, &node_api).await;
+    let hello_b = hello_from(&peer, &peer_api).await;
+    run(&manager, &node_api, vec![message_received(PEER_B, hello_b)]).await;
+    run(&peer, &peer_api, vec![message_received(PEER_A, hello_a)]).await;
+
+    let packet = MeshPacketBuilder::new(peer.node_id(), manager.node_id())
+        .packet_type(PacketType::BitcoinP2P)
+        .payload(vec![7])
+        .build()
+        .unwrap();
+    peer.route_packet(&packet).await.unwrap();
+    let (addr, frame) = peer_api.take_sent().pop().unwrap();
+    assert_eq!(addr, PEER_A);
+
+    run(&manager, &node_api, vec![message_received(PEER_B, frame)]).await;
+    let delivered = manager.poll_delivered(10);
+    assert_eq!(delivered.len(), 1);
+    assert_eq!(delivered[0].payload, vec![7]);
+    assert_eq!(manager.metrics().malformed_packets(), 0);
+}
+
+#[tokio::test]
+async fn test_malformed_mesh_frames_counted_and_penalized() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let ctx = test_context(&[
+        ("mesh.enabled", "true"),
+        ("mesh.mode", "open"),
+        ("mesh.malformed_disconnect_threshold", "2"),
+    ]);
+    let manager = MeshManager::new(&ctx, node_api.clone()).await.unwrap();
+    let (peer, peer_api) = node().await;
+    let hello = hello_from(&peer, &peer_api).await;
+    run(&manager, &node_api, vec![message_received(PEER_B, hello)]).await;
+    assert_eq!(direct_peers(&manager), vec![peer.node_id()]);
+
+    // Non-mesh traffic is ignored without counting
+    run(&manager, &node_api, vec![message_received(PEER_B, b"\x01\x02not mesh".to_vec())]).await;
+    assert_eq!(manager.metrics().malformed_packets(), 0);
+
+    run(&manager, &node_api, vec![message_received(PEER_B, b"MESH\x02garbage".to_vec())]).await;
+    assert_eq!(manager.metrics().malformed_packets(), 1);
+    assert_eq!(direct_peers(&manager), vec![peer.node_id()]);
+
+    run(&manager, &node_api, vec![message_received(PEER_B, b"MESH\x02garbage".to_vec())]).await;
+    assert_eq!(manager.metrics().malformed_packets(), 2);
+    assert!(direct_peers(&manager).is_empty());
+}
+
+#[tokio::test]
+async fn test_mesh_packet_in_message_received_is_forwarded() {
+    let (manager, node_api) = node().await;
+    let (peer, peer_api) = node().await;
+    let hello_a = hello_from(&manager, &node_api).await;
+    let hello_b = hello_from(&peer, &peer_api).await;
+    run(&manager, &node_api, vec![message_received(PEER_B, hello_b)]).await;
+    run(&peer, &peer_api, vec![message_received(PEER_A, hello_a)]).await;
+    node_api.take_sent();
+
+    // The peer reaches the destination through us
+    let destination = [9u8; 32];
+    manager.routing_table().add_direct_peer(destination, PEER_C.as_bytes().to_vec());
+    peer.routing_table().add_direct_peer(destination, PEER_A.as_bytes().to_vec());
+
+    let packet = MeshPacketBuilder::new(peer.node_id(), destination)
+        .packet_type(PacketType::BitcoinP2P)
+        .payload(vec![7])
+        .build()
+        .unwrap();
+    peer.route_packet(&packet).await.unwrap();
+    let (_, frame) = peer_api.take_sent().pop().unwrap();
+
+    run(&manager, &node_api, vec![message_received(PEER_B, frame)]).await;
+    let sent = node_api.take_sent();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].0, PEER_C);
+    let forwarded = deserialize_mesh_packet(&sent[0].1).unwrap();
+    assert_eq!(forwarded.payload, vec![7]);
+    assert_eq!(forwarded.route, vec![peer.node_id(), manager.node_id(), destination]);
+    assert!(manager.poll_delivered(10).is_empty());
+}
+
+#[tokio::test]
+async fn test_batches_capped_at_max_size() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let ctx = test_context(&[
+        ("mesh.enabled", "true"),
+        ("mesh.mode", "open"),
+        ("mesh.batch.max_size", "2"),
+        ("mesh.batch.max_concurrent", "1"),
+    ]);
+    let manager = MeshManager::new(&ctx, node_api.clone()).await.unwrap();
+
+    // Five queued events are taken as batches of 2, 2 and 1
+    let events = (0..5)
+        .map(|_| message_received(PEER_B, b"\x01\x02not mesh".to_vec()))
+        .collect();
+    run(&manager, &node_api, events).await;
+
+    let text = manager.metrics_exporter().render().await;
+    assert!(text.contains("mesh_event_batch_size_bucket{le=\"1\"} 1\n"));
+    assert!(text.contains("mesh_event_batch_size_bucket{le=\"2\"} 3\n"));
+    assert!(text.contains("mesh_event_batch_size_count 3\n"));
+    assert!(text.contains("# TYPE mesh_event_processing_lag_ms gauge\n"));
+}
@@ -0,0 +1,107 @@
+//! Tests for forwarding retries on unreachable routes
+
+mod common;
+
+use bllvm_mesh::manager::MeshManager;
+use bllvm_mesh::routing::{NodeId, RoutingEntry};
+use common::{test_context, MockNodeAPI};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const RELAY: NodeId = [2u8; 32];
+const OTHER_RELAY: NodeId = [4u8; 32];
+const DESTINATION: NodeId = [3u8; 32];
+const RELAY_ADDR: &str = "10.0.0.2:8334";
+const OTHER_RELAY_ADDR: &str = "10.0.0.4:8334";
+
+async fn manager(node_api: Arc<MockNodeAPI>, retries: &str) -> MeshManager {
+    let ctx = test_context(&[
+        ("mesh.enabled", "true"),
+        ("mesh.mode", "open"),
+        ("mesh.forward_retries", retries),
+        ("mesh.forward_retry_backoff_ms", "50"),
+    ]);
+    let manager = MeshManager::new(&ctx, node_api).await.unwrap();
+    manager.routing_table().add_direct_peer(RELAY, RELAY_ADDR.as_bytes().to_vec());
+    manager.routing_table().add_route(route_via(&manager, RELAY));
+    manager
+}
+
+fn route_via(manager: &MeshManager, relay: NodeId) -> RoutingEntry {
+    RoutingEntry {
+        node_id: DESTINATION,
+        direct_address: None,
+        next_hop: Some(relay),
+        route_path: vec![manager.node_id(), relay, DESTINATION],
+        route_cost: if relay == RELAY { 100 } else { 300 },
+        last_updated: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(),
+        quality_score: 0.8,
+    }
+}
+
+#[tokio::test]
+async fn test_retry_uses_route_learned_during_backoff() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    node_api.unreachable.lock().unwrap().insert(RELAY_ADDR.to_string());
+    let manager = manager(node_api.clone(), "2").await;
+
+    // A second relay becomes known while the first attempt backs off
+    let table = Arc::clone(manager.routing_table());
+    let alternative = route_via(&manager, OTHER_RELAY);
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        table.add_direct_peer(OTHER_RELAY, OTHER_RELAY_ADDR.as_bytes().to_vec());
+        table.add_route(alternative);
+    });
+
+    manager.send_packet(DESTINATION, vec![1], None).await.unwrap();
+    let sent = node_api.take_sent();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].0, OTHER_RELAY_ADDR);
+    assert_eq!(manager.metrics().forward_failures(), 0);
+}
+
+#[tokio::test]
+async fn test_exhausted_retries_surface_error_and_drop_route() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    node_api.unreachable.lock().unwrap().insert(RELAY_ADDR.to_string());
+    let manager = manager(node_api.clone(), "2").await;
+
+    assert!(manager.send_packet(DESTINATION, vec![1], None).await.is_err());
+    assert_eq!(manager.metrics().forward_failures(), 1);
+    assert!(node_api.take_sent().is_empty());
+
+    // Three failures push the learned route below the quality floor
+    assert!(manager.routing_table().get_route(&DESTINATION).is_none());
+}
+
+#[tokio::test]
+async fn test_no_retries_fails_at_once() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    node_api.unreachable.lock().unwrap().insert(RELAY_ADDR.to_string());
+    let manager = manager(node_api.clone(), "0").await;
+
+    assert!(manager.send_packet(DESTINATION, vec![1], None).await.is_err());
+    assert_eq!(manager.metrics().forward_failures(), 1);
+    let entry = manager.routing_table().get_route(&DESTINATION).unwrap();
+    assert_eq!(entry.quality_score, 0.4);
+}
+
+#[tokio::test]
+async fn test_backup_route_used_without_discovery() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let manager = manager(node_api.clone(), "0").await;
+    manager.routing_table().add_direct_peer(OTHER_RELAY, OTHER_RELAY_ADDR.as_bytes().to_vec());
+    manager.routing_table().add_route(route_via(&manager, OTHER_RELAY));
+
+    // The dearer route does not replace the working one
+    manager.send_packet(DESTINATION, vec![1], None).await.unwrap();
+    assert_eq!(node_api.take_sent()[0].0, RELAY_ADDR);
+
+    // The relay goes away: the stored backup carries the next packet
+    manager.routing_table().remove_direct_peer(&RELAY);
+    manager.send_packet(DESTINATION, vec![2], None).await.unwrap();
+    let sent = node_api.take_sent();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].0, OTHER_RELAY_ADDR);
+}
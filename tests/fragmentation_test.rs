@@ -0,0 +1,162 @@
+//! Tests for transparent fragmentation of packets larger than the MTU
+
+mod common;
+
+use bllvm_mesh::manager::MeshManager;
+use bllvm_mesh::network::deserialize_mesh_packet;
+use bllvm_mesh::packet::{MeshPacketBuilder, PacketType};
+use bllvm_mesh::routing::RoutingEntry;
+use common::{test_context, MockNodeAPI};
+use std::sync::Arc;
+
+const MTU: usize = 1024;
+
+async fn node() -> (MeshManager, Arc<MockNodeAPI>) {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let mtu = MTU.to_string();
+    let ctx = test_context(&[("mesh.enabled", "true"), ("mesh.mode", "open"), ("mesh.mtu", &mtu)]);
+    let manager = MeshManager::new(&ctx, node_api.clone()).await.unwrap();
+    (manager, node_api)
+}
+
+fn large_payload() -> Vec<u8> {
+    (0..10_000u32).map(|i| (i % 251) as u8).collect()
+}
+
+/// Hand every frame sent by one node to another over the link at `from_addr`
+async fn deliver_all(frames: Vec<(String, Vec<u8>)>, from_addr: &str, to: &MeshManager) {
+    for (_, frame) in frames {
+        assert!(frame.len() <= MTU, "frame of {} bytes exceeds MTU", frame.len());
+        to.handle_peer_frame(from_addr, &frame).await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_large_packet_is_fragmented_and_reassembled() {
+    let (sender, sender_api) = node().await;
+    let (receiver, _) = node().await;
+    sender
+        .routing_table()
+        .add_direct_peer(receiver.node_id(), b"10.0.0.2:8334".to_vec());
+    receiver.add_direct_peer(sender.node_id(), "10.0.0.1:8334", "tcp");
+
+    let payload = large_payload();
+    let packet = MeshPacketBuilder::new(sender.node_id(), receiver.node_id())
+        .packet_type(PacketType::BitcoinP2P)
+        .payload(payload.clone())
+        .build()
+        .unwrap();
+    sender.route_packet(&packet).await.unwrap();
+
+    let frames = sender_api.take_sent();
+    assert!(frames.len() > 1);
+
+    // Nothing is delivered until the last fragment arrives
+    let (last, rest) = frames.split_last().unwrap();
+    deliver_all(rest.to_vec(), "10.0.0.1:8334", &receiver).await;
+    assert!(receiver.poll_delivered(10).is_empty());
+    deliver_all(vec![last.clone()], "10.0.0.1:8334", &receiver).await;
+
+    let delivered = receiver.poll_delivered(10);
+    assert_eq!(delivered.len(), 1);
+    assert_eq!(delivered[0].payload, payload);
+    assert_eq!(delivered[0].source, sender.node_id());
+}
+
+#[tokio::test]
+async fn test_relay_reassembles_before_forwarding() {
+    let (sender, sender_api) = node().await;
+    let (relay, relay_api) = node().await;
+    let (destination, _) = node().await;
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    sender.routing_table().add_direct_peer(relay.node_id(), b"10.0.0.2:8334".to_vec());
+    sender.routing_table().add_route(RoutingEntry {
+        node_id: destination.node_id(),
+        direct_address: None,
+        next_hop: Some(relay.node_id()),
+        route_path: vec![sender.node_id(), relay.node_id(), destination.node_id()],
+        route_cost: 100,
+        last_updated: now,
+        quality_score: 0.8,
+    });
+    relay
+        .routing_table()
+        .add_direct_peer(destination.node_id(), b"10.0.0.3:8334".to_vec());
+    relay.add_direct_peer(sender.node_id(), "10.0.0.1:8334", "tcp");
+    destination.add_direct_peer(relay.node_id(), "10.0.0.2:8334", "tcp");
+
+    let payload = large_payload();
+    let packet = MeshPacketBuilder::new(sender.node_id(), destination.node_id())
+        .packet_type(PacketType::BitcoinP2P)
+        .payload(payload.clone())
+        .build()
+        .unwrap();
+    sender.route_packet(&packet).await.unwrap();
+
+    // Fragments are addressed to the relay, which re-fragments for the next link
+    deliver_all(sender_api.take_sent(), "10.0.0.1:8334", &relay).await;
+    assert!(relay.poll_delivered(10).is_empty());
+    let forwarded = relay_api.take_sent();
+    assert!(forwarded.len() > 1);
+    assert!(forwarded.iter().all(|(addr, _)| addr == "10.0.0.3:8334"));
+    deliver_all(forwarded, "10.0.0.2:8334", &destination).await;
+
+    let delivered = destination.poll_delivered(10);
+    assert_eq!(delivered.len(), 1);
+    assert_eq!(delivered[0].payload, payload);
+}
+
+#[tokio::test]
+async fn test_fragment_for_another_node_is_rejected() {
+    let (sender, sender_api) = node().await;
+    let (receiver, _) = node().await;
+    let (bystander, _) = node().await;
+    sender
+        .routing_table()
+        .add_direct_peer(receiver.node_id(), b"10.0.0.2:8334".to_vec());
+
+    let packet = MeshPacketBuilder::new(sender.node_id(), receiver.node_id())
+        .packet_type(PacketType::BitcoinP2P)
+        .payload(large_payload())
+        .build()
+        .unwrap();
+    sender.route_packet(&packet).await.unwrap();
+
+    bystander.add_direct_peer(sender.node_id(), "10.0.0.1:8334", "tcp");
+
+    let (_, frame) = &sender_api.take_sent()[0];
+    let fragment = deserialize_mesh_packet(frame).unwrap();
+    assert_eq!(fragment.packet_type, PacketType::Fragment);
+    assert!(bystander.handle_peer_frame("10.0.0.1:8334", frame).await.is_err());
+}
+
+#[tokio::test]
+async fn test_fragment_with_spoofed_source_is_rejected() {
+    let (sender, sender_api) = node().await;
+    let (receiver, _) = node().await;
+    sender
+        .routing_table()
+        .add_direct_peer(receiver.node_id(), b"10.0.0.2:8334".to_vec());
+    // The link belongs to another peer than the fragments claim to come from
+    receiver.add_direct_peer([7u8; 32], "10.0.0.7:8334", "tcp");
+
+    let packet = MeshPacketBuilder::new(sender.node_id(), receiver.node_id())
+        .packet_type(PacketType::BitcoinP2P)
+        .payload(large_payload())
+        .build()
+        .unwrap();
+    sender.route_packet(&packet).await.unwrap();
+
+    for (_, frame) in sender_api.take_sent() {
+        assert!(receiver.handle_peer_frame("10.0.0.7:8334", &frame).await.is_err());
+        // Nor are fragments accepted without a link at all
+        let fragment = deserialize_mesh_packet(&frame).unwrap();
+        assert!(receiver.handle_incoming_packet(&fragment).await.is_err());
+        // Or from an address with no known peer
+        assert!(receiver.handle_peer_frame("10.0.0.9:8334", &frame).await.is_err());
+    }
+    assert!(receiver.poll_delivered(10).is_empty());
+}
@@ -0,0 +1,22 @@
+//! Tests for the module health check
+
+mod common;
+
+use bllvm_mesh::manager::MeshManager;
+use bllvm_node::module::process::monitor::ModuleHealth;
+use common::{test_context, MockNodeAPI};
+use std::sync::Arc;
+
+#[tokio::test]
+async fn test_health_needs_a_direct_peer() {
+    let ctx = test_context(&[("mesh.enabled", "true"), ("mesh.mode", "open")]);
+    let manager = MeshManager::new(&ctx, Arc::new(MockNodeAPI::new())).await.unwrap();
+
+    let ModuleHealth::Degraded(reason) = manager.health_check().await else {
+        panic!("expected degraded health without peers");
+    };
+    assert_eq!(reason, "no direct peers");
+
+    manager.routing_table().add_direct_peer([2u8; 32], b"10.0.0.2:8334".to_vec());
+    assert!(matches!(manager.health_check().await, ModuleHealth::Healthy));
+}
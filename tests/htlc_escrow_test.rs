@@ -0,0 +1,137 @@
+//! Tests for routing fees escrowed in HTLCs
+
+mod common;
+
+use bllvm_mesh::error::MeshError;
+use bllvm_mesh::manager::MeshManager;
+use bllvm_mesh::packet::{MeshPacket, MeshPacketBuilder, PacketType};
+use bllvm_mesh::payment_proof::PaymentProof;
+use bllvm_mesh::routing::{NodeId, RoutingEntry};
+use bllvm_mesh::verifier::HtlcVerifier;
+use common::{test_context, MockNodeAPI};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SOURCE: NodeId = [1u8; 32];
+const PEER: NodeId = [2u8; 32];
+const DESTINATION: NodeId = [3u8; 32];
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+async fn manager(mode: &str, node_api: Arc<MockNodeAPI>) -> MeshManager {
+    let ctx = test_context(&[("mesh.enabled", "true"), ("mesh.mode", mode)]);
+    let manager = MeshManager::new(&ctx, node_api).await.unwrap();
+    manager.routing_table().add_direct_peer(PEER, b"10.0.0.2:8334".to_vec());
+    manager.routing_table().add_route(RoutingEntry {
+        node_id: DESTINATION,
+        direct_address: None,
+        next_hop: Some(PEER),
+        route_path: vec![manager.node_id(), PEER, DESTINATION],
+        route_cost: 200,
+        last_updated: now(),
+        quality_score: 0.8,
+    });
+    manager
+}
+
+fn escrow(htlc_expiry: u64) -> PaymentProof {
+    PaymentProof::HtlcEscrow {
+        payment_hash: Sha256::digest([5u8; 32]).into(),
+        htlc_expiry,
+        amount_msats: 50_000,
+    }
+}
+
+fn paid_packet(source: NodeId, proof: PaymentProof) -> MeshPacket {
+    MeshPacketBuilder::new(source, DESTINATION)
+        .packet_type(PacketType::Paid)
+        .payload(vec![0x12; 64])
+        .payment_proof(proof)
+        .ttl(5)
+        .build()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_unlocked_escrow_rejected_at_source() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let manager = manager("payment_gated", node_api.clone()).await;
+
+    let result = manager
+        .route_packet(&paid_packet(manager.node_id(), escrow(now() + 3600)))
+        .await;
+    match result {
+        Err(MeshError::PaymentVerification(reason)) => assert_eq!(reason, "HTLC escrow not found"),
+        other => panic!("expected an escrow error, got {:?}", other),
+    }
+    assert!(node_api.take_sent().is_empty());
+}
+
+#[tokio::test]
+async fn test_relay_checks_escrow_before_forwarding() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let relay = manager("open", node_api.clone()).await;
+
+    // The HTLC is not known to the relay's Lightning backend
+    let result = relay
+        .handle_incoming_packet(&paid_packet(SOURCE, escrow(now() + 3600)))
+        .await;
+    assert!(matches!(result, Err(MeshError::PaymentVerification(_))));
+    assert!(node_api.take_sent().is_empty());
+
+    // An HTLC about to time out is refused without asking the node
+    let calls = node_api.call_count();
+    let result = relay
+        .handle_incoming_packet(&paid_packet(SOURCE, escrow(now() + 60)))
+        .await;
+    match result {
+        Err(MeshError::PaymentVerification(reason)) => assert_eq!(reason, "HTLC escrow expires too soon"),
+        other => panic!("expected an escrow error, got {:?}", other),
+    }
+    assert_eq!(node_api.call_count(), calls);
+    assert!(node_api.take_sent().is_empty());
+}
+
+#[tokio::test]
+async fn test_preimage_released_once_after_delivery() {
+    let htlc = HtlcVerifier::new(Arc::new(MockNodeAPI::new()));
+    let preimage = [5u8; 32];
+    let proof = htlc.hold(preimage, now() + 3600, 50_000);
+    let PaymentProof::HtlcEscrow { payment_hash, .. } = proof else {
+        panic!("expected an escrow proof, got {:?}", proof);
+    };
+    assert_eq!(proof.amount_sats(), 50);
+
+    // Nothing is revealed before the packet is acked, and only once after
+    htlc.await_delivery(7, &payment_hash);
+    assert_eq!(htlc.release(8), None);
+    assert_eq!(htlc.release(7), Some((payment_hash, preimage)));
+    assert_eq!(htlc.release(7), None);
+
+    // Escrows opened elsewhere are never released by this node
+    htlc.await_delivery(9, &[6u8; 32]);
+    assert_eq!(htlc.release(9), None);
+}
+
+#[tokio::test]
+async fn test_claims_need_matching_preimage() {
+    let htlc = HtlcVerifier::new(Arc::new(MockNodeAPI::new()));
+    let PaymentProof::HtlcEscrow { payment_hash, .. } = htlc.hold([5u8; 32], now() + 3600, 50_000) else {
+        panic!("expected an escrow proof");
+    };
+
+    assert!(!htlc.accept_claim(&payment_hash, &[6u8; 32]));
+    assert!(!htlc.accept_claim(&Sha256::digest([6u8; 32]).into(), &[6u8; 32]));
+    assert!(htlc.accept_claim(&payment_hash, &[5u8; 32]));
+    assert_eq!(htlc.take_claims(), vec![(payment_hash, [5u8; 32])]);
+    assert!(htlc.take_claims().is_empty());
+
+    // Claims on timed-out HTLCs are dropped
+    assert!(htlc.accept_claim(&payment_hash, &[5u8; 32]));
+    assert_eq!(htlc.expire(now() + 3600), 1);
+    assert!(htlc.take_claims().is_empty());
+    assert!(!htlc.accept_claim(&payment_hash, &[5u8; 32]));
+}
@@ -0,0 +1,144 @@
+//! Tests for keepalive probing and dead-peer detection (feature `testkit`)
+
+use bllvm_mesh::testkit::MeshCluster;
+
+const IDLE_SECS: u64 = 60;
+const TIMEOUT_SECS: u64 = 15;
+const MAX_MISSES: u64 = 3;
+
+async fn keepalive_pair() -> MeshCluster {
+    let cluster = MeshCluster::new(
+        2,
+        &[
+            ("mesh.mode", "open"),
+            ("mesh.keepalive_idle_secs", "60"),
+            ("mesh.keepalive_timeout_secs", "15"),
+            ("mesh.keepalive_max_misses", "3"),
+        ],
+    )
+    .await
+    .unwrap();
+    cluster.connect(0, 1);
+    cluster
+}
+
+fn is_direct_peer(cluster: &MeshCluster, node: usize, peer: usize) -> bool {
+    cluster
+        .node(node)
+        .routing_table()
+        .direct_peer_addresses()
+        .iter()
+        .any(|(node_id, _)| *node_id == cluster.node_id(peer))
+}
+
+#[tokio::test]
+async fn test_idle_peer_answers_keepalive() {
+    let cluster = keepalive_pair().await;
+
+    cluster.advance(IDLE_SECS);
+    assert!(cluster.node(0).run_keepalive().await.is_empty());
+    // Probe and its answer
+    assert_eq!(cluster.run_until_idle().await, 2);
+
+    // Answered probes never accumulate into a dead peer
+    for _ in 0..MAX_MISSES {
+        cluster.advance(TIMEOUT_SECS);
+        assert!(cluster.node(0).run_keepalive().await.is_empty());
+        cluster.run_until_idle().await;
+    }
+    assert!(is_direct_peer(&cluster, 0, 1));
+}
+
+#[tokio::test]
+async fn test_silent_peer_removed_after_max_misses() {
+    let cluster = keepalive_pair().await;
+    cluster.sever(0, 1);
+
+    cluster.advance(IDLE_SECS);
+    assert!(cluster.node(0).run_keepalive().await.is_empty());
+
+    for miss in 1..=MAX_MISSES {
+        cluster.advance(TIMEOUT_SECS);
+        cluster.run_until_idle().await;
+        let dead = cluster.node(0).run_keepalive().await;
+        if miss < MAX_MISSES {
+            assert!(dead.is_empty());
+            assert!(is_direct_peer(&cluster, 0, 1));
+        } else {
+            assert_eq!(dead, vec![cluster.node_id(1)]);
+        }
+    }
+    assert!(!is_direct_peer(&cluster, 0, 1));
+}
+
+#[tokio::test]
+async fn test_chatty_peer_gets_no_keepalives() {
+    let cluster = keepalive_pair().await;
+
+    for round in 0..10u8 {
+        cluster.send(0, 1, vec![round]).await.unwrap();
+        cluster.send(1, 0, vec![round]).await.unwrap();
+        cluster.run_until_idle().await;
+
+        cluster.advance(IDLE_SECS / 2);
+        cluster.node(0).run_keepalive().await;
+        cluster.run_until_idle().await;
+    }
+
+    // Only data packets were ever sent
+    assert_eq!(cluster.cluster_node(0).node_api.sent_count(), 10);
+    assert_eq!(cluster.cluster_node(1).node_api.sent_count(), 10);
+    assert!(is_direct_peer(&cluster, 0, 1));
+}
+
+#[tokio::test]
+async fn test_dead_peer_torn_down_like_disconnect() {
+    let cluster = MeshCluster::line(
+        3,
+        &[
+            ("mesh.mode", "open"),
+            ("mesh.keepalive_idle_secs", "60"),
+            ("mesh.keepalive_timeout_secs", "15"),
+            ("mesh.keepalive_max_misses", "3"),
+        ],
+    )
+    .await
+    .unwrap();
+    let far = cluster.node_id(2);
+    assert!(cluster.node(0).routing_table().get_route(&far).is_some());
+    cluster.sever(0, 1);
+
+    cluster.advance(IDLE_SECS);
+    cluster.node(0).run_keepalive().await;
+    for _ in 0..MAX_MISSES {
+        cluster.advance(TIMEOUT_SECS);
+        cluster.run_until_idle().await;
+        cluster.node(0).run_keepalive().await;
+    }
+
+    // Routes through the dead peer go with it
+    assert!(!is_direct_peer(&cluster, 0, 1));
+    assert!(cluster.node(0).routing_table().get_route(&far).is_none());
+
+    // A later disconnect event for its address finds nothing left to remove
+    assert!(cluster
+        .node(0)
+        .handle_peer_disconnected(&MeshCluster::node_addr(1))
+        .is_empty());
+}
+
+#[tokio::test]
+async fn test_probes_count_against_bandwidth() {
+    let cluster = keepalive_pair().await;
+    let now = cluster.now();
+    assert_eq!(cluster.node(0).bandwidth().used_bytes(now), 0);
+
+    cluster.advance(IDLE_SECS);
+    cluster.node(0).run_keepalive().await;
+    cluster.run_until_idle().await;
+
+    // Node 0's probe and node 1's answer are both accounted by their senders
+    let now = cluster.now();
+    assert!(cluster.node(0).bandwidth().used_bytes(now) > 0);
+    assert!(cluster.node(1).bandwidth().used_bytes(now) > 0);
+}
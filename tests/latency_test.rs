@@ -0,0 +1,41 @@
+//! Tests for direct peer latency probing (feature `testkit`)
+
+use bllvm_mesh::testkit::MeshCluster;
+
+async fn probe_pair() -> MeshCluster {
+    let cluster = MeshCluster::new(2, &[("mesh.mode", "open")]).await.unwrap();
+    cluster.connect(0, 1);
+    cluster
+}
+
+#[tokio::test]
+async fn test_probe_reply_records_rtt_and_quality() {
+    let cluster = probe_pair().await;
+    let peer = cluster.node_id(1);
+    let routing_table = cluster.node(0).routing_table();
+    assert_eq!(routing_table.update_quality_score(&peer, -0.5), Some(0.5));
+
+    assert_eq!(cluster.node(0).probe_latency().await, 1);
+    // Probe and its reply
+    assert_eq!(cluster.run_until_idle().await, 2);
+
+    // An in-process round trip is far below the reference latency
+    let rtt = cluster.node(0).peer_rtt_ms(&peer).unwrap();
+    assert!(rtt < 100.0);
+    let quality = routing_table.get_route(&peer).unwrap().quality_score;
+    assert!(quality > 0.5 && quality <= 0.55);
+    // Only the prober measures
+    assert!(cluster.node(1).peer_rtt_ms(&cluster.node_id(0)).is_none());
+}
+
+#[tokio::test]
+async fn test_unanswered_probe_leaves_quality() {
+    let cluster = probe_pair().await;
+    let peer = cluster.node_id(1);
+    cluster.sever(0, 1);
+
+    cluster.node(0).probe_latency().await;
+    cluster.run_until_idle().await;
+    assert!(cluster.node(0).peer_rtt_ms(&peer).is_none());
+    assert_eq!(cluster.node(0).routing_table().get_route(&peer).unwrap().quality_score, 1.0);
+}
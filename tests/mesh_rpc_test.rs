@@ -0,0 +1,115 @@
+//! Tests for request-response calls between modules over mesh packets
+
+mod common;
+
+use bllvm_mesh::error::MeshError;
+use bllvm_mesh::manager::MeshManager;
+use bllvm_mesh::network::deserialize_mesh_packet;
+use bllvm_mesh::packet::{MeshPacket, MeshPacketBuilder, PacketMetadata, PacketType};
+use bllvm_mesh::routing::NodeId;
+use bllvm_mesh::rpc::{CORRELATION_ID_FIELD, RPC_REQUEST, RPC_ROLE_FIELD};
+use common::{test_context, MockNodeAPI};
+use std::sync::Arc;
+use std::time::Duration;
+
+const PEER: NodeId = [2u8; 32];
+const PEER_ADDR: &str = "10.0.0.2:8334";
+
+async fn manager(node_api: Arc<MockNodeAPI>) -> MeshManager {
+    let ctx = test_context(&[("mesh.enabled", "true"), ("mesh.mode", "open")]);
+    let manager = MeshManager::new(&ctx, node_api).await.unwrap();
+    manager.routing_table().add_direct_peer(PEER, PEER_ADDR.as_bytes().to_vec());
+    manager
+}
+
+/// Wait for the manager to send the call and return it
+async fn sent_request(node_api: &MockNodeAPI) -> MeshPacket {
+    loop {
+        if let Some((_, frame)) = node_api.take_sent().pop() {
+            return deserialize_mesh_packet(&frame).unwrap();
+        }
+        tokio::task::yield_now().await;
+    }
+}
+
+fn reply(request: &MeshPacket, source: NodeId, payload: &[u8]) -> MeshPacket {
+    let mut reply = MeshPacketBuilder::new(source, request.source)
+        .packet_type(PacketType::BitcoinP2P)
+        .payload(payload.to_vec())
+        .build()
+        .unwrap();
+    reply.set_rpc_reply(request.correlation_id().unwrap());
+    reply
+}
+
+#[tokio::test]
+async fn test_call_receives_reply() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let manager = Arc::new(manager(node_api.clone()).await);
+    let caller = Arc::clone(&manager);
+    let call = tokio::spawn(async move { caller.call(PEER, b"balance?".to_vec(), Duration::from_secs(5)).await });
+
+    let request = sent_request(&node_api).await;
+    assert!(request.is_rpc_request());
+    assert_eq!(request.payload, b"balance?");
+
+    // A reply from a node the call did not go to is dropped
+    manager.handle_incoming_packet(&reply(&request, [9u8; 32], b"forged")).await.unwrap();
+    manager.handle_incoming_packet(&reply(&request, PEER, b"42")).await.unwrap();
+
+    let answer = call.await.unwrap().unwrap();
+    assert_eq!(answer.payload, b"42");
+    // Replies never reach the application
+    assert!(manager.poll_delivered(10).is_empty());
+}
+
+#[tokio::test]
+async fn test_call_times_out() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let manager = manager(node_api.clone()).await;
+
+    let result = manager.call(PEER, b"balance?".to_vec(), Duration::from_millis(10)).await;
+    assert!(matches!(result, Err(MeshError::Timeout(_))));
+
+    // A late reply finds no waiting call
+    let request = sent_request(&node_api).await;
+    manager.handle_incoming_packet(&reply(&request, PEER, b"42")).await.unwrap();
+    assert!(manager.poll_delivered(10).is_empty());
+}
+
+#[tokio::test]
+async fn test_request_answered_with_send_reply() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let manager = manager(node_api.clone()).await;
+
+    let plain = MeshPacketBuilder::new(PEER, manager.node_id())
+        .packet_type(PacketType::BitcoinP2P)
+        .payload(b"balance?".to_vec())
+        .sequence(2)
+        .build()
+        .unwrap();
+    let mut metadata = PacketMetadata::default();
+    metadata.fields.insert(CORRELATION_ID_FIELD.to_string(), "7".to_string());
+    metadata.fields.insert(RPC_ROLE_FIELD.to_string(), RPC_REQUEST.to_string());
+    let mut request = plain.clone();
+    request.sequence = 1;
+    request.metadata = Some(metadata);
+    manager.handle_incoming_packet(&request).await.unwrap();
+    manager.handle_incoming_packet(&plain).await.unwrap();
+    node_api.take_sent();
+
+    let delivered = manager.poll_delivered(10);
+    assert_eq!(delivered.len(), 2);
+    assert!(delivered[1].correlation_id.is_none());
+    assert!(manager.send_reply(&delivered[1], b"42".to_vec()).await.is_err());
+
+    assert_eq!(delivered[0].correlation_id, Some(7));
+    manager.send_reply(&delivered[0], b"42".to_vec()).await.unwrap();
+    let sent = node_api.take_sent();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].0, PEER_ADDR);
+    let answer = deserialize_mesh_packet(&sent[0].1).unwrap();
+    assert!(answer.is_rpc_reply());
+    assert_eq!(answer.correlation_id(), Some(7));
+    assert_eq!(answer.payload, b"42");
+}
@@ -0,0 +1,118 @@
+//! Tests for the Prometheus metrics exporter and stats snapshots
+
+mod common;
+
+use bllvm_mesh::discovery::DiscoveryMessage;
+use bllvm_mesh::manager::{MeshManager, MeshStats};
+use bllvm_mesh::metrics::PacketCounters;
+use bllvm_mesh::packet::{MeshPacketBuilder, PacketType};
+use common::{test_context, MockNodeAPI};
+use std::sync::Arc;
+
+#[tokio::test]
+async fn test_exporter_reflects_manager_state() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let ctx = test_context(&[("mesh.enabled", "true"), ("mesh.mode", "open")]);
+    let manager = MeshManager::new(&ctx, node_api.clone()).await.unwrap();
+    let peer = [2u8; 32];
+    manager.routing_table().add_direct_peer(peer, b"10.0.0.2:8334".to_vec());
+
+    let packet = MeshPacketBuilder::new(manager.node_id(), peer)
+        .packet_type(PacketType::BitcoinP2P)
+        .payload(vec![1, 2, 3])
+        .build()
+        .unwrap();
+    manager.route_packet(&packet).await.unwrap();
+
+    // Unknown destination: discovery is attempted and timed, nothing is routed
+    let packet = MeshPacketBuilder::new(manager.node_id(), [9u8; 32])
+        .packet_type(PacketType::BitcoinP2P)
+        .payload(vec![1])
+        .build()
+        .unwrap();
+    assert!(manager.route_packet(&packet).await.is_err());
+
+    let text = manager.metrics_exporter().render().await;
+    assert!(text.contains("mesh_packets_routed_total 1\n"));
+    assert!(text.contains("mesh_routes_active 1\n"));
+    assert!(text.contains("mesh_route_discovery_latency_seconds_count 1\n"));
+    assert!(text.contains("mesh_payment_verifications_total{result=\"ok\"} 0\n"));
+}
+
+#[tokio::test]
+async fn test_stats_counters_and_json_round_trip() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let ctx = test_context(&[("mesh.enabled", "true"), ("mesh.mode", "open")]);
+    let manager = MeshManager::new(&ctx, node_api.clone()).await.unwrap();
+    let peer = [2u8; 32];
+    manager.routing_table().add_direct_peer(peer, b"10.0.0.2:8334".to_vec());
+    assert_eq!(manager.get_stats().await.packets, PacketCounters::default());
+
+    let packet = MeshPacketBuilder::new(manager.node_id(), peer)
+        .packet_type(PacketType::BitcoinP2P)
+        .payload(vec![1, 2, 3])
+        .build()
+        .unwrap();
+    manager.route_packet(&packet).await.unwrap();
+    let packet = MeshPacketBuilder::new(manager.node_id(), [9u8; 32])
+        .packet_type(PacketType::BitcoinP2P)
+        .payload(vec![1])
+        .build()
+        .unwrap();
+    assert!(manager.route_packet(&packet).await.is_err());
+    let inbound = MeshPacketBuilder::new(peer, manager.node_id())
+        .packet_type(PacketType::BitcoinP2P)
+        .payload(vec![4])
+        .build()
+        .unwrap();
+    manager.handle_incoming_packet(&inbound).await.unwrap();
+
+    let stats = manager.get_stats().await;
+    assert_eq!(stats.packets.routed, 1);
+    assert_eq!(stats.packets.dropped, 1);
+    assert_eq!(stats.packets.delivered, 1);
+    assert_eq!(stats.packets.forwarded, 0);
+    assert!(stats.timestamp > 0);
+
+    let json = manager.stats_json().await;
+    assert_eq!(json["packets"]["routed"], 1);
+    let decoded: MeshStats = serde_json::from_value(json).unwrap();
+    assert_eq!(decoded.packets, stats.packets);
+    assert_eq!(decoded.mode, stats.mode);
+    assert_eq!(decoded.routing.direct_peers, 1);
+}
+
+#[tokio::test]
+async fn test_route_request_flood_counted() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let ctx = test_context(&[
+        ("mesh.enabled", "true"),
+        ("mesh.mode", "open"),
+        ("mesh.discovery.request_burst", "2"),
+    ]);
+    let manager = MeshManager::new(&ctx, node_api.clone()).await.unwrap();
+    let peer = [2u8; 32];
+    manager.routing_table().add_direct_peer(peer, b"10.0.0.2:8334".to_vec());
+
+    for request_id in 1..=4u64 {
+        let request = DiscoveryMessage::RouteRequest {
+            destination: [9u8; 32],
+            source: peer,
+            request_id,
+            max_hops: 5,
+            path: vec![peer],
+        };
+        let packet = MeshPacketBuilder::new(peer, manager.node_id())
+            .packet_type(PacketType::Discovery)
+            .payload(request.encode().unwrap())
+            .sequence(request_id)
+            .build()
+            .unwrap();
+        manager.handle_incoming_packet(&packet).await.unwrap();
+    }
+
+    assert_eq!(manager.get_stats().await.discovery.requests_rate_limited, 2);
+    let text = manager.metrics_exporter().render().await;
+    assert!(text.contains("mesh_route_requests_rate_limited_total 2\n"));
+    assert!(text.contains("mesh_route_discovery_pending 0\n"));
+}
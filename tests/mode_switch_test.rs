@@ -0,0 +1,199 @@
+//! Tests for switching the mesh mode and on/off state at runtime
+
+mod common;
+
+use bllvm_mesh::config::CONFIG_TREE;
+use bllvm_mesh::error::MeshError;
+use bllvm_mesh::manager::MeshManager;
+use bllvm_mesh::packet::{MeshPacket, MeshPacketBuilder, PacketType};
+use bllvm_mesh::routing_policy::{MeshMode, RoutingPolicy};
+use bllvm_mesh::rpc::{RPC_DISABLE, RPC_ENABLE, RPC_SET_MODE};
+use bllvm_node::module::ipc::protocol::{EventMessage, ModuleMessage};
+use bllvm_node::module::traits::{EventPayload, EventType};
+use common::{test_context, MockNodeAPI};
+use serde_json::json;
+use std::sync::Arc;
+
+const PEER: [u8; 32] = [2u8; 32];
+
+/// Bitcoin mainnet `inv` message header
+const BITCOIN_INV: [u8; 24] = [
+    0xf9, 0xbe, 0xb4, 0xd9, b'i', b'n', b'v', 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+];
+
+async fn manager(node_api: Arc<MockNodeAPI>) -> MeshManager {
+    let ctx = test_context(&[("mesh.enabled", "true"), ("mesh.mode", "open")]);
+    let manager = MeshManager::new(&ctx, node_api).await.unwrap();
+    manager.routing_table().add_direct_peer(PEER, b"10.0.0.2:8334".to_vec());
+    manager
+}
+
+fn packet(manager: &MeshManager, payload: &[u8]) -> MeshPacket {
+    MeshPacketBuilder::new(manager.node_id(), PEER)
+        .packet_type(PacketType::BitcoinP2P)
+        .payload(payload.to_vec())
+        .build()
+        .unwrap()
+}
+
+fn config_changed(key: &str, value: &str) -> ModuleMessage {
+    ModuleMessage::Event(EventMessage {
+        event_type: EventType::ConfigChanged,
+        payload: EventPayload::ConfigChanged {
+            key: key.to_string(),
+            value: value.to_string(),
+        },
+    })
+}
+
+#[tokio::test]
+async fn test_mode_switch_changes_policy_mid_stream() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let manager = manager(node_api.clone()).await;
+    let mesh_payload = [0x12, 0x34, 0x56, 0x78];
+
+    manager.route_packet(&packet(&manager, &mesh_payload)).await.unwrap();
+    assert_eq!(manager.determine_routing_policy(&mesh_payload), RoutingPolicy::Free);
+
+    let result = manager
+        .handle_rpc_call(RPC_SET_MODE, &json!({ "mode": "bitcoin_only" }))
+        .await
+        .unwrap();
+    assert_eq!(result, json!({ "previous": "open", "mode": "bitcoin_only" }));
+    assert_eq!(manager.get_stats().await.mode, MeshMode::BitcoinOnly);
+
+    // Mesh traffic is refused at once, Bitcoin P2P still flows
+    assert!(matches!(
+        manager.route_packet(&packet(&manager, &mesh_payload)).await,
+        Err(MeshError::MeshDisabled(_))
+    ));
+    manager.route_packet(&packet(&manager, &BITCOIN_INV)).await.unwrap();
+
+    manager.set_mode(MeshMode::PaymentGated).await;
+    assert_eq!(manager.determine_routing_policy(&mesh_payload), RoutingPolicy::PaymentRequired);
+    assert!(manager.route_packet(&packet(&manager, &mesh_payload)).await.is_err());
+
+    manager.set_mode(MeshMode::Open).await;
+    manager.route_packet(&packet(&manager, &mesh_payload)).await.unwrap();
+    assert_eq!(node_api.take_sent().len(), 3);
+}
+
+#[tokio::test]
+async fn test_config_changed_event_switches_mode() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let manager = manager(node_api.clone()).await;
+
+    manager
+        .handle_event(&config_changed("mesh.mode", "payment_gated"), node_api.as_ref())
+        .await
+        .unwrap();
+    assert_eq!(manager.get_stats().await.mode, MeshMode::PaymentGated);
+
+    // Other keys are ignored, unknown modes rejected
+    manager
+        .handle_event(&config_changed("mesh.max_ttl", "8"), node_api.as_ref())
+        .await
+        .unwrap();
+    assert!(manager
+        .handle_event(&config_changed("mesh.mode", "closed"), node_api.as_ref())
+        .await
+        .is_err());
+    assert_eq!(manager.get_stats().await.mode, MeshMode::PaymentGated);
+}
+
+#[tokio::test]
+async fn test_mode_survives_restart() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let manager = manager(node_api.clone()).await;
+    manager.set_mode(MeshMode::BitcoinOnly).await;
+    drop(manager);
+
+    // The stored mode wins over mesh.mode
+    let restarted = self::manager(node_api.clone()).await;
+    assert_eq!(restarted.get_stats().await.mode, MeshMode::BitcoinOnly);
+
+    assert!(restarted
+        .handle_rpc_call(RPC_SET_MODE, &json!({ "mode": "closed" }))
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_disable_and_enable_without_restart() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let manager = manager(node_api.clone()).await;
+    let payload = [0x12, 0x34, 0x56, 0x78];
+
+    let result = manager.handle_rpc_call(RPC_DISABLE, &json!({})).await.unwrap();
+    assert_eq!(result, json!({ "previous": true, "enabled": false }));
+    assert!(!manager.get_stats().await.enabled);
+    assert!(matches!(
+        manager.route_packet(&packet(&manager, &payload)).await,
+        Err(MeshError::MeshDisabled(_))
+    ));
+
+    // The routing table survives the round trip
+    manager.handle_rpc_call(RPC_ENABLE, &json!({})).await.unwrap();
+    assert!(manager.routing_table().get_route(&PEER).is_some());
+    manager.route_packet(&packet(&manager, &payload)).await.unwrap();
+    assert_eq!(node_api.take_sent().len(), 1);
+
+    // The stored state wins over mesh.enabled
+    manager.set_enabled(false).await;
+    drop(manager);
+    let restarted = self::manager(node_api.clone()).await;
+    assert!(!restarted.is_enabled());
+}
+
+#[tokio::test]
+async fn test_config_changed_event_reenables_mesh() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let manager = manager(node_api.clone()).await;
+    manager.set_enabled(false).await;
+
+    manager
+        .handle_event(&config_changed("mesh.enabled", "true"), node_api.as_ref())
+        .await
+        .unwrap();
+    assert!(manager.is_enabled());
+    assert!(manager
+        .handle_event(&config_changed("mesh.enabled", "maybe"), node_api.as_ref())
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_stored_mode_change_applied_without_restart() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let manager = manager(node_api.clone()).await;
+    let mesh_payload = [0x12, 0x34, 0x56, 0x78];
+    assert_eq!(manager.poll_config().await, None);
+    assert_eq!(manager.determine_routing_policy(&mesh_payload), RoutingPolicy::Free);
+
+    // An operator tool writes the mode into the node's storage
+    node_api
+        .storage
+        .lock()
+        .unwrap()
+        .entry(CONFIG_TREE.to_string())
+        .or_default()
+        .insert(b"mode".to_vec(), b"payment_gated".to_vec());
+    assert_eq!(manager.poll_config().await, Some(MeshMode::PaymentGated));
+    assert_eq!(manager.determine_routing_policy(&mesh_payload), RoutingPolicy::PaymentRequired);
+    assert_eq!(manager.poll_config().await, None);
+
+    {
+        let published = node_api.published.lock().unwrap();
+        assert_eq!(published.len(), 1);
+        assert!(matches!(
+            &published[0],
+            (EventType::ConfigChanged, EventPayload::ConfigChanged { key, value })
+                if key == "mesh.mode" && value == "payment_gated"
+        ));
+    }
+
+    // A switch made through the manager is stored, so the watcher keeps it
+    manager.set_mode(MeshMode::Open).await;
+    assert_eq!(manager.poll_config().await, None);
+    assert_eq!(manager.determine_routing_policy(&mesh_payload), RoutingPolicy::Free);
+}
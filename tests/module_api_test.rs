@@ -0,0 +1,180 @@
+//! Tests for the cross-module mesh transport API
+
+mod common;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use bllvm_mesh::api::{
+    self, QuoteRequest, QuoteResponse, ReceivePollRequest, ReceivePollResponse, ResolveNameRequest,
+    ResolveNameResponse, SendRequest, SendResponse, METHOD_QUOTE, METHOD_RECEIVE_POLL,
+    METHOD_RESOLVE_NAME, METHOD_SEND,
+};
+use bllvm_mesh::error::MeshError;
+use bllvm_mesh::manager::MeshManager;
+use bllvm_mesh::packet::{MeshPacketBuilder, PacketType};
+use common::{test_context, MockNodeAPI};
+use serde_json::json;
+use std::sync::Arc;
+
+const PEER_ADDR: &str = "10.0.0.9:8334";
+
+async fn open_manager() -> (MeshManager, Arc<MockNodeAPI>) {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let ctx = test_context(&[("mesh.enabled", "true"), ("mesh.mode", "open")]);
+    let manager = MeshManager::new(&ctx, node_api.clone()).await.unwrap();
+    (manager, node_api)
+}
+
+#[tokio::test]
+async fn test_send() {
+    let (manager, node_api) = open_manager().await;
+    let peer = [9u8; 32];
+    manager
+        .routing_table()
+        .add_direct_peer(peer, PEER_ADDR.as_bytes().to_vec());
+
+    let request = SendRequest {
+        destination: peer,
+        packet_type: PacketType::BitcoinP2P,
+        payload: vec![1, 2, 3],
+        payment_proof: None,
+    };
+    let response = manager
+        .handle_api_call(METHOD_SEND, &api::encode(&request).unwrap())
+        .await
+        .unwrap();
+    let _: SendResponse = api::decode(&response).unwrap();
+
+    let sent = node_api.take_sent();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].0, PEER_ADDR);
+}
+
+#[tokio::test]
+async fn test_receive_poll() {
+    let (manager, _node_api) = open_manager().await;
+    let packet = MeshPacketBuilder::new([7u8; 32], manager.node_id())
+        .packet_type(PacketType::BitcoinP2P)
+        .payload(vec![4, 5, 6])
+        .build()
+        .unwrap();
+    manager.handle_incoming_packet(&packet).await.unwrap();
+
+    let request = ReceivePollRequest { max_packets: 10 };
+    let response = manager
+        .handle_api_call(METHOD_RECEIVE_POLL, &api::encode(&request).unwrap())
+        .await
+        .unwrap();
+    let response: ReceivePollResponse = api::decode(&response).unwrap();
+    assert_eq!(response.packets.len(), 1);
+    assert_eq!(response.packets[0].source, [7u8; 32]);
+    assert_eq!(response.packets[0].payload, vec![4, 5, 6]);
+
+    // Queue is drained by polling
+    let response = manager
+        .handle_api_call(METHOD_RECEIVE_POLL, &api::encode(&request).unwrap())
+        .await
+        .unwrap();
+    let response: ReceivePollResponse = api::decode(&response).unwrap();
+    assert!(response.packets.is_empty());
+}
+
+#[tokio::test]
+async fn test_quote() {
+    let (manager, _node_api) = open_manager().await;
+    let peer = [9u8; 32];
+    manager
+        .routing_table()
+        .add_direct_peer(peer, PEER_ADDR.as_bytes().to_vec());
+
+    let request = QuoteRequest {
+        destination: peer,
+        payload_len: 2048,
+    };
+    let response = manager
+        .handle_api_call(METHOD_QUOTE, &api::encode(&request).unwrap())
+        .await
+        .unwrap();
+    let response: QuoteResponse = api::decode(&response).unwrap();
+    assert!(response.routable);
+    assert_eq!(response.hop_count, 1);
+    assert!(response.amount_sats > 0);
+}
+
+#[tokio::test]
+async fn test_resolve_name() {
+    let (manager, _node_api) = open_manager().await;
+
+    let request = ResolveNameRequest {
+        name: hex::encode([5u8; 32]),
+    };
+    let response = manager
+        .handle_api_call(METHOD_RESOLVE_NAME, &api::encode(&request).unwrap())
+        .await
+        .unwrap();
+    let response: ResolveNameResponse = api::decode(&response).unwrap();
+    assert_eq!(response.node_id, Some([5u8; 32]));
+}
+
+#[tokio::test]
+async fn test_malformed_request() {
+    let (manager, _node_api) = open_manager().await;
+
+    let result = manager.handle_api_call(METHOD_QUOTE, &[0xff, 0x01]).await;
+    assert!(matches!(result, Err(MeshError::InvalidRequest(_))));
+}
+
+#[tokio::test]
+async fn test_unknown_version_rejected() {
+    let (manager, _node_api) = open_manager().await;
+
+    let request = ReceivePollRequest { max_packets: 1 };
+    let result = manager
+        .handle_api_call("mesh.v2.receive_poll", &api::encode(&request).unwrap())
+        .await;
+    assert!(matches!(result, Err(MeshError::UnsupportedVersion(_))));
+}
+
+#[tokio::test]
+async fn test_send_rejects_internal_packet_types() {
+    let (manager, node_api) = open_manager().await;
+    let peer = [9u8; 32];
+    manager
+        .routing_table()
+        .add_direct_peer(peer, PEER_ADDR.as_bytes().to_vec());
+
+    for packet_type in [PacketType::Control, PacketType::Ack, PacketType::Discovery, PacketType::Fragment] {
+        let request = SendRequest {
+            destination: peer,
+            packet_type,
+            payload: vec![1, 2, 3],
+            payment_proof: None,
+        };
+        let result = manager
+            .handle_api_call(METHOD_SEND, &api::encode(&request).unwrap())
+            .await;
+        assert!(matches!(result, Err(MeshError::InvalidRequest(_))));
+    }
+    assert!(node_api.take_sent().is_empty());
+}
+
+#[tokio::test]
+async fn test_api_call_through_node_rpc() {
+    let (manager, _node_api) = open_manager().await;
+
+    // call_module invocations reach the same dispatcher as operator RPC
+    let request = ResolveNameRequest {
+        name: hex::encode([5u8; 32]),
+    };
+    let params = json!({ "request": BASE64.encode(api::encode(&request).unwrap()) });
+    let result = manager.handle_rpc_call(METHOD_RESOLVE_NAME, &params).await.unwrap();
+    let encoded = BASE64.decode(result["response"].as_str().unwrap()).unwrap();
+    let response: ResolveNameResponse = api::decode(&encoded).unwrap();
+    assert_eq!(response.node_id, Some([5u8; 32]));
+
+    let result = manager.handle_rpc_call("mesh.v2.resolve_name", &params).await;
+    assert!(matches!(result, Err(MeshError::UnsupportedVersion(_))));
+
+    let result = manager.handle_rpc_call(METHOD_RESOLVE_NAME, &json!({ "request": "not base64!" })).await;
+    assert!(matches!(result, Err(MeshError::InvalidRequest(_))));
+}
@@ -0,0 +1,156 @@
+//! Tests for gossiping mesh control traffic over node peers
+
+mod common;
+
+use bllvm_mesh::discovery::{DiscoveryMessage, RouteAdvertisementEntry};
+use bllvm_mesh::gossip::BloomFilter;
+use bllvm_mesh::manager::MeshManager;
+use bllvm_mesh::network::deserialize_mesh_packet;
+use bllvm_mesh::node_gossip::{NodeGossipBridge, DEFAULT_MAX_GOSSIP_FRAME_BYTES};
+use bllvm_mesh::routing::NodeId;
+use common::{test_context, MockNodeAPI};
+use std::sync::Arc;
+
+const FIVE_PEERS: [&str; 5] = [
+    "10.0.0.1:8333",
+    "10.0.0.2:8333",
+    "10.0.0.3:8333",
+    "10.0.0.4:8333",
+    "10.0.0.5:8333",
+];
+
+/// Mesh peer of the advertising node that isn't a manager in these tests
+const FAR_PEER: NodeId = [6u8; 32];
+
+async fn gossiping_manager(node_api: Arc<MockNodeAPI>) -> MeshManager {
+    let ctx = test_context(&[
+        ("mesh.enabled", "true"),
+        ("mesh.mode", "payment_gated"),
+        ("mesh.gossip_via_node", "true"),
+    ]);
+    MeshManager::new(&ctx, node_api).await.unwrap()
+}
+
+fn advertisement_bytes() -> Vec<u8> {
+    let advertisement = DiscoveryMessage::RouteAdvertisement {
+        routes: vec![RouteAdvertisementEntry {
+            destination: [3u8; 32],
+            next_hop: [2u8; 32],
+            cost: 100,
+            hop_count: 2,
+        }],
+        source: [1u8; 32],
+        sequence: 1,
+        seen: BloomFilter::default(),
+    };
+    bincode::serialize(&advertisement).unwrap()
+}
+
+#[tokio::test]
+async fn test_one_wrapped_advertisement_per_peer() {
+    let node_api = Arc::new(MockNodeAPI::with_peers(&FIVE_PEERS));
+    let bridge = NodeGossipBridge::new(node_api.clone(), DEFAULT_MAX_GOSSIP_FRAME_BYTES, 600);
+    let control = advertisement_bytes();
+
+    let sent = bridge.broadcast(&control).await.unwrap();
+    assert_eq!(sent, 5);
+
+    let sends = node_api.take_sent();
+    assert_eq!(sends.len(), 5);
+    for peer in FIVE_PEERS {
+        let frames: Vec<_> = sends.iter().filter(|(addr, _)| addr == peer).collect();
+        assert_eq!(frames.len(), 1);
+        assert!(NodeGossipBridge::is_gossip_frame(&frames[0].1));
+        assert_eq!(&frames[0].1[4..], control.as_slice());
+    }
+
+    // Re-broadcasting the same frame doesn't resend to any peer
+    assert_eq!(bridge.broadcast(&control).await.unwrap(), 0);
+    assert!(node_api.take_sent().is_empty());
+}
+
+#[tokio::test]
+async fn test_echoed_copy_is_deduplicated() {
+    let node_api = Arc::new(MockNodeAPI::with_peers(&FIVE_PEERS));
+    let bridge = NodeGossipBridge::new(node_api.clone(), DEFAULT_MAX_GOSSIP_FRAME_BYTES, 600);
+    let control = advertisement_bytes();
+
+    bridge.broadcast(&control).await.unwrap();
+    let (_, echoed) = node_api.take_sent().remove(0);
+
+    // Our own frame coming back via MessageReceived is ignored
+    assert!(bridge.accept(&echoed).is_none());
+}
+
+#[tokio::test]
+async fn test_received_frame_accepted_once() {
+    let node_api = Arc::new(MockNodeAPI::with_peers(&FIVE_PEERS));
+    let bridge = NodeGossipBridge::new(node_api, DEFAULT_MAX_GOSSIP_FRAME_BYTES, 600);
+    let frame = NodeGossipBridge::wrap(&advertisement_bytes());
+
+    assert_eq!(bridge.accept(&frame), Some(advertisement_bytes()));
+    assert!(bridge.accept(&frame).is_none());
+
+    // Non-gossip data is ignored
+    assert!(bridge.accept(b"MESH\x00\x00").is_none());
+}
+
+#[tokio::test]
+async fn test_oversized_frame_rejected() {
+    let node_api = Arc::new(MockNodeAPI::with_peers(&FIVE_PEERS));
+    let bridge = NodeGossipBridge::new(node_api.clone(), 64, 600);
+
+    assert!(bridge.broadcast(&[0u8; 128]).await.is_err());
+    assert!(node_api.take_sent().is_empty());
+}
+
+#[tokio::test]
+async fn test_manager_advertises_through_gossip_bridge() {
+    let node_api = Arc::new(MockNodeAPI::with_peers(&FIVE_PEERS));
+    let advertiser = gossiping_manager(node_api.clone()).await;
+    let receiver = gossiping_manager(Arc::new(MockNodeAPI::new())).await;
+
+    // Two of the five node peers are also mesh peers
+    advertiser.add_direct_peer(receiver.node_id(), FIVE_PEERS[0], "tcp");
+    advertiser.add_direct_peer(FAR_PEER, FIVE_PEERS[1], "tcp");
+    receiver.add_direct_peer(advertiser.node_id(), "10.0.0.9:8333", "tcp");
+    receiver
+        .register_peer_key(advertiser.node_id(), &advertiser.signing_public_key())
+        .unwrap();
+
+    assert_eq!(advertiser.advertise_routes().await.unwrap(), 2);
+
+    // Each mesh peer's advertisement is a gossip frame sent to every node peer
+    let sends = node_api.take_sent();
+    assert_eq!(sends.len(), 2 * FIVE_PEERS.len());
+    assert!(sends.iter().all(|(_, frame)| NodeGossipBridge::is_gossip_frame(frame)));
+    for peer in FIVE_PEERS {
+        assert_eq!(sends.iter().filter(|(addr, _)| addr == peer).count(), 2);
+    }
+
+    // The receiver learns the route from its frame and ignores the one for FAR_PEER
+    for (_, frame) in sends.iter().filter(|(addr, _)| addr == FIVE_PEERS[0]) {
+        receiver.handle_gossip_frame(frame).await.unwrap();
+    }
+    let route = receiver.routing_table().get_route(&FAR_PEER).unwrap();
+    assert_eq!(route.next_hop, Some(advertiser.node_id()));
+}
+
+#[tokio::test]
+async fn test_gossip_frame_for_another_node_ignored() {
+    let node_api = Arc::new(MockNodeAPI::with_peers(&FIVE_PEERS[..1]));
+    let advertiser = gossiping_manager(node_api.clone()).await;
+    let bystander = gossiping_manager(Arc::new(MockNodeAPI::new())).await;
+    advertiser.add_direct_peer(FAR_PEER, FIVE_PEERS[0], "tcp");
+    advertiser.add_direct_peer([7u8; 32], FIVE_PEERS[1], "tcp");
+    bystander.add_direct_peer(advertiser.node_id(), "10.0.0.9:8333", "tcp");
+
+    advertiser.advertise_routes().await.unwrap();
+    let (_, frame) = node_api.take_sent().remove(0);
+    let packet = deserialize_mesh_packet(&frame[4..]).unwrap();
+    assert_ne!(packet.destination, bystander.node_id());
+
+    bystander.handle_gossip_frame(&frame).await.unwrap();
+    assert!(bystander.routing_table().get_route(&FAR_PEER).is_none());
+    assert!(bystander.routing_table().get_route(&[7u8; 32]).is_none());
+}
@@ -0,0 +1,69 @@
+//! Tests for deriving the mesh node ID
+
+mod common;
+
+use bllvm_mesh::config::CONFIG_TREE;
+use bllvm_mesh::manager::MeshManager;
+use common::{test_context, MockNodeAPI};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+
+const PUBKEY: [u8; 33] = [2u8; 33];
+
+fn stored_node_id(node_api: &MockNodeAPI) -> Option<Vec<u8>> {
+    node_api
+        .storage
+        .lock()
+        .unwrap()
+        .get(CONFIG_TREE)
+        .and_then(|tree| tree.get(b"node_id".as_slice()).cloned())
+}
+
+fn with_pubkey() -> MockNodeAPI {
+    MockNodeAPI {
+        node_public_key: Some(PUBKEY.to_vec()),
+        ..MockNodeAPI::default()
+    }
+}
+
+#[tokio::test]
+async fn test_node_id_derived_from_signing_key() {
+    let node_api = Arc::new(with_pubkey());
+    let ctx = test_context(&[("mesh.enabled", "true")]);
+    let manager = MeshManager::new(&ctx, node_api.clone()).await.unwrap();
+
+    // Bound to the key Hello is signed with, not the node public key
+    let expected: [u8; 32] = Sha256::digest(manager.signing_public_key()).into();
+    assert_eq!(manager.node_id(), expected);
+    assert_ne!(manager.node_id(), <[u8; 32]>::from(Sha256::digest(PUBKEY)));
+    assert_eq!(stored_node_id(&node_api), Some(expected.to_vec()));
+}
+
+#[tokio::test]
+async fn test_signing_key_replaces_stored_node_id() {
+    let node_api = Arc::new(with_pubkey());
+    node_api
+        .storage
+        .lock()
+        .unwrap()
+        .entry(CONFIG_TREE.to_string())
+        .or_default()
+        .insert(b"node_id".to_vec(), vec![7u8; 32]);
+
+    let ctx = test_context(&[("mesh.enabled", "true")]);
+    let manager = MeshManager::new(&ctx, node_api.clone()).await.unwrap();
+
+    let expected: [u8; 32] = Sha256::digest(manager.signing_public_key()).into();
+    assert_eq!(manager.node_id(), expected);
+    assert_eq!(stored_node_id(&node_api), Some(expected.to_vec()));
+}
+
+#[tokio::test]
+async fn test_node_id_stable_across_restarts() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let ctx = test_context(&[("mesh.enabled", "true")]);
+    let first = MeshManager::new(&ctx, node_api.clone()).await.unwrap().node_id();
+    let second = MeshManager::new(&ctx, node_api.clone()).await.unwrap().node_id();
+    assert_eq!(first, second);
+    assert_eq!(stored_node_id(&node_api), Some(first.to_vec()));
+}
@@ -0,0 +1,73 @@
+//! Tests for the caching NodeAPI wrapper
+
+mod common;
+
+use bllvm_mesh::clock::ManualClock;
+use bllvm_mesh::nodeapi_cached::{CacheStats, NodeApiIpcCached, CHAIN_TIP_TTL_SECS, MEMPOOL_TTL_SECS};
+use bllvm_node::module::traits::NodeAPI;
+use common::MockNodeAPI;
+use std::sync::Arc;
+
+fn cached(capacity: usize) -> (NodeApiIpcCached, Arc<MockNodeAPI>, Arc<ManualClock>) {
+    let inner = Arc::new(MockNodeAPI::new());
+    let clock = Arc::new(ManualClock::new(1_000));
+    let api = NodeApiIpcCached::with_capacity(inner.clone(), capacity).with_clock(clock.clone());
+    (api, inner, clock)
+}
+
+#[tokio::test]
+async fn test_repeated_queries_hit_cache_until_ttl() {
+    let (api, inner, clock) = cached(16);
+
+    assert_eq!(api.get_chain_tip().await.unwrap(), [0u8; 32]);
+    assert_eq!(api.get_chain_tip().await.unwrap(), [0u8; 32]);
+    assert_eq!(api.get_block_height().await.unwrap(), 100);
+    assert_eq!(api.get_block_height().await.unwrap(), 100);
+    assert_eq!(inner.call_count(), 2);
+    assert_eq!(
+        api.get_cache_stats(),
+        CacheStats {
+            hits: 2,
+            misses: 2,
+            evictions: 0
+        }
+    );
+
+    // Mempool entries expire first
+    api.get_mempool_transactions().await.unwrap();
+    clock.advance(MEMPOOL_TTL_SECS);
+    api.get_mempool_transactions().await.unwrap();
+    api.get_chain_tip().await.unwrap();
+    assert_eq!(inner.call_count(), 4);
+
+    clock.advance(CHAIN_TIP_TTL_SECS);
+    api.get_chain_tip().await.unwrap();
+    assert_eq!(inner.call_count(), 5);
+}
+
+#[tokio::test]
+async fn test_keys_include_arguments_and_lru_evicts() {
+    let (api, inner, _) = cached(2);
+
+    api.get_block(&[1u8; 32]).await.unwrap();
+    api.get_block(&[2u8; 32]).await.unwrap();
+    api.get_block(&[1u8; 32]).await.unwrap();
+    assert_eq!(inner.call_count(), 2);
+
+    // [2; 32] is least recently used and makes room
+    api.get_block(&[3u8; 32]).await.unwrap();
+    assert_eq!(api.get_cache_stats().evictions, 1);
+    api.get_block(&[1u8; 32]).await.unwrap();
+    assert_eq!(inner.call_count(), 3);
+    api.get_block(&[2u8; 32]).await.unwrap();
+    assert_eq!(inner.call_count(), 4);
+}
+
+#[tokio::test]
+async fn test_other_methods_pass_through() {
+    let (api, inner, _) = cached(16);
+    api.get_network_peers().await.unwrap();
+    api.get_network_peers().await.unwrap();
+    assert_eq!(inner.call_count(), 2);
+    assert_eq!(api.get_cache_stats(), CacheStats::default());
+}
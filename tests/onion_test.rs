@@ -0,0 +1,97 @@
+//! Tests for onion-routed packets across relays
+
+mod common;
+
+use bllvm_mesh::manager::MeshManager;
+use bllvm_mesh::network::deserialize_mesh_packet;
+use bllvm_mesh::onion::{OnionPacketBuilder, ONION_PACKET_SIZE};
+use common::{test_context, MockNodeAPI};
+use std::sync::Arc;
+
+const PAYLOAD: &[u8] = b"onion-routed secret payload";
+
+struct Node {
+    manager: MeshManager,
+    api: Arc<MockNodeAPI>,
+    addr: &'static str,
+}
+
+async fn node(addr: &'static str) -> Node {
+    let api = Arc::new(MockNodeAPI::new());
+    let ctx = test_context(&[("mesh.enabled", "true"), ("mesh.mode", "open")]);
+    let manager = MeshManager::new(&ctx, api.clone()).await.unwrap();
+    Node { manager, api, addr }
+}
+
+fn link(a: &Node, b: &Node) {
+    a.manager.routing_table().add_direct_peer(b.manager.node_id(), b.addr.as_bytes().to_vec());
+    b.manager.routing_table().add_direct_peer(a.manager.node_id(), a.addr.as_bytes().to_vec());
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|window| window == needle)
+}
+
+#[tokio::test]
+async fn test_onion_packet_crosses_two_relays() {
+    let sender = node("10.0.0.1:8334").await;
+    let relay_a = node("10.0.0.2:8334").await;
+    let relay_b = node("10.0.0.3:8334").await;
+    let destination = node("10.0.0.4:8334").await;
+    link(&sender, &relay_a);
+    link(&relay_a, &relay_b);
+    link(&relay_b, &destination);
+
+    let hops = [&relay_a, &relay_b, &destination];
+    let packet = OnionPacketBuilder::new(
+        hops.iter().map(|n| n.manager.node_id()).collect(),
+        PAYLOAD.to_vec(),
+        hops.iter().map(|n| n.manager.onion_public_key()).collect(),
+    )
+    .build(sender.manager.node_id())
+    .unwrap();
+    sender.manager.route_packet(&packet).await.unwrap();
+
+    // Shuttle the packet hop by hop
+    let mut previous = &sender;
+    for hop in hops {
+        let sent = previous.api.take_sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].0, hop.addr);
+        assert!(!contains(&sent[0].1, PAYLOAD), "payload visible on the wire");
+
+        let received = deserialize_mesh_packet(&sent[0].1).unwrap();
+        assert_eq!(received.source, previous.manager.node_id());
+        assert_eq!(received.payload.len(), ONION_PACKET_SIZE);
+        hop.manager.handle_incoming_packet(&received).await.unwrap();
+        previous = hop;
+    }
+
+    // Relays only forwarded; the destination got the plaintext
+    assert!(relay_a.manager.poll_delivered(10).is_empty());
+    assert!(relay_b.manager.poll_delivered(10).is_empty());
+    let delivered = destination.manager.poll_delivered(10);
+    assert_eq!(delivered.len(), 1);
+    assert_eq!(delivered[0].payload, PAYLOAD);
+    assert!(destination.api.take_sent().is_empty());
+}
+
+#[tokio::test]
+async fn test_onion_packet_for_wrong_key_is_rejected() {
+    let sender = node("10.0.0.1:8334").await;
+    let relay = node("10.0.0.2:8334").await;
+    let other = node("10.0.0.3:8334").await;
+    link(&sender, &relay);
+
+    // Layer encrypted to a different node's key
+    let packet = OnionPacketBuilder::new(
+        vec![relay.manager.node_id()],
+        PAYLOAD.to_vec(),
+        vec![other.manager.onion_public_key()],
+    )
+    .build(sender.manager.node_id())
+    .unwrap();
+
+    assert!(relay.manager.handle_incoming_packet(&packet).await.is_err());
+    assert!(relay.manager.poll_delivered(10).is_empty());
+}
@@ -0,0 +1,153 @@
+//! Tests for checking payment amounts against the fee for a packet
+
+mod common;
+
+use bllvm_mesh::error::MeshError;
+use bllvm_mesh::manager::MeshManager;
+use bllvm_mesh::packet::{MeshPacket, MeshPacketBuilder, PacketType};
+use bllvm_mesh::payment_proof::PaymentProof;
+use bllvm_mesh::routing::{NodeId, RoutingEntry};
+use common::{test_context, MockNodeAPI, TEST_LIGHTNING_KEY};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const PEER: NodeId = [2u8; 32];
+const DESTINATION: NodeId = [3u8; 32];
+
+/// Bitcoin mainnet `inv` message header
+const BITCOIN_INV: [u8; 24] = [
+    0xf9, 0xbe, 0xb4, 0xd9, b'i', b'n', b'v', 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+];
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+/// Manager charging 1 sat per KB plus 2 sats per hop, two hops from DESTINATION
+async fn manager(node_api: Arc<MockNodeAPI>) -> MeshManager {
+    let ctx = test_context(&[
+        ("mesh.enabled", "true"),
+        ("mesh.mode", "payment_gated"),
+        ("mesh.fee.rate_sats_per_kb", "1"),
+        ("mesh.fee.hop_surcharge_sats", "2"),
+        ("mesh.keysend_verify_settlement", "false"),
+        ("mesh.payment.local_payee", TEST_LIGHTNING_KEY),
+    ]);
+    let manager = MeshManager::new(&ctx, node_api).await.unwrap();
+    manager.routing_table().add_direct_peer(PEER, b"10.0.0.2:8334".to_vec());
+    manager.routing_table().add_route(RoutingEntry {
+        node_id: DESTINATION,
+        direct_address: None,
+        next_hop: Some(PEER),
+        route_path: vec![manager.node_id(), PEER, DESTINATION],
+        route_cost: 200,
+        last_updated: now(),
+        quality_score: 0.8,
+    });
+    manager
+}
+
+fn keysend_proof(preimage: [u8; 32], amount_sats: u64) -> PaymentProof {
+    PaymentProof::Keysend {
+        payment_hash: Sha256::digest(preimage).into(),
+        preimage,
+        amount_msats: amount_sats * 1000,
+        timestamp: now(),
+        custom_tlv: Vec::new(),
+        destination_pubkey: vec![2u8; 33],
+    }
+}
+
+/// 4 KB paid packet: (4 + 2) sats per hop over two hops
+///
+/// Each packet needs its own sequence to pass the replay window.
+fn paid_packet(manager: &MeshManager, sequence: u64, proof: Option<PaymentProof>) -> MeshPacket {
+    let mut builder = MeshPacketBuilder::new(manager.node_id(), DESTINATION)
+        .packet_type(PacketType::BitcoinP2P)
+        .payload(vec![0x12; 4096])
+        .sequence(sequence);
+    if let Some(proof) = proof {
+        builder = builder.payment_proof(proof);
+    }
+    builder.build().unwrap()
+}
+
+#[tokio::test]
+async fn test_underpaid_packet_rejected() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let manager = manager(node_api.clone()).await;
+    assert_eq!(manager.required_payment(&paid_packet(&manager, 1, None)), 12);
+
+    let result = manager
+        .route_packet(&paid_packet(&manager, 1, Some(keysend_proof([1u8; 32], 10))))
+        .await;
+    match result {
+        Err(MeshError::PaymentVerification(reason)) => assert_eq!(reason, "insufficient payment: need 12, got 10"),
+        other => panic!("expected an underpayment error, got {:?}", other),
+    }
+    assert!(node_api.take_sent().is_empty());
+
+    // Overpayment is accepted and counted
+    manager
+        .route_packet(&paid_packet(&manager, 2, Some(keysend_proof([2u8; 32], 20))))
+        .await
+        .unwrap();
+    assert_eq!(node_api.take_sent().len(), 1);
+    let text = manager.metrics_exporter().render().await;
+    assert!(text.contains("mesh_payments_underpaid_total 1\n"));
+    assert!(text.contains("mesh_payment_overpaid_sats_total 8\n"));
+}
+
+#[tokio::test]
+async fn test_free_traffic_needs_no_payment() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let manager = manager(node_api.clone()).await;
+
+    let packet = MeshPacketBuilder::new(manager.node_id(), DESTINATION)
+        .packet_type(PacketType::BitcoinP2P)
+        .payload(BITCOIN_INV.to_vec())
+        .build()
+        .unwrap();
+    manager.route_packet(&packet).await.unwrap();
+    assert_eq!(node_api.take_sent().len(), 1);
+}
+
+#[tokio::test]
+async fn test_source_route_priced_by_declared_hops() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let manager = manager(node_api.clone()).await;
+
+    // Three hops declared by the sender, whatever the routing table says
+    let mut packet = paid_packet(&manager, 1, None);
+    packet.source_routed = true;
+    packet.fixed_route = vec![manager.node_id(), PEER, [4u8; 32], DESTINATION];
+    assert_eq!(manager.required_payment(&packet), 18);
+}
+
+#[tokio::test]
+async fn test_failed_verification_burns_neither_proof_nor_sequence() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let manager = manager(node_api.clone()).await;
+
+    let underpaid = paid_packet(&manager, 1, Some(keysend_proof([1u8; 32], 10)));
+    assert!(matches!(
+        manager.route_packet(&underpaid).await,
+        Err(MeshError::PaymentVerification(_))
+    ));
+
+    // The sequence is still free for a packet that pays
+    manager
+        .route_packet(&paid_packet(&manager, 1, Some(keysend_proof([2u8; 32], 20))))
+        .await
+        .unwrap();
+    assert_eq!(node_api.take_sent().len(), 1);
+
+    // A verified proof is burned
+    assert!(matches!(
+        manager
+            .route_packet(&paid_packet(&manager, 2, Some(keysend_proof([2u8; 32], 20))))
+            .await,
+        Err(MeshError::ReplayDetected(_))
+    ));
+}
@@ -0,0 +1,101 @@
+//! Tests for the outgoing packet priority queue
+
+mod common;
+
+use bllvm_mesh::error::MeshError;
+use bllvm_mesh::manager::MeshManager;
+use bllvm_mesh::packet::{MeshPacketBuilder, PacketType};
+use bllvm_mesh::payment_proof::PaymentProof;
+use bllvm_mesh::priority_queue::Priority;
+use common::{test_context, MockNodeAPI};
+use futures::FutureExt;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const PEER: [u8; 32] = [2u8; 32];
+
+/// Bitcoin mainnet `inv` message header
+const BITCOIN_INV: [u8; 24] = [
+    0xf9, 0xbe, 0xb4, 0xd9, b'i', b'n', b'v', 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+];
+
+async fn manager(node_api: Arc<MockNodeAPI>) -> MeshManager {
+    let ctx = test_context(&[("mesh.enabled", "true"), ("mesh.mode", "open")]);
+    let manager = MeshManager::new(&ctx, node_api).await.unwrap();
+    manager.routing_table().add_direct_peer(PEER, b"10.0.0.2:8334".to_vec());
+    manager
+}
+
+#[tokio::test]
+async fn test_packet_priority_classes() {
+    let manager = manager(Arc::new(MockNodeAPI::new())).await;
+    let packet = |packet_type, payload: &[u8]| {
+        MeshPacketBuilder::new(manager.node_id(), PEER)
+            .packet_type(packet_type)
+            .payload(payload.to_vec())
+            .build()
+            .unwrap()
+    };
+
+    assert_eq!(manager.packet_priority(&packet(PacketType::BitcoinP2P, &BITCOIN_INV)), Priority::High);
+    assert_eq!(manager.packet_priority(&packet(PacketType::Discovery, b"request")), Priority::High);
+    assert_eq!(manager.packet_priority(&packet(PacketType::BitcoinP2P, b"arbitrary data")), Priority::Low);
+
+    // A payment proof lifts unclassified traffic (verified later, when routed)
+    let preimage = [7u8; 32];
+    let proof = PaymentProof::Keysend {
+        payment_hash: Sha256::digest(preimage).into(),
+        preimage,
+        amount_msats: 10_000,
+        timestamp: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(),
+        custom_tlv: Vec::new(),
+        destination_pubkey: vec![2u8; 33],
+    };
+    let paid = MeshPacketBuilder::new(manager.node_id(), PEER)
+        .packet_type(PacketType::Paid)
+        .payload(b"arbitrary data".to_vec())
+        .payment_proof(proof)
+        .build()
+        .unwrap();
+    assert_eq!(manager.packet_priority(&paid), Priority::Medium);
+}
+
+#[tokio::test]
+async fn test_queued_packets_are_routed() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let manager = Arc::new(manager(node_api.clone()).await);
+    let queue_manager = Arc::clone(&manager);
+    tokio::spawn(async move { queue_manager.run_packet_queue().await });
+    tokio::task::yield_now().await;
+
+    manager.send_packet(PEER, BITCOIN_INV.to_vec(), None).await.unwrap();
+    assert_eq!(node_api.take_sent().len(), 1);
+
+    // Routing errors reach the sender through the queue
+    assert!(manager.send_packet([9u8; 32], BITCOIN_INV.to_vec(), None).await.is_err());
+    assert!(node_api.take_sent().is_empty());
+}
+
+#[tokio::test]
+async fn test_stopped_queue_fails_waiting_senders() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let manager = Arc::new(manager(node_api.clone()).await);
+
+    // Start the drain but never poll it again, so queued packets stay queued
+    let mut drain = Box::pin(manager.run_packet_queue());
+    assert!(drain.as_mut().now_or_never().is_none());
+    let sender = Arc::clone(&manager);
+    let waiting = tokio::spawn(async move { sender.send_packet(PEER, BITCOIN_INV.to_vec(), None).await });
+    tokio::task::yield_now().await;
+    assert!(!waiting.is_finished());
+
+    // Stopping the drain fails the waiting sender instead of leaving it hanging
+    drop(drain);
+    assert!(matches!(waiting.await.unwrap(), Err(MeshError::MeshDisabled(_))));
+    assert!(node_api.take_sent().is_empty());
+
+    // Later packets are routed directly again
+    manager.send_packet(PEER, BITCOIN_INV.to_vec(), None).await.unwrap();
+    assert_eq!(node_api.take_sent().len(), 1);
+}
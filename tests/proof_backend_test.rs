@@ -0,0 +1,117 @@
+//! Tests for pluggable payment proof backends
+
+mod common;
+
+use bllvm_mesh::error::MeshError;
+use bllvm_mesh::payment_proof::{PaymentProof, VerificationResult};
+use bllvm_mesh::proof_backend::{ProofBackend, VerifyContext, LIGHTNING_PROOF_KIND};
+use bllvm_mesh::verifier::PaymentVerifier;
+use common::MockNodeAPI;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Accepts blobs equal to its token, for the amount claimed
+struct TokenBackend {
+    token: Vec<u8>,
+}
+
+#[async_trait::async_trait]
+impl ProofBackend for TokenBackend {
+    fn kind(&self) -> &str {
+        "ecash"
+    }
+
+    async fn verify(&self, proof_bytes: &[u8], ctx: &VerifyContext) -> Result<VerificationResult, MeshError> {
+        if proof_bytes != self.token.as_slice() {
+            return Ok(VerificationResult::failure("Unknown token".to_string()));
+        }
+        Ok(VerificationResult::success(ctx.amount_sats, ctx.timestamp, None))
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+fn custom_proof(kind: &str, blob: &[u8], expires_at: u64) -> PaymentProof {
+    PaymentProof::Custom {
+        kind: kind.to_string(),
+        blob: blob.to_vec(),
+        amount_sats: 500,
+        timestamp: now(),
+        expires_at,
+    }
+}
+
+fn verifier() -> PaymentVerifier {
+    PaymentVerifier::new(Arc::new(MockNodeAPI::new()))
+        .with_backend(Arc::new(TokenBackend { token: b"token".to_vec() }))
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_custom_proof_dispatched_by_kind() {
+    let verifier = verifier();
+    let expires_at = now() + 60;
+
+    let result = verifier.verify(&custom_proof("ecash", b"token", expires_at)).await.unwrap();
+    assert!(result.verified, "{:?}", result.error);
+    assert_eq!(result.amount, 500);
+    // Cached no longer than the proof claims to be valid
+    assert_eq!(result.expires_at, Some(expires_at));
+
+    let result = verifier.verify(&custom_proof("ecash", b"forged", expires_at)).await.unwrap();
+    assert_eq!(result.error.as_deref(), Some("Unknown token"));
+
+    let result = verifier.verify(&custom_proof("ecash", b"token", now() - 1)).await.unwrap();
+    assert_eq!(result.error.as_deref(), Some("Payment proof expired"));
+}
+
+#[tokio::test]
+async fn test_unknown_kind_fails_closed() {
+    let verifier = verifier();
+    let result = verifier.verify(&custom_proof("sidechain", b"token", now() + 60)).await.unwrap();
+    assert!(!result.verified);
+    assert_eq!(
+        result.error.as_deref(),
+        Some("No verification backend for proof kind sidechain")
+    );
+}
+
+#[test]
+fn test_built_in_kinds_cannot_be_replaced() {
+    struct Impostor;
+
+    #[async_trait::async_trait]
+    impl ProofBackend for Impostor {
+        fn kind(&self) -> &str {
+            LIGHTNING_PROOF_KIND
+        }
+
+        async fn verify(&self, _: &[u8], ctx: &VerifyContext) -> Result<VerificationResult, MeshError> {
+            Ok(VerificationResult::success(ctx.amount_sats, ctx.timestamp, None))
+        }
+    }
+
+    assert!(PaymentVerifier::new(Arc::new(MockNodeAPI::new()))
+        .with_backend(Arc::new(Impostor))
+        .is_err());
+    assert!(verifier().backend_kinds().contains(&"ecash".to_string()));
+}
+
+#[test]
+fn test_custom_hash_ignores_restamping() {
+    let proof = custom_proof("ecash", b"token", now() + 60);
+    let PaymentProof::Custom { kind, blob, .. } = proof.clone() else {
+        unreachable!()
+    };
+    let restamped = PaymentProof::Custom {
+        kind,
+        blob,
+        amount_sats: 1_000,
+        timestamp: now() + 5,
+        expires_at: now() + 3600,
+    };
+    assert_eq!(proof.hash(), restamped.hash());
+    assert_ne!(proof.hash(), custom_proof("ecash", b"other", now() + 60).hash());
+}
@@ -0,0 +1,68 @@
+//! Tests for replay prevention state surviving restarts
+
+mod common;
+
+use bllvm_mesh::payment_proof::PaymentProof;
+use bllvm_mesh::replay::{ReplayPrevention, ReplayWindowConfig};
+use common::MockNodeAPI;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const PEER: [u8; 32] = [7u8; 32];
+
+fn proof(preimage: u8) -> PaymentProof {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    PaymentProof::Lightning {
+        invoice: format!("lnbc10n1test{}", preimage),
+        preimage: [preimage; 32],
+        amount_msats: 10_000,
+        timestamp: now,
+        expires_at: now + 3600,
+    }
+}
+
+async fn load(node_api: &Arc<MockNodeAPI>, config: ReplayWindowConfig) -> ReplayPrevention {
+    ReplayPrevention::load_from_storage(node_api.clone(), config).await.unwrap()
+}
+
+#[tokio::test]
+async fn test_used_proof_rejected_after_restart() {
+    let node_api = Arc::new(MockNodeAPI::new());
+
+    let before = load(&node_api, ReplayWindowConfig::default()).await;
+    before.check_replay(&proof(1), &PEER, 10).unwrap();
+    assert!(!before.needs_flush());
+    assert_eq!(before.flush().await.unwrap(), 2);
+    drop(before);
+
+    let after = load(&node_api, ReplayWindowConfig::default()).await;
+    assert!(after.check_replay(&proof(1), &PEER, 11).is_err());
+    // The sequence window survived too
+    assert!(after.check_replay(&proof(2), &PEER, 10).is_err());
+    assert!(after.check_replay(&proof(3), &PEER, 11).is_ok());
+}
+
+#[tokio::test]
+async fn test_flush_batches_changes() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let replay = load(&node_api, ReplayWindowConfig::default().with_flush_batch(4)).await;
+
+    // Each accepted proof adds a hash and touches the peer's window
+    replay.check_replay(&proof(1), &PEER, 1).unwrap();
+    replay.check_replay(&proof(2), &PEER, 2).unwrap();
+    assert!(!replay.needs_flush());
+    replay.check_replay(&proof(3), &PEER, 3).unwrap();
+    assert!(replay.needs_flush());
+
+    replay.flush().await.unwrap();
+    assert!(!replay.needs_flush());
+    assert_eq!(replay.flush().await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn test_in_memory_replay_never_flushes() {
+    let replay = ReplayPrevention::new(ReplayWindowConfig::default().with_flush_batch(1));
+    replay.check_replay(&proof(1), &PEER, 1).unwrap();
+    assert!(!replay.needs_flush());
+    assert_eq!(replay.flush().await.unwrap(), 0);
+}
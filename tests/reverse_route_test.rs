@@ -0,0 +1,60 @@
+//! Tests for route responses travelling back along reverse routes
+
+mod common;
+
+use bllvm_mesh::discovery::DiscoveryMessage;
+use bllvm_mesh::manager::MeshManager;
+use bllvm_mesh::packet::{MeshPacket, MeshPacketBuilder, PacketType};
+use bllvm_mesh::routing::{NodeId, RoutingEntry};
+use common::{test_context, MockNodeAPI};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const RELAY: NodeId = [2u8; 32];
+const RESPONDER: NodeId = [3u8; 32];
+const REQUESTER: NodeId = [4u8; 32];
+const RELAY_ADDR: &str = "10.0.0.2:8334";
+
+fn response(manager: &MeshManager) -> MeshPacket {
+    let message = DiscoveryMessage::RouteResponse {
+        destination: RESPONDER,
+        source: REQUESTER,
+        request_id: 1,
+        route: vec![REQUESTER, RELAY, manager.node_id(), RESPONDER],
+        cost: 400,
+    };
+    MeshPacketBuilder::new(RESPONDER, REQUESTER)
+        .packet_type(PacketType::Discovery)
+        .payload(message.encode().unwrap())
+        .build()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_response_without_reverse_route_is_dropped() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let ctx = test_context(&[("mesh.enabled", "true"), ("mesh.mode", "open")]);
+    let manager = MeshManager::new(&ctx, node_api.clone()).await.unwrap();
+    manager.routing_table().add_direct_peer(RELAY, RELAY_ADDR.as_bytes().to_vec());
+
+    // The reverse route to the requester expired: no discovery is started for it
+    manager.handle_incoming_packet(&response(&manager)).await.unwrap();
+    assert!(node_api.take_sent().is_empty());
+    let text = manager.metrics_exporter().render().await;
+    assert!(text.contains("mesh_route_responses_orphaned_total 1\n"));
+
+    // With the reverse route in place the response is unicast back
+    manager.routing_table().add_route(RoutingEntry {
+        node_id: REQUESTER,
+        direct_address: None,
+        next_hop: Some(RELAY),
+        route_path: vec![manager.node_id(), RELAY, REQUESTER],
+        route_cost: 300,
+        last_updated: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(),
+        quality_score: 0.7,
+    });
+    manager.handle_incoming_packet(&response(&manager)).await.unwrap();
+    let sent = node_api.take_sent();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].0, RELAY_ADDR);
+}
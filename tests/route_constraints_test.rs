@@ -0,0 +1,178 @@
+//! Tests for sender-side route constraints
+
+mod common;
+
+use bllvm_mesh::control::ControlMessage;
+use bllvm_mesh::error::MeshError;
+use bllvm_mesh::manager::MeshManager;
+use bllvm_mesh::network::deserialize_mesh_packet;
+use bllvm_mesh::packet::{MeshPacket, MeshPacketBuilder, PacketMetadata, PacketType, RouteConstraints};
+use bllvm_mesh::routing::{NodeId, RoutingEntry};
+use common::{test_context, MockNodeAPI};
+use std::sync::Arc;
+
+const RELAY_ADDR: &str = "10.0.0.2:8334";
+const SOURCE_ADDR: &str = "10.0.0.1:8334";
+
+/// Manager that can only reach `destination` through `relay`
+async fn manager_with_single_relay(
+    relay: NodeId,
+    destination: NodeId,
+) -> (MeshManager, Arc<MockNodeAPI>) {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let ctx = test_context(&[("mesh.enabled", "true"), ("mesh.mode", "open")]);
+    let manager = MeshManager::new(&ctx, node_api.clone()).await.unwrap();
+
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    manager
+        .routing_table()
+        .add_direct_peer(relay, RELAY_ADDR.as_bytes().to_vec());
+    manager.routing_table().add_route(RoutingEntry {
+        node_id: destination,
+        direct_address: None,
+        next_hop: Some(relay),
+        route_path: vec![manager.node_id(), relay, destination],
+        route_cost: 100,
+        last_updated: now,
+        quality_score: 0.8,
+    });
+
+    (manager, node_api)
+}
+
+fn constrained_packet(source: NodeId, destination: NodeId, constraints: RouteConstraints) -> MeshPacket {
+    MeshPacketBuilder::new(source, destination)
+        .packet_type(PacketType::BitcoinP2P)
+        .payload(vec![1, 2, 3, 4])
+        .metadata(PacketMetadata {
+            route_constraints: Some(constraints),
+            ..Default::default()
+        })
+        .build()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_avoiding_only_relay_is_rejected() {
+    let relay = [2u8; 32];
+    let destination = [3u8; 32];
+    let (manager, node_api) = manager_with_single_relay(relay, destination).await;
+
+    let packet = constrained_packet(
+        manager.node_id(),
+        destination,
+        RouteConstraints {
+            avoid: vec![relay],
+            ..Default::default()
+        },
+    );
+
+    let result = manager.route_packet(&packet).await;
+    assert!(matches!(result, Err(MeshError::ConstraintUnsatisfiable(_))));
+    assert!(node_api.take_sent().is_empty());
+}
+
+#[tokio::test]
+async fn test_relaxed_constraints_deliver() {
+    let relay = [2u8; 32];
+    let destination = [3u8; 32];
+    let (manager, node_api) = manager_with_single_relay(relay, destination).await;
+
+    let packet = constrained_packet(manager.node_id(), destination, RouteConstraints::default());
+
+    manager.route_packet(&packet).await.unwrap();
+    let sent = node_api.take_sent();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].0, RELAY_ADDR);
+}
+
+#[tokio::test]
+async fn test_max_hops_constraint() {
+    let relay = [2u8; 32];
+    let destination = [3u8; 32];
+    let (manager, node_api) = manager_with_single_relay(relay, destination).await;
+
+    // Two hops are needed (us -> relay -> destination)
+    let too_short = constrained_packet(
+        manager.node_id(),
+        destination,
+        RouteConstraints {
+            max_hops: Some(1),
+            ..Default::default()
+        },
+    );
+    assert!(matches!(
+        manager.route_packet(&too_short).await,
+        Err(MeshError::ConstraintUnsatisfiable(_))
+    ));
+
+    let enough = constrained_packet(
+        manager.node_id(),
+        destination,
+        RouteConstraints {
+            max_hops: Some(2),
+            ..Default::default()
+        },
+    );
+    manager.route_packet(&enough).await.unwrap();
+    assert_eq!(node_api.take_sent().len(), 1);
+}
+
+#[tokio::test]
+async fn test_relay_reports_unsatisfiable_constraints_to_source() {
+    let source_api = Arc::new(MockNodeAPI::new());
+    let ctx = test_context(&[("mesh.enabled", "true"), ("mesh.mode", "open")]);
+    let source = MeshManager::new(&ctx, source_api).await.unwrap();
+
+    // This node relays for the source and reaches the destination only through `avoided`
+    let avoided = [2u8; 32];
+    let destination = [3u8; 32];
+    let (relay, node_api) = manager_with_single_relay(avoided, destination).await;
+    relay
+        .routing_table()
+        .add_direct_peer(source.node_id(), SOURCE_ADDR.as_bytes().to_vec());
+
+    let packet = constrained_packet(
+        source.node_id(),
+        destination,
+        RouteConstraints {
+            avoid: vec![avoided],
+            ..Default::default()
+        },
+    );
+    let result = relay.handle_incoming_packet(&packet).await;
+    assert!(matches!(result, Err(MeshError::ConstraintUnsatisfiable(_))));
+
+    // The only packet sent is the error, back to the source
+    let sent = node_api.take_sent();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].0, SOURCE_ADDR);
+    let error = deserialize_mesh_packet(&sent[0].1).unwrap();
+    assert_eq!(error.packet_type, PacketType::Control);
+    assert_eq!(error.destination, source.node_id());
+    assert_eq!(
+        ControlMessage::decode(&error.payload).unwrap(),
+        ControlMessage::ConstraintUnsatisfiable {
+            sequence: packet.sequence,
+            destination,
+        }
+    );
+
+    // The source counts the rejection
+    source.handle_incoming_packet(&error).await.unwrap();
+    let text = source.metrics_exporter().render().await;
+    assert!(text.contains("mesh_route_constraint_rejections_total 1\n"));
+}
+
+#[test]
+fn test_destination_never_avoided() {
+    let destination = [3u8; 32];
+    let constraints = RouteConstraints {
+        avoid: vec![destination],
+        ..Default::default()
+    };
+    assert!(constraints.allows(&[destination], &destination, 0));
+}
@@ -0,0 +1,111 @@
+//! Tests for route errors sent when a next hop becomes unreachable
+
+mod common;
+
+use bllvm_mesh::discovery::DiscoveryMessage;
+use bllvm_mesh::manager::MeshManager;
+use bllvm_mesh::network::deserialize_mesh_packet;
+use bllvm_mesh::packet::{MeshPacketBuilder, PacketType};
+use bllvm_mesh::routing::{NodeId, RoutingEntry};
+use common::{test_context, MockNodeAPI};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const DESTINATION: NodeId = [4u8; 32];
+const UPSTREAM_ADDR: &str = "10.0.0.2:8334";
+const NEXT_ADDR: &str = "10.0.0.3:8334";
+
+/// Node ID of a peer added with `handle_peer_connected` (derived from its address)
+fn peer_id(addr: &str) -> NodeId {
+    Sha256::digest(addr.as_bytes()).into()
+}
+
+/// Upstream learns a route to the destination through the manager, which
+/// reaches it through its direct peer `NEXT`
+async fn relay(node_api: Arc<MockNodeAPI>) -> MeshManager {
+    let ctx = test_context(&[("mesh.enabled", "true"), ("mesh.mode", "open")]);
+    let manager = MeshManager::new(&ctx, node_api.clone()).await.unwrap();
+    manager.handle_peer_connected(UPSTREAM_ADDR, "tcp");
+    manager.handle_peer_connected(NEXT_ADDR, "tcp");
+    let (upstream, next) = (peer_id(UPSTREAM_ADDR), peer_id(NEXT_ADDR));
+    manager.routing_table().add_route(RoutingEntry {
+        node_id: DESTINATION,
+        direct_address: None,
+        next_hop: Some(next),
+        route_path: vec![manager.node_id(), next, DESTINATION],
+        route_cost: 200,
+        last_updated: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(),
+        quality_score: 0.8,
+    });
+
+    let request = DiscoveryMessage::RouteRequest {
+        destination: DESTINATION,
+        source: upstream,
+        request_id: 1,
+        max_hops: 5,
+        path: vec![upstream],
+    };
+    let packet = MeshPacketBuilder::new(upstream, manager.node_id())
+        .packet_type(PacketType::Discovery)
+        .payload(request.encode().unwrap())
+        .build()
+        .unwrap();
+    manager.handle_incoming_packet(&packet).await.unwrap();
+    let sent = node_api.take_sent();
+    assert_eq!(sent.len(), 1, "the route request is answered");
+    assert_eq!(sent[0].0, UPSTREAM_ADDR);
+    manager
+}
+
+fn route_error(frame: &[u8]) -> (Vec<NodeId>, NodeId) {
+    let packet = deserialize_mesh_packet(frame).unwrap();
+    match DiscoveryMessage::decode(&packet.payload).unwrap() {
+        DiscoveryMessage::RouteError { unreachable, from } => (unreachable, from),
+        other => panic!("expected a route error, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_disconnected_next_hop_reported_upstream() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let manager = relay(node_api.clone()).await;
+
+    manager.disconnect_peer(NEXT_ADDR).await.unwrap();
+    assert!(manager.routing_table().get_route(&DESTINATION).is_none());
+
+    let sent = node_api.take_sent();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].0, UPSTREAM_ADDR);
+    assert_eq!(route_error(&sent[0].1), (vec![DESTINATION], manager.node_id()));
+}
+
+#[tokio::test]
+async fn test_route_error_propagates_once() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let manager = relay(node_api.clone()).await;
+    let next = peer_id(NEXT_ADDR);
+
+    let error = DiscoveryMessage::RouteError {
+        unreachable: vec![DESTINATION],
+        from: next,
+    };
+    let packet = MeshPacketBuilder::new(next, manager.node_id())
+        .packet_type(PacketType::Discovery)
+        .payload(error.encode().unwrap())
+        .build()
+        .unwrap();
+    manager.handle_incoming_packet(&packet).await.unwrap();
+
+    // The route through NEXT is gone and upstream is told
+    assert!(manager.routing_table().get_route(&DESTINATION).is_none());
+    let sent = node_api.take_sent();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(route_error(&sent[0].1), (vec![DESTINATION], manager.node_id()));
+
+    // A repeated error changes nothing and goes nowhere
+    let mut repeat = packet.clone();
+    repeat.sequence += 1;
+    manager.handle_incoming_packet(&repeat).await.unwrap();
+    assert!(node_api.take_sent().is_empty());
+}
@@ -0,0 +1,247 @@
+//! Tests for persisting learned routes across restarts
+
+mod common;
+
+use bllvm_mesh::error::MeshError;
+use bllvm_mesh::manager::MeshManager;
+use bllvm_mesh::routing::{RoutingEntry, RoutingTable, ROUTES_SCHEMA, ROUTE_KEY_PREFIX};
+use bllvm_mesh::storage_schema::{stored_version, PrefixStorage, SCHEMA_VERSION_KEY};
+use bllvm_mesh::subscriptions::SUBSCRIPTION_KEY_PREFIX;
+use bllvm_node::module::traits::ModuleError;
+use common::{test_context, MockNodeAPI};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const ROUTE_EXPIRY_SECONDS: u64 = 3600;
+const LOCAL: [u8; 32] = [1u8; 32];
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+fn learned_route(destination: u8, via: u8, last_updated: u64) -> RoutingEntry {
+    RoutingEntry {
+        node_id: [destination; 32],
+        direct_address: None,
+        next_hop: Some([via; 32]),
+        route_path: vec![LOCAL, [via; 32], [destination; 32]],
+        route_cost: 200,
+        last_updated,
+        quality_score: 0.8,
+    }
+}
+
+fn table() -> RoutingTable {
+    RoutingTable::new(ROUTE_EXPIRY_SECONDS).with_local_node_id(LOCAL)
+}
+
+/// Prefix scans answered from the mock's storage, recording each prefix
+struct NodePrefixStorage {
+    node_api: Arc<MockNodeAPI>,
+    prefixes: Mutex<Vec<Vec<u8>>>,
+}
+
+#[async_trait::async_trait]
+impl PrefixStorage for NodePrefixStorage {
+    async fn storage_iter_prefix(
+        &self,
+        tree_id: String,
+        prefix: Vec<u8>,
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>, ModuleError> {
+        let storage = self.node_api.storage.lock().unwrap();
+        let pairs = storage
+            .get(&tree_id)
+            .map(|tree| {
+                tree.iter()
+                    .filter(|(key, _)| key.starts_with(&prefix))
+                    .map(|(key, value)| (key.clone(), value.clone()))
+                    .collect()
+            })
+            .unwrap_or_default();
+        self.prefixes.lock().unwrap().push(prefix);
+        Ok(pairs)
+    }
+}
+
+#[tokio::test]
+async fn test_learned_routes_survive_restart() {
+    let node_api = MockNodeAPI::new();
+    let before = table();
+    before.add_direct_peer([2u8; 32], b"10.0.0.2:8334".to_vec());
+    before.add_route(learned_route(3, 2, now()));
+    assert_eq!(before.save(&node_api).await.unwrap(), 1);
+
+    let after = table();
+    assert_eq!(after.load(&node_api).await.unwrap(), 1);
+    assert_eq!(
+        stored_version(&node_api, ROUTES_SCHEMA.tree).await.unwrap(),
+        Some(ROUTES_SCHEMA.version)
+    );
+
+    // Direct peers come back from PeerConnected events, not storage
+    assert!(after.get_route(&[2u8; 32]).is_none());
+    let restored = after.get_route(&[3u8; 32]).unwrap();
+    assert_eq!(restored.route_path, vec![LOCAL, [2u8; 32], [3u8; 32]]);
+    assert_eq!(restored.route_cost, 200);
+}
+
+#[tokio::test]
+async fn test_stale_routes_dropped_on_load() {
+    let node_api = MockNodeAPI::new();
+    let before = table();
+    before.add_route(learned_route(3, 2, now()));
+    before.add_route(learned_route(4, 2, now() - ROUTE_EXPIRY_SECONDS - 10));
+    before.save(&node_api).await.unwrap();
+
+    let after = table();
+    assert_eq!(after.load(&node_api).await.unwrap(), 1);
+    assert!(after.get_route(&[3u8; 32]).is_some());
+    assert!(after.get_route(&[4u8; 32]).is_none());
+}
+
+#[tokio::test]
+async fn test_save_removes_forgotten_routes() {
+    let node_api = MockNodeAPI::new();
+    let first = table();
+    first.add_route(learned_route(3, 2, now()));
+    first.add_route(learned_route(4, 2, now()));
+    first.save(&node_api).await.unwrap();
+
+    let second = table();
+    second.load(&node_api).await.unwrap();
+    second.add_route(learned_route(4, 2, now() - ROUTE_EXPIRY_SECONDS - 10));
+    second.cleanup_expired();
+    second.save(&node_api).await.unwrap();
+
+    let third = table();
+    assert_eq!(third.load(&node_api).await.unwrap(), 1);
+    assert!(third.get_route(&[4u8; 32]).is_none());
+}
+
+#[tokio::test]
+async fn test_manager_restores_routes_on_construction() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let before = table();
+    before.add_route(learned_route(3, 2, now()));
+    before.save(node_api.as_ref()).await.unwrap();
+
+    let ctx = test_context(&[("mesh.enabled", "true")]);
+    let manager = MeshManager::new(&ctx, node_api.clone()).await.unwrap();
+    assert!(manager.routing_table().get_route(&[3u8; 32]).is_some());
+}
+
+#[tokio::test]
+async fn test_single_route_v1_tree_migrated() {
+    let node_api = MockNodeAPI::new();
+    {
+        let mut storage = node_api.storage.lock().unwrap();
+        let tree = storage.entry(ROUTES_SCHEMA.tree.to_string()).or_default();
+        tree.insert(SCHEMA_VERSION_KEY.to_vec(), 1u32.to_be_bytes().to_vec());
+        tree.insert(vec![3u8; 32], bincode::serialize(&learned_route(3, 2, now())).unwrap());
+    }
+
+    let after = table();
+    assert_eq!(after.load(&node_api).await.unwrap(), 1);
+    assert_eq!(after.candidates(&[3u8; 32]).len(), 1);
+    assert_eq!(
+        stored_version(&node_api, ROUTES_SCHEMA.tree).await.unwrap(),
+        Some(ROUTES_SCHEMA.version)
+    );
+}
+
+#[tokio::test]
+async fn test_candidate_routes_survive_restart() {
+    let node_api = MockNodeAPI::new();
+    let before = table();
+    before.add_route(learned_route(3, 2, now()));
+    before.add_route(learned_route(3, 4, now()));
+    assert_eq!(before.save(&node_api).await.unwrap(), 2);
+
+    let after = table();
+    assert_eq!(after.load(&node_api).await.unwrap(), 2);
+    assert_eq!(after.candidates(&[3u8; 32]).len(), 2);
+}
+
+#[tokio::test]
+async fn test_newer_routes_schema_refuses_to_start() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    node_api
+        .storage
+        .lock()
+        .unwrap()
+        .entry(ROUTES_SCHEMA.tree.to_string())
+        .or_default()
+        .insert(
+            SCHEMA_VERSION_KEY.to_vec(),
+            (ROUTES_SCHEMA.version + 1).to_be_bytes().to_vec(),
+        );
+
+    let ctx = test_context(&[("mesh.enabled", "true")]);
+    let result = MeshManager::new(&ctx, node_api.clone()).await;
+    assert!(matches!(result, Err(MeshError::UnsupportedVersion(_))));
+}
+
+#[tokio::test]
+async fn test_raw_key_v2_tree_migrated() {
+    let node_api = MockNodeAPI::new();
+    {
+        let mut storage = node_api.storage.lock().unwrap();
+        let tree = storage.entry(ROUTES_SCHEMA.tree.to_string()).or_default();
+        tree.insert(SCHEMA_VERSION_KEY.to_vec(), 2u32.to_be_bytes().to_vec());
+        tree.insert(vec![3u8; 32], bincode::serialize(&vec![learned_route(3, 2, now())]).unwrap());
+    }
+
+    let after = table();
+    assert_eq!(after.load(&node_api).await.unwrap(), 1);
+    let storage = node_api.storage.lock().unwrap();
+    let tree = &storage[ROUTES_SCHEMA.tree];
+    assert!(!tree.contains_key(&vec![3u8; 32]));
+    let mut key = ROUTE_KEY_PREFIX.to_vec();
+    key.extend_from_slice(hex::encode([3u8; 32]).as_bytes());
+    assert!(tree.contains_key(&key));
+}
+
+#[tokio::test]
+async fn test_save_keeps_keys_outside_route_prefix() {
+    let node_api = MockNodeAPI::new();
+    let first = table();
+    first.add_route(learned_route(3, 2, now()));
+    first.save(&node_api).await.unwrap();
+    node_api
+        .storage
+        .lock()
+        .unwrap()
+        .get_mut(ROUTES_SCHEMA.tree)
+        .unwrap()
+        .insert(b"peer/02".to_vec(), vec![1]);
+
+    // Forgetting every route leaves other records in the tree alone
+    table().save(&node_api).await.unwrap();
+    let storage = node_api.storage.lock().unwrap();
+    let tree = &storage[ROUTES_SCHEMA.tree];
+    assert!(tree.contains_key(&b"peer/02".to_vec()));
+    assert!(!tree.keys().any(|key| key.starts_with(ROUTE_KEY_PREFIX)));
+}
+
+#[tokio::test]
+async fn test_prefix_storage_used_for_route_and_subscription_scans() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let before = table();
+    before.add_route(learned_route(3, 2, now()));
+    before.save(node_api.as_ref()).await.unwrap();
+
+    let prefix_storage = Arc::new(NodePrefixStorage {
+        node_api: node_api.clone(),
+        prefixes: Mutex::new(Vec::new()),
+    });
+    let ctx = test_context(&[("mesh.enabled", "true")]);
+    let manager = MeshManager::with_prefix_storage(&ctx, node_api.clone(), prefix_storage.clone())
+        .await
+        .unwrap();
+    assert!(manager.routing_table().get_route(&[3u8; 32]).is_some());
+
+    // Routes and subscriptions are both scanned on the node
+    let prefixes = prefix_storage.prefixes.lock().unwrap().clone();
+    assert!(prefixes.contains(&ROUTE_KEY_PREFIX.to_vec()));
+    assert!(prefixes.contains(&SUBSCRIPTION_KEY_PREFIX.to_vec()));
+}
@@ -0,0 +1,94 @@
+//! Tests for cost-based route selection in the manager
+
+mod common;
+
+use bllvm_mesh::manager::MeshManager;
+use bllvm_mesh::packet::{MeshPacketBuilder, PacketType};
+use bllvm_mesh::payment_proof::PaymentProof;
+use bllvm_mesh::routing::{NodeId, RoutingEntry};
+use common::{test_context, MockNodeAPI};
+use std::sync::Arc;
+
+const BACKUP_ADDR: &str = "10.0.0.3:8334";
+
+fn route(path: Vec<NodeId>, cost: u64) -> RoutingEntry {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    RoutingEntry {
+        node_id: *path.last().unwrap(),
+        direct_address: None,
+        next_hop: Some(path[1]),
+        route_path: path,
+        route_cost: cost,
+        last_updated: now,
+        quality_score: 0.8,
+    }
+}
+
+#[tokio::test]
+async fn test_unreachable_first_hop_falls_back_to_next_route() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let ctx = test_context(&[("mesh.enabled", "true"), ("mesh.mode", "open")]);
+    let manager = MeshManager::new(&ctx, node_api.clone()).await.unwrap();
+    let me = manager.node_id();
+
+    let (stale, backup, destination) = ([2u8; 32], [3u8; 32], [9u8; 32]);
+    let table = manager.routing_table();
+    // Cheapest known links go through a relay we have no address for
+    table.add_route(route(vec![me, stale, destination, [5u8; 32]], 15));
+    table.add_direct_peer(backup, BACKUP_ADDR.as_bytes().to_vec());
+    table.add_route(route(vec![me, backup, [4u8; 32], destination], 300));
+
+    assert_eq!(table.find_route(&destination).unwrap()[1], stale);
+
+    let packet = MeshPacketBuilder::new(me, destination)
+        .packet_type(PacketType::BitcoinP2P)
+        .payload(vec![1, 2, 3])
+        .build()
+        .unwrap();
+    manager.route_packet(&packet).await.unwrap();
+
+    let sent = node_api.take_sent();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].0, BACKUP_ADDR);
+}
+
+#[tokio::test]
+async fn test_paid_packet_fee_split_across_equal_cost_next_hops() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let ctx = test_context(&[("mesh.enabled", "true"), ("mesh.mode", "open")]);
+    let manager = MeshManager::new(&ctx, node_api.clone()).await.unwrap();
+    let me = manager.node_id();
+
+    // Two parallel 3-hop routes of equal cost
+    let (left, right, destination) = ([2u8; 32], [3u8; 32], [9u8; 32]);
+    let table = manager.routing_table();
+    table.add_direct_peer(left, b"10.0.0.2:8334".to_vec());
+    table.add_direct_peer(right, BACKUP_ADDR.as_bytes().to_vec());
+    table.add_route(route(vec![me, left, destination], 100));
+    // Stored under node 5, teaches the right -> destination link at the same cost
+    table.add_route(route(vec![me, right, destination, [5u8; 32]], 150));
+    assert_eq!(table.find_k_routes(&destination, 2).len(), 2);
+
+    let packet = MeshPacketBuilder::new(me, destination)
+        .packet_type(PacketType::BitcoinP2P)
+        .payload(vec![1, 2, 3])
+        .payment_proof(PaymentProof::Lightning {
+            invoice: "lnbc10u1test".to_string(),
+            preimage: [7u8; 32],
+            amount_msats: 1_000_000,
+            timestamp: 0,
+            expires_at: u64::MAX,
+        })
+        .build()
+        .unwrap();
+    manager.route_packet(&packet).await.unwrap();
+    assert_eq!(node_api.take_sent().len(), 1);
+
+    // 30% of 1000 sats split evenly between the next hops, the rest to the destination
+    let mut accruals = manager.fee_accruals();
+    accruals.sort();
+    assert_eq!(accruals, vec![(left, 150), (right, 150), (destination, 600)]);
+}
@@ -0,0 +1,94 @@
+//! Tests for route withdrawals sent by nodes going offline
+
+mod common;
+
+use bllvm_mesh::discovery::DiscoveryMessage;
+use bllvm_mesh::manager::MeshManager;
+use bllvm_mesh::network::deserialize_mesh_packet;
+use bllvm_mesh::packet::{MeshPacketBuilder, PacketType};
+use bllvm_mesh::routing::{NodeId, RoutingEntry};
+use common::{test_context, MockNodeAPI};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const DESTINATION: NodeId = [4u8; 32];
+const PEER_ADDR: &str = "10.0.0.2:8334";
+
+/// Node ID of a peer added with `handle_peer_connected` (derived from its address)
+fn peer_id(addr: &str) -> NodeId {
+    Sha256::digest(addr.as_bytes()).into()
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+/// Manager with a direct peer and a route to the destination through it
+async fn manager(node_api: &Arc<MockNodeAPI>) -> MeshManager {
+    let ctx = test_context(&[("mesh.enabled", "true"), ("mesh.mode", "open")]);
+    let manager = MeshManager::new(&ctx, node_api.clone()).await.unwrap();
+    manager.handle_peer_connected(PEER_ADDR, "tcp");
+    let peer = peer_id(PEER_ADDR);
+    manager.routing_table().add_route(RoutingEntry {
+        node_id: DESTINATION,
+        direct_address: None,
+        next_hop: Some(peer),
+        route_path: vec![manager.node_id(), peer, DESTINATION],
+        route_cost: 200,
+        last_updated: now(),
+        quality_score: 0.8,
+    });
+    node_api.take_sent();
+    manager
+}
+
+#[tokio::test]
+async fn test_stop_withdraws_routes() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let manager = manager(&node_api).await;
+
+    manager.stop().await;
+    let sent = node_api.take_sent();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].0, PEER_ADDR);
+    let packet = deserialize_mesh_packet(&sent[0].1).unwrap();
+    let DiscoveryMessage::Signed(envelope) = DiscoveryMessage::decode(&packet.payload).unwrap() else {
+        panic!("route withdrawals are signed");
+    };
+    assert_eq!(envelope.originator, manager.node_id());
+    match *envelope.message {
+        DiscoveryMessage::RouteWithdrawal {
+            withdrawn_destinations,
+            source,
+            ..
+        } => {
+            assert_eq!(source, manager.node_id());
+            assert_eq!(withdrawn_destinations[0], manager.node_id());
+            assert!(withdrawn_destinations.contains(&DESTINATION));
+        }
+        other => panic!("expected a route withdrawal, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_unverified_withdrawal_ignored() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let manager = manager(&node_api).await;
+    let peer = peer_id(PEER_ADDR);
+
+    // Unsigned (open mode only): anyone could have forged it
+    let withdrawal = DiscoveryMessage::RouteWithdrawal {
+        withdrawn_destinations: vec![peer, DESTINATION],
+        source: peer,
+        timestamp: now(),
+    };
+    let packet = MeshPacketBuilder::new(peer, manager.node_id())
+        .packet_type(PacketType::Discovery)
+        .payload(withdrawal.encode().unwrap())
+        .build()
+        .unwrap();
+    manager.handle_incoming_packet(&packet).await.unwrap();
+    assert!(manager.routing_table().get_route(&DESTINATION).is_some());
+    assert!(node_api.take_sent().is_empty());
+}
@@ -0,0 +1,152 @@
+//! Tests for the mesh_getstats, mesh_listroutes and mesh_sendpacket RPC endpoints
+
+mod common;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use bllvm_mesh::manager::MeshManager;
+use bllvm_mesh::network::deserialize_mesh_packet;
+use bllvm_mesh::routing::RoutingEntry;
+use bllvm_mesh::rpc::{MESH_RPC_METHODS, RPC_GET_STATS, RPC_LIST_ROUTES, RPC_SEND_PACKET};
+use common::{test_context, MockNodeAPI};
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+const PEER_ADDR: &str = "10.0.0.7:8334";
+
+async fn rpc_manager() -> (MeshManager, Arc<MockNodeAPI>) {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let ctx = test_context(&[("mesh.enabled", "true"), ("mesh.mode", "open")]);
+    let manager = MeshManager::new(&ctx, node_api.clone()).await.unwrap();
+    (manager, node_api)
+}
+
+#[tokio::test]
+async fn test_endpoints_registered_and_unregistered() {
+    let (manager, node_api) = rpc_manager().await;
+    manager.start().await.unwrap();
+    let registered = node_api.rpc_endpoints.lock().unwrap().clone();
+    for (method, _) in MESH_RPC_METHODS {
+        assert!(registered.iter().any(|m| m == method), "{} not registered", method);
+    }
+
+    manager.stop().await;
+    assert!(node_api.rpc_endpoints.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_getstats_returns_mesh_stats() {
+    let (manager, _) = rpc_manager().await;
+    manager.routing_table().add_direct_peer([7u8; 32], PEER_ADDR.as_bytes().to_vec());
+
+    let stats = manager.handle_rpc_call(RPC_GET_STATS, &Value::Null).await.unwrap();
+    assert_eq!(stats["enabled"], true);
+    assert_eq!(stats["mode"], "open");
+    assert_eq!(stats["routing"]["direct_peers"], 1);
+    assert!(stats["replay"].is_object());
+    assert!(stats["rate_limit"].is_object());
+}
+
+#[tokio::test]
+async fn test_listroutes_pages_through_entries() {
+    let (manager, _) = rpc_manager().await;
+    for i in 0..5u8 {
+        manager
+            .routing_table()
+            .add_direct_peer([10 + i; 32], format!("10.0.0.{}:8334", i).into_bytes());
+    }
+
+    let first = manager.handle_rpc_call(RPC_LIST_ROUTES, &json!({ "limit": 2 })).await.unwrap();
+    assert_eq!(first["total"], 5);
+    let routes = first["routes"].as_array().unwrap();
+    assert_eq!(routes.len(), 2);
+    assert_eq!(routes[0]["node_id"], hex::encode([10u8; 32]));
+    assert_eq!(routes[0]["direct"], true);
+    assert!(routes[0]["quality"].is_number());
+    assert!(routes[0]["cost"].is_number());
+    assert!(routes[0]["age_secs"].as_u64().unwrap() < 60);
+    assert_eq!(first["next_offset"], 2);
+
+    let last = manager
+        .handle_rpc_call(RPC_LIST_ROUTES, &json!({ "offset": 4, "limit": 2 }))
+        .await
+        .unwrap();
+    assert_eq!(last["routes"].as_array().unwrap().len(), 1);
+    assert_eq!(last["routes"][0]["node_id"], hex::encode([14u8; 32]));
+    assert!(last["next_offset"].is_null());
+
+    assert!(manager
+        .handle_rpc_call(RPC_LIST_ROUTES, &json!({ "offset": -1 }))
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_listroutes_reports_delivery_outcomes() {
+    let (manager, _) = rpc_manager().await;
+    let relay = [7u8; 32];
+    let destination = [8u8; 32];
+    manager.routing_table().add_direct_peer(relay, PEER_ADDR.as_bytes().to_vec());
+    manager.routing_table().add_route(RoutingEntry {
+        node_id: destination,
+        direct_address: None,
+        next_hop: Some(relay),
+        route_path: vec![manager.node_id(), relay, destination],
+        route_cost: 100,
+        last_updated: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs(),
+        quality_score: 0.8,
+    });
+
+    manager.send_packet(destination, vec![1], None).await.unwrap();
+    manager.routing_table().record_failure(&destination);
+
+    let listed = manager.handle_rpc_call(RPC_LIST_ROUTES, &json!({})).await.unwrap();
+    let route = &listed["routes"][1];
+    assert_eq!(route["node_id"], hex::encode(destination));
+    assert_eq!(route["successes"], 1);
+    assert_eq!(route["failures"], 1);
+    assert!((route["quality"].as_f64().unwrap() - 0.45).abs() < 1e-9);
+}
+
+#[tokio::test]
+async fn test_sendpacket_routes_packet() {
+    let (manager, node_api) = rpc_manager().await;
+    let destination = [7u8; 32];
+    manager.routing_table().add_direct_peer(destination, PEER_ADDR.as_bytes().to_vec());
+
+    let params = json!({
+        "destination": hex::encode(destination),
+        "payload": BASE64.encode([1u8, 2, 3]),
+    });
+    let result = manager.handle_rpc_call(RPC_SEND_PACKET, &params).await.unwrap();
+    assert!(result["sequence"].is_u64());
+
+    let sent = node_api.take_sent();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].0, PEER_ADDR);
+    let packet = deserialize_mesh_packet(&sent[0].1).unwrap();
+    assert_eq!(packet.destination, destination);
+    assert_eq!(packet.payload, vec![1, 2, 3]);
+}
+
+#[tokio::test]
+async fn test_sendpacket_rejects_bad_params() {
+    let (manager, node_api) = rpc_manager().await;
+
+    let bad_destination = json!({ "destination": "abcd", "payload": BASE64.encode([1u8]) });
+    assert!(manager.handle_rpc_call(RPC_SEND_PACKET, &bad_destination).await.is_err());
+
+    let bad_payload = json!({ "destination": hex::encode([7u8; 32]), "payload": "not base64!" });
+    assert!(manager.handle_rpc_call(RPC_SEND_PACKET, &bad_payload).await.is_err());
+
+    let bad_proof = json!({
+        "destination": hex::encode([7u8; 32]),
+        "payload": BASE64.encode([1u8]),
+        "payment_proof": "nope",
+    });
+    assert!(manager.handle_rpc_call(RPC_SEND_PACKET, &bad_proof).await.is_err());
+    assert!(node_api.take_sent().is_empty());
+}
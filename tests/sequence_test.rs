@@ -0,0 +1,154 @@
+//! Tests for sequence numbers of originated packets and duplicate packet suppression
+
+mod common;
+
+use bllvm_mesh::manager::MeshManager;
+use bllvm_mesh::network::deserialize_mesh_packet;
+use bllvm_mesh::packet::{MeshPacketBuilder, PacketType};
+use common::{test_context, MockNodeAPI};
+use std::sync::Arc;
+
+const PEER: [u8; 32] = [2u8; 32];
+
+async fn manager(node_api: Arc<MockNodeAPI>) -> MeshManager {
+    manager_with(node_api, &[]).await
+}
+
+async fn manager_with(node_api: Arc<MockNodeAPI>, extra: &[(&str, &str)]) -> MeshManager {
+    let mut config = vec![("mesh.enabled", "true"), ("mesh.mode", "open")];
+    config.extend_from_slice(extra);
+    let ctx = test_context(&config);
+    let manager = MeshManager::new(&ctx, node_api).await.unwrap();
+    manager.routing_table().add_direct_peer(PEER, b"10.0.0.2:8334".to_vec());
+    manager
+}
+
+fn sent_sequences(node_api: &MockNodeAPI) -> Vec<u64> {
+    node_api
+        .take_sent()
+        .iter()
+        .map(|(_, frame)| deserialize_mesh_packet(frame).unwrap().sequence)
+        .collect()
+}
+
+#[tokio::test]
+async fn test_sequences_monotonic_across_restart() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let manager = manager(node_api.clone()).await;
+
+    let first = manager.send_packet(PEER, vec![1], None).await.unwrap();
+    let second = manager.send_packet(PEER, vec![2], None).await.unwrap();
+    assert!(first > 0);
+    assert!(second > first);
+    assert_eq!(sent_sequences(&node_api), vec![first, second]);
+
+    // Packets handed to route_packet unstamped are numbered too
+    let packet = MeshPacketBuilder::new(manager.node_id(), PEER)
+        .packet_type(PacketType::BitcoinP2P)
+        .payload(vec![3])
+        .build()
+        .unwrap();
+    manager.route_packet(&packet).await.unwrap();
+    let third = sent_sequences(&node_api)[0];
+    assert!(third > second);
+    drop(manager);
+
+    let restarted = self::manager(node_api.clone()).await;
+    let after_restart = restarted.send_packet(PEER, vec![4], None).await.unwrap();
+    assert!(after_restart > third);
+}
+
+#[tokio::test]
+async fn test_duplicate_forwarded_once() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let relay = manager(node_api.clone()).await;
+
+    let mut packet = MeshPacketBuilder::new([7u8; 32], PEER)
+        .packet_type(PacketType::BitcoinP2P)
+        .payload(vec![1, 2, 3])
+        .sequence(42)
+        .build()
+        .unwrap();
+    relay.handle_incoming_packet(&packet).await.unwrap();
+    assert_eq!(node_api.take_sent().len(), 1);
+
+    // The same packet over a second path
+    let mut via_other_relay = packet.clone();
+    via_other_relay.add_to_route([8u8; 32]);
+    relay.handle_incoming_packet(&via_other_relay).await.unwrap();
+    assert!(node_api.take_sent().is_empty());
+
+    // A different sequence from the same source goes through
+    packet.sequence = 43;
+    relay.handle_incoming_packet(&packet).await.unwrap();
+    assert_eq!(node_api.take_sent().len(), 1);
+}
+
+#[tokio::test]
+async fn test_duplicate_delivered_once_and_counted() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let destination = manager(node_api.clone()).await;
+
+    let packet = MeshPacketBuilder::new(PEER, destination.node_id())
+        .packet_type(PacketType::BitcoinP2P)
+        .payload(vec![1, 2, 3])
+        .sequence(42)
+        .ack_requested()
+        .build()
+        .unwrap();
+    destination.handle_incoming_packet(&packet).await.unwrap();
+    assert_eq!(destination.poll_delivered(10).len(), 1);
+    assert_eq!(node_api.take_sent().len(), 1, "the first copy is acked");
+
+    // The copy is dropped without delivery, but acked in case the first ack was lost
+    let mut via_other_relay = packet.clone();
+    via_other_relay.add_to_route([8u8; 32]);
+    destination.handle_incoming_packet(&via_other_relay).await.unwrap();
+    assert!(destination.poll_delivered(10).is_empty());
+    assert_eq!(node_api.take_sent().len(), 1);
+
+    let text = destination.metrics_exporter().render().await;
+    assert!(text.contains("mesh_duplicates_dropped_total 1\n"));
+}
+
+#[tokio::test]
+async fn test_retransmission_after_lost_ack_is_acked() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let destination = manager(node_api.clone()).await;
+
+    let packet = MeshPacketBuilder::new(PEER, destination.node_id())
+        .packet_type(PacketType::BitcoinP2P)
+        .payload(vec![1, 2, 3])
+        .sequence(42)
+        .ack_requested()
+        .build()
+        .unwrap();
+    destination.handle_incoming_packet(&packet).await.unwrap();
+    assert_eq!(destination.poll_delivered(10).len(), 1);
+    // The ack is lost on the way back
+    node_api.take_sent();
+
+    // The source retransmits within the dedup window and gets its ack
+    destination.handle_incoming_packet(&packet).await.unwrap();
+    assert!(destination.poll_delivered(10).is_empty());
+    let sent = node_api.take_sent();
+    assert_eq!(sent.len(), 1);
+    let ack = deserialize_mesh_packet(&sent[0].1).unwrap();
+    assert_eq!(ack.destination, PEER);
+}
+
+#[tokio::test]
+async fn test_dedup_window_configurable() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let relay = manager_with(node_api.clone(), &[("mesh.dedup_window_seconds", "0")]).await;
+
+    let packet = MeshPacketBuilder::new([7u8; 32], PEER)
+        .packet_type(PacketType::BitcoinP2P)
+        .payload(vec![1, 2, 3])
+        .sequence(42)
+        .build()
+        .unwrap();
+    relay.handle_incoming_packet(&packet).await.unwrap();
+    relay.handle_incoming_packet(&packet).await.unwrap();
+    assert_eq!(node_api.take_sent().len(), 2);
+}
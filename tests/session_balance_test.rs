@@ -0,0 +1,151 @@
+//! Tests for prepaid session balances opened by overpaying proofs
+
+mod common;
+
+use bllvm_mesh::error::MeshError;
+use bllvm_mesh::manager::MeshManager;
+use bllvm_mesh::packet::{MeshPacket, MeshPacketBuilder, PacketType};
+use bllvm_mesh::payment_proof::PaymentProof;
+use bllvm_mesh::routing::{NodeId, RoutingEntry};
+use common::{test_context, MockNodeAPI, TEST_LIGHTNING_KEY};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const PEER: NodeId = [2u8; 32];
+const DESTINATION: NodeId = [3u8; 32];
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+/// Manager charging 12 sats for a 4 KB packet to DESTINATION
+async fn start_manager(node_api: &Arc<MockNodeAPI>) -> MeshManager {
+    let ctx = test_context(&[
+        ("mesh.enabled", "true"),
+        ("mesh.mode", "payment_gated"),
+        ("mesh.fee.rate_sats_per_kb", "1"),
+        ("mesh.fee.hop_surcharge_sats", "2"),
+        ("mesh.keysend_verify_settlement", "false"),
+        ("mesh.payment.local_payee", TEST_LIGHTNING_KEY),
+    ]);
+    let manager = MeshManager::new(&ctx, node_api.clone()).await.unwrap();
+    manager.routing_table().add_direct_peer(PEER, b"10.0.0.2:8334".to_vec());
+    manager.routing_table().add_route(RoutingEntry {
+        node_id: DESTINATION,
+        direct_address: None,
+        next_hop: Some(PEER),
+        route_path: vec![manager.node_id(), PEER, DESTINATION],
+        route_cost: 200,
+        last_updated: now(),
+        quality_score: 0.8,
+    });
+    manager
+}
+
+fn keysend_proof(amount_sats: u64) -> PaymentProof {
+    let preimage = [1u8; 32];
+    PaymentProof::Keysend {
+        payment_hash: Sha256::digest(preimage).into(),
+        preimage,
+        amount_msats: amount_sats * 1000,
+        timestamp: now(),
+        custom_tlv: Vec::new(),
+        destination_pubkey: vec![2u8; 33],
+    }
+}
+
+fn prepaid_packet(manager: &MeshManager, sequence: u64, proof: PaymentProof) -> MeshPacket {
+    MeshPacketBuilder::new(manager.node_id(), DESTINATION)
+        .packet_type(PacketType::BitcoinP2P)
+        .payload(vec![0x12; 4096])
+        .payment_proof(proof)
+        .sequence(sequence)
+        .build()
+        .unwrap()
+}
+
+fn session_packet(manager: &MeshManager, sequence: u64, proof_hash: [u8; 32]) -> MeshPacket {
+    MeshPacketBuilder::new(manager.node_id(), DESTINATION)
+        .packet_type(PacketType::BitcoinP2P)
+        .payload(vec![0x12; 4096])
+        .payment_session(proof_hash)
+        .sequence(sequence)
+        .build()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_overpayment_pays_later_packets() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let manager = start_manager(&node_api).await;
+    let proof = keysend_proof(30);
+
+    // 30 sats pay for this packet and leave 18
+    manager.route_packet(&prepaid_packet(&manager, 1, proof.clone())).await.unwrap();
+    let balances = manager.session_balances();
+    assert_eq!(balances.len(), 1);
+    assert_eq!(balances[0].proof_hash, hex::encode(proof.hash()));
+    assert_eq!(balances[0].source, hex::encode(manager.node_id()));
+    assert_eq!(balances[0].remaining_sats, 18);
+
+    manager.route_packet(&session_packet(&manager, 2, proof.hash())).await.unwrap();
+    assert_eq!(manager.session_balances()[0].remaining_sats, 6);
+
+    let result = manager.route_packet(&session_packet(&manager, 3, proof.hash())).await;
+    match result {
+        Err(MeshError::PaymentVerification(reason)) => {
+            assert_eq!(reason, "payment session exhausted: need 12, have 6")
+        }
+        other => panic!("expected an exhausted balance, got {:?}", other),
+    }
+    assert_eq!(node_api.take_sent().len(), 2);
+
+    let stats = manager.get_stats().await;
+    assert_eq!(stats.balances.sessions, 1);
+    assert_eq!(stats.balances.remaining_sats, 6);
+}
+
+#[tokio::test]
+async fn test_session_packets_keep_sequence_window() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let manager = start_manager(&node_api).await;
+    let proof = keysend_proof(50);
+    manager.route_packet(&prepaid_packet(&manager, 1, proof.clone())).await.unwrap();
+
+    let packet = session_packet(&manager, 2, proof.hash());
+    manager.route_packet(&packet).await.unwrap();
+    let result = manager.route_packet(&packet).await;
+    assert!(matches!(result, Err(MeshError::ReplayDetected(_))));
+
+    // The replayed packet is not charged
+    assert_eq!(manager.session_balances()[0].remaining_sats, 26);
+    assert_eq!(node_api.take_sent().len(), 2);
+}
+
+#[tokio::test]
+async fn test_unknown_session_rejected() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let manager = start_manager(&node_api).await;
+
+    let result = manager.route_packet(&session_packet(&manager, 1, [9u8; 32])).await;
+    match result {
+        Err(MeshError::PaymentVerification(reason)) => assert_eq!(reason, "unknown payment session"),
+        other => panic!("expected an unknown session, got {:?}", other),
+    }
+    assert!(node_api.take_sent().is_empty());
+}
+
+#[tokio::test]
+async fn test_balances_survive_restart() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let manager = start_manager(&node_api).await;
+    let proof = keysend_proof(30);
+    manager.route_packet(&prepaid_packet(&manager, 1, proof.clone())).await.unwrap();
+    drop(manager);
+
+    let restarted = start_manager(&node_api).await;
+    assert_eq!(restarted.session_balances()[0].remaining_sats, 18);
+    restarted.route_packet(&session_packet(&restarted, 2, proof.hash())).await.unwrap();
+    assert_eq!(restarted.session_balances()[0].remaining_sats, 6);
+}
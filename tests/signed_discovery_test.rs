@@ -0,0 +1,113 @@
+//! Tests for signed route advertisements and route responses
+
+mod common;
+
+use bllvm_mesh::discovery::{
+    DiscoveryMessage, RouteAdvertisementEntry, SignedDiscovery, QUARANTINED_ROUTE_QUALITY,
+};
+use bllvm_mesh::error::MeshError;
+use bllvm_mesh::gossip::BloomFilter;
+use bllvm_mesh::manager::MeshManager;
+use bllvm_mesh::packet::{MeshPacket, MeshPacketBuilder, PacketType};
+use bllvm_mesh::routing::NodeId;
+use bllvm_mesh::signing::{signing_key_from_bytes, signing_public_key};
+use common::{test_context, MockNodeAPI};
+use secp256k1::Keypair;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const PEER: NodeId = [2u8; 32];
+const PEER_ADDR: &str = "10.0.0.2:8334";
+const NEXT_HOP: NodeId = [3u8; 32];
+const DESTINATION: NodeId = [4u8; 32];
+
+async fn manager(mode: &str, keypair: &Keypair) -> MeshManager {
+    let ctx = test_context(&[("mesh.enabled", "true"), ("mesh.mode", mode)]);
+    let manager = MeshManager::new(&ctx, Arc::new(MockNodeAPI::new())).await.unwrap();
+    manager.routing_table().add_direct_peer(PEER, PEER_ADDR.as_bytes().to_vec());
+    manager.register_peer_key(PEER, &signing_public_key(keypair)).unwrap();
+    manager
+}
+
+fn advertisement(sequence: u64) -> DiscoveryMessage {
+    DiscoveryMessage::RouteAdvertisement {
+        routes: vec![RouteAdvertisementEntry {
+            destination: DESTINATION,
+            next_hop: NEXT_HOP,
+            cost: 200,
+            hop_count: 2,
+        }],
+        source: PEER,
+        sequence,
+        seen: BloomFilter::default(),
+    }
+}
+
+fn signed(message: DiscoveryMessage, keypair: &Keypair, timestamp: u64) -> DiscoveryMessage {
+    DiscoveryMessage::Signed(SignedDiscovery::sign(message, PEER, timestamp, keypair).unwrap())
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+/// Discovery packet from PEER, signed at the packet level
+fn packet(manager: &MeshManager, message: &DiscoveryMessage, sequence: u64, keypair: &Keypair) -> MeshPacket {
+    let mut packet = MeshPacketBuilder::new(PEER, manager.node_id())
+        .packet_type(PacketType::Discovery)
+        .payload(message.encode().unwrap())
+        .ttl(1)
+        .sequence(sequence)
+        .build()
+        .unwrap();
+    packet.sign(keypair);
+    packet
+}
+
+#[tokio::test]
+async fn test_tampered_advertisement_rejected() {
+    let keypair = signing_key_from_bytes(&[7u8; 32]).unwrap();
+    let manager = manager("payment_gated", &keypair).await;
+
+    // A relay redirects the advertised route through a node it controls
+    let mut tampered = signed(advertisement(1), &keypair, now());
+    if let DiscoveryMessage::Signed(envelope) = &mut tampered {
+        if let DiscoveryMessage::RouteAdvertisement { routes, .. } = envelope.message.as_mut() {
+            routes[0].next_hop = [9u8; 32];
+        }
+    }
+    let result = manager.handle_incoming_packet(&packet(&manager, &tampered, 1, &keypair)).await;
+    assert!(matches!(result, Err(MeshError::InvalidSignature(_))));
+
+    // Unsigned and stale advertisements are rejected outside open mode
+    let unsigned = advertisement(2);
+    let result = manager.handle_incoming_packet(&packet(&manager, &unsigned, 2, &keypair)).await;
+    assert!(matches!(result, Err(MeshError::InvalidSignature(_))));
+    let stale = signed(advertisement(3), &keypair, now() - 3600);
+    let result = manager.handle_incoming_packet(&packet(&manager, &stale, 3, &keypair)).await;
+    assert!(matches!(result, Err(MeshError::InvalidSignature(_))));
+    assert!(manager.routing_table().get_route(&DESTINATION).is_none());
+
+    let genuine = signed(advertisement(4), &keypair, now());
+    manager.handle_incoming_packet(&packet(&manager, &genuine, 4, &keypair)).await.unwrap();
+    let entry = manager.routing_table().get_route(&DESTINATION).unwrap();
+    assert_eq!(entry.next_hop, Some(NEXT_HOP));
+    assert_eq!(entry.quality_score, 0.7);
+}
+
+#[tokio::test]
+async fn test_unverified_advertisement_quarantined_in_open_mode() {
+    let keypair = signing_key_from_bytes(&[7u8; 32]).unwrap();
+    let manager = manager("open", &keypair).await;
+
+    let unsigned = advertisement(1);
+    manager.handle_incoming_packet(&packet(&manager, &unsigned, 1, &keypair)).await.unwrap();
+    let entry = manager.routing_table().get_route(&DESTINATION).unwrap();
+    assert_eq!(entry.quality_score, QUARANTINED_ROUTE_QUALITY);
+
+    // A verified advertisement of the same route lifts the quarantine
+    let genuine = signed(advertisement(2), &keypair, now());
+    manager.handle_incoming_packet(&packet(&manager, &genuine, 2, &keypair)).await.unwrap();
+    let entry = manager.routing_table().get_route(&DESTINATION).unwrap();
+    assert_eq!(entry.quality_score, 0.7);
+}
@@ -0,0 +1,111 @@
+//! Tests for packet source authentication
+
+mod common;
+
+use bllvm_mesh::error::MeshError;
+use bllvm_mesh::manager::MeshManager;
+use bllvm_mesh::network::deserialize_mesh_packet;
+use bllvm_mesh::packet::{MeshPacketBuilder, PacketType};
+use bllvm_mesh::signing::generate_signing_key;
+use common::{test_context, MockNodeAPI};
+use std::sync::Arc;
+
+fn bitcoin_version_message() -> Vec<u8> {
+    vec![
+        0xf9, 0xbe, 0xb4, 0xd9, // magic
+        0x76, 0x65, 0x72, 0x73, 0x69, 0x6f, 0x6e, 0x00, // "version\0"
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ]
+}
+
+async fn node(mode: &str) -> (MeshManager, Arc<MockNodeAPI>) {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let ctx = test_context(&[("mesh.enabled", "true"), ("mesh.mode", mode)]);
+    let manager = MeshManager::new(&ctx, node_api.clone()).await.unwrap();
+    (manager, node_api)
+}
+
+/// Sender and receiver linked directly, receiver knows the sender's key
+async fn pair(mode: &str) -> (MeshManager, Arc<MockNodeAPI>, MeshManager) {
+    let (sender, sender_api) = node(mode).await;
+    let (receiver, _) = node(mode).await;
+    sender
+        .routing_table()
+        .add_direct_peer(receiver.node_id(), b"10.0.0.2:8334".to_vec());
+    receiver
+        .register_peer_key(sender.node_id(), &sender.signing_public_key())
+        .unwrap();
+    (sender, sender_api, receiver)
+}
+
+#[tokio::test]
+async fn test_originated_packets_are_signed_and_accepted() {
+    let (sender, sender_api, receiver) = pair("payment_gated").await;
+
+    let packet = MeshPacketBuilder::new(sender.node_id(), receiver.node_id())
+        .packet_type(PacketType::BitcoinP2P)
+        .payload(bitcoin_version_message())
+        .build()
+        .unwrap();
+    sender.route_packet(&packet).await.unwrap();
+
+    let received = deserialize_mesh_packet(&sender_api.take_sent()[0].1).unwrap();
+    assert!(received.signature.is_some());
+    receiver.handle_incoming_packet(&received).await.unwrap();
+    assert_eq!(receiver.poll_delivered(10).len(), 1);
+}
+
+#[tokio::test]
+async fn test_unsigned_and_forged_packets_rejected_when_gated() {
+    let (sender, _, receiver) = pair("payment_gated").await;
+
+    let unsigned = MeshPacketBuilder::new(sender.node_id(), receiver.node_id())
+        .packet_type(PacketType::BitcoinP2P)
+        .payload(bitcoin_version_message())
+        .build()
+        .unwrap();
+    assert!(matches!(
+        receiver.handle_incoming_packet(&unsigned).await,
+        Err(MeshError::InvalidSignature(_))
+    ));
+
+    // Claims the sender's node ID but is signed with someone else's key
+    let mut forged = unsigned.clone();
+    forged.sign(&generate_signing_key());
+    assert!(matches!(
+        receiver.handle_incoming_packet(&forged).await,
+        Err(MeshError::InvalidSignature(_))
+    ));
+
+    // Signed, but by a node whose key was never exchanged
+    let mut unknown = MeshPacketBuilder::new([7u8; 32], receiver.node_id())
+        .packet_type(PacketType::BitcoinP2P)
+        .payload(bitcoin_version_message())
+        .build()
+        .unwrap();
+    unknown.sign(&generate_signing_key());
+    assert!(receiver.handle_incoming_packet(&unknown).await.is_err());
+
+    assert!(receiver.poll_delivered(10).is_empty());
+}
+
+#[tokio::test]
+async fn test_open_mode_accepts_unsigned_but_not_forged() {
+    let (sender, _, receiver) = pair("open").await;
+
+    let unsigned = MeshPacketBuilder::new(sender.node_id(), receiver.node_id())
+        .packet_type(PacketType::BitcoinP2P)
+        .payload(vec![1, 2, 3])
+        .build()
+        .unwrap();
+    receiver.handle_incoming_packet(&unsigned).await.unwrap();
+    assert_eq!(receiver.poll_delivered(10).len(), 1);
+
+    let mut forged = MeshPacketBuilder::new(sender.node_id(), receiver.node_id())
+        .packet_type(PacketType::BitcoinP2P)
+        .payload(vec![4])
+        .build()
+        .unwrap();
+    forged.sign(&generate_signing_key());
+    assert!(receiver.handle_incoming_packet(&forged).await.is_err());
+}
@@ -0,0 +1,275 @@
+//! End-to-end mesh simulation: several managers linked by in-memory channels
+
+mod common;
+
+use bllvm_mesh::error::MeshError;
+use bllvm_mesh::manager::MeshManager;
+use bllvm_mesh::payment_proof::PaymentProof;
+use bllvm_mesh::routing::{NodeId, RoutingEntry};
+use common::{test_context, MockNodeAPI, TEST_LIGHTNING_KEY};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+
+/// Frames a node may have in flight before its sends wait
+const OUTBOX_CAPACITY: usize = 1024;
+
+/// One simulated node
+struct SimulatedNode {
+    manager: MeshManager,
+    address: String,
+    outbox: Mutex<mpsc::Receiver<(String, Vec<u8>)>>,
+}
+
+/// N mesh managers whose mesh sends travel over in-memory channels
+///
+/// A frame is only delivered over a link added with `connect`; frames are
+/// moved by `pump`, which `drain` runs before collecting deliveries.
+struct SimulatedMesh {
+    nodes: Vec<SimulatedNode>,
+    links: Mutex<HashSet<(usize, usize)>>,
+    /// Frames delivered so far (from, to, bytes)
+    delivered_frames: Mutex<Vec<(usize, usize, Vec<u8>)>>,
+}
+
+impl SimulatedMesh {
+    /// Start `count` unconnected nodes with the given mesh config
+    async fn new(count: usize, config: &[(&str, &str)]) -> Self {
+        let mut config = config.to_vec();
+        config.push(("mesh.enabled", "true"));
+        config.push(("mesh.keysend_verify_settlement", "false"));
+        config.push(("mesh.payment.local_payee", TEST_LIGHTNING_KEY));
+        let ctx = test_context(&config);
+
+        let mut nodes = Vec::new();
+        for index in 0..count {
+            let (sender, receiver) = mpsc::channel(OUTBOX_CAPACITY);
+            let node_api = Arc::new(MockNodeAPI::with_outbox(sender));
+            nodes.push(SimulatedNode {
+                manager: MeshManager::new(&ctx, node_api).await.unwrap(),
+                address: format!("10.0.0.{}:8334", index + 1),
+                outbox: Mutex::new(receiver),
+            });
+        }
+        // Signing keys are distributed up front, as Hello would for direct peers
+        for node in &nodes {
+            for other in nodes.iter().filter(|other| other.address != node.address) {
+                node.manager
+                    .register_peer_key(other.manager.node_id(), &other.manager.signing_public_key())
+                    .unwrap();
+            }
+        }
+        Self {
+            nodes,
+            links: Mutex::new(HashSet::new()),
+            delivered_frames: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Node ID of node `index`
+    fn id(&self, index: usize) -> NodeId {
+        self.node(index).node_id()
+    }
+
+    fn node(&self, index: usize) -> &MeshManager {
+        &self.nodes[index].manager
+    }
+
+    /// Add a bidirectional link between two nodes
+    fn connect(&self, a: usize, b: usize) {
+        self.links.lock().unwrap().extend([(a, b), (b, a)]);
+        let address = |index: usize| self.nodes[index].address.as_bytes().to_vec();
+        self.node(a).routing_table().add_direct_peer(self.id(b), address(b));
+        self.node(b).routing_table().add_direct_peer(self.id(a), address(a));
+    }
+
+    /// Remove the link between two nodes
+    fn disconnect(&self, a: usize, b: usize) {
+        let mut links = self.links.lock().unwrap();
+        links.remove(&(a, b));
+        links.remove(&(b, a));
+        self.node(a).routing_table().remove_direct_peer(&self.id(b));
+        self.node(b).routing_table().remove_direct_peer(&self.id(a));
+    }
+
+    /// Install a route along `path` (node indexes) on every node before its destination
+    ///
+    /// The last relay reaches the destination over its direct link.
+    fn add_route(&self, path: &[usize], cost: u64) {
+        let destination = *path.last().unwrap();
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        for start in 0..path.len().saturating_sub(2) {
+            let route_path: Vec<NodeId> = path[start..].iter().map(|index| self.id(*index)).collect();
+            self.node(path[start]).routing_table().add_route(RoutingEntry {
+                node_id: self.id(destination),
+                direct_address: None,
+                next_hop: Some(route_path[1]),
+                route_path,
+                route_cost: cost,
+                last_updated: now,
+                quality_score: 0.8,
+            });
+        }
+    }
+
+    /// Send a payload from one node to another, returning its sequence number
+    async fn send(&self, src: usize, dst: usize, payload: &[u8]) -> Result<u64, MeshError> {
+        self.node(src).send_packet(self.id(dst), payload.to_vec(), None).await
+    }
+
+    /// Send a paid payload from one node to another
+    async fn send_paid(&self, src: usize, dst: usize, payload: &[u8], proof: PaymentProof) -> Result<u64, MeshError> {
+        self.node(src).send_packet(self.id(dst), payload.to_vec(), Some(proof)).await
+    }
+
+    /// Deliver a frame to node `to` as if it arrived from node `from`
+    async fn inject(&self, from: usize, to: usize, frame: &[u8]) -> Result<(), MeshError> {
+        self.delivered_frames.lock().unwrap().push((from, to, frame.to_vec()));
+        self.node(to).on_message_received(&self.nodes[from].address, frame).await
+    }
+
+    /// Move frames between nodes until every outbox is empty
+    ///
+    /// Frames to an address with no link from the sender are dropped.
+    async fn pump(&self) {
+        loop {
+            let mut moved = false;
+            for from in 0..self.nodes.len() {
+                loop {
+                    let next = self.nodes[from].outbox.lock().unwrap().try_recv();
+                    let Ok((address, frame)) = next else {
+                        break;
+                    };
+                    moved = true;
+                    let Some(to) = self.nodes.iter().position(|node| node.address == address) else {
+                        continue;
+                    };
+                    if !self.links.lock().unwrap().contains(&(from, to)) {
+                        continue;
+                    }
+                    let _ = self.inject(from, to, &frame).await;
+                }
+            }
+            if !moved {
+                return;
+            }
+        }
+    }
+
+    /// Deliver everything in flight, then take the payloads delivered to `dst`
+    async fn drain(&self, dst: usize) -> Vec<Vec<u8>> {
+        self.pump().await;
+        self.node(dst)
+            .poll_delivered(usize::MAX)
+            .into_iter()
+            .map(|received| received.payload)
+            .collect()
+    }
+
+    /// Last frame delivered to `to` (with the node it came from)
+    fn last_frame_to(&self, to: usize) -> (usize, Vec<u8>) {
+        self.delivered_frames
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .find(|(_, target, _)| *target == to)
+            .map(|(from, _, frame)| (*from, frame.clone()))
+            .unwrap()
+    }
+}
+
+fn keysend_proof(preimage: [u8; 32]) -> PaymentProof {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    PaymentProof::Keysend {
+        payment_hash: Sha256::digest(preimage).into(),
+        preimage,
+        amount_msats: 10_000,
+        timestamp: now,
+        custom_tlv: Vec::new(),
+        destination_pubkey: vec![2u8; 33],
+    }
+}
+
+#[tokio::test]
+async fn test_three_node_chain() {
+    let mesh = SimulatedMesh::new(3, &[("mesh.mode", "open")]).await;
+    mesh.connect(0, 1);
+    mesh.connect(1, 2);
+    mesh.add_route(&[0, 1, 2], 200);
+    mesh.add_route(&[2, 1, 0], 200);
+
+    mesh.send(0, 2, b"over two hops").await.unwrap();
+    assert_eq!(mesh.drain(2).await, vec![b"over two hops".to_vec()]);
+    assert_eq!(mesh.last_frame_to(2).0, 1);
+    assert!(mesh.drain(1).await.is_empty());
+
+    mesh.send(2, 0, b"reply").await.unwrap();
+    assert_eq!(mesh.drain(0).await, vec![b"reply".to_vec()]);
+}
+
+#[tokio::test]
+async fn test_four_node_diamond() {
+    let mesh = SimulatedMesh::new(4, &[("mesh.mode", "open")]).await;
+    for (a, b) in [(0, 1), (0, 2), (1, 3), (2, 3)] {
+        mesh.connect(a, b);
+    }
+    mesh.add_route(&[0, 1, 3], 200);
+    mesh.add_route(&[0, 2, 3], 300);
+    mesh.add_route(&[3, 1, 0], 200);
+    mesh.add_route(&[3, 2, 0], 300);
+
+    mesh.send(0, 3, b"first").await.unwrap();
+    assert_eq!(mesh.drain(3).await, vec![b"first".to_vec()]);
+    assert_eq!(mesh.last_frame_to(3).0, 1);
+
+    // One side of the diamond goes down: the other side carries the traffic
+    mesh.disconnect(0, 1);
+    mesh.send(0, 3, b"second").await.unwrap();
+    assert_eq!(mesh.drain(3).await, vec![b"second".to_vec()]);
+    assert_eq!(mesh.last_frame_to(3).0, 2);
+}
+
+#[tokio::test]
+async fn test_payment_gated_blocks_unpaid_traffic() {
+    let mesh = SimulatedMesh::new(3, &[("mesh.mode", "payment_gated")]).await;
+    mesh.connect(0, 1);
+    mesh.connect(1, 2);
+    mesh.add_route(&[0, 1, 2], 200);
+    mesh.add_route(&[2, 1, 0], 200);
+
+    assert!(matches!(
+        mesh.send(0, 2, b"arbitrary data").await,
+        Err(MeshError::PaymentVerification(_))
+    ));
+    assert!(mesh.drain(2).await.is_empty());
+
+    mesh.send_paid(0, 2, b"arbitrary data", keysend_proof([9u8; 32])).await.unwrap();
+    assert_eq!(mesh.drain(2).await, vec![b"arbitrary data".to_vec()]);
+}
+
+#[tokio::test]
+async fn test_replayed_delivery_rejected() {
+    let mesh = SimulatedMesh::new(3, &[("mesh.mode", "payment_gated")]).await;
+    mesh.connect(0, 1);
+    mesh.connect(1, 2);
+    mesh.add_route(&[0, 1, 2], 200);
+    mesh.add_route(&[2, 1, 0], 200);
+
+    mesh.send_paid(0, 2, b"pay once", keysend_proof([9u8; 32])).await.unwrap();
+    assert_eq!(mesh.drain(2).await.len(), 1);
+
+    // The relay hands the destination the same frame again: it is not delivered twice
+    let (from, frame) = mesh.last_frame_to(2);
+    mesh.inject(from, 2, &frame).await.unwrap();
+    assert!(mesh.drain(2).await.is_empty());
+
+    // The sender cannot reuse the proof for a new packet either
+    assert!(matches!(
+        mesh.send_paid(0, 2, b"pay twice", keysend_proof([9u8; 32])).await,
+        Err(MeshError::ReplayDetected(_))
+    ));
+    assert!(mesh.drain(2).await.is_empty());
+}
@@ -0,0 +1,140 @@
+//! Tests for storage schema versioning and migrations
+
+mod common;
+
+use bllvm_mesh::bandwidth::{BandwidthAccountant, BandwidthConfig, BANDWIDTH_SCHEMA};
+use bllvm_mesh::delivery_ledger::DELIVERY_LEDGER_SCHEMA;
+use bllvm_mesh::error::MeshError;
+use bllvm_mesh::manager::{MeshManager, MESH_CONFIG_SCHEMA};
+use bllvm_mesh::replay::{REPLAY_HASHES_SCHEMA, REPLAY_SEQUENCES_SCHEMA};
+use bllvm_mesh::storage_schema::{open_versioned_tree, stored_version, Migration, TreeSchema, SCHEMA_VERSION_KEY};
+use bllvm_node::module::ipc::protocol::StorageOperation;
+use common::{test_context, MockNodeAPI};
+use std::sync::Arc;
+
+const JAN_15_2026: u64 = 1_768_435_200;
+
+fn seed(node_api: &MockNodeAPI, tree: &str, key: &[u8], value: Vec<u8>) {
+    node_api
+        .storage
+        .lock()
+        .unwrap()
+        .entry(tree.to_string())
+        .or_default()
+        .insert(key.to_vec(), value);
+}
+
+fn stored(node_api: &MockNodeAPI, tree: &str, key: &[u8]) -> Option<Vec<u8>> {
+    node_api
+        .storage
+        .lock()
+        .unwrap()
+        .get(tree)
+        .and_then(|entries| entries.get(key).cloned())
+}
+
+#[tokio::test]
+async fn test_fresh_trees_get_current_version() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let ctx = test_context(&[("mesh.enabled", "true")]);
+    MeshManager::new(&ctx, node_api.clone()).await.unwrap();
+
+    for schema in [
+        &BANDWIDTH_SCHEMA,
+        &DELIVERY_LEDGER_SCHEMA,
+        &MESH_CONFIG_SCHEMA,
+        &REPLAY_HASHES_SCHEMA,
+        &REPLAY_SEQUENCES_SCHEMA,
+    ] {
+        assert_eq!(
+            stored_version(node_api.as_ref(), schema.tree).await.unwrap(),
+            Some(schema.version),
+            "tree {}",
+            schema.tree
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_legacy_bandwidth_record_is_migrated() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    // Unversioned v0 record { period: 24312 (January 2026), bytes: 5000 }
+    let fixture = vec![0xF8, 0x5E, 0, 0, 0, 0, 0, 0, 0x88, 0x13, 0, 0, 0, 0, 0, 0];
+    seed(&node_api, BANDWIDTH_SCHEMA.tree, b"month_to_date", fixture.clone());
+
+    let accountant = BandwidthAccountant::load(node_api.as_ref(), BandwidthConfig::default(), JAN_15_2026)
+        .await
+        .unwrap();
+
+    // Data survived the migration and is in the v1 layout (billing day unknown)
+    assert_eq!(accountant.used_bytes(), 5000);
+    let mut expected = fixture;
+    expected.push(0);
+    assert_eq!(stored(&node_api, BANDWIDTH_SCHEMA.tree, b"month_to_date"), Some(expected));
+    assert_eq!(
+        stored_version(node_api.as_ref(), BANDWIDTH_SCHEMA.tree).await.unwrap(),
+        Some(1)
+    );
+}
+
+#[tokio::test]
+async fn test_newer_schema_refuses_to_start() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    seed(
+        &node_api,
+        DELIVERY_LEDGER_SCHEMA.tree,
+        SCHEMA_VERSION_KEY,
+        (DELIVERY_LEDGER_SCHEMA.version + 1).to_be_bytes().to_vec(),
+    );
+
+    let ctx = test_context(&[("mesh.enabled", "true")]);
+    let result = MeshManager::new(&ctx, node_api.clone()).await;
+    assert!(matches!(result, Err(MeshError::UnsupportedVersion(_))));
+}
+
+fn double_values(entries: &[(Vec<u8>, Vec<u8>)]) -> Result<Vec<StorageOperation>, MeshError> {
+    Ok(entries
+        .iter()
+        .map(|(key, value)| StorageOperation::Insert {
+            key: key.clone(),
+            value: value.iter().map(|b| b * 2).collect(),
+        })
+        .collect())
+}
+
+fn add_one(entries: &[(Vec<u8>, Vec<u8>)]) -> Result<Vec<StorageOperation>, MeshError> {
+    Ok(entries
+        .iter()
+        .map(|(key, value)| StorageOperation::Insert {
+            key: key.clone(),
+            value: value.iter().map(|b| b + 1).collect(),
+        })
+        .collect())
+}
+
+#[tokio::test]
+async fn test_migrations_run_in_order_from_stored_version() {
+    const SCHEMA: TreeSchema = TreeSchema {
+        tree: "schema_test",
+        version: 3,
+        migrations: &[
+            Migration { from: 2, description: "add one", migrate: add_one },
+            Migration { from: 1, description: "double", migrate: double_values },
+            Migration { from: 0, description: "double", migrate: double_values },
+        ],
+    };
+
+    let node_api = Arc::new(MockNodeAPI::new());
+    seed(&node_api, SCHEMA.tree, b"key", vec![5]);
+    seed(&node_api, SCHEMA.tree, SCHEMA_VERSION_KEY, 1u32.to_be_bytes().to_vec());
+
+    open_versioned_tree(node_api.as_ref(), &SCHEMA).await.unwrap();
+
+    // v1 -> v2 doubles, v2 -> v3 adds one; the v0 step is skipped
+    assert_eq!(stored(&node_api, SCHEMA.tree, b"key"), Some(vec![11]));
+    assert_eq!(stored_version(node_api.as_ref(), SCHEMA.tree).await.unwrap(), Some(3));
+
+    // Already current: reopening changes nothing
+    open_versioned_tree(node_api.as_ref(), &SCHEMA).await.unwrap();
+    assert_eq!(stored(&node_api, SCHEMA.tree, b"key"), Some(vec![11]));
+}
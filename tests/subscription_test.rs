@@ -0,0 +1,98 @@
+//! Tests for topic subscriptions and mempool transaction relay
+
+mod common;
+
+use bllvm_mesh::control::ControlMessage;
+use bllvm_mesh::manager::MeshManager;
+use bllvm_mesh::network::deserialize_mesh_packet;
+use bllvm_mesh::packet::{MeshPacket, MeshPacketBuilder, PacketType};
+use bllvm_mesh::routing::NodeId;
+use bllvm_mesh::subscriptions::MeshTopic;
+use common::{test_context, MockNodeAPI};
+use std::sync::Arc;
+
+const PEER: NodeId = [7u8; 32];
+const PEER_ADDR: &str = "10.0.0.7:8334";
+
+async fn start_manager(node_api: &Arc<MockNodeAPI>) -> MeshManager {
+    let ctx = test_context(&[("mesh.enabled", "true"), ("mesh.mode", "open")]);
+    let manager = MeshManager::new(&ctx, node_api.clone()).await.unwrap();
+    manager
+        .routing_table()
+        .add_direct_peer(PEER, PEER_ADDR.as_bytes().to_vec());
+    manager
+}
+
+fn subscribe_packet(manager: &MeshManager, sequence: u64, topics: Vec<MeshTopic>) -> MeshPacket {
+    let payload = ControlMessage::Subscribe { topics }.encode().unwrap();
+    MeshPacketBuilder::new(PEER, manager.node_id())
+        .packet_type(PacketType::Control)
+        .payload(payload)
+        .sequence(sequence)
+        .build()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_subscriptions_survive_restart() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let manager = start_manager(&node_api).await;
+
+    manager
+        .handle_incoming_packet(&subscribe_packet(&manager, 1, vec![MeshTopic::MempoolTransactions]))
+        .await
+        .unwrap();
+    assert_eq!(manager.subscriptions().subscribers(MeshTopic::MempoolTransactions), vec![PEER]);
+    drop(manager);
+
+    let restarted = start_manager(&node_api).await;
+    assert_eq!(restarted.subscriptions().subscribers(MeshTopic::MempoolTransactions), vec![PEER]);
+
+    // No topics unsubscribes, also from storage
+    restarted
+        .handle_incoming_packet(&subscribe_packet(&restarted, 2, Vec::new()))
+        .await
+        .unwrap();
+    assert!(restarted.subscriptions().is_empty());
+    drop(restarted);
+    assert!(start_manager(&node_api).await.subscriptions().is_empty());
+}
+
+#[tokio::test]
+async fn test_subscribe_sends_control_message() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let manager = start_manager(&node_api).await;
+
+    manager.subscribe(PEER, vec![MeshTopic::MempoolTransactions]).await.unwrap();
+    let sent = node_api.take_sent();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].0, PEER_ADDR);
+    let packet = deserialize_mesh_packet(&sent[0].1).unwrap();
+    assert_eq!(packet.packet_type, PacketType::Control);
+    assert_eq!(
+        ControlMessage::decode(&packet.payload).unwrap(),
+        ControlMessage::Subscribe {
+            topics: vec![MeshTopic::MempoolTransactions]
+        }
+    );
+}
+
+#[tokio::test]
+async fn test_relay_skipped_without_subscribers_or_transaction() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let manager = start_manager(&node_api).await;
+
+    // Nobody subscribed: the node is not even asked for the transaction
+    let calls = node_api.call_count();
+    assert_eq!(manager.relay_mempool_transaction(&[1u8; 32]).await.unwrap(), 0);
+    assert_eq!(node_api.call_count(), calls);
+
+    // A transaction already gone from the mempool is not relayed
+    manager
+        .handle_incoming_packet(&subscribe_packet(&manager, 1, vec![MeshTopic::MempoolTransactions]))
+        .await
+        .unwrap();
+    node_api.take_sent();
+    assert_eq!(manager.relay_mempool_transaction(&[1u8; 32]).await.unwrap(), 0);
+    assert!(node_api.take_sent().is_empty());
+}
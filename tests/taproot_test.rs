@@ -0,0 +1,149 @@
+//! Tests for Taproot script path payment proofs
+
+mod common;
+
+use bllvm_mesh::payment_proof::PaymentProof;
+use bllvm_mesh::taproot::{proof_message, taproot_output_key, tapleaf_hash, TAPSCRIPT_LEAF_VERSION};
+use bllvm_mesh::verifier::PaymentVerifier;
+use common::MockNodeAPI;
+use secp256k1::{Keypair, Message, Secp256k1};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const TXID: [u8; 32] = [0x42; 32];
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+fn keypair(byte: u8) -> Keypair {
+    Keypair::from_seckey_slice(&Secp256k1::new(), &[byte; 32]).unwrap()
+}
+
+/// `<signer> OP_CHECKSIG` leaf of an output with internal key 0x09
+struct Output {
+    script: Vec<u8>,
+    control_block: Vec<u8>,
+    script_pubkey: Vec<u8>,
+}
+
+fn checksig_output(signer: &Keypair) -> Output {
+    let mut script = vec![32];
+    script.extend_from_slice(&signer.x_only_public_key().0.serialize());
+    script.push(0xac); // OP_CHECKSIG
+
+    let internal = keypair(9).x_only_public_key().0;
+    let (output_key, parity) = taproot_output_key(&internal, &tapleaf_hash(TAPSCRIPT_LEAF_VERSION, &script)).unwrap();
+    let mut control_block = vec![TAPSCRIPT_LEAF_VERSION | parity.to_u8()];
+    control_block.extend_from_slice(&internal.serialize());
+    let mut script_pubkey = vec![0x51, 32]; // OP_1 <output key>
+    script_pubkey.extend_from_slice(&output_key.serialize());
+
+    Output {
+        script,
+        control_block,
+        script_pubkey,
+    }
+}
+
+fn fund(node_api: &MockNodeAPI, output: &Output, value: u64) {
+    node_api.utxos.lock().unwrap().insert(
+        TXID,
+        bllvm_protocol::UTXO {
+            value: value.try_into().unwrap(),
+            script_pubkey: output.script_pubkey.clone(),
+            height: 100,
+        },
+    );
+}
+
+fn proof(output: &Output, signer: &Keypair, amount_sats: u64, timestamp: u64) -> PaymentProof {
+    let message = Message::from_digest(proof_message(&TXID, 0, amount_sats, timestamp));
+    let signature = Secp256k1::new().sign_schnorr_no_aux_rand(&message, signer);
+    PaymentProof::TaprootScript {
+        control_block: output.control_block.clone(),
+        script: output.script.clone(),
+        witness: vec![signature.as_ref().to_vec()],
+        txid: TXID,
+        vout: 0,
+        amount_sats,
+        timestamp,
+    }
+}
+
+#[tokio::test]
+async fn test_satisfied_script_path_verifies() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let signer = keypair(1);
+    let output = checksig_output(&signer);
+    fund(&node_api, &output, 5_000);
+    let verifier = PaymentVerifier::new(node_api);
+
+    let result = verifier.verify(&proof(&output, &signer, 1_000, now())).await.unwrap();
+    assert!(result.verified, "{:?}", result.error);
+    assert_eq!(result.amount, 1_000);
+}
+
+#[tokio::test]
+async fn test_unsatisfied_or_uncommitted_scripts_rejected() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let signer = keypair(1);
+    let output = checksig_output(&signer);
+    fund(&node_api, &output, 5_000);
+    let verifier = PaymentVerifier::new(node_api);
+    let timestamp = now();
+
+    // Someone outside the condition cannot sign for it
+    let result = verifier.verify(&proof(&output, &keypair(2), 1_000, timestamp)).await.unwrap();
+    assert!(!result.verified);
+    assert_eq!(
+        result.error.as_deref(),
+        Some("Taproot script not satisfied: signature check failed")
+    );
+
+    // The signature covers the claimed amount
+    let PaymentProof::TaprootScript { witness, .. } = proof(&output, &signer, 1_000, timestamp) else {
+        unreachable!()
+    };
+    let restamped = PaymentProof::TaprootScript {
+        control_block: output.control_block.clone(),
+        script: output.script.clone(),
+        witness,
+        txid: TXID,
+        vout: 0,
+        amount_sats: 2_000,
+        timestamp,
+    };
+    assert!(!verifier.verify(&restamped).await.unwrap().verified);
+
+    // A script the output does not commit to
+    let other = checksig_output(&keypair(2));
+    let result = verifier.verify(&proof(&other, &keypair(2), 1_000, timestamp)).await.unwrap();
+    assert_eq!(result.error.as_deref(), Some("Script is not committed to the Taproot output"));
+}
+
+#[tokio::test]
+async fn test_output_must_exist_and_cover_amount() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let signer = keypair(1);
+    let output = checksig_output(&signer);
+    let verifier = PaymentVerifier::new(node_api.clone());
+
+    let result = verifier.verify(&proof(&output, &signer, 1_000, now())).await.unwrap();
+    assert_eq!(result.error.as_deref(), Some("Taproot output not found or already spent"));
+
+    fund(&node_api, &output, 500);
+    let result = verifier.verify(&proof(&output, &signer, 1_000, now())).await.unwrap();
+    assert_eq!(result.error.as_deref(), Some("Taproot output holds 500 sats, proof claims 1000"));
+}
+
+#[test]
+fn test_proof_hash_covers_only_the_outpoint() {
+    let signer = keypair(1);
+    let output = checksig_output(&signer);
+    let timestamp = now();
+    assert_eq!(
+        proof(&output, &signer, 1_000, timestamp).hash(),
+        proof(&output, &signer, 2_000, timestamp + 1).hash()
+    );
+}
@@ -0,0 +1,147 @@
+//! Tests for the packet hop budget (TTL)
+
+mod common;
+
+use bllvm_mesh::error::MeshError;
+use bllvm_mesh::manager::MeshManager;
+use bllvm_mesh::network::deserialize_mesh_packet;
+use bllvm_mesh::packet::{MeshPacketBuilder, PacketType, DEFAULT_TTL};
+use bllvm_mesh::routing::{NodeId, RoutingEntry};
+use common::{test_context, MockNodeAPI};
+use std::sync::Arc;
+
+const SOURCE: NodeId = [1u8; 32];
+const DESTINATION: NodeId = [9u8; 32];
+
+async fn relay() -> (MeshManager, Arc<MockNodeAPI>) {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let ctx = test_context(&[("mesh.enabled", "true"), ("mesh.mode", "open")]);
+    let manager = MeshManager::new(&ctx, node_api.clone()).await.unwrap();
+    (manager, node_api)
+}
+
+/// Point `manager`'s route to the destination at `peer`
+fn route_via(manager: &MeshManager, peer: NodeId, peer_addr: &str) {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    manager.routing_table().add_direct_peer(peer, peer_addr.as_bytes().to_vec());
+    manager.routing_table().add_route(RoutingEntry {
+        node_id: DESTINATION,
+        direct_address: None,
+        next_hop: Some(peer),
+        route_path: vec![manager.node_id(), peer, DESTINATION],
+        route_cost: 100,
+        last_updated: now,
+        quality_score: 0.8,
+    });
+}
+
+#[tokio::test]
+async fn test_packet_looping_between_two_nodes_is_dropped() {
+    let (a, a_api) = relay().await;
+    let (b, b_api) = relay().await;
+    route_via(&a, b.node_id(), "10.0.0.3:8334");
+    route_via(&b, a.node_id(), "10.0.0.2:8334");
+
+    let ttl = 5;
+    let mut packet = MeshPacketBuilder::new(SOURCE, DESTINATION)
+        .packet_type(PacketType::BitcoinP2P)
+        .payload(vec![1, 2, 3])
+        .ttl(ttl)
+        .build()
+        .unwrap();
+
+    // Misbehaving relays strip the recorded route (and re-seal the packet), defeating the loop guard
+    let mut hops = 0;
+    let mut current = (&a, &a_api);
+    let mut next = (&b, &b_api);
+    let result = loop {
+        if let Err(e) = current.0.handle_incoming_packet(&packet).await {
+            break e;
+        }
+        let sent = current.1.take_sent();
+        assert_eq!(sent.len(), 1);
+        hops += 1;
+
+        packet = deserialize_mesh_packet(&sent[0].1).unwrap();
+        packet.route = vec![SOURCE, DESTINATION];
+        packet.checksum = packet.compute_checksum();
+        std::mem::swap(&mut current, &mut next);
+        assert!(hops <= usize::from(ttl), "packet outlived its TTL");
+    };
+
+    assert!(matches!(result, MeshError::TtlExpired(_)));
+    assert_eq!(hops, usize::from(ttl) - 1);
+    assert!(current.1.take_sent().is_empty());
+}
+
+#[tokio::test]
+async fn test_forwarding_decrements_ttl() {
+    let (a, a_api) = relay().await;
+    route_via(&a, [3u8; 32], "10.0.0.3:8334");
+
+    let packet = MeshPacketBuilder::new(SOURCE, DESTINATION)
+        .packet_type(PacketType::BitcoinP2P)
+        .payload(vec![1])
+        .build()
+        .unwrap();
+    a.handle_incoming_packet(&packet).await.unwrap();
+
+    let sent = a_api.take_sent();
+    assert_eq!(deserialize_mesh_packet(&sent[0].1).unwrap().ttl, DEFAULT_TTL - 1);
+}
+
+#[tokio::test]
+async fn test_validate_rejects_zero_and_oversized_ttl() {
+    let mut packet = MeshPacketBuilder::new(SOURCE, DESTINATION)
+        .packet_type(PacketType::BitcoinP2P)
+        .payload(vec![1])
+        .build()
+        .unwrap();
+    assert!(packet.validate_with_max_ttl(DEFAULT_TTL).is_ok());
+
+    packet.ttl = 0;
+    assert!(packet.validate().is_err());
+
+    packet.ttl = DEFAULT_TTL + 1;
+    assert!(packet.validate_with_max_ttl(DEFAULT_TTL).is_err());
+
+    // Oversized budgets are refused on receipt
+    let (a, _) = relay().await;
+    packet.ttl = u8::MAX;
+    assert!(matches!(
+        a.handle_incoming_packet(&packet).await,
+        Err(MeshError::InvalidPacket(_))
+    ));
+}
+
+#[test]
+fn test_builder_validates_packets() {
+    let builder = || {
+        MeshPacketBuilder::new(SOURCE, DESTINATION)
+            .packet_type(PacketType::BitcoinP2P)
+            .payload(vec![1])
+    };
+
+    let packet = builder().sequence(7).ttl(DEFAULT_TTL).build().unwrap();
+    assert_eq!((packet.sequence, packet.ttl), (7, DEFAULT_TTL));
+    assert_eq!(packet.route, vec![SOURCE, DESTINATION]);
+
+    assert!(matches!(builder().ttl(0).build(), Err(MeshError::InvalidPacket(_))));
+    assert!(builder().ttl(u8::MAX).build().is_err());
+    assert!(builder().ttl(u8::MAX).build_with_max_ttl(u8::MAX).is_ok());
+
+    // Paid packets need a proof
+    assert!(MeshPacketBuilder::new(SOURCE, DESTINATION)
+        .packet_type(PacketType::Paid)
+        .payload(vec![1])
+        .build()
+        .is_err());
+
+    // Without a type or payload, an empty Bitcoin packet
+    let packet = MeshPacketBuilder::new(SOURCE, DESTINATION).build().unwrap();
+    assert_eq!(packet.packet_type, PacketType::BitcoinP2P);
+    assert!(packet.payload.is_empty());
+}
@@ -0,0 +1,96 @@
+//! Tests for the payment verification result cache
+
+mod common;
+
+use bllvm_mesh::payment_proof::{PaymentProof, DEFAULT_KEYSEND_MAX_AGE_SECONDS};
+use bllvm_mesh::verifier::{PaymentVerifier, VerifierStats};
+use common::MockNodeAPI;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+fn keysend_proof(preimage: [u8; 32], amount_msats: u64, timestamp: u64) -> PaymentProof {
+    PaymentProof::Keysend {
+        payment_hash: Sha256::digest(preimage).into(),
+        preimage,
+        amount_msats,
+        timestamp,
+        custom_tlv: Vec::new(),
+        destination_pubkey: vec![2u8; 33],
+    }
+}
+
+/// Verifier with Lightning key 02..02 that checks keysend proofs offline
+fn offline_verifier(node_api: Arc<MockNodeAPI>) -> PaymentVerifier {
+    PaymentVerifier::new(node_api)
+        .with_keysend_settlement_check(false)
+        .with_local_payee(vec![2u8; 33])
+}
+
+#[tokio::test]
+async fn test_second_verification_makes_no_node_calls() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let verifier = offline_verifier(node_api.clone());
+    let proof = keysend_proof([5u8; 32], 7_000, now_secs());
+
+    let first = verifier.verify(&proof).await.unwrap();
+    assert!(first.verified);
+    let calls = node_api.call_count();
+    assert!(calls > 0);
+
+    let second = verifier.verify(&proof).await.unwrap();
+    assert!(second.verified);
+    assert_eq!(second.amount, first.amount);
+    assert_eq!(node_api.call_count(), calls);
+    assert_eq!(
+        verifier.stats(),
+        VerifierStats {
+            cache_hits: 1,
+            cache_misses: 1,
+            cached: 1,
+        }
+    );
+}
+
+#[tokio::test]
+async fn test_failures_and_variants_not_served_from_cache() {
+    let node_api = Arc::new(MockNodeAPI::new());
+    let verifier = offline_verifier(node_api.clone());
+
+    // Failed results are not cached
+    let zero = keysend_proof([5u8; 32], 0, now_secs());
+    assert!(!verifier.verify(&zero).await.unwrap().verified);
+    assert!(!verifier.verify(&zero).await.unwrap().verified);
+    assert_eq!(verifier.stats().cached, 0);
+
+    // The same payment claiming another amount is verified afresh
+    let proof = keysend_proof([5u8; 32], 7_000, now_secs());
+    assert!(verifier.verify(&proof).await.unwrap().verified);
+    let restamped = keysend_proof([5u8; 32], 9_000, now_secs());
+    let calls = node_api.call_count();
+    assert_eq!(verifier.verify(&restamped).await.unwrap().amount, 9);
+    assert!(node_api.call_count() > calls);
+    assert_eq!(verifier.stats().cache_hits, 0);
+}
+
+#[tokio::test]
+async fn test_expired_proof_dropped_from_cache() {
+    let verifier = offline_verifier(Arc::new(MockNodeAPI::new())).with_cache_size(2);
+    assert!(verifier.verify(&keysend_proof([5u8; 32], 7_000, now_secs())).await.unwrap().verified);
+    assert_eq!(verifier.stats().cached, 1);
+
+    // The payment's proof past its keysend age is refused and forgotten
+    let expired = keysend_proof([5u8; 32], 7_000, now_secs() - DEFAULT_KEYSEND_MAX_AGE_SECONDS - 60);
+    assert!(!verifier.verify(&expired).await.unwrap().verified);
+    assert_eq!(verifier.stats().cached, 0);
+
+    // The cache holds at most its configured size
+    for byte in 1..=3u8 {
+        assert!(verifier.verify(&keysend_proof([byte; 32], 7_000, now_secs())).await.unwrap().verified);
+    }
+    assert_eq!(verifier.stats().cached, 2);
+}
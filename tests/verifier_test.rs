@@ -1,12 +1,28 @@
 //! Unit tests for payment verifier
 
 use bllvm_mesh::verifier::PaymentVerifier;
-use bllvm_mesh::payment_proof::{PaymentProof, VerificationResult};
+use bllvm_mesh::payment_proof::{
+    silent_payment_label, PaymentProof, SilentPaymentAddress, SilentPaymentOutput, ToSilentPaymentAddress,
+    VerificationResult, BOLT12_PROOF_MAX_AGE_SECONDS, DEFAULT_KEYSEND_MAX_AGE_SECONDS, KEYSEND_PREIMAGE_TLV_TYPE,
+};
 use bllvm_node::module::traits::NodeAPI;
 use std::sync::Arc;
 
 // Mock NodeAPI for testing
-struct MockNodeAPI;
+struct MockNodeAPI {
+    /// Tip difficulty reported by `get_chain_info`
+    difficulty: f64,
+}
+
+impl MockNodeAPI {
+    fn mainnet() -> Self {
+        Self { difficulty: 9e13 }
+    }
+
+    fn testnet() -> Self {
+        Self { difficulty: 1.0 }
+    }
+}
 
 #[async_trait::async_trait]
 impl NodeAPI for MockNodeAPI {
@@ -31,7 +47,7 @@ impl NodeAPI for MockNodeAPI {
     }
     async fn get_network_peers(&self) -> Result<Vec<bllvm_node::module::traits::PeerInfo>, bllvm_node::module::traits::ModuleError> { Ok(Vec::new()) }
     async fn get_chain_info(&self) -> Result<bllvm_node::module::traits::ChainInfo, bllvm_node::module::traits::ModuleError> {
-        Ok(bllvm_node::module::traits::ChainInfo { tip: [0u8; 32], height: 100, difficulty: 1.0 })
+        Ok(bllvm_node::module::traits::ChainInfo { tip: [0u8; 32], height: 100, difficulty: self.difficulty })
     }
     async fn get_block_by_height(&self, _: u64) -> Result<Option<bllvm_protocol::Block>, bllvm_node::module::traits::ModuleError> { Ok(None) }
     async fn get_lightning_node_url(&self) -> Result<Option<String>, bllvm_node::module::traits::ModuleError> { Ok(None) }
@@ -81,7 +97,7 @@ impl NodeAPI for MockNodeAPI {
 
 #[tokio::test]
 async fn test_payment_verifier_creation() {
-    let node_api = Arc::new(MockNodeAPI);
+    let node_api = Arc::new(MockNodeAPI::mainnet());
     let verifier = PaymentVerifier::new(node_api);
     // Verifier should be created successfully
     assert!(true); // Basic creation test
@@ -89,7 +105,7 @@ async fn test_payment_verifier_creation() {
 
 #[tokio::test]
 async fn test_expired_payment_proof() {
-    let node_api = Arc::new(MockNodeAPI);
+    let node_api = Arc::new(MockNodeAPI::mainnet());
     let verifier = PaymentVerifier::new(node_api);
     
     // Create an expired payment proof
@@ -114,3 +130,493 @@ async fn test_expired_payment_proof() {
     assert!(verification.error.is_some());
 }
 
+
+fn bolt12_proof(timestamp: u64) -> PaymentProof {
+    PaymentProof::LightningBolt12 {
+        offer: "lno1notanoffer".to_string(),
+        invoice_request: vec![0u8; 16],
+        preimage: [3u8; 32],
+        amount_msats: 5_000,
+        timestamp,
+    }
+}
+
+#[tokio::test]
+async fn test_stale_bolt12_proof_is_expired() {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+
+    assert!(!bolt12_proof(now).is_expired());
+    let stale = bolt12_proof(now - BOLT12_PROOF_MAX_AGE_SECONDS - 1);
+    assert!(stale.is_expired());
+
+    let verifier = PaymentVerifier::new(Arc::new(MockNodeAPI::mainnet()));
+    let verification = verifier.verify(&stale).await.unwrap();
+    assert!(!verification.verified);
+}
+
+#[tokio::test]
+async fn test_malformed_bolt12_offer_rejected() {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+
+    let verifier = PaymentVerifier::new(Arc::new(MockNodeAPI::mainnet()));
+    let verification = verifier.verify(&bolt12_proof(now)).await.unwrap();
+    assert!(!verification.verified);
+    assert!(verification.error.unwrap().contains("BOLT12 offer"));
+}
+
+#[tokio::test]
+async fn test_bolt12_offer_refused_without_local_lightning_key() {
+    use lightning::bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+    use lightning::offers::offer::OfferBuilder;
+
+    // A well-formed offer anyone could have issued
+    let secp = Secp256k1::new();
+    let issuer = PublicKey::from_secret_key(&secp, &SecretKey::from_slice(&[7u8; 32]).unwrap());
+    let offer = OfferBuilder::new(issuer).amount_msats(5_000).build().unwrap();
+    let PaymentProof::LightningBolt12 { invoice_request, preimage, amount_msats, timestamp, .. } = bolt12_proof(now_secs())
+    else {
+        unreachable!()
+    };
+    let proof = PaymentProof::LightningBolt12 {
+        offer: offer.to_string(),
+        invoice_request,
+        preimage,
+        amount_msats,
+        timestamp,
+    };
+
+    let verifier = PaymentVerifier::new(Arc::new(MockNodeAPI::mainnet()));
+    let verification = verifier.verify(&proof).await.unwrap();
+    assert!(!verification.verified);
+    assert!(verification.error.unwrap().contains("offer issuer"));
+}
+
+#[test]
+fn test_bolt12_hash_ignores_restamping() {
+    let proof = bolt12_proof(1_700_000_000);
+    assert_eq!(proof.hash(), bolt12_proof(1_700_000_500).hash());
+
+    let PaymentProof::LightningBolt12 { offer, invoice_request, amount_msats, timestamp, .. } = proof.clone() else {
+        unreachable!()
+    };
+    let other_preimage = PaymentProof::LightningBolt12 {
+        offer,
+        invoice_request,
+        preimage: [4u8; 32],
+        amount_msats,
+        timestamp,
+    };
+    assert_ne!(proof.hash(), other_preimage.hash());
+}
+
+fn now_secs() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+fn keysend_proof(preimage: [u8; 32], amount_msats: u64, timestamp: u64) -> PaymentProof {
+    use sha2::{Digest, Sha256};
+    PaymentProof::Keysend {
+        payment_hash: Sha256::digest(preimage).into(),
+        preimage,
+        amount_msats,
+        timestamp,
+        custom_tlv: vec![(KEYSEND_PREIMAGE_TLV_TYPE, preimage.to_vec())],
+        destination_pubkey: vec![2u8; 33],
+    }
+}
+
+/// Verifier with Lightning key 02..02 that checks keysend proofs offline
+fn offline_verifier() -> PaymentVerifier {
+    PaymentVerifier::new(Arc::new(MockNodeAPI::mainnet()))
+        .with_keysend_settlement_check(false)
+        .with_local_payee(vec![2u8; 33])
+}
+
+#[tokio::test]
+async fn test_keysend_proof_verified() {
+    let verifier = offline_verifier();
+    let verification = verifier.verify(&keysend_proof([5u8; 32], 7_000, now_secs())).await.unwrap();
+    assert!(verification.verified);
+    assert_eq!(verification.amount, 7);
+
+    // Preimage that does not hash to the payment hash
+    let PaymentProof::Keysend { payment_hash, amount_msats, timestamp, .. } =
+        keysend_proof([5u8; 32], 7_000, now_secs())
+    else {
+        unreachable!()
+    };
+    let forged = PaymentProof::Keysend {
+        payment_hash,
+        preimage: [6u8; 32],
+        amount_msats,
+        timestamp,
+        custom_tlv: Vec::new(),
+        destination_pubkey: vec![2u8; 33],
+    };
+    assert!(!verifier.verify(&forged).await.unwrap().verified);
+
+    assert!(!verifier.verify(&keysend_proof([5u8; 32], 0, now_secs())).await.unwrap().verified);
+}
+
+#[tokio::test]
+async fn test_keysend_max_age_configurable() {
+    let old = keysend_proof([5u8; 32], 7_000, now_secs() - DEFAULT_KEYSEND_MAX_AGE_SECONDS - 60);
+    assert!(old.is_expired());
+    assert!(!old.is_expired_with_keysend_max_age(2 * DEFAULT_KEYSEND_MAX_AGE_SECONDS));
+
+    let strict = offline_verifier();
+    assert!(!strict.verify(&old).await.unwrap().verified);
+    let lenient = offline_verifier().with_keysend_max_age(2 * DEFAULT_KEYSEND_MAX_AGE_SECONDS);
+    assert!(lenient.verify(&old).await.unwrap().verified);
+}
+
+#[tokio::test]
+async fn test_keysend_refused_without_lightning_backend() {
+    // No Lightning backend: settlement cannot be confirmed, so nothing is credited
+    let verifier = PaymentVerifier::new(Arc::new(MockNodeAPI::mainnet())).with_local_payee(vec![2u8; 33]);
+    let verification = verifier.verify(&keysend_proof([5u8; 32], 7_000, now_secs())).await.unwrap();
+    assert!(!verification.verified);
+    assert!(verification.error.unwrap().contains("Cannot confirm keysend settlement"));
+}
+
+#[tokio::test]
+async fn test_keysend_to_another_node_refused() {
+    let verifier = PaymentVerifier::new(Arc::new(MockNodeAPI::mainnet()))
+        .with_keysend_settlement_check(false)
+        .with_local_payee(vec![3u8; 33]);
+    let verification = verifier.verify(&keysend_proof([5u8; 32], 7_000, now_secs())).await.unwrap();
+    assert!(!verification.verified);
+    assert_eq!(verification.error.unwrap(), "Keysend destination is not this node");
+
+    // Nor when this node's key is unknown
+    let unknown = PaymentVerifier::new(Arc::new(MockNodeAPI::mainnet())).with_keysend_settlement_check(false);
+    assert!(!unknown.verify(&keysend_proof([5u8; 32], 7_000, now_secs())).await.unwrap().verified);
+}
+
+fn secret_key(byte: u8) -> secp256k1::SecretKey {
+    secp256k1::SecretKey::from_slice(&[byte; 32]).unwrap()
+}
+
+/// A payment to `address` from a transaction whose inputs sum to key 0x07
+fn silent_payment(address: &SilentPaymentAddress, amount_sats: u64) -> SilentPaymentOutput {
+    let input_key_sum = secret_key(7);
+    let smallest_outpoint = [3u8; 36];
+    SilentPaymentOutput {
+        input_pubkey_sum: input_key_sum.public_key(&secp256k1::Secp256k1::new()),
+        smallest_outpoint,
+        output_key: address.derive_output(&input_key_sum, &smallest_outpoint, 0).unwrap(),
+        amount_sats,
+        timestamp: now_secs(),
+    }
+}
+
+#[test]
+fn test_silent_payment_address_round_trip() {
+    let address = SilentPaymentAddress::new(&secret_key(1), &secret_key(2));
+    let encoded = address.encode();
+    assert!(encoded.starts_with("sp1q"));
+    assert_eq!(SilentPaymentAddress::decode(&encoded).unwrap(), address);
+
+    assert!(SilentPaymentAddress::decode("bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq").is_err());
+}
+
+#[test]
+fn test_node_addresses_are_unlinkable_labels() {
+    let (scan_key, spend_key) = (secret_key(1), secret_key(2));
+    let first = [1u8; 32].to_silent_payment_address(&scan_key, &spend_key);
+    let second = [2u8; 32].to_silent_payment_address(&scan_key, &spend_key);
+    assert_ne!(first, second);
+
+    let labelled = SilentPaymentAddress::with_label(&scan_key, &spend_key, silent_payment_label(&[1u8; 32])).unwrap();
+    assert_eq!(labelled.encode(), first);
+}
+
+#[tokio::test]
+async fn test_verify_silent_payment() {
+    let verifier = PaymentVerifier::new(Arc::new(MockNodeAPI::mainnet()));
+    let (scan_key, spend_key) = (secret_key(1), secret_key(2));
+    let spend_pubkey = spend_key.public_key(&secp256k1::Secp256k1::new());
+
+    let output = silent_payment(&SilentPaymentAddress::new(&scan_key, &spend_key), 5_000);
+    let result = verifier.verify_silent_payment(&output, &scan_key, &spend_pubkey, None).unwrap();
+    assert!(result.verified);
+    assert_eq!(result.amount, 5_000);
+
+    // Someone else's scan key finds nothing
+    assert!(!verifier.verify_silent_payment(&output, &secret_key(9), &spend_pubkey, None).unwrap().verified);
+
+    // A labelled address is verified with its label
+    let label = silent_payment_label(&[4u8; 32]);
+    let labelled = silent_payment(&SilentPaymentAddress::with_label(&scan_key, &spend_key, label).unwrap(), 5_000);
+    assert!(verifier.verify_silent_payment(&labelled, &scan_key, &spend_pubkey, Some(label)).unwrap().verified);
+    assert!(!verifier.verify_silent_payment(&labelled, &scan_key, &spend_pubkey, None).unwrap().verified);
+}
+
+/// Preimage with the entropy of a random one
+const PREIMAGE: [u8; 32] = [
+    0x3f, 0xa1, 0x07, 0xc4, 0x5e, 0x92, 0x18, 0xdb, 0x66, 0x0c, 0xe7, 0x41, 0xb9, 0x2a, 0x85, 0x73,
+    0xf0, 0x1d, 0x58, 0xae, 0x34, 0xcb, 0x9f, 0x02, 0x7d, 0xe4, 0x16, 0x69, 0xb3, 0x50, 0x8a, 0xc7,
+];
+
+/// Options of a test BOLT11 invoice
+struct TestInvoice {
+    currency: lightning_invoice::Currency,
+    amount_msats: Option<u64>,
+    /// Seconds before now the invoice was issued
+    age_seconds: u64,
+    payment_secret: Option<[u8; 32]>,
+}
+
+impl Default for TestInvoice {
+    fn default() -> Self {
+        Self {
+            currency: lightning_invoice::Currency::Bitcoin,
+            amount_msats: Some(10_000),
+            age_seconds: 0,
+            payment_secret: Some([0x5a; 32]),
+        }
+    }
+}
+
+impl TestInvoice {
+    /// BOLT11 invoice for `preimage`, signed by `key`
+    fn sign(&self, key: &secp256k1::SecretKey, preimage: &[u8; 32]) -> String {
+        use lightning::bitcoin::hashes::{sha256, Hash};
+        use lightning_invoice::InvoiceBuilder;
+
+        let issued_at = std::time::SystemTime::now() - std::time::Duration::from_secs(self.age_seconds);
+        let builder = InvoiceBuilder::new(self.currency.clone())
+            .description("mesh routing fee".to_string())
+            .payment_hash(sha256::Hash::hash(preimage))
+            .timestamp(issued_at);
+        let builder = match self.amount_msats {
+            Some(amount_msats) => builder.amount_milli_satoshis(amount_msats),
+            None => builder,
+        };
+        let builder = match self.payment_secret {
+            Some(secret) => builder.payment_secret(lightning_invoice::PaymentSecret(secret)),
+            None => builder,
+        };
+        builder
+            .build_signed(|hash| secp256k1::Secp256k1::new().sign_ecdsa_recoverable(hash, key))
+            .unwrap()
+            .to_string()
+    }
+
+    /// Proof paying this invoice with `preimage`
+    fn proof(&self, key: &secp256k1::SecretKey, preimage: [u8; 32]) -> PaymentProof {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        PaymentProof::Lightning {
+            invoice: self.sign(key, &preimage),
+            preimage,
+            amount_msats: self.amount_msats.unwrap_or(10_000),
+            timestamp: now,
+            expires_at: now + 3600,
+        }
+    }
+}
+
+fn invoice_proof(key: &secp256k1::SecretKey) -> PaymentProof {
+    TestInvoice::default().proof(key, PREIMAGE)
+}
+
+#[tokio::test]
+async fn test_invoice_must_pay_this_node() {
+    let secp = secp256k1::Secp256k1::new();
+    let local_key = secret_key(7);
+    let local_payee = local_key.public_key(&secp).serialize().to_vec();
+    let verifier = PaymentVerifier::new(Arc::new(MockNodeAPI::mainnet())).with_local_payee(local_payee);
+
+    assert!(verifier.verify(&invoice_proof(&local_key)).await.unwrap().verified);
+
+    let result = verifier.verify(&invoice_proof(&secret_key(8))).await.unwrap();
+    assert!(!result.verified);
+    assert_eq!(result.error.as_deref(), Some("Invoice payee is not this node"));
+
+    // Without a Lightning backend the payee cannot be checked
+    let verifier = PaymentVerifier::new(Arc::new(MockNodeAPI::mainnet()));
+    assert!(!verifier.verify(&invoice_proof(&local_key)).await.unwrap().verified);
+}
+
+#[tokio::test]
+async fn test_relay_checks_payee_against_destination() {
+    let secp = secp256k1::Secp256k1::new();
+    let verifier = PaymentVerifier::new(Arc::new(MockNodeAPI::mainnet())).with_require_local_payee(false);
+
+    // The destination receives payments with its mesh signing key
+    let (destination, _) = secret_key(7).public_key(&secp).x_only_public_key();
+    let proof = invoice_proof(&secret_key(7));
+    assert!(verifier.verify_for_destination(&proof, Some(&destination)).await.unwrap().verified);
+
+    let result = verifier
+        .verify_for_destination(&invoice_proof(&secret_key(8)), Some(&destination))
+        .await
+        .unwrap();
+    assert_eq!(result.error.as_deref(), Some("Invoice payee is not the packet destination"));
+
+    // Cached for the right destination, the proof still fails for another
+    // or an unknown one
+    let (other, _) = secret_key(8).public_key(&secp).x_only_public_key();
+    let result = verifier.verify_for_destination(&proof, Some(&other)).await.unwrap();
+    assert_eq!(result.error.as_deref(), Some("Invoice payee is not the packet destination"));
+    let result = verifier.verify(&proof).await.unwrap();
+    assert_eq!(
+        result.error.as_deref(),
+        Some("Cannot check invoice payee: packet destination key is unknown")
+    );
+
+    // Keysend destinations are held to the same key
+    let keysend = keysend_proof([5u8; 32], 7_000, now_secs());
+    let result = verifier.verify_for_destination(&keysend, Some(&destination)).await.unwrap();
+    assert_eq!(result.error.as_deref(), Some("Keysend destination is not the packet destination"));
+
+    // Malformed payee keys are still refused
+    let mut keysend = keysend_proof([5u8; 32], 7_000, now_secs());
+    if let PaymentProof::Keysend { ref mut destination_pubkey, .. } = keysend {
+        destination_pubkey.truncate(32);
+    }
+    let result = verifier.verify_for_destination(&keysend, Some(&destination)).await.unwrap();
+    assert_eq!(result.error.as_deref(), Some("Keysend destination is not a valid public key"));
+}
+
+#[tokio::test]
+async fn test_forgeable_lightning_proofs_rejected() {
+    use lightning_invoice::Currency;
+
+    let local_key = secret_key(7);
+    let local_payee = local_key.public_key(&secp256k1::Secp256k1::new()).serialize().to_vec();
+    let verifier = PaymentVerifier::new(Arc::new(MockNodeAPI::mainnet())).with_local_payee(local_payee.clone());
+    let error = |result: VerificationResult| {
+        assert!(!result.verified);
+        result.error.unwrap()
+    };
+
+    let proof = TestInvoice::default().proof(&local_key, [0u8; 32]);
+    assert_eq!(error(verifier.verify(&proof).await.unwrap()), "Payment preimage has too little entropy");
+    let mut repeated = [0xabu8; 32];
+    repeated[..4].copy_from_slice(&[1, 2, 3, 4]);
+    let proof = TestInvoice::default().proof(&local_key, repeated);
+    assert_eq!(error(verifier.verify(&proof).await.unwrap()), "Payment preimage has too little entropy");
+
+    let testnet = TestInvoice {
+        currency: Currency::BitcoinTestnet,
+        ..TestInvoice::default()
+    };
+    let proof = testnet.proof(&local_key, PREIMAGE);
+    assert_eq!(error(verifier.verify(&proof).await.unwrap()), "Invoice is for another Bitcoin network");
+    let testnet_verifier = PaymentVerifier::new(Arc::new(MockNodeAPI::testnet()))
+        .with_local_payee(local_payee.clone())
+        .with_network("testnet")
+        .unwrap();
+    assert!(testnet_verifier.verify(&proof).await.unwrap().verified);
+
+    let no_amount = TestInvoice {
+        amount_msats: None,
+        ..TestInvoice::default()
+    };
+    let proof = no_amount.proof(&local_key, PREIMAGE);
+    assert_eq!(error(verifier.verify(&proof).await.unwrap()), "Invoice has no amount");
+    let lenient = PaymentVerifier::new(Arc::new(MockNodeAPI::mainnet()))
+        .with_local_payee(local_payee.clone())
+        .with_zero_amount_invoices(true);
+    assert!(lenient.verify(&proof).await.unwrap().verified);
+
+    let stale = TestInvoice {
+        age_seconds: 2 * 60 * 60,
+        ..TestInvoice::default()
+    };
+    let proof = stale.proof(&local_key, PREIMAGE);
+    assert!(verifier.verify(&proof).await.unwrap().verified);
+    let strict = PaymentVerifier::new(Arc::new(MockNodeAPI::mainnet()))
+        .with_local_payee(local_payee)
+        .with_max_invoice_age(60 * 60);
+    assert_eq!(error(strict.verify(&proof).await.unwrap()), "Invoice is too old");
+
+    for payment_secret in [None, Some([0u8; 32])] {
+        let no_secret = TestInvoice {
+            payment_secret,
+            ..TestInvoice::default()
+        };
+        let proof = no_secret.proof(&local_key, PREIMAGE);
+        assert_eq!(error(verifier.verify(&proof).await.unwrap()), "Invoice has no payment secret");
+    }
+}
+
+#[tokio::test]
+async fn test_invoice_network_must_match_node_chain() {
+    let local_key = secret_key(7);
+    let local_payee = local_key.public_key(&secp256k1::Secp256k1::new()).serialize().to_vec();
+    let proof = invoice_proof(&local_key);
+
+    // Mainnet configured (the default) on a node following testnet
+    let verifier = PaymentVerifier::new(Arc::new(MockNodeAPI::testnet())).with_local_payee(local_payee.clone());
+    let result = verifier.verify(&proof).await.unwrap();
+    assert!(!result.verified);
+    assert_eq!(
+        result.error.as_deref(),
+        Some("Configured Bitcoin network does not match the node's chain")
+    );
+
+    // A regtest node is told apart from testnet
+    let verifier = PaymentVerifier::new(Arc::new(MockNodeAPI { difficulty: 4.6e-10 }))
+        .with_local_payee(local_payee.clone())
+        .with_network("testnet")
+        .unwrap();
+    let testnet_proof = TestInvoice {
+        currency: lightning_invoice::Currency::BitcoinTestnet,
+        ..TestInvoice::default()
+    }
+    .proof(&local_key, PREIMAGE);
+    assert!(!verifier.verify(&testnet_proof).await.unwrap().verified);
+
+    let verifier = PaymentVerifier::new(Arc::new(MockNodeAPI::mainnet())).with_local_payee(local_payee);
+    assert!(verifier.verify(&proof).await.unwrap().verified);
+}
+
+#[test]
+fn test_unknown_invoice_network_rejected() {
+    assert!(PaymentVerifier::new(Arc::new(MockNodeAPI::mainnet())).with_network("signet").is_err());
+}
+
+#[cfg(feature = "ctv")]
+#[test]
+fn test_ctv_inclusion_policy_names() {
+    use bllvm_mesh::verifier::CtvInclusion;
+
+    assert_eq!(CtvInclusion::from_name("none"), Some(CtvInclusion::None));
+    assert_eq!(CtvInclusion::from_name("mempool"), Some(CtvInclusion::Mempool));
+    assert_eq!(CtvInclusion::from_name("confirmed:6"), Some(CtvInclusion::Confirmed(6)));
+    assert_eq!(CtvInclusion::from_name("confirmed:0"), None);
+    assert_eq!(CtvInclusion::from_name("confirmed"), None);
+}
+
+#[cfg(feature = "ctv")]
+#[test]
+fn test_merkle_branch_folds_by_index() {
+    use bllvm_mesh::verifier::merkle_root_from_branch;
+    use sha2::{Digest, Sha256};
+
+    let hash_pair = |left: &[u8; 32], right: &[u8; 32]| -> [u8; 32] {
+        Sha256::digest(Sha256::digest([left.as_slice(), right.as_slice()].concat())).into()
+    };
+    let leaves = [[1u8; 32], [2u8; 32], [3u8; 32], [4u8; 32]];
+    let root = hash_pair(&hash_pair(&leaves[0], &leaves[1]), &hash_pair(&leaves[2], &leaves[3]));
+
+    // Third transaction: right sibling first, then the left subtree
+    let branch = [leaves[3], hash_pair(&leaves[0], &leaves[1])];
+    assert_eq!(merkle_root_from_branch(&leaves[2], 2, &branch), root);
+    assert_ne!(merkle_root_from_branch(&leaves[2], 3, &branch), root);
+    assert_eq!(merkle_root_from_branch(&leaves[0], 0, &[]), leaves[0]);
+}